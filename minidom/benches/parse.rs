@@ -0,0 +1,68 @@
+//! Throughput of parsing documents into an `Element`, with the per-token
+//! costs dominating: many small elements, attributes and text nodes.
+//!
+//! Run with `cargo bench -p minidom --bench parse`.
+
+use minidom::Element;
+use std::time::{Duration, Instant};
+
+const ITERATIONS: usize = 20;
+
+fn document(stanzas: usize, comments: bool) -> String {
+    let mut data = String::from("<stream xmlns='jabber:client'>");
+    for i in 0..stanzas {
+        if comments {
+            data.push_str("<!-- A comment, which gets skipped. -->");
+        }
+        data.push_str(&format!(
+            "<message from='user{0}@example.org/res' to='room@muc.example.org' id='msg{0}' type='groupchat'>\
+             <body>Hello, this is message number {0}, with some filler text &amp; an entity.</body>\
+             <delay xmlns='urn:xmpp:delay' from='example.org' stamp='2002-09-10T23:08:25Z'/>\
+             <origin-id xmlns='urn:xmpp:sid:0' id='origin{0}'/>\
+             </message>",
+            i
+        ));
+    }
+    data.push_str("</stream>");
+    data
+}
+
+/// A file transfer, with a single large CDATA section.
+fn cdata(size: usize) -> String {
+    let payload: String = (0..size)
+        .map(|i| match i % 64 {
+            63 => ']',
+            i => (b'a' + (i % 26) as u8) as char,
+        })
+        .collect();
+    format!(
+        "<stream xmlns='jabber:client'><data><![CDATA[{}]]></data></stream>",
+        payload
+    )
+}
+
+fn report(name: &str, bytes: usize, elapsed: Duration) {
+    let per_iteration = elapsed / ITERATIONS as u32;
+    println!(
+        "{:<16} {:>8.1} ms  {:>8.1} MiB/s",
+        name,
+        per_iteration.as_secs_f64() * 1000.,
+        bytes as f64 / per_iteration.as_secs_f64() / 1024. / 1024.
+    );
+}
+
+fn bench(name: &str, data: &str) {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        let root: Element = data.parse().unwrap();
+        assert!(root.children().next().is_some());
+    }
+    report(name, data.len(), start.elapsed());
+}
+
+fn main() {
+    bench("small", &document(100, false));
+    bench("large", &document(10_000, false));
+    bench("with comments", &document(10_000, true));
+    bench("cdata", &cdata(16 * 1024 * 1024));
+}
@@ -0,0 +1,143 @@
+// Copyright (c) 2022 Emmanuel Gil Peyrot <linkmauve@linkmauve.fr>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Exclusive XML Canonicalization, without comments.
+//!
+//! See <https://www.w3.org/TR/xml-exc-c14n/>: only the namespace
+//! declarations visibly used by an element or its attributes get output,
+//! sorted by prefix before the attributes sorted by namespace and local
+//! name, and every element gets a start and an end tag.
+
+use crate::element::Element;
+use crate::error::{Error, Result};
+use crate::node::Node;
+use crate::prefixes::{Namespace, Prefix};
+use std::collections::BTreeMap;
+use std::io::Write;
+
+pub(crate) const XML_NS: &str = "http://www.w3.org/XML/1998/namespace";
+
+fn escape_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '\r' => escaped.push_str("&#xD;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn escape_attribute(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\t' => escaped.push_str("&#x9;"),
+            '\n' => escaped.push_str("&#xA;"),
+            '\r' => escaped.push_str("&#xD;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Write the canonical form of `elem`, whose ancestors declared the
+/// `in_scope` prefixes and had the `rendered` ones output.
+pub(crate) fn write<W: Write>(
+    elem: &Element,
+    writer: &mut W,
+    in_scope: &BTreeMap<Prefix, Namespace>,
+    rendered: &BTreeMap<Prefix, Namespace>,
+) -> Result<()> {
+    let mut in_scope = in_scope.clone();
+    for (prefix, namespace) in elem.prefixes.declared_prefixes() {
+        in_scope.insert(prefix.clone(), namespace.clone());
+    }
+
+    // Prefer the default namespace, then the first prefix bound to ours,
+    // else declare ours as the default one.
+    let namespace = elem.ns().to_owned();
+    let prefix = if in_scope.get(&None) == Some(&namespace) {
+        None
+    } else {
+        in_scope
+            .iter()
+            .find(|(prefix, ns)| prefix.is_some() && **ns == namespace)
+            .and_then(|(prefix, _)| prefix.clone())
+    };
+    let mut used = BTreeMap::new();
+    used.insert(prefix.clone(), namespace);
+
+    let mut attrs = Vec::new();
+    for (name, value) in elem.attrs() {
+        let (namespace, local) = match name.split_once(':') {
+            Some(("xml", local)) => (String::from(XML_NS), local),
+            Some((prefix, local)) => {
+                let prefix = Some(String::from(prefix));
+                let namespace = match in_scope.get(&prefix) {
+                    Some(namespace) => namespace.clone(),
+                    None => return Err(Error::InvalidPrefix),
+                };
+                used.insert(prefix, namespace.clone());
+                (namespace, local)
+            }
+            None => (String::new(), name),
+        };
+        attrs.push((namespace, local, name, value));
+    }
+    attrs.sort_unstable_by(|a, b| (&a.0, a.1).cmp(&(&b.0, b.1)));
+
+    let mut rendered = rendered.clone();
+    let qname = match prefix {
+        Some(ref prefix) => format!("{}:{}", prefix, elem.name()),
+        None => String::from(elem.name()),
+    };
+    let mut head = format!("<{}", qname);
+    for (prefix, namespace) in used {
+        let output = match rendered.get(&prefix) {
+            Some(previous) => *previous != namespace,
+            // An empty default namespace is the initial one.
+            None => prefix.is_some() || !namespace.is_empty(),
+        };
+        if !output {
+            continue;
+        }
+        match prefix {
+            Some(ref prefix) => head.push_str(&format!(" xmlns:{}=\"", prefix)),
+            None => head.push_str(" xmlns=\""),
+        }
+        head.push_str(&escape_attribute(&namespace));
+        head.push('"');
+        rendered.insert(prefix, namespace);
+    }
+    for (_, _, name, value) in attrs {
+        head.push_str(&format!(" {}=\"{}\"", name, escape_attribute(value)));
+    }
+    head.push('>');
+    writer
+        .write_all(head.as_bytes())
+        .map_err(rxml::Error::from)?;
+
+    for node in elem.nodes() {
+        match node {
+            Node::Element(child) => write(child, writer, &in_scope, &rendered)?,
+            Node::Text(text) => writer
+                .write_all(escape_text(text).as_bytes())
+                .map_err(rxml::Error::from)?,
+        }
+    }
+
+    writer
+        .write_all(format!("</{}>", qname).as_bytes())
+        .map_err(rxml::Error::from)?;
+    Ok(())
+}
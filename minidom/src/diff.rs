@@ -0,0 +1,236 @@
+// Copyright (c) 2022 Emmanuel Gil Peyrot <linkmauve@linkmauve.fr>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Comparing elements regardless of the prefixes they use.
+//!
+//! Two elements are considered the same when they have the same name and
+//! namespace, the same attributes once their prefixes are resolved to
+//! namespaces, and the same child nodes in the same order.  See
+//! [`Element::diff`] and [`Element::namespace_aware_eq`].
+
+use crate::c14n::XML_NS;
+use crate::element::Element;
+use crate::node::Node;
+use crate::prefixes::{Namespace, Prefix};
+use std::collections::BTreeMap;
+
+/// A difference between two elements, found by [`Element::diff`].
+///
+/// The paths are the names of the elements leading to it from the compared
+/// ones, like `iq > pubsub > publish`, or an empty string for the compared
+/// ones themselves.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[allow(clippy::large_enum_variant)]
+pub enum Difference {
+    /// The elements at this path have different names or namespaces, given
+    /// as `{namespace}name`.  Their content isn’t compared any further.
+    Name {
+        /// The path of the elements.
+        path: String,
+        /// The name of the left element.
+        left: String,
+        /// The name of the right element.
+        right: String,
+    },
+
+    /// An attribute of the elements at this path has different values, or
+    /// only exists on one of them.
+    Attribute {
+        /// The path of the elements.
+        path: String,
+        /// The name of the attribute, with the prefix of the left element
+        /// if it has it.
+        name: String,
+        /// Its value on the left element.
+        left: Option<String>,
+        /// Its value on the right element.
+        right: Option<String>,
+    },
+
+    /// A child node of the elements at this path differs, and isn’t two
+    /// elements of the same name to compare recursively, or only exists on
+    /// one of them.
+    Node {
+        /// The path of the elements.
+        path: String,
+        /// The index of the child node.
+        index: usize,
+        /// The child node of the left element.
+        left: Option<Node>,
+        /// The child node of the right element.
+        right: Option<Node>,
+    },
+}
+
+type Scope = BTreeMap<Prefix, Namespace>;
+
+fn clark(elem: &Element) -> String {
+    format!("{{{}}}{}", elem.ns(), elem.name())
+}
+
+/// The attributes of `elem`, by namespace and local name.  Those with an
+/// undeclared prefix are kept under their full name.
+fn attributes<'a>(
+    elem: &'a Element,
+    scope: &'a Scope,
+) -> BTreeMap<(Option<&'a str>, &'a str), (&'a str, &'a str)> {
+    elem.attrs()
+        .map(|(name, value)| {
+            let key = match name.split_once(':') {
+                Some(("xml", local)) => (Some(XML_NS), local),
+                Some((prefix, local)) => match scope.get(&Some(String::from(prefix))) {
+                    Some(namespace) => (Some(namespace.as_str()), local),
+                    None => (None, name),
+                },
+                None => (None, name),
+            };
+            (key, (name, value))
+        })
+        .collect()
+}
+
+pub(crate) fn diff(
+    left: &Element,
+    right: &Element,
+    path: &str,
+    left_scope: &Scope,
+    right_scope: &Scope,
+    differences: &mut Vec<Difference>,
+) {
+    if left.name() != right.name() || !left.has_ns(right.ns()) {
+        differences.push(Difference::Name {
+            path: String::from(path),
+            left: clark(left),
+            right: clark(right),
+        });
+        return;
+    }
+
+    let mut left_scope = left_scope.clone();
+    left_scope.extend(left.prefixes.declared_prefixes().clone());
+    let mut right_scope = right_scope.clone();
+    right_scope.extend(right.prefixes.declared_prefixes().clone());
+
+    let left_attrs = attributes(left, &left_scope);
+    let right_attrs = attributes(right, &right_scope);
+    for (key, (name, value)) in &left_attrs {
+        let other = right_attrs.get(key).map(|(_, value)| *value);
+        if other != Some(*value) {
+            differences.push(Difference::Attribute {
+                path: String::from(path),
+                name: String::from(*name),
+                left: Some(String::from(*value)),
+                right: other.map(String::from),
+            });
+        }
+    }
+    for (key, (name, value)) in &right_attrs {
+        if !left_attrs.contains_key(key) {
+            differences.push(Difference::Attribute {
+                path: String::from(path),
+                name: String::from(*name),
+                left: None,
+                right: Some(String::from(*value)),
+            });
+        }
+    }
+
+    let mut left_nodes = left.nodes();
+    let mut right_nodes = right.nodes();
+    let mut index = 0;
+    loop {
+        match (left_nodes.next(), right_nodes.next()) {
+            (None, None) => break,
+            (Some(Node::Element(l)), Some(Node::Element(r))) if l.name() == r.name() => {
+                let path = if path.is_empty() {
+                    String::from(l.name())
+                } else {
+                    format!("{} > {}", path, l.name())
+                };
+                diff(l, r, &path, &left_scope, &right_scope, differences);
+            }
+            (Some(Node::Text(l)), Some(Node::Text(r))) if l == r => (),
+            (l, r) => differences.push(Difference::Node {
+                path: String::from(path),
+                index,
+                left: l.cloned(),
+                right: r.cloned(),
+            }),
+        }
+        index += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prefixes() {
+        let left: Element = "<a xmlns='ns1' xmlns:p='ns2' p:b='1' xml:lang='en'><c/></a>"
+            .parse()
+            .unwrap();
+        let right: Element = "<q:a xmlns:q='ns1' xmlns:r='ns2' xml:lang='en'><q:c r:b='1'/></q:a>"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            left.diff(&right),
+            [
+                Difference::Attribute {
+                    path: String::new(),
+                    name: String::from("p:b"),
+                    left: Some(String::from("1")),
+                    right: None,
+                },
+                Difference::Attribute {
+                    path: String::from("c"),
+                    name: String::from("r:b"),
+                    left: None,
+                    right: Some(String::from("1")),
+                },
+            ]
+        );
+
+        let right: Element = "<q:a xmlns:q='ns1' xmlns:r='ns2' r:b='1' xml:lang='en'><q:c/></q:a>"
+            .parse()
+            .unwrap();
+        assert!(left.namespace_aware_eq(&right));
+        assert_eq!(left.diff(&right), []);
+    }
+
+    #[test]
+    fn test_children() {
+        let left: Element = "<a xmlns='ns1'><b><c>x</c></b><d xmlns='ns2'/>y</a>"
+            .parse()
+            .unwrap();
+        let right: Element = "<a xmlns='ns1'><b><c>z</c></b><d/>y<e/></a>"
+            .parse()
+            .unwrap();
+        assert!(!left.namespace_aware_eq(&right));
+        assert_eq!(
+            left.diff(&right),
+            [
+                Difference::Node {
+                    path: String::from("b > c"),
+                    index: 0,
+                    left: Some(Node::Text(String::from("x"))),
+                    right: Some(Node::Text(String::from("z"))),
+                },
+                Difference::Name {
+                    path: String::from("d"),
+                    left: String::from("{ns2}d"),
+                    right: String::from("{ns1}d"),
+                },
+                Difference::Node {
+                    path: String::new(),
+                    index: 3,
+                    left: None,
+                    right: Some(Node::Element(Element::bare("e", "ns1"))),
+                },
+            ]
+        );
+    }
+}
@@ -12,14 +12,20 @@
 
 //! Provides an `Element` type, which represents DOM nodes, and a builder to create them with.
 
+use crate::c14n;
 use crate::convert::IntoAttributeValue;
+use crate::diff::{self, Difference};
 use crate::error::{Error, Result};
+use crate::intern::{intern, same_str};
+use crate::misc::{EntityPolicy, MiscFilter, MiscFilterReader};
 use crate::namespaces::NSChoice;
 use crate::node::Node;
 use crate::prefixes::{Namespace, Prefix, Prefixes};
-use crate::tree_builder::TreeBuilder;
+use crate::query::{Matches, Query};
+use crate::tree_builder::{ParserLimits, TreeBuilder};
 
-use std::collections::{btree_map, BTreeMap};
+use indexmap::IndexMap;
+use std::collections::BTreeMap;
 use std::convert::{TryFrom, TryInto};
 use std::io::{BufRead, Write};
 use std::sync::Arc;
@@ -28,7 +34,7 @@ use std::borrow::Cow;
 use std::str;
 
 use rxml::writer::{Encoder, Item, TrackNamespace};
-use rxml::{EventRead, Lexer, PullDriver, RawParser, XmlVersion};
+use rxml::{EventRead, PullDriver, RawParser, XmlVersion};
 
 use std::str::FromStr;
 
@@ -66,6 +72,18 @@ impl<W: Write, T: rxml::writer::TrackNamespace> CustomItemWriter<W, T> {
     pub(crate) fn write(&mut self, item: Item<'_>) -> rxml::Result<()> {
         encode_and_write(item, &mut self.encoder, &mut self.writer)
     }
+
+    pub(crate) fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    pub(crate) fn get_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    pub(crate) fn into_inner(self) -> W {
+        self.writer
+    }
 }
 
 /// Type alias to simplify the use for the default namespace tracking
@@ -117,12 +135,15 @@ pub fn escape(raw: &[u8]) -> Cow<[u8]> {
 
 #[derive(Clone, Eq, Debug)]
 /// A struct representing a DOM Element.
+///
+/// It owns its attributes, namespace declarations and children, without any
+/// link to its parent, so it is `Send` and `Sync`.
 pub struct Element {
-    name: String,
-    namespace: String,
+    name: Arc<str>,
+    namespace: Arc<str>,
     /// Namespace declarations
     pub prefixes: Prefixes,
-    attributes: BTreeMap<String, String>,
+    attributes: IndexMap<Arc<str>, String>,
     children: Vec<Node>,
 }
 
@@ -144,7 +165,10 @@ impl FromStr for Element {
 
 impl PartialEq for Element {
     fn eq(&self, other: &Self) -> bool {
-        if self.name() == other.name() && self.ns() == other.ns() && self.attrs().eq(other.attrs())
+        // The attributes are compared regardless of their order.
+        if self.name == other.name
+            && self.namespace == other.namespace
+            && self.attributes == other.attributes
         {
             self.nodes()
                 .zip(other.nodes())
@@ -157,15 +181,15 @@ impl PartialEq for Element {
 
 impl Element {
     pub(crate) fn new<P: Into<Prefixes>>(
-        name: String,
-        namespace: String,
+        name: &str,
+        namespace: &str,
         prefixes: P,
-        attributes: BTreeMap<String, String>,
+        attributes: IndexMap<Arc<str>, String>,
         children: Vec<Node>,
     ) -> Element {
         Element {
-            name,
-            namespace,
+            name: intern(name),
+            namespace: intern(namespace),
             prefixes: prefixes.into(),
             attributes,
             children,
@@ -185,7 +209,7 @@ impl Element {
     ///                    .build();
     ///
     /// assert_eq!(elem.name(), "name");
-    /// assert_eq!(elem.ns(), "namespace".to_owned());
+    /// assert_eq!(elem.ns(), "namespace");
     /// assert_eq!(elem.attr("name"), Some("value"));
     /// assert_eq!(elem.attr("inexistent"), None);
     /// assert_eq!(elem.text(), "inner");
@@ -193,10 +217,10 @@ impl Element {
     pub fn builder<S: AsRef<str>, NS: Into<String>>(name: S, namespace: NS) -> ElementBuilder {
         ElementBuilder {
             root: Element::new(
-                name.as_ref().to_string(),
-                namespace.into(),
+                name.as_ref(),
+                &namespace.into(),
                 None,
-                BTreeMap::new(),
+                IndexMap::new(),
                 Vec::new(),
             ),
         }
@@ -218,10 +242,10 @@ impl Element {
     /// ```
     pub fn bare<S: Into<String>, NS: Into<String>>(name: S, namespace: NS) -> Element {
         Element::new(
-            name.into(),
-            namespace.into(),
+            &name.into(),
+            &namespace.into(),
             None,
-            BTreeMap::new(),
+            IndexMap::new(),
             Vec::new(),
         )
     }
@@ -232,8 +256,8 @@ impl Element {
     }
 
     /// Returns a reference to the namespace of this element.
-    pub fn ns(&self) -> String {
-        self.namespace.clone()
+    pub fn ns(&self) -> &str {
+        &self.namespace
     }
 
     /// Returns a reference to the value of the given attribute, if it exists, else `None`.
@@ -244,7 +268,8 @@ impl Element {
         None
     }
 
-    /// Returns an iterator over the attributes of this element.
+    /// Returns an iterator over the attributes of this element, in the order they were parsed or
+    /// first set, which is also the order they get serialised in.
     ///
     /// # Example
     ///
@@ -272,19 +297,81 @@ impl Element {
         }
     }
 
-    /// Modifies the value of an attribute.
+    /// Modifies the value of an attribute, or removes it if the value is `None`.
     pub fn set_attr<S: Into<String>, V: IntoAttributeValue>(&mut self, name: S, val: V) {
         let name = name.into();
-        let val = val.into_attribute_value();
+        match val.into_attribute_value() {
+            Some(val) => {
+                self.attributes.insert(intern(&name), val);
+            }
+            None => {
+                self.attributes.shift_remove(&*name);
+            }
+        }
+    }
 
-        if let Some(value) = self.attributes.get_mut(&name) {
-            *value = val
-                .expect("removing existing value via set_attr, this is not yet supported (TODO)"); // TODO
-            return;
+    /// Modifies the values of several attributes at once, like [`Element::set_attr`].
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use minidom::Element;
+    ///
+    /// let mut elem: Element = "<message xmlns=\"jabber:client\" from=\"a@b\" to=\"c@d\"/>".parse().unwrap();
+    /// elem.set_attrs(vec![("from", Some("c@d")), ("to", Some("e@f")), ("id", None)]);
+    ///
+    /// assert_eq!(elem.attr("from"), Some("c@d"));
+    /// assert_eq!(elem.attr("to"), Some("e@f"));
+    /// ```
+    pub fn set_attrs<S, V, I>(&mut self, attrs: I)
+    where
+        S: Into<String>,
+        V: IntoAttributeValue,
+        I: IntoIterator<Item = (S, V)>,
+    {
+        for (name, val) in attrs {
+            self.set_attr(name, val);
         }
+    }
+
+    /// Removes an attribute, returning its value if it existed.
+    pub fn remove_attr(&mut self, name: &str) -> Option<String> {
+        self.attributes.shift_remove(name)
+    }
+
+    /// Keeps only the attributes for which `f` returns `true`, given their name and value.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use minidom::Element;
+    ///
+    /// let mut elem: Element = "<message xmlns=\"jabber:client\" from=\"a@b\" to=\"c@d\" id=\"1\"/>".parse().unwrap();
+    /// elem.retain_attrs(|name, _| name != "from" && name != "to");
+    ///
+    /// assert_eq!(elem.attrs().collect::<Vec<_>>(), [("id", "1")]);
+    /// ```
+    pub fn retain_attrs<F: FnMut(&str, &str) -> bool>(&mut self, mut f: F) {
+        self.attributes.retain(|name, value| f(name, value));
+    }
 
-        if let Some(val) = val {
-            self.attributes.insert(name, val);
+    /// Sorts the attributes of this element and of all of its descendants by name, so that they
+    /// get serialised in the same order however they were set.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use minidom::Element;
+    ///
+    /// let mut elem: Element = "<a xmlns='ns1' c='1' b='2'><d f='3' e='4'/></a>".parse().unwrap();
+    /// elem.sort_attrs();
+    ///
+    /// assert_eq!(String::from(&elem), r#"<a xmlns='ns1' b="2" c="1"><d e="4" f="3"/></a>"#);
+    /// ```
+    pub fn sort_attrs(&mut self) {
+        self.attributes.sort_keys();
+        for child in self.children_mut() {
+            child.sort_attrs();
         }
     }
 
@@ -308,7 +395,7 @@ impl Element {
     /// assert_eq!(elem.is("name", NSChoice::Any), true);
     /// ```
     pub fn is<'a, N: AsRef<str>, NS: Into<NSChoice<'a>>>(&self, name: N, namespace: NS) -> bool {
-        self.name == name.as_ref() && namespace.into().compare(self.namespace.as_ref())
+        same_str(&self.name, name.as_ref()) && namespace.into().compare(&self.namespace)
     }
 
     /// Returns whether the element has the given namespace.
@@ -332,11 +419,122 @@ impl Element {
         namespace.into().compare(self.namespace.as_ref())
     }
 
-    /// Parse a document from a `BufRead`.
+    /// Returns whether this element and `other` have the same name, namespace, attributes and
+    /// child nodes, whichever prefixes they use for the namespaces of the elements and
+    /// attributes.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use minidom::Element;
+    ///
+    /// let a: Element = "<a xmlns='ns1' xmlns:p='ns2' p:b='c'/>".parse().unwrap();
+    /// let b: Element = "<q:a xmlns:q='ns1' xmlns:r='ns2' r:b='c'/>".parse().unwrap();
+    /// assert!(a.namespace_aware_eq(&b));
+    /// ```
+    pub fn namespace_aware_eq(&self, other: &Element) -> bool {
+        self.diff(other).is_empty()
+    }
+
+    /// Returns the differences between this element and `other`, ignoring the prefixes they
+    /// use like [`Element::namespace_aware_eq`], for instance to explain a failing test.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use minidom::{diff::Difference, Element};
+    ///
+    /// let a: Element = "<a xmlns='ns1'><b c='1'/></a>".parse().unwrap();
+    /// let b: Element = "<a xmlns='ns1'><b c='2'/></a>".parse().unwrap();
+    /// assert_eq!(a.diff(&b), [Difference::Attribute {
+    ///     path: String::from("b"),
+    ///     name: String::from("c"),
+    ///     left: Some(String::from("1")),
+    ///     right: Some(String::from("2")),
+    /// }]);
+    /// ```
+    pub fn diff(&self, other: &Element) -> Vec<Difference> {
+        let mut differences = Vec::new();
+        diff::diff(
+            self,
+            other,
+            "",
+            &BTreeMap::new(),
+            &BTreeMap::new(),
+            &mut differences,
+        );
+        differences
+    }
+
+    /// Parse a document from a `BufRead`, failing with `Error::Doctype` if
+    /// it contains a DOCTYPE declaration.  Its comments and processing
+    /// instructions get skipped.
     pub fn from_reader<R: BufRead>(reader: R) -> Result<Element> {
+        Element::from_reader_with_limits(reader, ParserLimits::default())
+    }
+
+    /// Parse a document from a `BufRead`, skipping its DOCTYPE declaration,
+    /// whose internal subset never gets processed, like its comments and
+    /// processing instructions.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use minidom::{Element, Error};
+    ///
+    /// let xml = "<!DOCTYPE p><!-- a --><p xmlns='ns1'/>";
+    /// assert!(matches!(Element::from_reader(xml.as_bytes()), Err(Error::Doctype)));
+    /// let elem = Element::from_reader_lenient(xml.as_bytes()).unwrap();
+    /// assert!(elem.is("p", "ns1"));
+    /// ```
+    pub fn from_reader_lenient<R: BufRead>(reader: R) -> Result<Element> {
+        let mut filter = MiscFilter::new();
+        filter.set_lenient(true);
+        let reader = MiscFilterReader::with_filter(reader, filter);
+        Element::from_filtered_reader(reader, ParserLimits::default())
+    }
+
+    /// Parse a document from a `BufRead`, failing with
+    /// `Error::LimitExceeded` if it goes over `limits`.
+    pub fn from_reader_with_limits<R: BufRead>(reader: R, limits: ParserLimits) -> Result<Element> {
+        Element::from_filtered_reader(MiscFilterReader::new(reader), limits)
+    }
+
+    /// Parse a document from a `BufRead`, handling the references to entities other than the
+    /// predefined ones according to `policy`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use minidom::misc::EntityPolicy;
+    /// use minidom::Element;
+    ///
+    /// let xml = "<p xmlns='ns1'>a&nbsp;b</p>";
+    /// assert!(Element::from_reader(xml.as_bytes()).is_err());
+    /// let elem = Element::from_reader_with_entity_policy(xml.as_bytes(), EntityPolicy::Keep).unwrap();
+    /// assert_eq!(elem.text(), "a&nbsp;b");
+    /// ```
+    pub fn from_reader_with_entity_policy<R: BufRead>(
+        reader: R,
+        policy: EntityPolicy,
+    ) -> Result<Element> {
+        let reader = MiscFilterReader::with_filter(reader, MiscFilter::with_entity_policy(policy));
+        Element::from_filtered_reader(reader, ParserLimits::default())
+    }
+
+    fn from_filtered_reader<R: BufRead>(
+        reader: MiscFilterReader<R>,
+        limits: ParserLimits,
+    ) -> Result<Element> {
         let mut tree_builder = TreeBuilder::new();
-        let mut driver = PullDriver::wrap(reader, Lexer::new(), RawParser::new());
-        while let Some(event) = driver.read()? {
+        tree_builder.set_limits(limits);
+        let mut driver = PullDriver::wrap(reader, limits.lexer(), RawParser::new());
+        while let Some(event) = driver.read().map_err(|err| {
+            if driver.get_inner().found_doctype() {
+                return tree_builder.error_context(Error::Doctype);
+            }
+            tree_builder.error_context(err.into())
+        })? {
             tree_builder.process_event(event)?;
 
             if let Some(root) = tree_builder.root.take() {
@@ -356,6 +554,40 @@ impl Element {
         self.to_writer_decl(&mut ItemWriter::new(writer))
     }
 
+    /// Output a document to a `Writer`, with each element on its own line
+    /// and indented by `indent` per level of nesting.  The elements
+    /// containing text are written as is, since whitespace is significant
+    /// there.
+    pub fn write_pretty<W: Write>(&self, writer: &mut W, indent: &str) -> Result<()> {
+        self.indented(indent, 0).write_to(writer)
+    }
+
+    fn indented(&self, indent: &str, depth: usize) -> Element {
+        let mixed = self.texts().any(|text| !text.trim().is_empty());
+        if mixed || self.children().next().is_none() {
+            return self.clone();
+        }
+        let mut children = Vec::new();
+        for child in self.children() {
+            children.push(Node::Text(format!("\n{}", indent.repeat(depth + 1))));
+            children.push(Node::Element(child.indented(indent, depth + 1)));
+        }
+        children.push(Node::Text(format!("\n{}", indent.repeat(depth))));
+        Element {
+            name: self.name.clone(),
+            namespace: self.namespace.clone(),
+            prefixes: self.prefixes.clone(),
+            attributes: self.attributes.clone(),
+            children,
+        }
+    }
+
+    /// Output the Exclusive XML Canonicalization of this element, without
+    /// comments, as used for signatures.
+    pub fn write_c14n<W: Write>(&self, writer: &mut W) -> Result<()> {
+        c14n::write(self, writer, &BTreeMap::new(), &BTreeMap::new())
+    }
+
     /// Output the document to quick-xml `Writer`
     pub fn to_writer<W: Write>(&self, writer: &mut ItemWriter<W>) -> Result<()> {
         self.write_to_inner(writer)
@@ -371,17 +603,40 @@ impl Element {
 
     /// Like `write_to()` but without the `<?xml?>` prelude
     pub fn write_to_inner<W: Write>(&self, writer: &mut ItemWriter<W>) -> Result<()> {
+        self.write_head(writer)?;
+        if !self.children.is_empty() {
+            writer.write(Item::ElementHeadEnd)?;
+            for child in self.children.iter() {
+                child.write_to_inner(writer)?;
+            }
+        }
+        writer.write(Item::ElementFoot)?;
+
+        Ok(())
+    }
+
+    /// Output the start of the opening tag of this element, along with its
+    /// attributes and the namespace declarations not inherited already.
+    pub(crate) fn write_head<W: Write>(&self, writer: &mut ItemWriter<W>) -> Result<()> {
         for (prefix, namespace) in self.prefixes.declared_prefixes() {
-            assert!(writer.encoder.inner_mut().declare_fixed(
-                prefix.as_ref().map(|x| (&**x).try_into()).transpose()?,
-                Some(Arc::new(namespace.clone().try_into()?))
-            ));
+            let prefix = prefix.as_ref().map(|x| (&**x).try_into()).transpose()?;
+            // Reuse the declarations of the ancestors, which can’t be
+            // redeclared anyway in the case of the root element.
+            if let Ok(declared) = writer.encoder.inner().lookup_prefix(prefix) {
+                if declared.as_str() == namespace {
+                    continue;
+                }
+            }
+            assert!(writer
+                .encoder
+                .inner_mut()
+                .declare_fixed(prefix, Some(Arc::new(namespace.clone().try_into()?))));
         }
 
         let namespace = if self.namespace.len() == 0 {
             None
         } else {
-            Some(Arc::new(self.namespace.clone().try_into()?))
+            Some(Arc::new(String::from(&*self.namespace).try_into()?))
         };
         writer.write(Item::ElementHeadStart(namespace, (*self.name).try_into()?))?;
 
@@ -400,14 +655,6 @@ impl Element {
             writer.write(Item::Attribute(namespace, name, (&**value).try_into()?))?;
         }
 
-        if !self.children.is_empty() {
-            writer.write(Item::ElementHeadEnd)?;
-            for child in self.children.iter() {
-                child.write_to_inner(writer)?;
-            }
-        }
-        writer.write(Item::ElementFoot)?;
-
         Ok(())
     }
 
@@ -567,6 +814,162 @@ impl Element {
         self.children.push(node);
     }
 
+    /// Inserts a node at position `index` in the child nodes of this
+    /// `Element`, shifting the following ones.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is greater than the number of child nodes.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use minidom::{Element, Node};
+    ///
+    /// let mut elem: Element = "<p xmlns=\"ns1\">Hello world!</p>".parse().unwrap();
+    /// elem.insert_node(0, Node::Element(Element::bare("br", "ns1")));
+    ///
+    /// assert_eq!(elem.nodes().next().unwrap().as_element().unwrap().name(), "br");
+    /// assert_eq!(elem.text(), "Hello world!");
+    /// ```
+    pub fn insert_node(&mut self, index: usize, node: Node) {
+        self.children.insert(index, node);
+    }
+
+    /// Removes the child node at position `index`, returning it, or `None`
+    /// if there is no such node.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use minidom::{Element, Node};
+    ///
+    /// let mut elem: Element = "<p xmlns=\"ns1\">Hello <em>big</em> world!</p>".parse().unwrap();
+    ///
+    /// assert_eq!(elem.remove_node(1).unwrap().as_element().unwrap().name(), "em");
+    /// assert_eq!(elem.remove_node(2), None);
+    /// assert_eq!(elem.text(), "Hello  world!");
+    /// ```
+    pub fn remove_node(&mut self, index: usize) -> Option<Node> {
+        if index < self.children.len() {
+            Some(self.children.remove(index))
+        } else {
+            None
+        }
+    }
+
+    /// Keeps only the child nodes for which `f` returns `true`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use minidom::Element;
+    ///
+    /// let mut elem: Element = "<p xmlns=\"ns1\">Hello <em>big</em> world!</p>".parse().unwrap();
+    /// elem.retain_nodes(|node| node.is_text());
+    ///
+    /// assert_eq!(elem.children().count(), 0);
+    /// assert_eq!(elem.text(), "Hello  world!");
+    /// ```
+    pub fn retain_nodes<F: FnMut(&Node) -> bool>(&mut self, f: F) {
+        self.children.retain(f);
+    }
+
+    /// Removes every child node of this `Element`, returning them in
+    /// order, for instance to rebuild the mixed content of a paragraph.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use minidom::Element;
+    ///
+    /// let mut elem: Element = "<p xmlns=\"ns1\">Hello <em>big</em> world!</p>".parse().unwrap();
+    /// let nodes = elem.take_nodes();
+    ///
+    /// assert_eq!(nodes.len(), 3);
+    /// assert_eq!(elem.nodes().count(), 0);
+    /// ```
+    pub fn take_nodes(&mut self) -> Vec<Node> {
+        std::mem::take(&mut self.children)
+    }
+
+    /// Keeps only the child elements for which `f` returns `true`, along with all of the text
+    /// nodes.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use minidom::Element;
+    ///
+    /// let mut elem: Element = "<message xmlns=\"jabber:client\"><body>Hi</body><active xmlns=\"http://jabber.org/protocol/chatstates\"/></message>".parse().unwrap();
+    /// elem.retain_children(|child| child.has_ns("jabber:client"));
+    ///
+    /// assert_eq!(elem.children().count(), 1);
+    /// ```
+    pub fn retain_children<F: FnMut(&Element) -> bool>(&mut self, mut f: F) {
+        self.children.retain(|node| match node {
+            Node::Element(elem) => f(elem),
+            Node::Text(_) => true,
+        });
+    }
+
+    /// Removes every text node of this `Element`, returning them in order, and keeps its child
+    /// elements.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use minidom::Element;
+    ///
+    /// let mut elem: Element = "<p xmlns=\"ns1\">Hello <em>big</em> world!</p>".parse().unwrap();
+    ///
+    /// assert_eq!(elem.take_texts(), ["Hello ", " world!"]);
+    /// assert_eq!(elem.nodes().count(), 1);
+    /// ```
+    pub fn take_texts(&mut self) -> Vec<String> {
+        let mut texts = Vec::new();
+        let mut children = Vec::with_capacity(self.children.len());
+        for node in self.children.drain(..) {
+            match node {
+                Node::Text(text) => texts.push(text),
+                node => children.push(node),
+            }
+        }
+        self.children = children;
+        texts
+    }
+
+    /// Returns the concatenation of all text nodes in the `Element` and
+    /// in all of its descendants, in document order.
+    ///
+    /// Unlike [`Element::text`], the text of child elements is kept, so
+    /// offsets into mixed content match what gets displayed.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use minidom::Element;
+    ///
+    /// let elem: Element = "<p xmlns=\"ns1\">Hello <em>big</em> world!</p>".parse().unwrap();
+    ///
+    /// assert_eq!(elem.text(), "Hello  world!");
+    /// assert_eq!(elem.deep_text(), "Hello big world!");
+    /// ```
+    pub fn deep_text(&self) -> String {
+        let mut text = String::new();
+        self.push_deep_text(&mut text);
+        text
+    }
+
+    pub(crate) fn push_deep_text(&self, text: &mut String) {
+        for node in self.nodes() {
+            match node {
+                Node::Element(elem) => elem.push_deep_text(text),
+                Node::Text(string) => text.push_str(string),
+            }
+        }
+    }
+
     /// Returns the concatenation of all text nodes in the `Element`.
     ///
     /// # Examples
@@ -632,6 +1035,30 @@ impl Element {
         None
     }
 
+    /// Returns an iterator over the descendants of this `Element` matched by a [`Query`], or by
+    /// a path like `pubsub/items/item`, see the [`query`](crate::query) module.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use minidom::Element;
+    ///
+    /// let elem: Element = r#"<node xmlns="ns"><a><b>1</b><b>2</b></a><a xmlns="other_ns"><b>3</b></a></node>"#.parse().unwrap();
+    /// let texts: Vec<_> = elem.find_all("a/b").map(Element::text).collect();
+    /// assert_eq!(texts, ["1", "2", "3"]);
+    /// let texts: Vec<_> = elem.find_all("{other_ns}a/b").map(Element::text).collect();
+    /// assert_eq!(texts, ["3"]);
+    /// ```
+    pub fn find_all<Q: Into<Query>>(&self, query: Q) -> Matches<'_> {
+        query.into().find_all(self)
+    }
+
+    /// Returns a reference to the first descendant of this `Element` matched by a [`Query`], or
+    /// by a path like `pubsub/items/item`, if it exists.
+    pub fn find<Q: Into<Query>>(&self, query: Q) -> Option<&Element> {
+        self.find_all(query).next()
+    }
+
     /// Returns whether a specific child with this name and namespace exists in the direct
     /// descendants of the `Element`.
     ///
@@ -687,6 +1114,51 @@ impl Element {
         self.children.remove(idx).into_element()
     }
 
+    /// Removes the first child element for which `f` returns `true`, if it exists, and returns it.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use minidom::Element;
+    ///
+    /// let mut elem: Element = r#"<node xmlns="ns"><a id="1"/><a id="2"/></node>"#.parse().unwrap();
+    /// let child = elem.remove_child_by(|child| child.attr("id") == Some("2")).unwrap();
+    /// assert_eq!(child.attr("id"), Some("2"));
+    /// assert!(elem.remove_child_by(|child| child.attr("id") == Some("2")).is_none());
+    /// ```
+    pub fn remove_child_by<F: FnMut(&Element) -> bool>(&mut self, mut f: F) -> Option<Element> {
+        let idx = self.children.iter().position(|node| match node {
+            Node::Element(elem) => f(elem),
+            Node::Text(_) => false,
+        })?;
+        self.children.remove(idx).into_element()
+    }
+
+    /// Replaces the first child with this name and namespace by `child`, at the same position,
+    /// and returns the replaced one.  Returns `None`, leaving this `Element` untouched, if no
+    /// child matches this name and namespace.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use minidom::Element;
+    ///
+    /// let mut elem: Element = r#"<node xmlns="ns"><a/><b/><c/></node>"#.parse().unwrap();
+    /// let old = elem.replace_child("b", "ns", Element::bare("d", "ns")).unwrap();
+    ///
+    /// assert!(old.is("b", "ns"));
+    /// assert_eq!(String::from(&elem), "<node xmlns='ns'><a/><d/><c/></node>");
+    /// ```
+    pub fn replace_child<'a, N: AsRef<str>, NS: Into<NSChoice<'a>>>(
+        &mut self,
+        name: N,
+        namespace: NS,
+        child: Element,
+    ) -> Option<Element> {
+        let old = self.get_child_mut(name, namespace)?;
+        Some(std::mem::replace(old, child))
+    }
+
     /// Remove the leading nodes up to the first child element and
     /// return it
     pub fn unshift_child(&mut self) -> Option<Element> {
@@ -780,7 +1252,7 @@ pub type NodesMut<'a> = slice::IterMut<'a, Node>;
 
 /// An iterator over the attributes of an `Element`.
 pub struct Attrs<'a> {
-    iter: btree_map::Iter<'a, String, String>,
+    iter: indexmap::map::Iter<'a, Arc<str>, String>,
 }
 
 impl<'a> Iterator for Attrs<'a> {
@@ -793,7 +1265,7 @@ impl<'a> Iterator for Attrs<'a> {
 
 /// An iterator over the attributes of an `Element`, with the values mutable.
 pub struct AttrsMut<'a> {
-    iter: btree_map::IterMut<'a, String, String>,
+    iter: indexmap::map::IterMut<'a, Arc<str>, String>,
 }
 
 impl<'a> Iterator for AttrsMut<'a> {
@@ -823,6 +1295,17 @@ impl ElementBuilder {
         Ok(self)
     }
 
+    /// Serialises this element with `prefix` for its namespace, like
+    /// `stream:stream`, declaring it on this element so that its children
+    /// in the same namespace use it too.
+    ///
+    /// The default namespace still gets used instead if it is the same as
+    /// the one of this element.
+    pub fn prefixed<P: Into<String>>(self, prefix: P) -> Result<ElementBuilder> {
+        let namespace = self.root.ns().to_owned();
+        self.prefix(Some(prefix.into()), namespace)
+    }
+
     /// Sets an attribute.
     pub fn attr<S: Into<String>, V: IntoAttributeValue>(
         mut self,
@@ -833,12 +1316,32 @@ impl ElementBuilder {
         self
     }
 
+    /// Sets several attributes, for instance out of a map, skipping those whose value is
+    /// `None`.
+    pub fn attrs<S, V, I>(mut self, attrs: I) -> ElementBuilder
+    where
+        S: Into<String>,
+        V: IntoAttributeValue,
+        I: IntoIterator<Item = (S, V)>,
+    {
+        self.root.set_attrs(attrs);
+        self
+    }
+
     /// Appends anything implementing `Into<Node>` into the tree.
     pub fn append<T: Into<Node>>(mut self, node: T) -> ElementBuilder {
         self.root.append_node(node.into());
         self
     }
 
+    /// Appends anything implementing `Into<Node>` into the tree, if it is `Some`.
+    pub fn append_opt<T: Into<Node>>(mut self, node: Option<T>) -> ElementBuilder {
+        if let Some(node) = node {
+            self.root.append_node(node.into());
+        }
+        self
+    }
+
     /// Appends an iterator of things implementing `Into<Node>` into the tree.
     pub fn append_all<T: Into<Node>, I: IntoIterator<Item = T>>(
         mut self,
@@ -865,10 +1368,10 @@ mod tests {
         use std::iter::FromIterator;
 
         let elem = Element::new(
-            "name".to_owned(),
-            "namespace".to_owned(),
+            "name",
+            "namespace",
             (None, "namespace".to_owned()),
-            BTreeMap::from_iter(vec![("name".to_string(), "value".to_string())].into_iter()),
+            IndexMap::from_iter(vec![(Arc::from("name"), "value".to_string())]),
             Vec::new(),
         );
 
@@ -958,4 +1461,23 @@ mod tests {
         assert_eq!(elem, elem3);
         assert_eq!(elem, elem4);
     }
+
+    #[test]
+    fn test_builder_optional() {
+        use std::collections::HashMap;
+
+        let mut attrs = HashMap::new();
+        attrs.insert("a", Some("1"));
+        attrs.insert("b", None);
+        let nick: Option<String> = None;
+        let elem = Element::builder("x", "ns")
+            .attrs(attrs)
+            .append_opt(Some(Element::bare("y", "ns")))
+            .append_opt(nick)
+            .append_all(vec!["z"])
+            .build();
+
+        assert_eq!(elem.attrs().collect::<Vec<_>>(), [("a", "1")]);
+        assert_eq!(String::from(&elem), "<x xmlns='ns' a=\"1\"><y/>z</x>");
+    }
 }
@@ -32,6 +32,48 @@ pub enum Error {
 
     /// An error which is returned when a prefixed is defined twice
     DuplicatePrefix,
+
+    /// An error which is returned when an attribute is present twice on
+    /// the same element.
+    DuplicateAttribute(String),
+
+    /// An error which is returned when the prefix of an attribute isn’t
+    /// declared.
+    UndeclaredPrefix(String),
+
+    /// An error which is returned when a document goes over one of the
+    /// `ParserLimits`, named here.
+    LimitExceeded(&'static str),
+
+    /// An error which is returned when the XML declaration specifies
+    /// another encoding than UTF-8.
+    UnsupportedEncoding,
+
+    /// An error which is returned when the document contains a DOCTYPE
+    /// declaration, outside of the lenient mode of `misc::MiscFilter`.
+    Doctype,
+
+    /// An error which happened inside of an element, along with the path of
+    /// names leading to it from the root, like `iq > pubsub > publish`.
+    InElement(String, Box<Error>),
+}
+
+impl Error {
+    /// The error without the path it happened at.
+    pub fn root_cause(&self) -> &Error {
+        match self {
+            Error::InElement(_, error) => error.root_cause(),
+            error => error,
+        }
+    }
+
+    /// The path of the element this error happened in, if known.
+    pub fn path(&self) -> Option<&str> {
+        match self {
+            Error::InElement(path, _) => Some(path),
+            _ => None,
+        }
+    }
 }
 
 impl StdError for Error {
@@ -42,6 +84,12 @@ impl StdError for Error {
             Error::InvalidPrefix => None,
             Error::MissingNamespace => None,
             Error::DuplicatePrefix => None,
+            Error::DuplicateAttribute(_) => None,
+            Error::UndeclaredPrefix(_) => None,
+            Error::LimitExceeded(_) => None,
+            Error::UnsupportedEncoding => None,
+            Error::Doctype => None,
+            Error::InElement(_, e) => Some(e),
         }
     }
 }
@@ -56,19 +104,33 @@ impl std::fmt::Display for Error {
             Error::InvalidPrefix => write!(fmt, "the prefix is invalid"),
             Error::MissingNamespace => write!(fmt, "the XML element is missing a namespace",),
             Error::DuplicatePrefix => write!(fmt, "the prefix is already defined"),
+            Error::DuplicateAttribute(name) => {
+                write!(fmt, "the attribute {} is present twice", name)
+            }
+            Error::UndeclaredPrefix(prefix) => write!(fmt, "the prefix {} isn’t declared", prefix),
+            Error::LimitExceeded(limit) => write!(fmt, "the limit on {} has been exceeded", limit),
+            Error::UnsupportedEncoding => write!(fmt, "only the UTF-8 encoding is supported"),
+            Error::Doctype => write!(fmt, "DOCTYPE declarations aren’t allowed"),
+            Error::InElement(path, e) => write!(fmt, "{} (in {})", e, path),
         }
     }
 }
 
 impl From<rxml::Error> for Error {
     fn from(err: rxml::Error) -> Error {
-        Error::XmlError(err)
+        match err {
+            // rxml only tells this case apart by its message.
+            rxml::Error::RestrictedXml(what) if what.contains("encoding") => {
+                Error::UnsupportedEncoding
+            }
+            err => Error::XmlError(err),
+        }
     }
 }
 
 impl From<rxml::error::XmlError> for Error {
     fn from(err: rxml::error::XmlError) -> Error {
-        Error::XmlError(err.into())
+        Error::from(rxml::Error::from(err))
     }
 }
 
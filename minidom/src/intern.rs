@@ -0,0 +1,127 @@
+// Copyright (c) 2022 Emmanuel Gil Peyrot <linkmauve@linkmauve.fr>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Sharing the names and namespaces of elements and attributes.
+//!
+//! They are stored as `Arc<str>`, so cloning an element doesn’t copy them.
+//! With the `intern` feature, they are also looked up in a global table, so
+//! that all of the elements parsed with the same name or namespace share a
+//! single allocation.
+
+use std::sync::Arc;
+
+#[cfg(feature = "intern")]
+mod table {
+    use std::collections::HashSet;
+    use std::sync::{Arc, Mutex};
+
+    /// Longer strings are unlikely to be seen again.
+    const MAX_LEN: usize = 256;
+
+    /// The table is never emptied, so bound it to not grow forever with the
+    /// random names a peer could send.
+    const MAX_ENTRIES: usize = 4096;
+
+    #[derive(Default)]
+    struct Table {
+        strings: HashSet<Arc<str>>,
+        /// The strings not added since the table got full.
+        misses: usize,
+    }
+
+    impl Table {
+        /// Once full, forget the strings no element uses anymore, so that
+        /// the first ones ever seen don’t keep the table full forever.
+        ///
+        /// This only happens every `MAX_ENTRIES` misses, to not go through
+        /// the whole table each time while all of it is still in use.
+        fn sweep(&mut self) {
+            self.misses += 1;
+            if self.misses >= MAX_ENTRIES {
+                self.misses = 0;
+                self.strings.retain(|string| Arc::strong_count(string) > 1);
+            }
+        }
+    }
+
+    static TABLE: Mutex<Option<Table>> = Mutex::new(None);
+
+    pub(super) fn intern(string: &str) -> Arc<str> {
+        if string.len() > MAX_LEN {
+            return Arc::from(string);
+        }
+        let mut table = TABLE.lock().unwrap_or_else(|err| err.into_inner());
+        let table = table.get_or_insert_with(Table::default);
+        if let Some(interned) = table.strings.get(string) {
+            return Arc::clone(interned);
+        }
+        let interned: Arc<str> = Arc::from(string);
+        if table.strings.len() >= MAX_ENTRIES {
+            table.sweep();
+        }
+        if table.strings.len() < MAX_ENTRIES {
+            table.strings.insert(Arc::clone(&interned));
+        }
+        interned
+    }
+
+    #[cfg(test)]
+    pub(super) fn is_interned(string: &str) -> bool {
+        let table = TABLE.lock().unwrap_or_else(|err| err.into_inner());
+        matches!(*table, Some(ref table) if table.strings.contains(string))
+    }
+}
+
+/// Returns a shared copy of `string`.
+#[cfg(feature = "intern")]
+pub(crate) fn intern(string: &str) -> Arc<str> {
+    table::intern(string)
+}
+
+/// Returns a shared copy of `string`.
+#[cfg(not(feature = "intern"))]
+pub(crate) fn intern(string: &str) -> Arc<str> {
+    Arc::from(string)
+}
+
+/// Whether `a` and `b` are equal, without comparing their bytes when
+/// they are the same string, like an interned namespace and itself.
+pub(crate) fn same_str(a: &str, b: &str) -> bool {
+    std::ptr::eq(a, b) || a == b
+}
+
+#[cfg(all(test, feature = "intern"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shared() {
+        let a = intern("jabber:client");
+        let b = intern(&String::from("jabber:client"));
+        assert!(Arc::ptr_eq(&a, &b));
+        assert!(!Arc::ptr_eq(&a, &intern("jabber:server")));
+
+        let long = "a".repeat(1000);
+        assert!(!Arc::ptr_eq(&intern(&long), &intern(&long)));
+    }
+
+    #[test]
+    fn test_sweep() {
+        // Fill the table with strings nothing keeps, then a new one gets
+        // in once they got swept away, within as many misses.
+        for i in 0..4096 {
+            intern(&format!("test-sweep-{}", i));
+        }
+        let mut misses = 0;
+        while !table::is_interned("test-sweep-kept") {
+            intern("test-sweep-kept");
+            misses += 1;
+            assert!(misses <= 4096);
+        }
+        let kept = intern("test-sweep-kept");
+        assert!(Arc::ptr_eq(&kept, &intern("test-sweep-kept")));
+    }
+}
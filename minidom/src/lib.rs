@@ -75,13 +75,22 @@
 //! minidom = "*"
 //! ```
 
+mod c14n;
 pub mod convert;
+pub mod diff;
 pub mod element;
 pub mod error;
+mod intern;
+pub mod misc;
 mod namespaces;
 pub mod node;
 mod prefixes;
+pub mod query;
+pub mod stream_parser;
+#[cfg(feature = "tokio")]
+pub mod token_stream;
 pub mod tree_builder;
+pub mod writer;
 
 #[cfg(test)]
 mod tests;
@@ -91,3 +100,11 @@ pub use element::{Children, ChildrenMut, Element, ElementBuilder};
 pub use error::{Error, Result};
 pub use namespaces::NSChoice;
 pub use node::Node;
+pub use query::Query;
+pub use stream_parser::{StreamEvent, StreamParser};
+#[cfg(feature = "tokio")]
+pub use token_stream::TokenStream;
+pub use tree_builder::ParserLimits;
+#[cfg(feature = "tokio")]
+pub use writer::AsyncWriter;
+pub use writer::Writer;
@@ -0,0 +1,567 @@
+// Copyright (c) 2022 Emmanuel Gil Peyrot <linkmauve@linkmauve.fr>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Skipping the comments and processing instructions of a document, and
+//! rejecting its DOCTYPE declarations or skipping them too.
+//!
+//! rxml implements the restricted subset of XML used by XMPP streams, and
+//! errors out on this markup, yet real-world files and BOSH endpoints do
+//! contain it.  [`MiscFilter`] removes the comments and processing
+//! instructions from the bytes before they reach the parser, keeping the
+//! XML declaration and the CDATA sections intact.  It stops at the first
+//! DOCTYPE declaration, which [`Error::Doctype`](crate::Error::Doctype)
+//! reports instead of a generic syntax error since a DTD is never
+//! processed, unless in its lenient mode which skips those as well.
+//!
+//! It can also let through the references to entities other than the five
+//! predefined ones, like the `&nbsp;` of documents written for HTML, which
+//! would otherwise fail the whole parse, see [`EntityPolicy`].  The
+//! character references are always checked by the parser, which rejects
+//! those of invalid code points like surrogates or most control characters.
+
+use std::io::{self, BufRead, Read};
+
+const XML: &[u8] = b"xml";
+
+/// The entities any document can reference.
+const PREDEFINED_ENTITIES: [&[u8]; 5] = [b"amp", b"lt", b"gt", b"apos", b"quot"];
+
+/// Longer references aren’t worth buffering, and get treated as a lone `&`.
+const MAX_REFERENCE_LEN: usize = 32;
+
+/// What to do with the references to entities other than the predefined
+/// `&amp;`, `&lt;`, `&gt;`, `&apos;` and `&quot;`, which can’t be declared
+/// since DOCTYPE declarations are never processed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EntityPolicy {
+    /// Fail to parse the document, the default.
+    #[default]
+    Reject,
+
+    /// Keep the reference as text, like `&nbsp;`.  A `&` starting no
+    /// reference at all is kept as text too.
+    Keep,
+
+    /// Replace the reference with U+FFFD REPLACEMENT CHARACTER.  A `&`
+    /// starting no reference at all is kept as text.
+    Replace,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Outside of any markup we skip.
+    Text,
+
+    /// After a `<`.
+    Lt,
+
+    /// After a `<!`.
+    Bang,
+
+    /// After a `<!-`.
+    BangDash,
+
+    /// In a comment, after this many consecutive `-`.
+    Comment(u8),
+
+    /// After a `<?` and this many bytes of `xml`.
+    Question(usize),
+
+    /// In a processing instruction, after a `?` or not.
+    Pi(bool),
+
+    /// In a DOCTYPE declaration, in its internal subset or not, and in a
+    /// quoted string or not.
+    Doctype(bool, Option<u8>),
+
+    /// In a CDATA section, after this many consecutive `]`.
+    CData(u8),
+
+    /// After a DOCTYPE declaration outside of the lenient mode, dropping
+    /// everything.
+    Rejected,
+
+    /// After a `&`, with what follows it in the reference buffer.
+    Reference,
+}
+
+/// Removes the comments and processing instructions of a document, fed
+/// chunk by chunk, and looks for its DOCTYPE declarations, or removes them
+/// too in its lenient mode.
+#[derive(Debug, Clone)]
+pub struct MiscFilter {
+    state: State,
+    lenient: bool,
+    entities: EntityPolicy,
+    /// The name of the entity or character being referenced.
+    reference: Vec<u8>,
+}
+
+impl Default for MiscFilter {
+    fn default() -> Self {
+        MiscFilter::new()
+    }
+}
+
+impl MiscFilter {
+    /// Create a filter for the start of a document, skipping the comments
+    /// and processing instructions and rejecting any DOCTYPE declaration.
+    pub fn new() -> Self {
+        MiscFilter::with_entity_policy(EntityPolicy::Reject)
+    }
+
+    /// Create a filter for the start of a document, also handling the
+    /// references to unknown entities according to `policy`.
+    pub fn with_entity_policy(policy: EntityPolicy) -> Self {
+        MiscFilter {
+            state: State::Text,
+            lenient: false,
+            entities: policy,
+            reference: Vec::new(),
+        }
+    }
+
+    /// Change what to do with the references to unknown entities from now
+    /// on.
+    pub fn set_entity_policy(&mut self, policy: EntityPolicy) {
+        self.entities = policy;
+    }
+
+    /// Skip the DOCTYPE declarations from now on instead of rejecting them,
+    /// or not.  The internal subset of a skipped DOCTYPE declaration is
+    /// ignored, so its entities never get declared.
+    pub fn set_lenient(&mut self, lenient: bool) {
+        self.lenient = lenient;
+    }
+
+    /// Whether a DOCTYPE declaration got found outside of the lenient
+    /// mode, in which case nothing gets output anymore.
+    pub fn found_doctype(&self) -> bool {
+        self.state == State::Rejected
+    }
+
+    /// Whether `b` is a byte the filter has to look at in text.
+    fn is_special(&self, b: u8) -> bool {
+        b == b'<' || (b == b'&' && self.entities != EntityPolicy::Reject)
+    }
+
+    /// Output the reference buffered so far, ended by a `;` or not.
+    fn end_reference(&mut self, terminated: bool, output: &mut Vec<u8>) {
+        let name = &self.reference[..];
+        let known = name.first() == Some(&b'#') || PREDEFINED_ENTITIES.contains(&name);
+        if terminated && known {
+            output.push(b'&');
+        } else if terminated && self.entities == EntityPolicy::Replace {
+            output.extend_from_slice("\u{FFFD}".as_bytes());
+            return;
+        } else {
+            output.extend_from_slice(b"&amp;");
+        }
+        output.extend_from_slice(name);
+        if terminated {
+            output.push(b';');
+        }
+    }
+
+    /// Whether `input` would be passed through unchanged, as is the case
+    /// of most chunks of a document.
+    fn passes_through(&self, input: &[u8]) -> bool {
+        match self.state {
+            State::Text => {
+                input.last() != Some(&b'<')
+                    && !(self.entities != EntityPolicy::Reject && input.contains(&b'&'))
+                    && !input
+                        .windows(2)
+                        .any(|pair| pair[0] == b'<' && (pair[1] == b'!' || pair[1] == b'?'))
+            }
+            // Like most chunks of a large CDATA section.
+            State::CData(0) => !input.contains(&b']'),
+            _ => false,
+        }
+    }
+
+    /// Append to `output` the bytes of `input` which aren’t part of the
+    /// skipped markup.  The markup can be split across several chunks.
+    pub fn feed(&mut self, input: &[u8], output: &mut Vec<u8>) {
+        let mut i = 0;
+        while i < input.len() {
+            if self.state == State::Text {
+                match input[i..].iter().position(|&b| self.is_special(b)) {
+                    Some(offset) => {
+                        output.extend_from_slice(&input[i..i + offset]);
+                        if input[i + offset] == b'<' {
+                            self.state = State::Lt;
+                        } else {
+                            self.state = State::Reference;
+                            self.reference.clear();
+                        }
+                        i += offset + 1;
+                    }
+                    None => {
+                        output.extend_from_slice(&input[i..]);
+                        return;
+                    }
+                }
+                continue;
+            }
+            if self.state == State::CData(0) {
+                // Only a `]` can start the end of the section.
+                match input[i..].iter().position(|&b| b == b']') {
+                    Some(offset) => {
+                        output.extend_from_slice(&input[i..=i + offset]);
+                        self.state = State::CData(1);
+                        i += offset + 1;
+                    }
+                    None => {
+                        output.extend_from_slice(&input[i..]);
+                        return;
+                    }
+                }
+                continue;
+            }
+            if self.state == State::Rejected {
+                return;
+            }
+            let b = input[i];
+            if self.state == State::Reference {
+                let name_char = b.is_ascii_alphanumeric() || b >= 0x80 || b"#_-.:".contains(&b);
+                if b == b';' {
+                    self.end_reference(true, output);
+                    i += 1;
+                } else if !name_char || self.reference.len() >= MAX_REFERENCE_LEN {
+                    // Not a reference, look at this byte again as text.
+                    self.end_reference(false, output);
+                } else {
+                    self.reference.push(b);
+                    i += 1;
+                    continue;
+                }
+                self.state = State::Text;
+                continue;
+            }
+            i += 1;
+            self.state = match self.state {
+                State::Text | State::Reference | State::Rejected => unreachable!(),
+                State::Lt => match b {
+                    b'!' => State::Bang,
+                    b'?' => State::Question(0),
+                    b => {
+                        output.extend_from_slice(&[b'<', b]);
+                        State::Text
+                    }
+                },
+                State::Bang => match b {
+                    b'-' => State::BangDash,
+                    b'D' if self.lenient => State::Doctype(false, None),
+                    b'D' => State::Rejected,
+                    b'[' => {
+                        output.extend_from_slice(b"<![");
+                        State::CData(0)
+                    }
+                    b => {
+                        output.extend_from_slice(&[b'<', b'!', b]);
+                        State::Text
+                    }
+                },
+                State::BangDash => match b {
+                    b'-' => State::Comment(0),
+                    b => {
+                        output.extend_from_slice(&[b'<', b'!', b'-', b]);
+                        State::Text
+                    }
+                },
+                State::Comment(dashes) => match b {
+                    b'-' => State::Comment(dashes.saturating_add(1)),
+                    b'>' if dashes >= 2 => State::Text,
+                    _ => State::Comment(0),
+                },
+                State::Question(matched) if matched < XML.len() => {
+                    if b == XML[matched] {
+                        State::Question(matched + 1)
+                    } else {
+                        State::Pi(b == b'?')
+                    }
+                }
+                State::Question(_) => match b {
+                    // The XML declaration, for the parser to check.
+                    b' ' | b'\t' | b'\r' | b'\n' => {
+                        output.extend_from_slice(b"<?xml");
+                        output.push(b);
+                        State::Text
+                    }
+                    b => State::Pi(b == b'?'),
+                },
+                State::Pi(question) => match b {
+                    b'>' if question => State::Text,
+                    b => State::Pi(b == b'?'),
+                },
+                State::Doctype(subset, Some(quote)) => {
+                    State::Doctype(subset, if b == quote { None } else { Some(quote) })
+                }
+                State::Doctype(subset, None) => match b {
+                    b'"' | b'\'' => State::Doctype(subset, Some(b)),
+                    b'[' => State::Doctype(true, None),
+                    b']' => State::Doctype(false, None),
+                    b'>' if !subset => State::Text,
+                    _ => State::Doctype(subset, None),
+                },
+                State::CData(brackets) => {
+                    output.push(b);
+                    match b {
+                        b']' => State::CData(brackets.saturating_add(1)),
+                        b'>' if brackets >= 2 => State::Text,
+                        _ => State::CData(0),
+                    }
+                }
+            };
+        }
+    }
+}
+
+/// A [`BufRead`] passing the bytes of another one through a
+/// [`MiscFilter`].
+///
+/// The chunks without any markup to skip are borrowed from the underlying
+/// reader rather than copied.
+#[derive(Debug)]
+pub struct MiscFilterReader<R> {
+    inner: R,
+    filter: MiscFilter,
+    buf: Vec<u8>,
+    pos: usize,
+    /// How many bytes of the current chunk of `inner` are left to read as
+    /// is.
+    borrowed: usize,
+}
+
+impl<R: BufRead> MiscFilterReader<R> {
+    /// Filter the bytes read from `inner`.
+    pub fn new(inner: R) -> Self {
+        MiscFilterReader::with_filter(inner, MiscFilter::new())
+    }
+
+    /// Whether a DOCTYPE declaration got found outside of the lenient
+    /// mode, failing the read.
+    pub fn found_doctype(&self) -> bool {
+        self.filter.found_doctype()
+    }
+
+    /// Filter the bytes read from `inner` with `filter`.
+    pub fn with_filter(inner: R, filter: MiscFilter) -> Self {
+        MiscFilterReader {
+            inner,
+            filter,
+            buf: Vec::new(),
+            pos: 0,
+            borrowed: 0,
+        }
+    }
+
+    /// Unwrap the underlying reader, dropping whatever got read from it but
+    /// not consumed yet.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: BufRead> Read for MiscFilterReader<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let len = available.len().min(out.len());
+        out[..len].copy_from_slice(&available[..len]);
+        self.consume(len);
+        Ok(len)
+    }
+}
+
+impl<R: BufRead> BufRead for MiscFilterReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.borrowed == 0 && self.pos == self.buf.len() {
+            let chunk = self.inner.fill_buf()?;
+            if self.filter.passes_through(chunk) {
+                self.borrowed = chunk.len();
+            }
+        }
+        if self.borrowed > 0 {
+            return self.inner.fill_buf();
+        }
+
+        // Some chunks could be entirely skipped, read until there is
+        // something left or the end of the reader.
+        while self.pos == self.buf.len() {
+            self.buf.clear();
+            self.pos = 0;
+            let chunk = self.inner.fill_buf()?;
+            if chunk.is_empty() {
+                break;
+            }
+            self.filter.feed(chunk, &mut self.buf);
+            let len = chunk.len();
+            self.inner.consume(len);
+            if self.filter.found_doctype() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "DOCTYPE declaration",
+                ));
+            }
+        }
+        Ok(&self.buf[self.pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        if self.borrowed > 0 {
+            let amt = amt.min(self.borrowed);
+            self.inner.consume(amt);
+            self.borrowed -= amt;
+            return;
+        }
+        self.pos = (self.pos + amt).min(self.buf.len());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(chunks: &[&str]) -> String {
+        let mut filter = MiscFilter::new();
+        filter.set_lenient(true);
+        let mut output = Vec::new();
+        for chunk in chunks {
+            filter.feed(chunk.as_bytes(), &mut output);
+        }
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_skipped() {
+        assert_eq!(filter(&["<a><!-- b --></a>"]), "<a></a>");
+        assert_eq!(filter(&["<a><!-- - -- b --->c</a>"]), "<a>c</a>");
+        assert_eq!(filter(&["<?xml-stylesheet href='a.xsl'?><a/>"]), "<a/>");
+        assert_eq!(filter(&["<a><?php echo '>' ?></a>"]), "<a></a>");
+        assert_eq!(
+            filter(&["<!DOCTYPE a [<!ENTITY b \"]>\">]>\n<a/>"]),
+            "\n<a/>"
+        );
+    }
+
+    #[test]
+    fn test_strict() {
+        let mut filter = MiscFilter::new();
+        let mut output = Vec::new();
+        let xml = "<?xml version='1.0'?><?a?><!-- b --><c/>";
+        filter.feed(xml.as_bytes(), &mut output);
+        assert_eq!(output, b"<?xml version='1.0'?><c/>");
+        assert!(!filter.found_doctype());
+
+        filter.feed(b"<!DOCTYPE a [<!ENTITY b 'c'>]><a/>", &mut output);
+        assert!(filter.found_doctype());
+        assert_eq!(output, b"<?xml version='1.0'?><c/>");
+
+        let mut reader = MiscFilterReader::new(&b"<a/><!DOCTYPE a>"[..]);
+        let mut output = String::new();
+        assert!(reader.read_to_string(&mut output).is_err());
+        assert!(reader.found_doctype());
+    }
+
+    #[test]
+    fn test_kept() {
+        let xml = "<?xml version='1.0'?>\n<a b='c'><![CDATA[<!-- d -->]]>e<f/></a>";
+        assert_eq!(filter(&[xml]), xml);
+        assert_eq!(filter(&["<!E"]), "<!E");
+    }
+
+    #[test]
+    fn test_chunks() {
+        let chunks = [
+            "<a>b<",
+            "!",
+            "-- c -",
+            "-",
+            "><?x",
+            "ml vers",
+            "ion='1.0'?>d</a>",
+        ];
+        assert_eq!(filter(&chunks), "<a>b<?xml version='1.0'?>d</a>");
+    }
+
+    fn filter_entities(policy: EntityPolicy, chunks: &[&str]) -> String {
+        let mut filter = MiscFilter::with_entity_policy(policy);
+        let mut output = Vec::new();
+        for chunk in chunks {
+            filter.feed(chunk.as_bytes(), &mut output);
+        }
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_entities() {
+        let xml = "<a b='&nbsp;&amp;'>&#xA0;&lt;&eacute;<![CDATA[&nbsp;]]> AT&T &</a>";
+        assert_eq!(filter_entities(EntityPolicy::Reject, &[xml]), xml);
+        assert_eq!(
+            filter_entities(EntityPolicy::Keep, &[xml]),
+            "<a b='&amp;nbsp;&amp;'>&#xA0;&lt;&amp;eacute;<![CDATA[&nbsp;]]> AT&amp;T &amp;</a>"
+        );
+        assert_eq!(
+            filter_entities(EntityPolicy::Replace, &[xml]),
+            "<a b='\u{FFFD}&amp;'>&#xA0;&lt;\u{FFFD}<![CDATA[&nbsp;]]> AT&amp;T &amp;</a>"
+        );
+        assert_eq!(
+            filter_entities(EntityPolicy::Keep, &["<a>&nb", "sp", ";&a", "mp;</a>"]),
+            "<a>&amp;nbsp;&amp;</a>"
+        );
+    }
+
+    #[test]
+    fn test_cdata() {
+        let chunks = [
+            "<a><![CDATA[b]",
+            "]c]]",
+            "]d",
+            "]",
+            "]",
+            ">e<![CDATA[]]>]]></a>",
+        ];
+        assert_eq!(
+            filter(&chunks),
+            "<a><![CDATA[b]]c]]]d]]>e<![CDATA[]]>]]></a>"
+        );
+
+        // A large section, split in chunks of all sizes.
+        let payload: String = (0..10_000)
+            .map(|i| if i % 7 == 0 { ']' } else { 'x' })
+            .collect();
+        let xml = format!("<a xmlns='ns1'><![CDATA[{}]]><!-- b --></a>", payload);
+        for capacity in [1, 2, 3, 5, 64, 4096] {
+            let reader = io::BufReader::with_capacity(capacity, xml.as_bytes());
+            let elem = crate::Element::from_reader_lenient(reader).unwrap();
+            assert_eq!(elem.text(), payload);
+        }
+    }
+
+    #[test]
+    fn test_reader() {
+        let mut lenient = MiscFilter::new();
+        lenient.set_lenient(true);
+        let mut reader =
+            MiscFilterReader::with_filter(&b"<!-- a --><b>c<!-- d --></b>"[..], lenient.clone());
+        let mut output = String::new();
+        reader.read_to_string(&mut output).unwrap();
+        assert_eq!(output, "<b>c</b>");
+
+        // Mixing borrowed chunks and filtered ones.
+        let xml = &b"<a xmlns='ns1'>bcd<!-- e --><f/>g<?h?>ij</a>"[..];
+        for capacity in 1..8 {
+            let mut reader = MiscFilterReader::with_filter(
+                io::BufReader::with_capacity(capacity, xml),
+                lenient.clone(),
+            );
+            let mut output = String::new();
+            reader.read_to_string(&mut output).unwrap();
+            assert_eq!(output, "<a xmlns='ns1'>bcd<f/>gij</a>");
+        }
+    }
+}
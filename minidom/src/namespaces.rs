@@ -7,6 +7,8 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
+use crate::intern::same_str;
+
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 /// Use to compare namespaces
 pub enum NSChoice<'a> {
@@ -31,8 +33,8 @@ impl<'a> NSChoice<'a> {
         match (ns, &self) {
             (_, NSChoice::None) => false,
             (_, NSChoice::Any) => true,
-            (ns, NSChoice::OneOf(wanted_ns)) => &ns == wanted_ns,
-            (ns, NSChoice::AnyOf(wanted_nss)) => wanted_nss.iter().any(|w| &ns == w),
+            (ns, NSChoice::OneOf(wanted_ns)) => same_str(ns, wanted_ns),
+            (ns, NSChoice::AnyOf(wanted_nss)) => wanted_nss.iter().any(|w| same_str(ns, w)),
         }
     }
 }
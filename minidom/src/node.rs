@@ -158,6 +158,61 @@ impl Node {
         }
     }
 
+    /// Returns whether this is an element node.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use minidom::Node;
+    ///
+    /// let elm = Node::Element("<meow xmlns=\"ns1\"/>".parse().unwrap());
+    /// let txt = Node::Text("meow".to_owned());
+    ///
+    /// assert!(elm.is_element());
+    /// assert!(!txt.is_element());
+    /// ```
+    pub fn is_element(&self) -> bool {
+        matches!(self, Node::Element(_))
+    }
+
+    /// Returns whether this is a text node.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use minidom::Node;
+    ///
+    /// let elm = Node::Element("<meow xmlns=\"ns1\"/>".parse().unwrap());
+    /// let txt = Node::Text("meow".to_owned());
+    ///
+    /// assert!(!elm.is_text());
+    /// assert!(txt.is_text());
+    /// ```
+    pub fn is_text(&self) -> bool {
+        matches!(self, Node::Text(_))
+    }
+
+    /// Returns the text of this node, including the text of all of the
+    /// descendants of an element node.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use minidom::Node;
+    ///
+    /// let elm = Node::Element("<em xmlns=\"ns1\">big <b>bold</b></em>".parse().unwrap());
+    /// let txt = Node::Text("meow".to_owned());
+    ///
+    /// assert_eq!(elm.deep_text(), "big bold");
+    /// assert_eq!(txt.deep_text(), "meow");
+    /// ```
+    pub fn deep_text(&self) -> String {
+        match self {
+            Node::Element(elem) => elem.deep_text(),
+            Node::Text(text) => text.clone(),
+        }
+    }
+
     #[doc(hidden)]
     pub(crate) fn write_to_inner<W: Write>(&self, writer: &mut ItemWriter<W>) -> Result<()> {
         match *self {
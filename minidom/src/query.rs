@@ -0,0 +1,224 @@
+// Copyright (c) 2022 Emmanuel Gil Peyrot <linkmauve@linkmauve.fr>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Finding the descendants of an element by their path.
+//!
+//! A [`Query`] is a list of steps, each matching the children of the
+//! elements matched by the previous one on their name and optionally their
+//! namespace.  It can be built step by step, or parsed out of a path like
+//! `pubsub/items/item`, where `*` matches any name and a namespace can be
+//! given in Clark notation, like
+//! `{http://jabber.org/protocol/pubsub}items`.
+
+use crate::element::{Children, Element};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Step {
+    /// `None` matches any name.
+    name: Option<String>,
+
+    /// `None` matches any namespace.
+    namespace: Option<String>,
+}
+
+impl Step {
+    fn new(name: &str) -> Step {
+        Step {
+            name: if name == "*" {
+                None
+            } else {
+                Some(String::from(name))
+            },
+            namespace: None,
+        }
+    }
+
+    fn matches(&self, elem: &Element) -> bool {
+        self.name.iter().all(|name| elem.name() == name)
+            && self
+                .namespace
+                .iter()
+                .all(|namespace| elem.has_ns(namespace.as_str()))
+    }
+}
+
+/// A path to some descendants of an element.
+///
+/// # Examples
+///
+/// ```rust
+/// use minidom::{Element, Query};
+///
+/// let elem: Element = r#"<pubsub xmlns="http://jabber.org/protocol/pubsub"><items node="a"><item id="1"/><item id="2"/></items></pubsub>"#.parse().unwrap();
+/// let query = Query::child("items")
+///     .ns("http://jabber.org/protocol/pubsub")
+///     .then_child("item");
+/// let ids: Vec<_> = elem.find_all(query).map(|item| item.attr("id").unwrap()).collect();
+/// assert_eq!(ids, ["1", "2"]);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Query {
+    steps: Vec<Step>,
+}
+
+impl Query {
+    /// Match the children named `name`, or all of them if it is `*`.
+    pub fn child<N: AsRef<str>>(name: N) -> Query {
+        Query {
+            steps: vec![Step::new(name.as_ref())],
+        }
+    }
+
+    /// Match the children named `name` of the elements matched so far.
+    pub fn then_child<N: AsRef<str>>(mut self, name: N) -> Query {
+        self.steps.push(Step::new(name.as_ref()));
+        self
+    }
+
+    /// Only match the elements of the last step in `namespace`.
+    pub fn ns<NS: Into<String>>(mut self, namespace: NS) -> Query {
+        if let Some(step) = self.steps.last_mut() {
+            step.namespace = Some(namespace.into());
+        }
+        self
+    }
+
+    /// Find the descendants of `elem` matched by this query, in document
+    /// order.
+    pub fn find_all(self, elem: &Element) -> Matches<'_> {
+        Matches {
+            stack: if self.steps.is_empty() {
+                Vec::new()
+            } else {
+                vec![elem.children()]
+            },
+            steps: self.steps,
+        }
+    }
+}
+
+impl<'a> From<&'a str> for Query {
+    /// Parse a path of `/`-separated names, each of them optionally
+    /// prefixed with a `{namespace}`.  Empty names are ignored.
+    fn from(path: &'a str) -> Query {
+        let mut steps = Vec::new();
+        let mut rest = path;
+        while !rest.is_empty() {
+            let mut namespace = None;
+            if let Some(qualified) = rest.strip_prefix('{') {
+                if let Some((ns, local)) = qualified.split_once('}') {
+                    namespace = Some(String::from(ns));
+                    rest = local;
+                }
+            }
+            let (name, next) = rest.split_once('/').unwrap_or((rest, ""));
+            rest = next;
+            if name.is_empty() {
+                continue;
+            }
+            let mut step = Step::new(name);
+            step.namespace = namespace;
+            steps.push(step);
+        }
+        Query { steps }
+    }
+}
+
+impl From<String> for Query {
+    fn from(path: String) -> Query {
+        Query::from(path.as_str())
+    }
+}
+
+/// An iterator over the descendants of an element matched by a [`Query`].
+pub struct Matches<'a> {
+    steps: Vec<Step>,
+
+    /// The children left to try at each step.
+    stack: Vec<Children<'a>>,
+}
+
+impl<'a> Iterator for Matches<'a> {
+    type Item = &'a Element;
+
+    fn next(&mut self) -> Option<&'a Element> {
+        loop {
+            let depth = self.stack.len();
+            let child = match self.stack.last_mut()?.next() {
+                Some(child) => child,
+                None => {
+                    self.stack.pop();
+                    continue;
+                }
+            };
+            if !self.steps[depth - 1].matches(child) {
+                continue;
+            }
+            if depth == self.steps.len() {
+                return Some(child);
+            }
+            self.stack.push(child.children());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NS: &str = "http://jabber.org/protocol/pubsub";
+
+    fn ids<'a, I: Iterator<Item = &'a Element>>(matches: I) -> Vec<&'a str> {
+        matches.map(|elem| elem.attr("id").unwrap()).collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            Query::from("pubsub/items/item"),
+            Query::child("pubsub")
+                .then_child("items")
+                .then_child("item")
+        );
+        assert_eq!(
+            Query::from("/{http://jabber.org/protocol/pubsub}items//*"),
+            Query::child("items").ns(NS).then_child("*")
+        );
+        assert_eq!(Query::from("").steps, []);
+    }
+
+    #[test]
+    fn test_find_all() {
+        let elem: Element = format!(
+            "<iq xmlns='jabber:client'><pubsub xmlns='{}'><items id='a'><item id='1'/><other id='2'/><item id='3'/></items><items id='b' xmlns='urn:other'><item id='4'/></items><items id='c'><item id='5'/></items></pubsub></iq>",
+            NS
+        )
+        .parse()
+        .unwrap();
+        assert_eq!(
+            ids(elem.find_all("pubsub/items/item")),
+            ["1", "3", "4", "5"]
+        );
+        assert_eq!(
+            ids(elem.find_all(
+                Query::child("pubsub")
+                    .then_child("items")
+                    .ns(NS)
+                    .then_child("item")
+            )),
+            ["1", "3", "5"]
+        );
+        assert_eq!(ids(elem.find_all("pubsub/{urn:other}*")), ["b"]);
+        assert_eq!(
+            ids(elem.find_all("pubsub/items/*")),
+            ["1", "2", "3", "4", "5"]
+        );
+        assert_eq!(ids(elem.find_all("pubsub/item")), Vec::<&str>::new());
+        assert_eq!(ids(elem.find_all("")), Vec::<&str>::new());
+        assert_eq!(elem.find("pubsub/items").unwrap().attr("id"), Some("a"));
+        assert_eq!(elem.find("pubsub/items/item/item"), None);
+    }
+}
@@ -0,0 +1,224 @@
+// Copyright (c) 2022 Emmanuel Gil Peyrot <linkmauve@linkmauve.fr>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Parsing a stream of elements, like the stanzas of an XMPP stream, as the
+//! bytes come in.
+//!
+//! A [`StreamParser`] reports the start of the root element of the
+//! document, then each of its children once it is complete, and finally
+//! its end, instead of waiting for the whole document.
+
+use crate::tree_builder::{ParserLimits, TreeBuilder};
+use crate::{Element, Result};
+use rxml::bytes::Buf;
+use rxml::{PushDriver, RawEvent, RawParser};
+use std::io;
+
+/// What a [`StreamParser`] found in the stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StreamEvent {
+    /// The start of the root element, like `<stream:stream>`, with its
+    /// attributes and namespace declarations but without any children.
+    Start(Element),
+
+    /// A complete child of the root element, like a stanza.
+    Child(Element),
+
+    /// Some text between the children of the root element, like a
+    /// whitespace keep-alive.
+    Text(String),
+
+    /// The end of the root element, like `</stream:stream>`.
+    End,
+}
+
+/// Parses the root element of a document and its children incrementally.
+///
+/// # Examples
+///
+/// ```rust
+/// use minidom::{StreamEvent, StreamParser};
+///
+/// let mut parser = StreamParser::new();
+/// let mut data = &b"<stream xmlns='jabber:client'><message/> <presence"[..];
+/// assert!(matches!(parser.parse(&mut data, false), Ok(Some(StreamEvent::Start(_)))));
+/// assert!(matches!(parser.parse(&mut data, false), Ok(Some(StreamEvent::Child(_)))));
+/// assert_eq!(parser.parse(&mut data, false).unwrap(), Some(StreamEvent::Text(String::from(" "))));
+/// assert_eq!(parser.parse(&mut data, false).unwrap(), None);
+///
+/// let mut data = &b"/></stream>"[..];
+/// match parser.parse(&mut data, false) {
+///     Ok(Some(StreamEvent::Child(stanza))) => assert!(stanza.is("presence", "jabber:client")),
+///     other => panic!("Unexpected {:?}", other),
+/// }
+/// assert_eq!(parser.parse(&mut data, false).unwrap(), Some(StreamEvent::End));
+/// ```
+pub struct StreamParser {
+    driver: PushDriver<RawParser>,
+    builder: TreeBuilder,
+    /// The root element without its children, between its start and end.
+    root: Option<Element>,
+}
+
+impl Default for StreamParser {
+    fn default() -> Self {
+        StreamParser::new()
+    }
+}
+
+impl StreamParser {
+    /// Create a parser for the start of a stream.
+    pub fn new() -> StreamParser {
+        StreamParser::with_limits(ParserLimits::default())
+    }
+
+    /// Create a parser failing once the stream goes over `limits`, its
+    /// root element being at depth 1.
+    pub fn with_limits(limits: ParserLimits) -> StreamParser {
+        let mut builder = TreeBuilder::new();
+        builder.set_limits(limits);
+        StreamParser {
+            driver: PushDriver::wrap(limits.lexer(), RawParser::new()),
+            builder,
+            root: None,
+        }
+    }
+
+    /// The root element, with its attributes and namespace declarations,
+    /// once it has started and until it ends.
+    pub fn root(&self) -> Option<&Element> {
+        self.root.as_ref()
+    }
+
+    /// Unwrap the underlying parser and tree builder, to carry on parsing
+    /// the stream differently.
+    pub fn into_parts(self) -> (PushDriver<RawParser>, TreeBuilder) {
+        (self.driver, self.builder)
+    }
+
+    /// Parse the bytes of `data` until the next event, consuming them.
+    ///
+    /// Returns `None` once all of `data` got consumed without completing
+    /// an event, until more data comes in, or at the end of the document
+    /// if `at_eof`.  The errors have the path of the element they
+    /// happened in.
+    pub fn parse<B: Buf>(&mut self, data: &mut B, at_eof: bool) -> Result<Option<StreamEvent>> {
+        loop {
+            let event = match self.driver.parse(data, at_eof) {
+                Ok(Some(event)) => event,
+                Ok(None) => return Ok(None),
+                Err(rxml::Error::IO(e)) if e.kind() == io::ErrorKind::WouldBlock => {
+                    return Ok(None)
+                }
+                Err(e) => return Err(self.builder.error_context(e.into())),
+            };
+
+            if self.builder.depth() == 1 {
+                if let RawEvent::Text(_, text) = event {
+                    return Ok(Some(StreamEvent::Text(String::from(text))));
+                }
+            }
+
+            let had_root = self.builder.depth() > 0;
+            self.builder.process_event(event)?;
+            let depth = self.builder.depth();
+
+            if !had_root && depth > 0 {
+                let root = self.builder.top().unwrap().clone();
+                self.root = Some(root.clone());
+                return Ok(Some(StreamEvent::Start(root)));
+            } else if depth == 1 {
+                if let Some(child) = self.builder.unshift_child() {
+                    self.driver.release_temporaries();
+                    return Ok(Some(StreamEvent::Child(child)));
+                }
+            } else if self.builder.root.take().is_some() {
+                self.root = None;
+                self.driver.release_temporaries();
+                return Ok(Some(StreamEvent::End));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+
+    fn parse_all(parser: &mut StreamParser, mut data: &[u8]) -> Result<Vec<StreamEvent>> {
+        let mut events = Vec::new();
+        while let Some(event) = parser.parse(&mut data, false)? {
+            events.push(event);
+        }
+        Ok(events)
+    }
+
+    #[test]
+    fn test_stream() {
+        let mut parser = StreamParser::new();
+        let events = parse_all(
+            &mut parser,
+            b"<?xml version='1.0'?><stream:stream xmlns='jabber:client' xmlns:stream='http://etherx.jabber.org/streams' to='example.org'><message><body>hi</body></mess",
+        )
+        .unwrap();
+        assert_eq!(events.len(), 1);
+        match &events[0] {
+            StreamEvent::Start(root) => {
+                assert!(root.is("stream", "http://etherx.jabber.org/streams"));
+                assert_eq!(root.attr("to"), Some("example.org"));
+                assert_eq!(root.nodes().count(), 0);
+            }
+            other => panic!("Unexpected {:?}", other),
+        }
+        assert_eq!(parser.root().unwrap().attr("to"), Some("example.org"));
+
+        let events = parse_all(
+            &mut parser,
+            b"age>\n<iq type='get' id='1'/></stream:stream>",
+        )
+        .unwrap();
+        let message: Element = "<message xmlns='jabber:client'><body>hi</body></message>"
+            .parse()
+            .unwrap();
+        let iq: Element = "<iq xmlns='jabber:client' type='get' id='1'/>"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            events,
+            [
+                StreamEvent::Child(message),
+                StreamEvent::Text(String::from("\n")),
+                StreamEvent::Child(iq),
+                StreamEvent::End,
+            ]
+        );
+        assert!(parser.root().is_none());
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut parser = StreamParser::new();
+        let err = parse_all(
+            &mut parser,
+            b"<stream xmlns='jabber:client'><message><body></message>",
+        )
+        .unwrap_err();
+        assert_eq!(err.path(), Some("stream > message > body"));
+
+        let limits = ParserLimits {
+            max_depth: 2,
+            ..ParserLimits::default()
+        };
+        let mut parser = StreamParser::with_limits(limits);
+        let err = parse_all(
+            &mut parser,
+            b"<stream xmlns='jabber:client'><message><body/></message>",
+        )
+        .unwrap_err();
+        assert!(matches!(err.root_cause(), Error::LimitExceeded("depth")));
+    }
+}
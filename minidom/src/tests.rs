@@ -12,13 +12,14 @@
 
 use crate::element::Element;
 use crate::error::Error;
+use crate::tree_builder::TreeBuilder;
 
 const TEST_STRING: &'static [u8] = br#"<root xmlns='root_ns' a="b" xml:lang="en">meow<child c="d"/><child xmlns='child_ns' d="e" xml:lang="fr"/>nya</root>"#;
 
 fn build_test_tree() -> Element {
     let mut root = Element::builder("root", "root_ns")
-        .attr("xml:lang", "en")
         .attr("a", "b")
+        .attr("xml:lang", "en")
         .build();
     root.append_text_node("meow");
     let child = Element::builder("child", "root_ns").attr("c", "d").build();
@@ -51,7 +52,7 @@ fn reader_deduplicate_prefixes() {
         .unwrap();
 
     match r#"<p1:root xmlns:p1="ns1"><child/></p1:root>"#.parse::<Element>() {
-        Err(Error::MissingNamespace) => (),
+        Err(err) if matches!(err.root_cause(), Error::MissingNamespace) => (),
         Err(err) => panic!("No or wrong error: {:?}", err),
         Ok(elem) => panic!(
             "Got Element: {}; was expecting Error::MissingNamespace",
@@ -64,7 +65,7 @@ fn reader_deduplicate_prefixes() {
 fn reader_no_deduplicate_sibling_prefixes() {
     // The reader shouldn't reuse the sibling's prefixes
     match r#"<root xmlns="ns1"><p1:child1 xmlns:p1="ns2"/><p1:child2/></root>"#.parse::<Element>() {
-        Err(Error::MissingNamespace) => (),
+        Err(err) if matches!(err.root_cause(), Error::MissingNamespace) => (),
         Err(err) => panic!("No or wrong error: {:?}", err),
         Ok(elem) => panic!(
             "Got Element:\n{:?}\n{}\n; was expecting Error::MissingNamespace",
@@ -171,6 +172,53 @@ fn writer_with_prefix() {
     );
 }
 
+#[test]
+fn writer_prefixed() {
+    let features = Element::builder("features", "http://etherx.jabber.org/streams").build();
+    let message = Element::builder("message", "jabber:client").build();
+    let stream = Element::builder("stream", "http://etherx.jabber.org/streams")
+        .prefixed("stream")
+        .unwrap()
+        .prefix(None, "jabber:client")
+        .unwrap()
+        .append(features)
+        .append(message)
+        .build();
+    assert_eq!(
+        String::from(&stream),
+        "<stream:stream xmlns='jabber:client' xmlns:stream='http://etherx.jabber.org/streams'><stream:features/><message/></stream:stream>",
+    );
+
+    let err = Element::builder("stream", "http://etherx.jabber.org/streams")
+        .prefix(Some(String::from("stream")), "urn:example")
+        .unwrap()
+        .prefixed("stream");
+    assert!(matches!(err, Err(Error::DuplicatePrefix)));
+}
+
+#[test]
+fn writer_reuses_root_prefixes() {
+    // Parsed children keep their own declarations, which are already in
+    // scope once appended to this root.
+    let features: Element =
+        "<stream:features xmlns:stream='http://etherx.jabber.org/streams'><bind xmlns='urn:ietf:params:xml:ns:xmpp-bind'/></stream:features>"
+            .parse()
+            .unwrap();
+    let message: Element = "<message xmlns='jabber:client'/>".parse().unwrap();
+    let stream = Element::builder("stream", "http://etherx.jabber.org/streams")
+        .prefixed("stream")
+        .unwrap()
+        .prefix(None, "jabber:client")
+        .unwrap()
+        .append(features)
+        .append(message)
+        .build();
+    assert_eq!(
+        String::from(&stream),
+        "<stream:stream xmlns='jabber:client' xmlns:stream='http://etherx.jabber.org/streams'><stream:features><bind xmlns='urn:ietf:params:xml:ns:xmpp-bind'/></stream:features><message/></stream:stream>",
+    );
+}
+
 #[test]
 fn writer_no_prefix_namespace() {
     let root = Element::builder("root", "ns1").build();
@@ -420,27 +468,69 @@ fn namespace_inherited_prefixed2() {
 }
 
 #[test]
-fn fail_comments() {
-    let elem: Result<Element, Error> = "<foo xmlns='ns1'><!-- bar --></foo>".parse();
-    match elem {
-        Err(_) => (),
-        _ => panic!(),
-    };
+fn skip_comments() {
+    let elem: Element = "<a xmlns='ns1'><!-- c --><?pi x?><b/></a>".parse().unwrap();
+    let expected: Element = "<a xmlns='ns1'><b/></a>".parse().unwrap();
+    assert_eq!(elem, expected);
+    assert_eq!(elem.nodes().count(), 1);
+
+    let elem: Element = "<foo xmlns='ns1'>a<!-- bar -->b</foo>".parse().unwrap();
+    assert_eq!(elem.text(), "ab");
+
+    // The DOCTYPE declaration only gets skipped in the lenient mode.
+    let xml = "<?xml version='1.0'?>\n<!DOCTYPE foo>\n<?xml-stylesheet href='foo.xsl'?>\n<!-- bar -->\n<foo xmlns='ns1'><?php echo 'baz'; ?></foo>";
+    assert!(matches!(xml.parse::<Element>(), Err(Error::Doctype)));
+    let elem = Element::from_reader_lenient(xml.as_bytes()).unwrap();
+    assert!(elem.is("foo", "ns1"));
+    assert_eq!(elem.nodes().count(), 0);
 }
 
 #[test]
 fn xml_error() {
     match "<a xmlns='ns1'></b>".parse::<Element>() {
-        Err(crate::error::Error::XmlError(rxml::Error::Xml(
-            rxml::error::XmlError::ElementMismatch,
-        ))) => (),
+        Err(ref err)
+            if matches!(
+                err.root_cause(),
+                crate::error::Error::XmlError(rxml::Error::Xml(
+                    rxml::error::XmlError::ElementMismatch,
+                ))
+            ) => {}
         err => panic!("No or wrong error: {:?}", err),
     }
 
     match "<a xmlns='ns1'></".parse::<Element>() {
-        Err(crate::error::Error::XmlError(rxml::Error::Xml(
-            rxml::error::XmlError::InvalidEof(_),
-        ))) => (),
+        Err(ref err)
+            if matches!(
+                err.root_cause(),
+                crate::error::Error::XmlError(rxml::Error::Xml(rxml::error::XmlError::InvalidEof(
+                    _
+                ),))
+            ) => {}
+        err => panic!("No or wrong error: {:?}", err),
+    }
+}
+
+#[test]
+fn xml_declaration() {
+    let elem: Element = "<?xml version='1.0' encoding='UTF-8'?><a xmlns='ns1'/>"
+        .parse()
+        .unwrap();
+    assert!(elem.is("a", "ns1"));
+
+    let mut tree_builder = TreeBuilder::new();
+    let mut driver = rxml::PullDriver::wrap(
+        &b"<?xml version='1.0'?><a xmlns='ns1'/>"[..],
+        rxml::Lexer::new(),
+        rxml::RawParser::new(),
+    );
+    while let Some(event) = rxml::EventRead::read(&mut driver).unwrap() {
+        tree_builder.process_event(event).unwrap();
+    }
+    assert_eq!(tree_builder.xml_declaration(), Some(rxml::XmlVersion::V1_0));
+    assert!(tree_builder.root.is_some());
+
+    match "<?xml version='1.0' encoding='ISO-8859-1'?><a xmlns='ns1'/>".parse::<Element>() {
+        Err(Error::UnsupportedEncoding) => (),
         err => panic!("No or wrong error: {:?}", err),
     }
 }
@@ -448,7 +538,308 @@ fn xml_error() {
 #[test]
 fn missing_namespace_error() {
     match "<a/>".parse::<Element>() {
-        Err(crate::error::Error::MissingNamespace) => (),
+        Err(ref err) if matches!(err.root_cause(), crate::error::Error::MissingNamespace) => {}
         err => panic!("No or wrong error: {:?}", err),
     }
 }
+
+#[test]
+fn error_path() {
+    let err = "<iq xmlns='jabber:client'><pubsub xmlns='http://jabber.org/protocol/pubsub'><publish><p:item/></publish></pubsub></iq>"
+        .parse::<Element>()
+        .unwrap_err();
+    assert_eq!(err.path(), Some("iq > pubsub > publish > item"));
+    assert!(matches!(err.root_cause(), Error::MissingNamespace));
+    assert_eq!(
+        err.to_string(),
+        "the XML element is missing a namespace (in iq > pubsub > publish > item)"
+    );
+
+    let err = "<iq xmlns='jabber:client'><query xmlns='jabber:iq:roster'></iq>"
+        .parse::<Element>()
+        .unwrap_err();
+    assert_eq!(err.path(), Some("iq > query"));
+}
+
+#[test]
+fn tree_builder_inherited_prefixes() {
+    use rxml::{EventRead, Lexer, PullDriver, RawParser};
+    use std::collections::BTreeMap;
+
+    let mut prefixes = BTreeMap::new();
+    prefixes.insert(None, String::from("jabber:client"));
+    prefixes.insert(
+        Some(String::from("stream")),
+        String::from("http://etherx.jabber.org/streams"),
+    );
+    let mut tree_builder = TreeBuilder::with_inherited_prefixes(prefixes);
+    let xml: &[u8] = b"<message><stream:error/></message>";
+    let mut driver = PullDriver::wrap(xml, Lexer::new(), RawParser::new());
+    while let Some(event) = driver.read().unwrap() {
+        tree_builder.process_event(event).unwrap();
+    }
+    let elem = tree_builder.root.take().unwrap();
+    assert!(elem.is("message", "jabber:client"));
+    assert!(elem.has_child("error", "http://etherx.jabber.org/streams"));
+}
+
+#[test]
+fn parser_limits() {
+    use crate::tree_builder::ParserLimits;
+
+    let parse =
+        |xml: &str, limits: ParserLimits| Element::from_reader_with_limits(xml.as_bytes(), limits);
+    let limits = ParserLimits {
+        max_token_length: 16,
+        max_attributes: 2,
+        max_depth: 3,
+        max_elements: 4,
+    };
+    assert!(parse("<a xmlns='ns1'><b><c x='1'/></b><d/></a>", limits).is_ok());
+
+    let err = parse("<a xmlns='ns1'><b><c><d/></c></b></a>", limits).unwrap_err();
+    assert!(matches!(err.root_cause(), Error::LimitExceeded("depth")));
+    assert_eq!(err.path(), Some("a > b > c > d"));
+
+    let err = parse("<a xmlns='ns1'><b x='1' y='2' z='3'/></a>", limits).unwrap_err();
+    assert!(matches!(
+        err.root_cause(),
+        Error::LimitExceeded("attributes")
+    ));
+
+    let err = parse("<a xmlns='ns1'><b/><c/><d/><e/></a>", limits).unwrap_err();
+    assert!(matches!(err.root_cause(), Error::LimitExceeded("elements")));
+
+    let err = parse("<a xmlns='ns1' x='a very long attribute value'/>", limits).unwrap_err();
+    assert!(matches!(err.root_cause(), Error::XmlError(_)));
+
+    // The elements taken out of the tree builder don’t count anymore, as
+    // is the case of the stanzas of a stream.
+    let mut tree_builder = TreeBuilder::new();
+    tree_builder.set_limits(limits);
+    let mut driver = rxml::PullDriver::wrap(
+        &b"<stream xmlns='ns1'><a/><b><c/></b><d/><e/><f/>"[..],
+        limits.lexer(),
+        rxml::RawParser::new(),
+    );
+    let mut stanzas = 0;
+    while let Ok(Some(event)) = rxml::EventRead::read(&mut driver) {
+        tree_builder.process_event(event).unwrap();
+        if tree_builder.depth() == 1 && tree_builder.unshift_child().is_some() {
+            stanzas += 1;
+        }
+    }
+    assert_eq!(stanzas, 5);
+
+    // The default limits stop a document nested too deep, or an element
+    // with too many attributes.
+    let deep = "<a xmlns='ns1'>".repeat(1000);
+    let err = deep.parse::<Element>().unwrap_err();
+    assert!(matches!(err.root_cause(), Error::LimitExceeded("depth")));
+    let attrs: String = (0..1000).map(|i| format!(" a{}='{}'", i, i)).collect();
+    let err = format!("<a xmlns='ns1'{}/>", attrs)
+        .parse::<Element>()
+        .unwrap_err();
+    assert!(matches!(
+        err.root_cause(),
+        Error::LimitExceeded("attributes")
+    ));
+}
+
+#[test]
+fn malformed_xml() {
+    let error = |xml: &str| xml.parse::<Element>().unwrap_err();
+
+    let err = error("<a xmlns='ns1'><b x='1' x='2'/></a>");
+    assert!(matches!(err.root_cause(), Error::DuplicateAttribute(name) if name == "x"));
+    assert_eq!(err.path(), Some("a > b"));
+    let err = error("<a xmlns='ns1' xmlns:p='u' p:x='1' p:x='2'/>");
+    assert!(matches!(err.root_cause(), Error::DuplicateAttribute(name) if name == "p:x"));
+    let err = error("<a xmlns='ns1' xmlns:p='u' q:x='1' p:x='2' xmlns:q='u'/>");
+    assert!(matches!(err.root_cause(), Error::DuplicateAttribute(name) if name == "p:x"));
+    let err = error("<a xmlns='ns1' xmlns:p='u'><b xmlns:q='u' p:x='1' q:x='2'/></a>");
+    assert!(matches!(err.root_cause(), Error::DuplicateAttribute(name) if name == "q:x"));
+    assert_eq!(err.path(), Some("a > b"));
+    let elem: Element = "<a xmlns='ns1' xmlns:p='u' xmlns:q='v' x='0' p:x='1' q:x='2'/>"
+        .parse()
+        .unwrap();
+    assert_eq!(elem.attrs().count(), 3);
+
+    let err = error("<a xmlns='ns1' xmlns='ns2'/>");
+    assert!(matches!(err.root_cause(), Error::DuplicatePrefix));
+    let err = error("<a xmlns='ns1' xmlns:p='u' xmlns:p='v'/>");
+    assert!(matches!(err.root_cause(), Error::DuplicatePrefix));
+
+    let err = error("<a xmlns='ns1'><b q:x='1'/></a>");
+    assert!(matches!(err.root_cause(), Error::UndeclaredPrefix(prefix) if prefix == "q"));
+    assert_eq!(err.path(), Some("a > b"));
+
+    // Declared later on the same element, on an ancestor, or predefined.
+    let elem: Element =
+        "<a xmlns='ns1' xmlns:p='u'><b p:x='1' q:y='2' xmlns:q='v' xml:lang='en'/></a>"
+            .parse()
+            .unwrap();
+    assert_eq!(elem.get_child("b", "ns1").unwrap().attr("q:y"), Some("2"));
+
+    // The entities a DTD would declare are never expanded, in the lenient
+    // mode, and DTDs are rejected otherwise.
+    let xxe = "<!DOCTYPE a [<!ENTITY b SYSTEM 'file:///etc/passwd'>]><a xmlns='ns1'>&b;</a>";
+    assert!(matches!(error(xxe).root_cause(), Error::Doctype));
+    let err = Element::from_reader_lenient(xxe.as_bytes()).unwrap_err();
+    assert!(matches!(
+        err.root_cause(),
+        Error::XmlError(rxml::Error::Xml(rxml::error::XmlError::UndeclaredEntity))
+    ));
+
+    let err = error("<a xmlns='ns1'><b></a></b>");
+    assert!(matches!(
+        err.root_cause(),
+        Error::XmlError(rxml::Error::Xml(rxml::error::XmlError::ElementMismatch))
+    ));
+}
+
+#[test]
+fn writer_pretty() {
+    let elem: Element = "<a xmlns='ns1'><b>\n<c/>  </b><d>some <e>mixed</e> text</d><f/></a>"
+        .parse()
+        .unwrap();
+    let mut writer = Vec::new();
+    elem.write_pretty(&mut writer, "  ").unwrap();
+    assert_eq!(
+        String::from_utf8(writer).unwrap(),
+        "<a xmlns='ns1'>\n  <b>\n    <c/>\n  </b>\n  <d>some <e>mixed</e> text</d>\n  <f/>\n</a>"
+    );
+}
+
+#[test]
+fn writer_c14n() {
+    let c14n = |elem: &Element| {
+        let mut writer = Vec::new();
+        elem.write_c14n(&mut writer).unwrap();
+        String::from_utf8(writer).unwrap()
+    };
+
+    // From the Exclusive XML Canonicalization specification.
+    let elem: Element = "<n0:local xmlns:n0='foo:bar' xmlns:n3='ftp://example.org'><n1:elem2 xmlns:n1='http://example.net' xml:lang='en'><n3:stuff xmlns:n3='ftp://example.org'/></n1:elem2></n0:local>"
+        .parse()
+        .unwrap();
+    let elem2 = elem.get_child("elem2", "http://example.net").unwrap();
+    assert_eq!(
+        c14n(elem2),
+        r#"<n1:elem2 xmlns:n1="http://example.net" xml:lang="en"><n3:stuff xmlns:n3="ftp://example.org"></n3:stuff></n1:elem2>"#
+    );
+    assert_eq!(
+        c14n(&elem),
+        r#"<n0:local xmlns:n0="foo:bar"><n1:elem2 xmlns:n1="http://example.net" xml:lang="en"><n3:stuff xmlns:n3="ftp://example.org"></n3:stuff></n1:elem2></n0:local>"#
+    );
+
+    // Sorted attributes, escaping, and inherited default namespaces.
+    let elem: Element = "<a xmlns='ns1' xmlns:p='ns2' z='&quot;1&#9;' p:b='2' a='&lt;3'><b>x &amp; y &gt; z&#13;</b><c xmlns='ns3'/></a>"
+        .parse()
+        .unwrap();
+    assert_eq!(
+        c14n(&elem),
+        r#"<a xmlns="ns1" xmlns:p="ns2" a="&lt;3" z="&quot;1&#x9;" p:b="2"><b>x &amp; y &gt; z&#xD;</b><c xmlns="ns3"></c></a>"#
+    );
+}
+
+#[test]
+fn edit_in_place() {
+    let mut elem: Element = "<message xmlns='jabber:client' from='a@b/c' to='d@e' id='1'>hi<body>Hi</body><x xmlns='urn:x'/>!<y xmlns='urn:y'/></message>"
+        .parse()
+        .unwrap();
+    elem.set_attr("from", None::<String>);
+    elem.set_attrs(vec![("to", "f@g"), ("type", "chat")]);
+    assert_eq!(elem.remove_attr("id"), Some(String::from("1")));
+    assert_eq!(elem.remove_attr("id"), None);
+    elem.retain_attrs(|name, _| name != "type");
+    assert_eq!(elem.attrs().collect::<Vec<_>>(), [("to", "f@g")]);
+
+    assert!(elem
+        .replace_child(
+            "body",
+            "jabber:client",
+            Element::bare("subject", "jabber:client")
+        )
+        .is_some());
+    assert!(elem
+        .remove_child_by(|child| child.has_ns("urn:y"))
+        .is_some());
+    elem.retain_children(|child| child.has_ns("jabber:client"));
+    assert_eq!(elem.take_texts(), ["hi", "!"]);
+    assert_eq!(
+        String::from(&elem),
+        "<message xmlns='jabber:client' to=\"f@g\"><subject/></message>"
+    );
+}
+
+#[test]
+fn send_sync() {
+    // Parsed stanzas get moved across threads and shared between them.
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Element>();
+    assert_send_sync::<crate::Node>();
+    assert_send_sync::<Error>();
+}
+
+#[test]
+fn character_references() {
+    let parse = |text: &str| format!("<a xmlns='ns1' b='{0}'>{0}</a>", text).parse::<Element>();
+    let elem = parse("&#65;&#x1F600;&#xA0;&#9;").unwrap();
+    assert_eq!(elem.text(), "A😀\u{a0}\t");
+    assert_eq!(elem.attr("b"), Some("A😀\u{a0}\t"));
+    for invalid in [
+        "&#xD800;",
+        "&#xDFFF;",
+        "&#1;",
+        "&#x1B;",
+        "&#xFFFE;",
+        "&#x110000;",
+        "&#;",
+    ] {
+        assert!(parse(invalid).is_err(), "{} got accepted", invalid);
+    }
+}
+
+#[test]
+fn entity_policy() {
+    use crate::misc::EntityPolicy;
+
+    let xml = "<p xmlns='ns1' title='&eacute;'>caf&eacute;&amp;&nbsp;</p>";
+    assert!(xml.parse::<Element>().is_err());
+    let elem = Element::from_reader_with_entity_policy(xml.as_bytes(), EntityPolicy::Keep).unwrap();
+    assert_eq!(elem.text(), "caf&eacute;&&nbsp;");
+    assert_eq!(elem.attr("title"), Some("&eacute;"));
+    let elem =
+        Element::from_reader_with_entity_policy(xml.as_bytes(), EntityPolicy::Replace).unwrap();
+    assert_eq!(elem.text(), "caf\u{FFFD}&\u{FFFD}");
+}
+
+#[test]
+fn attribute_order() {
+    let xml = r#"<message xmlns='jabber:client' type="chat" to="juliet@example.com" id="1" from="romeo@example.net"><body xml:lang="en" b="2" a="1">Hi</body></message>"#;
+    let mut elem: Element = xml.parse().unwrap();
+    assert_eq!(String::from(&elem), xml);
+
+    // Replacing a value keeps its place, removing an attribute keeps the
+    // order of the others.
+    elem.set_attr("to", "juliet@example.com/balcony");
+    elem.remove_attr("type");
+    elem.set_attr("type", "normal");
+    assert_eq!(
+        elem.attrs().map(|(name, _)| name).collect::<Vec<_>>(),
+        ["to", "id", "from", "type"]
+    );
+
+    // Comparisons still ignore the order.
+    let other: Element = r#"<message xmlns='jabber:client' from="romeo@example.net" id="1" to="juliet@example.com/balcony" type="normal"><body a="1" b="2" xml:lang="en">Hi</body></message>"#
+        .parse()
+        .unwrap();
+    assert_eq!(elem, other);
+
+    elem.sort_attrs();
+    assert_eq!(
+        String::from(&elem),
+        r#"<message xmlns='jabber:client' from="romeo@example.net" id="1" to="juliet@example.com/balcony" type="normal"><body a="1" b="2" xml:lang="en">Hi</body></message>"#
+    );
+}
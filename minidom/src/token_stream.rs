@@ -0,0 +1,235 @@
+// Copyright (c) 2022 Emmanuel Gil Peyrot <linkmauve@linkmauve.fr>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Parsing XML out of a tokio [`AsyncRead`], like a socket.
+//!
+//! [`TokenStream`] reads as much as it needs to produce the next
+//! [`RawEvent`], which can then be fed to a
+//! [`TreeBuilder`](crate::tree_builder::TreeBuilder).  The comments and
+//! processing instructions get skipped, while a DOCTYPE declaration fails
+//! it with [`Error::Doctype`], unless it got made lenient to skip those
+//! too.
+
+use crate::misc::{EntityPolicy, MiscFilter};
+use crate::tree_builder::{ParserLimits, TreeBuilder};
+use crate::{Element, Error, Result};
+use futures_core::Stream;
+use rxml::bytes::BytesMut;
+use rxml::{PushDriver, RawEvent, RawParser};
+use std::future::poll_fn;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, ReadBuf};
+
+/// How much to read at once from the underlying reader.
+const READ_SIZE: usize = 4096;
+
+/// A [`Stream`] of the events parsed out of an [`AsyncRead`].
+///
+/// It ends at the end of the reader, or after the first error.
+pub struct TokenStream<R> {
+    reader: R,
+    driver: PushDriver<RawParser>,
+    limits: ParserLimits,
+    filter: MiscFilter,
+    filtered: Vec<u8>,
+    buf: BytesMut,
+    eof: bool,
+    done: bool,
+}
+
+impl<R: AsyncRead + Unpin> TokenStream<R> {
+    /// Parse the XML read from `reader`.
+    pub fn new(reader: R) -> TokenStream<R> {
+        TokenStream::with_limits(reader, ParserLimits::default())
+    }
+
+    /// Parse the XML read from `reader`, failing if it goes over
+    /// `limits`.
+    pub fn with_limits(reader: R, limits: ParserLimits) -> TokenStream<R> {
+        TokenStream {
+            reader,
+            driver: PushDriver::wrap(limits.lexer(), RawParser::new()),
+            limits,
+            filter: MiscFilter::new(),
+            filtered: Vec::new(),
+            buf: BytesMut::new(),
+            eof: false,
+            done: false,
+        }
+    }
+
+    /// Handle the references to entities other than the predefined ones
+    /// according to `policy`, in what gets read from now on.
+    pub fn set_entity_policy(&mut self, policy: EntityPolicy) {
+        self.filter.set_entity_policy(policy);
+    }
+
+    /// Skip the DOCTYPE declarations in what gets read from now on instead
+    /// of failing, or not.
+    pub fn set_lenient(&mut self, lenient: bool) {
+        self.filter.set_lenient(lenient);
+    }
+
+    /// The underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    /// Unwrap the underlying reader, dropping whatever got read from it but
+    /// not parsed yet.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Read the next whole element at the root of the document, which
+    /// ends this stream since a document has only one of them.
+    pub async fn next_element(&mut self) -> Option<Result<Element>> {
+        let mut builder = TreeBuilder::new();
+        builder.set_limits(self.limits);
+        loop {
+            let next = poll_fn(|cx| Pin::new(&mut *self).poll_next(cx));
+            let event = match next.await? {
+                Ok(event) => event,
+                Err(e) => return Some(Err(builder.error_context(e))),
+            };
+            if let Err(e) = builder.process_event(event) {
+                self.done = true;
+                return Some(Err(e));
+            }
+            if let Some(root) = builder.root.take() {
+                return Some(Ok(root));
+            }
+        }
+    }
+
+    fn poll_fill(&mut self, cx: &mut Context) -> Poll<io::Result<()>> {
+        let mut chunk = [0u8; READ_SIZE];
+        let mut read_buf = ReadBuf::new(&mut chunk);
+        match Pin::new(&mut self.reader).poll_read(cx, &mut read_buf) {
+            Poll::Ready(Ok(())) => {
+                let filled = read_buf.filled();
+                if filled.is_empty() {
+                    self.eof = true;
+                }
+                self.filtered.clear();
+                self.filter.feed(filled, &mut self.filtered);
+                self.buf.extend_from_slice(&self.filtered);
+                if self.filter.found_doctype() {
+                    return Poll::Ready(Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "DOCTYPE declaration",
+                    )));
+                }
+                Poll::Ready(Ok(()))
+            }
+            Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> Stream for TokenStream<R> {
+    type Item = Result<RawEvent>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        while !this.done {
+            let eof = this.eof;
+            match this.driver.parse(&mut this.buf, eof) {
+                Ok(Some(event)) => return Poll::Ready(Some(Ok(event))),
+                Ok(None) => this.done = true,
+                Err(rxml::Error::IO(e)) if e.kind() == io::ErrorKind::WouldBlock => {
+                    match this.poll_fill(cx) {
+                        Poll::Ready(Ok(())) => (),
+                        Poll::Ready(Err(_)) if this.filter.found_doctype() => {
+                            this.done = true;
+                            return Poll::Ready(Some(Err(Error::Doctype)));
+                        }
+                        Poll::Ready(Err(e)) => {
+                            this.done = true;
+                            return Poll::Ready(Some(Err(Error::from(rxml::Error::from(e)))));
+                        }
+                        Poll::Pending => return Poll::Pending,
+                    }
+                }
+                Err(e) => {
+                    this.done = true;
+                    return Poll::Ready(Some(Err(e.into())));
+                }
+            }
+        }
+        Poll::Ready(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{duplex, AsyncWriteExt};
+
+    async fn next(stream: &mut TokenStream<&[u8]>) -> Option<Result<RawEvent>> {
+        poll_fn(|cx| Pin::new(&mut *stream).poll_next(cx)).await
+    }
+
+    #[tokio::test]
+    async fn test_events() {
+        let mut stream = TokenStream::new(&b"<a xmlns='ns1'>text</a>"[..]);
+        assert!(matches!(
+            next(&mut stream).await,
+            Some(Ok(RawEvent::ElementHeadOpen(_, _)))
+        ));
+        assert!(matches!(
+            next(&mut stream).await,
+            Some(Ok(RawEvent::Attribute(_, _, _)))
+        ));
+        assert!(matches!(
+            next(&mut stream).await,
+            Some(Ok(RawEvent::ElementHeadClose(_)))
+        ));
+        match next(&mut stream).await {
+            Some(Ok(RawEvent::Text(_, text))) => assert_eq!(text.as_str(), "text"),
+            other => panic!("Unexpected {:?}", other),
+        }
+        assert!(matches!(
+            next(&mut stream).await,
+            Some(Ok(RawEvent::ElementFoot(_)))
+        ));
+        assert!(next(&mut stream).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_element_in_pieces() {
+        // A tiny pipe, so that the parser has to wait for more data a lot.
+        let (client, mut server) = duplex(3);
+        let write = async move {
+            server
+                .write_all(b"<a xmlns='ns1'><b>t\xc3\xa9xt</b><c/></a>")
+                .await
+                .unwrap();
+        };
+        let mut stream = TokenStream::new(client);
+        let (elem, ()) = tokio::join!(stream.next_element(), write);
+        let expected: Element = "<a xmlns='ns1'><b>téxt</b><c/></a>".parse().unwrap();
+        assert_eq!(elem.unwrap().unwrap(), expected);
+    }
+
+    #[tokio::test]
+    async fn test_errors() {
+        let mut stream = TokenStream::new(&b"<a xmlns='ns1'><b></c></a>"[..]);
+        let err = stream.next_element().await.unwrap().unwrap_err();
+        assert_eq!(err.path(), Some("a > b"));
+        assert!(stream.next_element().await.is_none());
+
+        let mut stream = TokenStream::new(&b"<a xmlns='ns1'><b>"[..]);
+        let err = stream.next_element().await.unwrap().unwrap_err();
+        assert!(matches!(
+            err.root_cause(),
+            Error::XmlError(rxml::Error::Xml(rxml::error::XmlError::InvalidEof(_)))
+        ));
+    }
+}
@@ -2,18 +2,80 @@
 
 //! SAX events to DOM tree conversion
 
-use crate::prefixes::{Prefix, Prefixes};
+use crate::c14n::XML_NS;
+use crate::intern::intern;
+use crate::prefixes::{Namespace, Prefix, Prefixes};
 use crate::{Element, Error};
-use rxml::RawEvent;
-use std::collections::BTreeMap;
+use indexmap::{map, IndexMap};
+use rxml::{Lexer, LexerOptions, RawEvent, XmlVersion};
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+
+/// Limits on what a document can contain, so that a malicious peer can’t
+/// exhaust our memory with a giant attribute or deeply nested elements.
+///
+/// The default ones limit the size of tokens like rxml does, and the
+/// number of attributes and the depth to what no sane document goes over,
+/// but not the number of elements.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParserLimits {
+    /// Maximum size in bytes of a name, attribute value or other token;
+    /// longer texts get split instead.
+    pub max_token_length: usize,
+
+    /// Maximum number of attributes on an element, including the namespace
+    /// declarations.
+    pub max_attributes: usize,
+
+    /// Maximum nesting depth of the elements, the root being at depth 1.
+    pub max_depth: usize,
+
+    /// Maximum number of elements held at once by the tree builder.
+    pub max_elements: usize,
+}
+
+impl Default for ParserLimits {
+    fn default() -> Self {
+        ParserLimits {
+            max_token_length: LexerOptions::default().max_token_length,
+            max_attributes: 256,
+            max_depth: 256,
+            max_elements: usize::MAX,
+        }
+    }
+}
+
+impl ParserLimits {
+    /// A lexer enforcing the maximum token length.
+    pub fn lexer(&self) -> Lexer {
+        Lexer::with_options(LexerOptions::default().max_token_length(self.max_token_length))
+    }
+}
+
+/// The start tag being parsed, until its attributes are all known.
+struct NextTag {
+    prefix: Prefix,
+    name: String,
+    prefixes: Prefixes,
+    attrs: IndexMap<Arc<str>, String>,
+}
 
 /// Tree-building parser state
 pub struct TreeBuilder {
-    next_tag: Option<(Prefix, String, Prefixes, BTreeMap<String, String>)>,
+    next_tag: Option<NextTag>,
+    /// Number of attributes of the next tag
+    next_attributes: usize,
+    /// Number of elements being held
+    elements: usize,
+    limits: ParserLimits,
     /// Parsing stack
     stack: Vec<Element>,
     /// Namespace set stack by prefix
     prefixes_stack: Vec<Prefixes>,
+    /// Namespace declarations inherited from outside of the document
+    inherited_prefixes: Prefixes,
+    /// The version given by the XML declaration, if any
+    xml_version: Option<XmlVersion>,
     /// Document root element if finished
     pub root: Option<Element>,
 }
@@ -23,17 +85,69 @@ impl TreeBuilder {
     pub fn new() -> Self {
         TreeBuilder {
             next_tag: None,
+            next_attributes: 0,
+            elements: 0,
+            limits: ParserLimits::default(),
             stack: vec![],
             prefixes_stack: vec![],
+            inherited_prefixes: Prefixes::default(),
+            xml_version: None,
             root: None,
         }
     }
 
+    /// Create a new one, which resolves the prefixes not declared in the
+    /// document itself against `prefixes`, for example the ones declared on
+    /// an enclosing `<stream:stream>` element.
+    pub fn with_inherited_prefixes(prefixes: BTreeMap<Prefix, Namespace>) -> Self {
+        TreeBuilder {
+            inherited_prefixes: Prefixes::from(prefixes),
+            ..TreeBuilder::new()
+        }
+    }
+
+    /// Enforce `limits` on the rest of the document.
+    pub fn set_limits(&mut self, limits: ParserLimits) {
+        self.limits = limits;
+    }
+
+    /// The limits enforced on the document.
+    pub fn limits(&self) -> ParserLimits {
+        self.limits
+    }
+
+    /// The XML version given by the declaration of the document, if it
+    /// started with one.  Its encoding has been checked to be UTF-8.
+    pub fn xml_declaration(&self) -> Option<XmlVersion> {
+        self.xml_version
+    }
+
     /// Stack depth
     pub fn depth(&self) -> usize {
         self.stack.len()
     }
 
+    /// The names of the elements currently open, from the root to the one
+    /// being parsed, like `iq > pubsub > publish > item`.
+    pub fn path(&self) -> String {
+        let mut path: Vec<&str> = self.stack.iter().map(Element::name).collect();
+        if let Some(tag) = &self.next_tag {
+            path.push(&tag.name);
+        }
+        path.join(" > ")
+    }
+
+    /// Attach the path of the element being parsed to `error`, if any and
+    /// if it doesn’t have one already.
+    pub fn error_context(&self, error: Error) -> Error {
+        let path = self.path();
+        match error {
+            Error::InElement(_, _) => error,
+            error if path.is_empty() => error,
+            error => Error::InElement(path, Box::new(error)),
+        }
+    }
+
     /// Get the top-most element from the stack but don't remove it
     pub fn top(&mut self) -> Option<&Element> {
         self.stack.last()
@@ -49,12 +163,67 @@ impl TreeBuilder {
     pub fn unshift_child(&mut self) -> Option<Element> {
         let depth = self.stack.len();
         if depth > 0 {
-            self.stack[depth - 1].unshift_child()
+            let child = self.stack[depth - 1].unshift_child();
+            if let Some(ref child) = child {
+                self.elements -= count_elements(child);
+            }
+            child
         } else {
             None
         }
     }
 
+    /// The first prefix of the attributes of the next tag which isn’t
+    /// declared, if any.
+    fn undeclared_attribute_prefix(&self) -> Option<String> {
+        let NextTag {
+            prefixes, attrs, ..
+        } = self.next_tag.as_ref()?;
+        attrs
+            .keys()
+            .filter_map(|name| name.split_once(':'))
+            .map(|(prefix, _)| prefix)
+            .find(|&prefix| {
+                let prefix = Some(String::from(prefix));
+                prefix.as_deref() != Some("xml")
+                    && prefixes.get(&prefix).is_none()
+                    && self.lookup_prefix(&prefix).is_none()
+            })
+            .map(String::from)
+    }
+
+    /// The first attribute of the next tag whose prefix resolves to the same
+    /// namespace as the one of another attribute with the same local name,
+    /// like `p:x` and `q:x` with both `p` and `q` bound to the same URI.
+    fn duplicate_namespaced_attribute(&self) -> Option<String> {
+        let NextTag {
+            prefixes, attrs, ..
+        } = self.next_tag.as_ref()?;
+        let mut seen = HashSet::new();
+        attrs
+            .keys()
+            .filter_map(|name| name.split_once(':').map(|split| (name, split)))
+            .find(|(_, (prefix, local))| {
+                let prefix = Some(String::from(*prefix));
+                let namespace = match prefix.as_deref() {
+                    Some("xml") => Some(XML_NS),
+                    _ => prefixes
+                        .get(&prefix)
+                        .map(String::as_str)
+                        .or_else(|| self.lookup_prefix(&prefix)),
+                };
+                match namespace {
+                    Some(namespace) => !seen.insert((namespace, *local)),
+                    None => false,
+                }
+            })
+            .map(|(name, _)| String::from(&**name))
+    }
+
+    fn limit_exceeded(&self, limit: &'static str) -> Error {
+        self.error_context(Error::LimitExceeded(limit))
+    }
+
     /// Lookup XML namespace declaration for given prefix (or no prefix)
     fn lookup_prefix(&self, prefix: &Option<String>) -> Option<&str> {
         for nss in self.prefixes_stack.iter().rev() {
@@ -63,7 +232,7 @@ impl TreeBuilder {
             }
         }
 
-        None
+        self.inherited_prefixes.get(prefix).map(String::as_str)
     }
 
     fn process_end_tag(&mut self) -> Result<(), Error> {
@@ -89,60 +258,124 @@ impl TreeBuilder {
     /// Process a Event that you got out of a RawParser
     pub fn process_event(&mut self, event: RawEvent) -> Result<(), Error> {
         match event {
-            RawEvent::XmlDeclaration(_, _) => {}
+            RawEvent::XmlDeclaration(_, version) => self.xml_version = Some(version),
 
             RawEvent::ElementHeadOpen(_, (prefix, name)) => {
-                self.next_tag = Some((
-                    prefix.map(|prefix| prefix.as_str().to_owned()),
-                    name.as_str().to_owned(),
-                    Prefixes::default(),
-                    BTreeMap::new(),
-                ))
+                self.next_tag = Some(NextTag {
+                    prefix: prefix.map(String::from),
+                    name: String::from(name),
+                    prefixes: Prefixes::default(),
+                    attrs: IndexMap::new(),
+                });
+                self.next_attributes = 0;
+                if self.depth() >= self.limits.max_depth {
+                    return Err(self.limit_exceeded("depth"));
+                }
+                if self.elements >= self.limits.max_elements {
+                    return Err(self.limit_exceeded("elements"));
+                }
+                self.elements += 1;
             }
 
             RawEvent::Attribute(_, (prefix, name), value) => {
-                self.next_tag
-                    .as_mut()
-                    .map(
-                        |(_, _, ref mut prefixes, ref mut attrs)| match (prefix, name) {
-                            (None, xmlns) if xmlns == "xmlns" => {
+                self.next_attributes += 1;
+                if self.next_attributes > self.limits.max_attributes {
+                    return Err(self.limit_exceeded("attributes"));
+                }
+                let duplicate = match self.next_tag.as_mut() {
+                    Some(NextTag {
+                        ref mut prefixes,
+                        ref mut attrs,
+                        ..
+                    }) => match (prefix, name) {
+                        (None, xmlns) if xmlns == "xmlns" => {
+                            if prefixes.get(&None).is_some() {
+                                Some(Error::DuplicatePrefix)
+                            } else {
                                 prefixes.insert(None, value);
+                                None
                             }
-                            (Some(xmlns), prefix) if xmlns.as_str() == "xmlns" => {
-                                prefixes.insert(Some(prefix.as_str().to_owned()), value);
-                            }
-                            (Some(prefix), name) => {
-                                attrs.insert(
-                                    format!("{}:{}", prefix, name),
-                                    value.as_str().to_owned(),
-                                );
+                        }
+                        (Some(xmlns), prefix) if xmlns.as_str() == "xmlns" => {
+                            let prefix = Some(String::from(prefix));
+                            if prefixes.get(&prefix).is_some() {
+                                Some(Error::DuplicatePrefix)
+                            } else {
+                                prefixes.insert(prefix, value);
+                                None
                             }
-                            (None, name) => {
-                                attrs.insert(name.as_str().to_owned(), value.as_str().to_owned());
+                        }
+                        (prefix, name) => {
+                            let name = match prefix {
+                                Some(prefix) => format!("{}:{}", prefix, name),
+                                None => String::from(name),
+                            };
+                            match attrs.entry(intern(&name)) {
+                                map::Entry::Occupied(_) => Some(Error::DuplicateAttribute(name)),
+                                map::Entry::Vacant(entry) => {
+                                    entry.insert(String::from(value));
+                                    None
+                                }
                             }
-                        },
-                    );
+                        }
+                    },
+                    None => None,
+                };
+                if let Some(error) = duplicate {
+                    return Err(self.error_context(error));
+                }
             }
 
             RawEvent::ElementHeadClose(_) => {
-                if let Some((prefix, name, prefixes, attrs)) = self.next_tag.take() {
+                if let Some(prefix) = self.undeclared_attribute_prefix() {
+                    return Err(self.error_context(Error::UndeclaredPrefix(prefix)));
+                }
+                if let Some(name) = self.duplicate_namespaced_attribute() {
+                    return Err(self.error_context(Error::DuplicateAttribute(name)));
+                }
+                if let Some(NextTag {
+                    prefix,
+                    name,
+                    prefixes,
+                    attrs,
+                }) = self.next_tag.take()
+                {
                     self.prefixes_stack.push(prefixes.clone());
 
-                    let namespace = self
-                        .lookup_prefix(&prefix.clone().map(|prefix| prefix.as_str().to_owned()))
-                        .ok_or(Error::MissingNamespace)?
-                        .to_owned();
-                    let el =
-                        Element::new(name.as_str().to_owned(), namespace, prefixes, attrs, vec![]);
+                    let namespace = match self.lookup_prefix(&prefix) {
+                        Some(namespace) => namespace,
+                        None => {
+                            let mut path = self.path();
+                            if !path.is_empty() {
+                                path.push_str(" > ");
+                            }
+                            path.push_str(&name);
+                            return Err(Error::InElement(path, Box::new(Error::MissingNamespace)));
+                        }
+                    };
+                    let el = Element::new(&name, namespace, prefixes, attrs, vec![]);
                     self.stack.push(el);
                 }
             }
 
             RawEvent::ElementFoot(_) => self.process_end_tag()?,
 
-            RawEvent::Text(_, text) => self.process_text(text.as_str().to_owned()),
+            // The text is moved out of the event, not copied.
+            RawEvent::Text(_, text) => self.process_text(String::from(text)),
         }
 
         Ok(())
     }
 }
+
+/// The number of elements in the tree of `element`, counted without
+/// recursing so that a deep tree can't overflow the stack.
+fn count_elements(element: &Element) -> usize {
+    let mut count = 0;
+    let mut stack = vec![element];
+    while let Some(element) = stack.pop() {
+        count += 1;
+        stack.extend(element.children());
+    }
+    count
+}
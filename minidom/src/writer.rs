@@ -0,0 +1,216 @@
+// Copyright (c) 2022 Emmanuel Gil Peyrot <linkmauve@linkmauve.fr>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Serialising elements one after the other, like the stanzas of a stream.
+//!
+//! Unlike [`Element::write_to`], a [`Writer`] remembers the elements it
+//! opened with [`Writer::write_start`] and the namespaces they declared, so
+//! that the elements written inside of them don’t redeclare their
+//! namespace every time.
+
+use crate::element::ItemWriter;
+use crate::{Element, Result};
+use rxml::writer::Item;
+use rxml::XmlVersion;
+use std::convert::TryInto;
+use std::io::Write;
+
+/// Serialises elements, text and the start and end of enclosing elements
+/// to a [`Write`], incrementally.
+pub struct Writer<W: Write> {
+    inner: ItemWriter<W>,
+}
+
+impl<W: Write> Writer<W> {
+    /// Write to `writer`.
+    pub fn new(writer: W) -> Writer<W> {
+        Writer {
+            inner: ItemWriter::new(writer),
+        }
+    }
+
+    /// Write the `<?xml?>` declaration, which must come first.
+    pub fn write_declaration(&mut self) -> Result<()> {
+        self.inner.write(Item::XmlDeclaration(XmlVersion::V1_0))?;
+        Ok(())
+    }
+
+    /// Write the opening tag of `elem`, without its children, like
+    /// `<stream:stream>`.  Its namespace declarations are then inherited
+    /// by the elements written until the matching
+    /// [`write_end()`](Writer::write_end).
+    pub fn write_start(&mut self, elem: &Element) -> Result<()> {
+        elem.write_head(&mut self.inner)?;
+        self.inner.write(Item::ElementHeadEnd)?;
+        Ok(())
+    }
+
+    /// Write the closing tag of the last element opened with
+    /// [`write_start()`](Writer::write_start).
+    pub fn write_end(&mut self) -> Result<()> {
+        self.inner.write(Item::ElementFoot)?;
+        Ok(())
+    }
+
+    /// Write a whole element, declaring only the namespaces not inherited
+    /// already.
+    pub fn write_element(&mut self, elem: &Element) -> Result<()> {
+        elem.write_to_inner(&mut self.inner)
+    }
+
+    /// Write some text, escaped.
+    pub fn write_text(&mut self, text: &str) -> Result<()> {
+        self.inner.write(Item::Text(text.try_into()?))?;
+        Ok(())
+    }
+
+    /// Flush the underlying writer.
+    pub fn flush(&mut self) -> Result<()> {
+        self.inner.get_mut().flush().map_err(rxml::Error::from)?;
+        Ok(())
+    }
+
+    /// The underlying writer.
+    pub fn get_ref(&self) -> &W {
+        self.inner.get_ref()
+    }
+
+    /// The underlying writer, to which writing directly would confuse the
+    /// namespace tracking.
+    pub fn get_mut(&mut self) -> &mut W {
+        self.inner.get_mut()
+    }
+
+    /// Unwrap the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.inner.into_inner()
+    }
+}
+
+/// Like [`Writer`], for a tokio [`AsyncWrite`](tokio::io::AsyncWrite).
+///
+/// Each item gets serialised in memory, then written out as a whole.
+#[cfg(feature = "tokio")]
+pub struct AsyncWriter<W> {
+    writer: W,
+    buf: Writer<Vec<u8>>,
+}
+
+#[cfg(feature = "tokio")]
+impl<W: tokio::io::AsyncWrite + Unpin> AsyncWriter<W> {
+    /// Write to `writer`.
+    pub fn new(writer: W) -> AsyncWriter<W> {
+        AsyncWriter {
+            writer,
+            buf: Writer::new(Vec::new()),
+        }
+    }
+
+    async fn write_buf(&mut self) -> Result<()> {
+        use tokio::io::AsyncWriteExt;
+        let buf = self.buf.get_mut();
+        let result = self.writer.write_all(buf).await;
+        buf.clear();
+        result.map_err(rxml::Error::from)?;
+        Ok(())
+    }
+
+    /// See [`Writer::write_declaration`].
+    pub async fn write_declaration(&mut self) -> Result<()> {
+        self.buf.write_declaration()?;
+        self.write_buf().await
+    }
+
+    /// See [`Writer::write_start`].
+    pub async fn write_start(&mut self, elem: &Element) -> Result<()> {
+        self.buf.write_start(elem)?;
+        self.write_buf().await
+    }
+
+    /// See [`Writer::write_end`].
+    pub async fn write_end(&mut self) -> Result<()> {
+        self.buf.write_end()?;
+        self.write_buf().await
+    }
+
+    /// See [`Writer::write_element`].
+    pub async fn write_element(&mut self, elem: &Element) -> Result<()> {
+        self.buf.write_element(elem)?;
+        self.write_buf().await
+    }
+
+    /// See [`Writer::write_text`].
+    pub async fn write_text(&mut self, text: &str) -> Result<()> {
+        self.buf.write_text(text)?;
+        self.write_buf().await
+    }
+
+    /// Flush the underlying writer.
+    pub async fn flush(&mut self) -> Result<()> {
+        use tokio::io::AsyncWriteExt;
+        self.writer.flush().await.map_err(rxml::Error::from)?;
+        Ok(())
+    }
+
+    /// Unwrap the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn output(writer: Writer<Vec<u8>>) -> String {
+        String::from_utf8(writer.into_inner()).unwrap()
+    }
+
+    #[test]
+    fn test_stream() {
+        let stream = Element::builder("stream", "http://etherx.jabber.org/streams")
+            .prefixed("stream")
+            .unwrap()
+            .prefix(None, "jabber:client")
+            .unwrap()
+            .attr("to", "example.org")
+            .build();
+        let message = Element::builder("message", "jabber:client")
+            .attr("to", "juliet@example.org")
+            .append(Element::builder("body", "jabber:client").append("<3 & 'hi'"))
+            .build();
+        let ping = Element::bare("ping", "urn:xmpp:ping");
+
+        let mut writer = Writer::new(Vec::new());
+        writer.write_declaration().unwrap();
+        writer.write_start(&stream).unwrap();
+        writer.write_element(&message).unwrap();
+        writer.write_text("\n").unwrap();
+        writer.write_element(&ping).unwrap();
+        writer.write_end().unwrap();
+        assert_eq!(
+            output(writer),
+            "<?xml version='1.0' encoding='utf-8'?>\n<stream:stream xmlns='jabber:client' xmlns:stream='http://etherx.jabber.org/streams' to=\"example.org\"><message to=\"juliet@example.org\"><body>&lt;3 &amp; 'hi'</body></message>\n<ping xmlns='urn:xmpp:ping'/></stream:stream>"
+        );
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn test_async() {
+        let stream = Element::bare("stream", "jabber:client");
+        let mut writer = AsyncWriter::new(Vec::new());
+        writer.write_start(&stream).await.unwrap();
+        writer
+            .write_element(&Element::bare("message", "jabber:client"))
+            .await
+            .unwrap();
+        writer.write_end().await.unwrap();
+        assert_eq!(
+            String::from_utf8(writer.into_inner()).unwrap(),
+            "<stream xmlns='jabber:client'><message/></stream>"
+        );
+    }
+}
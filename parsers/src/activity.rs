@@ -0,0 +1,450 @@
+// Copyright (c) 2022 Emmanuel Gil Peyrot <linkmauve@linkmauve.fr>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use crate::ns;
+use crate::pubsub::PubSubPayload;
+use crate::util::error::Error;
+use crate::Element;
+use std::convert::TryFrom;
+
+generate_element_enum!(
+    /// Enum representing all of the possible values of the XEP-0108
+    /// general activities.
+    GeneralActivity, "activity", ACTIVITY, {
+        /// Doing chores.
+        DoingChores => "doing_chores",
+
+        /// Drinking.
+        Drinking => "drinking",
+
+        /// Eating.
+        Eating => "eating",
+
+        /// Exercising.
+        Exercising => "exercising",
+
+        /// Grooming.
+        Grooming => "grooming",
+
+        /// Having an appointment.
+        HavingAppointment => "having_appointment",
+
+        /// Inactive.
+        Inactive => "inactive",
+
+        /// Relaxing.
+        Relaxing => "relaxing",
+
+        /// Talking.
+        Talking => "talking",
+
+        /// Traveling.
+        Traveling => "traveling",
+
+        /// [Doing any activity not defined here.]
+        Undefined => "undefined",
+
+        /// Working.
+        Working => "working",
+    }
+);
+
+generate_element_enum!(
+    /// Enum representing all of the possible values of the XEP-0108
+    /// specific activities, each of them refining some general activity.
+    SpecificActivity, "activity", ACTIVITY, {
+        /// Doing chores: buying groceries.
+        BuyingGroceries => "buying_groceries",
+
+        /// Doing chores: cleaning.
+        Cleaning => "cleaning",
+
+        /// Doing chores: cooking.
+        Cooking => "cooking",
+
+        /// Doing chores: doing maintenance.
+        DoingMaintenance => "doing_maintenance",
+
+        /// Doing chores: doing the dishes.
+        DoingTheDishes => "doing_the_dishes",
+
+        /// Doing chores: doing the laundry.
+        DoingTheLaundry => "doing_the_laundry",
+
+        /// Doing chores: gardening.
+        Gardening => "gardening",
+
+        /// Doing chores: running an errand.
+        RunningAnErrand => "running_an_errand",
+
+        /// Doing chores: walking the dog.
+        WalkingTheDog => "walking_the_dog",
+
+        /// Drinking: having a beer.
+        HavingABeer => "having_a_beer",
+
+        /// Drinking: having coffee.
+        HavingCoffee => "having_coffee",
+
+        /// Drinking: having tea.
+        HavingTea => "having_tea",
+
+        /// Eating: having a snack.
+        HavingASnack => "having_a_snack",
+
+        /// Eating: having breakfast.
+        HavingBreakfast => "having_breakfast",
+
+        /// Eating: having dinner.
+        HavingDinner => "having_dinner",
+
+        /// Eating: having lunch.
+        HavingLunch => "having_lunch",
+
+        /// Exercising or traveling: cycling.
+        Cycling => "cycling",
+
+        /// Exercising: dancing.
+        Dancing => "dancing",
+
+        /// Exercising: hiking.
+        Hiking => "hiking",
+
+        /// Exercising: jogging.
+        Jogging => "jogging",
+
+        /// Exercising: playing sports.
+        PlayingSports => "playing_sports",
+
+        /// Exercising: running.
+        Running => "running",
+
+        /// Exercising: skiing.
+        Skiing => "skiing",
+
+        /// Exercising: swimming.
+        Swimming => "swimming",
+
+        /// Exercising: working out.
+        WorkingOut => "working_out",
+
+        /// Grooming: at the spa.
+        AtTheSpa => "at_the_spa",
+
+        /// Grooming: brushing teeth.
+        BrushingTeeth => "brushing_teeth",
+
+        /// Grooming: getting a haircut.
+        GettingAHaircut => "getting_a_haircut",
+
+        /// Grooming: shaving.
+        Shaving => "shaving",
+
+        /// Grooming: taking a bath.
+        TakingABath => "taking_a_bath",
+
+        /// Grooming: taking a shower.
+        TakingAShower => "taking_a_shower",
+
+        /// Inactive: day off.
+        DayOff => "day_off",
+
+        /// Inactive: hanging out.
+        HangingOut => "hanging_out",
+
+        /// Inactive: hiding.
+        Hiding => "hiding",
+
+        /// Inactive: on vacation.
+        OnVacation => "on_vacation",
+
+        /// Inactive: praying.
+        Praying => "praying",
+
+        /// Inactive: scheduled holiday.
+        ScheduledHoliday => "scheduled_holiday",
+
+        /// Inactive: sleeping.
+        Sleeping => "sleeping",
+
+        /// Inactive: thinking.
+        Thinking => "thinking",
+
+        /// Relaxing: fishing.
+        Fishing => "fishing",
+
+        /// Relaxing: gaming.
+        Gaming => "gaming",
+
+        /// Relaxing: going out.
+        GoingOut => "going_out",
+
+        /// Relaxing: partying.
+        Partying => "partying",
+
+        /// Relaxing: reading.
+        Reading => "reading",
+
+        /// Relaxing: rehearsing.
+        Rehearsing => "rehearsing",
+
+        /// Relaxing: shopping.
+        Shopping => "shopping",
+
+        /// Relaxing: smoking.
+        Smoking => "smoking",
+
+        /// Relaxing: socializing.
+        Socializing => "socializing",
+
+        /// Relaxing: sunbathing.
+        Sunbathing => "sunbathing",
+
+        /// Relaxing: watching TV.
+        WatchingTv => "watching_tv",
+
+        /// Relaxing: watching a movie.
+        WatchingAMovie => "watching_a_movie",
+
+        /// Talking: in real life.
+        InRealLife => "in_real_life",
+
+        /// Talking: on the phone.
+        OnThePhone => "on_the_phone",
+
+        /// Talking: on video phone.
+        OnVideoPhone => "on_video_phone",
+
+        /// Traveling: commuting.
+        Commuting => "commuting",
+
+        /// Traveling: driving.
+        Driving => "driving",
+
+        /// Traveling: in a car.
+        InACar => "in_a_car",
+
+        /// Traveling: on a bus.
+        OnABus => "on_a_bus",
+
+        /// Traveling: on a plane.
+        OnAPlane => "on_a_plane",
+
+        /// Traveling: on a train.
+        OnATrain => "on_a_train",
+
+        /// Traveling: on a trip.
+        OnATrip => "on_a_trip",
+
+        /// Traveling: walking.
+        Walking => "walking",
+
+        /// Working: coding.
+        Coding => "coding",
+
+        /// Working: in a meeting.
+        InAMeeting => "in_a_meeting",
+
+        /// Working: studying.
+        Studying => "studying",
+
+        /// Working: writing.
+        Writing => "writing",
+
+        /// [Doing any specific activity not defined here.]
+        Other => "other",
+    }
+);
+
+generate_elem_id!(
+    /// Free-form text description of the activity.
+    Text,
+    "text",
+    ACTIVITY
+);
+
+/// The activity of a user, or no activity once they stopped publishing
+/// one.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Activity {
+    /// The general activity, `None` if the user doesn’t want to share one
+    /// anymore.
+    pub general: Option<GeneralActivity>,
+
+    /// What the user is doing more specifically, within the general
+    /// activity.
+    pub specific: Option<SpecificActivity>,
+
+    /// A free-form description of this activity.
+    pub text: Option<String>,
+}
+
+impl PubSubPayload for Activity {}
+
+impl Activity {
+    /// Create a new activity.
+    pub fn new(general: GeneralActivity) -> Activity {
+        Activity {
+            general: Some(general),
+            specific: None,
+            text: None,
+        }
+    }
+
+    /// Tell more specifically what the user is doing.
+    pub fn with_specific(mut self, specific: SpecificActivity) -> Activity {
+        self.specific = Some(specific);
+        self
+    }
+
+    /// Describe this activity with some text.
+    pub fn with_text<S: Into<String>>(mut self, text: S) -> Activity {
+        self.text = Some(text.into());
+        self
+    }
+}
+
+impl TryFrom<Element> for Activity {
+    type Error = Error;
+
+    fn try_from(elem: Element) -> Result<Activity, Error> {
+        check_self!(elem, "activity", ACTIVITY);
+        check_no_attributes!(elem, "activity");
+
+        let mut activity = Activity::default();
+        for child in elem.children() {
+            if child.is("text", ns::ACTIVITY) {
+                if activity.text.is_some() {
+                    return Err(Error::ParseError("Activity can’t have more than one text."));
+                }
+                activity.text = Some(Text::try_from(child.clone())?.0);
+                continue;
+            }
+            if activity.general.is_some() {
+                return Err(Error::ParseError(
+                    "Activity can’t have more than one general activity.",
+                ));
+            }
+            // The specific activity is the only child of the general one.
+            let general = Element::bare(child.name(), ns::ACTIVITY);
+            activity.general = Some(GeneralActivity::try_from(general)?);
+            let mut specifics = child.children();
+            if let Some(specific) = specifics.next() {
+                activity.specific = Some(SpecificActivity::try_from(specific.clone())?);
+            }
+            if specifics.next().is_some() {
+                return Err(Error::ParseError(
+                    "Activity can’t have more than one specific activity.",
+                ));
+            }
+        }
+
+        Ok(activity)
+    }
+}
+
+impl From<Activity> for Element {
+    fn from(activity: Activity) -> Element {
+        let specific = activity.specific;
+        let general = activity.general.map(|general| {
+            let mut general = Element::from(general);
+            if let Some(specific) = specific {
+                general.append_child(Element::from(specific));
+            }
+            general
+        });
+        Element::builder("activity", ns::ACTIVITY)
+            .append_all(general)
+            .append_all(activity.text.map(Text))
+            .build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(target_pointer_width = "32")]
+    #[test]
+    fn test_size() {
+        assert_size!(GeneralActivity, 1);
+        assert_size!(SpecificActivity, 1);
+        assert_size!(Activity, 16);
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    fn test_size() {
+        assert_size!(GeneralActivity, 1);
+        assert_size!(SpecificActivity, 1);
+        assert_size!(Activity, 32);
+    }
+
+    #[test]
+    fn test_simple() {
+        let elem: Element = "<activity xmlns='http://jabber.org/protocol/activity'><relaxing><partying/></relaxing><text>My nurse’s birthday!</text></activity>"
+            .parse()
+            .unwrap();
+        let activity = Activity::try_from(elem.clone()).unwrap();
+        assert_eq!(activity.general, Some(GeneralActivity::Relaxing));
+        assert_eq!(activity.specific, Some(SpecificActivity::Partying));
+        assert_eq!(activity.text.as_deref(), Some("My nurse’s birthday!"));
+
+        let activity = Activity::new(GeneralActivity::Relaxing)
+            .with_specific(SpecificActivity::Partying)
+            .with_text("My nurse’s birthday!");
+        assert_eq!(Element::from(activity), elem);
+    }
+
+    #[test]
+    fn test_general_only() {
+        let elem: Element =
+            "<activity xmlns='http://jabber.org/protocol/activity'><having_appointment/></activity>"
+                .parse()
+                .unwrap();
+        let activity = Activity::try_from(elem.clone()).unwrap();
+        assert_eq!(activity, Activity::new(GeneralActivity::HavingAppointment));
+        assert_eq!(Element::from(activity), elem);
+    }
+
+    #[test]
+    fn test_empty() {
+        let elem: Element = "<activity xmlns='http://jabber.org/protocol/activity'/>"
+            .parse()
+            .unwrap();
+        let activity = Activity::try_from(elem.clone()).unwrap();
+        assert_eq!(activity, Activity::default());
+        assert_eq!(Element::from(activity), elem);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let elem: Element =
+            "<activity xmlns='http://jabber.org/protocol/activity'><flying/></activity>"
+                .parse()
+                .unwrap();
+        let error = Activity::try_from(elem).unwrap_err();
+        let message = match error {
+            Error::ParseError(string) => string,
+            _ => panic!(),
+        };
+        assert_eq!(message, "This is not a activity element.");
+
+        let elem: Element =
+            "<activity xmlns='http://jabber.org/protocol/activity'><eating/><drinking/></activity>"
+                .parse()
+                .unwrap();
+        let error = Activity::try_from(elem).unwrap_err();
+        let message = match error {
+            Error::ParseError(string) => string,
+            _ => panic!(),
+        };
+        assert_eq!(
+            message,
+            "Activity can’t have more than one general activity."
+        );
+    }
+}
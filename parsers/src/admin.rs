@@ -0,0 +1,446 @@
+// Copyright (c) 2022 Emmanuel Gil Peyrot <linkmauve@linkmauve.fr>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use crate::commands::CommandSession;
+use crate::data_forms::{DataForm, DataFormType, Field, FieldType};
+use crate::ns;
+use crate::util::error::Error;
+use jid::{BareJid, Jid};
+use std::convert::TryFrom;
+use std::str::FromStr;
+
+/// The standard administration commands handled by this module, each of
+/// them being an ad-hoc command (XEP-0050) executed on the service.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdminCommand {
+    /// Registers a new account, see [AddUser].
+    AddUser,
+
+    /// Removes some accounts, see [DeleteUser].
+    DeleteUser,
+
+    /// Closes all of the sessions of some accounts, or only some of them,
+    /// see [EndUserSession].
+    EndUserSession,
+
+    /// Lists the users currently online, see [GetOnlineUsers] and
+    /// [OnlineUsers].
+    GetOnlineUsers,
+}
+
+impl AdminCommand {
+    /// The node of this command.
+    pub fn node(self) -> &'static str {
+        match self {
+            AdminCommand::AddUser => "http://jabber.org/protocol/admin#add-user",
+            AdminCommand::DeleteUser => "http://jabber.org/protocol/admin#delete-user",
+            AdminCommand::EndUserSession => "http://jabber.org/protocol/admin#end-user-session",
+            AdminCommand::GetOnlineUsers => "http://jabber.org/protocol/admin#get-online-users",
+        }
+    }
+
+    /// The command of this node, if it is one of the handled ones.
+    pub fn from_node(node: &str) -> Option<AdminCommand> {
+        [
+            AdminCommand::AddUser,
+            AdminCommand::DeleteUser,
+            AdminCommand::EndUserSession,
+            AdminCommand::GetOnlineUsers,
+        ]
+        .iter()
+        .copied()
+        .find(|command| command.node() == node)
+    }
+
+    /// Prepares the execution of this command, the service replying to
+    /// its start with a form to submit, for instance
+    /// `DataForm::from(AddUser { … })`.
+    pub fn session(self) -> CommandSession {
+        CommandSession::new(self.node())
+    }
+}
+
+fn check_form_type(form: &DataForm) -> Result<(), Error> {
+    if form.form_type.as_deref() != Some(ns::ADMIN) {
+        return Err(Error::ParseError(
+            "Wrong FORM_TYPE for an administration command.",
+        ));
+    }
+    Ok(())
+}
+
+fn parse_jids<T: FromStr>(field: &Field) -> Result<Vec<T>, Error>
+where
+    Error: From<T::Err>,
+{
+    field
+        .values
+        .iter()
+        .map(|value| Ok(T::from_str(value)?))
+        .collect()
+}
+
+fn submit(fields: Vec<Field>) -> DataForm {
+    DataForm::new(DataFormType::Submit, ns::ADMIN, fields)
+}
+
+/// The form of the [add-user](enum.AdminCommand.html#variant.AddUser)
+/// command.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AddUser {
+    /// The JID of the new account.
+    pub jid: BareJid,
+
+    /// The password of the new account.
+    pub password: String,
+
+    /// The email address of the user.
+    pub email: Option<String>,
+
+    /// The given name of the user.
+    pub given_name: Option<String>,
+
+    /// The family name of the user.
+    pub surname: Option<String>,
+}
+
+impl AddUser {
+    /// Creates the account `jid`, protected by `password`.
+    pub fn new<P: Into<String>>(jid: BareJid, password: P) -> AddUser {
+        AddUser {
+            jid,
+            password: password.into(),
+            email: None,
+            given_name: None,
+            surname: None,
+        }
+    }
+}
+
+impl TryFrom<DataForm> for AddUser {
+    type Error = Error;
+
+    fn try_from(form: DataForm) -> Result<AddUser, Error> {
+        check_form_type(&form)?;
+        let mut jid = None;
+        let mut password: Option<String> = None;
+        let mut password_verify: Option<String> = None;
+        let mut email = None;
+        let mut given_name = None;
+        let mut surname = None;
+        for field in form.fields.iter() {
+            match field.var.as_str() {
+                "accountjid" => jid = field.parse_single()?,
+                "password" => password = field.parse_single()?,
+                "password-verify" => password_verify = field.parse_single()?,
+                "email" => email = field.parse_single()?,
+                "given_name" => given_name = field.parse_single()?,
+                "surname" => surname = field.parse_single()?,
+                // Ignore the fields of extensions.
+                _ => (),
+            }
+        }
+        let password = password.ok_or(Error::ParseError("Missing password to add a user."))?;
+        if matches!(password_verify, Some(ref verify) if *verify != password) {
+            return Err(Error::ParseError(
+                "Password verification doesn’t match the password.",
+            ));
+        }
+        Ok(AddUser {
+            jid: jid.ok_or(Error::ParseError("Missing JID of the user to add."))?,
+            password,
+            email,
+            given_name,
+            surname,
+        })
+    }
+}
+
+impl From<AddUser> for DataForm {
+    fn from(add_user: AddUser) -> DataForm {
+        let mut fields = vec![
+            Field::jid_single("accountjid", &Jid::Bare(add_user.jid)),
+            Field::text_private("password", &add_user.password),
+            Field::text_private("password-verify", &add_user.password),
+        ];
+        if let Some(ref email) = add_user.email {
+            fields.push(Field::text_single("email", email));
+        }
+        if let Some(ref given_name) = add_user.given_name {
+            fields.push(Field::text_single("given_name", given_name));
+        }
+        if let Some(ref surname) = add_user.surname {
+            fields.push(Field::text_single("surname", surname));
+        }
+        submit(fields)
+    }
+}
+
+/// The form of the [delete-user](enum.AdminCommand.html#variant.DeleteUser)
+/// command.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeleteUser {
+    /// The accounts to remove.
+    pub jids: Vec<BareJid>,
+}
+
+impl TryFrom<DataForm> for DeleteUser {
+    type Error = Error;
+
+    fn try_from(form: DataForm) -> Result<DeleteUser, Error> {
+        check_form_type(&form)?;
+        let jids = match form.get_field("accountjids") {
+            Some(field) => parse_jids(field)?,
+            None => Vec::new(),
+        };
+        Ok(DeleteUser { jids })
+    }
+}
+
+impl From<DeleteUser> for DataForm {
+    fn from(delete_user: DeleteUser) -> DataForm {
+        submit(vec![Field::jid_multi(
+            "accountjids",
+            delete_user.jids.into_iter().map(Jid::Bare),
+        )])
+    }
+}
+
+/// The form of the
+/// [end-user-session](enum.AdminCommand.html#variant.EndUserSession)
+/// command.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EndUserSession {
+    /// The sessions to close, a bare JID closing all of the sessions of this
+    /// account.
+    pub jids: Vec<Jid>,
+}
+
+impl TryFrom<DataForm> for EndUserSession {
+    type Error = Error;
+
+    fn try_from(form: DataForm) -> Result<EndUserSession, Error> {
+        check_form_type(&form)?;
+        let jids = match form.get_field("accountjids") {
+            Some(field) => parse_jids(field)?,
+            None => Vec::new(),
+        };
+        Ok(EndUserSession { jids })
+    }
+}
+
+impl From<EndUserSession> for DataForm {
+    fn from(end_user_session: EndUserSession) -> DataForm {
+        submit(vec![Field::jid_multi("accountjids", end_user_session.jids)])
+    }
+}
+
+/// The form of the
+/// [get-online-users](enum.AdminCommand.html#variant.GetOnlineUsers)
+/// command.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GetOnlineUsers {
+    /// The maximum number of users to return, or `None` for all of them.
+    pub max_items: Option<u32>,
+}
+
+impl TryFrom<DataForm> for GetOnlineUsers {
+    type Error = Error;
+
+    fn try_from(form: DataForm) -> Result<GetOnlineUsers, Error> {
+        check_form_type(&form)?;
+        let max_items = match form.get_field("max_items") {
+            Some(field) if field.values.iter().any(|value| value == "none") => None,
+            Some(field) => field.parse_single()?,
+            None => None,
+        };
+        Ok(GetOnlineUsers { max_items })
+    }
+}
+
+impl From<GetOnlineUsers> for DataForm {
+    fn from(get_online_users: GetOnlineUsers) -> DataForm {
+        let max_items = match get_online_users.max_items {
+            Some(max_items) => max_items.to_string(),
+            None => String::from("none"),
+        };
+        submit(vec![Field::list_single("max_items", &max_items)])
+    }
+}
+
+/// The result of the
+/// [get-online-users](enum.AdminCommand.html#variant.GetOnlineUsers)
+/// command.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OnlineUsers {
+    /// The users currently online.
+    pub jids: Vec<Jid>,
+}
+
+impl TryFrom<DataForm> for OnlineUsers {
+    type Error = Error;
+
+    fn try_from(form: DataForm) -> Result<OnlineUsers, Error> {
+        check_form_type(&form)?;
+        let jids = match form.get_field("onlineuserjids") {
+            Some(field) => parse_jids(field)?,
+            None => Vec::new(),
+        };
+        Ok(OnlineUsers { jids })
+    }
+}
+
+impl From<OnlineUsers> for DataForm {
+    fn from(online_users: OnlineUsers) -> DataForm {
+        DataForm::new(
+            DataFormType::Result_,
+            ns::ADMIN,
+            vec![Field::new("onlineuserjids", FieldType::TextMulti)
+                .with_values(online_users.jids.into_iter().map(String::from))],
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::{Action, Actions, Command, Status};
+    use crate::Element;
+
+    #[test]
+    fn test_nodes() {
+        let command = AdminCommand::from_node("http://jabber.org/protocol/admin#delete-user");
+        assert_eq!(command, Some(AdminCommand::DeleteUser));
+        assert_eq!(
+            AdminCommand::from_node("http://jabber.org/protocol/admin#get-user-roster"),
+            None
+        );
+        assert_eq!(
+            AdminCommand::GetOnlineUsers.session().start(),
+            Command::new("http://jabber.org/protocol/admin#get-online-users")
+        );
+    }
+
+    #[test]
+    fn test_add_user() {
+        let elem: Element = "<x xmlns='jabber:x:data' type='submit'><field type='hidden' var='FORM_TYPE'><value>http://jabber.org/protocol/admin</value></field><field var='accountjid'><value>juliet@shakespeare.lit</value></field><field var='password'><value>R0m30</value></field><field var='password-verify'><value>R0m30</value></field><field var='email'><value>juliet@capulet.com</value></field><field var='given_name'><value>Juliet</value></field><field var='surname'><value>Capulet</value></field></x>"
+            .parse()
+            .unwrap();
+        let form = DataForm::try_from(elem).unwrap();
+        let add_user = AddUser::try_from(form).unwrap();
+        let mut expected = AddUser::new(BareJid::new("juliet", "shakespeare.lit"), "R0m30");
+        expected.email = Some(String::from("juliet@capulet.com"));
+        expected.given_name = Some(String::from("Juliet"));
+        expected.surname = Some(String::from("Capulet"));
+        assert_eq!(add_user, expected);
+        let form = DataForm::from(add_user.clone());
+        assert_eq!(form.get_field("password-verify").unwrap().values, ["R0m30"]);
+        assert_eq!(AddUser::try_from(form).unwrap(), add_user);
+    }
+
+    #[test]
+    fn test_delete_user() {
+        let delete_user = DeleteUser {
+            jids: vec![
+                BareJid::new("juliet", "shakespeare.lit"),
+                BareJid::new("romeo", "shakespeare.lit"),
+            ],
+        };
+        let form = DataForm::from(delete_user.clone());
+        assert_eq!(
+            form.get_field("accountjids").unwrap().type_,
+            FieldType::JidMulti
+        );
+        assert_eq!(DeleteUser::try_from(form).unwrap(), delete_user);
+    }
+
+    #[test]
+    fn test_end_user_session() {
+        let end_user_session = EndUserSession {
+            jids: vec![
+                Jid::from_str("juliet@shakespeare.lit/balcony").unwrap(),
+                Jid::from_str("romeo@shakespeare.lit").unwrap(),
+            ],
+        };
+        let form = DataForm::from(end_user_session.clone());
+        assert_eq!(EndUserSession::try_from(form).unwrap(), end_user_session);
+    }
+
+    #[test]
+    fn test_get_online_users() {
+        let mut session = AdminCommand::GetOnlineUsers.session();
+        session
+            .handle_response(
+                Command::new(AdminCommand::GetOnlineUsers.node())
+                    .with_sessionid("get-online-users:20040408T0337Z")
+                    .with_status(Status::Executing)
+                    .with_actions(Actions {
+                        execute: Some(Action::Complete),
+                        complete: true,
+                        ..Actions::default()
+                    }),
+            )
+            .unwrap();
+        let request = session
+            .request(
+                Action::Complete,
+                Some(DataForm::from(GetOnlineUsers {
+                    max_items: Some(25),
+                })),
+            )
+            .unwrap();
+        let form = request.form.unwrap();
+        assert_eq!(form.get_field("max_items").unwrap().values, ["25"]);
+        assert_eq!(
+            GetOnlineUsers::try_from(form).unwrap(),
+            GetOnlineUsers {
+                max_items: Some(25)
+            }
+        );
+
+        let form = DataForm::from(GetOnlineUsers { max_items: None });
+        assert_eq!(
+            GetOnlineUsers::try_from(form).unwrap(),
+            GetOnlineUsers { max_items: None }
+        );
+
+        let elem: Element = "<x xmlns='jabber:x:data' type='result'><field type='hidden' var='FORM_TYPE'><value>http://jabber.org/protocol/admin</value></field><field type='text-multi' var='onlineuserjids'><value>juliet@shakespeare.lit</value><value>romeo@shakespeare.lit</value></field></x>"
+            .parse()
+            .unwrap();
+        let online_users = OnlineUsers::try_from(DataForm::try_from(elem).unwrap()).unwrap();
+        assert_eq!(online_users.jids.len(), 2);
+        assert_eq!(
+            OnlineUsers::try_from(DataForm::from(online_users.clone())).unwrap(),
+            online_users
+        );
+    }
+
+    #[test]
+    fn test_invalid() {
+        let form = submit(vec![
+            Field::jid_single(
+                "accountjid",
+                &Jid::from_str("juliet@shakespeare.lit").unwrap(),
+            ),
+            Field::text_private("password", "R0m30"),
+            Field::text_private("password-verify", "Romeo"),
+        ]);
+        let error = AddUser::try_from(form).unwrap_err();
+        let message = match error {
+            Error::ParseError(string) => string,
+            _ => panic!(),
+        };
+        assert_eq!(message, "Password verification doesn’t match the password.");
+
+        let form = DataForm::new(DataFormType::Submit, "urn:example", vec![]);
+        let error = DeleteUser::try_from(form).unwrap_err();
+        let message = match error {
+            Error::ParseError(string) => string,
+            _ => panic!(),
+        };
+        assert_eq!(message, "Wrong FORM_TYPE for an administration command.");
+    }
+}
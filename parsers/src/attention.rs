@@ -4,10 +4,16 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
+use crate::disco::DiscoInfoResult;
 use crate::message::MessagePayload;
+use crate::ns;
 
 generate_empty_element!(
-    /// Requests the attention of the recipient.
+    /// Requests the attention of the recipient, like ringing a doorbell.
+    ///
+    /// It should only be sent to entities advertising support for it, and
+    /// only in direct messages, never in a room; see
+    /// [Message::request_attention()](../message/struct.Message.html#method.request_attention).
     Attention,
     "attention",
     ATTENTION
@@ -15,6 +21,17 @@ generate_empty_element!(
 
 impl MessagePayload for Attention {}
 
+impl Attention {
+    /// Whether the entity which returned `disco` supports attention
+    /// requests.
+    pub fn is_supported_by(disco: &DiscoInfoResult) -> bool {
+        disco
+            .features
+            .iter()
+            .any(|feature| feature.var == ns::ATTENTION)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -62,6 +79,17 @@ mod tests {
         assert_eq!(message, "Unknown attribute in attention element.");
     }
 
+    #[test]
+    fn test_support() {
+        let elem: Element = "<query xmlns='http://jabber.org/protocol/disco#info'><identity category='client' type='pc'/><feature var='http://jabber.org/protocol/disco#info'/><feature var='urn:xmpp:attention:0'/></query>".parse().unwrap();
+        let disco = DiscoInfoResult::try_from(elem).unwrap();
+        assert!(Attention::is_supported_by(&disco));
+
+        let elem: Element = "<query xmlns='http://jabber.org/protocol/disco#info'><identity category='client' type='pc'/><feature var='http://jabber.org/protocol/disco#info'/></query>".parse().unwrap();
+        let disco = DiscoInfoResult::try_from(elem).unwrap();
+        assert!(!Attention::is_supported_by(&disco));
+    }
+
     #[test]
     fn test_serialise() {
         let elem: Element = "<attention xmlns='urn:xmpp:attention:0'/>".parse().unwrap();
@@ -4,6 +4,7 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
+use crate::bookmarks2;
 use jid::BareJid;
 
 generate_attribute!(
@@ -67,6 +68,38 @@ impl Storage {
     }
 }
 
+impl Conference {
+    /// Convert a PEP Native Bookmark (XEP-0402) of `jid` into a legacy one.
+    pub fn from_bookmarks2(jid: BareJid, conference: bookmarks2::Conference) -> Conference {
+        Conference {
+            autojoin: match conference.autojoin {
+                bookmarks2::Autojoin::True => Autojoin::True,
+                bookmarks2::Autojoin::False => Autojoin::False,
+            },
+            jid,
+            name: conference.name,
+            nick: conference.nick,
+            password: conference.password,
+        }
+    }
+
+    /// Convert this legacy bookmark into the room JID and the PEP Native
+    /// Bookmark (XEP-0402) to store.
+    pub fn into_bookmarks2(self) -> (BareJid, bookmarks2::Conference) {
+        let conference = bookmarks2::Conference {
+            autojoin: match self.autojoin {
+                Autojoin::True => bookmarks2::Autojoin::True,
+                Autojoin::False => bookmarks2::Autojoin::False,
+            },
+            name: self.name,
+            nick: self.nick,
+            password: self.password,
+            extensions: Vec::new(),
+        };
+        (self.jid, conference)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -118,4 +151,18 @@ mod tests {
         assert_eq!(storage.conferences[0].clone().nick.unwrap(), "Coucou");
         assert_eq!(storage.conferences[0].clone().password.unwrap(), "secret");
     }
+
+    #[test]
+    fn bookmarks2_conversion() {
+        let elem: Element = "<conference xmlns='storage:bookmarks' autojoin='true' jid='test-muc@muc.localhost' name='Test MUC'><nick>Coucou</nick></conference>".parse().unwrap();
+        let conference = Conference::try_from(elem.clone()).unwrap();
+        let (jid, conference2) = conference.into_bookmarks2();
+        assert_eq!(jid, BareJid::new("test-muc", "muc.localhost"));
+        assert_eq!(conference2.autojoin, bookmarks2::Autojoin::True);
+        assert_eq!(conference2.nick.as_deref(), Some("Coucou"));
+        assert_eq!(conference2.password, None);
+
+        let conference = Conference::from_bookmarks2(jid, conference2);
+        assert_eq!(Element::from(conference), elem);
+    }
 }
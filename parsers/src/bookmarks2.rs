@@ -4,6 +4,7 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 use crate::ns;
+use crate::pubsub::PubSubPayload;
 use crate::util::error::Error;
 use crate::Element;
 use std::convert::TryFrom;
@@ -41,6 +42,8 @@ impl Conference {
     }
 }
 
+impl PubSubPayload for Conference {}
+
 impl TryFrom<Element> for Conference {
     type Error = Error;
 
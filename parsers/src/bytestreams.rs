@@ -0,0 +1,284 @@
+// Copyright (c) 2022 Emmanuel Gil Peyrot <linkmauve@linkmauve.fr>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use crate::iq::{IqGetPayload, IqResultPayload, IqSetPayload};
+use crate::ns;
+use crate::util::error::Error;
+use crate::util::helpers::JidCodec;
+use crate::Element;
+use jid::Jid;
+use std::convert::TryFrom;
+
+pub use crate::jingle_s5b::{Mode, StreamId};
+
+/// The port SOCKS5 proxies listen on when a streamhost doesn’t tell.
+pub const DEFAULT_PORT: u16 = 1080;
+
+generate_element!(
+    /// A host the target can connect to, either the requester itself or a
+    /// proxy.
+    StreamHost, "streamhost", BYTESTREAMS,
+    attributes: [
+        /// The JID of this host.
+        jid: Required<Jid> = "jid",
+
+        /// The IP address or hostname to connect to.
+        host: Required<String> = "host",
+
+        /// The port to connect to, [`DEFAULT_PORT`] if `None`.
+        port: Option<u16> = "port",
+    ]
+);
+
+impl StreamHost {
+    /// Create a new streamhost.
+    pub fn new<H: Into<String>>(jid: Jid, host: H) -> StreamHost {
+        StreamHost {
+            jid,
+            host: host.into(),
+            port: None,
+        }
+    }
+
+    /// Set the port of this streamhost.
+    pub fn with_port(mut self, port: u16) -> StreamHost {
+        self.port = Some(port);
+        self
+    }
+}
+
+generate_element!(
+    /// The streamhost the target managed to connect to.
+    StreamHostUsed, "streamhost-used", BYTESTREAMS,
+    attributes: [
+        /// The JID of this streamhost.
+        jid: Required<Jid> = "jid",
+    ]
+);
+
+generate_element!(
+    /// Asks a proxy to start relaying the data to the target.
+    Activate, "activate", BYTESTREAMS,
+    text: (
+        /// The target of the bytestream.
+        target: JidCodec<Jid>
+    )
+);
+
+/// The content of a bytestreams query.
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueryPayload {
+    /// The streamhosts offered by the requester, or the address of a proxy.
+    StreamHosts(Vec<StreamHost>),
+
+    /// The streamhost the target connected to.
+    StreamHostUsed(StreamHostUsed),
+
+    /// The activation of the bytestream on a proxy.
+    Activate(Activate),
+
+    /// Nothing, to discover the address of a proxy.
+    None,
+}
+
+/// Negotiates a SOCKS5 bytestream, or queries a proxy.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query {
+    /// The identifier of this bytestream, absent when querying a proxy.
+    pub sid: Option<StreamId>,
+
+    /// The address the initiator will use instead of hashing the sid and
+    /// both JIDs.
+    pub dstaddr: Option<String>,
+
+    /// The mode of this bytestream.
+    pub mode: Mode,
+
+    /// The content of this query.
+    pub payload: QueryPayload,
+}
+
+impl IqGetPayload for Query {}
+impl IqSetPayload for Query {}
+impl IqResultPayload for Query {}
+
+impl Query {
+    /// Create an empty query, to discover the address of a proxy.
+    pub fn new() -> Query {
+        Query {
+            sid: None,
+            dstaddr: None,
+            mode: Mode::Tcp,
+            payload: QueryPayload::None,
+        }
+    }
+
+    /// Offer these streamhosts to connect to.
+    pub fn offer(sid: StreamId, streamhosts: Vec<StreamHost>) -> Query {
+        Query {
+            sid: Some(sid),
+            payload: QueryPayload::StreamHosts(streamhosts),
+            ..Query::new()
+        }
+    }
+
+    /// Tell the requester which streamhost the target connected to.
+    pub fn used(sid: StreamId, jid: Jid) -> Query {
+        Query {
+            sid: Some(sid),
+            payload: QueryPayload::StreamHostUsed(StreamHostUsed { jid }),
+            ..Query::new()
+        }
+    }
+
+    /// Ask a proxy to activate the bytestream to `target`.
+    pub fn activate(sid: StreamId, target: Jid) -> Query {
+        Query {
+            sid: Some(sid),
+            payload: QueryPayload::Activate(Activate { target }),
+            ..Query::new()
+        }
+    }
+}
+
+impl Default for Query {
+    fn default() -> Query {
+        Query::new()
+    }
+}
+
+impl TryFrom<Element> for Query {
+    type Error = Error;
+
+    fn try_from(elem: Element) -> Result<Query, Error> {
+        check_self!(elem, "query", BYTESTREAMS);
+        check_no_unknown_attributes!(elem, "query", ["sid", "dstaddr", "mode"]);
+        let mut query = Query {
+            sid: get_attr!(elem, "sid", Option),
+            dstaddr: get_attr!(elem, "dstaddr", Option),
+            mode: get_attr!(elem, "mode", Default),
+            payload: QueryPayload::None,
+        };
+        for child in elem.children() {
+            query.payload = match (query.payload, child) {
+                (QueryPayload::None, child) if child.is("streamhost", ns::BYTESTREAMS) => {
+                    QueryPayload::StreamHosts(vec![StreamHost::try_from(child.clone())?])
+                }
+                (QueryPayload::StreamHosts(mut streamhosts), child)
+                    if child.is("streamhost", ns::BYTESTREAMS) =>
+                {
+                    streamhosts.push(StreamHost::try_from(child.clone())?);
+                    QueryPayload::StreamHosts(streamhosts)
+                }
+                (QueryPayload::None, child) if child.is("streamhost-used", ns::BYTESTREAMS) => {
+                    QueryPayload::StreamHostUsed(StreamHostUsed::try_from(child.clone())?)
+                }
+                (QueryPayload::None, child) if child.is("activate", ns::BYTESTREAMS) => {
+                    QueryPayload::Activate(Activate::try_from(child.clone())?)
+                }
+                _ => {
+                    return Err(Error::ParseError(
+                        "Unknown or duplicate child in bytestreams query element.",
+                    ))
+                }
+            };
+        }
+        Ok(query)
+    }
+}
+
+impl From<Query> for Element {
+    fn from(query: Query) -> Element {
+        Element::builder("query", ns::BYTESTREAMS)
+            .attr("sid", query.sid)
+            .attr("dstaddr", query.dstaddr)
+            .attr("mode", query.mode)
+            .append_all(match query.payload {
+                QueryPayload::StreamHosts(streamhosts) => {
+                    streamhosts.into_iter().map(Element::from).collect()
+                }
+                QueryPayload::StreamHostUsed(used) => vec![Element::from(used)],
+                QueryPayload::Activate(activate) => vec![Element::from(activate)],
+                QueryPayload::None => vec![],
+            })
+            .build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[cfg(target_pointer_width = "32")]
+    #[test]
+    fn test_size() {
+        assert_size!(StreamHost, 52);
+        assert_size!(StreamHostUsed, 36);
+        assert_size!(Activate, 36);
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    fn test_size() {
+        assert_size!(StreamHost, 104);
+        assert_size!(StreamHostUsed, 72);
+        assert_size!(Activate, 72);
+    }
+
+    #[test]
+    fn test_offer() {
+        let elem: Element = "<query xmlns='http://jabber.org/protocol/bytestreams' sid='vxf9n471bn46'><streamhost jid='requester@example.com/foo' host='192.168.4.1' port='5086'/><streamhost jid='streamer.example.com' host='24.24.24.1'/></query>"
+            .parse()
+            .unwrap();
+        let query = Query::try_from(elem.clone()).unwrap();
+        assert_eq!(query.sid, Some(StreamId(String::from("vxf9n471bn46"))));
+        assert_eq!(query.mode, Mode::Tcp);
+        let streamhosts = match query.payload {
+            QueryPayload::StreamHosts(ref streamhosts) => streamhosts,
+            _ => panic!(),
+        };
+        assert_eq!(streamhosts.len(), 2);
+        assert_eq!(streamhosts[0].port, Some(5086));
+        assert_eq!(streamhosts[1].port, None);
+        assert_eq!(Element::from(query), elem);
+    }
+
+    #[test]
+    fn test_used_and_activate() {
+        let sid = StreamId(String::from("vxf9n471bn46"));
+        let jid = Jid::from_str("streamer.example.com").unwrap();
+        let elem: Element = "<query xmlns='http://jabber.org/protocol/bytestreams' sid='vxf9n471bn46'><streamhost-used jid='streamer.example.com'/></query>"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            Query::try_from(elem.clone()).unwrap(),
+            Query::used(sid.clone(), jid)
+        );
+
+        let target = Jid::from_str("target@example.org/bar").unwrap();
+        let elem: Element = "<query xmlns='http://jabber.org/protocol/bytestreams' sid='vxf9n471bn46'><activate>target@example.org/bar</activate></query>"
+            .parse()
+            .unwrap();
+        assert_eq!(Element::from(Query::activate(sid, target)), elem);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let elem: Element = "<query xmlns='http://jabber.org/protocol/bytestreams' sid='a'><streamhost-used jid='a@b'/><streamhost jid='a@b' host='c'/></query>"
+            .parse()
+            .unwrap();
+        let error = Query::try_from(elem).unwrap_err();
+        let message = match error {
+            Error::ParseError(string) => string,
+            _ => panic!(),
+        };
+        assert_eq!(
+            message,
+            "Unknown or duplicate child in bytestreams query element."
+        );
+    }
+}
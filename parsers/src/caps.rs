@@ -11,11 +11,6 @@ use crate::ns;
 use crate::presence::PresencePayload;
 use crate::util::error::Error;
 use crate::Element;
-use blake2::Blake2bVar;
-use digest::{Digest, Update, VariableOutput};
-use sha1::Sha1;
-use sha2::{Sha256, Sha512};
-use sha3::{Sha3_256, Sha3_512};
 use std::convert::TryFrom;
 
 /// Represents a capability hash for a given client.
@@ -157,53 +152,13 @@ pub fn compute_disco(disco: &DiscoInfoResult) -> Vec<u8> {
     final_string
 }
 
-fn get_hash_vec(hash: &[u8]) -> Vec<u8> {
-    hash.to_vec()
-}
-
 /// Hashes the result of [compute_disco()] with one of the supported [hash
 /// algorithms](../hashes/enum.Algo.html).
 pub fn hash_caps(data: &[u8], algo: Algo) -> Result<Hash, String> {
-    Ok(Hash {
-        hash: match algo {
-            Algo::Sha_1 => {
-                let hash = Sha1::digest(data);
-                get_hash_vec(hash.as_slice())
-            }
-            Algo::Sha_256 => {
-                let hash = Sha256::digest(data);
-                get_hash_vec(hash.as_slice())
-            }
-            Algo::Sha_512 => {
-                let hash = Sha512::digest(data);
-                get_hash_vec(hash.as_slice())
-            }
-            Algo::Sha3_256 => {
-                let hash = Sha3_256::digest(data);
-                get_hash_vec(hash.as_slice())
-            }
-            Algo::Sha3_512 => {
-                let hash = Sha3_512::digest(data);
-                get_hash_vec(hash.as_slice())
-            }
-            Algo::Blake2b_256 => {
-                let mut hasher = Blake2bVar::new(32).unwrap();
-                hasher.update(data);
-                let mut vec = vec![0u8; 32];
-                hasher.finalize_variable(&mut vec).unwrap();
-                vec
-            }
-            Algo::Blake2b_512 => {
-                let mut hasher = Blake2bVar::new(64).unwrap();
-                hasher.update(data);
-                let mut vec = vec![0u8; 64];
-                hasher.finalize_variable(&mut vec).unwrap();
-                vec
-            }
-            Algo::Unknown(algo) => return Err(format!("Unknown algorithm: {}.", algo)),
-        },
-        algo,
-    })
+    match algo.digest(data) {
+        Some(hash) => Ok(Hash { hash, algo }),
+        None => Err(format!("Unknown algorithm: {}.", String::from(algo))),
+    }
 }
 
 /// Helper function to create the query for the disco#info corresponding to a
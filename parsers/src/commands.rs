@@ -0,0 +1,690 @@
+// Copyright (c) 2022 Emmanuel Gil Peyrot <linkmauve@linkmauve.fr>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use crate::data_forms::DataForm;
+use crate::disco::{DiscoItemsResult, Item as DiscoItem};
+use crate::iq::{IqResultPayload, IqSetPayload};
+use crate::ns;
+use crate::stanza_error::{DefinedCondition, ErrorType, StanzaError};
+use crate::util::error::Error;
+use crate::util::helpers::Text;
+use crate::Element;
+use jid::Jid;
+use std::convert::TryFrom;
+
+generate_attribute!(
+    /// An action to execute on a command.
+    Action, "action", {
+        /// Cancel the execution of this command.
+        Cancel => "cancel",
+
+        /// Complete the execution of this command, with the data provided
+        /// so far.
+        Complete => "complete",
+
+        /// Execute this command, or perform the default action of the
+        /// current stage.
+        Execute => "execute",
+
+        /// Go to the next stage of this command.
+        Next => "next",
+
+        /// Go back to the previous stage of this command.
+        Prev => "prev",
+    }, Default = Execute
+);
+
+generate_attribute!(
+    /// The status of the execution of a command.
+    Status, "status", {
+        /// The command got canceled.
+        Canceled => "canceled",
+
+        /// The command completed.
+        Completed => "completed",
+
+        /// The command is being executed, more stages are to follow.
+        Executing => "executing",
+    }
+);
+
+generate_attribute!(
+    /// The severity of a [note](struct.Note.html).
+    NoteType, "type", {
+        /// Informational note.
+        Info => "info",
+
+        /// Warning, which doesn’t prevent the command from going on.
+        Warn => "warn",
+
+        /// The command failed.
+        Error => "error",
+    }, Default = Info
+);
+
+generate_element!(
+    /// A message about the execution of a command, for the user.
+    Note, "note", COMMANDS,
+    attributes: [
+        /// The severity of this note.
+        type_: Default<NoteType> = "type",
+    ],
+    text: (
+        /// The text of this note.
+        text: Text<String>
+    )
+);
+
+/// The actions allowed at the current stage of a command.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Actions {
+    /// The action to execute when the user doesn’t pick one.
+    pub execute: Option<Action>,
+
+    /// Whether going back to the previous stage is allowed.
+    pub prev: bool,
+
+    /// Whether going to the next stage is allowed.
+    pub next: bool,
+
+    /// Whether completing the command right now is allowed.
+    pub complete: bool,
+}
+
+impl Actions {
+    /// Whether `action` is allowed by these actions, `execute` and
+    /// `cancel` always being allowed.
+    pub fn allows(&self, action: &Action) -> bool {
+        match action {
+            Action::Cancel | Action::Execute => true,
+            Action::Complete => self.complete,
+            Action::Next => self.next,
+            Action::Prev => self.prev,
+        }
+    }
+}
+
+impl TryFrom<Element> for Actions {
+    type Error = Error;
+
+    fn try_from(elem: Element) -> Result<Actions, Error> {
+        check_self!(elem, "actions", COMMANDS);
+        check_no_unknown_attributes!(elem, "actions", ["execute"]);
+        let mut actions = Actions {
+            execute: get_attr!(elem, "execute", Option),
+            ..Actions::default()
+        };
+        for child in elem.children() {
+            let flag = if child.is("prev", ns::COMMANDS) {
+                &mut actions.prev
+            } else if child.is("next", ns::COMMANDS) {
+                &mut actions.next
+            } else if child.is("complete", ns::COMMANDS) {
+                &mut actions.complete
+            } else {
+                return Err(Error::ParseError("Unknown child in actions element."));
+            };
+            check_no_children!(child, "action");
+            check_no_attributes!(child, "action");
+            *flag = true;
+        }
+        Ok(actions)
+    }
+}
+
+impl From<Actions> for Element {
+    fn from(actions: Actions) -> Element {
+        let flag = |name, enabled| {
+            if enabled {
+                Some(Element::builder(name, ns::COMMANDS))
+            } else {
+                None
+            }
+        };
+        Element::builder("actions", ns::COMMANDS)
+            // The default value of Action would be omitted otherwise.
+            .attr(
+                "execute",
+                actions.execute.map(|action| match action {
+                    Action::Cancel => "cancel",
+                    Action::Complete => "complete",
+                    Action::Execute => "execute",
+                    Action::Next => "next",
+                    Action::Prev => "prev",
+                }),
+            )
+            .append_all(flag("prev", actions.prev))
+            .append_all(flag("next", actions.next))
+            .append_all(flag("complete", actions.complete))
+            .build()
+    }
+}
+
+/// An ad-hoc command request or response.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Command {
+    /// The node identifying this command.
+    pub node: String,
+
+    /// The session of a multi-stage command, chosen by the responder.
+    pub sessionid: Option<String>,
+
+    /// The action requested by the requester.
+    pub action: Action,
+
+    /// The status of the execution of the command, as told by the
+    /// responder.
+    pub status: Option<Status>,
+
+    /// The actions the requester is allowed to execute next.
+    pub actions: Option<Actions>,
+
+    /// The notes for the user about the execution of the command.
+    pub notes: Vec<Note>,
+
+    /// The form to be filled, or the result of the command.
+    pub form: Option<DataForm>,
+
+    /// Any other payload of this command.
+    pub payloads: Vec<Element>,
+}
+
+impl IqSetPayload for Command {}
+impl IqResultPayload for Command {}
+
+impl Command {
+    /// Create a request to execute the command of the given node.
+    pub fn new<N: Into<String>>(node: N) -> Command {
+        Command {
+            node: node.into(),
+            sessionid: None,
+            action: Action::Execute,
+            status: None,
+            actions: None,
+            notes: Vec::new(),
+            form: None,
+            payloads: Vec::new(),
+        }
+    }
+
+    /// Set the session of this command.
+    pub fn with_sessionid<S: Into<String>>(mut self, sessionid: S) -> Command {
+        self.sessionid = Some(sessionid.into());
+        self
+    }
+
+    /// Set the action requested.
+    pub fn with_action(mut self, action: Action) -> Command {
+        self.action = action;
+        self
+    }
+
+    /// Set the status of the execution.
+    pub fn with_status(mut self, status: Status) -> Command {
+        self.status = Some(status);
+        self
+    }
+
+    /// Set the actions allowed next.
+    pub fn with_actions(mut self, actions: Actions) -> Command {
+        self.actions = Some(actions);
+        self
+    }
+
+    /// Add a note for the user.
+    pub fn with_note<T: Into<String>>(mut self, type_: NoteType, text: T) -> Command {
+        self.notes.push(Note {
+            type_,
+            text: text.into(),
+        });
+        self
+    }
+
+    /// Set the form of this command.
+    pub fn with_form(mut self, form: DataForm) -> Command {
+        self.form = Some(form);
+        self
+    }
+}
+
+impl TryFrom<Element> for Command {
+    type Error = Error;
+
+    fn try_from(elem: Element) -> Result<Command, Error> {
+        check_self!(elem, "command", COMMANDS);
+        check_no_unknown_attributes!(
+            elem,
+            "command",
+            ["node", "sessionid", "action", "status", "xml:lang"]
+        );
+        let mut command = Command {
+            node: get_attr!(elem, "node", Required),
+            sessionid: get_attr!(elem, "sessionid", Option),
+            action: get_attr!(elem, "action", Default),
+            status: get_attr!(elem, "status", Option),
+            actions: None,
+            notes: Vec::new(),
+            form: None,
+            payloads: Vec::new(),
+        };
+        for child in elem.children() {
+            if child.is("actions", ns::COMMANDS) {
+                if command.actions.is_some() {
+                    return Err(Error::ParseError(
+                        "More than one actions element in command.",
+                    ));
+                }
+                command.actions = Some(Actions::try_from(child.clone())?);
+            } else if child.is("note", ns::COMMANDS) {
+                command.notes.push(Note::try_from(child.clone())?);
+            } else if child.is("x", ns::DATA_FORMS) && command.form.is_none() {
+                command.form = Some(DataForm::try_from(child.clone())?);
+            } else {
+                command.payloads.push(child.clone());
+            }
+        }
+        Ok(command)
+    }
+}
+
+impl From<Command> for Element {
+    fn from(command: Command) -> Element {
+        Element::builder("command", ns::COMMANDS)
+            .attr("node", command.node)
+            .attr("sessionid", command.sessionid)
+            .attr("action", command.action)
+            .attr("status", command.status)
+            .append_all(command.actions)
+            .append_all(command.notes)
+            .append_all(command.form)
+            .append_all(command.payloads)
+            .build()
+    }
+}
+
+/// The client side state of the execution of a command.
+///
+/// Send the requests it creates, starting with [start()](#method.start),
+/// and pass it the matching responses, until it either completed or got
+/// canceled.
+#[derive(Debug, Clone)]
+pub struct CommandSession {
+    node: String,
+    sessionid: Option<String>,
+    status: Option<Status>,
+    actions: Actions,
+    form: Option<DataForm>,
+    notes: Vec<Note>,
+}
+
+impl CommandSession {
+    /// Prepare the execution of the command of the given node.
+    pub fn new<N: Into<String>>(node: N) -> CommandSession {
+        CommandSession {
+            node: node.into(),
+            sessionid: None,
+            status: None,
+            actions: Actions::default(),
+            form: None,
+            notes: Vec::new(),
+        }
+    }
+
+    /// The first request, starting the execution.
+    pub fn start(&self) -> Command {
+        Command::new(self.node.clone())
+    }
+
+    /// Update the state of the session from a response of the responder.
+    pub fn handle_response(&mut self, response: Command) -> Result<(), Error> {
+        if response.node != self.node {
+            return Err(Error::ParseError("Command response for another node."));
+        }
+        match (&self.sessionid, &response.sessionid) {
+            (Some(ours), Some(theirs)) if ours != theirs => {
+                return Err(Error::ParseError("Command response for another session."))
+            }
+            (Some(_), None) => {
+                return Err(Error::ParseError("Command response without a session."))
+            }
+            _ => (),
+        }
+        self.sessionid = response.sessionid;
+        self.status = Some(response.status.unwrap_or(Status::Completed));
+        self.actions = response.actions.unwrap_or_default();
+        self.form = response.form;
+        self.notes = response.notes;
+        Ok(())
+    }
+
+    /// The request executing `action` on the current stage, along with a
+    /// filled form if needed, failing if this action isn’t allowed.
+    pub fn request(&self, action: Action, form: Option<DataForm>) -> Result<Command, Error> {
+        if self.status != Some(Status::Executing) {
+            return Err(Error::ParseError("Command isn’t being executed."));
+        }
+        if !self.actions.allows(&action) {
+            return Err(Error::ParseError("Action not allowed at this stage."));
+        }
+        let mut command = Command::new(self.node.clone()).with_action(action);
+        command.sessionid = self.sessionid.clone();
+        command.form = form;
+        Ok(command)
+    }
+
+    /// The status of the execution, `None` before the first response.
+    pub fn status(&self) -> Option<&Status> {
+        self.status.as_ref()
+    }
+
+    /// Whether the command still expects more requests.
+    pub fn is_executing(&self) -> bool {
+        self.status == Some(Status::Executing)
+    }
+
+    /// The actions allowed at the current stage.
+    pub fn actions(&self) -> &Actions {
+        &self.actions
+    }
+
+    /// The form of the current stage, or the result once completed.
+    pub fn form(&self) -> Option<&DataForm> {
+        self.form.as_ref()
+    }
+
+    /// The notes of the last response.
+    pub fn notes(&self) -> &[Note] {
+        &self.notes
+    }
+}
+
+/// The implementation of one command, on the responder side.
+pub trait CommandHandler {
+    /// The node identifying this command.
+    fn node(&self) -> &str;
+
+    /// The human-readable name of this command.
+    fn name(&self) -> &str;
+
+    /// Whether `requester` is allowed to see and execute this command.
+    fn is_allowed(&self, _requester: &Jid) -> bool {
+        true
+    }
+
+    /// Handle one request, returning the response, which must include
+    /// the same sessionid for any stage after the first one.
+    #[allow(clippy::result_large_err)]
+    fn execute(&mut self, requester: &Jid, request: Command) -> Result<Command, StanzaError>;
+}
+
+/// A set of commands, dispatching requests to the right handler.
+#[derive(Default)]
+pub struct CommandRegistry {
+    handlers: Vec<Box<dyn CommandHandler>>,
+}
+
+impl CommandRegistry {
+    /// Create an empty registry.
+    pub fn new() -> CommandRegistry {
+        CommandRegistry::default()
+    }
+
+    /// Add a command.
+    pub fn register(&mut self, handler: Box<dyn CommandHandler>) {
+        self.handlers.push(handler);
+    }
+
+    /// The list of commands `requester` is allowed to execute on `jid`,
+    /// in reply to a disco#items query on the commands node.
+    pub fn disco_items(&self, jid: &Jid, requester: &Jid) -> DiscoItemsResult {
+        DiscoItemsResult {
+            node: Some(String::from(ns::COMMANDS)),
+            items: self
+                .handlers
+                .iter()
+                .filter(|handler| handler.is_allowed(requester))
+                .map(|handler| DiscoItem {
+                    jid: jid.clone(),
+                    node: Some(String::from(handler.node())),
+                    name: Some(String::from(handler.name())),
+                })
+                .collect(),
+            rsm: None,
+        }
+    }
+
+    /// Dispatch `request` to the handler of its node.
+    #[allow(clippy::result_large_err)]
+    pub fn execute(&mut self, requester: &Jid, request: Command) -> Result<Command, StanzaError> {
+        let handler = self
+            .handlers
+            .iter_mut()
+            .find(|handler| handler.node() == request.node);
+        match handler {
+            Some(handler) if handler.is_allowed(requester) => handler.execute(requester, request),
+            Some(_) => Err(StanzaError::new(
+                ErrorType::Cancel,
+                DefinedCondition::Forbidden,
+                "en",
+                "You are not allowed to execute this command.",
+            )),
+            None => Err(StanzaError::new(
+                ErrorType::Cancel,
+                DefinedCondition::ItemNotFound,
+                "en",
+                "No such command.",
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_forms::{DataFormType, Field};
+    use std::str::FromStr;
+
+    #[cfg(target_pointer_width = "32")]
+    #[test]
+    fn test_size() {
+        assert_size!(Action, 1);
+        assert_size!(Status, 1);
+        assert_size!(NoteType, 1);
+        assert_size!(Note, 16);
+        assert_size!(Actions, 4);
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    fn test_size() {
+        assert_size!(Action, 1);
+        assert_size!(Status, 1);
+        assert_size!(NoteType, 1);
+        assert_size!(Note, 32);
+        assert_size!(Actions, 4);
+    }
+
+    #[test]
+    fn test_request() {
+        let elem: Element =
+            "<command xmlns='http://jabber.org/protocol/commands' node='list' action='execute'/>"
+                .parse()
+                .unwrap();
+        let command = Command::try_from(elem).unwrap();
+        assert_eq!(command.node, "list");
+        assert_eq!(command.action, Action::Execute);
+        assert_eq!(command.sessionid, None);
+        assert_eq!(command.status, None);
+
+        let elem: Element = Command::new("list").into();
+        let elem2: Element = "<command xmlns='http://jabber.org/protocol/commands' node='list'/>"
+            .parse()
+            .unwrap();
+        assert_eq!(elem, elem2);
+    }
+
+    #[test]
+    fn test_response() {
+        let elem: Element = "<command xmlns='http://jabber.org/protocol/commands' sessionid='config:20020923T213616Z-700' node='config' status='executing'><actions execute='next'><next/></actions><note type='warn'>Careful.</note><x xmlns='jabber:x:data' type='form'><title>Configure Service</title><field var='service' type='list-single'><option><value>httpd</value></option></field></x></command>"
+            .parse()
+            .unwrap();
+        let command = Command::try_from(elem.clone()).unwrap();
+        assert_eq!(
+            command.sessionid.as_deref(),
+            Some("config:20020923T213616Z-700")
+        );
+        assert_eq!(command.status, Some(Status::Executing));
+        let actions = command.actions.as_ref().unwrap();
+        assert_eq!(actions.execute, Some(Action::Next));
+        assert!(actions.next);
+        assert!(!actions.prev);
+        assert!(!actions.complete);
+        assert_eq!(command.notes[0].type_, NoteType::Warn);
+        assert_eq!(command.notes[0].text, "Careful.");
+        assert_eq!(
+            command.form.as_ref().unwrap().title.as_deref(),
+            Some("Configure Service")
+        );
+        assert!(command.payloads.is_empty());
+
+        let elem2: Element = command.into();
+        assert_eq!(elem, elem2);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let elem: Element = "<command xmlns='http://jabber.org/protocol/commands'/>"
+            .parse()
+            .unwrap();
+        let error = Command::try_from(elem).unwrap_err();
+        let message = match error {
+            Error::ParseError(string) => string,
+            _ => panic!(),
+        };
+        assert_eq!(message, "Required attribute 'node' missing.");
+
+        let elem: Element =
+            "<command xmlns='http://jabber.org/protocol/commands' node='a' action='run'/>"
+                .parse()
+                .unwrap();
+        let error = Command::try_from(elem).unwrap_err();
+        let message = match error {
+            Error::ParseError(string) => string,
+            _ => panic!(),
+        };
+        assert_eq!(message, "Unknown value for 'action' attribute.");
+
+        let elem: Element = "<command xmlns='http://jabber.org/protocol/commands' node='a'><actions><last/></actions></command>"
+            .parse()
+            .unwrap();
+        let error = Command::try_from(elem).unwrap_err();
+        let message = match error {
+            Error::ParseError(string) => string,
+            _ => panic!(),
+        };
+        assert_eq!(message, "Unknown child in actions element.");
+    }
+
+    #[test]
+    fn test_session() {
+        let mut session = CommandSession::new("config");
+        assert_eq!(session.start(), Command::new("config"));
+        assert!(session.request(Action::Next, None).is_err());
+
+        let form = DataForm::new(DataFormType::Form, "urn:example", vec![]);
+        session
+            .handle_response(
+                Command::new("config")
+                    .with_sessionid("s1")
+                    .with_status(Status::Executing)
+                    .with_actions(Actions {
+                        execute: Some(Action::Next),
+                        next: true,
+                        ..Actions::default()
+                    })
+                    .with_form(form),
+            )
+            .unwrap();
+        assert!(session.is_executing());
+        assert!(session.form().is_some());
+        assert!(session.request(Action::Prev, None).is_err());
+
+        let submit = DataForm::new(
+            DataFormType::Submit,
+            "urn:example",
+            vec![Field::text_single("a", "b")],
+        );
+        let request = session.request(Action::Next, Some(submit)).unwrap();
+        assert_eq!(request.sessionid.as_deref(), Some("s1"));
+        assert_eq!(request.action, Action::Next);
+
+        let error = session
+            .handle_response(Command::new("config").with_sessionid("s2"))
+            .unwrap_err();
+        let message = match error {
+            Error::ParseError(string) => string,
+            _ => panic!(),
+        };
+        assert_eq!(message, "Command response for another session.");
+
+        session
+            .handle_response(
+                Command::new("config")
+                    .with_sessionid("s1")
+                    .with_status(Status::Completed)
+                    .with_note(NoteType::Info, "Done."),
+            )
+            .unwrap();
+        assert!(!session.is_executing());
+        assert_eq!(session.status(), Some(&Status::Completed));
+        assert_eq!(session.notes()[0].text, "Done.");
+        assert!(session.request(Action::Cancel, None).is_err());
+    }
+
+    struct Echo;
+
+    impl CommandHandler for Echo {
+        fn node(&self) -> &str {
+            "echo"
+        }
+
+        fn name(&self) -> &str {
+            "Echo"
+        }
+
+        fn is_allowed(&self, requester: &Jid) -> bool {
+            requester.clone().domain() == "example.org"
+        }
+
+        fn execute(&mut self, _: &Jid, request: Command) -> Result<Command, StanzaError> {
+            Ok(Command::new(request.node).with_status(Status::Completed))
+        }
+    }
+
+    #[test]
+    fn test_registry() {
+        let mut registry = CommandRegistry::new();
+        registry.register(Box::new(Echo));
+        let service = Jid::from_str("service.example.org").unwrap();
+        let alice = Jid::from_str("alice@example.org/a").unwrap();
+        let mallory = Jid::from_str("mallory@evil.example/a").unwrap();
+
+        let items = registry.disco_items(&service, &alice);
+        assert_eq!(items.node.as_deref(), Some(ns::COMMANDS));
+        assert_eq!(items.items.len(), 1);
+        assert_eq!(items.items[0].node.as_deref(), Some("echo"));
+        assert!(registry.disco_items(&service, &mallory).items.is_empty());
+
+        let response = registry.execute(&alice, Command::new("echo")).unwrap();
+        assert_eq!(response.status, Some(Status::Completed));
+        let error = registry
+            .execute(&mallory, Command::new("echo"))
+            .unwrap_err();
+        assert_eq!(error.defined_condition, DefinedCondition::Forbidden);
+        let error = registry.execute(&alice, Command::new("none")).unwrap_err();
+        assert_eq!(error.defined_condition, DefinedCondition::ItemNotFound);
+    }
+}
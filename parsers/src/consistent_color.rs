@@ -0,0 +1,199 @@
+// Copyright (c) 2022 Emmanuel Gil Peyrot <linkmauve@linkmauve.fr>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Generation of the same color for a nickname or a JID in every client.
+//!
+//! The input, usually the nickname in a room or the bare JID of a contact,
+//! gets hashed into a hue angle, which then gets converted into an RGB
+//! color using the HSLuv color space, at full saturation and half lightness.
+
+use digest::Digest;
+use sha1::Sha1;
+use std::f64::consts::PI;
+
+/// The kind of color vision deficiency to correct the generated colors for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorVisionDeficiency {
+    /// No correction.
+    #[default]
+    None,
+
+    /// Red/green blindness, the hue only covering half of the circle.
+    RedGreen,
+
+    /// Blue blindness, the hue only covering the other half of the circle.
+    Blue,
+}
+
+/// The hue angle of this input, in degrees in the `[0, 360)` range.
+pub fn hue_angle(input: &str) -> f64 {
+    let hash = Sha1::digest(input.as_bytes());
+    let value = u16::from_le_bytes([hash[0], hash[1]]);
+    f64::from(value) / 65536. * 360.
+}
+
+/// Applies the correction for this color vision deficiency to a hue angle.
+pub fn correct_angle(angle: f64, deficiency: ColorVisionDeficiency) -> f64 {
+    match deficiency {
+        ColorVisionDeficiency::None => angle,
+        ColorVisionDeficiency::RedGreen => angle % 180.,
+        ColorVisionDeficiency::Blue => angle % 180. + 90.,
+    }
+}
+
+/// A color, each of its channels being in the `[0, 1]` range.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rgb {
+    /// The red channel.
+    pub red: f64,
+
+    /// The green channel.
+    pub green: f64,
+
+    /// The blue channel.
+    pub blue: f64,
+}
+
+impl Rgb {
+    /// Each channel of this color, in the `[0, 255]` range.
+    pub fn to_bytes(&self) -> [u8; 3] {
+        let byte = |channel: f64| (channel * 255.).round() as u8;
+        [byte(self.red), byte(self.green), byte(self.blue)]
+    }
+
+    /// This color in the `#rrggbb` form used by CSS.
+    pub fn to_hex(&self) -> String {
+        let [red, green, blue] = self.to_bytes();
+        format!("#{:02x}{:02x}{:02x}", red, green, blue)
+    }
+}
+
+/// The color of this input, corrected for this color vision deficiency.
+pub fn color(input: &str, deficiency: ColorVisionDeficiency) -> Rgb {
+    color_from_angle(correct_angle(hue_angle(input), deficiency))
+}
+
+/// The color of this hue angle, in degrees.
+pub fn color_from_angle(angle: f64) -> Rgb {
+    hsluv_to_rgb(angle, 100., 50.)
+}
+
+// The HSLuv color space, as specified on https://www.hsluv.org/.
+
+const M: [[f64; 3]; 3] = [
+    [3.240969941904521, -1.537383177570093, -0.498610760293],
+    [-0.96924363628087, 1.87596750150772, 0.041555057407175],
+    [0.055630079696993, -0.20397695888897, 1.056971514242878],
+];
+const REF_U: f64 = 0.19783000664283;
+const REF_V: f64 = 0.46831999493879;
+const KAPPA: f64 = 903.2962962;
+const EPSILON: f64 = 0.0088564516;
+
+fn max_chroma(lightness: f64, hue: f64) -> f64 {
+    let hue = hue / 360. * 2. * PI;
+    let sub1 = (lightness + 16.).powi(3) / 1560896.;
+    let sub2 = if sub1 > EPSILON {
+        sub1
+    } else {
+        lightness / KAPPA
+    };
+    let mut min = f64::MAX;
+    for [m1, m2, m3] in M.iter().copied() {
+        for t in 0..2 {
+            let t = f64::from(t);
+            let top1 = (284517. * m1 - 94839. * m3) * sub2;
+            let top2 = (838422. * m3 + 769860. * m2 + 731718. * m1) * lightness * sub2
+                - 769860. * t * lightness;
+            let bottom = (632260. * m3 - 126452. * m2) * sub2 + 126452. * t;
+            let (slope, intercept) = (top1 / bottom, top2 / bottom);
+            let length = intercept / (hue.sin() - slope * hue.cos());
+            if length >= 0. && length < min {
+                min = length;
+            }
+        }
+    }
+    min
+}
+
+fn from_linear(channel: f64) -> f64 {
+    let channel = if channel <= 0.0031308 {
+        12.92 * channel
+    } else {
+        1.055 * channel.powf(1. / 2.4) - 0.055
+    };
+    channel.clamp(0., 1.)
+}
+
+fn hsluv_to_rgb(hue: f64, saturation: f64, lightness: f64) -> Rgb {
+    let chroma = max_chroma(lightness, hue) / 100. * saturation;
+    let hue = hue / 360. * 2. * PI;
+    let (u, v) = (hue.cos() * chroma, hue.sin() * chroma);
+    let var_u = u / (13. * lightness) + REF_U;
+    let var_v = v / (13. * lightness) + REF_V;
+    let y = if lightness <= 8. {
+        lightness / KAPPA
+    } else {
+        ((lightness + 16.) / 116.).powi(3)
+    };
+    let x = -(9. * y * var_u) / ((var_u - 4.) * var_v - var_u * var_v);
+    let z = (9. * y - 15. * var_v * y - var_v * x) / (3. * var_v);
+    let channel = |[m1, m2, m3]: [f64; 3]| from_linear(m1 * x + m2 * y + m3 * z);
+    Rgb {
+        red: channel(M[0]),
+        green: channel(M[1]),
+        blue: channel(M[2]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 0.001, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn test_angle() {
+        assert_close(hue_angle("Romeo"), 327.255249);
+        assert_close(hue_angle("juliet@capulet.lit"), 209.410400);
+        assert_close(hue_angle("😺"), 331.199341);
+        assert_close(hue_angle("council"), 359.994507);
+    }
+
+    #[test]
+    fn test_corrections() {
+        assert_close(
+            correct_angle(327.255249, ColorVisionDeficiency::RedGreen),
+            147.255249,
+        );
+        assert_close(
+            correct_angle(327.255249, ColorVisionDeficiency::Blue),
+            237.255249,
+        );
+        assert_close(correct_angle(42., ColorVisionDeficiency::None), 42.);
+    }
+
+    #[test]
+    fn test_color() {
+        let romeo = color("Romeo", ColorVisionDeficiency::None);
+        assert_close(romeo.red, 0.865);
+        assert_close(romeo.green, 0.000);
+        assert_close(romeo.blue, 0.686);
+
+        let juliet = color("juliet@capulet.lit", ColorVisionDeficiency::None);
+        assert_close(juliet.red, 0.000);
+        assert_close(juliet.green, 0.515);
+        assert_close(juliet.blue, 0.573);
+
+        let council = color("council", ColorVisionDeficiency::None);
+        assert_close(council.red, 0.918);
+        assert_close(council.green, 0.000);
+        assert_close(council.blue, 0.394);
+        assert_eq!(council.to_hex(), "#ea0064");
+    }
+}
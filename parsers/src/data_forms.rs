@@ -7,8 +7,13 @@
 use crate::media_element::MediaElement;
 use crate::ns;
 use crate::util::error::Error;
+use crate::util::helpers::parse_bool;
 use crate::Element;
+use jid::Jid;
+use std::collections::HashMap;
 use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
 
 generate_element!(
     /// Represents one of the possible values for a list- field.
@@ -113,16 +118,207 @@ impl Field {
         self
     }
 
+    /// Set all of the values of this Field.
+    pub fn with_values<I: IntoIterator<Item = String>>(mut self, values: I) -> Field {
+        self.values = values.into_iter().collect();
+        self
+    }
+
+    /// Set the label of this Field.
+    pub fn with_label(mut self, label: &str) -> Field {
+        self.label = Some(String::from(label));
+        self
+    }
+
+    /// Mark this Field as required.
+    pub fn with_required(mut self) -> Field {
+        self.required = true;
+        self
+    }
+
+    /// Add one allowed value to this list- Field.
+    pub fn with_option(mut self, value: &str, label: Option<&str>) -> Field {
+        self.options.push(Option_ {
+            label: label.map(String::from),
+            value: String::from(value),
+        });
+        self
+    }
+
     /// Create a text-single Field with the given var and unique value.
     pub fn text_single(var: &str, value: &str) -> Field {
         Field::new(var, FieldType::TextSingle).with_value(value)
     }
 
+    /// Create a text-private Field with the given var and unique value.
+    pub fn text_private(var: &str, value: &str) -> Field {
+        Field::new(var, FieldType::TextPrivate).with_value(value)
+    }
+
+    /// Create a text-multi Field with the given var, and one value per line.
+    pub fn text_multi(var: &str, text: &str) -> Field {
+        Field::new(var, FieldType::TextMulti).with_values(text.lines().map(String::from))
+    }
+
+    /// Create a boolean Field with the given var and value.
+    pub fn boolean(var: &str, value: bool) -> Field {
+        Field::new(var, FieldType::Boolean).with_value(if value { "1" } else { "0" })
+    }
+
+    /// Create a hidden Field with the given var and unique value.
+    pub fn hidden(var: &str, value: &str) -> Field {
+        Field::new(var, FieldType::Hidden).with_value(value)
+    }
+
+    /// Create a fixed Field with the given var and text.
+    pub fn fixed(var: &str, text: &str) -> Field {
+        Field::new(var, FieldType::Fixed).with_value(text)
+    }
+
+    /// Create a jid-single Field with the given var and JID.
+    pub fn jid_single(var: &str, jid: &Jid) -> Field {
+        Field::new(var, FieldType::JidSingle).with_value(&String::from(jid.clone()))
+    }
+
+    /// Create a jid-multi Field with the given var and JIDs.
+    pub fn jid_multi<I: IntoIterator<Item = Jid>>(var: &str, jids: I) -> Field {
+        Field::new(var, FieldType::JidMulti).with_values(jids.into_iter().map(String::from))
+    }
+
+    /// Create a list-single Field with the given var and selected value,
+    /// the options being added with `with_option()`.
+    pub fn list_single(var: &str, value: &str) -> Field {
+        Field::new(var, FieldType::ListSingle).with_value(value)
+    }
+
+    /// Create a list-multi Field with the given var and selected values,
+    /// the options being added with `with_option()`.
+    pub fn list_multi<I: IntoIterator<Item = String>>(var: &str, values: I) -> Field {
+        Field::new(var, FieldType::ListMulti).with_values(values)
+    }
+
+    /// Get the value of a boolean Field, if it has a valid one.
+    pub fn as_bool(&self) -> Option<bool> {
+        match self.values.as_slice() {
+            [value] => parse_bool(value),
+            _ => None,
+        }
+    }
+
+    /// Parse the value of a single-valued Field, if it has any.
+    pub fn parse_single<T: FromStr>(&self) -> Result<Option<T>, Error>
+    where
+        Error: From<T::Err>,
+    {
+        match self.values.as_slice() {
+            [] => Ok(None),
+            [value] => Ok(Some(T::from_str(value)?)),
+            _ => Err(Error::ParseError(
+                "More than one value in a single-valued field.",
+            )),
+        }
+    }
+
+    /// Parse the value of a single-valued boolean Field, if it has any.
+    pub fn parse_single_bool(&self) -> Result<Option<bool>, Error> {
+        match self.values.as_slice() {
+            [] => Ok(None),
+            [value] => match parse_bool(value) {
+                Some(value) => Ok(Some(value)),
+                None => Err(Error::ParseError("Invalid boolean in a field.")),
+            },
+            _ => Err(Error::ParseError(
+                "More than one value in a single-valued field.",
+            )),
+        }
+    }
+
+    /// Check that `values` are acceptable for this Field, as defined in
+    /// a form.
+    pub fn validate(&self, values: &[String]) -> Result<(), ValidationErrorKind> {
+        if values.iter().all(|value| value.is_empty()) {
+            return if self.required {
+                Err(ValidationErrorKind::Missing)
+            } else {
+                Ok(())
+            };
+        }
+        let multiple = matches!(
+            self.type_,
+            FieldType::JidMulti | FieldType::ListMulti | FieldType::TextMulti
+        );
+        if !multiple && values.len() > 1 {
+            return Err(ValidationErrorKind::TooManyValues);
+        }
+        for value in values {
+            match self.type_ {
+                FieldType::Boolean if parse_bool(value).is_none() => {
+                    return Err(ValidationErrorKind::InvalidBoolean)
+                }
+                FieldType::JidSingle | FieldType::JidMulti if Jid::from_str(value).is_err() => {
+                    return Err(ValidationErrorKind::InvalidJid)
+                }
+                FieldType::ListSingle | FieldType::ListMulti
+                    if !self.options.is_empty()
+                        && !self.options.iter().any(|option| &option.value == value) =>
+                {
+                    return Err(ValidationErrorKind::NotAnOption)
+                }
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
     fn is_list(&self) -> bool {
         self.type_ == FieldType::ListSingle || self.type_ == FieldType::ListMulti
     }
 }
 
+/// Why a value got rejected by a [field](struct.Field.html).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValidationErrorKind {
+    /// A required field is missing, or is empty.
+    Missing,
+
+    /// More than one value has been provided to a single-valued field.
+    TooManyValues,
+
+    /// A boolean field got something else than "0", "1", "false" or "true".
+    InvalidBoolean,
+
+    /// A jid- field got an invalid JID.
+    InvalidJid,
+
+    /// A list- field got a value which isn’t one of its options.
+    NotAnOption,
+}
+
+/// A submitted form doesn’t match its definition.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationError {
+    /// The var of the offending field.
+    pub var: String,
+
+    /// What is wrong with it.
+    pub kind: ValidationErrorKind,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let reason = match self.kind {
+            ValidationErrorKind::Missing => "missing required value",
+            ValidationErrorKind::TooManyValues => "too many values",
+            ValidationErrorKind::InvalidBoolean => "invalid boolean",
+            ValidationErrorKind::InvalidJid => "invalid JID",
+            ValidationErrorKind::NotAnOption => "value not in the options",
+        };
+        write!(fmt, "field {}: {}", self.var, reason)
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
 impl TryFrom<Element> for Field {
     type Error = Error;
 
@@ -242,6 +438,161 @@ impl DataForm {
             fields,
         }
     }
+
+    /// Set the title of this form.
+    pub fn with_title(mut self, title: &str) -> DataForm {
+        self.title = Some(String::from(title));
+        self
+    }
+
+    /// Set the instructions given with this form.
+    pub fn with_instructions(mut self, instructions: &str) -> DataForm {
+        self.instructions = Some(String::from(instructions));
+        self
+    }
+
+    /// Append a field to this form.
+    pub fn with_field(mut self, field: Field) -> DataForm {
+        self.fields.push(field);
+        self
+    }
+
+    /// Create a new form out of the values of each field, sorted by var,
+    /// a "FORM_TYPE" entry going to the `form_type` member.
+    pub fn from_values(type_: DataFormType, mut values: HashMap<String, Vec<String>>) -> DataForm {
+        let form_type = values
+            .remove("FORM_TYPE")
+            .and_then(|mut values| values.pop());
+        let mut fields: Vec<_> = values
+            .into_iter()
+            .map(|(var, values)| Field::new(&var, FieldType::default()).with_values(values))
+            .collect();
+        fields.sort_by(|a, b| a.var.cmp(&b.var));
+        DataForm {
+            type_,
+            form_type,
+            title: None,
+            instructions: None,
+            fields,
+        }
+    }
+
+    /// Get the field of the given var.
+    pub fn get_field(&self, var: &str) -> Option<&Field> {
+        self.fields.iter().find(|field| field.var == var)
+    }
+
+    /// Check the values of `submitted` against this form definition,
+    /// every required field having to be present, and every value
+    /// matching the type of its field.
+    ///
+    /// Fields unknown to this form are ignored.
+    pub fn validate(&self, submitted: &DataForm) -> Result<(), ValidationError> {
+        for field in self.fields.iter() {
+            if field.type_ == FieldType::Fixed {
+                continue;
+            }
+            let values = submitted
+                .get_field(&field.var)
+                .map(|field| field.values.as_slice())
+                .unwrap_or(&[]);
+            field.validate(values).map_err(|kind| ValidationError {
+                var: field.var.clone(),
+                kind,
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Fill this form definition with stored `answers`, replacing the
+    /// default values of the fields they know about.
+    ///
+    /// Answers to fields this form doesn’t define are dropped, as are the
+    /// answers to fixed fields.
+    pub fn prefill(&self, answers: &HashMap<String, Vec<String>>) -> DataForm {
+        let mut form = self.clone();
+        for field in form.fields.iter_mut() {
+            if field.type_ == FieldType::Fixed {
+                continue;
+            }
+            if let Some(values) = answers.get(&field.var) {
+                field.values = values.clone();
+            }
+        }
+        form
+    }
+
+    /// Build the form submitting `answers` to this form definition, the
+    /// fields without an answer keeping their default value, after
+    /// validating it locally instead of waiting for the other entity to
+    /// reject it.
+    ///
+    /// Answers to fields this form doesn’t define are dropped.
+    pub fn submit(
+        &self,
+        answers: &HashMap<String, Vec<String>>,
+    ) -> Result<DataForm, ValidationError> {
+        let fields = self
+            .prefill(answers)
+            .fields
+            .into_iter()
+            .filter(|field| field.type_ != FieldType::Fixed)
+            .map(|field| Field::new(&field.var, field.type_).with_values(field.values))
+            .collect();
+        let submitted = DataForm {
+            type_: DataFormType::Submit,
+            form_type: self.form_type.clone(),
+            title: None,
+            instructions: None,
+            fields,
+        };
+        self.validate(&submitted)?;
+        Ok(submitted)
+    }
+
+    /// The vars of the fields whose values differ between this form and
+    /// `other`, in the order of this form, followed by the fields only
+    /// `other` has.
+    pub fn changed_fields<'a>(&'a self, other: &'a DataForm) -> Vec<&'a str> {
+        let empty: &[String] = &[];
+        let mut changed: Vec<&str> = self
+            .fields
+            .iter()
+            .filter(|field| field.type_ != FieldType::Fixed)
+            .filter(|field| {
+                let values = other
+                    .get_field(&field.var)
+                    .map(|field| field.values.as_slice())
+                    .unwrap_or(empty);
+                field.values.as_slice() != values
+            })
+            .map(|field| field.var.as_str())
+            .collect();
+        changed.extend(
+            other
+                .fields
+                .iter()
+                .filter(|field| field.type_ != FieldType::Fixed)
+                .filter(|field| self.get_field(&field.var).is_none())
+                .map(|field| field.var.as_str()),
+        );
+        changed
+    }
+}
+
+impl From<DataForm> for HashMap<String, Vec<String>> {
+    fn from(form: DataForm) -> HashMap<String, Vec<String>> {
+        form.form_type
+            .map(|form_type| (String::from("FORM_TYPE"), vec![form_type]))
+            .into_iter()
+            .chain(
+                form.fields
+                    .into_iter()
+                    .filter(|field| field.type_ != FieldType::Fixed)
+                    .map(|field| (field.var, field.values)),
+            )
+            .collect()
+    }
 }
 
 impl TryFrom<Element> for DataForm {
@@ -419,4 +770,170 @@ mod tests {
             "Element option must not have more than one value child."
         );
     }
+
+    #[test]
+    fn test_builder() {
+        let form = DataForm::new(DataFormType::Form, "urn:example:config", vec![])
+            .with_title("Configuration")
+            .with_field(Field::boolean("public", true).with_required())
+            .with_field(
+                Field::list_single("color", "red")
+                    .with_option("red", Some("Red"))
+                    .with_option("blue", None),
+            )
+            .with_field(Field::text_private("password", "hunter2"));
+        let elem: Element = form.into();
+        let elem2: Element = "<x xmlns='jabber:x:data' type='form'><title>Configuration</title><field var='FORM_TYPE' type='hidden'><value>urn:example:config</value></field><field var='public' type='boolean'><required/><value>1</value></field><field var='color' type='list-single'><option label='Red'><value>red</value></option><option><value>blue</value></option><value>red</value></field><field var='password' type='text-private'><value>hunter2</value></field></x>".parse().unwrap();
+        assert_eq!(elem, elem2);
+
+        let form = DataForm::try_from(elem).unwrap();
+        assert_eq!(form.get_field("public").unwrap().as_bool(), Some(true));
+    }
+
+    #[test]
+    fn test_validate() {
+        let definition = DataForm::new(DataFormType::Form, "urn:example:config", vec![])
+            .with_field(Field::fixed("intro", "Hello"))
+            .with_field(Field::new("public", FieldType::Boolean).with_required())
+            .with_field(Field::new("admins", FieldType::JidMulti))
+            .with_field(
+                Field::new("color", FieldType::ListSingle)
+                    .with_option("red", None)
+                    .with_option("blue", None),
+            );
+
+        let submit = |fields| DataForm::new(DataFormType::Submit, "urn:example:config", fields);
+        let check = |fields| definition.validate(&submit(fields));
+
+        check(vec![Field::boolean("public", false)]).unwrap();
+        check(vec![
+            Field::new("public", FieldType::Boolean).with_value("true"),
+            Field::list_single("color", "blue"),
+            Field::new("admins", FieldType::JidMulti)
+                .with_value("a@example.org")
+                .with_value("b@example.org"),
+        ])
+        .unwrap();
+
+        let error = check(vec![]).unwrap_err();
+        assert_eq!(error.var, "public");
+        assert_eq!(error.kind, ValidationErrorKind::Missing);
+
+        let error = check(vec![
+            Field::new("public", FieldType::Boolean).with_value("yes")
+        ])
+        .unwrap_err();
+        assert_eq!(error.kind, ValidationErrorKind::InvalidBoolean);
+
+        let error = check(vec![
+            Field::boolean("public", true),
+            Field::new("color", FieldType::ListSingle)
+                .with_value("red")
+                .with_value("blue"),
+        ])
+        .unwrap_err();
+        assert_eq!(error.var, "color");
+        assert_eq!(error.kind, ValidationErrorKind::TooManyValues);
+
+        let error = check(vec![
+            Field::boolean("public", true),
+            Field::list_single("color", "green"),
+        ])
+        .unwrap_err();
+        assert_eq!(error.kind, ValidationErrorKind::NotAnOption);
+
+        let error = check(vec![
+            Field::boolean("public", true),
+            Field::new("admins", FieldType::JidMulti).with_value("@example.org"),
+        ])
+        .unwrap_err();
+        assert_eq!(error.to_string(), "field admins: invalid JID");
+    }
+
+    #[test]
+    fn test_prefill_and_submit() {
+        let definition = DataForm::new(DataFormType::Form, "urn:example:config", vec![])
+            .with_field(Field::fixed("intro", "Hello"))
+            .with_field(Field::boolean("public", false).with_required())
+            .with_field(Field::text_single("name", "Room"))
+            .with_field(
+                Field::new("color", FieldType::ListSingle)
+                    .with_option("red", None)
+                    .with_option("blue", None),
+            );
+        let mut answers = HashMap::new();
+        answers.insert(String::from("color"), vec![String::from("blue")]);
+        answers.insert(String::from("intro"), vec![String::from("Bye")]);
+        answers.insert(String::from("unknown"), vec![String::from("1")]);
+
+        let prefilled = definition.prefill(&answers);
+        assert_eq!(prefilled.type_, DataFormType::Form);
+        assert_eq!(prefilled.get_field("color").unwrap().values, ["blue"]);
+        assert_eq!(prefilled.get_field("intro").unwrap().values, ["Hello"]);
+        assert!(prefilled.get_field("unknown").is_none());
+        assert_eq!(definition.changed_fields(&prefilled), ["color"]);
+
+        let submitted = definition.submit(&answers).unwrap();
+        assert_eq!(submitted.type_, DataFormType::Submit);
+        assert_eq!(submitted.form_type.as_deref(), Some("urn:example:config"));
+        let vars: Vec<_> = submitted.fields.iter().map(|field| &field.var).collect();
+        assert_eq!(vars, ["public", "name", "color"]);
+        assert_eq!(submitted.get_field("public").unwrap().values, ["0"]);
+        assert_eq!(definition.changed_fields(&submitted), ["color"]);
+
+        answers.insert(String::from("public"), vec![]);
+        let error = definition.submit(&answers).unwrap_err();
+        assert_eq!(error.var, "public");
+        assert_eq!(error.kind, ValidationErrorKind::Missing);
+
+        answers.insert(String::from("public"), vec![String::from("1")]);
+        answers.insert(String::from("color"), vec![String::from("green")]);
+        let error = definition.submit(&answers).unwrap_err();
+        assert_eq!(error.kind, ValidationErrorKind::NotAnOption);
+    }
+
+    #[test]
+    fn test_values() {
+        let form = DataForm::new(DataFormType::Submit, "urn:example:config", vec![])
+            .with_field(Field::fixed("intro", "Hello"))
+            .with_field(Field::boolean("public", true))
+            .with_field(Field::text_multi("description", "a\nb"));
+        let values: HashMap<String, Vec<String>> = form.into();
+        assert_eq!(values.len(), 3);
+        assert_eq!(values["FORM_TYPE"], ["urn:example:config"]);
+        assert_eq!(values["public"], ["1"]);
+        assert_eq!(values["description"], ["a", "b"]);
+
+        let form = DataForm::from_values(DataFormType::Submit, values);
+        assert_eq!(form.form_type.as_deref(), Some("urn:example:config"));
+        assert_eq!(form.fields.len(), 2);
+        assert_eq!(form.fields[0].var, "description");
+        assert_eq!(form.fields[1].values, ["1"]);
+    }
+
+    #[test]
+    fn test_parse_single() {
+        let field = Field::jid_single("owner", &Jid::from_str("a@b").unwrap());
+        assert_eq!(
+            field.parse_single::<Jid>().unwrap(),
+            Some(Jid::from_str("a@b").unwrap())
+        );
+        assert_eq!(
+            Field::new("owner", FieldType::JidSingle)
+                .parse_single::<Jid>()
+                .unwrap(),
+            None
+        );
+        assert!(Field::text_multi("owner", "a@b\nc@d")
+            .parse_single::<Jid>()
+            .is_err());
+
+        assert_eq!(
+            Field::boolean("public", false).parse_single_bool().unwrap(),
+            Some(false)
+        );
+        assert!(Field::text_single("public", "yes")
+            .parse_single_bool()
+            .is_err());
+    }
 }
@@ -5,9 +5,10 @@
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
 use crate::util::error::Error;
-use chrono::{DateTime as ChronoDateTime, FixedOffset};
+use chrono::{DateTime as ChronoDateTime, FixedOffset, Utc};
 use minidom::{IntoAttributeValue, Node};
 use std::str::FromStr;
+use std::time::SystemTime;
 
 /// Implements the DateTime profile of XEP-0082, which represents a
 /// non-recurring moment in time, with an accuracy of seconds or fraction of
@@ -26,6 +27,11 @@ impl DateTime {
         DateTime(self.0.with_timezone(&tz))
     }
 
+    /// Converts a system time to a `DateTime` in the `tz` timezone.
+    pub fn from_system_time(time: SystemTime, tz: FixedOffset) -> DateTime {
+        DateTime(ChronoDateTime::<Utc>::from(time).with_timezone(&tz))
+    }
+
     /// Formats this `DateTime` with the specified format string.
     pub fn format(&self, fmt: &str) -> String {
         format!("{}", self.0.format(fmt))
@@ -127,6 +133,17 @@ mod tests {
         assert_eq!(message.to_string(), "premature end of input");
     }
 
+    #[test]
+    fn test_system_time() {
+        let time = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000_000);
+        let date = DateTime::from_system_time(time, FixedOffset::east_opt(2 * 3600).unwrap());
+        assert_eq!(
+            date,
+            DateTime::from_str("2001-09-09T03:46:40+02:00").unwrap()
+        );
+        assert_eq!(date.timezone(), FixedOffset::east_opt(2 * 3600).unwrap());
+    }
+
     #[test]
     fn test_serialise() {
         let date =
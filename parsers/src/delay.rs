@@ -22,10 +22,33 @@ generate_element!(
     ],
     text: (
         /// The optional reason this message got delayed.
-        data: PlainText<Option<String>>
+        reason: PlainText<Option<String>>
     )
 );
 
+impl Delay {
+    /// Create a new delay at the given time.
+    pub fn new(stamp: DateTime) -> Delay {
+        Delay {
+            from: None,
+            stamp,
+            reason: None,
+        }
+    }
+
+    /// Sets the entity which delayed this stanza.
+    pub fn with_from(mut self, from: Jid) -> Delay {
+        self.from = Some(from);
+        self
+    }
+
+    /// Sets the reason this stanza got delayed.
+    pub fn with_reason<R: Into<String>>(mut self, reason: R) -> Delay {
+        self.reason = Some(reason.into());
+        self
+    }
+}
+
 impl MessagePayload for Delay {}
 impl PresencePayload for Delay {}
 
@@ -62,7 +85,7 @@ mod tests {
             delay.stamp,
             DateTime::from_str("2002-09-10T23:08:25Z").unwrap()
         );
-        assert_eq!(delay.data, None);
+        assert_eq!(delay.reason, None);
     }
 
     #[test]
@@ -96,23 +119,17 @@ mod tests {
         let elem: Element = "<delay xmlns='urn:xmpp:delay' stamp='2002-09-10T23:08:25+00:00'/>"
             .parse()
             .unwrap();
-        let delay = Delay {
-            from: None,
-            stamp: DateTime::from_str("2002-09-10T23:08:25Z").unwrap(),
-            data: None,
-        };
+        let delay = Delay::new(DateTime::from_str("2002-09-10T23:08:25Z").unwrap());
         let elem2 = delay.into();
         assert_eq!(elem, elem2);
     }
 
     #[test]
-    fn test_serialise_data() {
+    fn test_serialise_reason() {
         let elem: Element = "<delay xmlns='urn:xmpp:delay' from='juliet@example.org' stamp='2002-09-10T23:08:25+00:00'>Reason</delay>".parse().unwrap();
-        let delay = Delay {
-            from: Some(Jid::Bare(BareJid::new("juliet", "example.org"))),
-            stamp: DateTime::from_str("2002-09-10T23:08:25Z").unwrap(),
-            data: Some(String::from("Reason")),
-        };
+        let delay = Delay::new(DateTime::from_str("2002-09-10T23:08:25Z").unwrap())
+            .with_from(Jid::Bare(BareJid::new("juliet", "example.org")))
+            .with_reason("Reason");
         let elem2 = delay.into();
         assert_eq!(elem, elem2);
     }
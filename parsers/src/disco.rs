@@ -7,6 +7,8 @@
 use crate::data_forms::{DataForm, DataFormType};
 use crate::iq::{IqGetPayload, IqResultPayload};
 use crate::ns;
+use crate::rsm::{SetQuery, SetResult};
+use crate::server_info::ServerInfo;
 use crate::util::error::Error;
 use crate::Element;
 use jid::Jid;
@@ -114,6 +116,47 @@ pub struct DiscoInfoResult {
 
 impl IqResultPayload for DiscoInfoResult {}
 
+impl DiscoInfoResult {
+    /// Creates a disco#info result with these identities and features.
+    pub fn new(identities: Vec<Identity>, features: Vec<Feature>) -> DiscoInfoResult {
+        DiscoInfoResult {
+            node: None,
+            identities,
+            features,
+            extensions: vec![],
+        }
+    }
+
+    /// Sets the node this result is about.
+    pub fn with_node<N: Into<String>>(mut self, node: N) -> DiscoInfoResult {
+        self.node = Some(node.into());
+        self
+    }
+
+    /// Adds an extended information form (XEP-0128), which must be of
+    /// the 'result' type and have a FORM_TYPE.
+    pub fn with_extension(mut self, form: DataForm) -> DiscoInfoResult {
+        self.extensions.push(form);
+        self
+    }
+
+    /// Gets the extended information form of this FORM_TYPE, if any.
+    pub fn extension(&self, form_type: &str) -> Option<&DataForm> {
+        self.extensions
+            .iter()
+            .find(|form| form.form_type.as_deref() == Some(form_type))
+    }
+
+    /// Parses the contact addresses (XEP-0157) of this entity, if it
+    /// provided any.
+    pub fn server_info(&self) -> Result<Option<ServerInfo>, Error> {
+        self.extension(ns::SERVER_INFO)
+            .cloned()
+            .map(ServerInfo::try_from)
+            .transpose()
+    }
+}
+
 impl TryFrom<Element> for DiscoInfoResult {
     type Error = Error;
 
@@ -193,8 +236,25 @@ DiscoItemsQuery, "query", DISCO_ITEMS,
 attributes: [
     /// Node on which we are doing the discovery.
     node: Option<String> = "node",
+],
+children: [
+    /// Optional paging via Result Set Management.
+    rsm: Option<SetQuery> = ("set", RSM) => SetQuery
 ]);
 
+impl DiscoItemsQuery {
+    /// Query the items of `node`, or of the entity itself if `None`.
+    pub fn new(node: Option<String>) -> DiscoItemsQuery {
+        DiscoItemsQuery { node, rsm: None }
+    }
+
+    /// Only query one page of the items.
+    pub fn with_rsm(mut self, rsm: SetQuery) -> DiscoItemsQuery {
+        self.rsm = Some(rsm);
+        self
+    }
+}
+
 impl IqGetPayload for DiscoItemsQuery {}
 
 generate_element!(
@@ -209,6 +269,13 @@ attributes: [
     name: Option<String> = "name",
 ]);
 
+impl Item {
+    /// Create a new item pointing to `jid`, or to `node` on it.
+    pub fn new(jid: Jid, node: Option<String>, name: Option<String>) -> Item {
+        Item { jid, node, name }
+    }
+}
+
 generate_element!(
     /// Structure representing a `<query
     /// xmlns='http://jabber.org/protocol/disco#items'/>` element.
@@ -222,10 +289,30 @@ generate_element!(
     ],
     children: [
         /// List of items pointed by this entity.
-        items: Vec<Item> = ("item", DISCO_ITEMS) => Item
+        items: Vec<Item> = ("item", DISCO_ITEMS) => Item,
+
+        /// Which page of the items this is, if paged.
+        rsm: Option<SetResult> = ("set", RSM) => SetResult
     ]
 );
 
+impl DiscoItemsResult {
+    /// Create a result listing `items` of `node`.
+    pub fn new(node: Option<String>, items: Vec<Item>) -> DiscoItemsResult {
+        DiscoItemsResult {
+            node,
+            items,
+            rsm: None,
+        }
+    }
+
+    /// Tell which page of the items this is.
+    pub fn with_rsm(mut self, rsm: SetResult) -> DiscoItemsResult {
+        self.rsm = Some(rsm);
+        self
+    }
+}
+
 impl IqResultPayload for DiscoItemsResult {}
 
 #[cfg(test)]
@@ -242,8 +329,8 @@ mod tests {
         assert_size!(DiscoInfoResult, 48);
 
         assert_size!(Item, 64);
-        assert_size!(DiscoItemsQuery, 12);
-        assert_size!(DiscoItemsResult, 24);
+        assert_size!(DiscoItemsQuery, 52);
+        assert_size!(DiscoItemsResult, 64);
     }
 
     #[cfg(target_pointer_width = "64")]
@@ -255,8 +342,8 @@ mod tests {
         assert_size!(DiscoInfoResult, 96);
 
         assert_size!(Item, 128);
-        assert_size!(DiscoItemsQuery, 24);
-        assert_size!(DiscoItemsResult, 48);
+        assert_size!(DiscoItemsQuery, 104);
+        assert_size!(DiscoItemsResult, 128);
     }
 
     #[test]
@@ -302,6 +389,29 @@ mod tests {
         assert_eq!(elem1, elem2);
     }
 
+    #[test]
+    fn test_server_info() {
+        let elem: Element = "<query xmlns='http://jabber.org/protocol/disco#info'><identity category='server' type='im'/><feature var='http://jabber.org/protocol/disco#info'/><x xmlns='jabber:x:data' type='result'><field var='FORM_TYPE' type='hidden'><value>http://jabber.org/network/serverinfo</value></field><field var='abuse-addresses' type='list-multi'><value>mailto:abuse@shakespeare.lit</value><value>xmpp:abuse@shakespeare.lit</value></field><field var='status-addresses' type='list-multi'><value>https://status.shakespeare.lit</value></field><field var='support-addresses' type='list-multi'><value>xmpp:support@shakespeare.lit</value></field></x></query>".parse().unwrap();
+        let query = DiscoInfoResult::try_from(elem).unwrap();
+        assert!(query.extension("example").is_none());
+        let server_info = query.server_info().unwrap().unwrap();
+        assert_eq!(
+            server_info.abuse,
+            ["mailto:abuse@shakespeare.lit", "xmpp:abuse@shakespeare.lit"]
+        );
+        assert_eq!(server_info.status, ["https://status.shakespeare.lit"]);
+        assert_eq!(server_info.support, ["xmpp:support@shakespeare.lit"]);
+        assert!(server_info.admin.is_empty());
+
+        let query = DiscoInfoResult::new(
+            vec![Identity::new("server", "im", "en", "Shakespeare")],
+            vec![Feature::new(ns::DISCO_INFO)],
+        );
+        assert_eq!(query.server_info().unwrap(), None);
+        let query = query.with_extension(DataForm::from(server_info.clone()));
+        assert_eq!(query.server_info().unwrap(), Some(server_info));
+    }
+
     #[test]
     fn test_invalid() {
         let elem: Element =
@@ -449,4 +559,24 @@ mod tests {
         assert_eq!(query.items[1].node, Some(String::from("test")));
         assert_eq!(query.items[1].name, Some(String::from("A component")));
     }
+
+    #[test]
+    fn test_rsm() {
+        let elem: Element = "<query xmlns='http://jabber.org/protocol/disco#items'><set xmlns='http://jabber.org/protocol/rsm'><max>2</max></set></query>".parse().unwrap();
+        let query = DiscoItemsQuery::try_from(elem.clone()).unwrap();
+        assert_eq!(query.rsm.as_ref().unwrap().max, Some(2));
+        let elem2 = DiscoItemsQuery::new(None)
+            .with_rsm(SetQuery::first_page(Some(2)))
+            .into();
+        assert_eq!(elem, elem2);
+
+        let elem: Element = "<query xmlns='http://jabber.org/protocol/disco#items'><item jid='a.example'/><item jid='b.example'/><set xmlns='http://jabber.org/protocol/rsm'><first index='0'>a.example</first><last>b.example</last><count>5</count></set></query>".parse().unwrap();
+        let query = DiscoItemsResult::try_from(elem.clone()).unwrap();
+        assert_eq!(query.items.len(), 2);
+        let rsm = query.rsm.as_ref().unwrap();
+        assert_eq!(rsm.last.as_deref(), Some("b.example"));
+        assert_eq!(rsm.count, Some(5));
+        let elem2 = query.into();
+        assert_eq!(elem, elem2);
+    }
 }
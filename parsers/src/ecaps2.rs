@@ -10,10 +10,6 @@ use crate::hashes::{Algo, Hash};
 use crate::ns;
 use crate::presence::PresencePayload;
 use crate::util::error::Error;
-use blake2::Blake2bVar;
-use digest::{Digest, Update, VariableOutput};
-use sha2::{Sha256, Sha512};
-use sha3::{Sha3_256, Sha3_512};
 
 generate_element!(
     /// Represents a set of capability hashes, all of them must correspond to
@@ -118,52 +114,14 @@ pub fn compute_disco(disco: &DiscoInfoResult) -> Result<Vec<u8>, Error> {
     Ok(final_string)
 }
 
-fn get_hash_vec(hash: &[u8]) -> Vec<u8> {
-    let mut vec = Vec::with_capacity(hash.len());
-    vec.extend_from_slice(hash);
-    vec
-}
-
 /// Hashes the result of [compute_disco()] with one of the supported [hash
 /// algorithms](../hashes/enum.Algo.html).
 pub fn hash_ecaps2(data: &[u8], algo: Algo) -> Result<Hash, Error> {
-    Ok(Hash {
-        hash: match algo {
-            Algo::Sha_256 => {
-                let hash = Sha256::digest(data);
-                get_hash_vec(hash.as_slice())
-            }
-            Algo::Sha_512 => {
-                let hash = Sha512::digest(data);
-                get_hash_vec(hash.as_slice())
-            }
-            Algo::Sha3_256 => {
-                let hash = Sha3_256::digest(data);
-                get_hash_vec(hash.as_slice())
-            }
-            Algo::Sha3_512 => {
-                let hash = Sha3_512::digest(data);
-                get_hash_vec(hash.as_slice())
-            }
-            Algo::Blake2b_256 => {
-                let mut hasher = Blake2bVar::new(32).unwrap();
-                hasher.update(data);
-                let mut vec = vec![0u8; 32];
-                hasher.finalize_variable(&mut vec).unwrap();
-                vec
-            }
-            Algo::Blake2b_512 => {
-                let mut hasher = Blake2bVar::new(64).unwrap();
-                hasher.update(data);
-                let mut vec = vec![0u8; 64];
-                hasher.finalize_variable(&mut vec).unwrap();
-                vec
-            }
-            Algo::Sha_1 => return Err(Error::ParseError("Disabled algorithm sha-1: unsafe.")),
-            Algo::Unknown(_algo) => return Err(Error::ParseError("Unknown algorithm in ecaps2.")),
-        },
-        algo,
-    })
+    match algo {
+        Algo::Sha_1 => Err(Error::ParseError("Disabled algorithm sha-1: unsafe.")),
+        Algo::Unknown(_) => Err(Error::ParseError("Unknown algorithm in ecaps2.")),
+        algo => Hash::compute(algo, data),
+    }
 }
 
 /// Helper function to create the query for the disco#info corresponding to an
@@ -0,0 +1,292 @@
+// Copyright (c) 2022 Emmanuel Gil Peyrot <linkmauve@linkmauve.fr>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use crate::message::{Message, MessagePayload};
+use crate::ns;
+use crate::util::error::Error;
+use crate::Element;
+use std::convert::TryFrom;
+use std::ops;
+
+/// A range of the body or subject being a fallback, counted in code points.
+///
+/// When neither end is known, the whole body or subject is a fallback.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Range {
+    /// The index of the first character of the range.
+    pub start: Option<usize>,
+
+    /// The index of the character after the range.
+    pub end: Option<usize>,
+}
+
+impl Range {
+    /// A range covering the whole body or subject.
+    pub fn whole() -> Range {
+        Range {
+            start: None,
+            end: None,
+        }
+    }
+
+    /// The range of code points being a fallback, or `None` when it is the
+    /// whole text.
+    pub fn range(&self) -> Option<ops::Range<usize>> {
+        match (self.start, self.end) {
+            (Some(start), Some(end)) => Some(start..end),
+            _ => None,
+        }
+    }
+
+    fn try_from_element(elem: &Element) -> Result<Range, Error> {
+        let range = Range {
+            start: get_attr!(elem, "start", Option),
+            end: get_attr!(elem, "end", Option),
+        };
+        match (range.start, range.end) {
+            (Some(start), Some(end)) if start > end => Err(Error::ParseError(
+                "The start of a fallback range must not be after its end.",
+            )),
+            (Some(_), None) | (None, Some(_)) => Err(Error::ParseError(
+                "A fallback range must have both a start and an end.",
+            )),
+            _ => Ok(range),
+        }
+    }
+
+    fn to_element(&self, name: &str) -> Element {
+        Element::builder(name, ns::FALLBACK)
+            .attr("start", self.start)
+            .attr("end", self.end)
+            .build()
+    }
+}
+
+impl From<ops::Range<usize>> for Range {
+    fn from(range: ops::Range<usize>) -> Range {
+        Range {
+            start: Some(range.start),
+            end: Some(range.end),
+        }
+    }
+}
+
+/// Indicates that some or all of the body or subject of a message is only
+/// there for the recipients not supporting a specification.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fallback {
+    /// The namespace of the specification this is a fallback for.
+    pub for_: Option<String>,
+
+    /// The fallback ranges of the body, the whole body being a fallback
+    /// when empty and without any subject range either.
+    pub bodies: Vec<Range>,
+
+    /// The fallback ranges of the subject.
+    pub subjects: Vec<Range>,
+}
+
+impl MessagePayload for Fallback {}
+
+impl Fallback {
+    /// Creates a fallback for this specification, covering the whole body.
+    pub fn new<F: Into<String>>(for_: F) -> Fallback {
+        Fallback {
+            for_: Some(for_.into()),
+            bodies: Vec::new(),
+            subjects: Vec::new(),
+        }
+    }
+
+    /// Restricts this fallback to this range of the body.
+    pub fn with_body_range<R: Into<Range>>(mut self, range: R) -> Fallback {
+        self.bodies.push(range.into());
+        self
+    }
+
+    /// Restricts this fallback to this range of the subject.
+    pub fn with_subject_range<R: Into<Range>>(mut self, range: R) -> Fallback {
+        self.subjects.push(range.into());
+        self
+    }
+
+    /// Whether this is a fallback for this specification.
+    pub fn is_for(&self, namespace: &str) -> bool {
+        self.for_.as_deref() == Some(namespace)
+    }
+
+    fn covers_whole_body(&self) -> bool {
+        (self.bodies.is_empty() && self.subjects.is_empty())
+            || self.bodies.iter().any(|range| range.range().is_none())
+    }
+
+    /// Removes the parts of `body` covered by this fallback.
+    pub fn strip(&self, body: &str) -> String {
+        strip_body(body, std::iter::once(self))
+    }
+}
+
+/// Removes the parts of `body` covered by any of these fallbacks, which
+/// must all have been sent along it since their ranges all refer to the
+/// original body.
+pub fn strip_body<'a, I: IntoIterator<Item = &'a Fallback>>(body: &str, fallbacks: I) -> String {
+    let mut ranges = Vec::new();
+    for fallback in fallbacks {
+        if fallback.covers_whole_body() {
+            return String::new();
+        }
+        ranges.extend(fallback.bodies.iter().filter_map(Range::range));
+    }
+    body.chars()
+        .enumerate()
+        .filter(|(index, _)| !ranges.iter().any(|range| range.contains(index)))
+        .map(|(_, c)| c)
+        .collect()
+}
+
+impl TryFrom<Element> for Fallback {
+    type Error = Error;
+
+    fn try_from(elem: Element) -> Result<Fallback, Error> {
+        check_self!(elem, "fallback", FALLBACK);
+        check_no_unknown_attributes!(elem, "fallback", ["for"]);
+        let mut fallback = Fallback {
+            for_: get_attr!(elem, "for", Option),
+            bodies: Vec::new(),
+            subjects: Vec::new(),
+        };
+        for child in elem.children() {
+            if child.is("body", ns::FALLBACK) {
+                check_no_children!(child, "body");
+                check_no_unknown_attributes!(child, "body", ["start", "end"]);
+                fallback.bodies.push(Range::try_from_element(child)?);
+            } else if child.is("subject", ns::FALLBACK) {
+                check_no_children!(child, "subject");
+                check_no_unknown_attributes!(child, "subject", ["start", "end"]);
+                fallback.subjects.push(Range::try_from_element(child)?);
+            } else {
+                return Err(Error::ParseError("Unknown child in fallback element."));
+            }
+        }
+        Ok(fallback)
+    }
+}
+
+impl From<Fallback> for Element {
+    fn from(fallback: Fallback) -> Element {
+        Element::builder("fallback", ns::FALLBACK)
+            .attr("for", fallback.for_)
+            .append_all(fallback.bodies.iter().map(|range| range.to_element("body")))
+            .append_all(
+                fallback
+                    .subjects
+                    .iter()
+                    .map(|range| range.to_element("subject")),
+            )
+            .build()
+    }
+}
+
+impl Message {
+    /// The fallback indications attached to this message.
+    pub fn fallbacks(&self) -> Vec<Fallback> {
+        self.payloads
+            .iter()
+            .filter(|payload| payload.is("fallback", ns::FALLBACK))
+            .filter_map(|payload| Fallback::try_from(payload.clone()).ok())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(target_pointer_width = "32")]
+    #[test]
+    fn test_size() {
+        assert_size!(Range, 16);
+        assert_size!(Fallback, 36);
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    fn test_size() {
+        assert_size!(Range, 32);
+        assert_size!(Fallback, 72);
+    }
+
+    #[test]
+    fn test_whole_body() {
+        let elem: Element = "<fallback xmlns='urn:xmpp:fallback:0' for='urn:xmpp:eme:0'/>"
+            .parse()
+            .unwrap();
+        let fallback = Fallback::try_from(elem.clone()).unwrap();
+        assert_eq!(fallback, Fallback::new("urn:xmpp:eme:0"));
+        assert!(fallback.is_for(ns::EME));
+        assert_eq!(fallback.strip("This message is encrypted."), "");
+        assert_eq!(Element::from(fallback), elem);
+    }
+
+    #[test]
+    fn test_ranges() {
+        let elem: Element = "<fallback xmlns='urn:xmpp:fallback:0' for='urn:xmpp:reply:0'><body start='0' end='18'/></fallback>"
+            .parse()
+            .unwrap();
+        let fallback = Fallback::try_from(elem.clone()).unwrap();
+        assert_eq!(
+            fallback,
+            Fallback::new("urn:xmpp:reply:0").with_body_range(0..18)
+        );
+        assert_eq!(
+            fallback.strip("> Anna wrote: Héé\nWe should bake a cake"),
+            "We should bake a cake"
+        );
+        assert_eq!(Element::from(fallback), elem);
+    }
+
+    #[test]
+    fn test_message() {
+        let elem: Element = "<message xmlns='jabber:client' to='anna@example.com' type='chat'><body>&gt; Anna wrote:\n&gt; Hi!\nHello </body><fallback xmlns='urn:xmpp:fallback:0' for='urn:xmpp:reply:0'><body start='0' end='20'/></fallback><fallback xmlns='urn:xmpp:fallback:0' for='urn:example'><body start='25' end='26'/></fallback></message>"
+            .parse()
+            .unwrap();
+        let message = Message::try_from(elem).unwrap();
+        let fallbacks = message.fallbacks();
+        assert_eq!(fallbacks.len(), 2);
+        let body = &message.bodies[""].0;
+        assert_eq!(strip_body(body, &fallbacks), "Hello");
+    }
+
+    #[test]
+    fn test_invalid() {
+        let elem: Element = "<fallback xmlns='urn:xmpp:fallback:0'><body start='5'/></fallback>"
+            .parse()
+            .unwrap();
+        let error = Fallback::try_from(elem).unwrap_err();
+        let message = match error {
+            Error::ParseError(string) => string,
+            _ => panic!(),
+        };
+        assert_eq!(
+            message,
+            "A fallback range must have both a start and an end."
+        );
+
+        let elem: Element =
+            "<fallback xmlns='urn:xmpp:fallback:0'><body start='5' end='2'/></fallback>"
+                .parse()
+                .unwrap();
+        let error = Fallback::try_from(elem).unwrap_err();
+        let message = match error {
+            Error::ParseError(string) => string,
+            _ => panic!(),
+        };
+        assert_eq!(
+            message,
+            "The start of a fallback range must not be after its end."
+        );
+    }
+}
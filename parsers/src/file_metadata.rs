@@ -0,0 +1,293 @@
+// Copyright (c) 2022 Emmanuel Gil Peyrot <linkmauve@linkmauve.fr>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use crate::date::DateTime;
+use crate::hashes::Hash;
+use crate::jingle_ft::File as JingleFile;
+use crate::ns;
+use crate::thumbs::Thumbnail;
+use crate::util::error::Error;
+use crate::Element;
+use std::convert::TryFrom;
+
+/// The metadata of a file, shared or about to be.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct File {
+    /// The MIME type of this file.
+    pub media_type: Option<String>,
+
+    /// The name of this file.
+    pub name: Option<String>,
+
+    /// The date of last modification of this file.
+    pub date: Option<DateTime>,
+
+    /// The size of this file, in bytes.
+    pub size: Option<u64>,
+
+    /// A description of this file.
+    pub desc: Option<String>,
+
+    /// The hashes of this file.
+    pub hashes: Vec<Hash>,
+
+    /// The width of this image or video, in pixels.
+    pub width: Option<u32>,
+
+    /// The height of this image or video, in pixels.
+    pub height: Option<u32>,
+
+    /// The duration of this audio or video, in milliseconds.
+    pub length: Option<u64>,
+
+    /// Thumbnails of this image or video.
+    pub thumbnails: Vec<Thumbnail>,
+}
+
+impl File {
+    /// Creates an empty file metadata.
+    pub fn new() -> File {
+        File::default()
+    }
+
+    /// Sets the MIME type of this file.
+    pub fn with_media_type<M: Into<String>>(mut self, media_type: M) -> File {
+        self.media_type = Some(media_type.into());
+        self
+    }
+
+    /// Sets the name of this file.
+    pub fn with_name<N: Into<String>>(mut self, name: N) -> File {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Sets the size of this file, in bytes.
+    pub fn with_size(mut self, size: u64) -> File {
+        self.size = Some(size);
+        self
+    }
+
+    /// Sets the dimensions of this image or video, in pixels.
+    pub fn with_dimensions(mut self, width: u32, height: u32) -> File {
+        self.width = Some(width);
+        self.height = Some(height);
+        self
+    }
+
+    /// Add a hash of this file.
+    pub fn add_hash(mut self, hash: Hash) -> File {
+        self.hashes.push(hash);
+        self
+    }
+
+    /// Add a thumbnail of this file.
+    pub fn add_thumbnail(mut self, thumbnail: Thumbnail) -> File {
+        self.thumbnails.push(thumbnail);
+        self
+    }
+}
+
+/// Only the description in the default language is kept, and the range
+/// gets dropped.
+impl From<JingleFile> for File {
+    fn from(file: JingleFile) -> File {
+        let mut descs = file.descs;
+        let desc = descs
+            .remove("")
+            .or_else(|| descs.into_iter().next().map(|(_, desc)| desc))
+            .map(|desc| desc.0);
+        File {
+            media_type: file.media_type,
+            name: file.name,
+            date: file.date,
+            size: file.size,
+            desc,
+            hashes: file.hashes,
+            width: None,
+            height: None,
+            length: None,
+            thumbnails: file.thumbnails,
+        }
+    }
+}
+
+fn set_once<T>(slot: &mut Option<T>, value: T, error: &'static str) -> Result<(), Error> {
+    if slot.is_some() {
+        return Err(Error::ParseError(error));
+    }
+    *slot = Some(value);
+    Ok(())
+}
+
+impl TryFrom<Element> for File {
+    type Error = Error;
+
+    fn try_from(elem: Element) -> Result<File, Error> {
+        check_self!(elem, "file", FILE_METADATA);
+        check_no_attributes!(elem, "file");
+
+        let mut file = File::new();
+        for child in elem.children() {
+            if child.is("media-type", ns::FILE_METADATA) {
+                set_once(
+                    &mut file.media_type,
+                    child.text(),
+                    "File must not have more than one media-type.",
+                )?;
+            } else if child.is("name", ns::FILE_METADATA) {
+                set_once(
+                    &mut file.name,
+                    child.text(),
+                    "File must not have more than one name.",
+                )?;
+            } else if child.is("date", ns::FILE_METADATA) {
+                set_once(
+                    &mut file.date,
+                    child.text().parse()?,
+                    "File must not have more than one date.",
+                )?;
+            } else if child.is("size", ns::FILE_METADATA) {
+                set_once(
+                    &mut file.size,
+                    child.text().parse()?,
+                    "File must not have more than one size.",
+                )?;
+            } else if child.is("desc", ns::FILE_METADATA) {
+                set_once(
+                    &mut file.desc,
+                    child.text(),
+                    "File must not have more than one desc.",
+                )?;
+            } else if child.is("hash", ns::HASHES) {
+                file.hashes.push(Hash::try_from(child.clone())?);
+            } else if child.is("width", ns::FILE_METADATA) {
+                set_once(
+                    &mut file.width,
+                    child.text().parse()?,
+                    "File must not have more than one width.",
+                )?;
+            } else if child.is("height", ns::FILE_METADATA) {
+                set_once(
+                    &mut file.height,
+                    child.text().parse()?,
+                    "File must not have more than one height.",
+                )?;
+            } else if child.is("length", ns::FILE_METADATA) {
+                set_once(
+                    &mut file.length,
+                    child.text().parse()?,
+                    "File must not have more than one length.",
+                )?;
+            } else if child.is("thumbnail", ns::THUMBS) {
+                file.thumbnails.push(Thumbnail::try_from(child.clone())?);
+            } else {
+                return Err(Error::ParseError("Unknown element in file metadata."));
+            }
+        }
+        Ok(file)
+    }
+}
+
+fn text_child<T: ToString>(name: &str, value: Option<T>) -> Option<Element> {
+    value.map(|value| {
+        Element::builder(name, ns::FILE_METADATA)
+            .append(value.to_string())
+            .build()
+    })
+}
+
+impl From<File> for Element {
+    fn from(file: File) -> Element {
+        Element::builder("file", ns::FILE_METADATA)
+            .append_all(text_child("media-type", file.media_type))
+            .append_all(text_child("name", file.name))
+            .append_all(file.date.map(|date| {
+                Element::builder("date", ns::FILE_METADATA)
+                    .append(date)
+                    .build()
+            }))
+            .append_all(text_child("size", file.size))
+            .append_all(text_child("desc", file.desc))
+            .append_all(file.hashes)
+            .append_all(text_child("width", file.width))
+            .append_all(text_child("height", file.height))
+            .append_all(text_child("length", file.length))
+            .append_all(file.thumbnails)
+            .build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hashes::Algo;
+    use crate::jingle_ft::Desc;
+
+    #[cfg(target_pointer_width = "32")]
+    #[test]
+    fn test_size() {
+        assert_size!(File, 116);
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    fn test_size() {
+        assert_size!(File, 184);
+    }
+
+    #[test]
+    fn test_simple() {
+        let elem: Element = "<file xmlns='urn:xmpp:file:metadata:0'><media-type>image/jpeg</media-type><name>summit.jpg</name><size>3032449</size><hash xmlns='urn:xmpp:hashes:2' algo='sha3-256'>2XarmwTlNxDAMkvymloX3S5+VbylNrJt/l5QyPa+YoU=</hash><width>4096</width><height>2160</height><thumbnail xmlns='urn:xmpp:thumbs:1' uri='cid:sha1+ffd7c8d28e9c5e82afea41f97108c6b4@bob.xmpp.org' media-type='image/png' width='128' height='96'/></file>"
+            .parse()
+            .unwrap();
+        let file = File::try_from(elem.clone()).unwrap();
+        assert_eq!(file.media_type.as_deref(), Some("image/jpeg"));
+        assert_eq!(file.name.as_deref(), Some("summit.jpg"));
+        assert_eq!(file.size, Some(3032449));
+        assert_eq!(file.hashes[0].algo, Algo::Sha3_256);
+        assert_eq!((file.width, file.height), (Some(4096), Some(2160)));
+        assert_eq!(file.length, None);
+        assert_eq!(file.thumbnails.len(), 1);
+        assert_eq!(Element::from(file), elem);
+    }
+
+    #[test]
+    fn test_from_jingle() {
+        let jingle = JingleFile::new()
+            .with_name(String::from("test.txt"))
+            .with_size(6144)
+            .add_desc("fr", Desc(String::from("Fichier de test")));
+        let file = File::from(jingle);
+        assert_eq!(file.name.as_deref(), Some("test.txt"));
+        assert_eq!(file.size, Some(6144));
+        assert_eq!(file.desc.as_deref(), Some("Fichier de test"));
+    }
+
+    #[test]
+    fn test_invalid() {
+        let elem: Element =
+            "<file xmlns='urn:xmpp:file:metadata:0'><size>1</size><size>2</size></file>"
+                .parse()
+                .unwrap();
+        let error = File::try_from(elem).unwrap_err();
+        let message = match error {
+            Error::ParseError(string) => string,
+            _ => panic!(),
+        };
+        assert_eq!(message, "File must not have more than one size.");
+
+        let elem: Element = "<file xmlns='urn:xmpp:file:metadata:0'><coucou/></file>"
+            .parse()
+            .unwrap();
+        let error = File::try_from(elem).unwrap_err();
+        let message = match error {
+            Error::ParseError(string) => string,
+            _ => panic!(),
+        };
+        assert_eq!(message, "Unknown element in file metadata.");
+    }
+}
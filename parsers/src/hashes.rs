@@ -6,7 +6,12 @@
 
 use crate::util::error::Error;
 use crate::util::helpers::Base64;
+use blake2::Blake2bVar;
+use digest::{Digest, Update, VariableOutput};
 use minidom::IntoAttributeValue;
+use sha1::Sha1;
+use sha2::{Sha256, Sha512};
+use sha3::{Sha3_256, Sha3_512};
 use std::num::ParseIntError;
 use std::ops::{Deref, DerefMut};
 use std::str::FromStr;
@@ -88,6 +93,32 @@ impl From<Algo> for String {
     }
 }
 
+fn blake2b(data: &[u8], size: usize) -> Vec<u8> {
+    // Safe to unwrap, both sizes we use are supported by BLAKE2b.
+    let mut hasher = Blake2bVar::new(size).unwrap();
+    hasher.update(data);
+    let mut vec = vec![0u8; size];
+    hasher.finalize_variable(&mut vec).unwrap();
+    vec
+}
+
+impl Algo {
+    /// Computes the hash of `data` with this algorithm, or returns `None` if
+    /// it is unknown.
+    pub fn digest(&self, data: &[u8]) -> Option<Vec<u8>> {
+        Some(match self {
+            Algo::Sha_1 => Sha1::digest(data).to_vec(),
+            Algo::Sha_256 => Sha256::digest(data).to_vec(),
+            Algo::Sha_512 => Sha512::digest(data).to_vec(),
+            Algo::Sha3_256 => Sha3_256::digest(data).to_vec(),
+            Algo::Sha3_512 => Sha3_512::digest(data).to_vec(),
+            Algo::Blake2b_256 => blake2b(data, 32),
+            Algo::Blake2b_512 => blake2b(data, 64),
+            Algo::Unknown(_) => return None,
+        })
+    }
+}
+
 impl IntoAttributeValue for Algo {
     fn into_attribute_value(self) -> Option<String> {
         Some(String::from(self))
@@ -114,6 +145,21 @@ impl Hash {
         Hash { algo, hash }
     }
 
+    /// Computes the hash of `data`, for instance a file to transfer or a
+    /// blob of data to reference.
+    pub fn compute(algo: Algo, data: &[u8]) -> Result<Hash, Error> {
+        match algo.digest(data) {
+            Some(hash) => Ok(Hash::new(algo, hash)),
+            None => Err(Error::ParseError("Unknown hash algorithm.")),
+        }
+    }
+
+    /// Checks whether `data` matches this hash, or returns `None` if the
+    /// algorithm is unknown.
+    pub fn verify(&self, data: &[u8]) -> Option<bool> {
+        self.algo.digest(data).map(|hash| hash == self.hash)
+    }
+
     /// Like [new](#method.new) but takes base64-encoded data before decoding
     /// it.
     pub fn from_base64(algo: Algo, hash: &str) -> Result<Hash, Error> {
@@ -246,6 +292,32 @@ mod tests {
         assert_eq!(hash.to_colon_separated_hex(), "d9:76:ab:9b:04:e5:37:10:c0:32:4b:f2:9a:5a:17:dd:2e:7e:55:bc:a5:36:b2:6d:fe:5e:50:c8:f6:be:62:85");
     }
 
+    #[test]
+    fn test_compute() {
+        let hash = Hash::compute(Algo::Sha_256, b"coucou").unwrap();
+        assert_eq!(
+            hash.to_hex(),
+            "110812f67fa1e1f0117f6f3d70241c1a42a7b07711a93c2477cc516d9042f9db"
+        );
+        assert_eq!(hash.verify(b"coucou"), Some(true));
+        assert_eq!(hash.verify(b"hello"), Some(false));
+
+        let hash = Hash::compute(Algo::Blake2b_256, b"").unwrap();
+        assert_eq!(
+            hash.to_hex(),
+            "0e5751c026e543b2e8ab2eb06099daa1d1e5df47778f7787faab45cdf12fe3a8"
+        );
+
+        let algo = Algo::Unknown(String::from("md5"));
+        assert!(algo.digest(b"coucou").is_none());
+        let error = Hash::compute(algo, b"coucou").unwrap_err();
+        let message = match error {
+            Error::ParseError(string) => string,
+            _ => panic!(),
+        };
+        assert_eq!(message, "Unknown hash algorithm.");
+    }
+
     #[test]
     fn test_unknown() {
         let elem: Element = "<replace xmlns='urn:xmpp:message-correct:0'/>"
@@ -0,0 +1,106 @@
+// Copyright (c) 2022 Emmanuel Gil Peyrot <linkmauve@linkmauve.fr>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use crate::message::{Message, MessagePayload};
+use crate::ns;
+
+generate_empty_element!(
+    /// Asks the entities relaying this message not to store it permanently,
+    /// for instance in their archive, but they may still keep it in offline
+    /// storage.
+    NoPermanentStore,
+    "no-permanent-store",
+    HINTS
+);
+
+impl MessagePayload for NoPermanentStore {}
+
+generate_empty_element!(
+    /// Asks the entities relaying this message not to store it at all, be
+    /// it permanently or for offline delivery.
+    NoStore,
+    "no-store",
+    HINTS
+);
+
+impl MessagePayload for NoStore {}
+
+generate_empty_element!(
+    /// Asks the entities relaying this message not to copy it to the other
+    /// resources of the recipient, for instance as carbons.
+    NoCopy,
+    "no-copy",
+    HINTS
+);
+
+impl MessagePayload for NoCopy {}
+
+generate_empty_element!(
+    /// Asks the entities relaying this message to store it, even if it
+    /// wouldn’t otherwise be.
+    Store,
+    "store",
+    HINTS
+);
+
+impl MessagePayload for Store {}
+
+impl Message {
+    /// Whether this message carries a processing hint of this name.
+    pub fn has_hint(&self, name: &str) -> bool {
+        self.payloads
+            .iter()
+            .any(|payload| payload.is(name, ns::HINTS))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(not(feature = "disable-validation"))]
+    use crate::util::error::Error;
+    use crate::Element;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn test_size() {
+        assert_size!(NoPermanentStore, 0);
+        assert_size!(NoStore, 0);
+        assert_size!(NoCopy, 0);
+        assert_size!(Store, 0);
+    }
+
+    #[test]
+    fn test_simple() {
+        let elem: Element = "<message xmlns='jabber:client'><no-store xmlns='urn:xmpp:hints'/><no-copy xmlns='urn:xmpp:hints'/></message>"
+            .parse()
+            .unwrap();
+        let message = Message::try_from(elem).unwrap();
+        NoStore::try_from(message.payloads[0].clone()).unwrap();
+        NoCopy::try_from(message.payloads[1].clone()).unwrap();
+        assert!(message.has_hint("no-copy"));
+        assert!(!message.has_hint("store"));
+
+        let elem: Element = Store.into();
+        assert!(elem.is("store", ns::HINTS));
+        let elem: Element = NoPermanentStore.into();
+        assert!(elem.is("no-permanent-store", ns::HINTS));
+    }
+
+    #[cfg(not(feature = "disable-validation"))]
+    #[test]
+    fn test_invalid_child() {
+        let elem: Element = "<no-store xmlns='urn:xmpp:hints'><coucou/></no-store>"
+            .parse()
+            .unwrap();
+        let error = NoStore::try_from(elem).unwrap_err();
+        let message = match error {
+            Error::ParseError(string) => string,
+            _ => panic!(),
+        };
+        assert_eq!(message, "Unknown child in no-store element.");
+    }
+}
@@ -0,0 +1,113 @@
+// Copyright (c) 2022 Emmanuel Gil Peyrot <linkmauve@linkmauve.fr>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use crate::iq::IqGetPayload;
+use crate::message::MessagePayload;
+
+generate_element!(
+    /// Asks the user to confirm that they made an HTTP request, sent either
+    /// in an iq to a full JID, or in a message to a bare JID.
+    ///
+    /// The user confirms it by replying with an empty iq result, or a
+    /// message containing the same element in the same thread, and denies
+    /// it with a not-authorized error.
+    Confirm, "confirm", HTTP_AUTH,
+    attributes: [
+        /// The identifier of the transaction, as provided by the HTTP client.
+        id: Required<String> = "id",
+
+        /// The method of the HTTP request, for instance `GET` or `POST`.
+        method: Required<String> = "method",
+
+        /// The URL being requested.
+        url: Required<String> = "url",
+    ]
+);
+
+impl IqGetPayload for Confirm {}
+impl MessagePayload for Confirm {}
+
+impl Confirm {
+    /// Creates a confirmation request for this transaction.
+    pub fn new<I, M, U>(id: I, method: M, url: U) -> Confirm
+    where
+        I: Into<String>,
+        M: Into<String>,
+        U: Into<String>,
+    {
+        Confirm {
+            id: id.into(),
+            method: method.into(),
+            url: url.into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::Message;
+    use crate::util::error::Error;
+    use crate::Element;
+    use std::convert::TryFrom;
+
+    #[cfg(target_pointer_width = "32")]
+    #[test]
+    fn test_size() {
+        assert_size!(Confirm, 36);
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    fn test_size() {
+        assert_size!(Confirm, 72);
+    }
+
+    #[test]
+    fn test_simple() {
+        let elem: Element = "<confirm xmlns='http://jabber.org/protocol/http-auth' id='a7374jnjlalasdf82' method='GET' url='https://files.shakespeare.lit:9345/missive.html'/>"
+            .parse()
+            .unwrap();
+        let confirm = Confirm::try_from(elem.clone()).unwrap();
+        assert_eq!(
+            confirm,
+            Confirm::new(
+                "a7374jnjlalasdf82",
+                "GET",
+                "https://files.shakespeare.lit:9345/missive.html"
+            )
+        );
+        assert_eq!(Element::from(confirm), elem);
+    }
+
+    #[test]
+    fn test_message() {
+        let elem: Element = "<message xmlns='jabber:client' from='files.shakespeare.lit' to='juliet@capulet.com' id='x1'><thread>e0ffe42b28561960c6b12b944a092794b9683a38</thread><body>Someone (maybe you) has requested the following file: https://files.shakespeare.lit:9345/missive.html (transaction identifier: a7374jnjlalasdf82)</body><confirm xmlns='http://jabber.org/protocol/http-auth' id='a7374jnjlalasdf82' method='GET' url='https://files.shakespeare.lit:9345/missive.html'/></message>"
+            .parse()
+            .unwrap();
+        let message = Message::try_from(elem).unwrap();
+        let confirm = message
+            .payloads
+            .into_iter()
+            .find_map(|payload| Confirm::try_from(payload).ok())
+            .unwrap();
+        assert_eq!(confirm.id, "a7374jnjlalasdf82");
+    }
+
+    #[test]
+    fn test_invalid() {
+        let elem: Element =
+            "<confirm xmlns='http://jabber.org/protocol/http-auth' id='a7374jnjlalasdf82' method='GET'/>"
+                .parse()
+                .unwrap();
+        let error = Confirm::try_from(elem).unwrap_err();
+        let message = match error {
+            Error::ParseError(string) => string,
+            _ => panic!(),
+        };
+        assert_eq!(message, "Required attribute 'url' missing.");
+    }
+}
@@ -6,8 +6,10 @@
 
 use std::convert::TryFrom;
 
+use crate::date::DateTime;
 use crate::iq::{IqGetPayload, IqResultPayload};
 use crate::ns;
+use crate::stanza_error::StanzaError;
 use crate::util::error::Error;
 use crate::Element;
 
@@ -113,11 +115,112 @@ generate_element!(
 
 impl IqResultPayload for SlotResult {}
 
+/// The file is larger than what the service accepts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileTooLarge {
+    /// The maximal size of a file, in bytes, if the service told it.
+    pub max_file_size: Option<u64>,
+}
+
+impl TryFrom<Element> for FileTooLarge {
+    type Error = Error;
+
+    fn try_from(elem: Element) -> Result<FileTooLarge, Error> {
+        check_self!(elem, "file-too-large", HTTP_UPLOAD);
+        check_no_attributes!(elem, "file-too-large");
+        let mut max_file_size = None;
+        for child in elem.children() {
+            if child.is("max-file-size", ns::HTTP_UPLOAD) {
+                if max_file_size.is_some() {
+                    return Err(Error::ParseError(
+                        "File-too-large must not have more than one max-file-size.",
+                    ));
+                }
+                check_no_children!(child, "max-file-size");
+                check_no_attributes!(child, "max-file-size");
+                max_file_size = Some(child.text().trim().parse()?);
+            } else {
+                return Err(Error::ParseError(
+                    "Unknown child in file-too-large element.",
+                ));
+            }
+        }
+        Ok(FileTooLarge { max_file_size })
+    }
+}
+
+impl From<FileTooLarge> for Element {
+    fn from(file_too_large: FileTooLarge) -> Element {
+        Element::builder("file-too-large", ns::HTTP_UPLOAD)
+            .append_all(file_too_large.max_file_size.map(|size| {
+                Element::builder("max-file-size", ns::HTTP_UPLOAD)
+                    .append(size.to_string())
+                    .build()
+            }))
+            .build()
+    }
+}
+
+generate_element!(
+    /// The quota of the user is reached.
+    Retry, "retry", HTTP_UPLOAD,
+    attributes: [
+        /// When a new slot can be requested.
+        stamp: Required<DateTime> = "stamp",
+    ]
+);
+
+/// The reason why the service refused to give a slot, along the defined
+/// condition of a stanza error.
+#[derive(Debug, Clone, PartialEq)]
+pub enum UploadError {
+    /// The file is too large, it should be made smaller before requesting
+    /// a slot again.
+    FileTooLarge(FileTooLarge),
+
+    /// The quota is reached, a slot can be requested again later.
+    Retry(Retry),
+}
+
+impl UploadError {
+    /// Extracts the upload error carried by this stanza error, if any.
+    pub fn from_stanza_error(error: &StanzaError) -> Option<UploadError> {
+        error
+            .other
+            .clone()
+            .and_then(|elem| UploadError::try_from(elem).ok())
+    }
+}
+
+impl TryFrom<Element> for UploadError {
+    type Error = Error;
+
+    fn try_from(elem: Element) -> Result<UploadError, Error> {
+        Ok(if elem.is("file-too-large", ns::HTTP_UPLOAD) {
+            UploadError::FileTooLarge(FileTooLarge::try_from(elem)?)
+        } else if elem.is("retry", ns::HTTP_UPLOAD) {
+            UploadError::Retry(Retry::try_from(elem)?)
+        } else {
+            return Err(Error::ParseError("This is not an upload error element."));
+        })
+    }
+}
+
+impl From<UploadError> for Element {
+    fn from(error: UploadError) -> Element {
+        match error {
+            UploadError::FileTooLarge(file_too_large) => file_too_large.into(),
+            UploadError::Retry(retry) => retry.into(),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::Element;
     use std::convert::TryFrom;
+    use std::str::FromStr;
 
     #[test]
     fn test_slot_request() {
@@ -183,4 +286,54 @@ mod tests {
             .unwrap();
         SlotResult::try_from(elem).unwrap_err();
     }
+
+    #[test]
+    fn test_file_too_large() {
+        let elem: Element = "<error xmlns='jabber:client' type='modify'>
+            <not-acceptable xmlns='urn:ietf:params:xml:ns:xmpp-stanzas'/>
+            <text xmlns='urn:ietf:params:xml:ns:xmpp-stanzas'>File too large. The maximum file size is 20000 bytes</text>
+            <file-too-large xmlns='urn:xmpp:http:upload:0'>
+              <max-file-size>20000</max-file-size>
+            </file-too-large>
+          </error>"
+            .parse()
+            .unwrap();
+        let error = StanzaError::try_from(elem).unwrap();
+        assert_eq!(
+            UploadError::from_stanza_error(&error),
+            Some(UploadError::FileTooLarge(FileTooLarge {
+                max_file_size: Some(20000)
+            }))
+        );
+    }
+
+    #[test]
+    fn test_retry() {
+        let elem: Element = "<error xmlns='jabber:client' type='wait'>
+            <resource-constraint xmlns='urn:ietf:params:xml:ns:xmpp-stanzas'/>
+            <text xmlns='urn:ietf:params:xml:ns:xmpp-stanzas'>Quota reached. You can only upload 5 files in 5 minutes</text>
+            <retry xmlns='urn:xmpp:http:upload:0' stamp='2017-12-03T23:42:05Z'/>
+          </error>"
+            .parse()
+            .unwrap();
+        let error = StanzaError::try_from(elem).unwrap();
+        let retry = match UploadError::from_stanza_error(&error) {
+            Some(UploadError::Retry(retry)) => retry,
+            _ => panic!(),
+        };
+        assert_eq!(
+            retry.stamp,
+            DateTime::from_str("2017-12-03T23:42:05Z").unwrap()
+        );
+        let elem: Element = UploadError::Retry(retry).into();
+        assert!(elem.is("retry", ns::HTTP_UPLOAD));
+
+        let error = StanzaError::new(
+            crate::stanza_error::ErrorType::Cancel,
+            crate::stanza_error::DefinedCondition::NotAllowed,
+            "en",
+            "Nope",
+        );
+        assert_eq!(UploadError::from_stanza_error(&error), None);
+    }
 }
@@ -5,6 +5,7 @@
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
 use crate::iq::IqSetPayload;
+use crate::message::MessagePayload;
 use crate::util::helpers::Base64;
 
 generate_id!(
@@ -41,6 +42,24 @@ attributes: [
 
 impl IqSetPayload for Open {}
 
+impl Open {
+    /// Create a new stream, exchanging chunks of at most `block_size` bytes
+    /// in iqs.
+    pub fn new(sid: StreamId, block_size: u16) -> Open {
+        Open {
+            block_size,
+            sid,
+            stanza: Stanza::Iq,
+        }
+    }
+
+    /// Exchange the chunks with this stanza type instead.
+    pub fn with_stanza(mut self, stanza: Stanza) -> Open {
+        self.stanza = stanza;
+        self
+    }
+}
+
 generate_element!(
 /// Exchange a chunk of data in an open stream.
 Data, "data", IBB,
@@ -58,6 +77,14 @@ Data, "data", IBB,
 );
 
 impl IqSetPayload for Data {}
+impl MessagePayload for Data {}
+
+impl Data {
+    /// Create a new chunk of data with this sequence number.
+    pub fn new(sid: StreamId, seq: u16, data: Vec<u8>) -> Data {
+        Data { seq, sid, data }
+    }
+}
 
 generate_element!(
 /// Close an open stream.
@@ -69,6 +96,13 @@ attributes: [
 
 impl IqSetPayload for Close {}
 
+impl Close {
+    /// Close this stream.
+    pub fn new(sid: StreamId) -> Close {
+        Close { sid }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -168,4 +202,26 @@ mod tests {
         };
         assert_eq!(message, "Unknown value for 'stanza' attribute.");
     }
+
+    #[test]
+    fn test_serialise() {
+        let sid = StreamId(String::from("coucou"));
+        let elem: Element = "<open xmlns='http://jabber.org/protocol/ibb' block-size='4096' sid='coucou' stanza='message'/>".parse().unwrap();
+        let open = Open::new(sid.clone(), 4096).with_stanza(Stanza::Message);
+        assert_eq!(Element::from(open), elem);
+
+        let elem: Element =
+            "<data xmlns='http://jabber.org/protocol/ibb' seq='1' sid='coucou'>AAAA</data>"
+                .parse()
+                .unwrap();
+        assert_eq!(
+            Element::from(Data::new(sid.clone(), 1, vec![0, 0, 0])),
+            elem
+        );
+
+        let elem: Element = "<close xmlns='http://jabber.org/protocol/ibb' sid='coucou'/>"
+            .parse()
+            .unwrap();
+        assert_eq!(Element::from(Close::new(sid)), elem);
+    }
 }
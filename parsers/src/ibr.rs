@@ -13,7 +13,7 @@ use std::collections::HashMap;
 use std::convert::TryFrom;
 
 /// Query for registering against a service.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct Query {
     /// Deprecated fixed list of possible fields to fill before the user can
     /// register.
@@ -31,6 +31,72 @@ pub struct Query {
     //pub oob: Option<Oob>,
 }
 
+impl Query {
+    /// Create an empty query, as sent in a get to discover which fields
+    /// the service requires.
+    pub fn new() -> Query {
+        Query::default()
+    }
+
+    /// Create a query registering a new account with the legacy fields.
+    pub fn register<U: Into<String>, P: Into<String>>(username: U, password: P) -> Query {
+        Query::new()
+            .with_field("username", username)
+            .with_field("password", password)
+    }
+
+    /// Create a query changing the password of an already registered
+    /// account.
+    pub fn change_password<U: Into<String>, P: Into<String>>(username: U, password: P) -> Query {
+        Query::register(username, password)
+    }
+
+    /// Create a query cancelling the registration, deleting the account.
+    pub fn remove() -> Query {
+        Query {
+            remove: true,
+            ..Query::default()
+        }
+    }
+
+    /// Create a query submitting a filled data form.
+    pub fn submit(form: DataForm) -> Query {
+        Query {
+            form: Some(form),
+            ..Query::default()
+        }
+    }
+
+    /// Set a legacy field of this query.
+    pub fn with_field<N: Into<String>, V: Into<String>>(mut self, name: N, value: V) -> Query {
+        self.fields.insert(name.into(), value.into());
+        self
+    }
+
+    /// The instructions to display to the user, if any.
+    pub fn instructions(&self) -> Option<&str> {
+        self.fields.get("instructions").map(String::as_str)
+    }
+
+    /// The username of the account, if any.
+    pub fn username(&self) -> Option<&str> {
+        self.fields.get("username").map(String::as_str)
+    }
+
+    /// The names of the legacy fields the user has to fill, in
+    /// alphabetical order.
+    pub fn required_fields(&self) -> Vec<&str> {
+        let mut fields: Vec<&str> = self
+            .fields
+            .keys()
+            .map(String::as_str)
+            .filter(|name| *name != "instructions")
+            .collect();
+        fields.sort_unstable();
+        fields
+    }
+}
+
 impl IqGetPayload for Query {}
 impl IqSetPayload for Query {}
 impl IqResultPayload for Query {}
@@ -191,4 +257,33 @@ mod tests {
         let elem2 = query.into();
         assert_eq!(elem1, elem2);
     }
+
+    #[test]
+    fn test_requests() {
+        let elem: Element = "<query xmlns='jabber:iq:register'><instructions>Choose a username and password.</instructions><username/><password/></query>"
+            .parse()
+            .unwrap();
+        let query = Query::try_from(elem).unwrap();
+        assert_eq!(
+            query.instructions(),
+            Some("Choose a username and password.")
+        );
+        assert_eq!(query.required_fields(), ["password", "username"]);
+
+        let query = Query::register("bill", "Calliope");
+        assert_eq!(query.username(), Some("bill"));
+        assert_eq!(query.fields["password"], "Calliope");
+        assert!(!query.remove);
+
+        let elem: Element = Query::remove().into();
+        let expected: Element = "<query xmlns='jabber:iq:register'><remove/></query>"
+            .parse()
+            .unwrap();
+        assert_eq!(elem, expected);
+
+        let elem: Element = Query::change_password("bill", "newpass").into();
+        let query = Query::try_from(elem).unwrap();
+        assert_eq!(query.username(), Some("bill"));
+        assert_eq!(query.fields["password"], "newpass");
+    }
 }
@@ -6,6 +6,7 @@
 
 use crate::date::DateTime;
 use crate::presence::PresencePayload;
+use std::time::Duration;
 
 generate_element!(
     /// Represents the last time the user interacted with their system.
@@ -18,6 +19,20 @@ generate_element!(
 
 impl PresencePayload for Idle {}
 
+impl Idle {
+    /// Create a new idle indication, for a user who stopped interacting at
+    /// `since`.
+    pub fn new(since: DateTime) -> Idle {
+        Idle { since }
+    }
+
+    /// For how long the user has been idle at `now`, or `None` if `since`
+    /// is in the future.
+    pub fn duration_at(&self, now: &DateTime) -> Option<Duration> {
+        now.0.signed_duration_since(self.since.0).to_std().ok()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -39,6 +54,15 @@ mod tests {
         Idle::try_from(elem).unwrap();
     }
 
+    #[test]
+    fn test_duration() {
+        let since = DateTime::from_str("2017-05-21T20:19:55+01:00").unwrap();
+        let idle = Idle::new(since.clone());
+        let now = DateTime::from_str("2017-05-21T19:34:55Z").unwrap();
+        assert_eq!(idle.duration_at(&now), Some(Duration::from_secs(900)));
+        assert_eq!(Idle::new(now).duration_at(&since), None);
+    }
+
     #[test]
     fn test_invalid_child() {
         let elem: Element = "<idle xmlns='urn:xmpp:idle:1'><coucou/></idle>"
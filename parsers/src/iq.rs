@@ -120,6 +120,46 @@ impl Iq {
         }
     }
 
+    /// Creates the error reply to the `iq` request, sent back to its
+    /// emitter with the same id, including the original payload as RFC 6120
+    /// allows.
+    ///
+    /// Since an `Iq` of type error can’t carry the original payload, the
+    /// reply is returned as an `Element`.
+    pub fn error_reply(iq: &Iq, error: StanzaError) -> Element {
+        let original = match iq.payload {
+            IqType::Get(ref payload) | IqType::Set(ref payload) => Some(payload.clone()),
+            IqType::Result(_) | IqType::Error(_) => None,
+        };
+        let mut reply = Element::from(Iq {
+            from: iq.to.clone(),
+            to: iq.from.clone(),
+            id: iq.id.clone(),
+            payload: IqType::Error(error),
+        });
+        if let Some(original) = original {
+            let error = reply.remove_child("error", ns::DEFAULT_NS).unwrap();
+            reply.append_child(original);
+            reply.append_child(error);
+        }
+        reply
+    }
+
+    /// Parses the payload of this request or result as a `P`, for
+    /// instance `iq.get_payload::<DiscoInfoQuery>()`.
+    ///
+    /// Fails if this iq is an error, an empty result, or if its payload
+    /// isn’t a valid `P`.
+    pub fn get_payload<P: TryFrom<Element, Error = Error>>(&self) -> Result<P, Error> {
+        match self.payload {
+            IqType::Get(ref payload)
+            | IqType::Set(ref payload)
+            | IqType::Result(Some(ref payload)) => P::try_from(payload.clone()),
+            IqType::Result(None) => Err(Error::ParseError("Iq result has no payload.")),
+            IqType::Error(_) => Err(Error::ParseError("Iq of type error has no payload.")),
+        }
+    }
+
     /// Sets the recipient of this stanza.
     pub fn with_to(mut self, to: Jid) -> Iq {
         self.to = Some(to);
@@ -227,7 +267,8 @@ impl From<Iq> for Element {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::disco::DiscoInfoQuery;
+    use crate::disco::{DiscoInfoQuery, DiscoInfoResult};
+    use crate::ping::Ping;
     use crate::stanza_error::{DefinedCondition, ErrorType};
 
     #[cfg(target_pointer_width = "32")]
@@ -407,6 +448,38 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_error_reply() {
+        #[cfg(not(feature = "component"))]
+        let elem: Element = "<iq xmlns='jabber:client' from='juliet@capulet.com/balcony' to='capulet.com' type='get' id='ping1'><ping xmlns='urn:xmpp:ping'/></iq>"
+            .parse()
+            .unwrap();
+        #[cfg(feature = "component")]
+        let elem: Element = "<iq xmlns='jabber:component:accept' from='juliet@capulet.com/balcony' to='capulet.com' type='get' id='ping1'><ping xmlns='urn:xmpp:ping'/></iq>"
+            .parse()
+            .unwrap();
+        let iq = Iq::try_from(elem).unwrap();
+        let reply = Iq::error_reply(&iq, StanzaError::service_unavailable(""));
+        assert_eq!(reply.attr("from"), Some("capulet.com"));
+        assert_eq!(reply.attr("to"), Some("juliet@capulet.com/balcony"));
+        assert_eq!(reply.attr("id"), Some("ping1"));
+        assert_eq!(reply.attr("type"), Some("error"));
+        let children: Vec<_> = reply.children().map(Element::name).collect();
+        assert_eq!(children, ["ping", "error"]);
+
+        let reply = Iq::try_from(reply).unwrap();
+        match reply.payload {
+            IqType::Error(error) => {
+                assert_eq!(error.type_, ErrorType::Cancel);
+                assert_eq!(
+                    error.defined_condition,
+                    DefinedCondition::ServiceUnavailable
+                );
+            }
+            _ => panic!(),
+        }
+    }
+
     #[test]
     fn test_children_invalid() {
         #[cfg(not(feature = "component"))]
@@ -458,4 +531,22 @@ mod tests {
         };
         assert!(disco_info.node.is_none());
     }
+
+    #[test]
+    fn test_get_payload() {
+        let iq = Iq::from_get("disco", DiscoInfoQuery { node: None });
+        let disco_info = iq.get_payload::<DiscoInfoQuery>().unwrap();
+        assert!(disco_info.node.is_none());
+
+        // The payload of another type doesn’t parse.
+        let iq = Iq::from_get("ping", Ping);
+        assert!(iq.get_payload::<DiscoInfoQuery>().is_err());
+        assert!(iq.get_payload::<Ping>().is_ok());
+
+        let iq = Iq::from_result("disco", None::<DiscoInfoResult>);
+        match iq.get_payload::<DiscoInfoQuery>() {
+            Err(Error::ParseError(message)) => assert_eq!(message, "Iq result has no payload."),
+            _ => panic!(),
+        }
+    }
 }
@@ -5,11 +5,13 @@
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
 use crate::iq::IqSetPayload;
+use crate::jingle_ft::Description as FileTransferDescription;
 use crate::jingle_grouping::Group;
 use crate::jingle_ibb::Transport as IbbTransport;
 use crate::jingle_ice_udp::Transport as IceUdpTransport;
 use crate::jingle_rtp::Description as RtpDescription;
 use crate::jingle_s5b::Transport as Socks5Transport;
+use crate::lang;
 use crate::ns;
 use crate::util::error::Error;
 use crate::Element;
@@ -176,6 +178,9 @@ pub enum Description {
     /// Jingle RTP Sessions (XEP-0167) description.
     Rtp(RtpDescription),
 
+    /// Jingle File Transfer (XEP-0234) description.
+    FileTransfer(FileTransferDescription),
+
     /// To be used for any description that isn’t known at compile-time.
     Unknown(Element),
 }
@@ -186,6 +191,8 @@ impl TryFrom<Element> for Description {
     fn try_from(elem: Element) -> Result<Description, Error> {
         Ok(if elem.is("description", ns::JINGLE_RTP) {
             Description::Rtp(RtpDescription::try_from(elem)?)
+        } else if elem.is("description", ns::JINGLE_FT) {
+            Description::FileTransfer(FileTransferDescription::try_from(elem)?)
         } else {
             Description::Unknown(elem)
         })
@@ -198,10 +205,17 @@ impl From<RtpDescription> for Description {
     }
 }
 
+impl From<FileTransferDescription> for Description {
+    fn from(desc: FileTransferDescription) -> Description {
+        Description::FileTransfer(desc)
+    }
+}
+
 impl From<Description> for Element {
     fn from(desc: Description) -> Element {
         match desc {
             Description::Rtp(desc) => desc.into(),
+            Description::FileTransfer(desc) => desc.into(),
             Description::Unknown(elem) => elem,
         }
     }
@@ -349,7 +363,7 @@ pub enum Reason {
     /// initiate a new session; the Jingle session ID of the alternative
     /// session SHOULD be provided as the XML character data of the <sid/>
     /// child.
-    AlternativeSession, //(String),
+    AlternativeSession(Option<SessionId>),
 
     /// The party is busy and cannot accept a session.
     Busy,
@@ -411,7 +425,7 @@ impl FromStr for Reason {
 
     fn from_str(s: &str) -> Result<Reason, Error> {
         Ok(match s {
-            "alternative-session" => Reason::AlternativeSession,
+            "alternative-session" => Reason::AlternativeSession(None),
             "busy" => Reason::Busy,
             "cancel" => Reason::Cancel,
             "connectivity-error" => Reason::ConnectivityError,
@@ -436,9 +450,13 @@ impl FromStr for Reason {
 
 impl From<Reason> for Element {
     fn from(reason: Reason) -> Element {
+        let sid = match reason {
+            Reason::AlternativeSession(ref sid) => sid.clone(),
+            _ => None,
+        };
         Element::builder(
             match reason {
-                Reason::AlternativeSession => "alternative-session",
+                Reason::AlternativeSession(_) => "alternative-session",
                 Reason::Busy => "busy",
                 Reason::Cancel => "cancel",
                 Reason::ConnectivityError => "connectivity-error",
@@ -458,6 +476,7 @@ impl From<Reason> for Element {
             },
             ns::JINGLE,
         )
+        .append_all(sid.map(|sid| Element::builder("sid", ns::JINGLE).append(sid.0)))
         .build()
     }
 }
@@ -474,6 +493,28 @@ pub struct ReasonElement {
     pub texts: BTreeMap<Lang, String>,
 }
 
+impl ReasonElement {
+    /// Create a new reason, without any text.
+    pub fn new(reason: Reason) -> ReasonElement {
+        ReasonElement {
+            reason,
+            texts: BTreeMap::new(),
+        }
+    }
+
+    /// Returns the best matching text from a list of languages, see
+    /// [lang::get_best()](../lang/fn.get_best.html).
+    pub fn get_best_text(&self, preferred_langs: Vec<&str>) -> Option<(Lang, &String)> {
+        lang::get_best(&self.texts, &preferred_langs)
+    }
+
+    /// Add a human-readable description in this language.
+    pub fn with_text<L: Into<Lang>, T: Into<String>>(mut self, lang: L, text: T) -> ReasonElement {
+        self.texts.insert(lang.into(), text.into());
+        self
+    }
+}
+
 impl fmt::Display for ReasonElement {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         write!(fmt, "{}", Element::from(self.reason.clone()).name())?;
@@ -498,12 +539,31 @@ impl TryFrom<Element> for ReasonElement {
             if child.is("text", ns::JINGLE) {
                 check_no_children!(child, "text");
                 check_no_unknown_attributes!(child, "text", ["xml:lang"]);
-                let lang = get_attr!(elem, "xml:lang", Default);
+                let lang = get_attr!(child, "xml:lang", Default);
                 if texts.insert(lang, child.text()).is_some() {
                     return Err(Error::ParseError(
                         "Text element present twice for the same xml:lang.",
                     ));
                 }
+            } else if child.is("alternative-session", ns::JINGLE) {
+                if reason.is_some() {
+                    return Err(Error::ParseError(
+                        "Reason must not have more than one reason.",
+                    ));
+                }
+                check_no_attributes!(child, "reason");
+                let mut sid = None;
+                for sid_elem in child.children() {
+                    if !sid_elem.is("sid", ns::JINGLE) || sid.is_some() {
+                        return Err(Error::ParseError(
+                            "Alternative session must only contain one sid.",
+                        ));
+                    }
+                    check_no_children!(sid_elem, "sid");
+                    check_no_attributes!(sid_elem, "sid");
+                    sid = Some(SessionId(sid_elem.text()));
+                }
+                reason = Some(Reason::AlternativeSession(sid));
             } else if child.has_ns(ns::JINGLE) {
                 if reason.is_some() {
                     return Err(Error::ParseError(
@@ -673,6 +733,7 @@ impl From<Jingle> for Element {
             .append_all(jingle.contents)
             .append_all(jingle.reason.map(Element::from))
             .append_all(jingle.group.map(Element::from))
+            .append_all(jingle.other)
             .build()
     }
 }
@@ -690,8 +751,8 @@ mod tests {
         assert_size!(Disposition, 1);
         assert_size!(ContentId, 12);
         assert_size!(Content, 228);
-        assert_size!(Reason, 1);
-        assert_size!(ReasonElement, 16);
+        assert_size!(Reason, 12);
+        assert_size!(ReasonElement, 24);
         assert_size!(SessionId, 12);
         assert_size!(Jingle, 152);
     }
@@ -705,8 +766,8 @@ mod tests {
         assert_size!(Disposition, 1);
         assert_size!(ContentId, 24);
         assert_size!(Content, 456);
-        assert_size!(Reason, 1);
-        assert_size!(ReasonElement, 32);
+        assert_size!(Reason, 24);
+        assert_size!(ReasonElement, 48);
         assert_size!(SessionId, 24);
         assert_size!(Jingle, 304);
     }
@@ -829,6 +890,54 @@ mod tests {
         assert_eq!(reason.texts.get(""), Some(&String::from("coucou")));
     }
 
+    #[test]
+    fn test_reason_texts() {
+        let elem: Element = "<reason xmlns='urn:xmpp:jingle:1'><decline/><text xml:lang='en'>No thanks.</text><text xml:lang='fr'>Non merci.</text></reason>".parse().unwrap();
+        let reason = ReasonElement::try_from(elem.clone()).unwrap();
+        assert_eq!(reason.reason, Reason::Decline);
+        assert_eq!(reason.texts.len(), 2);
+        assert_eq!(reason.to_string(), "decline: No thanks.");
+
+        let built = ReasonElement::new(Reason::Decline)
+            .with_text("en", "No thanks.")
+            .with_text("fr", "Non merci.");
+        assert_eq!(built, reason);
+        assert_eq!(Element::from(built), elem);
+    }
+
+    #[test]
+    fn test_alternative_session() {
+        let elem: Element = "<reason xmlns='urn:xmpp:jingle:1'><alternative-session><sid>b84tkkwlmb48kgfb</sid></alternative-session></reason>".parse().unwrap();
+        let reason = ReasonElement::try_from(elem.clone()).unwrap();
+        assert_eq!(
+            reason.reason,
+            Reason::AlternativeSession(Some(SessionId(String::from("b84tkkwlmb48kgfb"))))
+        );
+        assert_eq!(Element::from(reason), elem);
+
+        let elem: Element = "<reason xmlns='urn:xmpp:jingle:1'><alternative-session><foo/></alternative-session></reason>".parse().unwrap();
+        let error = ReasonElement::try_from(elem).unwrap_err();
+        let message = match error {
+            Error::ParseError(string) => string,
+            _ => panic!(),
+        };
+        assert_eq!(message, "Alternative session must only contain one sid.");
+    }
+
+    #[test]
+    fn test_file_transfer_and_payloads() {
+        let elem: Element = "<jingle xmlns='urn:xmpp:jingle:1' action='session-initiate' sid='coucou'><content creator='initiator' name='a-file-offer'><description xmlns='urn:xmpp:jingle:apps:file-transfer:5'><file><name>test.txt</name><size>6144</size></file></description><transport xmlns='urn:xmpp:jingle:transports:ibb:1' block-size='4096' sid='ch3d9s71'/></content><custom xmlns='urn:example'/></jingle>".parse().unwrap();
+        let jingle = Jingle::try_from(elem.clone()).unwrap();
+        match jingle.contents[0].description {
+            Some(Description::FileTransfer(ref desc)) => {
+                assert_eq!(desc.file.name.as_deref(), Some("test.txt"))
+            }
+            _ => panic!(),
+        }
+        assert_eq!(jingle.other.len(), 1);
+        assert_eq!(Element::from(jingle), elem);
+    }
+
     #[test]
     fn test_invalid_reason() {
         let elem: Element = "<jingle xmlns='urn:xmpp:jingle:1' action='session-initiate' sid='coucou'><reason/></jingle>".parse().unwrap();
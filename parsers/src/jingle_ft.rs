@@ -8,6 +8,7 @@ use crate::date::DateTime;
 use crate::hashes::Hash;
 use crate::jingle::{ContentId, Creator};
 use crate::ns;
+use crate::thumbs::Thumbnail;
 use crate::util::error::Error;
 use minidom::{Element, Node};
 use std::collections::BTreeMap;
@@ -47,7 +48,7 @@ generate_id!(
 );
 
 /// Represents a file to be transferred.
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct File {
     /// The date of last modification of this file.
     pub date: Option<DateTime>,
@@ -69,6 +70,9 @@ pub struct File {
 
     /// A list of hashes matching this entire file.
     pub hashes: Vec<Hash>,
+
+    /// Thumbnails of this file (XEP-0264), if it is an image or a video.
+    pub thumbnails: Vec<Thumbnail>,
 }
 
 impl File {
@@ -125,6 +129,12 @@ impl File {
         self.hashes.push(hash);
         self
     }
+
+    /// Add a thumbnail of this file.
+    pub fn add_thumbnail(mut self, thumbnail: Thumbnail) -> File {
+        self.thumbnails.push(thumbnail);
+        self
+    }
 }
 
 impl TryFrom<Element> for File {
@@ -142,6 +152,7 @@ impl TryFrom<Element> for File {
             size: None,
             range: None,
             hashes: vec![],
+            thumbnails: vec![],
         };
 
         for child in elem.children() {
@@ -182,6 +193,8 @@ impl TryFrom<Element> for File {
                 file.range = Some(Range::try_from(child.clone())?);
             } else if child.is("hash", ns::HASHES) {
                 file.hashes.push(Hash::try_from(child.clone())?);
+            } else if child.is("thumbnail", ns::THUMBS) {
+                file.thumbnails.push(Thumbnail::try_from(child.clone())?);
             } else {
                 return Err(Error::ParseError("Unknown element in JingleFT file."));
             }
@@ -219,12 +232,13 @@ impl From<File> for Element {
             )
             .append_all(file.range)
             .append_all(file.hashes)
+            .append_all(file.thumbnails)
             .build()
     }
 }
 
 /// A wrapper element for a file.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Description {
     /// The actual file descriptor.
     pub file: File,
@@ -339,9 +353,9 @@ mod tests {
     #[ignore]
     fn test_size() {
         assert_size!(Range, 32);
-        assert_size!(File, 112);
-        assert_size!(Description, 112);
-        assert_size!(Checksum, 128);
+        assert_size!(File, 124);
+        assert_size!(Description, 124);
+        assert_size!(Checksum, 140);
         assert_size!(Received, 16);
     }
 
@@ -349,9 +363,9 @@ mod tests {
     #[test]
     fn test_size() {
         assert_size!(Range, 48);
-        assert_size!(File, 184);
-        assert_size!(Description, 184);
-        assert_size!(Checksum, 216);
+        assert_size!(File, 208);
+        assert_size!(Description, 208);
+        assert_size!(Checksum, 240);
         assert_size!(Received, 32);
     }
 
@@ -365,6 +379,7 @@ mod tests {
     <size>6144</size>
     <hash xmlns='urn:xmpp:hashes:2'
           algo='sha-1'>w0mcJylzCn+AfvuGdqkty2+KP48=</hash>
+    <thumbnail xmlns='urn:xmpp:thumbs:1' uri='cid:sha1+ffd7c8d28e9c5e82afea41f97108c6b4@bob.xmpp.org' media-type='image/png' width='128' height='96'/>
   </file>
 </description>
 "#
@@ -385,6 +400,11 @@ mod tests {
             desc.file.hashes[0].hash,
             base64::decode("w0mcJylzCn+AfvuGdqkty2+KP48=").unwrap()
         );
+        assert_eq!(desc.file.thumbnails[0].width, Some(128));
+        assert_eq!(
+            desc.file.thumbnails[0].media_type,
+            Some(String::from("image/png"))
+        );
     }
 
     #[test]
@@ -4,7 +4,7 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
-use crate::ibb::{Stanza, StreamId};
+use crate::ibb::{Open, Stanza, StreamId};
 
 generate_element!(
 /// Describes an [In-Band Bytestream](https://xmpp.org/extensions/xep-0047.html)
@@ -21,6 +21,37 @@ attributes: [
     stanza: Default<Stanza> = "stanza",
 ]);
 
+impl Transport {
+    /// Create a new transport, exchanging chunks of at most `block_size`
+    /// bytes in iqs.
+    pub fn new(sid: StreamId, block_size: u16) -> Transport {
+        Transport {
+            block_size,
+            sid,
+            stanza: Stanza::Iq,
+        }
+    }
+
+    /// Exchange the chunks with this stanza type instead.
+    pub fn with_stanza(mut self, stanza: Stanza) -> Transport {
+        self.stanza = stanza;
+        self
+    }
+}
+
+/// The transport accepted, opening the stream it describes.
+impl From<Transport> for Open {
+    fn from(transport: Transport) -> Open {
+        Open::new(transport.sid, transport.block_size).with_stanza(transport.stanza)
+    }
+}
+
+impl From<Open> for Transport {
+    fn from(open: Open) -> Transport {
+        Transport::new(open.sid, open.block_size).with_stanza(open.stanza)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -52,6 +83,15 @@ mod tests {
         assert_eq!(transport.stanza, Stanza::Iq);
     }
 
+    #[test]
+    fn test_open() {
+        let sid = StreamId(String::from("coucou"));
+        let transport = Transport::new(sid.clone(), 4096).with_stanza(Stanza::Message);
+        let open = Open::from(transport.clone());
+        assert_eq!(open, Open::new(sid, 4096).with_stanza(Stanza::Message));
+        assert_eq!(Transport::from(open), transport);
+    }
+
     #[test]
     fn test_invalid() {
         let elem: Element = "<transport xmlns='urn:xmpp:jingle:transports:ibb:1'/>"
@@ -4,6 +4,7 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
+use crate::bytestreams::{Query, QueryPayload, StreamHost};
 use crate::ns;
 use crate::util::error::Error;
 use crate::Element;
@@ -99,6 +100,37 @@ impl Candidate {
         self.type_ = type_;
         self
     }
+
+    /// Creates a new candidate from a streamhost, for instance the one
+    /// returned by a proxy, which must have an IP address as its host.
+    pub fn from_stream_host(
+        cid: CandidateId,
+        streamhost: StreamHost,
+        priority: u32,
+    ) -> Result<Candidate, Error> {
+        let host = streamhost
+            .host
+            .parse()
+            .map_err(|_| Error::ParseError("Streamhost without an IP address."))?;
+        Ok(Candidate {
+            cid,
+            host,
+            jid: streamhost.jid,
+            port: streamhost.port,
+            priority,
+            type_: Type::Direct,
+        })
+    }
+}
+
+impl From<Candidate> for StreamHost {
+    fn from(candidate: Candidate) -> StreamHost {
+        StreamHost {
+            jid: candidate.jid,
+            host: candidate.host.to_string(),
+            port: candidate.port,
+        }
+    }
 }
 
 /// The payload of a transport.
@@ -171,6 +203,25 @@ impl Transport {
     }
 }
 
+/// Only the candidates have an equivalent in a standalone bytestream
+/// (XEP-0065), the other payloads get converted to an empty query.
+impl From<Transport> for Query {
+    fn from(transport: Transport) -> Query {
+        let payload = match transport.payload {
+            TransportPayload::Candidates(candidates) => {
+                QueryPayload::StreamHosts(candidates.into_iter().map(StreamHost::from).collect())
+            }
+            _ => QueryPayload::None,
+        };
+        Query {
+            sid: Some(transport.sid),
+            dstaddr: transport.dstaddr,
+            mode: transport.mode,
+            payload,
+        }
+    }
+}
+
 impl TryFrom<Element> for Transport {
     type Error = Error;
 
@@ -350,4 +401,41 @@ mod tests {
         let elem2: Element = transport.into();
         assert_eq!(elem, elem2);
     }
+
+    #[test]
+    fn test_bytestreams() {
+        let jid = Jid::Bare(BareJid::new("coucou", "coucou"));
+        let candidate = Candidate::new(
+            CandidateId(String::from("coucou")),
+            IpAddr::from_str("127.0.0.1").unwrap(),
+            jid.clone(),
+            42,
+        )
+        .with_port(1234);
+        let transport = Transport::new(StreamId(String::from("coucou")))
+            .with_payload(TransportPayload::Candidates(vec![candidate.clone()]));
+        let query = Query::from(transport);
+        assert_eq!(query.sid, Some(StreamId(String::from("coucou"))));
+        let streamhost = match query.payload {
+            QueryPayload::StreamHosts(mut streamhosts) => streamhosts.pop().unwrap(),
+            _ => panic!(),
+        };
+        assert_eq!(
+            streamhost,
+            StreamHost::new(jid.clone(), "127.0.0.1").with_port(1234)
+        );
+        let candidate2 =
+            Candidate::from_stream_host(CandidateId(String::from("coucou")), streamhost, 42)
+                .unwrap();
+        assert_eq!(candidate2, candidate);
+
+        let proxy = StreamHost::new(jid, "proxy.coucou");
+        let error = Candidate::from_stream_host(CandidateId(String::from("coucou")), proxy, 42)
+            .unwrap_err();
+        let message = match error {
+            Error::ParseError(string) => string,
+            _ => panic!(),
+        };
+        assert_eq!(message, "Streamhost without an IP address.");
+    }
 }
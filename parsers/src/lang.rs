@@ -0,0 +1,68 @@
+// Copyright (c) 2022 Emmanuel Gil Peyrot <linkmauve@linkmauve.fr>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::collections::BTreeMap;
+
+/// The value of an xml:lang attribute, empty when absent.
+pub type Lang = String;
+
+/// Returns the best matching value of `map` from a list of languages.
+///
+/// Each preferred language is tried in order, first as is and then with
+/// its subtags removed one by one, so that `"en-GB"` can match a text in
+/// `"en"`.  Failing that, the value without an xml:lang is returned, or
+/// else an undefined one.
+pub fn get_best<'a, T>(
+    map: &'a BTreeMap<Lang, T>,
+    preferred_langs: &[&str],
+) -> Option<(Lang, &'a T)> {
+    if map.is_empty() {
+        return None;
+    }
+    for lang in preferred_langs {
+        let mut lang = *lang;
+        loop {
+            if let Some(value) = map.get(lang) {
+                return Some((Lang::from(lang), value));
+            }
+            match lang.rfind('-') {
+                Some(index) => lang = &lang[..index],
+                None => break,
+            }
+        }
+    }
+    if let Some(value) = map.get("") {
+        return Some((Lang::new(), value));
+    }
+    map.iter().map(|(lang, value)| (lang.clone(), value)).next()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_best() {
+        let mut map = BTreeMap::new();
+        assert_eq!(get_best::<&str>(&map, &["en"]), None);
+
+        map.insert(Lang::from("de"), "Hallo");
+        map.insert(Lang::from("fr"), "Salut");
+        assert_eq!(
+            get_best(&map, &["fr", "de"]),
+            Some((Lang::from("fr"), &"Salut"))
+        );
+        assert_eq!(
+            get_best(&map, &["fr-CA"]),
+            Some((Lang::from("fr"), &"Salut"))
+        );
+        assert_eq!(get_best(&map, &["ja"]), Some((Lang::from("de"), &"Hallo")));
+
+        map.insert(Lang::new(), "Hello");
+        assert_eq!(get_best(&map, &["ja"]), Some((Lang::new(), &"Hello")));
+        assert_eq!(get_best(&map, &[]), Some((Lang::new(), &"Hello")));
+    }
+}
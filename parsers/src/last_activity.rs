@@ -0,0 +1,120 @@
+// Copyright (c) 2022 Emmanuel Gil Peyrot <linkmauve@linkmauve.fr>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use crate::iq::{IqGetPayload, IqResultPayload};
+use crate::util::helpers::PlainText;
+
+generate_empty_element!(
+    /// Asks for the time since the last activity of an account, the uptime
+    /// of a server, or the time since an account went offline.
+    ///
+    /// It should only be used in an `<iq type='get'/>`, as it can only
+    /// represent the request, and not a result.
+    LastActivityQuery,
+    "query",
+    LAST_ACTIVITY
+);
+
+impl IqGetPayload for LastActivityQuery {}
+
+generate_element!(
+    /// The answer to a [`LastActivityQuery`].
+    ///
+    /// It should only be used in an `<iq type='result'/>`, as it can only
+    /// represent the result, and not a request.
+    LastActivityResult, "query", LAST_ACTIVITY,
+    attributes: [
+        /// How many seconds ago the last activity happened.
+        seconds: Required<u64> = "seconds",
+    ],
+    text: (
+        /// The status the account last went offline with, if any.
+        status: PlainText<Option<String>>
+    )
+);
+
+impl IqResultPayload for LastActivityResult {}
+
+impl LastActivityResult {
+    /// Create a new result, for an activity `seconds` seconds ago.
+    pub fn new(seconds: u64) -> LastActivityResult {
+        LastActivityResult {
+            seconds,
+            status: None,
+        }
+    }
+
+    /// Also tell the status the account last went offline with.
+    pub fn with_status<S: Into<String>>(mut self, status: S) -> LastActivityResult {
+        self.status = Some(status.into());
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::error::Error;
+    use crate::Element;
+    use std::convert::TryFrom;
+
+    #[cfg(target_pointer_width = "32")]
+    #[test]
+    fn test_size() {
+        assert_size!(LastActivityQuery, 0);
+        assert_size!(LastActivityResult, 24);
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    fn test_size() {
+        assert_size!(LastActivityQuery, 0);
+        assert_size!(LastActivityResult, 32);
+    }
+
+    #[test]
+    fn test_query() {
+        let elem: Element = "<query xmlns='jabber:iq:last'/>".parse().unwrap();
+        LastActivityQuery::try_from(elem.clone()).unwrap();
+        assert_eq!(Element::from(LastActivityQuery), elem);
+    }
+
+    #[test]
+    fn test_result() {
+        let elem: Element = "<query xmlns='jabber:iq:last' seconds='903'/>"
+            .parse()
+            .unwrap();
+        let result = LastActivityResult::try_from(elem.clone()).unwrap();
+        assert_eq!(result.seconds, 903);
+        assert_eq!(result.status, None);
+        assert_eq!(Element::from(LastActivityResult::new(903)), elem);
+
+        let elem: Element = "<query xmlns='jabber:iq:last' seconds='903'>Heading Home</query>"
+            .parse()
+            .unwrap();
+        let result = LastActivityResult::try_from(elem.clone()).unwrap();
+        assert_eq!(result.status.as_deref(), Some("Heading Home"));
+        let result = LastActivityResult::new(903).with_status("Heading Home");
+        assert_eq!(Element::from(result), elem);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let elem: Element = "<query xmlns='jabber:iq:last'/>".parse().unwrap();
+        let error = LastActivityResult::try_from(elem).unwrap_err();
+        let message = match error {
+            Error::ParseError(string) => string,
+            _ => panic!(),
+        };
+        assert_eq!(message, "Required attribute 'seconds' missing.");
+
+        let elem: Element = "<query xmlns='jabber:iq:last' seconds='-5'/>"
+            .parse()
+            .unwrap();
+        let error = LastActivityResult::try_from(elem).unwrap_err();
+        assert!(matches!(error, Error::ParseIntError(_)));
+    }
+}
@@ -30,6 +30,9 @@ pub use minidom::Element;
 /// XML namespace definitions used through XMPP.
 pub mod ns;
 
+/// Picking the best of the texts of an element by their xml:lang.
+pub mod lang;
+
 #[macro_use]
 mod util;
 
@@ -47,6 +50,8 @@ pub mod sasl;
 pub mod stanza_error;
 /// RFC 6120: Extensible Messaging and Presence Protocol (XMPP): Core
 pub mod stream;
+/// RFC 6120: Extensible Messaging and Presence Protocol (XMPP): Core
+pub mod stream_error;
 
 /// RFC 6121: Extensible Messaging and Presence Protocol (XMPP): Instant Messaging and Presence
 pub mod roster;
@@ -57,6 +62,12 @@ pub mod websocket;
 /// XEP-0004: Data Forms
 pub mod data_forms;
 
+/// XEP-0012: Last Activity
+pub mod last_activity;
+
+/// XEP-0016: Privacy Lists
+pub mod privacy;
+
 /// XEP-0030: Service Discovery
 pub mod disco;
 
@@ -69,12 +80,27 @@ pub mod ibb;
 /// XEP-0048: Bookmarks
 pub mod bookmarks;
 
+/// XEP-0049: Private XML Storage
+pub mod private;
+
+/// XEP-0050: Ad-Hoc Commands
+pub mod commands;
+
+/// XEP-0054: vcard-temp
+pub mod vcard;
+
 /// XEP-0059: Result Set Management
 pub mod rsm;
 
 /// XEP-0060: Publish-Subscribe
 pub mod pubsub;
 
+/// XEP-0065: SOCKS5 Bytestreams
+pub mod bytestreams;
+
+/// XEP-0070: Verifying HTTP Requests via XMPP
+pub mod http_auth;
+
 /// XEP-0071: XHTML-IM
 pub mod xhtml;
 
@@ -96,6 +122,9 @@ pub mod version;
 /// XEP-0107: User Mood
 pub mod mood;
 
+/// XEP-0108: User Activity
+pub mod activity;
+
 /// XEP-0114: Jabber Component Protocol
 pub mod component;
 
@@ -105,6 +134,12 @@ pub mod caps;
 /// XEP-0118: User Tune
 pub mod tune;
 
+/// XEP-0133: Service Administration
+pub mod admin;
+
+/// XEP-0153: vCard-Based Avatars
+pub mod vcard_update;
+
 /// XEP-0157: Contact Addresses for XMPP Services
 pub mod server_info;
 
@@ -159,12 +194,18 @@ pub mod jingle_ft;
 /// XEP-0257: Client Certificate Management for SASL EXTERNAL
 pub mod cert_management;
 
+/// XEP-0258: Security Labels in XMPP
+pub mod sec_label;
+
 /// XEP-0260: Jingle SOCKS5 Bytestreams Transport Method
 pub mod jingle_s5b;
 
 /// XEP-0261: Jingle In-Band Bytestreams Transport Method
 pub mod jingle_ibb;
 
+/// XEP-0264: Jingle Content Thumbnails
+pub mod thumbs;
+
 /// XEP-0280: Message Carbons
 pub mod carbons;
 
@@ -198,6 +239,9 @@ pub mod jingle_dtls_srtp;
 /// XEP-0328: JID Prep
 pub mod jid_prep;
 
+/// XEP-0334: Message Processing Hints
+pub mod hints;
+
 /// XEP-0338: Jingle Grouping Framework
 pub mod jingle_grouping;
 
@@ -219,6 +263,9 @@ pub mod http_upload;
 /// XEP-0369: Mediated Information eXchange (MIX)
 pub mod mix;
 
+/// XEP-0372: References
+pub mod reference;
+
 /// XEP-0373: OpenPGP for XMPP
 pub mod openpgp;
 
@@ -231,11 +278,35 @@ pub mod legacy_omemo;
 /// XEP-0390: Entity Capabilities 2.0
 pub mod ecaps2;
 
+/// XEP-0392: Consistent Color Generation
+pub mod consistent_color;
+
+/// XEP-0393: Message Styling
+pub mod styling;
+
 /// XEP-0402: PEP Native Bookmarks
 pub mod bookmarks2;
 
+/// XEP-0405: Mediated Information eXchange (MIX): Participant Server Requirements
+pub mod mix_pam;
+
+/// XEP-0406: Mediated Information eXchange (MIX): MIX Administration
+pub mod mix_admin;
+
 /// XEP-0421: Anonymous unique occupant identifiers for MUCs
 pub mod occupant_id;
 
+/// XEP-0428: Fallback Indication
+pub mod fallback;
+
 /// XEP-0441: Message Archive Management Preferences
 pub mod mam_prefs;
+
+/// XEP-0444: Message Reactions
+pub mod reactions;
+
+/// XEP-0446: File metadata element
+pub mod file_metadata;
+
+/// XEP-0447: Stateless file sharing
+pub mod sfs;
@@ -4,12 +4,17 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
-use crate::data_forms::DataForm;
+use crate::data_forms::{DataForm, DataFormType, Field, FieldType};
+use crate::date::DateTime;
 use crate::forwarding::Forwarded;
 use crate::iq::{IqGetPayload, IqResultPayload, IqSetPayload};
 use crate::message::MessagePayload;
+use crate::ns;
 use crate::pubsub::NodeName;
 use crate::rsm::{SetQuery, SetResult};
+use crate::util::error::Error;
+use jid::Jid;
+use std::convert::TryFrom;
 
 generate_id!(
     /// An identifier matching a result message to the query requesting it.
@@ -40,6 +45,120 @@ impl IqGetPayload for Query {}
 impl IqSetPayload for Query {}
 impl IqResultPayload for Query {}
 
+impl Query {
+    /// Creates a query for the whole archive.
+    pub fn new() -> Query {
+        Query {
+            queryid: None,
+            node: None,
+            form: None,
+            set: None,
+        }
+    }
+
+    /// Sets the identifier the forwarded messages will be tagged with.
+    pub fn with_queryid(mut self, queryid: QueryId) -> Query {
+        self.queryid = Some(queryid);
+        self
+    }
+
+    /// Only returns the archived messages matching this filter.
+    pub fn with_filter(mut self, filter: QueryFilter) -> Query {
+        self.form = Some(DataForm::from(filter));
+        self
+    }
+
+    /// Requests this page of the results.
+    pub fn with_set(mut self, set: SetQuery) -> Query {
+        self.set = Some(set);
+        self
+    }
+
+    /// Parses the filter of this query, if any.
+    pub fn filter(&self) -> Result<Option<QueryFilter>, Error> {
+        self.form.clone().map(QueryFilter::try_from).transpose()
+    }
+}
+
+impl Default for Query {
+    fn default() -> Query {
+        Query::new()
+    }
+}
+
+/// The typed fields of the filtering form of a query, the extended ones
+/// being only supported by archives advertising
+/// [MAM_EXTENDED](../ns/constant.MAM_EXTENDED.html).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct QueryFilter {
+    /// Only returns the messages exchanged with this JID.
+    pub with: Option<Jid>,
+
+    /// Only returns the messages received at or after this date.
+    pub start: Option<DateTime>,
+
+    /// Only returns the messages received at or before this date.
+    pub end: Option<DateTime>,
+
+    /// Only returns the messages archived before the one with this id.
+    pub before_id: Option<String>,
+
+    /// Only returns the messages archived after the one with this id.
+    pub after_id: Option<String>,
+
+    /// Only returns the messages with these ids.
+    pub ids: Vec<String>,
+}
+
+impl TryFrom<DataForm> for QueryFilter {
+    type Error = Error;
+
+    fn try_from(form: DataForm) -> Result<QueryFilter, Error> {
+        if form.form_type.as_deref() != Some(ns::MAM) {
+            return Err(Error::ParseError("Wrong FORM_TYPE for a query filter."));
+        }
+        let mut filter = QueryFilter::default();
+        for field in form.fields.iter() {
+            match field.var.as_str() {
+                "with" => filter.with = field.parse_single()?,
+                "start" => filter.start = field.parse_single()?,
+                "end" => filter.end = field.parse_single()?,
+                "before-id" => filter.before_id = field.parse_single()?,
+                "after-id" => filter.after_id = field.parse_single()?,
+                "ids" => filter.ids = field.values.clone(),
+                // Ignore the fields of extensions.
+                _ => (),
+            }
+        }
+        Ok(filter)
+    }
+}
+
+impl From<QueryFilter> for DataForm {
+    fn from(filter: QueryFilter) -> DataForm {
+        let mut form = DataForm::new(DataFormType::Submit, ns::MAM, Vec::new());
+        if let Some(ref with) = filter.with {
+            form = form.with_field(Field::jid_single("with", with));
+        }
+        if let Some(ref start) = filter.start {
+            form = form.with_field(Field::text_single("start", &start.format("%FT%T%:z")));
+        }
+        if let Some(ref end) = filter.end {
+            form = form.with_field(Field::text_single("end", &end.format("%FT%T%:z")));
+        }
+        if let Some(ref before_id) = filter.before_id {
+            form = form.with_field(Field::text_single("before-id", before_id));
+        }
+        if let Some(ref after_id) = filter.after_id {
+            form = form.with_field(Field::text_single("after-id", after_id));
+        }
+        if !filter.ids.is_empty() {
+            form = form.with_field(Field::new("ids", FieldType::ListMulti).with_values(filter.ids));
+        }
+        form
+    }
+}
+
 generate_element!(
     /// The wrapper around forwarded stanzas.
     Result_, "result", MAM,
@@ -90,9 +209,9 @@ impl IqResultPayload for Fin {}
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::util::error::Error;
+    use jid::BareJid;
     use minidom::Element;
-    use std::convert::TryFrom;
+    use std::str::FromStr;
 
     #[cfg(target_pointer_width = "32")]
     #[test]
@@ -288,4 +407,64 @@ mod tests {
         let serialized: Element = fin.into();
         assert_eq!(serialized, reference);
     }
+
+    #[test]
+    fn test_filter() {
+        let elem: Element = r#"<query xmlns='urn:xmpp:mam:2' queryid='f27'>
+  <x xmlns='jabber:x:data' type='submit'>
+    <field type='hidden' var='FORM_TYPE'><value>urn:xmpp:mam:2</value></field>
+    <field type='jid-single' var='with'><value>juliet@capulet.lit</value></field>
+    <field type='text-single' var='start'><value>2010-06-07T00:00:00+00:00</value></field>
+    <field type='text-single' var='after-id'><value>09af3-cc343-b409f</value></field>
+    <field type='list-multi' var='ids'><value>28482-98726-73623</value><value>09af3-cc343-b409f</value></field>
+  </x>
+</query>"#
+            .parse()
+            .unwrap();
+        let query = Query::try_from(elem).unwrap();
+        let filter = query.filter().unwrap().unwrap();
+        assert_eq!(
+            filter.with,
+            Some(Jid::Bare(BareJid::new("juliet", "capulet.lit")))
+        );
+        assert_eq!(
+            filter.start,
+            Some(DateTime::from_str("2010-06-07T00:00:00Z").unwrap())
+        );
+        assert_eq!(filter.end, None);
+        assert_eq!(filter.before_id, None);
+        assert_eq!(filter.after_id.as_deref(), Some("09af3-cc343-b409f"));
+        assert_eq!(filter.ids, ["28482-98726-73623", "09af3-cc343-b409f"]);
+
+        let query2 = Query::new()
+            .with_queryid(QueryId(String::from("f27")))
+            .with_filter(filter.clone());
+        assert_eq!(query2.filter().unwrap(), Some(filter));
+        assert_eq!(query2.queryid, query.queryid);
+    }
+
+    #[test]
+    fn test_filter_invalid() {
+        let form = DataForm::new(DataFormType::Submit, "urn:xmpp:mam:1", Vec::new());
+        let error = QueryFilter::try_from(form).unwrap_err();
+        let message = match error {
+            Error::ParseError(string) => string,
+            _ => panic!(),
+        };
+        assert_eq!(message, "Wrong FORM_TYPE for a query filter.");
+
+        let form = DataForm::new(
+            DataFormType::Submit,
+            ns::MAM,
+            vec![Field::new("with", FieldType::JidSingle)
+                .with_value("juliet@capulet.lit")
+                .with_value("romeo@montague.lit")],
+        );
+        let error = QueryFilter::try_from(form).unwrap_err();
+        let message = match error {
+            Error::ParseError(string) => string,
+            _ => panic!(),
+        };
+        assert_eq!(message, "More than one value in a single-valued field.");
+    }
 }
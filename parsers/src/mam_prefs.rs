@@ -7,7 +7,7 @@
 use crate::iq::{IqGetPayload, IqResultPayload, IqSetPayload};
 use crate::ns;
 use crate::util::error::Error;
-use jid::Jid;
+use jid::{BareJid, Jid};
 use minidom::{Element, Node};
 use std::convert::TryFrom;
 
@@ -27,7 +27,7 @@ generate_attribute!(
 );
 
 /// Controls the archiving preferences of the user.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Prefs {
     /// The default preference for JIDs in neither
     /// [always](#structfield.always) or [never](#structfield.never) lists.
@@ -44,6 +44,49 @@ impl IqGetPayload for Prefs {}
 impl IqSetPayload for Prefs {}
 impl IqResultPayload for Prefs {}
 
+impl Prefs {
+    /// Creates preferences with this default, and empty lists.
+    pub fn new(default_: DefaultPrefs) -> Prefs {
+        Prefs {
+            default_,
+            always: Vec::new(),
+            never: Vec::new(),
+        }
+    }
+
+    /// Adds a JID for which to always store messages in the archive.
+    pub fn with_always<J: Into<Jid>>(mut self, jid: J) -> Prefs {
+        self.always.push(jid.into());
+        self
+    }
+
+    /// Adds a JID for which to never store messages in the archive.
+    pub fn with_never<J: Into<Jid>>(mut self, jid: J) -> Prefs {
+        self.never.push(jid.into());
+        self
+    }
+
+    /// Whether messages exchanged with `jid` get stored in the archive, a
+    /// bare JID in a list matching all of its full JIDs.  `in_roster` tells
+    /// whether the contact is present in the user’s roster, and the never
+    /// list takes precedence over the always one.
+    pub fn should_archive(&self, jid: &Jid, in_roster: bool) -> bool {
+        let bare = Jid::Bare(BareJid::from(jid.clone()));
+        let matches = |list: &[Jid]| list.iter().any(|item| item == jid || *item == bare);
+        if matches(&self.never) {
+            false
+        } else if matches(&self.always) {
+            true
+        } else {
+            match self.default_ {
+                DefaultPrefs::Always => true,
+                DefaultPrefs::Never => false,
+                DefaultPrefs::Roster => in_roster,
+            }
+        }
+    }
+}
+
 impl TryFrom<Element> for Prefs {
     type Error = Error;
 
@@ -109,7 +152,7 @@ impl From<Prefs> for Element {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use jid::BareJid;
+    use jid::FullJid;
 
     #[cfg(target_pointer_width = "32")]
     #[test]
@@ -170,4 +213,33 @@ mod tests {
         assert_eq!(prefs.always, prefs2.always);
         assert_eq!(prefs.never, prefs2.never);
     }
+
+    #[test]
+    fn test_builders() {
+        let prefs = Prefs::new(DefaultPrefs::Roster)
+            .with_always(BareJid::new("romeo", "montague.lit"))
+            .with_never(BareJid::new("montague", "montague.lit"));
+        let elem: Element = "<prefs xmlns='urn:xmpp:mam:2' default='roster'><always><jid>romeo@montague.lit</jid></always><never><jid>montague@montague.lit</jid></never></prefs>"
+            .parse()
+            .unwrap();
+        assert_eq!(Prefs::try_from(elem.clone()).unwrap(), prefs);
+        assert_eq!(Element::from(prefs), elem);
+    }
+
+    #[test]
+    fn test_should_archive() {
+        let romeo = Jid::Full(FullJid::new("romeo", "montague.lit", "orchard"));
+        let juliet = Jid::Bare(BareJid::new("juliet", "capulet.lit"));
+        let prefs =
+            Prefs::new(DefaultPrefs::Never).with_always(BareJid::new("romeo", "montague.lit"));
+        assert!(prefs.should_archive(&romeo, false));
+        assert!(!prefs.should_archive(&juliet, true));
+
+        let prefs = Prefs::new(DefaultPrefs::Roster)
+            .with_always(romeo.clone())
+            .with_never(BareJid::new("romeo", "montague.lit"));
+        assert!(!prefs.should_archive(&romeo, true));
+        assert!(prefs.should_archive(&juliet, true));
+        assert!(!prefs.should_archive(&juliet, false));
+    }
 }
@@ -4,6 +4,8 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
+use crate::attention::Attention;
+use crate::lang;
 use crate::ns;
 use crate::util::error::Error;
 use crate::Element;
@@ -45,6 +47,28 @@ generate_elem_id!(
     DEFAULT_NS
 );
 
+impl Body {
+    /// Creates a [XEP-0245](https://xmpp.org/extensions/xep-0245.html)
+    /// body, describing an action of the sender, for instance
+    /// `Body::me("laughs")`.
+    pub fn me(action: &str) -> Body {
+        Body(format!("/me {}", action))
+    }
+
+    /// Returns the action of this body, if it is a
+    /// [XEP-0245](https://xmpp.org/extensions/xep-0245.html) `/me` command.
+    pub fn me_action(&self) -> Option<&str> {
+        self.0.strip_prefix("/me ")
+    }
+
+    /// Renders this body as an action of `nick`, like “* nick laughs”, if
+    /// it is a `/me` command.
+    pub fn render_me(&self, nick: &str) -> Option<String> {
+        self.me_action()
+            .map(|action| format!("* {} {}", nick, action))
+    }
+}
+
 generate_elem_id!(
     /// Defines the subject of a room, or of an email-like normal message.
     Subject,
@@ -112,31 +136,64 @@ impl Message {
         map: &'a BTreeMap<Lang, T>,
         preferred_langs: Vec<&str>,
     ) -> Option<(Lang, &'a T)> {
-        if map.is_empty() {
-            return None;
-        }
-        for lang in preferred_langs {
-            if let Some(value) = map.get(lang) {
-                return Some((Lang::from(lang), value));
-            }
-        }
-        if let Some(value) = map.get("") {
-            return Some((Lang::new(), value));
-        }
-        map.iter().map(|(lang, value)| (lang.clone(), value)).next()
+        lang::get_best(map, &preferred_langs)
     }
 
     /// Returns the best matching body from a list of languages.
     ///
     /// For instance, if a message contains both an xml:lang='de', an xml:lang='fr' and an English
     /// body without an xml:lang attribute, and you pass ["fr", "en"] as your preferred languages,
-    /// `Some(("fr", the_second_body))` will be returned.
+    /// `Some(("fr", the_second_body))` will be returned.  A preferred language like "fr-CA" also
+    /// matches a body in "fr".
     ///
     /// If no body matches, an undefined body will be returned.
     pub fn get_best_body(&self, preferred_langs: Vec<&str>) -> Option<(Lang, &Body)> {
         Message::get_best::<Body>(&self.bodies, preferred_langs)
     }
 
+    /// Adds a payload to this message.
+    pub fn add_payload<P: MessagePayload>(&mut self, payload: P) {
+        self.payloads.push(payload.into());
+    }
+
+    /// Returns the first payload of this message which parses as a `P`,
+    /// for instance `message.get_extension::<ChatState>()`.
+    ///
+    /// The payloads failing to parse, be it because they are of another
+    /// type or because they are invalid, get skipped.
+    pub fn get_extension<P: MessagePayload>(&self) -> Option<P> {
+        self.payloads
+            .iter()
+            .find_map(|payload| P::try_from(payload.clone()).ok())
+    }
+
+    /// Returns all of the payloads of this message which parse as a `P`,
+    /// in order.
+    pub fn get_extensions<P: MessagePayload>(&self) -> Vec<P> {
+        self.payloads
+            .iter()
+            .filter_map(|payload| P::try_from(payload.clone()).ok())
+            .collect()
+    }
+
+    /// Requests the attention of the recipient (XEP-0224), only once even
+    /// if called several times.
+    ///
+    /// Check with [Attention::is_supported_by()](../attention/struct.Attention.html#method.is_supported_by)
+    /// that the recipient supports it first.
+    pub fn request_attention(&mut self) {
+        if !self.requests_attention() {
+            self.payloads.push(Attention.into());
+        }
+    }
+
+    /// Whether this message requests the attention of its recipient.
+    pub fn requests_attention(&self) -> bool {
+        self.payloads
+            .iter()
+            .any(|payload| payload.is("attention", ns::ATTENTION))
+    }
+
     /// Returns the best matching subject from a list of languages.
     ///
     /// For instance, if a message contains both an xml:lang='de', an xml:lang='fr' and an English
@@ -401,7 +458,58 @@ mod tests {
         let elem: Element = "<message xmlns='jabber:component:accept' to='coucou@example.org' type='chat'><attention xmlns='urn:xmpp:attention:0'/></message>".parse().unwrap();
         let elem1 = elem.clone();
         let message = Message::try_from(elem).unwrap();
+        assert!(message.requests_attention());
         let elem2 = message.into();
         assert_eq!(elem1, elem2);
+
+        let mut message = Message::new(Jid::from_str("coucou@example.org").unwrap());
+        assert!(!message.requests_attention());
+        message.request_attention();
+        message.request_attention();
+        assert_eq!(message.payloads.len(), 1);
+        let elem3 = message.into();
+        assert_eq!(elem1, elem3);
+    }
+
+    #[test]
+    fn test_extensions() {
+        use crate::chatstates::ChatState;
+        use crate::receipts::{Received, Request};
+
+        #[cfg(not(feature = "component"))]
+        let elem: Element = "<message xmlns='jabber:client' to='coucou@example.org' type='chat'><active xmlns='http://jabber.org/protocol/chatstates'/><received xmlns='urn:xmpp:receipts' id='a'/><received xmlns='urn:xmpp:receipts' id='b'/></message>".parse().unwrap();
+        #[cfg(feature = "component")]
+        let elem: Element = "<message xmlns='jabber:component:accept' to='coucou@example.org' type='chat'><active xmlns='http://jabber.org/protocol/chatstates'/><received xmlns='urn:xmpp:receipts' id='a'/><received xmlns='urn:xmpp:receipts' id='b'/></message>".parse().unwrap();
+        let message = Message::try_from(elem).unwrap();
+        assert_eq!(
+            message.get_extension::<ChatState>(),
+            Some(ChatState::Active)
+        );
+        assert_eq!(message.get_extension::<Received>().unwrap().id, "a");
+        let ids: Vec<_> = message
+            .get_extensions::<Received>()
+            .into_iter()
+            .map(|received| received.id)
+            .collect();
+        assert_eq!(ids, ["a", "b"]);
+        assert!(message.get_extension::<Request>().is_none());
+
+        let mut message = Message::new(None);
+        message.add_payload(Request);
+        assert!(message.get_extension::<Request>().is_some());
+    }
+
+    #[test]
+    fn test_me() {
+        let body = Body::me("laughs");
+        assert_eq!(body.0, "/me laughs");
+        assert_eq!(body.me_action(), Some("laughs"));
+        assert_eq!(body.render_me("Juliet").as_deref(), Some("* Juliet laughs"));
+
+        for text in &["Hello", "/me", "/meh", " /me laughs", "/ME laughs"] {
+            let body = Body::from_str(text).unwrap();
+            assert_eq!(body.me_action(), None);
+            assert_eq!(body.render_me("Juliet"), None);
+        }
     }
 }
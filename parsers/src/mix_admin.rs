@@ -0,0 +1,421 @@
+// Copyright (c) 2022 Emmanuel Gil Peyrot <linkmauve@linkmauve.fr>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Channels get created and destroyed with [`Create`](crate::mix::Create)
+//! and [`Destroy`](crate::mix::Destroy), and configured by publishing a
+//! [`ChannelConfig`] form on their configuration node, while the allowed
+//! and banned JIDs are items of their own nodes.
+
+use crate::data_forms::{DataForm, DataFormType, Field, FieldType};
+use crate::date::DateTime;
+use crate::ns;
+use crate::pubsub::pubsub::{Item, PubSub, Publish, Retract};
+use crate::pubsub::{Item as PubSubItem, ItemId, NodeName};
+use crate::util::error::Error;
+use jid::{BareJid, Jid};
+use std::convert::TryFrom;
+use std::str::FromStr;
+
+generate_attribute!(
+    /// Who can subscribe to, update or access a node of a channel.
+    NodeAccess, "access", {
+        /// Nobody.
+        Nobody => "nobody",
+
+        /// Only the owners.
+        Owners => "owners",
+
+        /// The owners and administrators.
+        Admins => "admins",
+
+        /// The participants of the channel.
+        Participants => "participants",
+
+        /// The JIDs on the allowed node.
+        Allowed => "allowed",
+
+        /// Anyone not banned.
+        Anyone => "anyone",
+    }
+);
+
+/// The configuration of a channel, as published on its
+/// `urn:xmpp:mix:nodes:config` node.
+///
+/// The options left to `None` are kept as they are when updating a
+/// configuration.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ChannelConfig {
+    /// Who changed this configuration last.
+    pub last_change_made_by: Option<BareJid>,
+
+    /// The owners of this channel.
+    pub owners: Vec<BareJid>,
+
+    /// The administrators of this channel.
+    pub administrators: Vec<BareJid>,
+
+    /// When this channel will get destroyed.
+    pub end_of_life: Option<DateTime>,
+
+    /// The optional nodes this channel has, like `avatar` or `allowed`.
+    pub nodes_present: Vec<String>,
+
+    /// Who can subscribe to the messages node.
+    pub messages_node_subscription: Option<NodeAccess>,
+
+    /// Who can subscribe to the presence node.
+    pub presence_node_subscription: Option<NodeAccess>,
+
+    /// Who can subscribe to the participants node.
+    pub participants_node_subscription: Option<NodeAccess>,
+
+    /// Who can subscribe to the information node.
+    pub information_node_subscription: Option<NodeAccess>,
+
+    /// Who can update the information node.
+    pub information_node_update_rights: Option<NodeAccess>,
+
+    /// Who can update the avatar nodes.
+    pub avatar_nodes_update_rights: Option<NodeAccess>,
+
+    /// Who can access the configuration node.
+    pub configuration_node_access: Option<NodeAccess>,
+
+    /// Whether anyone can share their presence in the channel, or only
+    /// the allowed JIDs.
+    pub open_presence: Option<bool>,
+
+    /// Whether participants have to share their presence.
+    pub participants_must_provide_presence: Option<bool>,
+
+    /// Whether users can retract their own messages.
+    pub user_message_retraction: Option<bool>,
+
+    /// Whether administrators can retract any message.
+    pub administrator_message_retraction: Option<bool>,
+
+    /// Whether participants can invite other users.
+    pub participation_addition_by_invitation_from_participant: Option<bool>,
+
+    /// Whether participants can send private messages to each other.
+    pub private_messages: Option<bool>,
+}
+
+fn parse_jids(field: &Field) -> Result<Vec<BareJid>, Error> {
+    field
+        .values
+        .iter()
+        .map(|value| Ok(BareJid::from_str(value)?))
+        .collect()
+}
+
+impl TryFrom<DataForm> for ChannelConfig {
+    type Error = Error;
+
+    fn try_from(form: DataForm) -> Result<ChannelConfig, Error> {
+        if form.form_type.as_deref() != Some(ns::MIX_ADMIN) {
+            return Err(Error::ParseError(
+                "Wrong FORM_TYPE for a channel configuration.",
+            ));
+        }
+        let mut config = ChannelConfig::default();
+        for field in form.fields.iter() {
+            match field.var.as_str() {
+                "Last Change Made By" => config.last_change_made_by = field.parse_single()?,
+                "Owner" => config.owners = parse_jids(field)?,
+                "Administrator" => config.administrators = parse_jids(field)?,
+                "End of Life" => config.end_of_life = field.parse_single()?,
+                "Nodes Present" => config.nodes_present = field.values.clone(),
+                "Messages Node Subscription" => {
+                    config.messages_node_subscription = field.parse_single()?
+                }
+                "Presence Node Subscription" => {
+                    config.presence_node_subscription = field.parse_single()?
+                }
+                "Participants Node Subscription" => {
+                    config.participants_node_subscription = field.parse_single()?
+                }
+                "Information Node Subscription" => {
+                    config.information_node_subscription = field.parse_single()?
+                }
+                "Information Node Update Rights" => {
+                    config.information_node_update_rights = field.parse_single()?
+                }
+                "Avatar Nodes Update Rights" => {
+                    config.avatar_nodes_update_rights = field.parse_single()?
+                }
+                "Configuration Node Access" => {
+                    config.configuration_node_access = field.parse_single()?
+                }
+                "Open Presence" => config.open_presence = field.parse_single_bool()?,
+                "Participants Must Provide Presence" => {
+                    config.participants_must_provide_presence = field.parse_single_bool()?
+                }
+                "User Message Retraction" => {
+                    config.user_message_retraction = field.parse_single_bool()?
+                }
+                "Administrator Message Retraction" => {
+                    config.administrator_message_retraction = field.parse_single_bool()?
+                }
+                "Participation Addition by Invitation from Participant" => {
+                    config.participation_addition_by_invitation_from_participant =
+                        field.parse_single_bool()?
+                }
+                "Private Messages" => config.private_messages = field.parse_single_bool()?,
+                // Ignore the fields of extensions.
+                _ => (),
+            }
+        }
+        Ok(config)
+    }
+}
+
+impl ChannelConfig {
+    /// Serialise this configuration into a form of this type, only
+    /// including the options which are set.
+    pub fn to_form(&self, type_: DataFormType) -> DataForm {
+        let mut form = DataForm::new(type_, ns::MIX_ADMIN, Vec::new());
+        let jids = |jids: &[BareJid]| jids.iter().map(|jid| jid.to_string()).collect::<Vec<_>>();
+        if let Some(ref jid) = self.last_change_made_by {
+            form = form.with_field(Field::jid_single(
+                "Last Change Made By",
+                &Jid::Bare(jid.clone()),
+            ));
+        }
+        if !self.owners.is_empty() {
+            form = form.with_field(
+                Field::new("Owner", FieldType::JidMulti).with_values(jids(&self.owners)),
+            );
+        }
+        if !self.administrators.is_empty() {
+            form = form.with_field(
+                Field::new("Administrator", FieldType::JidMulti)
+                    .with_values(jids(&self.administrators)),
+            );
+        }
+        if let Some(ref end_of_life) = self.end_of_life {
+            form = form.with_field(Field::text_single(
+                "End of Life",
+                &end_of_life.format("%FT%T%:z"),
+            ));
+        }
+        if !self.nodes_present.is_empty() {
+            form = form.with_field(Field::list_multi(
+                "Nodes Present",
+                self.nodes_present.iter().cloned(),
+            ));
+        }
+        let accesses = [
+            (
+                "Messages Node Subscription",
+                &self.messages_node_subscription,
+            ),
+            (
+                "Presence Node Subscription",
+                &self.presence_node_subscription,
+            ),
+            (
+                "Participants Node Subscription",
+                &self.participants_node_subscription,
+            ),
+            (
+                "Information Node Subscription",
+                &self.information_node_subscription,
+            ),
+            (
+                "Information Node Update Rights",
+                &self.information_node_update_rights,
+            ),
+            (
+                "Avatar Nodes Update Rights",
+                &self.avatar_nodes_update_rights,
+            ),
+            ("Configuration Node Access", &self.configuration_node_access),
+        ];
+        for (var, access) in accesses.iter() {
+            if let Some(access) = access {
+                form = form.with_field(Field::list_single(var, &access.to_string()));
+            }
+        }
+        let booleans = [
+            ("Open Presence", self.open_presence),
+            (
+                "Participants Must Provide Presence",
+                self.participants_must_provide_presence,
+            ),
+            ("User Message Retraction", self.user_message_retraction),
+            (
+                "Administrator Message Retraction",
+                self.administrator_message_retraction,
+            ),
+            (
+                "Participation Addition by Invitation from Participant",
+                self.participation_addition_by_invitation_from_participant,
+            ),
+            ("Private Messages", self.private_messages),
+        ];
+        for (var, value) in booleans.iter() {
+            if let Some(value) = value {
+                form = form.with_field(Field::boolean(var, *value));
+            }
+        }
+        form
+    }
+
+    /// The request to publish this configuration on the configuration node
+    /// of a channel.
+    pub fn publish(&self) -> PubSub {
+        let item = PubSubItem {
+            id: None,
+            publisher: None,
+            payload: Some(self.to_form(DataFormType::Submit).into()),
+        };
+        publish(ns::MIX_NODES_CONFIG, item)
+    }
+}
+
+fn publish(node: &str, item: PubSubItem) -> PubSub {
+    PubSub::Publish {
+        publish: Publish {
+            node: NodeName(String::from(node)),
+            items: vec![Item(item)],
+        },
+        publish_options: None,
+    }
+}
+
+fn jid_item(jid: &BareJid) -> PubSubItem {
+    PubSubItem {
+        id: Some(ItemId(jid.to_string())),
+        publisher: None,
+        payload: None,
+    }
+}
+
+fn retract(node: &str, jid: &BareJid) -> PubSub {
+    PubSub::Retract(Retract {
+        node: NodeName(String::from(node)),
+        notify: Default::default(),
+        items: vec![Item(jid_item(jid))],
+    })
+}
+
+/// The request to allow this JID in a channel, for channels restricted to
+/// the allowed JIDs.
+pub fn allow(jid: &BareJid) -> PubSub {
+    publish(ns::MIX_NODES_ALLOWED, jid_item(jid))
+}
+
+/// The request to remove this JID from the allowed ones.
+pub fn disallow(jid: &BareJid) -> PubSub {
+    retract(ns::MIX_NODES_ALLOWED, jid)
+}
+
+/// The request to ban this JID from a channel.
+pub fn ban(jid: &BareJid) -> PubSub {
+    publish(ns::MIX_NODES_BANNED, jid_item(jid))
+}
+
+/// The request to lift the ban of this JID.
+pub fn unban(jid: &BareJid) -> PubSub {
+    retract(ns::MIX_NODES_BANNED, jid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Element;
+
+    #[test]
+    fn test_size() {
+        assert_size!(NodeAccess, 1);
+    }
+
+    #[test]
+    fn test_config() {
+        let elem: Element = "<x xmlns='jabber:x:data' type='result'>
+            <field var='FORM_TYPE' type='hidden'><value>urn:xmpp:mix:admin:0</value></field>
+            <field var='Last Change Made By'><value>tom@shakespeare.example</value></field>
+            <field var='Owner'><value>hecate@shakespeare.example</value><value>greymalkin@shakespeare.example</value></field>
+            <field var='Messages Node Subscription'><value>allowed</value></field>
+            <field var='Presence Node Subscription'><value>participants</value></field>
+            <field var='Information Node Update Rights'><value>admins</value></field>
+            <field var='Open Presence'><value>false</value></field>
+            <field var='Private Messages'><value>1</value></field>
+            <field var='urn:example#custom'><value>whatever</value></field>
+        </x>"
+            .parse()
+            .unwrap();
+        let form = DataForm::try_from(elem).unwrap();
+        let config = ChannelConfig::try_from(form).unwrap();
+        assert_eq!(
+            config.last_change_made_by,
+            Some(BareJid::new("tom", "shakespeare.example"))
+        );
+        assert_eq!(config.owners.len(), 2);
+        assert!(config.administrators.is_empty());
+        assert_eq!(config.messages_node_subscription, Some(NodeAccess::Allowed));
+        assert_eq!(
+            config.presence_node_subscription,
+            Some(NodeAccess::Participants)
+        );
+        assert_eq!(
+            config.information_node_update_rights,
+            Some(NodeAccess::Admins)
+        );
+        assert_eq!(config.configuration_node_access, None);
+        assert_eq!(config.open_presence, Some(false));
+        assert_eq!(config.private_messages, Some(true));
+        assert_eq!(config.user_message_retraction, None);
+
+        // Serialising it back drops the unknown field.
+        let form = config.to_form(DataFormType::Submit);
+        assert_eq!(form.fields.len(), 7);
+        assert_eq!(ChannelConfig::try_from(form).unwrap(), config);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let form = DataForm::new(DataFormType::Result_, ns::MIX_ADMIN, Vec::new())
+            .with_field(Field::list_single("Messages Node Subscription", "everyone"));
+        let error = ChannelConfig::try_from(form).unwrap_err();
+        let message = match error {
+            Error::ParseError(string) => string,
+            _ => panic!(),
+        };
+        assert_eq!(message, "Unknown value for 'access' attribute.");
+
+        let form = DataForm::new(DataFormType::Result_, "urn:example", Vec::new());
+        ChannelConfig::try_from(form).unwrap_err();
+    }
+
+    #[test]
+    fn test_requests() {
+        let config = ChannelConfig {
+            open_presence: Some(true),
+            ..ChannelConfig::default()
+        };
+        let elem: Element = config.publish().into();
+        let expected: Element = "<pubsub xmlns='http://jabber.org/protocol/pubsub'><publish node='urn:xmpp:mix:nodes:config'><item><x xmlns='jabber:x:data' type='submit'><field var='FORM_TYPE' type='hidden'><value>urn:xmpp:mix:admin:0</value></field><field var='Open Presence' type='boolean'><value>1</value></field></x></item></publish></pubsub>"
+            .parse()
+            .unwrap();
+        assert_eq!(elem, expected);
+
+        let jid = BareJid::new("hag66", "shakespeare.example");
+        let elem: Element = ban(&jid).into();
+        let expected: Element = "<pubsub xmlns='http://jabber.org/protocol/pubsub'><publish node='urn:xmpp:mix:nodes:banned'><item id='hag66@shakespeare.example'/></publish></pubsub>"
+            .parse()
+            .unwrap();
+        assert_eq!(elem, expected);
+
+        let elem: Element = disallow(&jid).into();
+        let expected: Element = "<pubsub xmlns='http://jabber.org/protocol/pubsub'><retract node='urn:xmpp:mix:nodes:allowed'><item id='hag66@shakespeare.example'/></retract></pubsub>"
+            .parse()
+            .unwrap();
+        assert_eq!(elem, expected);
+    }
+}
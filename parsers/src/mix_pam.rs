@@ -0,0 +1,102 @@
+// Copyright (c) 2022 Emmanuel Gil Peyrot <linkmauve@linkmauve.fr>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use crate::iq::{IqResultPayload, IqSetPayload};
+use crate::mix::{Join, Leave};
+use jid::BareJid;
+
+generate_element!(
+    /// Asks the server of the user to join a MIX channel on their behalf.
+    ClientJoin, "client-join", MIX_PAM,
+    attributes: [
+        /// The channel to join, absent in the result.
+        channel: Option<BareJid> = "channel",
+    ],
+    children: [
+        /// The join request to forward to the channel, or its answer.
+        join: Required<Join> = ("join", MIX_CORE) => Join
+    ]
+);
+
+impl IqSetPayload for ClientJoin {}
+impl IqResultPayload for ClientJoin {}
+
+impl ClientJoin {
+    /// Create a new request to join this channel.
+    pub fn new(channel: BareJid, join: Join) -> ClientJoin {
+        ClientJoin {
+            channel: Some(channel),
+            join,
+        }
+    }
+}
+
+generate_element!(
+    /// Asks the server of the user to leave a MIX channel on their behalf.
+    ClientLeave, "client-leave", MIX_PAM,
+    attributes: [
+        /// The channel to leave, absent in the result.
+        channel: Option<BareJid> = "channel",
+    ],
+    children: [
+        /// The leave request to forward to the channel, or its answer.
+        leave: Required<Leave> = ("leave", MIX_CORE) => Leave
+    ]
+);
+
+impl IqSetPayload for ClientLeave {}
+impl IqResultPayload for ClientLeave {}
+
+impl ClientLeave {
+    /// Create a new request to leave this channel.
+    pub fn new(channel: BareJid) -> ClientLeave {
+        ClientLeave {
+            channel: Some(channel),
+            leave: Leave,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ns;
+    use crate::Element;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn test_join() {
+        let elem: Element = "<client-join xmlns='urn:xmpp:mix:pam:2' channel='coven@mix.shakespeare.example'><join xmlns='urn:xmpp:mix:core:1'><nick>third witch</nick><subscribe node='urn:xmpp:mix:nodes:messages'/></join></client-join>"
+            .parse()
+            .unwrap();
+        let client_join = ClientJoin::try_from(elem.clone()).unwrap();
+        assert_eq!(
+            client_join.channel,
+            Some(BareJid::new("coven", "mix.shakespeare.example"))
+        );
+        assert_eq!(client_join.join.nick, "third witch");
+
+        let join = Join::from_nick_and_nodes("third witch", &[ns::MIX_NODES_MESSAGES]);
+        let client_join = ClientJoin::new(BareJid::new("coven", "mix.shakespeare.example"), join);
+        assert_eq!(Element::from(client_join), elem);
+    }
+
+    #[test]
+    fn test_leave() {
+        let elem: Element = "<client-leave xmlns='urn:xmpp:mix:pam:2' channel='coven@mix.shakespeare.example'><leave xmlns='urn:xmpp:mix:core:1'/></client-leave>"
+            .parse()
+            .unwrap();
+        ClientLeave::try_from(elem.clone()).unwrap();
+        let client_leave = ClientLeave::new(BareJid::new("coven", "mix.shakespeare.example"));
+        assert_eq!(Element::from(client_leave), elem);
+
+        let elem: Element = "<client-leave xmlns='urn:xmpp:mix:pam:2'><leave xmlns='urn:xmpp:mix:core:1'/></client-leave>"
+            .parse()
+            .unwrap();
+        let client_leave = ClientLeave::try_from(elem).unwrap();
+        assert_eq!(client_leave.channel, None);
+    }
+}
@@ -4,6 +4,12 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
+use crate::ns;
+use crate::pubsub::PubSubPayload;
+use crate::util::error::Error;
+use crate::Element;
+use std::convert::TryFrom;
+
 generate_element_enum!(
     /// Enum representing all of the possible values of the XEP-0107 moods.
     MoodEnum, "mood", MOOD, {
@@ -268,17 +274,80 @@ generate_elem_id!(
     MOOD
 );
 
+/// The mood of a user, or no mood once they stopped publishing one.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Mood {
+    /// The mood itself, `None` if the user doesn’t want to share one
+    /// anymore.
+    pub mood: Option<MoodEnum>,
+
+    /// A free-form description of this mood.
+    pub text: Option<String>,
+}
+
+impl PubSubPayload for Mood {}
+
+impl Mood {
+    /// Create a new mood.
+    pub fn new(mood: MoodEnum) -> Mood {
+        Mood {
+            mood: Some(mood),
+            text: None,
+        }
+    }
+
+    /// Describe this mood with some text.
+    pub fn with_text<S: Into<String>>(mut self, text: S) -> Mood {
+        self.text = Some(text.into());
+        self
+    }
+}
+
+impl TryFrom<Element> for Mood {
+    type Error = Error;
+
+    fn try_from(elem: Element) -> Result<Mood, Error> {
+        check_self!(elem, "mood", MOOD);
+        check_no_attributes!(elem, "mood");
+
+        let mut mood = Mood::default();
+        for child in elem.children() {
+            if child.is("text", ns::MOOD) {
+                if mood.text.is_some() {
+                    return Err(Error::ParseError("Mood can’t have more than one text."));
+                }
+                mood.text = Some(Text::try_from(child.clone())?.0);
+            } else {
+                if mood.mood.is_some() {
+                    return Err(Error::ParseError("Mood can’t have more than one mood."));
+                }
+                mood.mood = Some(MoodEnum::try_from(child.clone())?);
+            }
+        }
+
+        Ok(mood)
+    }
+}
+
+impl From<Mood> for Element {
+    fn from(mood: Mood) -> Element {
+        Element::builder("mood", ns::MOOD)
+            .append_all(mood.mood)
+            .append_all(mood.text.map(Text))
+            .build()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::Element;
-    use std::convert::TryFrom;
 
     #[cfg(target_pointer_width = "32")]
     #[test]
     fn test_size() {
         assert_size!(MoodEnum, 1);
         assert_size!(Text, 12);
+        assert_size!(Mood, 16);
     }
 
     #[cfg(target_pointer_width = "64")]
@@ -286,6 +355,7 @@ mod tests {
     fn test_size() {
         assert_size!(MoodEnum, 1);
         assert_size!(Text, 24);
+        assert_size!(Mood, 32);
     }
 
     #[test]
@@ -309,4 +379,49 @@ mod tests {
         let elem3 = text.into();
         assert_eq!(elem2, elem3);
     }
+
+    #[test]
+    fn test_mood() {
+        let elem: Element =
+            "<mood xmlns='http://jabber.org/protocol/mood'><happy/><text>Yay!</text></mood>"
+                .parse()
+                .unwrap();
+        let mood = Mood::try_from(elem.clone()).unwrap();
+        assert_eq!(mood.mood, Some(MoodEnum::Happy));
+        assert_eq!(mood.text.as_deref(), Some("Yay!"));
+        assert_eq!(
+            Element::from(Mood::new(MoodEnum::Happy).with_text("Yay!")),
+            elem
+        );
+
+        let elem: Element = "<mood xmlns='http://jabber.org/protocol/mood'/>"
+            .parse()
+            .unwrap();
+        let mood = Mood::try_from(elem.clone()).unwrap();
+        assert_eq!(mood, Mood::default());
+        assert_eq!(Element::from(mood), elem);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let elem: Element = "<mood xmlns='http://jabber.org/protocol/mood'><happy/><sad/></mood>"
+            .parse()
+            .unwrap();
+        let error = Mood::try_from(elem).unwrap_err();
+        let message = match error {
+            Error::ParseError(string) => string,
+            _ => panic!(),
+        };
+        assert_eq!(message, "Mood can’t have more than one mood.");
+
+        let elem: Element = "<mood xmlns='http://jabber.org/protocol/mood'><meh/></mood>"
+            .parse()
+            .unwrap();
+        let error = Mood::try_from(elem).unwrap_err();
+        let message = match error {
+            Error::ParseError(string) => string,
+            _ => panic!(),
+        };
+        assert_eq!(message, "This is not a mood element.");
+    }
 }
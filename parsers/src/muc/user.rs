@@ -6,6 +6,7 @@
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
 use crate::ns;
+use crate::presence::Presence;
 use crate::util::error::Error;
 use crate::Element;
 use jid::FullJid;
@@ -233,6 +234,35 @@ generate_element!(
     ]
 );
 
+impl MucUser {
+    /// Whether this status code is present.
+    pub fn has_status(&self, status: &Status) -> bool {
+        self.status.contains(status)
+    }
+
+    /// Whether this is the presence of the user themself in the room, which
+    /// tells which of the occupants they are.
+    pub fn is_self_presence(&self) -> bool {
+        self.has_status(&Status::SelfPresence)
+    }
+
+    /// Whether the room assigned or modified the nick of the user.
+    pub fn nick_assigned(&self) -> bool {
+        self.has_status(&Status::AssignedNick)
+    }
+}
+
+impl Presence {
+    /// The MUC user payload of this presence, sent by a room about one of
+    /// its occupants.
+    pub fn muc_user(&self) -> Option<MucUser> {
+        self.payloads
+            .iter()
+            .find(|payload| payload.is("x", ns::MUC_USER))
+            .and_then(|payload| MucUser::try_from(payload.clone()).ok())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -263,6 +293,29 @@ mod tests {
         assert_eq!(muc_user.items[0].role, Role::Moderator);
     }
 
+    #[test]
+    fn test_self_presence() {
+        let elem: Element =
+            "<presence xmlns='jabber:client' from='coven@chat.shakespeare.lit/thirdwitch'>
+                <x xmlns='http://jabber.org/protocol/muc#user'>
+                    <item affiliation='member' role='participant'/>
+                    <status code='110'/>
+                    <status code='210'/>
+                </x>
+            </presence>"
+                .parse()
+                .unwrap();
+        let presence = Presence::try_from(elem).unwrap();
+        let muc_user = presence.muc_user().unwrap();
+        assert!(muc_user.is_self_presence());
+        assert!(muc_user.nick_assigned());
+        assert!(!muc_user.has_status(&Status::Kicked));
+
+        let elem: Element = "<presence xmlns='jabber:client'/>".parse().unwrap();
+        let presence = Presence::try_from(elem).unwrap();
+        assert_eq!(presence.muc_user(), None);
+    }
+
     #[test]
     fn test_invalid_child() {
         let elem: Element = "<x xmlns='http://jabber.org/protocol/muc#user'>
@@ -12,21 +12,37 @@ pub const XMPP_STANZAS: &str = "urn:ietf:params:xml:ns:xmpp-stanzas";
 /// RFC 6120: Extensible Messaging and Presence Protocol (XMPP): Core
 pub const STREAM: &str = "http://etherx.jabber.org/streams";
 /// RFC 6120: Extensible Messaging and Presence Protocol (XMPP): Core
+pub const XMPP_STREAMS: &str = "urn:ietf:params:xml:ns:xmpp-streams";
+/// RFC 6120: Extensible Messaging and Presence Protocol (XMPP): Core
 pub const TLS: &str = "urn:ietf:params:xml:ns:xmpp-tls";
 /// RFC 6120: Extensible Messaging and Presence Protocol (XMPP): Core
 pub const SASL: &str = "urn:ietf:params:xml:ns:xmpp-sasl";
 /// RFC 6120: Extensible Messaging and Presence Protocol (XMPP): Core
 pub const BIND: &str = "urn:ietf:params:xml:ns:xmpp-bind";
 
+/// RFC 3921: Extensible Messaging and Presence Protocol (XMPP): Instant Messaging and Presence
+pub const SESSION: &str = "urn:ietf:params:xml:ns:xmpp-session";
+
 /// RFC 6121: Extensible Messaging and Presence Protocol (XMPP): Instant Messaging and Presence
 pub const ROSTER: &str = "jabber:iq:roster";
+/// RFC 6121: Extensible Messaging and Presence Protocol (XMPP): Instant Messaging and Presence
+pub const PRE_APPROVAL: &str = "urn:xmpp:features:pre-approval";
 
 /// RFC 7395: An Extensible Messaging and Presence Protocol (XMPP) Subprotocol for WebSocket
 pub const WEBSOCKET: &str = "urn:ietf:params:xml:ns:xmpp-framing";
 
+/// XML 1.0: the namespace bound to the `xml:` prefix
+pub const XML: &str = "http://www.w3.org/XML/1998/namespace";
+
 /// XEP-0004: Data Forms
 pub const DATA_FORMS: &str = "jabber:x:data";
 
+/// XEP-0012: Last Activity
+pub const LAST_ACTIVITY: &str = "jabber:iq:last";
+
+/// XEP-0016: Privacy Lists
+pub const PRIVACY: &str = "jabber:iq:privacy";
+
 /// XEP-0030: Service Discovery
 pub const DISCO_INFO: &str = "http://jabber.org/protocol/disco#info";
 /// XEP-0030: Service Discovery
@@ -36,6 +52,10 @@ pub const DISCO_ITEMS: &str = "http://jabber.org/protocol/disco#items";
 pub const MUC: &str = "http://jabber.org/protocol/muc";
 /// XEP-0045: Multi-User Chat
 pub const MUC_USER: &str = "http://jabber.org/protocol/muc#user";
+/// XEP-0045: Multi-User Chat
+pub const MUC_ADMIN: &str = "http://jabber.org/protocol/muc#admin";
+/// XEP-0045: Multi-User Chat
+pub const MUC_OWNER: &str = "http://jabber.org/protocol/muc#owner";
 
 /// XEP-0047: In-Band Bytestreams
 pub const IBB: &str = "http://jabber.org/protocol/ibb";
@@ -43,6 +63,15 @@ pub const IBB: &str = "http://jabber.org/protocol/ibb";
 /// XEP-0048: Bookmarks
 pub const BOOKMARKS: &str = "storage:bookmarks";
 
+/// XEP-0049: Private XML Storage
+pub const PRIVATE: &str = "jabber:iq:private";
+
+/// XEP-0050: Ad-Hoc Commands
+pub const COMMANDS: &str = "http://jabber.org/protocol/commands";
+
+/// XEP-0054: vcard-temp
+pub const VCARD: &str = "vcard-temp";
+
 /// XEP-0059: Result Set Management
 pub const RSM: &str = "http://jabber.org/protocol/rsm";
 
@@ -56,6 +85,14 @@ pub const PUBSUB_EVENT: &str = "http://jabber.org/protocol/pubsub#event";
 pub const PUBSUB_OWNER: &str = "http://jabber.org/protocol/pubsub#owner";
 /// XEP-0060: Publish-Subscribe node configuration
 pub const PUBSUB_CONFIGURE: &str = "http://jabber.org/protocol/pubsub#node_config";
+/// XEP-0060: Publish-Subscribe publish options
+pub const PUBSUB_PUBLISH_OPTIONS: &str = "http://jabber.org/protocol/pubsub#publish-options";
+
+/// XEP-0065: SOCKS5 Bytestreams
+pub const BYTESTREAMS: &str = "http://jabber.org/protocol/bytestreams";
+
+/// XEP-0070: Verifying HTTP Requests via XMPP
+pub const HTTP_AUTH: &str = "http://jabber.org/protocol/http-auth";
 
 /// XEP-0071: XHTML-IM
 pub const XHTML_IM: &str = "http://jabber.org/protocol/xhtml-im";
@@ -64,6 +101,13 @@ pub const XHTML: &str = "http://www.w3.org/1999/xhtml";
 
 /// XEP-0077: In-Band Registration
 pub const REGISTER: &str = "jabber:iq:register";
+/// XEP-0077: In-Band Registration, as a stream feature
+pub const REGISTER_FEATURE: &str = "http://jabber.org/features/iq-register";
+
+/// XEP-0078: Non-SASL Authentication
+pub const IQ_AUTH: &str = "jabber:iq:auth";
+/// XEP-0078: Non-SASL Authentication, as a stream feature
+pub const IQ_AUTH_FEATURE: &str = "http://jabber.org/features/iq-auth";
 
 /// XEP-0084: User Avatar
 pub const AVATAR_DATA: &str = "urn:xmpp:avatar:data";
@@ -76,9 +120,15 @@ pub const CHATSTATES: &str = "http://jabber.org/protocol/chatstates";
 /// XEP-0092: Software Version
 pub const VERSION: &str = "jabber:iq:version";
 
+/// XEP-0103: URL Address Information
+pub const URL_DATA: &str = "http://jabber.org/protocol/url-data";
+
 /// XEP-0107: User Mood
 pub const MOOD: &str = "http://jabber.org/protocol/mood";
 
+/// XEP-0108: User Activity
+pub const ACTIVITY: &str = "http://jabber.org/protocol/activity";
+
 /// XEP-0114: Jabber Component Protocol
 pub const COMPONENT_ACCEPT: &str = "jabber:component:accept";
 
@@ -91,6 +141,12 @@ pub const CAPS: &str = "http://jabber.org/protocol/caps";
 /// XEP-0118: User Tune
 pub const TUNE: &str = "http://jabber.org/protocol/tune";
 
+/// XEP-0133: Service Administration
+pub const ADMIN: &str = "http://jabber.org/protocol/admin";
+
+/// XEP-0153: vCard-Based Avatars
+pub const VCARD_UPDATE: &str = "vcard-temp:x:update";
+
 /// XEP-0157: Contact Addresses for XMPP Services
 pub const SERVER_INFO: &str = "http://jabber.org/network/serverinfo";
 
@@ -121,8 +177,12 @@ pub const BLOCKING: &str = "urn:xmpp:blocking";
 /// XEP-0191: Blocking Command
 pub const BLOCKING_ERRORS: &str = "urn:xmpp:blocking:errors";
 
-/// XEP-0198: Stream Management
-pub const SM: &str = "urn:xmpp:sm:3";
+/// XEP-0198: Stream Management, the version implemented by this crate
+pub const SM: &str = SM_3;
+/// XEP-0198: Stream Management, legacy version 2
+pub const SM_2: &str = "urn:xmpp:sm:2";
+/// XEP-0198: Stream Management, version 3
+pub const SM_3: &str = "urn:xmpp:sm:3";
 
 /// XEP-0199: XMPP Ping
 pub const PING: &str = "urn:xmpp:ping";
@@ -150,15 +210,26 @@ pub const JINGLE_FT: &str = "urn:xmpp:jingle:apps:file-transfer:5";
 /// XEP-0234: Jingle File Transfer
 pub const JINGLE_FT_ERROR: &str = "urn:xmpp:jingle:apps:file-transfer:errors:0";
 
+/// XEP-0237: Roster Versioning
+pub const ROSTER_VER: &str = "urn:xmpp:features:rosterver";
+
 /// XEP-0257: Client Certificate Management for SASL EXTERNAL
 pub const SASL_CERT: &str = "urn:xmpp:saslcert:1";
 
+/// XEP-0258: Security Labels in XMPP
+pub const SEC_LABEL: &str = "urn:xmpp:sec-label:0";
+/// XEP-0258: Security Labels in XMPP
+pub const SEC_LABEL_CATALOG: &str = "urn:xmpp:sec-label:catalog:2";
+
 /// XEP-0260: Jingle SOCKS5 Bytestreams Transport Method
 pub const JINGLE_S5B: &str = "urn:xmpp:jingle:transports:s5b:1";
 
 /// XEP-0261: Jingle In-Band Bytestreams Transport Method
 pub const JINGLE_IBB: &str = "urn:xmpp:jingle:transports:ibb:1";
 
+/// XEP-0264: Jingle Content Thumbnails
+pub const THUMBS: &str = "urn:xmpp:thumbs:1";
+
 /// XEP-0277: Microblogging over XMPP
 pub const MICROBLOG: &str = "urn:xmpp:microblog:0";
 
@@ -195,8 +266,13 @@ pub const RTT: &str = "urn:xmpp:rtt:0";
 /// XEP-0308: Last Message Correction
 pub const MESSAGE_CORRECT: &str = "urn:xmpp:message-correct:0";
 
-/// XEP-0313: Message Archive Management
-pub const MAM: &str = "urn:xmpp:mam:2";
+/// XEP-0313: Message Archive Management, the version implemented by this
+/// crate
+pub const MAM: &str = MAM_2;
+/// XEP-0313: Message Archive Management, version 2
+pub const MAM_2: &str = "urn:xmpp:mam:2";
+/// XEP-0313: Message Archive Management, extended query fields
+pub const MAM_EXTENDED: &str = "urn:xmpp:mam:2#extended";
 
 /// XEP-0319: Last User Interaction in Presence
 pub const IDLE: &str = "urn:xmpp:idle:1";
@@ -207,6 +283,9 @@ pub const JINGLE_DTLS: &str = "urn:xmpp:jingle:apps:dtls:0";
 /// XEP-0328: JID Prep
 pub const JID_PREP: &str = "urn:xmpp:jidprep:0";
 
+/// XEP-0334: Message Processing Hints
+pub const HINTS: &str = "urn:xmpp:hints";
+
 /// XEP-0338: Jingle Grouping Framework
 pub const JINGLE_GROUPING: &str = "urn:xmpp:jingle:apps:grouping:0";
 
@@ -219,6 +298,9 @@ pub const CSI: &str = "urn:xmpp:csi:0";
 /// XEP-0353: Jingle Message Initiation
 pub const JINGLE_MESSAGE: &str = "urn:xmpp:jingle-message:0";
 
+/// XEP-0358: Publishing Available Jingle Sessions
+pub const JINGLEPUB: &str = "urn:xmpp:jinglepub:1";
+
 /// XEP-0359: Unique and Stable Stanza IDs
 pub const SID: &str = "urn:xmpp:sid:0";
 
@@ -241,6 +323,13 @@ pub const MIX_NODES_MESSAGES: &str = "urn:xmpp:mix:nodes:messages";
 pub const MIX_NODES_CONFIG: &str = "urn:xmpp:mix:nodes:config";
 /// XEP-0369: Mediated Information eXchange (MIX)
 pub const MIX_NODES_INFO: &str = "urn:xmpp:mix:nodes:info";
+/// XEP-0369: Mediated Information eXchange (MIX)
+pub const MIX_NODES_ALLOWED: &str = "urn:xmpp:mix:nodes:allowed";
+/// XEP-0369: Mediated Information eXchange (MIX)
+pub const MIX_NODES_BANNED: &str = "urn:xmpp:mix:nodes:banned";
+
+/// XEP-0372: References
+pub const REFERENCE: &str = "urn:xmpp:reference:0";
 
 /// XEP-0373: OpenPGP for XMPP
 pub const OX: &str = "urn:xmpp:openpgp:0";
@@ -256,12 +345,21 @@ pub const LEGACY_OMEMO: &str = "eu.siacs.conversations.axolotl";
 pub const LEGACY_OMEMO_DEVICELIST: &str = "eu.siacs.conversations.axolotl.devicelist";
 /// XEP-0384: OMEMO Encryption (experimental version 0.3.0)
 pub const LEGACY_OMEMO_BUNDLES: &str = "eu.siacs.conversations.axolotl.bundles";
+/// XEP-0384: OMEMO Encryption (version 0.8.0 onwards)
+pub const OMEMO_2: &str = "urn:xmpp:omemo:2";
+/// XEP-0384: OMEMO Encryption (version 0.8.0 onwards)
+pub const OMEMO_2_DEVICES: &str = "urn:xmpp:omemo:2:devices";
+/// XEP-0384: OMEMO Encryption (version 0.8.0 onwards)
+pub const OMEMO_2_BUNDLES: &str = "urn:xmpp:omemo:2:bundles";
 
 /// XEP-0390: Entity Capabilities 2.0
 pub const ECAPS2: &str = "urn:xmpp:caps";
 /// XEP-0390: Entity Capabilities 2.0
 pub const ECAPS2_OPTIMIZE: &str = "urn:xmpp:caps:optimize";
 
+/// XEP-0393: Message Styling
+pub const STYLING: &str = "urn:xmpp:styling:0";
+
 /// XEP-0402: PEP Native Bookmarks
 pub const BOOKMARKS2: &str = "urn:xmpp:bookmarks:1";
 /// XEP-0402: PEP Native Bookmarks
@@ -269,9 +367,27 @@ pub const BOOKMARKS2_COMPAT: &str = "urn:xmpp:bookmarks:1#compat";
 /// XEP-0402: PEP Native Bookmarks
 pub const BOOKMARKS2_COMPAT_PEP: &str = "urn:xmpp:bookmarks:1#compat-pep";
 
+/// XEP-0405: Mediated Information eXchange (MIX): Participant Server Requirements
+pub const MIX_PAM: &str = "urn:xmpp:mix:pam:2";
+
+/// XEP-0406: Mediated Information eXchange (MIX): MIX Administration
+pub const MIX_ADMIN: &str = "urn:xmpp:mix:admin:0";
+
 /// XEP-0421: Anonymous unique occupant identifiers for MUCs
 pub const OID: &str = "urn:xmpp:occupant-id:0";
 
+/// XEP-0428: Fallback Indication
+pub const FALLBACK: &str = "urn:xmpp:fallback:0";
+
+/// XEP-0444: Message Reactions
+pub const REACTIONS: &str = "urn:xmpp:reactions:0";
+
+/// XEP-0446: File metadata element
+pub const FILE_METADATA: &str = "urn:xmpp:file:metadata:0";
+
+/// XEP-0447: Stateless file sharing
+pub const SFS: &str = "urn:xmpp:sfs:0";
+
 /// Alias for the main namespace of the stream, that is "jabber:client" when
 /// the component feature isn’t enabled.
 #[cfg(not(feature = "component"))]
@@ -4,8 +4,11 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
-use crate::message::MessagePayload;
-use crate::presence::PresencePayload;
+use crate::message::{Message, MessagePayload};
+use crate::ns;
+use crate::presence::{Presence, PresencePayload};
+use crate::Element;
+use std::convert::TryFrom;
 
 generate_element!(
     /// Unique identifier given to a MUC participant.
@@ -23,12 +26,39 @@ generate_element!(
 impl MessagePayload for OccupantId {}
 impl PresencePayload for OccupantId {}
 
+fn find_occupant_id(payloads: &[Element]) -> Option<OccupantId> {
+    payloads
+        .iter()
+        .find(|payload| payload.is("occupant-id", ns::OID))
+        .and_then(|payload| OccupantId::try_from(payload.clone()).ok())
+}
+
+impl Message {
+    /// The occupant id the room attached to this message.
+    ///
+    /// It can only be trusted if the room advertises the
+    /// `urn:xmpp:occupant-id:0` feature, as the room then replaces any id
+    /// set by the sender.
+    pub fn occupant_id(&self) -> Option<OccupantId> {
+        find_occupant_id(&self.payloads)
+    }
+}
+
+impl Presence {
+    /// The occupant id the room attached to this presence.
+    ///
+    /// It can only be trusted if the room advertises the
+    /// `urn:xmpp:occupant-id:0` feature, as the room then replaces any id
+    /// set by the sender.
+    pub fn occupant_id(&self) -> Option<OccupantId> {
+        find_occupant_id(&self.payloads)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::util::error::Error;
-    use crate::Element;
-    use std::convert::TryFrom;
 
     #[cfg(target_pointer_width = "32")]
     #[test]
@@ -88,4 +118,22 @@ mod tests {
         let elem2 = occupant_id.into();
         assert_eq!(elem, elem2);
     }
+
+    #[test]
+    fn test_from_stanzas() {
+        let elem: Element = "<presence xmlns='jabber:client' from='coven@chat.shakespeare.lit/thirdwitch'><x xmlns='http://jabber.org/protocol/muc#user'><item affiliation='none' role='participant'/></x><occupant-id xmlns='urn:xmpp:occupant-id:0' id='dd72603deec90a38ba552f7c68cbcc61bca202cd'/></presence>"
+            .parse()
+            .unwrap();
+        let presence = Presence::try_from(elem).unwrap();
+        assert_eq!(
+            presence.occupant_id().unwrap().id,
+            "dd72603deec90a38ba552f7c68cbcc61bca202cd"
+        );
+
+        let elem: Element = "<message xmlns='jabber:client' type='groupchat'><body>Harpier cries: 'tis time, 'tis time.</body></message>"
+            .parse()
+            .unwrap();
+        let message = Message::try_from(elem).unwrap();
+        assert_eq!(message.occupant_id(), None);
+    }
 }
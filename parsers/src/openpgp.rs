@@ -5,8 +5,14 @@
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
 use crate::date::DateTime;
+use crate::message::MessagePayload;
+use crate::ns;
 use crate::pubsub::PubSubPayload;
+use crate::util::error::Error;
 use crate::util::helpers::Base64;
+use crate::Element;
+use jid::Jid;
+use std::convert::TryFrom;
 
 // TODO: Merge this container with the PubKey struct
 generate_element!(
@@ -46,7 +52,7 @@ generate_element!(
 
 generate_element!(
     /// List of public key metadata
-    PubKeysMeta, "public-key-list", OX,
+    PubKeysMeta, "public-keys-list", OX,
     children: [
         /// Public keys
         pubkeys: Vec<PubKeyMeta> = ("pubkey-metadata", OX) => PubKeyMeta
@@ -55,10 +61,202 @@ generate_element!(
 
 impl PubSubPayload for PubKeysMeta {}
 
+generate_element!(
+    /// An OpenPGP message, containing an encrypted and/or signed content
+    /// element.
+    OpenPgp, "openpgp", OX,
+    text: (
+        /// The OpenPGP message, to decrypt and/or verify into a
+        /// [`Content`].
+        data: Base64<Vec<u8>>
+    )
+);
+
+impl MessagePayload for OpenPgp {}
+
+/// Whether the content gets signed, encrypted, or both.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentKind {
+    /// Signed and encrypted, as used for instant messaging.
+    SignCrypt,
+
+    /// Only signed.
+    Sign,
+
+    /// Only encrypted.
+    Crypt,
+}
+
+impl ContentKind {
+    fn name(self) -> &'static str {
+        match self {
+            ContentKind::SignCrypt => "signcrypt",
+            ContentKind::Sign => "sign",
+            ContentKind::Crypt => "crypt",
+        }
+    }
+}
+
+/// The content of an OpenPGP message, once decrypted and/or verified.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Content {
+    /// Whether this content got signed, encrypted, or both.
+    pub kind: ContentKind,
+
+    /// The intended recipients, to check against the recipients of the
+    /// stanza.
+    pub to: Vec<Jid>,
+
+    /// When this content got created.
+    pub time: DateTime,
+
+    /// Random padding, hiding the length of the payload.
+    pub rpad: Option<String>,
+
+    /// The payloads to add to the stanza once decrypted.
+    pub payload: Vec<Element>,
+}
+
+impl Content {
+    /// Creates a content to sign and encrypt for these recipients.
+    pub fn signcrypt(to: Vec<Jid>, time: DateTime, payload: Vec<Element>) -> Content {
+        Content {
+            kind: ContentKind::SignCrypt,
+            to,
+            time,
+            rpad: None,
+            payload,
+        }
+    }
+
+    /// Creates a content to only sign, for these recipients.
+    pub fn sign(to: Vec<Jid>, time: DateTime, payload: Vec<Element>) -> Content {
+        Content {
+            kind: ContentKind::Sign,
+            to,
+            time,
+            rpad: None,
+            payload,
+        }
+    }
+
+    /// Creates a content to only encrypt.
+    pub fn crypt(time: DateTime, payload: Vec<Element>) -> Content {
+        Content {
+            kind: ContentKind::Crypt,
+            to: Vec::new(),
+            time,
+            rpad: None,
+            payload,
+        }
+    }
+
+    /// Sets the random padding of this content.
+    pub fn with_rpad<S: Into<String>>(mut self, rpad: S) -> Content {
+        self.rpad = Some(rpad.into());
+        self
+    }
+}
+
+impl TryFrom<Element> for Content {
+    type Error = Error;
+
+    fn try_from(elem: Element) -> Result<Content, Error> {
+        let kind = if elem.is("signcrypt", ns::OX) {
+            ContentKind::SignCrypt
+        } else if elem.is("sign", ns::OX) {
+            ContentKind::Sign
+        } else if elem.is("crypt", ns::OX) {
+            ContentKind::Crypt
+        } else {
+            return Err(Error::ParseError("This is not an OpenPGP content element."));
+        };
+        check_no_attributes!(elem, "content");
+        let mut to = Vec::new();
+        let mut time = None;
+        let mut rpad = None;
+        let mut payload = None;
+        for child in elem.children() {
+            if child.is("to", ns::OX) {
+                check_no_children!(child, "to");
+                check_no_unknown_attributes!(child, "to", ["jid"]);
+                to.push(get_attr!(child, "jid", Required));
+            } else if child.is("time", ns::OX) {
+                if time.is_some() {
+                    return Err(Error::ParseError(
+                        "Content must not have more than one time.",
+                    ));
+                }
+                check_no_children!(child, "time");
+                check_no_unknown_attributes!(child, "time", ["stamp"]);
+                time = Some(get_attr!(child, "stamp", Required));
+            } else if child.is("rpad", ns::OX) {
+                if rpad.is_some() {
+                    return Err(Error::ParseError(
+                        "Content must not have more than one rpad.",
+                    ));
+                }
+                check_no_children!(child, "rpad");
+                check_no_attributes!(child, "rpad");
+                rpad = Some(child.text());
+            } else if child.is("payload", ns::OX) {
+                if payload.is_some() {
+                    return Err(Error::ParseError(
+                        "Content must not have more than one payload.",
+                    ));
+                }
+                check_no_attributes!(child, "payload");
+                payload = Some(child.children().cloned().collect());
+            } else {
+                return Err(Error::ParseError("Unknown child in content element."));
+            }
+        }
+        if kind != ContentKind::Crypt && to.is_empty() {
+            return Err(Error::ParseError(
+                "Signed content must have at least one recipient.",
+            ));
+        }
+        Ok(Content {
+            kind,
+            to,
+            time: time.ok_or(Error::ParseError("Missing time in content element."))?,
+            rpad,
+            payload: payload.ok_or(Error::ParseError("Missing payload in content element."))?,
+        })
+    }
+}
+
+impl From<Content> for Element {
+    fn from(content: Content) -> Element {
+        Element::builder(content.kind.name(), ns::OX)
+            .append_all(
+                content
+                    .to
+                    .into_iter()
+                    .map(|jid| Element::builder("to", ns::OX).attr("jid", jid).build()),
+            )
+            .append(
+                Element::builder("time", ns::OX)
+                    .attr("stamp", content.time)
+                    .build(),
+            )
+            .append_all(
+                content
+                    .rpad
+                    .map(|rpad| Element::builder("rpad", ns::OX).append(rpad).build()),
+            )
+            .append(
+                Element::builder("payload", ns::OX)
+                    .append_all(content.payload)
+                    .build(),
+            )
+            .build()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::ns;
     use crate::pubsub::{
         pubsub::{Item as PubSubItem, Publish},
         Item, NodeName,
@@ -116,4 +314,76 @@ mod tests {
         let serialized: Element = pubkey.into();
         assert_eq!(serialized, reference);
     }
+
+    #[test]
+    fn test_public_keys_list() {
+        let elem: Element = "<public-keys-list xmlns='urn:xmpp:openpgp:0'><pubkey-metadata v4-fingerprint='1357B01865B2503C18453D208CAC2A9678548E35' date='2018-03-01T15:26:12+00:00'/></public-keys-list>"
+            .parse()
+            .unwrap();
+        let list = PubKeysMeta::try_from(elem.clone()).unwrap();
+        assert_eq!(list.pubkeys.len(), 1);
+        assert_eq!(
+            list.pubkeys[0].v4fingerprint,
+            "1357B01865B2503C18453D208CAC2A9678548E35"
+        );
+        assert_eq!(Element::from(list), elem);
+    }
+
+    #[test]
+    fn test_openpgp() {
+        let elem: Element = "<openpgp xmlns='urn:xmpp:openpgp:0'>AAAA</openpgp>"
+            .parse()
+            .unwrap();
+        let openpgp = OpenPgp::try_from(elem.clone()).unwrap();
+        assert_eq!(openpgp.data, b"\0\0\0");
+        assert_eq!(Element::from(openpgp), elem);
+    }
+
+    #[test]
+    fn test_signcrypt() {
+        let elem: Element = "<signcrypt xmlns='urn:xmpp:openpgp:0'><to jid='juliet@example.org'/><time stamp='2014-07-10T17:06:00+02:00'/><rpad>f0rm1l4n4-mT8y33j!Y%fRSrcd^ZE4Q7VDt1L%WEgR!kv</rpad><payload><body xmlns='jabber:client'>This is a secret message.</body></payload></signcrypt>"
+            .parse()
+            .unwrap();
+        let content = Content::try_from(elem.clone()).unwrap();
+        assert_eq!(content.kind, ContentKind::SignCrypt);
+        assert_eq!(content.to, [Jid::from_str("juliet@example.org").unwrap()]);
+        assert_eq!(
+            content.rpad.as_deref(),
+            Some("f0rm1l4n4-mT8y33j!Y%fRSrcd^ZE4Q7VDt1L%WEgR!kv")
+        );
+        assert_eq!(content.payload.len(), 1);
+        assert!(content.payload[0].is("body", ns::JABBER_CLIENT));
+        assert_eq!(Element::from(content.clone()), elem);
+
+        let generated = Content::signcrypt(
+            vec![Jid::from_str("juliet@example.org").unwrap()],
+            content.time.clone(),
+            content.payload.clone(),
+        )
+        .with_rpad("f0rm1l4n4-mT8y33j!Y%fRSrcd^ZE4Q7VDt1L%WEgR!kv");
+        assert_eq!(generated, content);
+    }
+
+    #[test]
+    fn test_invalid_content() {
+        let elem: Element = "<sign xmlns='urn:xmpp:openpgp:0'><time stamp='2014-07-10T17:06:00+02:00'/><payload/></sign>"
+            .parse()
+            .unwrap();
+        let error = Content::try_from(elem).unwrap_err();
+        let message = match error {
+            Error::ParseError(string) => string,
+            _ => panic!(),
+        };
+        assert_eq!(message, "Signed content must have at least one recipient.");
+
+        let elem: Element = "<crypt xmlns='urn:xmpp:openpgp:0'><payload/></crypt>"
+            .parse()
+            .unwrap();
+        let error = Content::try_from(elem).unwrap_err();
+        let message = match error {
+            Error::ParseError(string) => string,
+            _ => panic!(),
+        };
+        assert_eq!(message, "Missing time in content element.");
+    }
 }
@@ -5,6 +5,7 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
+use crate::lang;
 use crate::ns;
 use crate::util::error::Error;
 use jid::Jid;
@@ -238,6 +239,12 @@ impl Presence {
         self.statuses.insert(lang.into(), status.into());
     }
 
+    /// Returns the best matching status from a list of languages, see
+    /// [lang::get_best()](../lang/fn.get_best.html).
+    pub fn get_best_status(&self, preferred_langs: Vec<&str>) -> Option<(Lang, &Status)> {
+        lang::get_best(&self.statuses, &preferred_langs)
+    }
+
     /// Add a payload to this presence.
     pub fn add_payload<P: PresencePayload>(&mut self, payload: P) {
         self.payloads.push(payload.into());
@@ -0,0 +1,464 @@
+// Copyright (c) 2022 Emmanuel Gil Peyrot <linkmauve@linkmauve.fr>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use crate::iq::{IqGetPayload, IqResultPayload, IqSetPayload};
+use crate::ns;
+use crate::roster::Subscription;
+use crate::util::error::Error;
+use crate::Element;
+use jid::Jid;
+use std::convert::TryFrom;
+
+generate_attribute!(
+    /// What to do with the stanzas matching an item.
+    Action, "action", {
+        /// Let these stanzas through.
+        Allow => "allow",
+
+        /// Block these stanzas.
+        Deny => "deny",
+    }
+);
+
+/// Which entities an item applies to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Match {
+    /// Entities matching this JID, a bare JID or a domain matching all of
+    /// the entities under it.
+    Jid(Jid),
+
+    /// Contacts in this roster group.
+    Group(String),
+
+    /// Contacts with this subscription state, never `Remove`.
+    Subscription(Subscription),
+}
+
+/// A rule of a privacy list.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Item {
+    /// The entities this item applies to, or `None` for all of them.
+    pub match_: Option<Match>,
+
+    /// What to do with the stanzas matching this item.
+    pub action: Action,
+
+    /// The position of this item in its list, the lowest one being
+    /// processed first.
+    pub order: u32,
+
+    /// Whether this item applies to incoming iqs.
+    pub iq: bool,
+
+    /// Whether this item applies to incoming messages.
+    pub message: bool,
+
+    /// Whether this item applies to incoming presences.
+    pub presence_in: bool,
+
+    /// Whether this item applies to outgoing presences.
+    pub presence_out: bool,
+}
+
+impl Item {
+    /// Creates an item applying to all kinds of stanzas.
+    pub fn new(match_: Option<Match>, action: Action, order: u32) -> Item {
+        Item {
+            match_,
+            action,
+            order,
+            iq: false,
+            message: false,
+            presence_in: false,
+            presence_out: false,
+        }
+    }
+
+    /// Restricts this item to incoming iqs, along with the other kinds it
+    /// already got restricted to.
+    pub fn with_iq(mut self) -> Item {
+        self.iq = true;
+        self
+    }
+
+    /// Restricts this item to incoming messages, along with the other
+    /// kinds it already got restricted to.
+    pub fn with_message(mut self) -> Item {
+        self.message = true;
+        self
+    }
+
+    /// Restricts this item to incoming presences, along with the other
+    /// kinds it already got restricted to.
+    pub fn with_presence_in(mut self) -> Item {
+        self.presence_in = true;
+        self
+    }
+
+    /// Restricts this item to outgoing presences, along with the other
+    /// kinds it already got restricted to.
+    pub fn with_presence_out(mut self) -> Item {
+        self.presence_out = true;
+        self
+    }
+
+    /// Whether this item applies to every kind of stanza, which is the
+    /// case when it didn’t get restricted to any.
+    pub fn applies_to_all_stanzas(&self) -> bool {
+        !(self.iq || self.message || self.presence_in || self.presence_out)
+    }
+}
+
+impl TryFrom<Element> for Item {
+    type Error = Error;
+
+    fn try_from(elem: Element) -> Result<Item, Error> {
+        check_self!(elem, "item", PRIVACY);
+        check_no_unknown_attributes!(elem, "item", ["type", "value", "action", "order"]);
+        let type_: Option<String> = get_attr!(elem, "type", Option);
+        let value: Option<String> = get_attr!(elem, "value", Option);
+        let match_ = match (type_.as_deref(), value) {
+            (None, None) => None,
+            (Some("jid"), Some(value)) => Some(Match::Jid(value.parse()?)),
+            (Some("group"), Some(value)) => Some(Match::Group(value)),
+            (Some("subscription"), Some(value)) => match value.parse()? {
+                Subscription::Remove => {
+                    return Err(Error::ParseError(
+                        "Invalid subscription value in privacy item.",
+                    ))
+                }
+                subscription => Some(Match::Subscription(subscription)),
+            },
+            (Some("jid"), None) | (Some("group"), None) | (Some("subscription"), None) => {
+                return Err(Error::ParseError("Missing value in privacy item."))
+            }
+            (None, Some(_)) => return Err(Error::ParseError("Missing type in privacy item.")),
+            (Some(_), _) => return Err(Error::ParseError("Unknown type in privacy item.")),
+        };
+        let mut item = Item::new(
+            match_,
+            get_attr!(elem, "action", Required),
+            get_attr!(elem, "order", Required),
+        );
+        for child in elem.children() {
+            let kind = if child.is("iq", ns::PRIVACY) {
+                &mut item.iq
+            } else if child.is("message", ns::PRIVACY) {
+                &mut item.message
+            } else if child.is("presence-in", ns::PRIVACY) {
+                &mut item.presence_in
+            } else if child.is("presence-out", ns::PRIVACY) {
+                &mut item.presence_out
+            } else {
+                return Err(Error::ParseError("Unknown child in privacy item."));
+            };
+            if *kind {
+                return Err(Error::ParseError(
+                    "Privacy item must not have the same stanza kind twice.",
+                ));
+            }
+            check_no_attributes!(child, "stanza kind");
+            check_no_children!(child, "stanza kind");
+            *kind = true;
+        }
+        Ok(item)
+    }
+}
+
+impl From<Item> for Element {
+    fn from(item: Item) -> Element {
+        let (type_, value) = match item.match_ {
+            None => (None, None),
+            Some(Match::Jid(jid)) => (Some("jid"), Some(String::from(jid))),
+            Some(Match::Group(group)) => (Some("group"), Some(group)),
+            Some(Match::Subscription(subscription)) => {
+                let subscription = match subscription {
+                    Subscription::None => "none",
+                    Subscription::From => "from",
+                    Subscription::To => "to",
+                    Subscription::Both => "both",
+                    Subscription::Remove => "remove",
+                };
+                (Some("subscription"), Some(String::from(subscription)))
+            }
+        };
+        let kinds = [
+            ("iq", item.iq),
+            ("message", item.message),
+            ("presence-in", item.presence_in),
+            ("presence-out", item.presence_out),
+        ];
+        Element::builder("item", ns::PRIVACY)
+            .attr("type", type_)
+            .attr("value", value)
+            .attr("action", item.action)
+            .attr("order", item.order)
+            .append_all(
+                kinds
+                    .iter()
+                    .filter(|(_, enabled)| *enabled)
+                    .map(|(name, _)| Element::builder(*name, ns::PRIVACY)),
+            )
+            .build()
+    }
+}
+
+generate_element!(
+    /// A named list of rules.
+    List, "list", PRIVACY,
+    attributes: [
+        /// The name of this list.
+        name: Required<String> = "name",
+    ],
+    children: [
+        /// The rules of this list, empty when only requesting it or
+        /// removing it.
+        items: Vec<Item> = ("item", PRIVACY) => Item
+    ]
+);
+
+impl List {
+    /// Creates an empty list of this name, to request or remove it.
+    pub fn new<N: Into<String>>(name: N) -> List {
+        List {
+            name: name.into(),
+            items: Vec::new(),
+        }
+    }
+
+    /// Appends a rule to this list.
+    pub fn with_item(mut self, item: Item) -> List {
+        self.items.push(item);
+        self
+    }
+
+    /// The rules of this list, in the order they get processed.
+    pub fn sorted_items(&self) -> Vec<&Item> {
+        let mut items: Vec<_> = self.items.iter().collect();
+        items.sort_by_key(|item| item.order);
+        items
+    }
+}
+
+generate_element!(
+    /// The list active for the current session, or no name to decline the
+    /// use of any.
+    Active, "active", PRIVACY,
+    attributes: [
+        /// The name of the active list.
+        name: Option<String> = "name",
+    ]
+);
+
+generate_element!(
+    /// The list applying to the sessions without an active one, or no name
+    /// to decline the use of any.
+    DefaultList, "default", PRIVACY,
+    attributes: [
+        /// The name of the default list.
+        name: Option<String> = "name",
+    ]
+);
+
+generate_element!(
+    /// Requests or sets the privacy lists of the user, or the result of
+    /// such a request.
+    Query, "query", PRIVACY,
+    children: [
+        /// The active list.
+        active: Option<Active> = ("active", PRIVACY) => Active,
+
+        /// The default list.
+        default: Option<DefaultList> = ("default", PRIVACY) => DefaultList,
+
+        /// The lists, only with their name when requesting all of them.
+        lists: Vec<List> = ("list", PRIVACY) => List
+    ]
+);
+
+impl IqGetPayload for Query {}
+impl IqSetPayload for Query {}
+impl IqResultPayload for Query {}
+
+impl Query {
+    /// Requests the names of all of the lists, along with the active and
+    /// default ones.
+    pub fn request_names() -> Query {
+        Query {
+            active: None,
+            default: None,
+            lists: Vec::new(),
+        }
+    }
+
+    /// Requests, sets, or removes when empty, this list.
+    pub fn list(list: List) -> Query {
+        Query {
+            active: None,
+            default: None,
+            lists: vec![list],
+        }
+    }
+
+    /// Sets the list active for the current session, or declines the use of
+    /// any when `None`.
+    pub fn set_active(name: Option<String>) -> Query {
+        Query {
+            active: Some(Active { name }),
+            default: None,
+            lists: Vec::new(),
+        }
+    }
+
+    /// Sets the default list, or declines the use of any when `None`.
+    pub fn set_default(name: Option<String>) -> Query {
+        Query {
+            active: None,
+            default: Some(DefaultList { name }),
+            lists: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jid::BareJid;
+
+    #[cfg(target_pointer_width = "32")]
+    #[test]
+    fn test_size() {
+        assert_size!(Action, 1);
+        assert_size!(Match, 36);
+        assert_size!(Item, 48);
+        assert_size!(List, 24);
+        assert_size!(Active, 12);
+        assert_size!(DefaultList, 12);
+        assert_size!(Query, 36);
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    fn test_size() {
+        assert_size!(Action, 1);
+        assert_size!(Match, 72);
+        assert_size!(Item, 88);
+        assert_size!(List, 48);
+        assert_size!(Active, 24);
+        assert_size!(DefaultList, 24);
+        assert_size!(Query, 72);
+    }
+
+    #[test]
+    fn test_names() {
+        let elem: Element = "<query xmlns='jabber:iq:privacy'/>".parse().unwrap();
+        let query = Query::try_from(elem.clone()).unwrap();
+        assert_eq!(query, Query::request_names());
+        assert_eq!(Element::from(query), elem);
+
+        let elem: Element = "<query xmlns='jabber:iq:privacy'><active name='private'/><default name='public'/><list name='public'/><list name='private'/><list name='special'/></query>"
+            .parse()
+            .unwrap();
+        let query = Query::try_from(elem).unwrap();
+        assert_eq!(query.active.unwrap().name.as_deref(), Some("private"));
+        assert_eq!(query.default.unwrap().name.as_deref(), Some("public"));
+        assert_eq!(query.lists.len(), 3);
+        assert_eq!(query.lists[2], List::new("special"));
+    }
+
+    #[test]
+    fn test_list() {
+        let elem: Element = "<query xmlns='jabber:iq:privacy'><list name='public'><item type='jid' value='tybalt@example.com' action='deny' order='1'/><item type='subscription' value='both' action='allow' order='3'><message/><presence-in/></item><item type='group' value='Enemies' action='deny' order='2'><presence-out/></item><item action='allow' order='4'/></list></query>"
+            .parse()
+            .unwrap();
+        let query = Query::try_from(elem.clone()).unwrap();
+        let list = &query.lists[0];
+        assert_eq!(list.name, "public");
+        assert_eq!(
+            list.items[0],
+            Item::new(
+                Some(Match::Jid(Jid::Bare(BareJid::new("tybalt", "example.com")))),
+                Action::Deny,
+                1
+            )
+        );
+        assert!(list.items[0].applies_to_all_stanzas());
+        assert_eq!(
+            list.items[1],
+            Item::new(
+                Some(Match::Subscription(Subscription::Both)),
+                Action::Allow,
+                3
+            )
+            .with_message()
+            .with_presence_in()
+        );
+        assert_eq!(
+            list.items[2].match_,
+            Some(Match::Group(String::from("Enemies")))
+        );
+        assert!(list.items[2].presence_out);
+        assert_eq!(list.items[3].match_, None);
+        let orders: Vec<_> = list.sorted_items().iter().map(|item| item.order).collect();
+        assert_eq!(orders, [1, 2, 3, 4]);
+        assert_eq!(Element::from(query), elem);
+    }
+
+    #[test]
+    fn test_active_default() {
+        let elem: Element = "<query xmlns='jabber:iq:privacy'><active/></query>"
+            .parse()
+            .unwrap();
+        let query = Query::try_from(elem.clone()).unwrap();
+        assert_eq!(query, Query::set_active(None));
+        assert_eq!(Element::from(query), elem);
+
+        let elem: Element = "<query xmlns='jabber:iq:privacy'><default name='public'/></query>"
+            .parse()
+            .unwrap();
+        let query = Query::try_from(elem.clone()).unwrap();
+        assert_eq!(query, Query::set_default(Some(String::from("public"))));
+        assert_eq!(Element::from(query), elem);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let elem: Element =
+            "<item xmlns='jabber:iq:privacy' type='subscription' value='remove' action='deny' order='1'/>"
+                .parse()
+                .unwrap();
+        let error = Item::try_from(elem).unwrap_err();
+        let message = match error {
+            Error::ParseError(string) => string,
+            _ => panic!(),
+        };
+        assert_eq!(message, "Invalid subscription value in privacy item.");
+
+        let elem: Element = "<item xmlns='jabber:iq:privacy' type='jid' action='deny' order='1'/>"
+            .parse()
+            .unwrap();
+        let error = Item::try_from(elem).unwrap_err();
+        let message = match error {
+            Error::ParseError(string) => string,
+            _ => panic!(),
+        };
+        assert_eq!(message, "Missing value in privacy item.");
+
+        let elem: Element =
+            "<item xmlns='jabber:iq:privacy' action='deny' order='1'><iq/><iq/></item>"
+                .parse()
+                .unwrap();
+        let error = Item::try_from(elem).unwrap_err();
+        let message = match error {
+            Error::ParseError(string) => string,
+            _ => panic!(),
+        };
+        assert_eq!(
+            message,
+            "Privacy item must not have the same stanza kind twice."
+        );
+    }
+}
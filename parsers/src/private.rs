@@ -0,0 +1,90 @@
+// Copyright (c) 2022 Emmanuel Gil Peyrot <linkmauve@linkmauve.fr>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use crate::iq::{IqGetPayload, IqResultPayload, IqSetPayload};
+use crate::ns;
+use crate::util::error::Error;
+use crate::Element;
+use std::convert::TryFrom;
+
+/// Stores or retrieves a private XML payload on the server of the user.
+///
+/// In a get, the payload is an empty element naming what to retrieve, for
+/// instance `<storage xmlns='storage:bookmarks'/>`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query {
+    /// The stored payload.
+    pub payload: Element,
+}
+
+impl IqGetPayload for Query {}
+impl IqSetPayload for Query {}
+impl IqResultPayload for Query {}
+
+impl Query {
+    /// Create a query around this payload.
+    pub fn new<P: Into<Element>>(payload: P) -> Query {
+        Query {
+            payload: payload.into(),
+        }
+    }
+}
+
+impl TryFrom<Element> for Query {
+    type Error = Error;
+
+    fn try_from(elem: Element) -> Result<Query, Error> {
+        check_self!(elem, "query", PRIVATE);
+        check_no_attributes!(elem, "query");
+        let mut children = elem.children();
+        let payload = match children.next() {
+            Some(payload) => payload.clone(),
+            None => return Err(Error::ParseError("Private query without a payload.")),
+        };
+        if children.next().is_some() {
+            return Err(Error::ParseError(
+                "Private query with more than one payload.",
+            ));
+        }
+        Ok(Query { payload })
+    }
+}
+
+impl From<Query> for Element {
+    fn from(query: Query) -> Element {
+        Element::builder("query", ns::PRIVATE)
+            .append(query.payload)
+            .build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bookmarks::Storage;
+
+    #[test]
+    fn test_bookmarks() {
+        let elem: Element =
+            "<query xmlns='jabber:iq:private'><storage xmlns='storage:bookmarks'/></query>"
+                .parse()
+                .unwrap();
+        let query = Query::try_from(elem.clone()).unwrap();
+        Storage::try_from(query.payload).unwrap();
+        assert_eq!(Element::from(Query::new(Storage::new())), elem);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let elem: Element = "<query xmlns='jabber:iq:private'/>".parse().unwrap();
+        let error = Query::try_from(elem).unwrap_err();
+        let message = match error {
+            Error::ParseError(string) => string,
+            _ => panic!(),
+        };
+        assert_eq!(message, "Private query without a payload.");
+    }
+}
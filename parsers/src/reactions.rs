@@ -0,0 +1,256 @@
+// Copyright (c) 2022 Emmanuel Gil Peyrot <linkmauve@linkmauve.fr>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use crate::message::MessagePayload;
+use crate::ns;
+use crate::util::error::Error;
+use crate::Element;
+use jid::Jid;
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Sets the reactions of the sender to a message, replacing any previous
+/// reactions they sent to this same message.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reactions {
+    /// The id of the message being reacted to.
+    pub id: String,
+
+    /// The emojis used as reactions, each one being a single grapheme
+    /// cluster.  An empty list removes all previous reactions.
+    pub emojis: Vec<String>,
+}
+
+impl MessagePayload for Reactions {}
+
+impl Reactions {
+    /// Create a new set of reactions to the message `id`.
+    pub fn new<I: Into<String>>(id: I, emojis: Vec<String>) -> Reactions {
+        Reactions {
+            id: id.into(),
+            emojis,
+        }
+    }
+}
+
+fn is_single_grapheme(text: &str) -> bool {
+    let mut graphemes = text.graphemes(true);
+    graphemes.next().is_some() && graphemes.next().is_none()
+}
+
+impl TryFrom<Element> for Reactions {
+    type Error = Error;
+
+    fn try_from(elem: Element) -> Result<Reactions, Error> {
+        check_self!(elem, "reactions", REACTIONS);
+        check_no_unknown_attributes!(elem, "reactions", ["id"]);
+        let id = get_attr!(elem, "id", Required);
+        let mut emojis = Vec::new();
+        for child in elem.children() {
+            if !child.is("reaction", ns::REACTIONS) {
+                return Err(Error::ParseError("Unknown child in reactions element."));
+            }
+            check_no_children!(child, "reaction");
+            check_no_attributes!(child, "reaction");
+            let emoji = child.text();
+            if !is_single_grapheme(&emoji) {
+                return Err(Error::ParseError(
+                    "Reaction is not a single grapheme cluster.",
+                ));
+            }
+            emojis.push(emoji);
+        }
+        Ok(Reactions { id, emojis })
+    }
+}
+
+impl From<Reactions> for Element {
+    fn from(reactions: Reactions) -> Element {
+        Element::builder("reactions", ns::REACTIONS)
+            .attr("id", reactions.id)
+            .append_all(
+                reactions
+                    .emojis
+                    .into_iter()
+                    .map(|emoji| Element::builder("reaction", ns::REACTIONS).append(emoji)),
+            )
+            .build()
+    }
+}
+
+/// Keeps track of the latest reactions of every sender, for every message.
+#[derive(Debug, Clone, Default)]
+pub struct ReactionsAggregate {
+    messages: HashMap<String, HashMap<Jid, Vec<String>>>,
+}
+
+impl ReactionsAggregate {
+    /// Create an empty aggregate.
+    pub fn new() -> ReactionsAggregate {
+        ReactionsAggregate::default()
+    }
+
+    /// Record the reactions received from `from`, replacing the ones it
+    /// previously sent to the same message.
+    pub fn add(&mut self, from: Jid, reactions: Reactions) {
+        let Reactions { id, mut emojis } = reactions;
+        emojis.sort();
+        emojis.dedup();
+        if emojis.is_empty() {
+            if let Some(senders) = self.messages.get_mut(&id) {
+                senders.remove(&from);
+                if senders.is_empty() {
+                    self.messages.remove(&id);
+                }
+            }
+        } else {
+            self.messages.entry(id).or_default().insert(from, emojis);
+        }
+    }
+
+    /// The current reactions of `from` to the message `id`.
+    pub fn get(&self, id: &str, from: &Jid) -> Option<&[String]> {
+        self.messages
+            .get(id)
+            .and_then(|senders| senders.get(from))
+            .map(Vec::as_slice)
+    }
+
+    /// Count how many senders reacted with each emoji to the message `id`.
+    pub fn counts(&self, id: &str) -> BTreeMap<&str, usize> {
+        let mut counts = BTreeMap::new();
+        if let Some(senders) = self.messages.get(id) {
+            for emoji in senders.values().flatten() {
+                *counts.entry(emoji.as_str()).or_insert(0) += 1;
+            }
+        }
+        counts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[cfg(target_pointer_width = "32")]
+    #[test]
+    fn test_size() {
+        assert_size!(Reactions, 24);
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    fn test_size() {
+        assert_size!(Reactions, 48);
+    }
+
+    #[test]
+    fn test_simple() {
+        let elem: Element = "<reactions xmlns='urn:xmpp:reactions:0' id='744f6e18-a57a-11e9-a656-4889e7820c76'><reaction>👋</reaction><reaction>🐢</reaction></reactions>"
+            .parse()
+            .unwrap();
+        let reactions = Reactions::try_from(elem).unwrap();
+        assert_eq!(reactions.id, "744f6e18-a57a-11e9-a656-4889e7820c76");
+        assert_eq!(reactions.emojis, ["👋", "🐢"]);
+    }
+
+    #[test]
+    fn test_empty() {
+        let elem: Element = "<reactions xmlns='urn:xmpp:reactions:0' id='foo'/>"
+            .parse()
+            .unwrap();
+        let reactions = Reactions::try_from(elem).unwrap();
+        assert!(reactions.emojis.is_empty());
+    }
+
+    #[test]
+    fn test_multi_codepoint_grapheme() {
+        // Woman technologist, medium skin tone, a ZWJ sequence.
+        let elem: Element =
+            "<reactions xmlns='urn:xmpp:reactions:0' id='foo'><reaction>👩🏽‍💻</reaction></reactions>"
+                .parse()
+                .unwrap();
+        let reactions = Reactions::try_from(elem).unwrap();
+        assert_eq!(reactions.emojis, ["👩🏽‍💻"]);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let elem: Element = "<reactions xmlns='urn:xmpp:reactions:0' id='foo'><reaction>👋🐢</reaction></reactions>"
+            .parse()
+            .unwrap();
+        let error = Reactions::try_from(elem).unwrap_err();
+        let message = match error {
+            Error::ParseError(string) => string,
+            _ => panic!(),
+        };
+        assert_eq!(message, "Reaction is not a single grapheme cluster.");
+
+        let elem: Element =
+            "<reactions xmlns='urn:xmpp:reactions:0' id='foo'><reaction/></reactions>"
+                .parse()
+                .unwrap();
+        let error = Reactions::try_from(elem).unwrap_err();
+        let message = match error {
+            Error::ParseError(string) => string,
+            _ => panic!(),
+        };
+        assert_eq!(message, "Reaction is not a single grapheme cluster.");
+
+        let elem: Element = "<reactions xmlns='urn:xmpp:reactions:0'/>".parse().unwrap();
+        let error = Reactions::try_from(elem).unwrap_err();
+        let message = match error {
+            Error::ParseError(string) => string,
+            _ => panic!(),
+        };
+        assert_eq!(message, "Required attribute 'id' missing.");
+    }
+
+    #[test]
+    fn test_serialise() {
+        let reactions = Reactions::new("foo", vec![String::from("🐢")]);
+        let elem: Element = reactions.into();
+        let elem2: Element =
+            "<reactions xmlns='urn:xmpp:reactions:0' id='foo'><reaction>🐢</reaction></reactions>"
+                .parse()
+                .unwrap();
+        assert_eq!(elem, elem2);
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let alice = Jid::from_str("alice@example.org/a").unwrap();
+        let bob = Jid::from_str("bob@example.org/b").unwrap();
+        let mut aggregate = ReactionsAggregate::new();
+        aggregate.add(
+            alice.clone(),
+            Reactions::new("foo", vec![String::from("👋"), String::from("🐢")]),
+        );
+        aggregate.add(bob.clone(), Reactions::new("foo", vec![String::from("🐢")]));
+        aggregate.add(bob.clone(), Reactions::new("bar", vec![String::from("👋")]));
+
+        let counts = aggregate.counts("foo");
+        assert_eq!(counts.len(), 2);
+        assert_eq!(counts["🐢"], 2);
+        assert_eq!(counts["👋"], 1);
+
+        // A new set of reactions replaces the previous one.
+        aggregate.add(
+            alice.clone(),
+            Reactions::new("foo", vec![String::from("👋")]),
+        );
+        assert_eq!(aggregate.get("foo", &alice).unwrap(), ["👋"]);
+        assert_eq!(aggregate.counts("foo")["🐢"], 1);
+
+        // And an empty one removes them all.
+        aggregate.add(bob.clone(), Reactions::new("foo", vec![]));
+        assert_eq!(aggregate.get("foo", &bob), None);
+        assert_eq!(aggregate.counts("foo").len(), 1);
+        assert_eq!(aggregate.get("bar", &bob).unwrap(), ["👋"]);
+    }
+}
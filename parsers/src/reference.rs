@@ -0,0 +1,231 @@
+// Copyright (c) 2022 Emmanuel Gil Peyrot <linkmauve@linkmauve.fr>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use crate::message::MessagePayload;
+use crate::ns;
+use crate::util::error::Error;
+use crate::Element;
+use std::convert::TryFrom;
+use std::ops::Range;
+
+generate_attribute!(
+    /// What the reference points to.
+    Type, "type", {
+        /// An entity, usually a user, being mentioned.
+        Mention => "mention",
+
+        /// Some data, for instance an attached file.
+        Data => "data",
+    }
+);
+
+/// A reference from a range of the body to an URI.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reference {
+    /// What this reference points to.
+    pub type_: Type,
+
+    /// The URI being referenced, for instance `xmpp:juliet@capulet.lit`.
+    pub uri: String,
+
+    /// The index of the first character of the range, counted in code
+    /// points.
+    pub begin: Option<usize>,
+
+    /// The index of the character after the range, counted in code points.
+    pub end: Option<usize>,
+
+    /// The URI of another part of the stanza this reference applies to.
+    pub anchor: Option<String>,
+
+    /// Extra payloads describing the referenced data.
+    pub payloads: Vec<Element>,
+}
+
+impl MessagePayload for Reference {}
+
+impl Reference {
+    /// Creates a reference to `uri`, of this type.
+    pub fn new<U: Into<String>>(type_: Type, uri: U) -> Reference {
+        Reference {
+            type_,
+            uri: uri.into(),
+            begin: None,
+            end: None,
+            anchor: None,
+            payloads: Vec::new(),
+        }
+    }
+
+    /// Creates a mention of `uri` at this range of the body.
+    pub fn mention<U: Into<String>>(uri: U, range: Range<usize>) -> Reference {
+        Reference::new(Type::Mention, uri).with_range(range)
+    }
+
+    /// Anchors this reference to a range of the body, counted in code
+    /// points.
+    pub fn with_range(mut self, range: Range<usize>) -> Reference {
+        self.begin = Some(range.start);
+        self.end = Some(range.end);
+        self
+    }
+
+    /// Sets the URI of the part of the stanza this reference applies to.
+    pub fn with_anchor<A: Into<String>>(mut self, anchor: A) -> Reference {
+        self.anchor = Some(anchor.into());
+        self
+    }
+
+    /// The range of the body this reference is anchored to, counted in code
+    /// points, if both of its ends are known.
+    pub fn range(&self) -> Option<Range<usize>> {
+        match (self.begin, self.end) {
+            (Some(begin), Some(end)) if begin <= end => Some(begin..end),
+            _ => None,
+        }
+    }
+
+    /// The part of `body` this reference is anchored to, if its range fits
+    /// in it.
+    pub fn slice<'a>(&self, body: &'a str) -> Option<&'a str> {
+        let range = self.range()?;
+        let mut offsets = body
+            .char_indices()
+            .map(|(offset, _)| offset)
+            .chain(std::iter::once(body.len()));
+        let start = offsets.nth(range.start)?;
+        let end = if range.is_empty() {
+            start
+        } else {
+            offsets.nth(range.end - range.start - 1)?
+        };
+        Some(&body[start..end])
+    }
+}
+
+impl TryFrom<Element> for Reference {
+    type Error = Error;
+
+    fn try_from(elem: Element) -> Result<Reference, Error> {
+        check_self!(elem, "reference", REFERENCE);
+        check_no_unknown_attributes!(elem, "reference", ["type", "uri", "begin", "end", "anchor"]);
+        let reference = Reference {
+            type_: get_attr!(elem, "type", Required),
+            uri: get_attr!(elem, "uri", Required),
+            begin: get_attr!(elem, "begin", Option),
+            end: get_attr!(elem, "end", Option),
+            anchor: get_attr!(elem, "anchor", Option),
+            payloads: elem.children().cloned().collect(),
+        };
+        if let (Some(begin), Some(end)) = (reference.begin, reference.end) {
+            if begin > end {
+                return Err(Error::ParseError(
+                    "The begin of a reference must not be after its end.",
+                ));
+            }
+        }
+        Ok(reference)
+    }
+}
+
+impl From<Reference> for Element {
+    fn from(reference: Reference) -> Element {
+        Element::builder("reference", ns::REFERENCE)
+            .attr("type", reference.type_)
+            .attr("uri", reference.uri)
+            .attr("begin", reference.begin)
+            .attr("end", reference.end)
+            .attr("anchor", reference.anchor)
+            .append_all(reference.payloads)
+            .build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(target_pointer_width = "32")]
+    #[test]
+    fn test_size() {
+        assert_size!(Type, 1);
+        assert_size!(Reference, 56);
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    fn test_size() {
+        assert_size!(Type, 1);
+        assert_size!(Reference, 112);
+    }
+
+    #[test]
+    fn test_mention() {
+        let elem: Element = "<reference xmlns='urn:xmpp:reference:0' begin='72' end='78' type='mention' uri='xmpp:juliet@capulet.lit'/>"
+            .parse()
+            .unwrap();
+        let reference = Reference::try_from(elem.clone()).unwrap();
+        assert_eq!(reference.type_, Type::Mention);
+        assert_eq!(reference.uri, "xmpp:juliet@capulet.lit");
+        assert_eq!(reference.range(), Some(72..78));
+        assert_eq!(
+            reference,
+            Reference::mention("xmpp:juliet@capulet.lit", 72..78)
+        );
+        assert_eq!(Element::from(reference), elem);
+    }
+
+    #[test]
+    fn test_data() {
+        let elem: Element = "<reference xmlns='urn:xmpp:reference:0' type='data' uri='https://download.montague.lit/summit.jpg'><media-sharing xmlns='urn:xmpp:sims:1'/></reference>"
+            .parse()
+            .unwrap();
+        let reference = Reference::try_from(elem.clone()).unwrap();
+        assert_eq!(reference.type_, Type::Data);
+        assert_eq!(reference.range(), None);
+        assert_eq!(reference.payloads.len(), 1);
+        assert_eq!(Element::from(reference), elem);
+    }
+
+    #[test]
+    fn test_slice() {
+        let body = "Héllo Juliet, ça va ?";
+        let reference = Reference::mention("xmpp:juliet@capulet.lit", 6..12);
+        assert_eq!(reference.slice(body), Some("Juliet"));
+        let reference = Reference::mention("xmpp:juliet@capulet.lit", 14..16);
+        assert_eq!(reference.slice(body), Some("ça"));
+        let reference = Reference::mention("xmpp:juliet@capulet.lit", 21..21);
+        assert_eq!(reference.slice(body), Some(""));
+        let reference = Reference::mention("xmpp:juliet@capulet.lit", 20..22);
+        assert_eq!(reference.slice(body), None);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let elem: Element = "<reference xmlns='urn:xmpp:reference:0' type='mention'/>"
+            .parse()
+            .unwrap();
+        let error = Reference::try_from(elem).unwrap_err();
+        let message = match error {
+            Error::ParseError(string) => string,
+            _ => panic!(),
+        };
+        assert_eq!(message, "Required attribute 'uri' missing.");
+
+        let elem: Element = "<reference xmlns='urn:xmpp:reference:0' type='mention' uri='xmpp:juliet@capulet.lit' begin='5' end='2'/>"
+            .parse()
+            .unwrap();
+        let error = Reference::try_from(elem).unwrap_err();
+        let message = match error {
+            Error::ParseError(string) => string,
+            _ => panic!(),
+        };
+        assert_eq!(
+            message,
+            "The begin of a reference must not be after its end."
+        );
+    }
+}
@@ -11,7 +11,7 @@ use std::convert::TryFrom;
 
 /// Requests paging through a potentially big set of items (represented by an
 /// UID).
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Default)]
 pub struct SetQuery {
     /// Limit the number of items, or use the recipient’s defaults if None.
     pub max: Option<usize>,
@@ -28,6 +28,42 @@ pub struct SetQuery {
     pub index: Option<usize>,
 }
 
+impl SetQuery {
+    /// Request the first page of the set, of at most `max` items.
+    pub fn first_page(max: Option<usize>) -> SetQuery {
+        SetQuery {
+            max,
+            ..SetQuery::default()
+        }
+    }
+
+    /// Request the last page of the set, of at most `max` items.
+    pub fn last_page(max: Option<usize>) -> SetQuery {
+        SetQuery {
+            max,
+            before: Some(String::new()),
+            ..SetQuery::default()
+        }
+    }
+
+    /// Request the page following the item `after`.
+    pub fn with_after<S: Into<String>>(mut self, after: S) -> SetQuery {
+        self.after = Some(after.into());
+        self
+    }
+
+    /// Request the page preceding the item `before`.
+    pub fn with_before<S: Into<String>>(mut self, before: S) -> SetQuery {
+        self.before = Some(before.into());
+        self
+    }
+
+    /// Request only the number of items in the set, without any of them.
+    pub fn count_only() -> SetQuery {
+        SetQuery::first_page(Some(0))
+    }
+}
+
 impl TryFrom<Element> for SetQuery {
     type Error = Error;
 
@@ -92,7 +128,7 @@ impl From<SetQuery> for Element {
 }
 
 /// Describes the paging result of a [query](struct.SetQuery.html).
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Default)]
 pub struct SetResult {
     /// The UID of the first item of the page.
     pub first: Option<String>,
@@ -108,6 +144,32 @@ pub struct SetResult {
     pub count: Option<usize>,
 }
 
+impl SetResult {
+    /// Whether this page contains no item at all.
+    pub fn is_empty(&self) -> bool {
+        self.first.is_none() && self.last.is_none()
+    }
+
+    /// The query for the page following this one, of at most `max` items,
+    /// or `None` if this page was empty.
+    pub fn next_page(&self, max: Option<usize>) -> Option<SetQuery> {
+        self.last
+            .as_ref()
+            .map(|last| SetQuery::first_page(max).with_after(last.clone()))
+    }
+
+    /// The query for the page preceding this one, of at most `max` items,
+    /// or `None` if this page was empty or the first one.
+    pub fn previous_page(&self, max: Option<usize>) -> Option<SetQuery> {
+        if self.first_index == Some(0) {
+            return None;
+        }
+        self.first
+            .as_ref()
+            .map(|first| SetQuery::first_page(max).with_before(first.clone()))
+    }
+}
+
 impl TryFrom<Element> for SetResult {
     type Error = Error;
 
@@ -299,4 +361,45 @@ mod tests {
         let elem2 = set2.into();
         assert_eq!(elem1, elem2);
     }
+
+    #[test]
+    fn test_pages() {
+        let elem: Element =
+            "<set xmlns='http://jabber.org/protocol/rsm'><max>10</max><before/></set>"
+                .parse()
+                .unwrap();
+        let elem2 = SetQuery::last_page(Some(10)).into();
+        assert_eq!(elem, elem2);
+
+        let elem: Element = "<set xmlns='http://jabber.org/protocol/rsm'><max>0</max></set>"
+            .parse()
+            .unwrap();
+        let elem2 = SetQuery::count_only().into();
+        assert_eq!(elem, elem2);
+
+        let result = SetResult {
+            first: Some(String::from("a")),
+            first_index: Some(0),
+            last: Some(String::from("j")),
+            count: Some(20),
+        };
+        assert_eq!(
+            result.next_page(Some(10)),
+            Some(SetQuery::first_page(Some(10)).with_after("j"))
+        );
+        assert_eq!(result.previous_page(Some(10)), None);
+
+        let result = SetResult {
+            first_index: Some(10),
+            ..result
+        };
+        assert_eq!(
+            result.previous_page(None),
+            Some(SetQuery::first_page(None).with_before("a"))
+        );
+
+        let result = SetResult::default();
+        assert!(result.is_empty());
+        assert_eq!(result.next_page(Some(10)), None);
+    }
 }
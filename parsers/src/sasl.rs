@@ -4,6 +4,7 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
+use crate::lang;
 use crate::ns;
 use crate::util::error::Error;
 use crate::util::helpers::Base64;
@@ -150,6 +151,14 @@ pub struct Failure {
     pub texts: BTreeMap<Lang, String>,
 }
 
+impl Failure {
+    /// Returns the best matching text from a list of languages, see
+    /// [lang::get_best()](../lang/fn.get_best.html).
+    pub fn get_best_text(&self, preferred_langs: Vec<&str>) -> Option<(Lang, &String)> {
+        lang::get_best(&self.texts, &preferred_langs)
+    }
+}
+
 impl TryFrom<Element> for Failure {
     type Error = Error;
 
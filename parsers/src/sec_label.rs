@@ -0,0 +1,326 @@
+// Copyright (c) 2022 Emmanuel Gil Peyrot <linkmauve@linkmauve.fr>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use crate::iq::{IqGetPayload, IqResultPayload};
+use crate::message::MessagePayload;
+use crate::ns;
+use crate::util::error::Error;
+use crate::util::helpers::Text;
+use crate::Element;
+use jid::Jid;
+use std::convert::TryFrom;
+
+generate_element!(
+    /// The marking to display along a labelled stanza.
+    DisplayMarking, "displaymarking", SEC_LABEL,
+    attributes: [
+        /// The foreground colour to display the marking with.
+        fgcolor: Option<String> = "fgcolor",
+
+        /// The background colour to display the marking with.
+        bgcolor: Option<String> = "bgcolor",
+    ],
+    text: (
+        /// The marking itself, for instance “SECRET”.
+        marking: Text<String>
+    )
+);
+
+/// A security label attached to a stanza, telling how its content has to be
+/// handled.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SecurityLabel {
+    /// The marking to display to the user.
+    pub display_marking: Option<DisplayMarking>,
+
+    /// The label itself, in a format defined by the security policy, for
+    /// instance an ESS security label.
+    pub label: Element,
+
+    /// Labels from other policies, equivalent to this one.
+    pub equivalent_labels: Vec<Element>,
+}
+
+impl MessagePayload for SecurityLabel {}
+
+impl SecurityLabel {
+    /// Creates a security label from its policy-specific payload.
+    pub fn new(label: Element) -> SecurityLabel {
+        SecurityLabel {
+            display_marking: None,
+            label,
+            equivalent_labels: Vec::new(),
+        }
+    }
+
+    /// Sets the marking to display to the user.
+    pub fn with_display_marking(mut self, display_marking: DisplayMarking) -> SecurityLabel {
+        self.display_marking = Some(display_marking);
+        self
+    }
+
+    /// Adds a label from another policy, equivalent to this one.
+    pub fn add_equivalent_label(mut self, label: Element) -> SecurityLabel {
+        self.equivalent_labels.push(label);
+        self
+    }
+}
+
+fn parse_label_payload(elem: &Element) -> Result<Element, Error> {
+    let mut children = elem.children();
+    match (children.next(), children.next()) {
+        (Some(payload), None) => Ok(payload.clone()),
+        (None, _) => Err(Error::ParseError("Missing payload in label element.")),
+        (Some(_), Some(_)) => Err(Error::ParseError(
+            "Label element must not have more than one payload.",
+        )),
+    }
+}
+
+impl TryFrom<Element> for SecurityLabel {
+    type Error = Error;
+
+    fn try_from(elem: Element) -> Result<SecurityLabel, Error> {
+        check_self!(elem, "securitylabel", SEC_LABEL);
+        check_no_attributes!(elem, "securitylabel");
+        let mut display_marking = None;
+        let mut label = None;
+        let mut equivalent_labels = Vec::new();
+        for child in elem.children() {
+            if child.is("displaymarking", ns::SEC_LABEL) {
+                if display_marking.is_some() {
+                    return Err(Error::ParseError(
+                        "Security label must not have more than one displaymarking.",
+                    ));
+                }
+                display_marking = Some(DisplayMarking::try_from(child.clone())?);
+            } else if child.is("label", ns::SEC_LABEL) {
+                if label.is_some() {
+                    return Err(Error::ParseError(
+                        "Security label must not have more than one label.",
+                    ));
+                }
+                check_no_attributes!(child, "label");
+                label = Some(parse_label_payload(child)?);
+            } else if child.is("equivalentlabel", ns::SEC_LABEL) {
+                check_no_attributes!(child, "equivalentlabel");
+                equivalent_labels.push(parse_label_payload(child)?);
+            } else {
+                return Err(Error::ParseError("Unknown child in securitylabel element."));
+            }
+        }
+        Ok(SecurityLabel {
+            display_marking,
+            label: label.ok_or(Error::ParseError("Missing label in securitylabel element."))?,
+            equivalent_labels,
+        })
+    }
+}
+
+impl From<SecurityLabel> for Element {
+    fn from(security_label: SecurityLabel) -> Element {
+        Element::builder("securitylabel", ns::SEC_LABEL)
+            .append_all(security_label.display_marking)
+            .append(
+                Element::builder("label", ns::SEC_LABEL)
+                    .append(security_label.label)
+                    .build(),
+            )
+            .append_all(security_label.equivalent_labels.into_iter().map(|label| {
+                Element::builder("equivalentlabel", ns::SEC_LABEL)
+                    .append(label)
+                    .build()
+            }))
+            .build()
+    }
+}
+
+generate_attribute!(
+    /// Whether the client may only use the labels from this catalog.
+    Restrict,
+    "restrict",
+    bool
+);
+
+generate_attribute!(
+    /// Whether this item is the one to select by default.
+    IsDefault,
+    "default",
+    bool
+);
+
+generate_element!(
+    /// A security label the user can select.
+    Item, "item", SEC_LABEL_CATALOG,
+    attributes: [
+        /// The name to display in the selection list, with `|` separating
+        /// the levels of a hierarchical menu.
+        selector: Option<String> = "selector",
+
+        /// Whether this item is the one to select by default.
+        default: Default<IsDefault> = "default",
+    ],
+    children: [
+        /// The label, or `None` to send the stanza without any label.
+        security_label: Option<SecurityLabel> = ("securitylabel", SEC_LABEL) => SecurityLabel
+    ]
+);
+
+generate_element!(
+    /// Asks a server for the catalog of security labels to use when sending
+    /// stanzas to an entity, or the returned catalog.
+    Catalog, "catalog", SEC_LABEL_CATALOG,
+    attributes: [
+        /// The entity the stanzas will be sent to.
+        to: Option<Jid> = "to",
+
+        /// The entity this catalog is provided by.
+        from: Option<Jid> = "from",
+
+        /// The name of this catalog.
+        name: Option<String> = "name",
+
+        /// A description of this catalog.
+        desc: Option<String> = "desc",
+
+        /// An identifier of this catalog.
+        id: Option<String> = "id",
+
+        /// The total number of items in this catalog.
+        size: Option<u32> = "size",
+
+        /// The index of the first returned item, when paging.
+        index: Option<u32> = "index",
+
+        /// Whether the client may only use the labels from this catalog.
+        restrict: Default<Restrict> = "restrict",
+    ],
+    children: [
+        /// The labels the user can select.
+        items: Vec<Item> = ("item", SEC_LABEL_CATALOG) => Item
+    ]
+);
+
+impl IqGetPayload for Catalog {}
+impl IqResultPayload for Catalog {}
+
+impl Catalog {
+    /// Creates a request for the catalog of labels to use when sending
+    /// stanzas to `to`.
+    pub fn request(to: Jid) -> Catalog {
+        Catalog {
+            to: Some(to),
+            from: None,
+            name: None,
+            desc: None,
+            id: None,
+            size: None,
+            index: None,
+            restrict: Restrict::False,
+            items: Vec::new(),
+        }
+    }
+
+    /// The item to select by default, if any.
+    pub fn default_item(&self) -> Option<&Item> {
+        self.items
+            .iter()
+            .find(|item| item.default == IsDefault::True)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[cfg(target_pointer_width = "32")]
+    #[test]
+    fn test_size() {
+        assert_size!(DisplayMarking, 36);
+        assert_size!(SecurityLabel, 132);
+        assert_size!(Restrict, 1);
+        assert_size!(IsDefault, 1);
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    fn test_size() {
+        assert_size!(DisplayMarking, 72);
+        assert_size!(SecurityLabel, 248);
+        assert_size!(Restrict, 1);
+        assert_size!(IsDefault, 1);
+    }
+
+    #[test]
+    fn test_simple() {
+        let elem: Element = "<securitylabel xmlns='urn:xmpp:sec-label:0'><displaymarking fgcolor='black' bgcolor='red'>SECRET</displaymarking><label><esssecuritylabel xmlns='urn:xmpp:sec-label:ess:0'>MQYCAQQGASk=</esssecuritylabel></label><equivalentlabel><esssecuritylabel xmlns='urn:xmpp:sec-label:ess:0'>MRUCAgD9DA9BcXVhIChvYnNvbGV0ZSk=</esssecuritylabel></equivalentlabel></securitylabel>"
+            .parse()
+            .unwrap();
+        let security_label = SecurityLabel::try_from(elem.clone()).unwrap();
+        let display_marking = security_label.display_marking.clone().unwrap();
+        assert_eq!(display_marking.marking, "SECRET");
+        assert_eq!(display_marking.fgcolor.as_deref(), Some("black"));
+        assert_eq!(display_marking.bgcolor.as_deref(), Some("red"));
+        assert!(security_label
+            .label
+            .is("esssecuritylabel", "urn:xmpp:sec-label:ess:0"));
+        assert_eq!(security_label.label.text(), "MQYCAQQGASk=");
+        assert_eq!(security_label.equivalent_labels.len(), 1);
+        assert_eq!(Element::from(security_label), elem);
+    }
+
+    #[test]
+    fn test_catalog() {
+        let elem: Element = "<catalog xmlns='urn:xmpp:sec-label:catalog:2' to='example.com'/>"
+            .parse()
+            .unwrap();
+        let request = Catalog::try_from(elem.clone()).unwrap();
+        assert_eq!(
+            request,
+            Catalog::request(Jid::from_str("example.com").unwrap())
+        );
+        assert_eq!(Element::from(request), elem);
+
+        let elem: Element = "<catalog xmlns='urn:xmpp:sec-label:catalog:2' to='example.com' name='Default' desc='an example set of labels' restrict='true'><item selector='Classified|SECRET'><securitylabel xmlns='urn:xmpp:sec-label:0'><displaymarking fgcolor='black' bgcolor='red'>SECRET</displaymarking><label><esssecuritylabel xmlns='urn:xmpp:sec-label:ess:0'>MQYCAQQGASk=</esssecuritylabel></label></securitylabel></item><item selector='Unclassified|UNCLASSIFIED' default='1'/></catalog>"
+            .parse()
+            .unwrap();
+        let catalog = Catalog::try_from(elem).unwrap();
+        assert_eq!(catalog.name.as_deref(), Some("Default"));
+        assert_eq!(catalog.restrict, Restrict::True);
+        assert_eq!(catalog.items.len(), 2);
+        assert!(catalog.items[0].security_label.is_some());
+        let default = catalog.default_item().unwrap();
+        assert_eq!(
+            default.selector.as_deref(),
+            Some("Unclassified|UNCLASSIFIED")
+        );
+        assert!(default.security_label.is_none());
+    }
+
+    #[test]
+    fn test_invalid() {
+        let elem: Element = "<securitylabel xmlns='urn:xmpp:sec-label:0'><displaymarking>SECRET</displaymarking></securitylabel>"
+            .parse()
+            .unwrap();
+        let error = SecurityLabel::try_from(elem).unwrap_err();
+        let message = match error {
+            Error::ParseError(string) => string,
+            _ => panic!(),
+        };
+        assert_eq!(message, "Missing label in securitylabel element.");
+
+        let elem: Element = "<securitylabel xmlns='urn:xmpp:sec-label:0'><label/></securitylabel>"
+            .parse()
+            .unwrap();
+        let error = SecurityLabel::try_from(elem).unwrap_err();
+        let message = match error {
+            Error::ParseError(string) => string,
+            _ => panic!(),
+        };
+        assert_eq!(message, "Missing payload in label element.");
+    }
+}
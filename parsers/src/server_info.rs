@@ -26,10 +26,26 @@ pub struct ServerInfo {
     /// Security addresses
     pub security: Vec<String>,
 
+    /// Status addresses
+    pub status: Vec<String>,
+
     /// Support addresses
     pub support: Vec<String>,
 }
 
+impl ServerInfo {
+    /// Whether no address at all has been provided.
+    pub fn is_empty(&self) -> bool {
+        self.abuse.is_empty()
+            && self.admin.is_empty()
+            && self.feedback.is_empty()
+            && self.sales.is_empty()
+            && self.security.is_empty()
+            && self.status.is_empty()
+            && self.support.is_empty()
+    }
+}
+
 impl TryFrom<DataForm> for ServerInfo {
     type Error = Error;
 
@@ -45,20 +61,16 @@ impl TryFrom<DataForm> for ServerInfo {
             if field.type_ != FieldType::ListMulti {
                 return Err(Error::ParseError("Field is not of the required type."));
             }
-            if field.var == "abuse-addresses" {
-                server_info.abuse = field.values;
-            } else if field.var == "admin-addresses" {
-                server_info.admin = field.values;
-            } else if field.var == "feedback-addresses" {
-                server_info.feedback = field.values;
-            } else if field.var == "sales-addresses" {
-                server_info.sales = field.values;
-            } else if field.var == "security-addresses" {
-                server_info.security = field.values;
-            } else if field.var == "support-addresses" {
-                server_info.support = field.values;
-            } else {
-                return Err(Error::ParseError("Unknown form field var."));
+            match field.var.as_str() {
+                "abuse-addresses" => server_info.abuse = field.values,
+                "admin-addresses" => server_info.admin = field.values,
+                "feedback-addresses" => server_info.feedback = field.values,
+                "sales-addresses" => server_info.sales = field.values,
+                "security-addresses" => server_info.security = field.values,
+                "status-addresses" => server_info.status = field.values,
+                "support-addresses" => server_info.support = field.values,
+                // Ignore the addresses registered after this implementation.
+                _ => (),
             }
         }
 
@@ -79,6 +91,7 @@ impl From<ServerInfo> for DataForm {
                 generate_address_field("feedback-addresses", server_info.feedback),
                 generate_address_field("sales-addresses", server_info.sales),
                 generate_address_field("security-addresses", server_info.security),
+                generate_address_field("status-addresses", server_info.status),
                 generate_address_field("support-addresses", server_info.support),
             ],
         }
@@ -106,13 +119,13 @@ mod tests {
     #[cfg(target_pointer_width = "32")]
     #[test]
     fn test_size() {
-        assert_size!(ServerInfo, 72);
+        assert_size!(ServerInfo, 84);
     }
 
     #[cfg(target_pointer_width = "64")]
     #[test]
     fn test_size() {
-        assert_size!(ServerInfo, 144);
+        assert_size!(ServerInfo, 168);
     }
 
     #[test]
@@ -200,6 +213,7 @@ mod tests {
                 String::from("xmpp:security@foo.bar"),
                 String::from("mailto:security@foo.bar"),
             ],
+            status: vec![],
             support: vec![String::from("mailto:support@foo.bar")],
         };
 
@@ -0,0 +1,260 @@
+// Copyright (c) 2022 Emmanuel Gil Peyrot <linkmauve@linkmauve.fr>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use crate::file_metadata::File;
+use crate::message::MessagePayload;
+use crate::ns;
+use crate::util::error::Error;
+use crate::Element;
+use jid::Jid;
+use std::convert::TryFrom;
+
+generate_attribute!(
+    /// How the recipient should present the shared file.
+    Disposition, "disposition", {
+        /// Display the file along the message, for instance an image.
+        Inline => "inline",
+
+        /// Only offer to download the file.
+        Attachment => "attachment",
+    }
+);
+
+generate_element!(
+    /// A URL the file can be downloaded from (XEP-0103).
+    UrlData, "url-data", URL_DATA,
+    attributes: [
+        /// The URL of the file.
+        target: Required<String> = "target",
+    ]
+);
+
+generate_element!(
+    /// A file published by an entity, to retrieve with Jingle (XEP-0358).
+    JinglePub, "jinglepub", JINGLEPUB,
+    attributes: [
+        /// The entity publishing the file.
+        from: Required<Jid> = "from",
+
+        /// The identifier of the publication.
+        id: Required<String> = "id",
+    ],
+    children: [
+        /// The Jingle File Transfer description to request the file with.
+        description: Option<Element> = ("description", JINGLE_FT) => Element
+    ]
+);
+
+/// Where the file can be retrieved from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    /// From a URL, usually HTTP.
+    UrlData(UrlData),
+
+    /// From an entity, with Jingle.
+    JinglePub(Box<JinglePub>),
+}
+
+impl TryFrom<Element> for Source {
+    type Error = Error;
+
+    fn try_from(elem: Element) -> Result<Source, Error> {
+        Ok(if elem.is("url-data", ns::URL_DATA) {
+            Source::UrlData(UrlData::try_from(elem)?)
+        } else if elem.is("jinglepub", ns::JINGLEPUB) {
+            Source::JinglePub(Box::new(JinglePub::try_from(elem)?))
+        } else {
+            return Err(Error::ParseError("Unknown source in sources element."));
+        })
+    }
+}
+
+impl From<Source> for Element {
+    fn from(source: Source) -> Element {
+        match source {
+            Source::UrlData(url_data) => url_data.into(),
+            Source::JinglePub(jinglepub) => (*jinglepub).into(),
+        }
+    }
+}
+
+/// Shares a file in a message, along with its metadata and where to
+/// retrieve it from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileSharing {
+    /// How the recipient should present the file.
+    pub disposition: Option<Disposition>,
+
+    /// An identifier, to attach more sources to this file later.
+    pub id: Option<String>,
+
+    /// The metadata of the file.
+    pub file: File,
+
+    /// Where the file can be retrieved from, in order of preference.
+    pub sources: Vec<Source>,
+}
+
+impl MessagePayload for FileSharing {}
+
+impl FileSharing {
+    /// Shares this file, downloadable from `url`.
+    pub fn new<U: Into<String>>(file: File, url: U) -> FileSharing {
+        FileSharing {
+            disposition: None,
+            id: None,
+            file,
+            sources: vec![Source::UrlData(UrlData { target: url.into() })],
+        }
+    }
+
+    /// Sets how the recipient should present the file.
+    pub fn with_disposition(mut self, disposition: Disposition) -> FileSharing {
+        self.disposition = Some(disposition);
+        self
+    }
+
+    /// Adds another source for this file.
+    pub fn add_source(mut self, source: Source) -> FileSharing {
+        self.sources.push(source);
+        self
+    }
+
+    /// The URLs the file can be downloaded from, for instance to fill an
+    /// out of band data (XEP-0066) fallback.
+    pub fn urls(&self) -> impl Iterator<Item = &str> {
+        self.sources.iter().filter_map(|source| match source {
+            Source::UrlData(url_data) => Some(url_data.target.as_str()),
+            Source::JinglePub(_) => None,
+        })
+    }
+}
+
+impl TryFrom<Element> for FileSharing {
+    type Error = Error;
+
+    fn try_from(elem: Element) -> Result<FileSharing, Error> {
+        check_self!(elem, "file-sharing", SFS);
+        check_no_unknown_attributes!(elem, "file-sharing", ["disposition", "id"]);
+        let mut file = None;
+        let mut sources = None;
+        for child in elem.children() {
+            if child.is("file", ns::FILE_METADATA) {
+                if file.is_some() {
+                    return Err(Error::ParseError(
+                        "File sharing must not have more than one file.",
+                    ));
+                }
+                file = Some(File::try_from(child.clone())?);
+            } else if child.is("sources", ns::SFS) {
+                if sources.is_some() {
+                    return Err(Error::ParseError(
+                        "File sharing must not have more than one sources.",
+                    ));
+                }
+                check_no_attributes!(child, "sources");
+                sources = Some(
+                    child
+                        .children()
+                        .cloned()
+                        .map(Source::try_from)
+                        .collect::<Result<Vec<_>, _>>()?,
+                );
+            } else {
+                return Err(Error::ParseError("Unknown child in file-sharing element."));
+            }
+        }
+        Ok(FileSharing {
+            disposition: get_attr!(elem, "disposition", Option),
+            id: get_attr!(elem, "id", Option),
+            file: file.ok_or(Error::ParseError("Missing file in file-sharing element."))?,
+            sources: sources.ok_or(Error::ParseError(
+                "Missing sources in file-sharing element.",
+            ))?,
+        })
+    }
+}
+
+impl From<FileSharing> for Element {
+    fn from(sharing: FileSharing) -> Element {
+        Element::builder("file-sharing", ns::SFS)
+            .attr("disposition", sharing.disposition)
+            .attr("id", sharing.id)
+            .append(sharing.file)
+            .append(
+                Element::builder("sources", ns::SFS)
+                    .append_all(sharing.sources)
+                    .build(),
+            )
+            .build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_simple() {
+        let elem: Element = "<file-sharing xmlns='urn:xmpp:sfs:0' disposition='inline'><file xmlns='urn:xmpp:file:metadata:0'><media-type>image/jpeg</media-type><name>summit.jpg</name><size>3032449</size></file><sources><url-data xmlns='http://jabber.org/protocol/url-data' target='https://download.montague.lit/4a771ac1-f0b2-4a4a-9700-f2a26fa2bb67/summit.jpg'/><jinglepub xmlns='urn:xmpp:jinglepub:1' from='romeo@montague.lit/resource' id='9559976B-3FBF-4E7E-B457-2DAA225972BB'><description xmlns='urn:xmpp:jingle:apps:file-transfer:5'/></jinglepub></sources></file-sharing>"
+            .parse()
+            .unwrap();
+        let sharing = FileSharing::try_from(elem.clone()).unwrap();
+        assert_eq!(sharing.disposition, Some(Disposition::Inline));
+        assert_eq!(sharing.file.name.as_deref(), Some("summit.jpg"));
+        assert_eq!(sharing.sources.len(), 2);
+        match sharing.sources[1] {
+            Source::JinglePub(ref jinglepub) => {
+                assert_eq!(
+                    jinglepub.from,
+                    Jid::from_str("romeo@montague.lit/resource").unwrap()
+                );
+                assert!(jinglepub.description.is_some());
+            }
+            _ => panic!(),
+        }
+        let urls: Vec<_> = sharing.urls().collect();
+        assert_eq!(
+            urls,
+            ["https://download.montague.lit/4a771ac1-f0b2-4a4a-9700-f2a26fa2bb67/summit.jpg"]
+        );
+        assert_eq!(Element::from(sharing), elem);
+    }
+
+    #[test]
+    fn test_generate() {
+        let file = File::new().with_name("summit.jpg").with_size(3032449);
+        let sharing = FileSharing::new(file, "https://example.org/summit.jpg")
+            .with_disposition(Disposition::Attachment);
+        let elem: Element = sharing.clone().into();
+        assert_eq!(elem.attr("disposition"), Some("attachment"));
+        assert_eq!(FileSharing::try_from(elem).unwrap(), sharing);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let elem: Element = "<file-sharing xmlns='urn:xmpp:sfs:0'><file xmlns='urn:xmpp:file:metadata:0'/></file-sharing>"
+            .parse()
+            .unwrap();
+        let error = FileSharing::try_from(elem).unwrap_err();
+        let message = match error {
+            Error::ParseError(string) => string,
+            _ => panic!(),
+        };
+        assert_eq!(message, "Missing sources in file-sharing element.");
+
+        let elem: Element = "<file-sharing xmlns='urn:xmpp:sfs:0'><file xmlns='urn:xmpp:file:metadata:0'/><sources><coucou/></sources></file-sharing>"
+            .parse()
+            .unwrap();
+        let error = FileSharing::try_from(elem).unwrap_err();
+        let message = match error {
+            Error::ParseError(string) => string,
+            _ => panic!(),
+        };
+        assert_eq!(message, "Unknown source in sources element.");
+    }
+}
@@ -4,6 +4,7 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
+use crate::lang;
 use crate::message::MessagePayload;
 use crate::ns;
 use crate::presence::PresencePayload;
@@ -215,7 +216,94 @@ pub struct StanzaError {
 impl MessagePayload for StanzaError {}
 impl PresencePayload for StanzaError {}
 
+macro_rules! stanza_error_constructors {
+    ($($(#[$meta:meta])* $fn:ident => $type:ident, $condition:ident),+$(,)?) => (
+        $(
+            $(#[$meta])*
+            pub fn $fn<T: Into<String>>(text: T) -> StanzaError {
+                StanzaError::with_condition(ErrorType::$type, DefinedCondition::$condition, text)
+            }
+        )+
+    );
+}
+
 impl StanzaError {
+    /// Create a new `<error/>` of this type and condition, with this
+    /// English text unless it is empty.
+    pub fn with_condition<T: Into<String>>(
+        type_: ErrorType,
+        defined_condition: DefinedCondition,
+        text: T,
+    ) -> StanzaError {
+        let text = text.into();
+        let mut texts = BTreeMap::new();
+        if !text.is_empty() {
+            texts.insert(Lang::from("en"), text);
+        }
+        StanzaError {
+            type_,
+            by: None,
+            defined_condition,
+            texts,
+            other: None,
+        }
+    }
+
+    stanza_error_constructors!(
+        /// Create a bad-request error, of type modify.
+        bad_request => Modify, BadRequest,
+        /// Create a conflict error, of type cancel.
+        conflict => Cancel, Conflict,
+        /// Create a feature-not-implemented error, of type cancel.
+        feature_not_implemented => Cancel, FeatureNotImplemented,
+        /// Create a forbidden error, of type auth.
+        forbidden => Auth, Forbidden,
+        /// Create an internal-server-error error, of type cancel.
+        internal_server_error => Cancel, InternalServerError,
+        /// Create an item-not-found error, of type cancel.
+        item_not_found => Cancel, ItemNotFound,
+        /// Create a jid-malformed error, of type modify.
+        jid_malformed => Modify, JidMalformed,
+        /// Create a not-acceptable error, of type modify.
+        not_acceptable => Modify, NotAcceptable,
+        /// Create a not-allowed error, of type cancel.
+        not_allowed => Cancel, NotAllowed,
+        /// Create a not-authorized error, of type auth.
+        not_authorized => Auth, NotAuthorized,
+        /// Create a policy-violation error, of type modify.
+        policy_violation => Modify, PolicyViolation,
+        /// Create a recipient-unavailable error, of type wait.
+        recipient_unavailable => Wait, RecipientUnavailable,
+        /// Create a registration-required error, of type auth.
+        registration_required => Auth, RegistrationRequired,
+        /// Create a remote-server-not-found error, of type cancel.
+        remote_server_not_found => Cancel, RemoteServerNotFound,
+        /// Create a remote-server-timeout error, of type wait.
+        remote_server_timeout => Wait, RemoteServerTimeout,
+        /// Create a resource-constraint error, of type wait.
+        resource_constraint => Wait, ResourceConstraint,
+        /// Create a service-unavailable error, of type cancel.
+        service_unavailable => Cancel, ServiceUnavailable,
+        /// Create a subscription-required error, of type auth.
+        subscription_required => Auth, SubscriptionRequired,
+        /// Create an undefined-condition error, of type cancel.
+        undefined_condition => Cancel, UndefinedCondition,
+        /// Create an unexpected-request error, of type modify.
+        unexpected_request => Modify, UnexpectedRequest,
+    );
+
+    /// Set the JID of the entity setting this error.
+    pub fn with_by(mut self, by: Jid) -> StanzaError {
+        self.by = Some(by);
+        self
+    }
+
+    /// Returns the best matching text from a list of languages, see
+    /// [lang::get_best()](../lang/fn.get_best.html).
+    pub fn get_best_text(&self, preferred_langs: Vec<&str>) -> Option<(Lang, &String)> {
+        lang::get_best(&self.texts, &preferred_langs)
+    }
+
     /// Create a new `<error/>` with the according content.
     pub fn new<L, T>(
         type_: ErrorType,
@@ -343,6 +431,19 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_constructors() {
+        let error = StanzaError::bad_request("Missing node.");
+        assert_eq!(error.type_, ErrorType::Modify);
+        assert_eq!(error.defined_condition, DefinedCondition::BadRequest);
+        assert_eq!(error.texts["en"], "Missing node.");
+
+        let error = StanzaError::forbidden("");
+        assert_eq!(error.type_, ErrorType::Auth);
+        assert_eq!(error.defined_condition, DefinedCondition::Forbidden);
+        assert!(error.texts.is_empty());
+    }
+
     #[test]
     fn test_invalid_type() {
         #[cfg(not(feature = "component"))]
@@ -0,0 +1,335 @@
+// Copyright (c) 2022 Emmanuel Gil Peyrot <linkmauve@linkmauve.fr>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use crate::lang::{self, Lang};
+use crate::ns;
+use crate::util::error::Error;
+use crate::Element;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+
+generate_element_enum!(
+    /// List of valid stream error conditions.
+    DefinedCondition, "condition", XMPP_STREAMS, {
+        /// The entity has sent XML that cannot be processed.
+        BadFormat => "bad-format",
+
+        /// The entity has sent a namespace prefix that is unsupported, or has
+        /// sent no namespace prefix on an element that needs such a prefix.
+        BadNamespacePrefix => "bad-namespace-prefix",
+
+        /// The server either (1) is closing the existing stream for this
+        /// entity because a new stream has been initiated that conflicts with
+        /// the existing stream, or (2) is refusing a new stream for this
+        /// entity because allowing the new stream would conflict with an
+        /// existing stream.
+        Conflict => "conflict",
+
+        /// One party is closing the stream because it has reason to believe
+        /// that the other party has permanently lost the ability to
+        /// communicate over the stream.
+        ConnectionTimeout => "connection-timeout",
+
+        /// The value of the 'to' attribute provided in the initial stream
+        /// header corresponds to an FQDN that is no longer serviced by the
+        /// receiving entity.
+        HostGone => "host-gone",
+
+        /// The value of the 'to' attribute provided in the initial stream
+        /// header does not correspond to an FQDN that is serviced by the
+        /// receiving entity.
+        HostUnknown => "host-unknown",
+
+        /// A stanza sent between two servers lacks a 'to' or 'from' attribute,
+        /// the 'from' or 'to' attribute has no value, or the value violates
+        /// the rules for XMPP addresses.
+        ImproperAddressing => "improper-addressing",
+
+        /// The server has experienced a misconfiguration or other internal
+        /// error that prevents it from servicing the stream.
+        InternalServerError => "internal-server-error",
+
+        /// The data provided in a 'from' attribute does not match an
+        /// authorized JID or validated domain.
+        InvalidFrom => "invalid-from",
+
+        /// The stream namespace name is something other than
+        /// "http://etherx.jabber.org/streams" or the content namespace
+        /// declared as the default namespace is not supported.
+        InvalidNamespace => "invalid-namespace",
+
+        /// The entity has sent invalid XML over the stream to a server that
+        /// performs validation.
+        InvalidXml => "invalid-xml",
+
+        /// The entity has attempted to send XML stanzas or other outbound
+        /// data before the stream has been authenticated.
+        NotAuthorized => "not-authorized",
+
+        /// The initiating entity has sent XML that violates the
+        /// well-formedness rules of XML.
+        NotWellFormed => "not-well-formed",
+
+        /// The entity has violated some local service policy, like a limit on
+        /// the size of stanzas.
+        PolicyViolation => "policy-violation",
+
+        /// The server is unable to properly connect to a remote entity that
+        /// is needed for authentication or authorization.
+        RemoteConnectionFailed => "remote-connection-failed",
+
+        /// The server is closing the stream because it has new features to
+        /// offer, because the keys or certificates used to establish a secure
+        /// context for the stream have expired or have been revoked during the
+        /// life of the stream, or because the TLS sequence number has wrapped.
+        Reset => "reset",
+
+        /// The server lacks the system resources necessary to service the
+        /// stream.
+        ResourceConstraint => "resource-constraint",
+
+        /// The entity has attempted to send restricted XML features such as a
+        /// comment, processing instruction, DTD subset, or XML entity
+        /// reference.
+        RestrictedXml => "restricted-xml",
+
+        /// The server will not provide service to the initiating entity but
+        /// is redirecting traffic to another host, given as the text of this
+        /// element.
+        SeeOtherHost => "see-other-host",
+
+        /// The server is being shut down and all active streams are being
+        /// closed.
+        SystemShutdown => "system-shutdown",
+
+        /// The error condition is not one of those defined by the other
+        /// conditions in this list.
+        UndefinedCondition => "undefined-condition",
+
+        /// The initiating entity has encoded the stream in an encoding that
+        /// is not supported by the server.
+        UnsupportedEncoding => "unsupported-encoding",
+
+        /// The receiving entity has advertised a mandatory-to-negotiate
+        /// stream feature that the initiating entity does not support.
+        UnsupportedFeature => "unsupported-feature",
+
+        /// The initiating entity has sent a first-level child of the stream
+        /// that is not supported by the server.
+        UnsupportedStanzaType => "unsupported-stanza-type",
+
+        /// The 'version' attribute provided by the initiating entity in the
+        /// stream header specifies a version of XMPP that is not supported by
+        /// the server.
+        UnsupportedVersion => "unsupported-version",
+    }
+);
+
+/// The representation of a stream error, after which the stream gets
+/// closed.
+#[derive(Debug, Clone)]
+pub struct StreamError {
+    /// One of the defined conditions for this error to happen.
+    pub defined_condition: DefinedCondition,
+
+    /// Human-readable description of this error.
+    pub texts: BTreeMap<Lang, String>,
+
+    /// An application-specific extension for this error.
+    pub other: Option<Element>,
+}
+
+macro_rules! stream_error_constructors {
+    ($($(#[$meta:meta])* $fn:ident => $condition:ident),+$(,)?) => (
+        $(
+            $(#[$meta])*
+            pub fn $fn<T: Into<String>>(text: T) -> StreamError {
+                StreamError::new(DefinedCondition::$condition, text)
+            }
+        )+
+    );
+}
+
+impl StreamError {
+    /// Create a new `<stream:error/>` with this condition, and this
+    /// English text unless it is empty.
+    pub fn new<T: Into<String>>(defined_condition: DefinedCondition, text: T) -> StreamError {
+        let text = text.into();
+        let mut texts = BTreeMap::new();
+        if !text.is_empty() {
+            texts.insert(Lang::from("en"), text);
+        }
+        StreamError {
+            defined_condition,
+            texts,
+            other: None,
+        }
+    }
+
+    stream_error_constructors!(
+        /// Create a bad-format stream error.
+        bad_format => BadFormat,
+        /// Create a conflict stream error.
+        conflict => Conflict,
+        /// Create a connection-timeout stream error.
+        connection_timeout => ConnectionTimeout,
+        /// Create a host-unknown stream error.
+        host_unknown => HostUnknown,
+        /// Create an internal-server-error stream error.
+        internal_server_error => InternalServerError,
+        /// Create an invalid-namespace stream error.
+        invalid_namespace => InvalidNamespace,
+        /// Create a not-authorized stream error.
+        not_authorized => NotAuthorized,
+        /// Create a not-well-formed stream error.
+        not_well_formed => NotWellFormed,
+        /// Create a policy-violation stream error.
+        policy_violation => PolicyViolation,
+        /// Create a reset stream error.
+        reset => Reset,
+        /// Create a resource-constraint stream error.
+        resource_constraint => ResourceConstraint,
+        /// Create a system-shutdown stream error.
+        system_shutdown => SystemShutdown,
+        /// Create an undefined-condition stream error.
+        undefined_condition => UndefinedCondition,
+        /// Create an unsupported-stanza-type stream error.
+        unsupported_stanza_type => UnsupportedStanzaType,
+        /// Create an unsupported-version stream error.
+        unsupported_version => UnsupportedVersion,
+    );
+
+    /// Set an application-specific extension for this error.
+    pub fn with_other(mut self, other: Element) -> StreamError {
+        self.other = Some(other);
+        self
+    }
+
+    /// Returns the best matching text from a list of languages, see
+    /// [lang::get_best()](../lang/fn.get_best.html).
+    pub fn get_best_text(&self, preferred_langs: Vec<&str>) -> Option<(Lang, &String)> {
+        lang::get_best(&self.texts, &preferred_langs)
+    }
+}
+
+impl TryFrom<Element> for StreamError {
+    type Error = Error;
+
+    fn try_from(elem: Element) -> Result<StreamError, Error> {
+        check_self!(elem, "error", STREAM, "stream:error");
+        check_no_attributes!(elem, "stream:error");
+
+        let mut defined_condition = None;
+        let mut texts = BTreeMap::new();
+        let mut other = None;
+
+        for child in elem.children() {
+            if child.is("text", ns::XMPP_STREAMS) {
+                check_no_children!(child, "text");
+                check_no_unknown_attributes!(child, "text", ["xml:lang"]);
+                let lang = get_attr!(child, "xml:lang", Default);
+                if texts.insert(lang, child.text()).is_some() {
+                    return Err(Error::ParseError(
+                        "Text element present twice for the same xml:lang.",
+                    ));
+                }
+            } else if child.has_ns(ns::XMPP_STREAMS) {
+                if defined_condition.is_some() {
+                    return Err(Error::ParseError(
+                        "Stream error must not have more than one defined-condition.",
+                    ));
+                }
+                defined_condition = Some(DefinedCondition::try_from(child.clone())?);
+            } else {
+                if other.is_some() {
+                    return Err(Error::ParseError(
+                        "Stream error must not have more than one other element.",
+                    ));
+                }
+                other = Some(child.clone());
+            }
+        }
+        let defined_condition = defined_condition.ok_or(Error::ParseError(
+            "Stream error must have a defined-condition.",
+        ))?;
+
+        Ok(StreamError {
+            defined_condition,
+            texts,
+            other,
+        })
+    }
+}
+
+impl From<StreamError> for Element {
+    fn from(err: StreamError) -> Element {
+        Element::builder("error", ns::STREAM)
+            .append(err.defined_condition)
+            .append_all(err.texts.into_iter().map(|(lang, text)| {
+                Element::builder("text", ns::XMPP_STREAMS)
+                    .attr("xml:lang", lang)
+                    .append(text)
+            }))
+            .append_all(err.other)
+            .build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(target_pointer_width = "32")]
+    #[test]
+    fn test_size() {
+        assert_size!(DefinedCondition, 1);
+        assert_size!(StreamError, 100);
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    fn test_size() {
+        assert_size!(DefinedCondition, 1);
+        assert_size!(StreamError, 184);
+    }
+
+    #[test]
+    fn test_simple() {
+        let elem: Element = "<stream:error xmlns:stream='http://etherx.jabber.org/streams'><policy-violation xmlns='urn:ietf:params:xml:ns:xmpp-streams'/><text xmlns='urn:ietf:params:xml:ns:xmpp-streams' xml:lang='en'>Too many stanzas.</text></stream:error>".parse().unwrap();
+        let error = StreamError::try_from(elem).unwrap();
+        assert_eq!(error.defined_condition, DefinedCondition::PolicyViolation);
+        assert_eq!(
+            error.get_best_text(vec!["fr"]),
+            Some((Lang::from("en"), &String::from("Too many stanzas.")))
+        );
+        assert!(error.other.is_none());
+    }
+
+    #[test]
+    fn test_constructors() {
+        let error = StreamError::policy_violation("Too many stanzas.");
+        assert_eq!(error.defined_condition, DefinedCondition::PolicyViolation);
+        let elem = Element::from(error);
+        let error = StreamError::try_from(elem).unwrap();
+        assert_eq!(error.texts["en"], "Too many stanzas.");
+
+        let error = StreamError::system_shutdown("");
+        assert!(error.texts.is_empty());
+    }
+
+    #[test]
+    fn test_missing_condition() {
+        let elem: Element = "<stream:error xmlns:stream='http://etherx.jabber.org/streams'/>"
+            .parse()
+            .unwrap();
+        let error = StreamError::try_from(elem).unwrap_err();
+        let message = match error {
+            Error::ParseError(string) => string,
+            _ => panic!(),
+        };
+        assert_eq!(message, "Stream error must have a defined-condition.");
+    }
+}
@@ -0,0 +1,259 @@
+// Copyright (c) 2022 Emmanuel Gil Peyrot <linkmauve@linkmauve.fr>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use crate::message::MessagePayload;
+
+generate_empty_element!(
+    /// Asks the recipient not to apply any styling to the body of this
+    /// message.
+    Unstyled,
+    "unstyled",
+    STYLING
+);
+
+impl MessagePayload for Unstyled {}
+
+/// An inline span of styled text, within a single line.
+///
+/// The styling directives themselves aren’t part of the text.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Span {
+    /// Unstyled text.
+    Text(String),
+
+    /// Text between `*` directives.
+    Strong(Vec<Span>),
+
+    /// Text between `_` directives.
+    Emphasis(Vec<Span>),
+
+    /// Text between `~` directives.
+    Strikethrough(Vec<Span>),
+
+    /// Text between `` ` `` directives, in which no styling applies.
+    Pre(String),
+}
+
+/// A block of a styled message, spanning one or more lines.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Block {
+    /// A single line of text.
+    Plain(Vec<Span>),
+
+    /// Lines between ```` ``` ```` lines, in which no styling applies.
+    Pre(String),
+
+    /// Consecutive lines starting with `>`, once stripped from it and the
+    /// whitespace following it.
+    Quote(Vec<Block>),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Strong,
+    Emphasis,
+    Strikethrough,
+    Pre,
+}
+
+impl Kind {
+    fn from_directive(c: char) -> Option<Kind> {
+        Some(match c {
+            '*' => Kind::Strong,
+            '_' => Kind::Emphasis,
+            '~' => Kind::Strikethrough,
+            '`' => Kind::Pre,
+            _ => return None,
+        })
+    }
+}
+
+fn flush(text: &mut String, spans: &mut Vec<Span>) {
+    if !text.is_empty() {
+        spans.push(Span::Text(std::mem::take(text)));
+    }
+}
+
+/// Parses the spans of `chars[start..end]`, which starts right after the
+/// beginning of a line or an opening directive of one of the `open` kinds.
+fn parse_spans(chars: &[char], start: usize, end: usize, open: &[Kind]) -> Vec<Span> {
+    let mut spans = Vec::new();
+    let mut text = String::new();
+    let mut i = start;
+    while i < end {
+        let c = chars[i];
+        let kind = match Kind::from_directive(c) {
+            Some(kind) if !open.contains(&kind) => kind,
+            _ => {
+                text.push(c);
+                i += 1;
+                continue;
+            }
+        };
+        // An opening directive follows the start, whitespace or another
+        // opening directive, and precedes some non-whitespace text.
+        let can_open = (i == start || chars[i - 1].is_whitespace())
+            && i + 1 < end
+            && !chars[i + 1].is_whitespace();
+        // A closing directive follows non-whitespace text, and the span
+        // isn’t empty.
+        let close = if can_open {
+            (i + 2..end).find(|&j| chars[j] == c && !chars[j - 1].is_whitespace())
+        } else {
+            None
+        };
+        let close = match close {
+            Some(close) => close,
+            None => {
+                text.push(c);
+                i += 1;
+                continue;
+            }
+        };
+        flush(&mut text, &mut spans);
+        let span = if kind == Kind::Pre {
+            Span::Pre(chars[i + 1..close].iter().collect())
+        } else {
+            let mut open = open.to_vec();
+            open.push(kind);
+            let children = parse_spans(chars, i + 1, close, &open);
+            match kind {
+                Kind::Strong => Span::Strong(children),
+                Kind::Emphasis => Span::Emphasis(children),
+                Kind::Strikethrough => Span::Strikethrough(children),
+                Kind::Pre => unreachable!(),
+            }
+        };
+        spans.push(span);
+        i = close + 1;
+    }
+    flush(&mut text, &mut spans);
+    spans
+}
+
+fn parse_line(line: &str) -> Vec<Span> {
+    let chars: Vec<char> = line.chars().collect();
+    parse_spans(&chars, 0, chars.len(), &[])
+}
+
+fn parse_blocks(lines: &[&str]) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i];
+        if line.starts_with("```") {
+            // Anything after the opening directive is ignored, and the block
+            // ends with its parent if never closed.
+            let end = lines[i + 1..]
+                .iter()
+                .position(|line| *line == "```")
+                .map(|position| i + 1 + position);
+            let content = &lines[i + 1..end.unwrap_or(lines.len())];
+            blocks.push(Block::Pre(content.join("\n")));
+            i = end.map(|end| end + 1).unwrap_or(lines.len());
+        } else if line.starts_with('>') {
+            let quoted: Vec<&str> = lines[i..]
+                .iter()
+                .take_while(|line| line.starts_with('>'))
+                .map(|line| line[1..].trim_start())
+                .collect();
+            i += quoted.len();
+            blocks.push(Block::Quote(parse_blocks(&quoted)));
+        } else {
+            blocks.push(Block::Plain(parse_line(line)));
+            i += 1;
+        }
+    }
+    blocks
+}
+
+/// Parses the styling of a message body, one block per line except for
+/// preformatted and quote blocks.
+pub fn parse(body: &str) -> Vec<Block> {
+    let lines: Vec<&str> = body.split('\n').collect();
+    parse_blocks(&lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Element;
+    use std::convert::TryFrom;
+
+    fn text(text: &str) -> Span {
+        Span::Text(String::from(text))
+    }
+
+    #[test]
+    fn test_unstyled() {
+        let elem: Element = "<unstyled xmlns='urn:xmpp:styling:0'/>".parse().unwrap();
+        let unstyled = Unstyled::try_from(elem.clone()).unwrap();
+        let elem2: Element = unstyled.into();
+        assert_eq!(elem, elem2);
+    }
+
+    #[test]
+    fn test_spans() {
+        assert_eq!(
+            parse_line("I *really* like _this_ ~one~ `*a*`"),
+            [
+                text("I "),
+                Span::Strong(vec![text("really")]),
+                text(" like "),
+                Span::Emphasis(vec![text("this")]),
+                text(" "),
+                Span::Strikethrough(vec![text("one")]),
+                text(" "),
+                Span::Pre(String::from("*a*")),
+            ]
+        );
+        assert_eq!(
+            parse_line("*_both_*"),
+            [Span::Strong(vec![Span::Emphasis(vec![text("both")])])]
+        );
+    }
+
+    #[test]
+    fn test_invalid_spans() {
+        // Empty spans, whitespace after opening or before closing.
+        for line in &["**", "* a*", "*a *", "a*b*", "*a", "2 * 3 * 4"] {
+            assert_eq!(parse_line(line), [text(line)], "{}", line);
+        }
+        // The same directive doesn’t nest.
+        assert_eq!(
+            parse_line("*a *b* c*"),
+            [Span::Strong(vec![text("a *b")]), text(" c*")]
+        );
+    }
+
+    #[test]
+    fn test_blocks() {
+        let body = "Hi\n```rust\nlet *a* = 1;\n```\n> quoted *text*\n>> nested\nend";
+        assert_eq!(
+            parse(body),
+            [
+                Block::Plain(vec![text("Hi")]),
+                Block::Pre(String::from("let *a* = 1;")),
+                Block::Quote(vec![
+                    Block::Plain(vec![text("quoted "), Span::Strong(vec![text("text")])]),
+                    Block::Quote(vec![Block::Plain(vec![text("nested")])]),
+                ]),
+                Block::Plain(vec![text("end")]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unclosed_pre() {
+        assert_eq!(
+            parse("> ```\n> *a*\nb"),
+            [
+                Block::Quote(vec![Block::Pre(String::from("*a*"))]),
+                Block::Plain(vec![text("b")]),
+            ]
+        );
+    }
+}
@@ -0,0 +1,109 @@
+// Copyright (c) 2022 Emmanuel Gil Peyrot <linkmauve@linkmauve.fr>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+generate_element!(
+    /// A thumbnail of an image or a video, to display before retrieving the
+    /// whole file.
+    Thumbnail, "thumbnail", THUMBS,
+    attributes: [
+        /// Where to retrieve the thumbnail, usually a cid: URI pointing to
+        /// a Bits of Binary (XEP-0231) blob.
+        uri: Required<String> = "uri",
+
+        /// The MIME type of the thumbnail.
+        media_type: Option<String> = "media-type",
+
+        /// The width of the thumbnail, in pixels.
+        width: Option<u16> = "width",
+
+        /// The height of the thumbnail, in pixels.
+        height: Option<u16> = "height",
+    ]
+);
+
+impl Thumbnail {
+    /// Creates a new thumbnail available at this URI.
+    pub fn new<U: Into<String>>(uri: U) -> Thumbnail {
+        Thumbnail {
+            uri: uri.into(),
+            media_type: None,
+            width: None,
+            height: None,
+        }
+    }
+
+    /// Sets the MIME type of this thumbnail.
+    pub fn with_media_type<M: Into<String>>(mut self, media_type: M) -> Thumbnail {
+        self.media_type = Some(media_type.into());
+        self
+    }
+
+    /// Sets the dimensions of this thumbnail, in pixels.
+    pub fn with_size(mut self, width: u16, height: u16) -> Thumbnail {
+        self.width = Some(width);
+        self.height = Some(height);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::error::Error;
+    use crate::Element;
+    use std::convert::TryFrom;
+
+    #[cfg(target_pointer_width = "32")]
+    #[test]
+    fn test_size() {
+        assert_size!(Thumbnail, 32);
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    fn test_size() {
+        assert_size!(Thumbnail, 56);
+    }
+
+    #[test]
+    fn test_simple() {
+        let elem: Element = "<thumbnail xmlns='urn:xmpp:thumbs:1' uri='cid:sha1+ffd7c8d28e9c5e82afea41f97108c6b4@bob.xmpp.org' media-type='image/png' width='128' height='96'/>"
+            .parse()
+            .unwrap();
+        let thumbnail = Thumbnail::try_from(elem.clone()).unwrap();
+        assert_eq!(
+            thumbnail,
+            Thumbnail::new("cid:sha1+ffd7c8d28e9c5e82afea41f97108c6b4@bob.xmpp.org")
+                .with_media_type("image/png")
+                .with_size(128, 96)
+        );
+        assert_eq!(Element::from(thumbnail), elem);
+
+        let elem: Element =
+            "<thumbnail xmlns='urn:xmpp:thumbs:1' uri='https://example.org/thumb.jpg'/>"
+                .parse()
+                .unwrap();
+        let thumbnail = Thumbnail::try_from(elem).unwrap();
+        assert_eq!(thumbnail.media_type, None);
+        assert_eq!(thumbnail.width, None);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let elem: Element = "<thumbnail xmlns='urn:xmpp:thumbs:1'/>".parse().unwrap();
+        let error = Thumbnail::try_from(elem).unwrap_err();
+        let message = match error {
+            Error::ParseError(string) => string,
+            _ => panic!(),
+        };
+        assert_eq!(message, "Required attribute 'uri' missing.");
+
+        let elem: Element = "<thumbnail xmlns='urn:xmpp:thumbs:1' uri='cid:a' width='-1'/>"
+            .parse()
+            .unwrap();
+        Thumbnail::try_from(elem).unwrap_err();
+    }
+}
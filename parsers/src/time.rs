@@ -12,6 +12,7 @@ use crate::Element;
 use chrono::FixedOffset;
 use std::convert::TryFrom;
 use std::str::FromStr;
+use std::time::SystemTime;
 
 generate_empty_element!(
     /// An entity time query.
@@ -26,6 +27,13 @@ impl IqGetPayload for TimeQuery {}
 #[derive(Debug, Clone)]
 pub struct TimeResult(pub DateTime);
 
+impl TimeResult {
+    /// The current time, in the `tzo` timezone.
+    pub fn now(tzo: FixedOffset) -> TimeResult {
+        TimeResult(DateTime::from_system_time(SystemTime::now(), tzo))
+    }
+}
+
 impl IqResultPayload for TimeResult {}
 
 impl TryFrom<Element> for TimeResult {
@@ -112,4 +120,13 @@ mod tests {
         let elem2 = Element::from(time);
         assert_eq!(elem1, elem2);
     }
+
+    #[test]
+    fn test_now() {
+        let time = TimeResult::now(FixedOffset::west_opt(6 * 3600).unwrap());
+        let elem = Element::from(time.clone());
+        assert_eq!(elem.get_child("tzo", ns::TIME).unwrap().text(), "-06:00");
+        let time2 = TimeResult::try_from(elem).unwrap();
+        assert_eq!(time2.0.timezone(), time.0.timezone());
+    }
 }
@@ -63,37 +63,38 @@ generate_elem_id!(
     TUNE
 );
 
-/// Container for formatted text.
-#[derive(Debug, Clone)]
+/// The tune a user is listening to, or an empty one once they stopped.
+#[derive(Debug, Clone, Default)]
 pub struct Tune {
     /// The artist or performer of the song or piece.
-    artist: Option<Artist>,
+    pub artist: Option<Artist>,
 
     /// The duration of the song or piece in seconds.
-    length: Option<Length>,
+    pub length: Option<Length>,
 
     /// The user's rating of the song or piece, from 1 (lowest) to 10 (highest).
-    rating: Option<Rating>,
+    pub rating: Option<Rating>,
 
     /// The collection (e.g., album) or other source (e.g., a band website that hosts streams or
     /// audio files).
-    source: Option<Source>,
+    pub source: Option<Source>,
 
     /// The title of the song or piece.
-    title: Option<Title>,
+    pub title: Option<Title>,
 
     /// A unique identifier for the tune; e.g., the track number within a collection or the
     /// specific URI for the object (e.g., a stream or audio file).
-    track: Option<Track>,
+    pub track: Option<Track>,
 
     /// A URI or URL pointing to information about the song, collection, or artist.
-    uri: Option<Uri>,
+    pub uri: Option<Uri>,
 }
 
 impl PubSubPayload for Tune {}
 
 impl Tune {
-    fn new() -> Tune {
+    /// Create an empty tune, which means the user stopped listening.
+    pub fn new() -> Tune {
         Tune {
             artist: None,
             length: None,
@@ -104,6 +105,18 @@ impl Tune {
             uri: None,
         }
     }
+
+    /// Whether the user stopped listening, rather than listening to this
+    /// tune.
+    pub fn is_empty(&self) -> bool {
+        self.artist.is_none()
+            && self.length.is_none()
+            && self.rating.is_none()
+            && self.source.is_none()
+            && self.title.is_none()
+            && self.track.is_none()
+            && self.uri.is_none()
+    }
 }
 
 impl TryFrom<Element> for Tune {
@@ -218,6 +231,7 @@ mod tests {
         assert!(tune.title.is_none());
         assert!(tune.track.is_none());
         assert!(tune.uri.is_none());
+        assert!(tune.is_empty());
 
         let elem3 = tune.into();
         assert_eq!(elem2, elem3);
@@ -229,6 +243,7 @@ mod tests {
             .parse()
             .unwrap();
         let tune = Tune::try_from(elem).unwrap();
+        assert!(!tune.is_empty());
         assert_eq!(tune.artist, Some(Artist::from_str("Yes").unwrap()));
         assert_eq!(tune.length, Some(Length(686)));
         assert_eq!(tune.rating, Some(Rating(8)));
@@ -8,6 +8,25 @@ use crate::util::error::Error;
 use jid::Jid;
 use std::str::FromStr;
 
+/// Strips the whitespace around an attribute value of an enumerated type,
+/// as done by the attribute value normalisation of validating parsers.
+pub fn trim_attribute(s: &str) -> &str {
+    s.trim_matches(|c| matches!(c, ' ' | '\t' | '\n' | '\r'))
+}
+
+/// Parses an xs:boolean, either "true" or "1", or "false" or "0", ignoring
+/// the case and the surrounding whitespace.
+pub fn parse_bool(s: &str) -> Option<bool> {
+    let s = trim_attribute(s);
+    if s == "1" || s.eq_ignore_ascii_case("true") {
+        Some(true)
+    } else if s == "0" || s.eq_ignore_ascii_case("false") {
+        Some(false)
+    } else {
+        None
+    }
+}
+
 /// Codec for text content.
 pub struct Text;
 
@@ -120,3 +139,26 @@ impl JidCodec {
         Some(jid.to_string())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_bool() {
+        assert_eq!(parse_bool("true"), Some(true));
+        assert_eq!(parse_bool("1"), Some(true));
+        assert_eq!(parse_bool(" TRUE\n"), Some(true));
+        assert_eq!(parse_bool("False"), Some(false));
+        assert_eq!(parse_bool("\t0 "), Some(false));
+        assert_eq!(parse_bool("yes"), None);
+        assert_eq!(parse_bool("01"), None);
+        assert_eq!(parse_bool(""), None);
+    }
+
+    #[test]
+    fn test_trim_attribute() {
+        assert_eq!(trim_attribute(" chat\n"), "chat");
+        assert_eq!(trim_attribute("group chat"), "group chat");
+    }
+}
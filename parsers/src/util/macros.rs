@@ -73,7 +73,7 @@ macro_rules! generate_attribute {
         impl ::std::str::FromStr for $elem {
             type Err = crate::util::error::Error;
             fn from_str(s: &str) -> Result<$elem, crate::util::error::Error> {
-                Ok(match s {
+                Ok(match crate::util::helpers::trim_attribute(s) {
                     $($b => $elem::$a),+,
                     _ => return Err(crate::util::error::Error::ParseError(concat!("Unknown value for '", $name, "' attribute."))),
                 })
@@ -106,7 +106,7 @@ macro_rules! generate_attribute {
         impl ::std::str::FromStr for $elem {
             type Err = crate::util::error::Error;
             fn from_str(s: &str) -> Result<$elem, crate::util::error::Error> {
-                Ok(match s {
+                Ok(match crate::util::helpers::trim_attribute(s) {
                     $($b => $elem::$a),+,
                     _ => return Err(crate::util::error::Error::ParseError(concat!("Unknown value for '", $name, "' attribute."))),
                 })
@@ -139,7 +139,7 @@ macro_rules! generate_attribute {
         impl ::std::str::FromStr for $elem {
             type Err = crate::util::error::Error;
             fn from_str(s: &str) -> Result<Self, crate::util::error::Error> {
-                Ok(match s {
+                Ok(match crate::util::helpers::trim_attribute(s) {
                     $value => $elem::$symbol,
                     _ => return Err(crate::util::error::Error::ParseError(concat!("Unknown value for '", $name, "' attribute."))),
                 })
@@ -171,10 +171,10 @@ macro_rules! generate_attribute {
         impl ::std::str::FromStr for $elem {
             type Err = crate::util::error::Error;
             fn from_str(s: &str) -> Result<Self, crate::util::error::Error> {
-                Ok(match s {
-                    "true" | "1" => $elem::True,
-                    "false" | "0" => $elem::False,
-                    _ => return Err(crate::util::error::Error::ParseError(concat!("Unknown value for '", $name, "' attribute."))),
+                Ok(match crate::util::helpers::parse_bool(s) {
+                    Some(true) => $elem::True,
+                    Some(false) => $elem::False,
+                    None => return Err(crate::util::error::Error::ParseError(concat!("Unknown value for '", $name, "' attribute."))),
                 })
             }
         }
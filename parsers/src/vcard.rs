@@ -0,0 +1,210 @@
+// Copyright (c) 2022 Emmanuel Gil Peyrot <linkmauve@linkmauve.fr>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Only the most common fields of a vCard are parsed, the others are kept
+//! as they are in [`VCard::payloads`].
+//!
+//! The vCard of a MUC room is fetched from its bare JID, and it is
+//! published by its owners (XEP-0486).
+
+use crate::iq::{IqGetPayload, IqResultPayload, IqSetPayload};
+use crate::ns;
+use crate::util::error::Error;
+use crate::util::helpers::WhitespaceAwareBase64;
+use crate::Element;
+use digest::Digest;
+use sha1::Sha1;
+use std::convert::TryFrom;
+
+generate_element!(
+    /// The binary data of a photo.
+    Binval, "BINVAL", VCARD,
+    text: (
+        /// The raw bytes of this photo.
+        data: WhitespaceAwareBase64<Vec<u8>>
+    )
+);
+
+generate_element!(
+    /// A photo, used as the avatar of this entity.
+    Photo, "PHOTO", VCARD,
+    children: [
+        /// The media type of this photo, for instance `image/png`.
+        type_: Required<String> = ("TYPE", VCARD) => String,
+
+        /// The data of this photo.
+        binval: Required<Binval> = ("BINVAL", VCARD) => Binval
+    ]
+);
+
+impl Photo {
+    /// Create a new photo from its media type and data.
+    pub fn new<S: Into<String>>(type_: S, data: Vec<u8>) -> Photo {
+        Photo {
+            type_: type_.into(),
+            binval: Binval { data },
+        }
+    }
+
+    /// The hex-encoded SHA-1 of this photo, as advertised in presences
+    /// (XEP-0153).
+    pub fn hash(&self) -> String {
+        format!("{:x}", Sha1::digest(&self.binval.data))
+    }
+}
+
+generate_empty_element!(
+    /// Requests the vCard of an entity.
+    VCardQuery,
+    "vCard",
+    VCARD
+);
+
+impl IqGetPayload for VCardQuery {}
+
+/// The vCard of a user or of a MUC room.
+#[derive(Debug, Clone, Default)]
+pub struct VCard {
+    /// The full name of this entity.
+    pub full_name: Option<String>,
+
+    /// The nickname of this entity.
+    pub nickname: Option<String>,
+
+    /// A description of this entity, like the subject of a room.
+    pub description: Option<String>,
+
+    /// A website of this entity.
+    pub url: Option<String>,
+
+    /// The avatar of this entity.
+    pub photo: Option<Photo>,
+
+    /// All of the other fields.
+    pub payloads: Vec<Element>,
+}
+
+impl IqSetPayload for VCard {}
+impl IqResultPayload for VCard {}
+
+impl VCard {
+    /// Create an empty vCard.
+    pub fn new() -> VCard {
+        VCard::default()
+    }
+
+    /// Set the avatar of this vCard.
+    pub fn with_photo(mut self, photo: Photo) -> VCard {
+        self.photo = Some(photo);
+        self
+    }
+}
+
+fn set_text(field: &mut Option<String>, child: &Element) -> Result<(), Error> {
+    if field.is_some() {
+        return Err(Error::ParseError("Duplicate field in vCard element."));
+    }
+    check_no_children!(child, "vCard field");
+    check_no_attributes!(child, "vCard field");
+    *field = Some(child.text());
+    Ok(())
+}
+
+impl TryFrom<Element> for VCard {
+    type Error = Error;
+
+    fn try_from(elem: Element) -> Result<VCard, Error> {
+        check_self!(elem, "vCard", VCARD);
+        let mut vcard = VCard::default();
+        for child in elem.children() {
+            if child.is("FN", ns::VCARD) {
+                set_text(&mut vcard.full_name, child)?;
+            } else if child.is("NICKNAME", ns::VCARD) {
+                set_text(&mut vcard.nickname, child)?;
+            } else if child.is("DESC", ns::VCARD) {
+                set_text(&mut vcard.description, child)?;
+            } else if child.is("URL", ns::VCARD) {
+                set_text(&mut vcard.url, child)?;
+            } else if child.is("PHOTO", ns::VCARD) {
+                if vcard.photo.is_some() {
+                    return Err(Error::ParseError("Duplicate field in vCard element."));
+                }
+                vcard.photo = Some(Photo::try_from(child.clone())?);
+            } else {
+                vcard.payloads.push(child.clone());
+            }
+        }
+        Ok(vcard)
+    }
+}
+
+impl From<VCard> for Element {
+    fn from(vcard: VCard) -> Element {
+        let text = |name, text: Option<String>| {
+            text.map(|text| Element::builder(name, ns::VCARD).append(text).build())
+        };
+        Element::builder("vCard", ns::VCARD)
+            .append_all(text("FN", vcard.full_name))
+            .append_all(text("NICKNAME", vcard.nickname))
+            .append_all(text("DESC", vcard.description))
+            .append_all(text("URL", vcard.url))
+            .append_all(vcard.photo)
+            .append_all(vcard.payloads)
+            .build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_room_vcard() {
+        let elem: Element = "<vCard xmlns='vcard-temp'><DESC>Sleep on a bed of money.</DESC><PHOTO><TYPE>image/png</TYPE><BINVAL>iVBORw0KGgo=</BINVAL></PHOTO><BDAY>1996-08-13</BDAY></vCard>"
+            .parse()
+            .unwrap();
+        let vcard = VCard::try_from(elem.clone()).unwrap();
+        assert_eq!(vcard.full_name, None);
+        assert_eq!(
+            vcard.description.as_deref(),
+            Some("Sleep on a bed of money.")
+        );
+        let photo = vcard.photo.clone().unwrap();
+        assert_eq!(photo.type_, "image/png");
+        assert_eq!(photo.binval.data, b"\x89PNG\r\n\x1a\n");
+        assert_eq!(photo.hash(), "4caece539b039b16e16206ea2478f8c5ffb2ca05");
+        assert_eq!(vcard.payloads.len(), 1);
+        assert_eq!(Element::from(vcard), elem);
+
+        let vcard = VCard::new().with_photo(Photo::new("image/png", b"\x89PNG\r\n\x1a\n".to_vec()));
+        let elem: Element = "<vCard xmlns='vcard-temp'><PHOTO><TYPE>image/png</TYPE><BINVAL>iVBORw0KGgo=</BINVAL></PHOTO></vCard>"
+            .parse()
+            .unwrap();
+        assert_eq!(Element::from(vcard), elem);
+    }
+
+    #[test]
+    fn test_whitespace_binval() {
+        let elem: Element = "<vCard xmlns='vcard-temp'><PHOTO><TYPE>image/png</TYPE><BINVAL>iVBO\n  Rw0KGgo=</BINVAL></PHOTO></vCard>"
+            .parse()
+            .unwrap();
+        let vcard = VCard::try_from(elem).unwrap();
+        assert_eq!(vcard.photo.unwrap().binval.data, b"\x89PNG\r\n\x1a\n");
+    }
+
+    #[test]
+    fn test_invalid() {
+        let elem: Element = "<vCard xmlns='vcard-temp'><FN>a</FN><FN>b</FN></vCard>"
+            .parse()
+            .unwrap();
+        let error = VCard::try_from(elem).unwrap_err();
+        let message = match error {
+            Error::ParseError(string) => string,
+            _ => panic!(),
+        };
+        assert_eq!(message, "Duplicate field in vCard element.");
+    }
+}
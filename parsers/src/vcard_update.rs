@@ -0,0 +1,73 @@
+// Copyright (c) 2022 Emmanuel Gil Peyrot <linkmauve@linkmauve.fr>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+use crate::presence::PresencePayload;
+use crate::util::helpers::PlainText;
+
+generate_element!(
+    /// The hash of the current vCard avatar.
+    Photo, "photo", VCARD_UPDATE,
+    text: (
+        /// The hex-encoded SHA-1 of the avatar, or `None` if there is no
+        /// avatar.
+        hash: PlainText<Option<String>>
+    )
+);
+
+generate_element!(
+    /// Advertises the vCard avatar of a user or a MUC room in its
+    /// presence.
+    VCardUpdate, "x", VCARD_UPDATE,
+    children: [
+        /// The current avatar, or `None` if the entity isn’t ready to
+        /// advertise it yet.
+        photo: Option<Photo> = ("photo", VCARD_UPDATE) => Photo
+    ]
+);
+
+impl PresencePayload for VCardUpdate {}
+
+impl VCardUpdate {
+    /// The hash of the current avatar, if it is known and there is one.
+    pub fn hash(&self) -> Option<&str> {
+        self.photo.as_ref()?.hash.as_deref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Element;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn test_hash() {
+        let elem: Element = "<x xmlns='vcard-temp:x:update'><photo>01b87fcd030b72895ff8e88db57ec525450f000d</photo></x>"
+            .parse()
+            .unwrap();
+        let update = VCardUpdate::try_from(elem.clone()).unwrap();
+        assert_eq!(
+            update.hash(),
+            Some("01b87fcd030b72895ff8e88db57ec525450f000d")
+        );
+        assert_eq!(Element::from(update), elem);
+    }
+
+    #[test]
+    fn test_no_avatar() {
+        let elem: Element = "<x xmlns='vcard-temp:x:update'><photo/></x>"
+            .parse()
+            .unwrap();
+        let update = VCardUpdate::try_from(elem.clone()).unwrap();
+        assert!(update.photo.is_some());
+        assert_eq!(update.hash(), None);
+        assert_eq!(Element::from(update), elem);
+
+        let elem: Element = "<x xmlns='vcard-temp:x:update'/>".parse().unwrap();
+        let update = VCardUpdate::try_from(elem).unwrap();
+        assert!(update.photo.is_none());
+    }
+}
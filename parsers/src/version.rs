@@ -36,6 +36,23 @@ generate_element!(
     ]
 );
 
+impl VersionResult {
+    /// Create a new answer for this client name and version.
+    pub fn new<N: Into<String>, V: Into<String>>(name: N, version: V) -> VersionResult {
+        VersionResult {
+            name: name.into(),
+            version: version.into(),
+            os: None,
+        }
+    }
+
+    /// Also tell which OS this client is running on.
+    pub fn with_os<O: Into<String>>(mut self, os: O) -> VersionResult {
+        self.os = Some(os.into());
+        self
+    }
+}
+
 impl IqResultPayload for VersionResult {}
 
 #[cfg(test)]
@@ -72,11 +89,7 @@ mod tests {
 
     #[test]
     fn serialisation() {
-        let version = VersionResult {
-            name: String::from("xmpp-rs"),
-            version: String::from("0.3.0"),
-            os: None,
-        };
+        let version = VersionResult::new("xmpp-rs", "0.3.0");
         let elem1 = Element::from(version);
         let elem2: Element =
             "<query xmlns='jabber:iq:version'><name>xmpp-rs</name><version>0.3.0</version></query>"
@@ -0,0 +1,107 @@
+//! Throughput of decoding stanzas on a component stream, sequentially on
+//! the I/O task versus through a `Pipeline` of varying parallelism.
+//!
+//! The pipeline only pays off once building and parsing a stanza costs
+//! more than tokenizing it and handing it over to another task, so expect
+//! it to trail behind with cheap payloads like the ones below.
+//!
+//! Run with `cargo bench -p tokio-xmpp --bench component_pipeline`.
+
+use futures::stream::StreamExt;
+use std::convert::TryFrom;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use tokio::io::{duplex, AsyncWriteExt, DuplexStream};
+use tokio_xmpp::pipeline::Pipeline;
+use tokio_xmpp::xmpp_stream::XMPPStream;
+use tokio_xmpp::Packet;
+use xmpp_parsers::message::Message;
+use xmpp_parsers::{ns, Jid};
+
+const STANZAS: usize = 20_000;
+
+fn payload() -> Vec<u8> {
+    let mut data = Vec::from(
+        &b"<stream:stream xmlns='jabber:client' xmlns:stream='http://etherx.jabber.org/streams' from='component.example' id='bench'>"[..],
+    );
+    for i in 0..STANZAS {
+        data.extend_from_slice(
+            format!(
+                "<message from='user{0}@example.org/res' to='room@component.example' id='msg{0}' type='groupchat'>\
+                 <body>Hello, this is message number {0}, with some filler text to make it look real.</body>\
+                 <delay xmlns='urn:xmpp:delay' from='example.org' stamp='2002-09-10T23:08:25Z'/>\
+                 <origin-id xmlns='urn:xmpp:sid:0' id='origin{0}'/>\
+                 </message>",
+                i
+            )
+            .as_bytes(),
+        );
+    }
+    data.extend_from_slice(b"</stream:stream>");
+    data
+}
+
+async fn connect(data: &'static [u8]) -> XMPPStream<DuplexStream> {
+    let (client, mut server) = duplex(64 * 1024);
+    tokio::spawn(async move {
+        server.write_all(data).await.unwrap();
+        // Keep our end open until everything has been read.
+        let mut sink = tokio::io::sink();
+        let _ = tokio::io::copy(&mut server, &mut sink).await;
+    });
+    let jid = Jid::from_str("component.example").unwrap();
+    XMPPStream::start(client, jid, ns::DEFAULT_NS.to_owned())
+        .await
+        .unwrap()
+}
+
+async fn sequential(data: &'static [u8]) -> usize {
+    let mut stream = connect(data).await;
+    let mut count = 0;
+    while let Some(packet) = stream.next().await {
+        match packet.unwrap() {
+            Packet::Stanza(elem) => {
+                Message::try_from(elem).unwrap();
+                count += 1;
+            }
+            Packet::StreamEnd => break,
+            _ => (),
+        }
+    }
+    count
+}
+
+async fn pipelined(data: &'static [u8], parallelism: usize) -> usize {
+    let stream = connect(data).await;
+    let mut pipeline = Pipeline::new(stream, parallelism, Message::try_from);
+    let mut count = 0;
+    while let Some(message) = pipeline.next().await {
+        message.unwrap().unwrap();
+        count += 1;
+    }
+    count
+}
+
+fn report(name: &str, elapsed: Duration) {
+    println!(
+        "{:<16} {:>8.1} ms  {:>10.0} stanzas/s",
+        name,
+        elapsed.as_secs_f64() * 1000.,
+        STANZAS as f64 / elapsed.as_secs_f64()
+    );
+}
+
+#[tokio::main]
+async fn main() {
+    let data: &'static [u8] = Box::leak(payload().into_boxed_slice());
+
+    let start = Instant::now();
+    assert_eq!(sequential(data).await, STANZAS);
+    report("sequential", start.elapsed());
+
+    for &parallelism in &[1, 2, 4, 8, 16] {
+        let start = Instant::now();
+        assert_eq!(pipelined(data, parallelism).await, STANZAS);
+        report(&format!("pipeline({})", parallelism), start.elapsed());
+    }
+}
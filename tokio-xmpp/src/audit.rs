@@ -0,0 +1,153 @@
+//! Trail of what happened on the connections of a client
+//!
+//! The milestones of the negotiation of each connection, and the errors
+//! ending them, are kept in a bounded ring buffer, to attach to bug
+//! reports when a disconnection needs explaining. This is independent of
+//! logging, which may be disabled or too verbose to keep.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use xmpp_parsers::Jid;
+
+/// How many entries a trail keeps by default
+pub const DEFAULT_CAPACITY: usize = 64;
+
+/// Something which happened on a connection
+#[derive(Debug, Clone, PartialEq)]
+pub enum AuditEvent {
+    /// A new connection is being attempted
+    Connecting,
+    /// The TCP connection to the server got established
+    TcpConnected,
+    /// The server opened its stream
+    StreamOpened,
+    /// The connection got encrypted, directly or with STARTTLS
+    TlsEstablished,
+    /// The SASL authentication succeeded
+    Authenticated,
+    /// The server bound this resource
+    Bound(Jid),
+    /// The server resumed the stream management session of this JID
+    Resumed(Jid),
+    /// The connection ended, or couldn't be established, with this error
+    Disconnected(String),
+}
+
+impl fmt::Display for AuditEvent {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AuditEvent::Connecting => write!(fmt, "connecting"),
+            AuditEvent::TcpConnected => write!(fmt, "TCP connected"),
+            AuditEvent::StreamOpened => write!(fmt, "stream opened"),
+            AuditEvent::TlsEstablished => write!(fmt, "TLS established"),
+            AuditEvent::Authenticated => write!(fmt, "authenticated"),
+            AuditEvent::Bound(jid) => write!(fmt, "bound as {}", jid),
+            AuditEvent::Resumed(jid) => write!(fmt, "resumed as {}", jid),
+            AuditEvent::Disconnected(error) => write!(fmt, "disconnected: {}", error),
+        }
+    }
+}
+
+/// An event, and when it happened
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditEntry {
+    /// When it happened
+    pub time: SystemTime,
+    /// What happened
+    pub event: AuditEvent,
+}
+
+#[derive(Debug)]
+struct Trail {
+    capacity: usize,
+    entries: VecDeque<AuditEntry>,
+}
+
+/// Bounded trail of the last events, dropping the oldest ones once full
+///
+/// Clones share the same entries, so that the connection tasks can record
+/// into the trail of their client.
+#[derive(Debug, Clone)]
+pub struct AuditTrail(Arc<Mutex<Trail>>);
+
+impl AuditTrail {
+    /// Create an empty trail keeping the last `capacity` events
+    pub fn new(capacity: usize) -> AuditTrail {
+        AuditTrail(Arc::new(Mutex::new(Trail {
+            capacity,
+            entries: VecDeque::with_capacity(capacity),
+        })))
+    }
+
+    /// Record that `event` just happened
+    pub fn record(&self, event: AuditEvent) {
+        let mut trail = self.0.lock().unwrap();
+        if trail.capacity == 0 {
+            return;
+        }
+        while trail.entries.len() >= trail.capacity {
+            trail.entries.pop_front();
+        }
+        trail.entries.push_back(AuditEntry {
+            time: SystemTime::now(),
+            event,
+        });
+    }
+
+    /// Keep the last `capacity` events from now on, dropping the oldest
+    /// ones if there are more
+    pub fn set_capacity(&self, capacity: usize) {
+        let mut trail = self.0.lock().unwrap();
+        trail.capacity = capacity;
+        while trail.entries.len() > capacity {
+            trail.entries.pop_front();
+        }
+    }
+
+    /// The events recorded, oldest first
+    pub fn entries(&self) -> Vec<AuditEntry> {
+        self.0.lock().unwrap().entries.iter().cloned().collect()
+    }
+}
+
+impl Default for AuditTrail {
+    fn default() -> AuditTrail {
+        AuditTrail::new(DEFAULT_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ring_buffer() {
+        let trail = AuditTrail::new(3);
+        trail.record(AuditEvent::Connecting);
+        trail.record(AuditEvent::TcpConnected);
+        trail.clone().record(AuditEvent::StreamOpened);
+        trail.record(AuditEvent::Disconnected(String::from("TLS error")));
+        let events: Vec<_> = trail
+            .entries()
+            .into_iter()
+            .map(|entry| entry.event)
+            .collect();
+        assert_eq!(
+            events,
+            [
+                AuditEvent::TcpConnected,
+                AuditEvent::StreamOpened,
+                AuditEvent::Disconnected(String::from("TLS error")),
+            ]
+        );
+        assert_eq!(events[2].to_string(), "disconnected: TLS error");
+
+        trail.set_capacity(1);
+        assert_eq!(trail.entries().len(), 1);
+        trail.set_capacity(0);
+        trail.record(AuditEvent::Connecting);
+        assert!(trail.entries().is_empty());
+    }
+}
@@ -1,29 +1,46 @@
 use futures::{sink::SinkExt, task::Poll, Future, Sink, Stream};
-use sasl::common::{ChannelBinding, Credentials};
+use log::warn;
+use minidom::tree_builder::ParserLimits;
+use std::collections::VecDeque;
+use std::convert::TryFrom;
 use std::mem::replace;
 use std::pin::Pin;
 use std::str::FromStr;
-use std::task::Context;
+use std::sync::Arc;
+use std::task::{Context, Waker};
+use std::time::Duration;
 use tokio::net::TcpStream;
 use tokio::task::JoinHandle;
 #[cfg(feature = "tls-native")]
 use tokio_native_tls::TlsStream;
 #[cfg(feature = "tls-rust")]
 use tokio_rustls::client::TlsStream;
+use tokio_util::codec::Framed;
+use xmpp_parsers::sm::{A, R};
 use xmpp_parsers::{ns, Element, Jid, JidParseError};
 
-use super::auth::auth;
-use super::bind::bind;
+use super::{
+    authenticate, bind_session, connect_tls, connect_tls_with_srv, TlsMode, DEFAULT_CONNECT_TIMEOUT,
+};
+use crate::audit::{AuditEvent, AuditTrail};
+use crate::clock::{Clock, Timer, TokioClock};
 use crate::event::Event;
-use crate::happy_eyeballs::{connect_to_host, connect_with_srv};
-use crate::starttls::starttls;
-use crate::xmpp_codec::Packet;
+use crate::keepalive::{Keepalive, KeepaliveAction, KeepaliveIncoming};
+use crate::memory::{MemoryAccount, MemoryCharge, MemoryUse};
+use crate::outbox::{Outbox, OutboxStorage};
+use crate::sanitize::CharPolicy;
+use crate::sasl_policy::SaslPolicy;
+use crate::stream_management::{self, is_stanza, SmState, SmStorage};
+use crate::stream_start;
+use crate::tls::TlsConfig;
+use crate::xmpp_codec::{Packet, XMPPCodec};
 use crate::xmpp_stream;
 use crate::{Error, ProtocolError};
 
 /// XMPP client connection and state
 ///
-/// It is able to reconnect. TODO: implement session management.
+/// It is able to reconnect, resuming the session when stream management
+/// got enabled with [`Client::set_stream_management`].
 ///
 /// This implements the `futures` crate's [`Stream`](#impl-Stream) and
 /// [`Sink`](#impl-Sink<Packet>) traits.
@@ -31,36 +48,82 @@ pub struct Client {
     config: Config,
     state: ClientState,
     reconnect: bool,
+    memory: Option<MemoryAccount>,
+    char_policy: CharPolicy,
+    outbox: Option<Outbox<Box<dyn OutboxStorage + Send>>>,
+    outbox_capacity: Option<usize>,
+    sm: Option<SmState>,
+    sm_storage: Option<Box<dyn SmStorage + Send>>,
+    sm_awaiting_ack: bool,
+    sm_memory: Option<MemoryCharge>,
+    // Stanzas received while enabling stream management, to yield first.
+    received: VecDeque<Element>,
+    waker: Option<Waker>,
+    keepalive: Option<Keepalive>,
+    keepalive_timer: Option<Timer>,
+    clock: Arc<dyn Clock>,
+    audit: AuditTrail,
     // TODO: tls_required=true
 }
 
 /// XMPP server connection configuration
 #[derive(Clone)]
 pub enum ServerConfig {
+    /// Use SRV record to find server host
     UseSrv,
     #[allow(unused)]
+    /// Manually define server host and port
     Manual {
+        /// Server host name
         host: String,
+        /// Server port
         port: u16,
     },
 }
 
 /// XMMPP client configuration
+#[derive(Clone)]
 pub struct Config {
+    /// jid of the account
     pub jid: Jid,
+    /// password of the account
     pub password: String,
+    /// server configuration for the account
     pub server: ServerConfig,
+    /// how to get the connection encrypted
+    pub tls_mode: TlsMode,
+    /// how to establish TLS
+    pub tls: TlsConfig,
+    /// how long to wait for each endpoint to get the connection
+    /// encrypted, before trying the next one
+    pub connect_timeout: Duration,
+    /// which SASL mechanisms to authenticate with
+    pub sasl_policy: SaslPolicy,
+    /// what the server may send us, like how deep the elements nest
+    pub parser_limits: ParserLimits,
 }
 
 type XMPPStream = xmpp_stream::XMPPStream<TlsStream<TcpStream>>;
 
 enum ClientState {
     Invalid,
+    Idle,
     Disconnected,
-    Connecting(JoinHandle<Result<XMPPStream, Error>>),
+    Connecting(JoinHandle<Result<Session, Error>>),
     Connected(XMPPStream),
 }
 
+/// A new connection, ready to be used
+struct Session {
+    stream: XMPPStream,
+    sm: Option<SmState>,
+    resumed: bool,
+    /// Stanzas to send again on a resumed session
+    resend: Vec<Element>,
+    /// Stanzas received before stream management got enabled
+    early: Vec<Element>,
+}
+
 impl Client {
     /// Start a new XMPP client
     ///
@@ -72,22 +135,38 @@ impl Client {
             jid: jid.clone(),
             password: password.into(),
             server: ServerConfig::UseSrv,
+            tls_mode: TlsMode::default(),
+            tls: TlsConfig::default(),
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+            sasl_policy: SaslPolicy::default(),
+            parser_limits: ParserLimits::default(),
         };
         let client = Self::new_with_config(config);
         Ok(client)
     }
 
     /// Start a new client given that the JID is already parsed.
+    ///
+    /// It only connects once polled, with the settings made until then.
     pub fn new_with_config(config: Config) -> Self {
-        let connect = tokio::spawn(Self::connect(
-            config.server.clone(),
-            config.jid.clone(),
-            config.password.clone(),
-        ));
         let client = Client {
             config,
-            state: ClientState::Connecting(connect),
+            state: ClientState::Idle,
             reconnect: false,
+            memory: None,
+            char_policy: CharPolicy::default(),
+            outbox: None,
+            outbox_capacity: None,
+            sm: None,
+            sm_storage: None,
+            sm_awaiting_ack: false,
+            sm_memory: None,
+            received: VecDeque::new(),
+            waker: None,
+            keepalive: None,
+            keepalive_timer: None,
+            clock: Arc::new(TokioClock),
+            audit: AuditTrail::default(),
         };
         client
     }
@@ -99,50 +178,389 @@ impl Client {
         self
     }
 
-    async fn connect(
-        server: ServerConfig,
-        jid: Jid,
-        password: String,
-    ) -> Result<XMPPStream, Error> {
-        let username = jid.clone().node().unwrap();
-        let password = password;
-
-        // TCP connection
-        let tcp_stream = match server {
-            ServerConfig::UseSrv => {
-                connect_with_srv(&jid.clone().domain(), "_xmpp-client._tcp", 5222).await?
+    /// Account the memory used by this client, including while
+    /// negotiating and after reconnecting, to `account`, disconnecting
+    /// with `Error::MemoryLimit` when it goes over its ceiling.
+    ///
+    /// This covers the bytes being decoded and the stanzas waiting for
+    /// the acknowledgement of stream management.
+    pub fn set_memory_account(&mut self, account: MemoryAccount) -> &mut Self {
+        if let ClientState::Connected(ref mut stream) = self.state {
+            stream.stream.codec_mut().set_memory_account(&account);
+        }
+        self.sm_memory = Some(account.charge(MemoryUse::SmQueue));
+        // Going over the ceiling gets reported once the queue grows.
+        let _ = self.charge_sm_queue();
+        self.memory = Some(account);
+        self
+    }
+
+    /// Set what to do with the characters forbidden in XML sent by the
+    /// server, including after reconnecting.
+    pub fn set_char_policy(&mut self, policy: CharPolicy) -> &mut Self {
+        if let ClientState::Connected(ref mut stream) = self.state {
+            stream.stream.codec_mut().set_char_policy(&policy);
+        }
+        self.char_policy = policy;
+        self
+    }
+
+    /// Keep the stanzas sent while disconnected in an outbox backed by
+    /// `storage`, to send them in order once connected again.
+    ///
+    /// Without this, sending while disconnected fails with
+    /// `Error::InvalidState`.
+    pub fn set_outbox<S: OutboxStorage + Send + 'static>(&mut self, storage: S) -> &mut Self {
+        let storage: Box<dyn OutboxStorage + Send> = Box::new(storage);
+        let mut outbox = Outbox::new(storage);
+        outbox.set_capacity(self.outbox_capacity);
+        self.outbox = Some(outbox);
+        self
+    }
+
+    /// Refuse to queue more than `capacity` stanzas in the outbox, with
+    /// `Error::OutboxFull`, or accept any number of them with `None`.
+    ///
+    /// This only bounds the outbox set with [`Client::set_outbox`], before
+    /// or after this, and doesn't set up any outbox by itself.
+    pub fn set_outbox_capacity(&mut self, capacity: Option<usize>) -> &mut Self {
+        self.outbox_capacity = capacity;
+        if let Some(ref mut outbox) = self.outbox {
+            outbox.set_capacity(capacity);
+        }
+        self
+    }
+
+    /// Get the number of stanzas in the outbox, including those sent but
+    /// not acknowledged yet.
+    pub fn outbox_len(&mut self) -> Result<usize, Error> {
+        match self.outbox {
+            Some(ref mut outbox) => outbox.count(),
+            None => Ok(0),
+        }
+    }
+
+    /// Get whether the outbox would refuse a new stanza, in which case
+    /// senders should wait for the queued ones to get sent.
+    pub fn is_outbox_full(&mut self) -> Result<bool, Error> {
+        match self.outbox {
+            Some(ref mut outbox) => outbox.is_full(),
+            None => Ok(false),
+        }
+    }
+
+    /// Enable stream management (XEP-0198) when the server supports it,
+    /// keeping its state in `storage` so that the session can get resumed
+    /// after reconnecting, including from another process.
+    ///
+    /// A state already in `storage` gets resumed on the next connection.
+    pub fn set_stream_management<S: SmStorage + Send + 'static>(
+        &mut self,
+        storage: S,
+    ) -> &mut Self {
+        self.sm_storage = Some(Box::new(storage));
+        self
+    }
+
+    /// Get the stream management state of the current session, if the
+    /// server enabled it.
+    pub fn stream_management(&self) -> Option<&SmState> {
+        self.sm.as_ref()
+    }
+
+    /// Ping the server after `interval` without receiving anything from
+    /// it, and disconnect with `Error::PingTimeout` if it doesn't answer
+    /// within `timeout`. This also answers the pings of the server.
+    pub fn set_keepalive(&mut self, interval: Duration, timeout: Duration) -> &mut Self {
+        let mut keepalive = Keepalive::new(interval, timeout);
+        if let ClientState::Connected(_) = self.state {
+            keepalive.reset(self.clock.now());
+            self.keepalive_timer = Some(self.clock.sleep_until(keepalive.deadline()));
+        }
+        self.keepalive = Some(keepalive);
+        self
+    }
+
+    /// Read the time and sleep through `clock`, instead of the clock of
+    /// the Tokio runtime.
+    pub fn set_clock<C: Clock + 'static>(&mut self, clock: C) -> &mut Self {
+        self.clock = Arc::new(clock);
+        if let (Some(keepalive), Some(_)) = (&self.keepalive, &self.keepalive_timer) {
+            self.keepalive_timer = Some(self.clock.sleep_until(keepalive.deadline()));
+        }
+        self
+    }
+
+    fn start_keepalive(&mut self) {
+        if let Some(ref mut keepalive) = self.keepalive {
+            keepalive.reset(self.clock.now());
+            self.keepalive_timer = Some(self.clock.sleep_until(keepalive.deadline()));
+        }
+    }
+
+    fn poll_keepalive(&mut self, stream: &mut XMPPStream, cx: &mut Context) -> Result<(), Error> {
+        let (keepalive, timer) = match (&mut self.keepalive, &mut self.keepalive_timer) {
+            (Some(keepalive), Some(timer)) => (keepalive, timer),
+            _ => return Ok(()),
+        };
+        while timer.as_mut().poll(cx).is_ready() {
+            let now = self.clock.now();
+            // Stanzas received since may have postponed the deadline.
+            if now >= keepalive.deadline() {
+                match keepalive.on_deadline(now) {
+                    KeepaliveAction::SendPing(ping) => start_send(
+                        &mut self.sm,
+                        &mut self.sm_memory,
+                        stream,
+                        Packet::Stanza(ping),
+                    )?,
+                    KeepaliveAction::Dead => return Err(Error::PingTimeout),
+                }
             }
-            ServerConfig::Manual { host, port } => connect_to_host(host.as_str(), port).await?,
+            *timer = self.clock.sleep_until(keepalive.deadline());
+        }
+        Ok(())
+    }
+
+    /// Save the stream management state, or forget about it once the
+    /// session can't get resumed anymore.
+    fn save_sm(&mut self) {
+        let storage = match self.sm_storage {
+            Some(ref mut storage) => storage,
+            None => return,
         };
+        let result = match self.sm {
+            Some(ref state) => storage.save(state),
+            None => storage.clear(),
+        };
+        if let Err(e) = result {
+            warn!("Failed to save the stream management state: {}", e);
+        }
+    }
 
-        // Unencryped XMPPStream
-        let xmpp_stream =
-            xmpp_stream::XMPPStream::start(tcp_stream, jid.clone(), ns::JABBER_CLIENT.to_owned())
-                .await?;
+    /// Handle a stream management nonza, answering the requests of the
+    /// server and dropping what it acknowledged.
+    #[allow(clippy::result_large_err)]
+    fn handle_sm(&mut self, stream: &mut XMPPStream, elem: Element) -> Result<(), Error> {
+        let state = match self.sm {
+            Some(ref mut state) => state,
+            None => return Ok(()),
+        };
+        if elem.is("r", ns::SM) {
+            let answer = A::new(state.inbound);
+            Pin::new(stream).start_send(Packet::Stanza(answer.into()))?;
+        } else if elem.is("a", ns::SM) {
+            let a = A::try_from(elem).map_err(ProtocolError::Parsers)?;
+            let acknowledged = state.acknowledged(a.h)?;
+            self.sm_awaiting_ack = false;
+            if let Some(ref mut memory) = self.sm_memory {
+                let bytes: usize = acknowledged.iter().map(stanza_bytes).sum();
+                memory.set(memory.bytes().saturating_sub(bytes))?;
+            }
+            if let Some(ref mut outbox) = self.outbox {
+                outbox.reconcile(state)?;
+            }
+        } else {
+            return Ok(());
+        }
+        self.save_sm();
+        Ok(())
+    }
 
-        let xmpp_stream = if xmpp_stream.stream_features.can_starttls() {
-            // TlsStream
-            let tls_stream = starttls(xmpp_stream).await?;
-            // Encrypted XMPPStream
-            xmpp_stream::XMPPStream::start(tls_stream, jid.clone(), ns::JABBER_CLIENT.to_owned())
+    /// Charge the stanzas waiting for the acknowledgement of the server
+    /// to the memory account, if any.
+    #[allow(clippy::result_large_err)]
+    fn charge_sm_queue(&mut self) -> Result<(), Error> {
+        let memory = match self.sm_memory {
+            Some(ref mut memory) => memory,
+            None => return Ok(()),
+        };
+        let bytes = match self.sm {
+            Some(ref state) => state.unacked.iter().map(stanza_bytes).sum(),
+            None => 0,
+        };
+        memory.set(bytes)?;
+        Ok(())
+    }
+
+    /// Ask the server to acknowledge the stanzas sent, unless it already
+    /// got asked.
+    #[allow(clippy::result_large_err)]
+    fn request_ack(&mut self, stream: &mut XMPPStream) -> Result<(), Error> {
+        match self.sm {
+            Some(ref state) if !state.unacked.is_empty() && !self.sm_awaiting_ack => {
+                Pin::new(stream).start_send(Packet::Stanza(R.into()))?;
+                self.sm_awaiting_ack = true;
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Send the stanzas of the outbox, in order, as long as the stream
+    /// accepts them.
+    fn poll_outbox(
+        outbox: &mut Option<Outbox<Box<dyn OutboxStorage + Send>>>,
+        sm: &mut Option<SmState>,
+        sm_memory: &mut Option<MemoryCharge>,
+        stream: &mut XMPPStream,
+        cx: &mut Context,
+    ) -> Result<(), Error> {
+        let outbox = match outbox {
+            Some(outbox) => outbox,
+            None => return Ok(()),
+        };
+        while outbox.pending()? > 0 {
+            match Pin::new(&mut *stream).poll_ready(cx) {
+                Poll::Ready(Ok(())) => (),
+                Poll::Ready(Err(e)) => return Err(e),
+                Poll::Pending => break,
+            }
+            let stanza = match outbox.next_unsent()? {
+                Some(stanza) => stanza,
+                None => break,
+            };
+            start_send(sm, sm_memory, stream, Packet::Stanza(stanza))?;
+            outbox.mark_sent();
+        }
+        Ok(())
+    }
+
+    /// Forget about the stanzas of the outbox which got flushed, unless
+    /// they wait for the acknowledgement of stream management instead.
+    #[allow(clippy::result_large_err)]
+    fn outbox_flushed(
+        outbox: &mut Option<Outbox<Box<dyn OutboxStorage + Send>>>,
+        sm: &Option<SmState>,
+    ) -> Result<(), Error> {
+        match (outbox, sm) {
+            (Some(outbox), None) => outbox.delivered(),
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether stanzas have to go through the outbox, either because we
+    /// are disconnected or to keep them after those queued before.
+    fn must_queue(&mut self) -> Result<bool, Error> {
+        match self.outbox {
+            Some(ref mut outbox) => match self.state {
+                ClientState::Connected(_) => Ok(outbox.pending()? > 0),
+                _ => Ok(true),
+            },
+            None => Ok(false),
+        }
+    }
+
+    fn start_connecting(&mut self) {
+        // The state in memory is more recent than the saved one.
+        let sm = match self.sm_storage {
+            Some(ref mut storage) => Some(match self.sm.take() {
+                Some(state) => Some(state),
+                None => storage.load().unwrap_or_else(|e| {
+                    warn!("Failed to load the stream management state: {}", e);
+                    None
+                }),
+            }),
+            None => None,
+        };
+        let mut codec = XMPPCodec::with_parser_limits(self.config.parser_limits);
+        if let Some(ref memory) = self.memory {
+            codec.set_memory_account(memory);
+        }
+        codec.set_char_policy(&self.char_policy);
+        let connect = tokio::spawn(Self::connect(
+            self.config.clone(),
+            codec,
+            self.audit.clone(),
+            sm,
+        ));
+        self.state = ClientState::Connecting(connect);
+    }
+
+    /// Connect with `codec`, resuming the stream management session of
+    /// `sm` or else enabling it when `sm` isn't `None`.
+    async fn connect(
+        config: Config,
+        codec: XMPPCodec,
+        audit: AuditTrail,
+        sm: Option<Option<SmState>>,
+    ) -> Result<Session, Error> {
+        audit.record(AuditEvent::Connecting);
+        let jid = config.jid;
+        // Without any node, the client authenticates anonymously.
+        let username = jid.clone().node();
+
+        // TlsStream, either direct or after STARTTLS
+        let tls_stream = match config.server {
+            ServerConfig::UseSrv => {
+                connect_tls_with_srv(
+                    &jid,
+                    config.tls_mode,
+                    &config.tls,
+                    config.connect_timeout,
+                    Some(&audit),
+                )
                 .await?
-        } else {
-            return Err(Error::Protocol(ProtocolError::NoTls));
+            }
+            ServerConfig::Manual { host, port } => {
+                let direct_tls = config.tls_mode.prefers_direct_tls();
+                connect_tls(&jid, &host, port, direct_tls, &config.tls, Some(&audit)).await?
+            }
         };
 
-        let creds = Credentials::default()
-            .with_username(username)
-            .with_password(password)
-            .with_channel_binding(ChannelBinding::None);
-        // Authenticated (unspecified) stream
-        let stream = auth(xmpp_stream, creds).await?;
-        // Authenticated XMPPStream
-        let xmpp_stream =
-            xmpp_stream::XMPPStream::start(stream, jid, ns::JABBER_CLIENT.to_owned()).await?;
+        // Encrypted XMPPStream
+        let xmpp_stream = stream_start::start(
+            Framed::new(tls_stream, codec),
+            jid.clone(),
+            ns::JABBER_CLIENT.to_owned(),
+        )
+        .await?;
+        audit.record(AuditEvent::StreamOpened);
+
+        let mut xmpp_stream = authenticate(
+            xmpp_stream,
+            username,
+            config.password,
+            &config.sasl_policy,
+            Some(&audit),
+        )
+        .await?;
 
-        // XMPPStream bound to user session
-        let xmpp_stream = bind(xmpp_stream).await?;
-        Ok(xmpp_stream)
+        let sm = match sm {
+            Some(saved) if xmpp_stream.stream_features.can_stream_management() => saved,
+            _ => {
+                return Ok(Session {
+                    stream: bind_session(xmpp_stream, Some(&audit)).await?,
+                    sm: None,
+                    resumed: false,
+                    resend: Vec::new(),
+                    early: Vec::new(),
+                })
+            }
+        };
+        if let Some(mut state) = sm {
+            if let Some(resend) = stream_management::resume(&mut xmpp_stream, &mut state).await? {
+                xmpp_stream.jid = state.bound_jid.clone();
+                audit.record(AuditEvent::Resumed(state.bound_jid.clone()));
+                return Ok(Session {
+                    stream: xmpp_stream,
+                    sm: Some(state),
+                    resumed: true,
+                    resend,
+                    early: Vec::new(),
+                });
+            }
+        }
+        let mut xmpp_stream = bind_session(xmpp_stream, Some(&audit)).await?;
+        let mut early = Vec::new();
+        let sm = stream_management::enable(&mut xmpp_stream, &mut early).await?;
+        Ok(Session {
+            stream: xmpp_stream,
+            sm,
+            resumed: false,
+            resend: Vec::new(),
+            early,
+        })
     }
 
     /// Get the client's bound JID (the one reported by the XMPP
@@ -154,62 +572,159 @@ impl Client {
         }
     }
 
+    /// Get the round-trip time to the server, as estimated from the
+    /// keepalive pings, if [`Client::set_keepalive`] has been used and
+    /// one of them has been answered on this connection.
+    pub fn latency(&self) -> Option<Duration> {
+        self.keepalive.as_ref().and_then(Keepalive::latency)
+    }
+
+    /// Get the trail of the milestones and errors of the last
+    /// connections, for instance to tell what happened before a
+    /// disconnection.
+    pub fn audit_trail(&self) -> &AuditTrail {
+        &self.audit
+    }
+
     /// Send stanza
+    ///
+    /// While disconnected, or while stanzas queued before are still
+    /// waiting, it gets queued in the outbox if there is one.
     pub async fn send_stanza(&mut self, stanza: Element) -> Result<(), Error> {
+        if self.must_queue()? {
+            return self.queue_stanza(stanza);
+        }
         self.send(Packet::Stanza(stanza)).await
     }
 
+    /// Queue `stanza` in the outbox without waiting, to be sent in order
+    /// as soon as the connection accepts it
+    ///
+    /// This fails with `Error::OutboxFull` when the outbox is full, and
+    /// with `Error::InvalidState` without any outbox.
+    pub fn queue_stanza(&mut self, stanza: Element) -> Result<(), Error> {
+        let outbox = self.outbox.as_mut().ok_or(Error::InvalidState)?;
+        outbox.push(stanza)?;
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+        Ok(())
+    }
+
+    /// Send all of `packets`, flushing them at once
+    ///
+    /// While disconnected, or while stanzas queued before are still
+    /// waiting, their stanzas get queued in the outbox if there is one.
+    pub async fn send_all<I: IntoIterator<Item = Packet>>(
+        &mut self,
+        packets: I,
+    ) -> Result<(), Error> {
+        if self.must_queue()? {
+            for packet in packets {
+                match packet {
+                    Packet::Stanza(stanza) => self.queue_stanza(stanza)?,
+                    _ => return Err(Error::InvalidState),
+                }
+            }
+            return Ok(());
+        }
+        for packet in packets {
+            self.feed(packet).await?;
+        }
+        self.flush().await
+    }
+
     /// End connection by sending `</stream:stream>`
     ///
     /// You may expect the server to respond with the same. This
-    /// client will then drop its connection.
+    /// client will then drop its connection, and the stream management
+    /// session, if any, can't get resumed anymore.
     ///
     /// Make sure to disable reconnect.
     pub async fn send_end(&mut self) -> Result<(), Error> {
+        self.sm = None;
+        self.save_sm();
+        self.charge_sm_queue()?;
         self.send(Packet::StreamEnd).await
     }
 }
 
-/// Incoming XMPP events
-///
-/// In an `async fn` you may want to use this with `use
-/// futures::stream::StreamExt;`
-impl Stream for Client {
-    type Item = Event;
-
-    /// Low-level read on the XMPP stream, allowing the underlying
-    /// machinery to:
-    ///
-    /// * connect,
-    /// * starttls,
-    /// * authenticate,
-    /// * bind a session, and finally
-    /// * receive stanzas
-    ///
-    /// ...for your client
-    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+impl Client {
+    fn poll_state(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Event>> {
         let state = replace(&mut self.state, ClientState::Invalid);
 
         match state {
             ClientState::Invalid => panic!("Invalid client state"),
+            ClientState::Idle => {
+                self.start_connecting();
+                self.poll_state(cx)
+            }
             ClientState::Disconnected if self.reconnect => {
                 // TODO: add timeout
-                let connect = tokio::spawn(Self::connect(
-                    self.config.server.clone(),
-                    self.config.jid.clone(),
-                    self.config.password.clone(),
-                ));
-                self.state = ClientState::Connecting(connect);
-                self.poll_next(cx)
+                self.start_connecting();
+                self.poll_state(cx)
             }
             ClientState::Disconnected => Poll::Ready(None),
             ClientState::Connecting(mut connect) => match Pin::new(&mut connect).poll(cx) {
-                Poll::Ready(Ok(Ok(stream))) => {
+                Poll::Ready(Ok(Ok(session))) => {
+                    let mut stream = session.stream;
+                    // In case they changed while connecting
+                    if let Some(ref memory) = self.memory {
+                        stream.stream.codec_mut().set_memory_account(memory);
+                    }
+                    stream.stream.codec_mut().set_char_policy(&self.char_policy);
+                    self.sm = session.sm;
+                    self.sm_awaiting_ack = false;
+                    self.save_sm();
+                    if let Err(e) = self.charge_sm_queue() {
+                        self.state = ClientState::Disconnected;
+                        return Poll::Ready(Some(Event::Disconnected(e)));
+                    }
+                    self.received = session.early.into();
+                    // Stanzas of the outbox which weren't delivered before
+                    // either get sent again with the resumed session, or
+                    // have to get sent anew.
+                    let this = &mut *self;
+                    if let Some(ref mut outbox) = this.outbox {
+                        let result = match (session.resumed, &this.sm) {
+                            (true, Some(state)) => outbox.reconcile(state),
+                            _ => {
+                                outbox.requeue();
+                                Ok(())
+                            }
+                        };
+                        if let Err(e) = result {
+                            warn!("Failed to reconcile the outbox: {}", e);
+                        }
+                    }
+                    for stanza in session.resend {
+                        let this = &mut *self;
+                        if let Err(e) = start_send(
+                            &mut this.sm,
+                            &mut this.sm_memory,
+                            &mut stream,
+                            Packet::Stanza(stanza),
+                        ) {
+                            self.state = ClientState::Disconnected;
+                            return Poll::Ready(Some(Event::Disconnected(e)));
+                        }
+                    }
+                    let this = &mut *self;
+                    if let Err(e) = Self::poll_outbox(
+                        &mut this.outbox,
+                        &mut this.sm,
+                        &mut this.sm_memory,
+                        &mut stream,
+                        cx,
+                    ) {
+                        warn!("Failed to flush the outbox: {}", e);
+                    }
+                    self.start_keepalive();
                     let bound_jid = stream.jid.clone();
                     self.state = ClientState::Connected(stream);
                     Poll::Ready(Some(Event::Online {
                         bound_jid,
-                        resumed: false,
+                        resumed: session.resumed,
                     }))
                 }
                 Poll::Ready(Ok(Err(e))) => {
@@ -226,10 +741,47 @@ impl Stream for Client {
                 }
             },
             ClientState::Connected(mut stream) => {
-                // Poll sink
-                match Pin::new(&mut stream).poll_ready(cx) {
+                // Yield what got received while enabling stream management
+                if let Some(stanza) = self.received.pop_front() {
+                    self.state = ClientState::Connected(stream);
+                    return Poll::Ready(Some(Event::Stanza(stanza)));
+                }
+
+                // Send a ping if it is time to, before flushing it below
+                if let Err(e) = self.poll_keepalive(&mut stream, cx) {
+                    self.state = ClientState::Disconnected;
+                    return Poll::Ready(Some(Event::Disconnected(e)));
+                }
+
+                // Send what the outbox has, as far as the sink accepts it
+                let this = &mut *self;
+                if let Err(e) = Self::poll_outbox(
+                    &mut this.outbox,
+                    &mut this.sm,
+                    &mut this.sm_memory,
+                    &mut stream,
+                    cx,
+                ) {
+                    self.state = ClientState::Disconnected;
+                    return Poll::Ready(Some(Event::Disconnected(e)));
+                }
+
+                // Ask for the acknowledgement of what got sent until now
+                if let Err(e) = self.request_ack(&mut stream) {
+                    self.state = ClientState::Disconnected;
+                    return Poll::Ready(Some(Event::Disconnected(e)));
+                }
+
+                // Poll sink, sending what the outbox might have left there
+                match Pin::new(&mut stream).poll_flush(cx) {
                     Poll::Pending => (),
-                    Poll::Ready(Ok(())) => (),
+                    Poll::Ready(Ok(())) => {
+                        let this = &mut *self;
+                        if let Err(e) = Self::outbox_flushed(&mut this.outbox, &this.sm) {
+                            self.state = ClientState::Disconnected;
+                            return Poll::Ready(Some(Event::Disconnected(e)));
+                        }
+                    }
                     Poll::Ready(Err(e)) => {
                         self.state = ClientState::Disconnected;
                         return Poll::Ready(Some(Event::Disconnected(e.into())));
@@ -243,10 +795,54 @@ impl Stream for Client {
                         self.state = ClientState::Disconnected;
                         Poll::Ready(Some(Event::Disconnected(Error::Disconnected)))
                     }
-                    Poll::Ready(Some(Ok(Packet::Stanza(stanza)))) => {
-                        // Receive stanza
+                    Poll::Ready(Some(Ok(Packet::Stanza(stanza))))
+                        if self.sm.is_some() && stanza.ns() == ns::SM =>
+                    {
+                        // Stream management nonza, handled here
+                        if let Err(e) = self.handle_sm(&mut stream, stanza) {
+                            self.state = ClientState::Disconnected;
+                            return Poll::Ready(Some(Event::Disconnected(e)));
+                        }
                         self.state = ClientState::Connected(stream);
-                        Poll::Ready(Some(Event::Stanza(stanza)))
+                        self.poll_state(cx)
+                    }
+                    Poll::Ready(Some(Ok(Packet::Stanza(stanza)))) => {
+                        // Receive stanza, counting it even when the
+                        // keepalive handles it
+                        if let Some(ref mut state) = self.sm {
+                            if is_stanza(&stanza) {
+                                state.stanza_received();
+                            }
+                        }
+                        let now = self.clock.now();
+                        let incoming = match self.keepalive {
+                            Some(ref mut keepalive) => keepalive.on_stanza(&stanza, now),
+                            None => KeepaliveIncoming::Other,
+                        };
+                        match incoming {
+                            KeepaliveIncoming::Other => {
+                                self.state = ClientState::Connected(stream);
+                                Poll::Ready(Some(Event::Stanza(stanza)))
+                            }
+                            KeepaliveIncoming::Pong => {
+                                self.state = ClientState::Connected(stream);
+                                self.poll_state(cx)
+                            }
+                            KeepaliveIncoming::Ping(pong) => {
+                                let this = &mut *self;
+                                if let Err(e) = start_send(
+                                    &mut this.sm,
+                                    &mut this.sm_memory,
+                                    &mut stream,
+                                    Packet::Stanza(pong),
+                                ) {
+                                    self.state = ClientState::Disconnected;
+                                    return Poll::Ready(Some(Event::Disconnected(e)));
+                                }
+                                self.state = ClientState::Connected(stream);
+                                self.poll_state(cx)
+                            }
+                        }
                     }
                     Poll::Ready(Some(Ok(Packet::Text(_)))) => {
                         // Ignore text between stanzas
@@ -280,6 +876,34 @@ impl Stream for Client {
     }
 }
 
+/// Incoming XMPP events
+///
+/// In an `async fn` you may want to use this with `use
+/// futures::stream::StreamExt;`
+impl Stream for Client {
+    type Item = Event;
+
+    /// Low-level read on the XMPP stream, allowing the underlying
+    /// machinery to:
+    ///
+    /// * connect,
+    /// * starttls,
+    /// * authenticate,
+    /// * bind a session, and finally
+    /// * receive stanzas
+    ///
+    /// ...for your client
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        // To get woken up when stanzas get queued in the outbox
+        self.waker = Some(cx.waker().clone());
+        let event = self.as_mut().poll_state(cx);
+        if let Poll::Ready(Some(Event::Disconnected(ref e))) = event {
+            self.audit.record(AuditEvent::Disconnected(e.to_string()));
+        }
+        event
+    }
+}
+
 /// Outgoing XMPP packets
 ///
 /// See `send_stanza()` for an `async fn`
@@ -287,29 +911,48 @@ impl Sink<Packet> for Client {
     type Error = Error;
 
     fn start_send(mut self: Pin<&mut Self>, item: Packet) -> Result<(), Self::Error> {
-        match self.state {
+        let item = match item {
+            Packet::Stanza(stanza) if self.must_queue()? => return self.queue_stanza(stanza),
+            item => item,
+        };
+        let this = &mut *self;
+        match this.state {
             ClientState::Connected(ref mut stream) => {
-                Pin::new(stream).start_send(item).map_err(|e| e.into())
+                start_send(&mut this.sm, &mut this.sm_memory, stream, item)
             }
             _ => Err(Error::InvalidState),
         }
     }
 
+    /// While disconnected, this is ready as long as the outbox, if any,
+    /// isn't full, and fails with `Error::OutboxFull` otherwise.
     fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
-        match self.state {
-            ClientState::Connected(ref mut stream) => {
+        let this = &mut *self;
+        match (&mut this.state, &mut this.outbox) {
+            (ClientState::Connected(ref mut stream), outbox) => {
+                Self::poll_outbox(outbox, &mut this.sm, &mut this.sm_memory, stream, cx)?;
                 Pin::new(stream).poll_ready(cx).map_err(|e| e.into())
             }
-            _ => Poll::Pending,
+            (_, Some(outbox)) => match outbox.is_full()? {
+                false => Poll::Ready(Ok(())),
+                true => Poll::Ready(Err(Error::OutboxFull)),
+            },
+            (_, None) => Poll::Pending,
         }
     }
 
     fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
-        match self.state {
-            ClientState::Connected(ref mut stream) => {
-                Pin::new(stream).poll_flush(cx).map_err(|e| e.into())
+        let this = &mut *self;
+        match (&mut this.state, &mut this.outbox) {
+            (ClientState::Connected(ref mut stream), outbox) => {
+                Self::poll_outbox(outbox, &mut this.sm, &mut this.sm_memory, stream, cx)?;
+                match Pin::new(stream).poll_flush(cx) {
+                    Poll::Ready(Ok(())) => Poll::Ready(Self::outbox_flushed(outbox, &this.sm)),
+                    poll => poll,
+                }
             }
-            _ => Poll::Pending,
+            (_, Some(_)) => Poll::Ready(Ok(())),
+            (_, None) => Poll::Pending,
         }
     }
 
@@ -322,3 +965,33 @@ impl Sink<Packet> for Client {
         }
     }
 }
+
+/// Start sending `packet`, keeping its stanza until the server
+/// acknowledges it when stream management is enabled, and charging it
+/// to `sm_memory` meanwhile
+#[allow(clippy::result_large_err)]
+fn start_send(
+    sm: &mut Option<SmState>,
+    sm_memory: &mut Option<MemoryCharge>,
+    stream: &mut XMPPStream,
+    packet: Packet,
+) -> Result<(), Error> {
+    let sent = match (&*sm, &packet) {
+        (Some(_), Packet::Stanza(stanza)) if is_stanza(stanza) => Some(stanza.clone()),
+        _ => None,
+    };
+    Pin::new(stream).start_send(packet)?;
+    if let (Some(state), Some(stanza)) = (sm, sent) {
+        let bytes = stanza_bytes(&stanza);
+        state.stanza_sent(stanza);
+        if let Some(memory) = sm_memory {
+            memory.set(memory.bytes() + bytes)?;
+        }
+    }
+    Ok(())
+}
+
+/// Size of `stanza` once serialized, as kept by stream management
+fn stanza_bytes(stanza: &Element) -> usize {
+    String::from(stanza).len()
+}
@@ -1,34 +1,47 @@
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
 use futures::stream::StreamExt;
 use sasl::client::mechanisms::{Anonymous, Plain, Scram};
 use sasl::client::Mechanism;
-use sasl::common::scram::{Sha1, Sha256};
-use sasl::common::Credentials;
+use sasl::common::scram::{ScramProvider, Sha1, Sha256};
+use sasl::common::{Credentials, Identity};
 use std::collections::HashSet;
 use std::convert::TryFrom;
 use std::str::FromStr;
 use tokio::io::{AsyncRead, AsyncWrite};
 use xmpp_parsers::sasl::{Auth, Challenge, Failure, Mechanism as XMPPMechanism, Response, Success};
 
+use crate::sasl_policy::SaslPolicy;
 use crate::xmpp_codec::Packet;
 use crate::xmpp_stream::XMPPStream;
 use crate::{AuthError, Error, ProtocolError};
 
+type LocalMechanism<'a> = Box<dyn Fn() -> Box<dyn Mechanism + Send + Sync> + Send + 'a>;
+
 pub async fn auth<S: AsyncRead + AsyncWrite + Unpin>(
     mut stream: XMPPStream<S>,
     creds: Credentials,
-) -> Result<S, Error> {
-    let local_mechs: Vec<Box<dyn Fn() -> Box<dyn Mechanism + Send + Sync> + Send>> = vec![
-        Box::new(|| Box::new(Scram::<Sha256>::from_credentials(creds.clone()).unwrap())),
-        Box::new(|| Box::new(Scram::<Sha1>::from_credentials(creds.clone()).unwrap())),
-        Box::new(|| Box::new(Plain::from_credentials(creds.clone()).unwrap())),
-        Box::new(|| Box::new(Anonymous::new())),
-    ];
+    policy: &SaslPolicy,
+    secure: bool,
+) -> Result<XMPPStream<S>, Error> {
+    // Without a username, only authenticate anonymously, and never
+    // downgrade to it otherwise.
+    let local_mechs: Vec<LocalMechanism> = match creds.identity {
+        Identity::None => vec![Box::new(|| Box::new(Anonymous::new()))],
+        Identity::Username(_) => vec![
+            Box::new(|| Box::new(Scram::<Sha256>::from_credentials(creds.clone()).unwrap())),
+            Box::new(|| Box::new(Scram::<Sha1>::from_credentials(creds.clone()).unwrap())),
+            Box::new(|| Box::new(Plain::from_credentials(creds.clone()).unwrap())),
+        ],
+    };
 
     let remote_mechs: HashSet<String> = stream.stream_features.sasl_mechanisms()?.collect();
+    let channel_bindings: Vec<String> = stream.stream_features.sasl_channel_bindings().collect();
+    let ssdp = ssdp_input(&remote_mechs, &channel_bindings);
 
     for local_mech in local_mechs {
         let mut mechanism = local_mech();
-        if remote_mechs.contains(mechanism.name()) {
+        if remote_mechs.contains(mechanism.name()) && policy.permits(mechanism.name(), secure) {
             let initial = mechanism.initial();
             let mechanism_name =
                 XMPPMechanism::from_str(mechanism.name()).map_err(ProtocolError::Parsers)?;
@@ -40,10 +53,15 @@ pub async fn auth<S: AsyncRead + AsyncWrite + Unpin>(
                 })
                 .await?;
 
+            let mut first_challenge = true;
             loop {
                 match stream.next().await {
                     Some(Ok(Packet::Stanza(stanza))) => {
                         if let Ok(challenge) = Challenge::try_from(stanza.clone()) {
+                            if first_challenge {
+                                first_challenge = false;
+                                check_ssdp(mechanism.name(), &challenge.data, &ssdp)?;
+                            }
                             let response = mechanism
                                 .response(&challenge.data)
                                 .map_err(|e| AuthError::Sasl(e))?;
@@ -51,7 +69,7 @@ pub async fn auth<S: AsyncRead + AsyncWrite + Unpin>(
                             // Send response and loop
                             stream.send_stanza(Response { data: response }).await?;
                         } else if let Ok(_) = Success::try_from(stanza.clone()) {
-                            return Ok(stream.into_inner());
+                            return Ok(stream);
                         } else if let Ok(failure) = Failure::try_from(stanza.clone()) {
                             return Err(Error::Auth(AuthError::Fail(failure.defined_condition)));
                         // TODO: This code was needed for compatibility with some broken server,
@@ -78,3 +96,78 @@ pub async fn auth<S: AsyncRead + AsyncWrite + Unpin>(
 
     Err(AuthError::NoMechanism.into())
 }
+
+/// The list of mechanisms and channel binding types which the server
+/// signs in its SCRAM challenge for downgrade protection (XEP-0474), before
+/// hashing.
+fn ssdp_input<'a, I: IntoIterator<Item = &'a String>>(
+    mechanisms: I,
+    channel_bindings: &[String],
+) -> String {
+    let mut mechanisms: Vec<&str> = mechanisms.into_iter().map(String::as_str).collect();
+    mechanisms.sort_unstable();
+    let mut channel_bindings: Vec<&str> = channel_bindings.iter().map(String::as_str).collect();
+    channel_bindings.sort_unstable();
+    format!("{}|{}", mechanisms.join(","), channel_bindings.join(","))
+}
+
+/// Compare the downgrade protection attribute of the first SCRAM
+/// challenge, if the server sent one, against what the stream features
+/// advertised, to detect an attacker having removed some of them.
+fn check_ssdp(mechanism: &str, server_first: &[u8], ssdp: &str) -> Result<(), AuthError> {
+    let hash = match mechanism {
+        "SCRAM-SHA-1" | "SCRAM-SHA-1-PLUS" => Sha1::hash(ssdp.as_bytes()),
+        "SCRAM-SHA-256" | "SCRAM-SHA-256-PLUS" => Sha256::hash(ssdp.as_bytes()),
+        _ => return Ok(()),
+    };
+    let attribute = server_first
+        .split(|&c| c == b',')
+        .find_map(|attribute| attribute.strip_prefix(b"d="));
+    match attribute {
+        None => Ok(()),
+        Some(value) if BASE64.decode(value).ok() == Some(hash) => Ok(()),
+        Some(_) => Err(AuthError::Downgrade),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| String::from(*s)).collect()
+    }
+
+    #[test]
+    fn test_ssdp_input() {
+        let mechanisms = strings(&["SCRAM-SHA-256", "PLAIN", "SCRAM-SHA-1"]);
+        let channel_bindings = strings(&["tls-server-end-point", "tls-exporter"]);
+        assert_eq!(
+            ssdp_input(&mechanisms, &channel_bindings),
+            "PLAIN,SCRAM-SHA-1,SCRAM-SHA-256|tls-exporter,tls-server-end-point"
+        );
+        assert_eq!(
+            ssdp_input(&mechanisms, &[]),
+            "PLAIN,SCRAM-SHA-1,SCRAM-SHA-256|"
+        );
+    }
+
+    #[test]
+    fn test_check_ssdp() {
+        let ssdp = "PLAIN,SCRAM-SHA-1,SCRAM-SHA-256|";
+        let d = BASE64.encode(Sha256::hash(ssdp.as_bytes()));
+        let server_first = format!("r=abc,s=c2FsdA==,i=4096,d={}", d);
+        assert!(check_ssdp("SCRAM-SHA-256", server_first.as_bytes(), ssdp).is_ok());
+
+        // PLAIN got stripped from the features the client received.
+        let stripped = "SCRAM-SHA-1,SCRAM-SHA-256|";
+        assert!(matches!(
+            check_ssdp("SCRAM-SHA-256", server_first.as_bytes(), stripped),
+            Err(AuthError::Downgrade)
+        ));
+
+        // Without the attribute, the server doesn't support it.
+        assert!(check_ssdp("SCRAM-SHA-256", b"r=abc,s=c2FsdA==,i=4096", stripped).is_ok());
+        assert!(check_ssdp("PLAIN", server_first.as_bytes(), stripped).is_ok());
+    }
+}
@@ -1,5 +1,356 @@
+use futures::Future;
+use log::warn;
+use sasl::common::{ChannelBinding, Credentials};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+use tokio::time;
+#[cfg(feature = "tls-native")]
+use tokio_native_tls::TlsStream;
+#[cfg(feature = "tls-rust")]
+use tokio_rustls::client::TlsStream;
+use xmpp_parsers::{ns, Jid};
+
+use self::auth::auth;
+use self::bind::bind;
+use crate::audit::{AuditEvent, AuditTrail};
+use crate::discovery::{lookup_srv, SrvTarget};
+use crate::happy_eyeballs::connect_to_host;
+use crate::sasl_policy::SaslPolicy;
+use crate::starttls::starttls_with_trust;
+use crate::tls::TlsConfig;
+use crate::xmpp_stream::XMPPStream;
+use crate::{ConnecterError, Error, ProtocolError};
+
 mod auth;
 mod bind;
+#[cfg(test)]
+mod replay;
 
 pub mod async_client;
 pub mod simple_client;
+
+/// How long to wait for each endpoint to get the connection encrypted by
+/// default, before trying the next one
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How to get the connection to the server encrypted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TlsMode {
+    /// Try the STARTTLS and direct TLS endpoints together, in the order of
+    /// the priority and weight of their SRV records (XEP-0368)
+    #[default]
+    Any,
+    /// Try the STARTTLS endpoints first, then the direct TLS ones
+    PreferStartTls,
+    /// Try the direct TLS endpoints (XEP-0368) first, then the STARTTLS
+    /// ones
+    PreferDirectTls,
+    /// Only use STARTTLS
+    RequireStartTls,
+    /// Only use direct TLS
+    RequireDirectTls,
+}
+
+impl TlsMode {
+    /// Whether a server configured manually should get TLS negotiated
+    /// right after connecting, instead of using STARTTLS.
+    pub fn prefers_direct_tls(self) -> bool {
+        matches!(self, TlsMode::PreferDirectTls | TlsMode::RequireDirectTls)
+    }
+
+    /// Drop the targets this mode doesn't allow, and move the preferred
+    /// ones first, otherwise keeping their order.
+    pub fn select_targets(self, mut targets: Vec<SrvTarget>) -> Vec<SrvTarget> {
+        match self {
+            TlsMode::Any => (),
+            TlsMode::PreferStartTls => targets.sort_by_key(|target| target.direct_tls),
+            TlsMode::PreferDirectTls => targets.sort_by_key(|target| !target.direct_tls),
+            TlsMode::RequireStartTls => targets.retain(|target| !target.direct_tls),
+            TlsMode::RequireDirectTls => targets.retain(|target| target.direct_tls),
+        }
+        targets
+    }
+
+    /// The targets to try when `domain` doesn't have any SRV record: the
+    /// standard ports on the domain itself.
+    fn fallback_targets(self, domain: &str) -> Vec<SrvTarget> {
+        let target = |port, direct_tls| SrvTarget {
+            host: domain.to_owned(),
+            port,
+            priority: 0,
+            weight: 0,
+            direct_tls,
+        };
+        match self {
+            TlsMode::Any | TlsMode::PreferStartTls | TlsMode::RequireStartTls => {
+                vec![target(5222, false)]
+            }
+            TlsMode::PreferDirectTls => vec![target(5223, true), target(5222, false)],
+            TlsMode::RequireDirectTls => vec![target(5223, true)],
+        }
+    }
+}
+
+/// Connect to `host` and get the connection encrypted with `tls`, either
+/// right away or with STARTTLS, recording each step in `audit` if any.
+pub(crate) async fn connect_tls(
+    jid: &Jid,
+    host: &str,
+    port: u16,
+    direct_tls: bool,
+    tls: &TlsConfig,
+    audit: Option<&AuditTrail>,
+) -> Result<TlsStream<TcpStream>, Error> {
+    let record = |event| {
+        if let Some(audit) = audit {
+            audit.record(event);
+        }
+    };
+    let domain = idna::domain_to_ascii(&jid.clone().domain()).map_err(|_| Error::Idna)?;
+
+    let trust = tls
+        .verification
+        .resolve(&domain, Some((host, port)))
+        .await?;
+    let tcp_stream = connect_to_host(host, port).await?;
+    record(AuditEvent::TcpConnected);
+
+    if direct_tls {
+        let tls_stream = tls.connect(&domain, tcp_stream, &trust, true).await?;
+        record(AuditEvent::TlsEstablished);
+        return Ok(tls_stream);
+    }
+
+    // Unencryped XMPPStream
+    let xmpp_stream =
+        XMPPStream::start(tcp_stream, jid.clone(), ns::JABBER_CLIENT.to_owned()).await?;
+    if !xmpp_stream.stream_features.can_starttls() {
+        return Err(Error::Protocol(ProtocolError::NoTls));
+    }
+    let tls_stream = starttls_with_trust(xmpp_stream, tls, &trust).await?;
+    record(AuditEvent::TlsEstablished);
+    Ok(tls_stream)
+}
+
+/// Try `connect` on each target in order, giving each of them `timeout`,
+/// until one succeeds, or else return the error of the last one.
+async fn try_targets<T, F, Fut>(
+    targets: Vec<SrvTarget>,
+    timeout: Duration,
+    mut connect: F,
+) -> Result<T, Error>
+where
+    F: FnMut(SrvTarget) -> Fut,
+    Fut: Future<Output = Result<T, Error>>,
+{
+    let mut last_error = Error::Connection(ConnecterError::AllFailed);
+    for target in targets {
+        let (host, port) = (target.host.clone(), target.port);
+        match time::timeout(timeout, connect(target)).await {
+            Ok(Ok(stream)) => return Ok(stream),
+            Ok(Err(e)) => {
+                warn!("Failed to connect to {}:{}: {}", host, port, e);
+                last_error = e;
+            }
+            Err(_) => {
+                warn!("Timed out connecting to {}:{}", host, port);
+                last_error = ConnecterError::Timeout.into();
+            }
+        }
+    }
+    Err(last_error)
+}
+
+/// Connect to the first endpoint of the domain of `jid` allowed by
+/// `mode` which gets the connection encrypted within `timeout`, falling
+/// back to the standard ports when the domain doesn't have any SRV record.
+pub(crate) async fn connect_tls_with_srv(
+    jid: &Jid,
+    mode: TlsMode,
+    tls: &TlsConfig,
+    timeout: Duration,
+    audit: Option<&AuditTrail>,
+) -> Result<TlsStream<TcpStream>, Error> {
+    let domain = jid.clone().domain();
+    let mut targets = mode.select_targets(lookup_srv(&domain).await?);
+    if targets.is_empty() {
+        targets = mode.fallback_targets(&domain);
+    }
+    try_targets(targets, timeout, |target| async move {
+        connect_tls(
+            jid,
+            &target.host,
+            target.port,
+            target.direct_tls,
+            tls,
+            audit,
+        )
+        .await
+    })
+    .await
+}
+
+/// Whether a transport encrypts what gets sent over it, for the
+/// [`SaslPolicy`] to only allow PLAIN over encrypted ones
+pub(crate) trait Encrypted {
+    /// Whether this transport is encrypted
+    fn is_encrypted(&self) -> bool;
+}
+
+impl<S> Encrypted for TlsStream<S> {
+    fn is_encrypted(&self) -> bool {
+        true
+    }
+}
+
+/// Authenticate, anonymously without any `username`, then restart the
+/// stream, recording it in `audit` if any.
+pub(crate) async fn authenticate<S: AsyncRead + AsyncWrite + Unpin + Encrypted>(
+    xmpp_stream: XMPPStream<S>,
+    username: Option<String>,
+    password: String,
+    policy: &SaslPolicy,
+    audit: Option<&AuditTrail>,
+) -> Result<XMPPStream<S>, Error> {
+    let mut creds = Credentials::default()
+        .with_password(password)
+        .with_channel_binding(ChannelBinding::None);
+    if let Some(username) = username {
+        creds = creds.with_username(username);
+    }
+    let secure = xmpp_stream.stream.get_ref().is_encrypted();
+    // Authenticated stream, to restart
+    let stream = auth(xmpp_stream, creds, policy, secure).await?;
+    if let Some(audit) = audit {
+        audit.record(AuditEvent::Authenticated);
+    }
+    // Authenticated XMPPStream, keeping the settings of the codec
+    stream.restart().await
+}
+
+/// Bind the session of an authenticated stream
+pub(crate) async fn bind_session<S: AsyncRead + AsyncWrite + Unpin>(
+    xmpp_stream: XMPPStream<S>,
+    audit: Option<&AuditTrail>,
+) -> Result<XMPPStream<S>, Error> {
+    let xmpp_stream = bind(xmpp_stream).await?;
+    if let Some(audit) = audit {
+        audit.record(AuditEvent::Bound(xmpp_stream.jid.clone()));
+    }
+    Ok(xmpp_stream)
+}
+
+/// Authenticate, anonymously without any `username`, then bind a
+/// resource, recording both steps in `audit` if any.
+pub(crate) async fn login<S: AsyncRead + AsyncWrite + Unpin + Encrypted>(
+    xmpp_stream: XMPPStream<S>,
+    username: Option<String>,
+    password: String,
+    policy: &SaslPolicy,
+    audit: Option<&AuditTrail>,
+) -> Result<XMPPStream<S>, Error> {
+    let xmpp_stream = authenticate(xmpp_stream, username, password, policy, audit).await?;
+    bind_session(xmpp_stream, audit).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::FutureExt;
+
+    fn target(host: &str, direct_tls: bool) -> SrvTarget {
+        SrvTarget {
+            host: host.to_owned(),
+            port: if direct_tls { 5223 } else { 5222 },
+            priority: 0,
+            weight: 0,
+            direct_tls,
+        }
+    }
+
+    fn hosts(targets: &[SrvTarget]) -> Vec<&str> {
+        targets.iter().map(|target| target.host.as_str()).collect()
+    }
+
+    #[test]
+    fn test_select_targets() {
+        let targets = vec![
+            target("tls1", true),
+            target("plain1", false),
+            target("tls2", true),
+            target("plain2", false),
+        ];
+        let selected = TlsMode::Any.select_targets(targets.clone());
+        assert_eq!(hosts(&selected), ["tls1", "plain1", "tls2", "plain2"]);
+        let selected = TlsMode::PreferStartTls.select_targets(targets.clone());
+        assert_eq!(hosts(&selected), ["plain1", "plain2", "tls1", "tls2"]);
+        let selected = TlsMode::PreferDirectTls.select_targets(targets.clone());
+        assert_eq!(hosts(&selected), ["tls1", "tls2", "plain1", "plain2"]);
+        let selected = TlsMode::RequireStartTls.select_targets(targets.clone());
+        assert_eq!(hosts(&selected), ["plain1", "plain2"]);
+        let selected = TlsMode::RequireDirectTls.select_targets(targets);
+        assert_eq!(hosts(&selected), ["tls1", "tls2"]);
+    }
+
+    #[test]
+    fn test_fallback_targets() {
+        let fallback = TlsMode::PreferDirectTls.fallback_targets("example.org");
+        assert_eq!(
+            fallback,
+            [target("example.org", true), target("example.org", false)]
+        );
+        let fallback = TlsMode::Any.fallback_targets("example.org");
+        assert_eq!(fallback, [target("example.org", false)]);
+        let fallback = TlsMode::RequireStartTls.fallback_targets("example.org");
+        assert_eq!(fallback, [target("example.org", false)]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_try_targets() {
+        let targets = vec![
+            target("slow", false),
+            target("broken", true),
+            target("working", false),
+            target("unused", false),
+        ];
+        let mut tried = Vec::new();
+        let result = try_targets(targets, Duration::from_secs(10), |target| {
+            tried.push(target.host.clone());
+            async move {
+                match target.host.as_str() {
+                    "slow" => {
+                        time::sleep(Duration::from_secs(60)).await;
+                        unreachable!()
+                    }
+                    "broken" => Err(Error::Disconnected),
+                    _ => Ok(target.host),
+                }
+            }
+        })
+        .await;
+        assert_eq!(result.unwrap(), "working");
+        assert_eq!(tried, ["slow", "broken", "working"]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_try_targets_timeout() {
+        let result: Result<(), Error> =
+            try_targets(vec![target("slow", false)], Duration::from_secs(10), |_| {
+                time::sleep(Duration::from_secs(60)).then(|_| futures::future::ok(()))
+            })
+            .await;
+        assert!(matches!(
+            result,
+            Err(Error::Connection(ConnecterError::Timeout))
+        ));
+        let result: Result<(), Error> = try_targets(Vec::new(), Duration::from_secs(10), |_| {
+            futures::future::ok(())
+        })
+        .await;
+        assert!(matches!(
+            result,
+            Err(Error::Connection(ConnecterError::AllFailed))
+        ));
+    }
+}
@@ -0,0 +1,213 @@
+//! Replays server sides of whole negotiations, from the stream header to
+//! resource binding, to catch quirks of specific server implementations
+//! like unusual feature ordering or whitespace between elements.
+//!
+//! The transcripts are synthetic: they were written by hand after the
+//! documented behaviour of each server, not captured from real traffic, so
+//! they may miss quirks a real capture would show.
+//!
+//! The full replays are ignored by default, run them with
+//! `cargo test -p tokio-xmpp -- --ignored replay`.
+
+use futures::StreamExt;
+use minidom::tree_builder::ParserLimits;
+use std::str::FromStr;
+use tokio::io::{duplex, AsyncReadExt, AsyncWriteExt, DuplexStream};
+use tokio_util::codec::Framed;
+use xmpp_parsers::{ns, Jid};
+
+use super::{login, Encrypted};
+use crate::sasl_policy::SaslPolicy;
+use crate::stream_start;
+use crate::xmpp_codec::XMPPCodec;
+use crate::xmpp_stream::XMPPStream;
+use crate::{AuthError, Error, ProtocolError};
+
+/// The duplex streams stand for the TLS ones, but aren’t encrypted.
+impl Encrypted for DuplexStream {
+    fn is_encrypted(&self) -> bool {
+        false
+    }
+}
+
+/// One step of a transcript.
+enum Step<'a> {
+    /// Wait for the client to send something containing this text.
+    Client(&'a str),
+
+    /// Send this text to the client.
+    Server(&'a str),
+}
+
+/// Splits a transcript in its steps before and after the switch to TLS.
+fn parse(transcript: &str) -> (Vec<Step<'_>>, Vec<Step<'_>>) {
+    let mut phases = (Vec::new(), Vec::new());
+    let mut tls = false;
+    for line in transcript.lines() {
+        let step = if line.is_empty() || line.starts_with('#') {
+            continue;
+        } else if line == "TLS" {
+            tls = true;
+            continue;
+        } else if line == "S:" {
+            Step::Server("\n")
+        } else if let Some(text) = line.strip_prefix("S: ") {
+            Step::Server(text)
+        } else if let Some(text) = line.strip_prefix("C: ") {
+            Step::Client(text)
+        } else {
+            panic!("Invalid transcript line: {}", line);
+        };
+        if tls {
+            phases.1.push(step);
+        } else {
+            phases.0.push(step);
+        }
+    }
+    (phases.0, phases.1)
+}
+
+/// Plays the server side of `steps` on `server`.
+async fn play(mut server: DuplexStream, steps: Vec<Step<'_>>) -> DuplexStream {
+    let mut received = String::new();
+    let mut buf = [0u8; 4096];
+    for step in steps {
+        match step {
+            Step::Client(expected) => loop {
+                if let Some(index) = received.find(expected) {
+                    received.drain(..index + expected.len());
+                    break;
+                }
+                let read = server.read(&mut buf).await.unwrap();
+                assert!(read > 0, "Client closed while waiting for {}", expected);
+                received.push_str(std::str::from_utf8(&buf[..read]).unwrap());
+            },
+            Step::Server(text) => server.write_all(text.as_bytes()).await.unwrap(),
+        }
+    }
+    server
+}
+
+async fn replay(transcript: &str, bound: &str) {
+    let (before_tls, after_tls) = parse(transcript);
+    let jid = Jid::from_str("user@example.org").unwrap();
+
+    let (client, server) = duplex(65536);
+    let (stream, _server) = tokio::join!(
+        XMPPStream::start(client, jid.clone(), ns::JABBER_CLIENT.to_owned()),
+        play(server, before_tls),
+    );
+    assert!(stream.unwrap().stream_features.can_starttls());
+
+    let (client, server) = duplex(65536);
+    let negotiation = async {
+        let stream = XMPPStream::start(client, jid, ns::JABBER_CLIENT.to_owned()).await?;
+        login(
+            stream,
+            Some(String::from("user")),
+            String::from("password"),
+            &SaslPolicy::default().allowing_plain_without_tls(),
+            None,
+        )
+        .await
+    };
+    let (stream, _server) = tokio::join!(negotiation, play(server, after_tls));
+    assert_eq!(stream.unwrap().jid, Jid::from_str(bound).unwrap());
+}
+
+#[tokio::test]
+async fn plain_without_tls() {
+    let (_, after_tls) = parse(include_str!("transcripts/prosody.txt"));
+    let jid = Jid::from_str("user@example.org").unwrap();
+
+    let (client, server) = duplex(65536);
+    let negotiation = async {
+        let stream = XMPPStream::start(client, jid, ns::JABBER_CLIENT.to_owned()).await?;
+        login(
+            stream,
+            Some(String::from("user")),
+            String::from("password"),
+            &SaslPolicy::default(),
+            None,
+        )
+        .await
+    };
+    let result = tokio::select! {
+        result = negotiation => result,
+        _ = play(server, after_tls) => panic!("PLAIN got used without TLS"),
+    };
+    assert!(matches!(result, Err(Error::Auth(AuthError::NoMechanism))));
+}
+
+#[tokio::test]
+async fn limits_kept_after_authentication() {
+    let (_, after_tls) = parse(include_str!("transcripts/prosody.txt"));
+    let jid = Jid::from_str("user@example.org").unwrap();
+    let limits = ParserLimits {
+        max_depth: 4,
+        ..ParserLimits::default()
+    };
+
+    let (client, server) = duplex(65536);
+    let negotiation = async {
+        let codec = XMPPCodec::with_parser_limits(limits);
+        let stream = stream_start::start(
+            Framed::new(client, codec),
+            jid,
+            ns::JABBER_CLIENT.to_owned(),
+        )
+        .await?;
+        login(
+            stream,
+            Some(String::from("user")),
+            String::from("password"),
+            &SaslPolicy::default().allowing_plain_without_tls(),
+            None,
+        )
+        .await
+    };
+    let (stream, server) = tokio::join!(negotiation, play(server, after_tls));
+    let mut stream = stream.unwrap();
+
+    let deep = "<message><a xmlns='ns1'><b><c/></b></a></message>";
+    play(server, vec![Step::Server(deep)]).await;
+    match stream.next().await {
+        Some(Err(Error::Protocol(ProtocolError::Parser(e)))) => {
+            assert!(matches!(
+                e.root_cause(),
+                minidom::Error::LimitExceeded("depth")
+            ))
+        }
+        other => panic!("Unexpected {:?}", other),
+    }
+}
+
+#[tokio::test]
+#[ignore = "replays whole negotiations, run with --ignored"]
+async fn ejabberd() {
+    replay(
+        include_str!("transcripts/ejabberd.txt"),
+        "user@example.org/189385283957193284",
+    )
+    .await;
+}
+
+#[tokio::test]
+#[ignore = "replays whole negotiations, run with --ignored"]
+async fn prosody() {
+    replay(
+        include_str!("transcripts/prosody.txt"),
+        "user@example.org/GXrWtCmx",
+    )
+    .await;
+}
+
+#[tokio::test]
+#[ignore = "replays whole negotiations, run with --ignored"]
+async fn tigase() {
+    replay(
+        include_str!("transcripts/tigase.txt"),
+        "user@example.org/tigase-1234",
+    )
+    .await;
+}
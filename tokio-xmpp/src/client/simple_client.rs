@@ -1,6 +1,5 @@
 use futures::{sink::SinkExt, Sink, Stream};
-use idna;
-use sasl::common::{ChannelBinding, Credentials};
+use std::collections::VecDeque;
 use std::pin::Pin;
 use std::str::FromStr;
 use std::task::{Context, Poll};
@@ -12,13 +11,12 @@ use tokio_rustls::client::TlsStream;
 use tokio_stream::StreamExt;
 use xmpp_parsers::{ns, Element, Jid};
 
-use super::auth::auth;
-use super::bind::bind;
-use crate::happy_eyeballs::connect_with_srv;
-use crate::starttls::starttls;
+use super::{connect_tls_with_srv, login, TlsMode, DEFAULT_CONNECT_TIMEOUT};
+use crate::sasl_policy::SaslPolicy;
+use crate::tls::TlsConfig;
 use crate::xmpp_codec::Packet;
 use crate::xmpp_stream;
-use crate::{Error, ProtocolError};
+use crate::Error;
 
 /// A simple XMPP client connection
 ///
@@ -26,6 +24,7 @@ use crate::{Error, ProtocolError};
 /// [`Sink`](#impl-Sink<Packet>) traits.
 pub struct Client {
     stream: XMPPStream,
+    deferred: VecDeque<Element>,
 }
 
 type XMPPStream = xmpp_stream::XMPPStream<TlsStream<TcpStream>>;
@@ -41,7 +40,10 @@ impl Client {
     /// Start a new client given that the JID is already parsed.
     pub async fn new_with_jid(jid: Jid, password: String) -> Result<Self, Error> {
         let stream = Self::connect(jid.clone(), password.clone()).await?;
-        Ok(Client { stream })
+        Ok(Client {
+            stream,
+            deferred: VecDeque::new(),
+        })
     }
 
     /// Get direct access to inner XMPP Stream
@@ -50,41 +52,31 @@ impl Client {
     }
 
     async fn connect(jid: Jid, password: String) -> Result<XMPPStream, Error> {
-        let username = jid.clone().node().unwrap();
-        let password = password;
-        let domain = idna::domain_to_ascii(&jid.clone().domain()).map_err(|_| Error::Idna)?;
-
-        // TCP connection
-        let tcp_stream = connect_with_srv(&domain, "_xmpp-client._tcp", 5222).await?;
-
-        // Unencryped XMPPStream
+        let username = jid.clone().node();
+
+        // TlsStream, either direct or after STARTTLS
+        let tls_stream = connect_tls_with_srv(
+            &jid,
+            TlsMode::default(),
+            &TlsConfig::default(),
+            DEFAULT_CONNECT_TIMEOUT,
+            None,
+        )
+        .await?;
+
+        // Encrypted XMPPStream
         let xmpp_stream =
-            xmpp_stream::XMPPStream::start(tcp_stream, jid.clone(), ns::JABBER_CLIENT.to_owned())
-                .await?;
-
-        let xmpp_stream = if xmpp_stream.stream_features.can_starttls() {
-            // TlsStream
-            let tls_stream = starttls(xmpp_stream).await?;
-            // Encrypted XMPPStream
             xmpp_stream::XMPPStream::start(tls_stream, jid.clone(), ns::JABBER_CLIENT.to_owned())
-                .await?
-        } else {
-            return Err(Error::Protocol(ProtocolError::NoTls));
-        };
-
-        let creds = Credentials::default()
-            .with_username(username)
-            .with_password(password)
-            .with_channel_binding(ChannelBinding::None);
-        // Authenticated (unspecified) stream
-        let stream = auth(xmpp_stream, creds).await?;
-        // Authenticated XMPPStream
-        let xmpp_stream =
-            xmpp_stream::XMPPStream::start(stream, jid, ns::JABBER_CLIENT.to_owned()).await?;
+                .await?;
 
-        // XMPPStream bound to user session
-        let xmpp_stream = bind(xmpp_stream).await?;
-        Ok(xmpp_stream)
+        login(
+            xmpp_stream,
+            username,
+            password,
+            &SaslPolicy::default(),
+            None,
+        )
+        .await
     }
 
     /// Get the client's bound JID (the one reported by the XMPP
@@ -93,6 +85,15 @@ impl Client {
         &self.stream.jid
     }
 
+    /// Put back `stanza`, to be yielded by the stream before anything
+    /// received after it
+    ///
+    /// This lets a caller waiting for one stanza in particular keep the
+    /// others around.
+    pub fn defer(&mut self, stanza: Element) {
+        self.deferred.push_back(stanza);
+    }
+
     /// Send stanza
     pub async fn send_stanza<E>(&mut self, stanza: E) -> Result<(), Error>
     where
@@ -101,6 +102,14 @@ impl Client {
         self.send(Packet::Stanza(stanza.into())).await
     }
 
+    /// Send all of `packets`, flushing them at once
+    pub async fn send_all<I: IntoIterator<Item = Packet>>(
+        &mut self,
+        packets: I,
+    ) -> Result<(), Error> {
+        self.stream.send_all(packets).await
+    }
+
     /// End connection by sending `</stream:stream>`
     ///
     /// You may expect the server to respond with the same. This
@@ -124,6 +133,9 @@ impl Stream for Client {
 
     /// Low-level read on the XMPP stream
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        if let Some(stanza) = self.deferred.pop_front() {
+            return Poll::Ready(Some(Ok(stanza)));
+        }
         loop {
             match Pin::new(&mut self.stream).poll_next(cx) {
                 Poll::Pending => return Poll::Pending,
@@ -0,0 +1,141 @@
+//! Source of time for the timers of the client
+//!
+//! The keepalive and the other timers read the time and sleep through a
+//! [`Clock`], so that tests can drive time deterministically instead of
+//! waiting for real: either with [`TokioClock`] and
+//! `tokio::time::pause()`, or with a [`MockClock`] advanced by hand.
+
+use futures::task::{Context, Poll};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::Waker;
+use std::time::{Duration, Instant};
+
+/// A future completing once a deadline is reached
+pub type Timer = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Source of the current time, and of timers
+pub trait Clock: Send + Sync {
+    /// The current time
+    fn now(&self) -> Instant;
+
+    /// A timer completing once `deadline` is reached
+    fn sleep_until(&self, deadline: Instant) -> Timer;
+}
+
+/// The clock of the Tokio runtime, which tests can pause and advance with
+/// `tokio::time::pause()` and `tokio::time::advance()`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokioClock;
+
+impl Clock for TokioClock {
+    fn now(&self) -> Instant {
+        tokio::time::Instant::now().into_std()
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> Timer {
+        Box::pin(tokio::time::sleep_until(deadline.into()))
+    }
+}
+
+#[derive(Debug)]
+struct MockState {
+    now: Instant,
+    wakers: Vec<Waker>,
+}
+
+/// A clock only moving when advanced by hand, for tests
+///
+/// Clones share the same time.
+#[derive(Debug, Clone)]
+pub struct MockClock(Arc<Mutex<MockState>>);
+
+impl MockClock {
+    /// Create a clock stopped at the current time
+    pub fn new() -> MockClock {
+        MockClock(Arc::new(Mutex::new(MockState {
+            now: Instant::now(),
+            wakers: Vec::new(),
+        })))
+    }
+
+    /// Move the time forward, completing the timers whose deadline got
+    /// reached
+    pub fn advance(&self, duration: Duration) {
+        let wakers = {
+            let mut state = self.0.lock().unwrap();
+            state.now += duration;
+            std::mem::take(&mut state.wakers)
+        };
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+}
+
+impl Default for MockClock {
+    fn default() -> MockClock {
+        MockClock::new()
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        self.0.lock().unwrap().now
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> Timer {
+        Box::pin(MockTimer {
+            clock: self.clone(),
+            deadline,
+        })
+    }
+}
+
+struct MockTimer {
+    clock: MockClock,
+    deadline: Instant,
+}
+
+impl Future for MockTimer {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        let mut state = self.clock.0.lock().unwrap();
+        if state.now >= self.deadline {
+            return Poll::Ready(());
+        }
+        state.wakers.push(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::FutureExt;
+
+    #[test]
+    fn test_mock() {
+        let clock = MockClock::new();
+        let start = clock.now();
+        let mut timer = clock.sleep_until(start + Duration::from_secs(30));
+        assert!(timer.as_mut().now_or_never().is_none());
+
+        clock.clone().advance(Duration::from_secs(20));
+        assert_eq!(clock.now(), start + Duration::from_secs(20));
+        assert!(timer.as_mut().now_or_never().is_none());
+
+        clock.advance(Duration::from_secs(10));
+        assert!(timer.now_or_never().is_some());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_tokio_paused() {
+        let clock = TokioClock;
+        let start = clock.now();
+        clock.sleep_until(start + Duration::from_secs(3600)).await;
+        assert!(clock.now() >= start + Duration::from_secs(3600));
+    }
+}
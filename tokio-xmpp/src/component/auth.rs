@@ -21,9 +21,7 @@ pub async fn auth<S: AsyncRead + AsyncWrite + Unpin>(
             {
                 return Ok(());
             }
-            Some(Ok(Packet::Stanza(ref stanza)))
-                if stanza.is("error", "http://etherx.jabber.org/streams") =>
-            {
+            Some(Ok(Packet::Stanza(ref stanza))) if stanza.is("error", ns::STREAM) => {
                 return Err(AuthError::ComponentFail.into());
             }
             Some(_) => {}
@@ -31,3 +29,61 @@ pub async fn auth<S: AsyncRead + AsyncWrite + Unpin>(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use tokio::io::{duplex, AsyncReadExt, AsyncWriteExt, DuplexStream};
+    use xmpp_parsers::Jid;
+
+    const HEADER: &str = "<stream:stream xmlns='jabber:component:accept' xmlns:stream='http://etherx.jabber.org/streams' from='pubsub.example.org' id='3BF96D32'>";
+
+    /// Answers the handshake of the component with `reply`, checking that
+    /// it got hashed from the stream id and the secret.
+    async fn serve(mut server: DuplexStream, reply: &str) {
+        let expected = Handshake::from_password_and_stream_id("sesame", "3BF96D32")
+            .data
+            .unwrap();
+        let mut received = String::new();
+        let mut buf = [0u8; 4096];
+        server.write_all(HEADER.as_bytes()).await.unwrap();
+        while !received.contains("</handshake>") {
+            let read = server.read(&mut buf).await.unwrap();
+            assert!(read > 0);
+            received.push_str(std::str::from_utf8(&buf[..read]).unwrap());
+        }
+        assert!(
+            received.contains("xmlns='jabber:component:accept'")
+                || received.contains("xmlns=\"jabber:component:accept\"")
+        );
+        assert!(received.contains(&expected));
+        server.write_all(reply.as_bytes()).await.unwrap();
+    }
+
+    async fn handshake(reply: &str) -> Result<(), Error> {
+        let jid = Jid::from_str("pubsub.example.org").unwrap();
+        let (client, server) = duplex(65536);
+        let negotiation = async {
+            let mut stream =
+                XMPPStream::start(client, jid, ns::COMPONENT_ACCEPT.to_owned()).await?;
+            assert_eq!(stream.id, "3BF96D32");
+            auth(&mut stream, String::from("sesame")).await
+        };
+        let (result, ()) = tokio::join!(negotiation, serve(server, reply));
+        result
+    }
+
+    #[tokio::test]
+    async fn test_handshake() {
+        handshake("<handshake/>").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_handshake_failure() {
+        let error = handshake("<stream:error><not-authorized xmlns='urn:ietf:params:xml:ns:xmpp-streams'/></stream:error>")
+            .await
+            .unwrap_err();
+        assert!(matches!(error, Error::Auth(AuthError::ComponentFail)));
+    }
+}
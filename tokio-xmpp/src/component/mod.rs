@@ -1,19 +1,26 @@
 //! Components in XMPP are services/gateways that are logged into an
 //! XMPP server under a JID consisting of just a domain name. They are
 //! allowed to use any user and resource identifiers in their stanzas.
-use futures::{sink::SinkExt, task::Poll, Sink, Stream};
+use futures::{sink::SinkExt, stream::StreamExt, task::Poll, Sink, Stream};
+use minidom::tree_builder::ParserLimits;
 use std::pin::Pin;
 use std::str::FromStr;
 use std::task::Context;
 use tokio::net::TcpStream;
+use tokio_util::codec::Framed;
 use xmpp_parsers::{ns, Element, Jid};
 
 use super::happy_eyeballs::connect_to_host;
-use super::xmpp_codec::Packet;
+use super::memory::MemoryAccount;
+use super::pipeline::Pipeline;
+use super::sanitize::CharPolicy;
+use super::stream_start;
+use super::xmpp_codec::{Packet, XMPPCodec};
 use super::xmpp_stream;
 use super::Error;
 
 mod auth;
+pub mod router;
 
 /// Component connection to an XMPP server
 ///
@@ -30,9 +37,21 @@ type XMPPStream = xmpp_stream::XMPPStream<TcpStream>;
 impl Component {
     /// Start a new XMPP component
     pub async fn new(jid: &str, password: &str, server: &str, port: u16) -> Result<Self, Error> {
+        Self::new_with_parser_limits(jid, password, server, port, ParserLimits::default()).await
+    }
+
+    /// Start a new XMPP component, ending the stream once the server
+    /// sends anything going over `limits`, like elements nested deeper.
+    pub async fn new_with_parser_limits(
+        jid: &str,
+        password: &str,
+        server: &str,
+        port: u16,
+        limits: ParserLimits,
+    ) -> Result<Self, Error> {
         let jid = Jid::from_str(jid)?;
         let password = password.to_owned();
-        let stream = Self::connect(jid.clone(), password, server, port).await?;
+        let stream = Self::connect(jid.clone(), password, server, port, limits).await?;
         Ok(Component { jid, stream })
     }
 
@@ -41,25 +60,85 @@ impl Component {
         password: String,
         server: &str,
         port: u16,
+        limits: ParserLimits,
     ) -> Result<XMPPStream, Error> {
         let password = password;
         let tcp_stream = connect_to_host(server, port).await?;
-        let mut xmpp_stream =
-            xmpp_stream::XMPPStream::start(tcp_stream, jid, ns::COMPONENT_ACCEPT.to_owned())
-                .await?;
+        let mut xmpp_stream = stream_start::start(
+            Framed::new(tcp_stream, XMPPCodec::with_parser_limits(limits)),
+            jid,
+            ns::COMPONENT_ACCEPT.to_owned(),
+        )
+        .await?;
         auth::auth(&mut xmpp_stream, password).await?;
         Ok(xmpp_stream)
     }
 
+    /// Get the JID the component got authenticated as, which is also
+    /// the domain it serves.
+    pub fn bound_jid(&self) -> &Jid {
+        &self.jid
+    }
+
     /// Send stanza
     pub async fn send_stanza(&mut self, stanza: Element) -> Result<(), Error> {
         self.send(stanza).await
     }
 
+    /// Send all of `packets`, flushing them at once
+    pub async fn send_all<I: IntoIterator<Item = Packet>>(
+        &mut self,
+        packets: I,
+    ) -> Result<(), Error> {
+        self.stream.send_all(packets).await
+    }
+
+    /// Send a packet, like a stanza or the end of the stream
+    pub async fn send_packet(&mut self, packet: Packet) -> Result<(), Error> {
+        self.stream.send(packet).await
+    }
+
     /// End connection
     pub async fn send_end(&mut self) -> Result<(), Error> {
         self.close().await
     }
+
+    /// End connection by sending `</stream:stream>`, then wait for the
+    /// server to do the same before dropping it.
+    pub async fn end(mut self) -> Result<(), Error> {
+        self.send_packet(Packet::StreamEnd).await?;
+
+        // Wait for stream end from server
+        while self.next().await.is_some() {}
+
+        Ok(())
+    }
+
+    /// Account the memory used by this connection to `account`, the
+    /// stream ending once it goes over its ceiling.
+    pub fn set_memory_account(&mut self, account: &MemoryAccount) {
+        self.stream.stream.codec_mut().set_memory_account(account);
+    }
+
+    /// Set what to do with the characters forbidden in XML sent by the
+    /// server.
+    pub fn set_char_policy(&mut self, policy: &CharPolicy) {
+        self.stream.stream.codec_mut().set_char_policy(policy);
+    }
+
+    /// Hand the incoming stanzas over to the tokio worker pool, where
+    /// up to `parallelism` of them get built and passed to `parse`
+    /// concurrently, while still being yielded in order.
+    ///
+    /// Use this for high-throughput components, where building and
+    /// parsing stanzas on the I/O task becomes the bottleneck.
+    pub fn into_pipeline<T, F>(self, parallelism: usize, parse: F) -> Pipeline<TcpStream, T>
+    where
+        T: Send + 'static,
+        F: Fn(Element) -> T + Send + Sync + 'static,
+    {
+        Pipeline::new(self.stream, parallelism, parse)
+    }
 }
 
 impl Stream for Component {
@@ -0,0 +1,313 @@
+//! Sharing one component connection between several workers
+//!
+//! A [`Router`] owns the connection, and dispatches every incoming
+//! stanza to one of its [`Worker`]s, as chosen by a
+//! [`ShardingStrategy`]. All the workers can send stanzas through the
+//! same connection.
+//!
+//! Every worker has a bounded queue: once it is full, the router stops
+//! reading from the connection until the worker catches up, which lets
+//! the server know to slow down instead of buffering without limits.
+
+use futures::{Sink, SinkExt, Stream, StreamExt};
+use log::warn;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+use tokio::sync::mpsc;
+use xmpp_parsers::{BareJid, Element, Jid};
+
+use crate::Error;
+
+/// Picks the worker which will handle an incoming stanza
+pub trait ShardingStrategy {
+    /// Return the index of the worker, below `workers`, to which
+    /// `stanza` should be dispatched.
+    fn shard(&mut self, stanza: &Element, workers: usize) -> usize;
+}
+
+fn hash_attr(stanza: &Element, attr: &str, workers: usize) -> usize {
+    let mut hasher = DefaultHasher::new();
+    stanza.attr(attr).unwrap_or("").hash(&mut hasher);
+    (hasher.finish() % workers as u64) as usize
+}
+
+/// Shard by hash of the `from` attribute, so that all stanzas from a
+/// given full JID are handled by the same worker.
+#[derive(Debug, Clone, Default)]
+pub struct ShardByFrom;
+
+impl ShardingStrategy for ShardByFrom {
+    fn shard(&mut self, stanza: &Element, workers: usize) -> usize {
+        hash_attr(stanza, "from", workers)
+    }
+}
+
+/// Shard by hash of the `to` attribute, so that all stanzas to a given
+/// entity of the component are handled by the same worker.
+#[derive(Debug, Clone, Default)]
+pub struct ShardByTo;
+
+impl ShardingStrategy for ShardByTo {
+    fn shard(&mut self, stanza: &Element, workers: usize) -> usize {
+        hash_attr(stanza, "to", workers)
+    }
+}
+
+/// Hand out every new bare JID of the `from` attribute to the next
+/// worker in turn, and stick to this worker afterwards.
+///
+/// Stanzas without a valid `from` go to the first worker.
+#[derive(Debug, Clone, Default)]
+pub struct StickyByBareJid {
+    sessions: HashMap<BareJid, usize>,
+    next: usize,
+}
+
+impl StickyByBareJid {
+    /// Create a strategy without any session yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Forget about the worker of `jid`, for instance once this user
+    /// went offline.
+    pub fn forget(&mut self, jid: &BareJid) {
+        self.sessions.remove(jid);
+    }
+}
+
+impl ShardingStrategy for StickyByBareJid {
+    fn shard(&mut self, stanza: &Element, workers: usize) -> usize {
+        let jid = match stanza.attr("from").map(Jid::from_str) {
+            Some(Ok(jid)) => BareJid::from(jid),
+            _ => return 0,
+        };
+        let next = &mut self.next;
+        let worker = *self.sessions.entry(jid).or_insert_with(|| {
+            let worker = *next;
+            *next = (*next + 1) % workers;
+            worker
+        });
+        // The number of workers may have changed since.
+        worker % workers
+    }
+}
+
+/// One of the workers sharing the connection of a [`Router`]
+#[derive(Debug)]
+pub struct Worker {
+    incoming: mpsc::Receiver<Element>,
+    outgoing: mpsc::Sender<Element>,
+}
+
+impl Worker {
+    /// Wait for the next stanza dispatched to this worker, `None`
+    /// meaning the router stopped.
+    pub async fn recv(&mut self) -> Option<Element> {
+        self.incoming.recv().await
+    }
+
+    /// Send a stanza through the shared connection, waiting while the
+    /// outgoing queue is full.
+    pub async fn send(&self, stanza: Element) -> Result<(), Error> {
+        self.outgoing
+            .send(stanza)
+            .await
+            .map_err(|_| Error::Disconnected)
+    }
+}
+
+/// Dispatcher of the stanzas of one connection over several [`Worker`]s
+pub struct Router<C, R> {
+    stream: C,
+    strategy: R,
+    workers: Vec<mpsc::Sender<Element>>,
+    outgoing: mpsc::Receiver<Element>,
+}
+
+impl<C, R> Router<C, R>
+where
+    C: Stream<Item = Element> + Sink<Element, Error = Error> + Unpin,
+    R: ShardingStrategy,
+{
+    /// Take over `stream`, usually a [`Component`](../struct.Component.html),
+    /// and create `workers` workers with queues of `capacity` stanzas.
+    pub fn new(stream: C, strategy: R, workers: usize, capacity: usize) -> (Self, Vec<Worker>) {
+        let (outgoing_tx, outgoing) = mpsc::channel(capacity);
+        let (senders, workers) = (0..workers.max(1))
+            .map(|_| {
+                let (tx, incoming) = mpsc::channel(capacity);
+                let worker = Worker {
+                    incoming,
+                    outgoing: outgoing_tx.clone(),
+                };
+                (tx, worker)
+            })
+            .unzip();
+        let router = Router {
+            stream,
+            strategy,
+            workers: senders,
+            outgoing,
+        };
+        (router, workers)
+    }
+
+    /// Dispatch stanzas until either the connection or all of the
+    /// workers are gone.
+    pub async fn run(self) -> Result<(), Error> {
+        let Router {
+            mut stream,
+            mut strategy,
+            workers,
+            mut outgoing,
+        } = self;
+        let mut pending: Option<(usize, Element)> = None;
+
+        loop {
+            let worker = pending.as_ref().map(|(index, _)| &workers[*index]);
+            tokio::select! {
+                permit = async { worker.unwrap().reserve().await }, if worker.is_some() => {
+                    let (index, stanza) = pending.take().unwrap();
+                    match permit {
+                        Ok(permit) => permit.send(stanza),
+                        Err(_) => warn!("Worker {} is gone, dropping stanza.", index),
+                    }
+                }
+                stanza = stream.next(), if pending.is_none() => {
+                    match stanza {
+                        Some(stanza) => {
+                            let index = strategy.shard(&stanza, workers.len());
+                            pending = Some((index, stanza));
+                        }
+                        None => return Ok(()),
+                    }
+                }
+                stanza = outgoing.recv() => {
+                    match stanza {
+                        Some(stanza) => stream.send(stanza).await?,
+                        None => return Ok(()),
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::channel::mpsc as fmpsc;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    struct MockStream {
+        incoming: fmpsc::UnboundedReceiver<Element>,
+        outgoing: fmpsc::UnboundedSender<Element>,
+    }
+
+    impl Stream for MockStream {
+        type Item = Element;
+
+        fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Element>> {
+            Pin::new(&mut self.incoming).poll_next(cx)
+        }
+    }
+
+    impl Sink<Element> for MockStream {
+        type Error = Error;
+
+        fn poll_ready(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<Result<(), Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn start_send(self: Pin<&mut Self>, item: Element) -> Result<(), Error> {
+            self.outgoing
+                .unbounded_send(item)
+                .map_err(|_| Error::Disconnected)
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<Result<(), Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<Result<(), Error>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    fn message(from: &str) -> Element {
+        Element::builder("message", "jabber:component:accept")
+            .attr("from", from)
+            .attr("to", "gateway.example")
+            .build()
+    }
+
+    #[test]
+    fn test_sticky() {
+        let mut strategy = StickyByBareJid::new();
+        assert_eq!(strategy.shard(&message("a@example/1"), 3), 0);
+        assert_eq!(strategy.shard(&message("b@example/1"), 3), 1);
+        assert_eq!(strategy.shard(&message("a@example/2"), 3), 0);
+        assert_eq!(strategy.shard(&message("c@example"), 3), 2);
+        assert_eq!(strategy.shard(&message("d@example"), 3), 0);
+        assert_eq!(strategy.shard(&message("b@example/2"), 3), 1);
+
+        strategy.forget(&BareJid::from_str("b@example").unwrap());
+        assert_eq!(strategy.shard(&message("b@example/2"), 3), 1);
+        assert_eq!(strategy.shard(&message("e@example/2"), 3), 2);
+    }
+
+    #[test]
+    fn test_hash() {
+        let index = ShardByFrom.shard(&message("a@example/1"), 4);
+        assert!(index < 4);
+        assert_eq!(ShardByFrom.shard(&message("a@example/1"), 4), index);
+        let index = ShardByTo.shard(&message("a@example/1"), 4);
+        assert_eq!(ShardByTo.shard(&message("b@example/1"), 4), index);
+    }
+
+    #[tokio::test]
+    async fn test_router() {
+        let (in_tx, incoming) = fmpsc::unbounded();
+        let (outgoing, mut out_rx) = fmpsc::unbounded();
+        let stream = MockStream { incoming, outgoing };
+        let (router, mut workers) = Router::new(stream, StickyByBareJid::new(), 2, 1);
+        let router = tokio::spawn(router.run());
+
+        for from in &["a@example/1", "b@example/1", "a@example/2", "b@example/2"] {
+            in_tx.unbounded_send(message(from)).unwrap();
+        }
+
+        let mut second = workers.pop().unwrap();
+        let mut first = workers.pop().unwrap();
+        assert_eq!(
+            first.recv().await.unwrap().attr("from"),
+            Some("a@example/1")
+        );
+        assert_eq!(
+            first.recv().await.unwrap().attr("from"),
+            Some("a@example/2")
+        );
+        assert_eq!(
+            second.recv().await.unwrap().attr("from"),
+            Some("b@example/1")
+        );
+        second.send(message("reply@example")).await.unwrap();
+        assert_eq!(
+            out_rx.next().await.unwrap().attr("from"),
+            Some("reply@example")
+        );
+        assert_eq!(
+            second.recv().await.unwrap().attr("from"),
+            Some("b@example/2")
+        );
+
+        // The connection goes away.
+        drop(in_tx);
+        router.await.unwrap().unwrap();
+        assert!(first.recv().await.is_none());
+    }
+}
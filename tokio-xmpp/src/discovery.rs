@@ -0,0 +1,294 @@
+//! Discovery of the endpoints serving a domain.
+//!
+//! The TCP endpoints are found using the DNS SRV records of the domain, for
+//! both STARTTLS and direct TLS (XEP-0368), ordered by priority and weight
+//! as described in RFC 2782.  When a domain doesn’t have any, the BOSH and
+//! WebSocket endpoints it may advertise in its host-meta file (XEP-0156)
+//! get looked up instead.
+
+use log::warn;
+use rand::Rng;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use trust_dns_resolver::lookup::SrvLookup;
+use trust_dns_resolver::{IntoName, TokioAsyncResolver};
+use xmpp_parsers::Element;
+
+use crate::happy_eyeballs::connect_to_host;
+use crate::tls::TlsConfig;
+use crate::verify::Trust;
+use crate::{ConnecterError, Error};
+
+/// The SRV service of the client endpoints negotiating TLS with STARTTLS.
+pub const CLIENT_SERVICE: &str = "_xmpp-client._tcp";
+
+/// The SRV service of the client endpoints expecting TLS right away.
+pub const DIRECT_TLS_CLIENT_SERVICE: &str = "_xmpps-client._tcp";
+
+const XRD: &str = "http://docs.oasis-open.org/ns/xri/xrd-1.0";
+const BOSH_REL: &str = "urn:xmpp:alt-connections:xbosh";
+const WEBSOCKET_REL: &str = "urn:xmpp:alt-connections:websocket";
+
+/// A TCP endpoint taken from a SRV record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SrvTarget {
+    /// The host to connect to.
+    pub host: String,
+    /// The port to connect to.
+    pub port: u16,
+    /// The priority of this endpoint, the lowest one getting tried first.
+    pub priority: u16,
+    /// The relative weight of this endpoint among the ones of the same
+    /// priority.
+    pub weight: u16,
+    /// Whether TLS has to be negotiated right after connecting, instead of
+    /// using STARTTLS.
+    pub direct_tls: bool,
+}
+
+/// Orders targets as described in RFC 2782: by increasing priority, and
+/// randomly among the ones of the same priority, each of them getting picked
+/// with a probability proportional to its weight.
+///
+/// `random(n)` has to return a number between 0 and n, both included.
+pub fn order_targets<R: FnMut(u32) -> u32>(
+    mut targets: Vec<SrvTarget>,
+    mut random: R,
+) -> Vec<SrvTarget> {
+    // Zero-weight targets first, so that they have a tiny chance of getting
+    // picked before the others.
+    targets.sort_by_key(|target| (target.priority, target.weight != 0));
+    let mut ordered = Vec::with_capacity(targets.len());
+    while !targets.is_empty() {
+        let priority = targets[0].priority;
+        let group_len = targets
+            .iter()
+            .take_while(|target| target.priority == priority)
+            .count();
+        let total: u32 = targets[..group_len]
+            .iter()
+            .map(|target| u32::from(target.weight))
+            .sum();
+        let chosen = random(total);
+        let mut running = 0;
+        let index = targets[..group_len]
+            .iter()
+            .position(|target| {
+                running += u32::from(target.weight);
+                running >= chosen
+            })
+            .unwrap_or(group_len - 1);
+        ordered.push(targets.remove(index));
+    }
+    ordered
+}
+
+/// Converts the records of a SRV lookup, dropping the single `.` target
+/// meaning that this service isn’t available.
+pub(crate) fn targets_from_lookup(lookup: &SrvLookup, direct_tls: bool) -> Vec<SrvTarget> {
+    let targets: Vec<_> = lookup
+        .iter()
+        .map(|srv| SrvTarget {
+            host: srv.target().to_ascii().trim_end_matches('.').to_owned(),
+            port: srv.port(),
+            priority: srv.priority(),
+            weight: srv.weight(),
+            direct_tls,
+        })
+        .collect();
+    match targets.as_slice() {
+        [target] if target.host.is_empty() => Vec::new(),
+        _ => targets,
+    }
+}
+
+/// Orders targets using the thread-local random number generator.
+pub(crate) fn order_targets_randomly(targets: Vec<SrvTarget>) -> Vec<SrvTarget> {
+    let mut rng = rand::thread_rng();
+    order_targets(targets, |total| rng.gen_range(0..=total))
+}
+
+/// Looks up the STARTTLS and direct TLS client endpoints of this domain,
+/// in the order they should be tried in.
+pub async fn lookup_srv(domain: &str) -> Result<Vec<SrvTarget>, Error> {
+    let ascii_domain = idna::domain_to_ascii(domain).map_err(|_| Error::Idna)?;
+    let resolver = TokioAsyncResolver::tokio_from_system_conf().map_err(ConnecterError::Resolve)?;
+    let mut targets = Vec::new();
+    for (service, direct_tls) in [(CLIENT_SERVICE, false), (DIRECT_TLS_CLIENT_SERVICE, true)] {
+        let name = format!("{}.{}.", service, ascii_domain)
+            .into_name()
+            .map_err(ConnecterError::Dns)?;
+        // A missing record isn’t an error, the other service may exist.
+        if let Ok(lookup) = resolver.srv_lookup(name).await {
+            targets.extend(targets_from_lookup(&lookup, direct_tls));
+        }
+    }
+    Ok(order_targets_randomly(targets))
+}
+
+/// An alternative connection method advertised in a host-meta file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AltConnection {
+    /// The URL of a BOSH (XEP-0124) endpoint.
+    Bosh(String),
+    /// The URL of a WebSocket (RFC 7395) endpoint.
+    WebSocket(String),
+}
+
+/// Extracts the alternative connection methods of a host-meta file, in the
+/// XRD format.
+#[allow(clippy::result_large_err)]
+pub fn parse_host_meta(xrd: &Element) -> Result<Vec<AltConnection>, ConnecterError> {
+    if !xrd.is("XRD", XRD) {
+        return Err(ConnecterError::HostMeta("not an XRD document"));
+    }
+    let mut connections = Vec::new();
+    for link in xrd.children().filter(|child| child.is("Link", XRD)) {
+        let href = match link.attr("href") {
+            Some(href) => href.to_owned(),
+            None => continue,
+        };
+        match link.attr("rel") {
+            Some(BOSH_REL) => connections.push(AltConnection::Bosh(href)),
+            Some(WEBSOCKET_REL) => connections.push(AltConnection::WebSocket(href)),
+            _ => (),
+        }
+    }
+    Ok(connections)
+}
+
+/// Fetches this file over HTTPS from the web server of this domain,
+/// returning its body.
+pub(crate) async fn https_get(domain: &str, path: &str, accept: &str) -> Result<String, Error> {
+    let ascii_domain = idna::domain_to_ascii(domain).map_err(|_| Error::Idna)?;
+    let tcp_stream = connect_to_host(&ascii_domain, 443).await?;
+    // Not an XMPP connection, so without the xmpp-client ALPN.
+    let mut stream = TlsConfig::default()
+        .connect(&ascii_domain, tcp_stream, &Trust::default(), false)
+        .await?;
+    // HTTP/1.0 keeps the body from getting chunked.
+    let request = format!(
+        "GET {} HTTP/1.0\r\nHost: {}\r\nAccept: {}\r\n\r\n",
+        path, ascii_domain, accept
+    );
+    stream.write_all(request.as_bytes()).await?;
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await?;
+    let response = String::from_utf8(response)
+        .map_err(|_| ConnecterError::Http("response isn’t valid UTF-8"))?;
+    let (head, body) = response
+        .split_once("\r\n\r\n")
+        .ok_or(ConnecterError::Http("truncated HTTP response"))?;
+    let status = head.split(' ').nth(1);
+    if status != Some("200") {
+        return Err(ConnecterError::Http("unsuccessful HTTP response").into());
+    }
+    Ok(body.to_owned())
+}
+
+/// Fetches the host-meta file of this domain over HTTPS, and extracts its
+/// alternative connection methods.
+pub async fn lookup_host_meta(domain: &str) -> Result<Vec<AltConnection>, Error> {
+    let body = https_get(domain, "/.well-known/host-meta", "application/xrd+xml").await?;
+    let xrd: Element = body
+        .parse()
+        .map_err(|_| ConnecterError::HostMeta("invalid XML"))?;
+    Ok(parse_host_meta(&xrd)?)
+}
+
+/// Everything known about how to connect to a domain.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Endpoints {
+    /// The TCP endpoints, in the order they should be tried in.
+    pub srv: Vec<SrvTarget>,
+    /// The alternative connection methods, only looked up when there is no
+    /// TCP endpoint.
+    pub alternatives: Vec<AltConnection>,
+}
+
+/// Discovers the endpoints of this domain, falling back to its host-meta
+/// file when it doesn’t have any SRV record.
+pub async fn discover(domain: &str) -> Result<Endpoints, Error> {
+    let srv = lookup_srv(domain).await?;
+    let alternatives = if srv.is_empty() {
+        match lookup_host_meta(domain).await {
+            Ok(alternatives) => alternatives,
+            Err(e) => {
+                warn!("Failed to fetch the host-meta file of {}: {}", domain, e);
+                Vec::new()
+            }
+        }
+    } else {
+        Vec::new()
+    };
+    Ok(Endpoints { srv, alternatives })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target(host: &str, priority: u16, weight: u16) -> SrvTarget {
+        SrvTarget {
+            host: host.to_owned(),
+            port: 5222,
+            priority,
+            weight,
+            direct_tls: false,
+        }
+    }
+
+    fn hosts(targets: &[SrvTarget]) -> Vec<&str> {
+        targets.iter().map(|target| target.host.as_str()).collect()
+    }
+
+    #[test]
+    fn test_priority() {
+        let targets = vec![target("c", 20, 0), target("a", 5, 0), target("b", 10, 0)];
+        let ordered = order_targets(targets, |_| 0);
+        assert_eq!(hosts(&ordered), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_weight() {
+        let targets = vec![
+            target("light", 10, 10),
+            target("heavy", 10, 90),
+            target("backup", 20, 50),
+        ];
+        // 10 < 50 <= 10 + 90, so the heavy one gets picked first.
+        let ordered = order_targets(targets.clone(), |total| total.min(50));
+        assert_eq!(hosts(&ordered), ["heavy", "light", "backup"]);
+
+        let ordered = order_targets(targets, |_| 5);
+        assert_eq!(hosts(&ordered), ["light", "heavy", "backup"]);
+    }
+
+    #[test]
+    fn test_zero_weight() {
+        let targets = vec![target("weighted", 0, 1), target("zero", 0, 0)];
+        let ordered = order_targets(targets.clone(), |_| 0);
+        assert_eq!(hosts(&ordered), ["zero", "weighted"]);
+        let ordered = order_targets(targets, |total| total);
+        assert_eq!(hosts(&ordered), ["weighted", "zero"]);
+    }
+
+    #[test]
+    fn test_host_meta() {
+        let xrd: Element = "<XRD xmlns='http://docs.oasis-open.org/ns/xri/xrd-1.0'><Link rel='urn:xmpp:alt-connections:xbosh' href='https://web.example.com:5280/bosh'/><Link rel='urn:xmpp:alt-connections:websocket' href='wss://web.example.com:443/ws'/><Link rel='lrdd' href='https://example.com/lrdd'/></XRD>"
+            .parse()
+            .unwrap();
+        let connections = parse_host_meta(&xrd).unwrap();
+        assert_eq!(
+            connections,
+            [
+                AltConnection::Bosh(String::from("https://web.example.com:5280/bosh")),
+                AltConnection::WebSocket(String::from("wss://web.example.com:443/ws")),
+            ]
+        );
+
+        let other: Element = "<feed xmlns='http://www.w3.org/2005/Atom'/>"
+            .parse()
+            .unwrap();
+        assert!(parse_host_meta(&other).is_err());
+    }
+}
@@ -12,6 +12,7 @@ use tokio_rustls::rustls::Error as TlsError;
 use trust_dns_proto::error::ProtoError;
 use trust_dns_resolver::error::ResolveError;
 
+use crate::memory::MemoryLimitExceeded;
 use xmpp_parsers::sasl::DefinedCondition as SaslDefinedCondition;
 use xmpp_parsers::{Error as ParsersError, JidParseError};
 
@@ -38,6 +39,15 @@ pub enum Error {
     DnsNameError(InvalidDnsNameError),
     /// Connection closed
     Disconnected,
+    /// Too much memory used by the connection
+    MemoryLimit(MemoryLimitExceeded),
+    /// No answer to a keepalive ping in time
+    PingTimeout,
+    /// The outbox can't take more stanzas until some get sent
+    OutboxFull,
+    /// A task parsing stanzas got cancelled, like when its runtime shuts
+    /// down
+    Cancelled,
     /// Shoud never happen
     InvalidState,
 }
@@ -55,6 +65,10 @@ impl fmt::Display for Error {
             #[cfg(feature = "tls-rust")]
             Error::DnsNameError(e) => write!(fmt, "DNS name error: {}", e),
             Error::Disconnected => write!(fmt, "disconnected"),
+            Error::MemoryLimit(e) => write!(fmt, "memory limit: {}", e),
+            Error::PingTimeout => write!(fmt, "ping timeout"),
+            Error::OutboxFull => write!(fmt, "outbox full"),
+            Error::Cancelled => write!(fmt, "task cancelled"),
             Error::InvalidState => write!(fmt, "invalid state"),
         }
     }
@@ -68,6 +82,12 @@ impl From<IoError> for Error {
     }
 }
 
+impl From<MemoryLimitExceeded> for Error {
+    fn from(e: MemoryLimitExceeded) -> Self {
+        Error::MemoryLimit(e)
+    }
+}
+
 impl From<ConnecterError> for Error {
     fn from(e: ConnecterError) -> Self {
         Error::Connection(e)
@@ -143,6 +163,8 @@ pub enum ProtocolError {
     InvalidToken,
     /// Unexpected <stream:stream> (shouldn't occur)
     InvalidStreamStart,
+    /// Stream management acknowledgement of more stanzas than were sent
+    InvalidAck,
 }
 
 impl fmt::Display for ProtocolError {
@@ -160,6 +182,7 @@ impl fmt::Display for ProtocolError {
             ProtocolError::NoStreamId => write!(fmt, "no id attribute in <stream:stream>"),
             ProtocolError::InvalidToken => write!(fmt, "encountered an unexpected XML token"),
             ProtocolError::InvalidStreamStart => write!(fmt, "unexpected <stream:stream>"),
+            ProtocolError::InvalidAck => write!(fmt, "acknowledgement of unsent stanzas"),
         }
     }
 }
@@ -195,6 +218,9 @@ pub enum AuthError {
     Fail(SaslDefinedCondition),
     /// Component authentication failure
     ComponentFail,
+    /// The mechanisms or channel binding types advertised by the server
+    /// got tampered with (XEP-0474)
+    Downgrade,
 }
 
 impl StdError for AuthError {}
@@ -206,6 +232,7 @@ impl fmt::Display for AuthError {
             AuthError::Sasl(s) => write!(fmt, "local SASL implementation error: {}", s),
             AuthError::Fail(c) => write!(fmt, "failure from the server: {:?}", c),
             AuthError::ComponentFail => write!(fmt, "component authentication failure"),
+            AuthError::Downgrade => write!(fmt, "SASL downgrade attack detected"),
         }
     }
 }
@@ -219,6 +246,14 @@ pub enum ConnecterError {
     Dns(ProtoError),
     /// DNS resolution error
     Resolve(ResolveError),
+    /// Invalid host-meta file
+    HostMeta(&'static str),
+    /// Failed HTTPS request
+    Http(&'static str),
+    /// Certificate of the server rejected by the verification policy
+    Certificate(&'static str),
+    /// An endpoint took too long to get the connection encrypted
+    Timeout,
 }
 
 impl StdError for ConnecterError {}
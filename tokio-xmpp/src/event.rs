@@ -13,8 +13,6 @@ pub enum Event {
         /// the connection was setup.
         bound_jid: Jid,
         /// Was this session resumed?
-        ///
-        /// Not yet implemented for the Client
         resumed: bool,
     },
     /// Stream end
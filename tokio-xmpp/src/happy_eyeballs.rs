@@ -2,7 +2,7 @@ use crate::{ConnecterError, Error};
 use idna;
 use std::net::SocketAddr;
 use tokio::net::TcpStream;
-use trust_dns_resolver::{IntoName, TokioAsyncResolver};
+use trust_dns_resolver::TokioAsyncResolver;
 
 pub async fn connect_to_host(domain: &str, port: u16) -> Result<TcpStream, Error> {
     let ascii_domain = idna::domain_to_ascii(&domain).map_err(|_| Error::Idna)?;
@@ -25,39 +25,3 @@ pub async fn connect_to_host(domain: &str, port: u16) -> Result<TcpStream, Error
     }
     Err(Error::Disconnected)
 }
-
-pub async fn connect_with_srv(
-    domain: &str,
-    srv: &str,
-    fallback_port: u16,
-) -> Result<TcpStream, Error> {
-    let ascii_domain = idna::domain_to_ascii(&domain).map_err(|_| Error::Idna)?;
-
-    if let Ok(ip) = ascii_domain.parse() {
-        return Ok(TcpStream::connect(&SocketAddr::new(ip, fallback_port)).await?);
-    }
-
-    let resolver = TokioAsyncResolver::tokio_from_system_conf().map_err(ConnecterError::Resolve)?;
-
-    let srv_domain = format!("{}.{}.", srv, ascii_domain)
-        .into_name()
-        .map_err(ConnecterError::Dns)?;
-    let srv_records = resolver.srv_lookup(srv_domain).await.ok();
-
-    match srv_records {
-        Some(lookup) => {
-            // TODO: sort lookup records by priority/weight
-            for srv in lookup.iter() {
-                match connect_to_host(&srv.target().to_ascii(), srv.port()).await {
-                    Ok(stream) => return Ok(stream),
-                    Err(_) => {}
-                }
-            }
-            Err(Error::Disconnected)
-        }
-        None => {
-            // SRV lookup error, retry with hostname
-            connect_to_host(domain, fallback_port).await
-        }
-    }
-}
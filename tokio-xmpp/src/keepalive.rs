@@ -0,0 +1,261 @@
+//! Keeping a connection alive with XMPP pings (XEP-0199)
+//!
+//! Pings get sent at a regular interval, and the connection gets
+//! considered dead once one of them stays unanswered for too long,
+//! which a silently dropped TCP connection would otherwise only reveal
+//! much later.
+
+use std::convert::TryFrom;
+use std::time::{Duration, Instant};
+use xmpp_parsers::iq::{Iq, IqType};
+use xmpp_parsers::ping::Ping;
+use xmpp_parsers::{ns, Element};
+
+/// What to do once the [`Keepalive::deadline`] has been reached
+#[derive(Debug)]
+pub enum KeepaliveAction {
+    /// Send this ping to the server
+    SendPing(Element),
+    /// The last ping never got answered, the connection is dead
+    Dead,
+}
+
+/// What an incoming stanza was, for the keepalive
+#[derive(Debug)]
+pub enum KeepaliveIncoming {
+    /// The answer to our last ping, not to be passed to the application
+    Pong,
+    /// A ping from the server, to be answered with this stanza instead of
+    /// being passed to the application
+    Ping(Element),
+    /// Anything else, for the application
+    Other,
+}
+
+/// State of the pings sent on a connection
+#[derive(Debug)]
+pub struct Keepalive {
+    interval: Duration,
+    timeout: Duration,
+    deadline: Instant,
+    pending: Option<(String, Instant)>,
+    next_id: u64,
+    latency: Option<Duration>,
+}
+
+impl Keepalive {
+    /// Ping every `interval` without hearing back from the server, and
+    /// consider the connection dead if a ping stays unanswered after
+    /// `timeout`.
+    pub fn new(interval: Duration, timeout: Duration) -> Keepalive {
+        Keepalive {
+            interval,
+            timeout,
+            deadline: Instant::now() + interval,
+            pending: None,
+            next_id: 0,
+            latency: None,
+        }
+    }
+
+    /// Start over, for a new connection established at `now`.
+    pub fn reset(&mut self, now: Instant) {
+        self.deadline = now + self.interval;
+        self.pending = None;
+        self.latency = None;
+    }
+
+    /// When to call [`Keepalive::on_deadline`] next.
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+
+    /// The round-trip time to the server, smoothed over the last pings
+    /// the same way as TCP does, or `None` until one got answered.
+    pub fn latency(&self) -> Option<Duration> {
+        self.latency
+    }
+
+    fn record_latency(&mut self, rtt: Duration) {
+        self.latency = Some(match self.latency {
+            Some(latency) => (latency * 7 + rtt) / 8,
+            None => rtt,
+        });
+    }
+
+    /// Whether a ping has been sent but not answered yet.
+    pub fn is_waiting(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Called once the deadline has been reached, at `now`.
+    pub fn on_deadline(&mut self, now: Instant) -> KeepaliveAction {
+        if self.pending.is_some() {
+            return KeepaliveAction::Dead;
+        }
+        let id = format!("keepalive-{}", self.next_id);
+        self.next_id += 1;
+        self.pending = Some((id.clone(), now));
+        self.deadline = now + self.timeout;
+        KeepaliveAction::SendPing(Iq::from_get(id, Ping).into())
+    }
+
+    /// Handle a stanza received at `now`, answering pings and catching
+    /// the answers to ours.
+    ///
+    /// Any stanza shows the connection is alive, and postpones the next
+    /// ping unless one is already waiting for its answer.
+    pub fn on_stanza(&mut self, stanza: &Element, now: Instant) -> KeepaliveIncoming {
+        if self.pending.is_none() {
+            self.deadline = now + self.interval;
+        }
+        if !stanza.is("iq", ns::JABBER_CLIENT) {
+            return KeepaliveIncoming::Other;
+        }
+        let sent = match self.pending {
+            Some((ref id, sent)) if stanza.attr("id") == Some(id) => Some(sent),
+            _ => None,
+        };
+        if let (Some(sent), Some("result") | Some("error")) = (sent, stanza.attr("type")) {
+            self.pending = None;
+            self.record_latency(now.saturating_duration_since(sent));
+            self.deadline = now + self.interval;
+            return KeepaliveIncoming::Pong;
+        }
+        if stanza.attr("type") != Some("get") || !stanza.has_child("ping", ns::PING) {
+            return KeepaliveIncoming::Other;
+        }
+        match Iq::try_from(stanza.clone()) {
+            Ok(Iq {
+                from,
+                id,
+                payload: IqType::Get(_),
+                ..
+            }) => {
+                let pong = Iq {
+                    from: None,
+                    to: from,
+                    id,
+                    payload: IqType::Result(None),
+                };
+                KeepaliveIncoming::Ping(pong.into())
+            }
+            _ => KeepaliveIncoming::Other,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ping_id(action: KeepaliveAction) -> String {
+        match action {
+            KeepaliveAction::SendPing(ping) => {
+                assert!(ping.has_child("ping", ns::PING));
+                ping.attr("id").unwrap().to_owned()
+            }
+            KeepaliveAction::Dead => panic!("Dead too early"),
+        }
+    }
+
+    #[test]
+    fn test_pong() {
+        let start = Instant::now();
+        let mut keepalive = Keepalive::new(Duration::from_secs(60), Duration::from_secs(10));
+        keepalive.reset(start);
+        assert_eq!(keepalive.deadline(), start + Duration::from_secs(60));
+
+        let now = keepalive.deadline();
+        let id = ping_id(keepalive.on_deadline(now));
+        assert!(keepalive.is_waiting());
+        assert_eq!(keepalive.deadline(), now + Duration::from_secs(10));
+
+        let other: Element = "<iq xmlns='jabber:client' type='result' id='other'/>"
+            .parse()
+            .unwrap();
+        assert!(matches!(
+            keepalive.on_stanza(&other, now),
+            KeepaliveIncoming::Other
+        ));
+        assert_eq!(keepalive.deadline(), now + Duration::from_secs(10));
+        let pong: Element = format!("<iq xmlns='jabber:client' type='result' id='{}'/>", id)
+            .parse()
+            .unwrap();
+        let later = now + Duration::from_secs(1);
+        assert!(matches!(
+            keepalive.on_stanza(&pong, later),
+            KeepaliveIncoming::Pong
+        ));
+        assert!(!keepalive.is_waiting());
+        assert_eq!(keepalive.deadline(), later + Duration::from_secs(60));
+        assert_eq!(keepalive.latency(), Some(Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn test_latency() {
+        let start = Instant::now();
+        let mut keepalive = Keepalive::new(Duration::from_secs(60), Duration::from_secs(10));
+        keepalive.reset(start);
+        assert_eq!(keepalive.latency(), None);
+        for rtt in &[800, 0] {
+            let now = keepalive.deadline();
+            let id = ping_id(keepalive.on_deadline(now));
+            let pong: Element = format!("<iq xmlns='jabber:client' type='error' id='{}'/>", id)
+                .parse()
+                .unwrap();
+            keepalive.on_stanza(&pong, now + Duration::from_millis(*rtt));
+        }
+        assert_eq!(keepalive.latency(), Some(Duration::from_millis(700)));
+
+        keepalive.reset(start);
+        assert_eq!(keepalive.latency(), None);
+    }
+
+    #[test]
+    fn test_postponed() {
+        let start = Instant::now();
+        let mut keepalive = Keepalive::new(Duration::from_secs(60), Duration::from_secs(10));
+        keepalive.reset(start);
+        let message: Element = "<message xmlns='jabber:client'/>".parse().unwrap();
+        let later = start + Duration::from_secs(30);
+        assert!(matches!(
+            keepalive.on_stanza(&message, later),
+            KeepaliveIncoming::Other
+        ));
+        assert_eq!(keepalive.deadline(), later + Duration::from_secs(60));
+    }
+
+    #[test]
+    fn test_dead() {
+        let start = Instant::now();
+        let mut keepalive = Keepalive::new(Duration::from_secs(60), Duration::from_secs(10));
+        keepalive.reset(start);
+        ping_id(keepalive.on_deadline(keepalive.deadline()));
+        assert!(matches!(
+            keepalive.on_deadline(keepalive.deadline()),
+            KeepaliveAction::Dead
+        ));
+
+        // A new connection starts afresh.
+        keepalive.reset(start);
+        assert!(!keepalive.is_waiting());
+    }
+
+    #[test]
+    fn test_incoming_ping() {
+        let mut keepalive = Keepalive::new(Duration::from_secs(60), Duration::from_secs(10));
+        let ping: Element = "<iq xmlns='jabber:client' from='example.org' type='get' id='s2c1'><ping xmlns='urn:xmpp:ping'/></iq>"
+            .parse()
+            .unwrap();
+        let pong = match keepalive.on_stanza(&ping, Instant::now()) {
+            KeepaliveIncoming::Ping(pong) => pong,
+            other => panic!("Unexpected {:?}", other),
+        };
+        let expected: Element =
+            "<iq xmlns='jabber:client' to='example.org' type='result' id='s2c1'/>"
+                .parse()
+                .unwrap();
+        assert_eq!(pong, expected);
+    }
+}
@@ -5,16 +5,29 @@
 mod starttls;
 mod stream_start;
 mod xmpp_codec;
-pub use crate::xmpp_codec::Packet;
+pub use crate::xmpp_codec::{Packet, PullAll, XMPPCodec};
 mod event;
 pub use event::Event;
+pub mod audit;
 mod client;
+pub mod clock;
+pub mod discovery;
 mod happy_eyeballs;
+pub mod keepalive;
+pub mod memory;
+pub mod outbox;
+pub mod pipeline;
+pub mod sanitize;
+pub mod sasl_policy;
 pub mod stream_features;
+pub mod stream_management;
+pub mod tls;
+pub mod verify;
 pub mod xmpp_stream;
-pub use client::{async_client::Client as AsyncClient, simple_client::Client as SimpleClient, async_client::Config as AsyncClientConfig, async_client::ServerConfig as AsyncClientServerConfig};
+pub use client::{async_client::Client as AsyncClient, simple_client::Client as SimpleClient, async_client::Config as AsyncClientConfig, async_client::ServerConfig as AsyncClientServerConfig, TlsMode, DEFAULT_CONNECT_TIMEOUT};
 mod component;
-pub use crate::component::Component;
+pub use crate::component::{router, Component};
 mod error;
 pub use crate::error::{AuthError, ConnecterError, Error, ParseError, ProtocolError};
-pub use starttls::starttls;
+pub use starttls::{starttls, starttls_with_config};
+pub use xmpp_parsers::ns;
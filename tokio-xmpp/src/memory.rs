@@ -0,0 +1,207 @@
+//! Accounting of the memory used by a connection
+//!
+//! A [`MemoryAccount`] is shared by all the parts of a session which may
+//! buffer an unbounded amount of data on behalf of the peer, each one
+//! keeping track of its own usage through a [`MemoryCharge`]. As soon
+//! as the total goes over the ceiling of the account, the part which
+//! grew gets a [`MemoryLimitExceeded`] error, which ends the session.
+//!
+//! The same account may be shared by several connections, to get a
+//! global ceiling instead of a per-connection one.
+
+use std::error::Error as StdError;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// What some memory is being used for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryUse {
+    /// Received bytes not yet decoded into a complete stanza
+    Decoder,
+    /// Stanzas kept around until the peer acknowledges them
+    SmQueue,
+}
+
+impl MemoryUse {
+    const ALL: [MemoryUse; 2] = [MemoryUse::Decoder, MemoryUse::SmQueue];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+impl fmt::Display for MemoryUse {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MemoryUse::Decoder => write!(fmt, "decoder buffer"),
+            MemoryUse::SmQueue => write!(fmt, "stream management queue"),
+        }
+    }
+}
+
+/// Memory ceiling went over
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryLimitExceeded {
+    /// Part of the session which went over the ceiling
+    pub culprit: MemoryUse,
+    /// Total number of bytes in use
+    pub used: usize,
+    /// The ceiling of the account
+    pub ceiling: usize,
+}
+
+impl fmt::Display for MemoryLimitExceeded {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            fmt,
+            "{} bytes in use, over the ceiling of {} bytes, by the {}",
+            self.used, self.ceiling, self.culprit
+        )
+    }
+}
+
+impl StdError for MemoryLimitExceeded {}
+
+#[derive(Debug)]
+struct Counters {
+    ceiling: usize,
+    used: [AtomicUsize; 2],
+}
+
+/// Shared memory usage counters, along with their ceiling
+#[derive(Debug, Clone)]
+pub struct MemoryAccount {
+    counters: Arc<Counters>,
+}
+
+impl MemoryAccount {
+    /// Create an account which doesn't allow more than `ceiling` bytes
+    /// to be in use.
+    pub fn new(ceiling: usize) -> Self {
+        MemoryAccount {
+            counters: Arc::new(Counters {
+                ceiling,
+                used: Default::default(),
+            }),
+        }
+    }
+
+    /// Create an account without any ceiling, only keeping track of the
+    /// usage.
+    pub fn unlimited() -> Self {
+        Self::new(usize::MAX)
+    }
+
+    /// The maximum number of bytes allowed to be in use
+    pub fn ceiling(&self) -> usize {
+        self.counters.ceiling
+    }
+
+    /// Total number of bytes in use
+    pub fn used(&self) -> usize {
+        MemoryUse::ALL
+            .iter()
+            .map(|&category| self.used_by(category))
+            .fold(0, usize::saturating_add)
+    }
+
+    /// Number of bytes in use for `category`
+    pub fn used_by(&self, category: MemoryUse) -> usize {
+        self.counters.used[category.index()].load(Ordering::Relaxed)
+    }
+
+    /// Start tracking memory used for `category`, initially none.
+    pub fn charge(&self, category: MemoryUse) -> MemoryCharge {
+        MemoryCharge {
+            account: self.clone(),
+            category,
+            bytes: 0,
+        }
+    }
+}
+
+/// Usage of a [`MemoryAccount`] by one part of a session
+///
+/// It gets released once dropped.
+#[derive(Debug)]
+pub struct MemoryCharge {
+    account: MemoryAccount,
+    category: MemoryUse,
+    bytes: usize,
+}
+
+impl MemoryCharge {
+    /// Number of bytes currently charged
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// Update the number of bytes charged, failing if the account then
+    /// goes over its ceiling.
+    ///
+    /// The new usage gets recorded even on failure, since it reflects
+    /// what has already been allocated.
+    pub fn set(&mut self, bytes: usize) -> Result<(), MemoryLimitExceeded> {
+        let counter = &self.account.counters.used[self.category.index()];
+        if bytes >= self.bytes {
+            counter.fetch_add(bytes - self.bytes, Ordering::Relaxed);
+        } else {
+            counter.fetch_sub(self.bytes - bytes, Ordering::Relaxed);
+        }
+        let grew = bytes > self.bytes;
+        self.bytes = bytes;
+
+        let used = self.account.used();
+        let ceiling = self.account.ceiling();
+        if grew && used > ceiling {
+            return Err(MemoryLimitExceeded {
+                culprit: self.category,
+                used,
+                ceiling,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Drop for MemoryCharge {
+    fn drop(&mut self) {
+        self.account.counters.used[self.category.index()].fetch_sub(self.bytes, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_charges() {
+        let account = MemoryAccount::new(100);
+        let mut decoder = account.charge(MemoryUse::Decoder);
+        let mut sm = account.charge(MemoryUse::SmQueue);
+        decoder.set(60).unwrap();
+        sm.set(30).unwrap();
+        assert_eq!(account.used(), 90);
+        assert_eq!(account.used_by(MemoryUse::Decoder), 60);
+
+        let err = sm.set(50).unwrap_err();
+        assert_eq!(
+            err,
+            MemoryLimitExceeded {
+                culprit: MemoryUse::SmQueue,
+                used: 110,
+                ceiling: 100,
+            }
+        );
+
+        // Shrinking is always fine.
+        decoder.set(55).unwrap();
+        assert_eq!(account.used(), 105);
+
+        drop(sm);
+        assert_eq!(account.used(), 55);
+        decoder.set(0).unwrap();
+        assert_eq!(account.used(), 0);
+    }
+}
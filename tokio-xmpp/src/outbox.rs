@@ -0,0 +1,432 @@
+//! Queue of stanzas composed while disconnected
+//!
+//! An [`Outbox`] persists stanzas through an [`OutboxStorage`] until they
+//! can be sent, in order, once connected again. When stream management
+//! is in use, stanzas stay in the outbox until the server acknowledged
+//! them, without being sent twice when the session gets resumed.
+//!
+//! An outbox can be bounded, refusing new stanzas with
+//! `Error::OutboxFull` until the queued ones get sent, so that senders
+//! slow down instead of growing it without limit.
+
+use log::warn;
+use std::collections::VecDeque;
+use xmpp_parsers::Element;
+
+use crate::stream_management::SmState;
+use crate::Error;
+
+/// Persistent storage of the stanzas of an [`Outbox`], in order
+pub trait OutboxStorage {
+    /// Append `stanza` at the end of the queue.
+    fn push(&mut self, stanza: &Element) -> Result<(), Error>;
+
+    /// All the stanzas of the queue, oldest first.
+    fn load(&mut self) -> Result<Vec<Element>, Error>;
+
+    /// Remove the `count` oldest stanzas of the queue.
+    fn pop(&mut self, count: usize) -> Result<(), Error>;
+
+    /// The stanza at `index` in the queue, the oldest being at 0.
+    ///
+    /// This loads the whole queue by default, storages able to read a
+    /// single stanza should do so instead.
+    #[allow(clippy::result_large_err)]
+    fn get(&mut self, index: usize) -> Result<Option<Element>, Error> {
+        Ok(self.load()?.into_iter().nth(index))
+    }
+
+    /// Number of stanzas in the queue.
+    ///
+    /// This loads the whole queue by default, storages able to count
+    /// their stanzas should do so instead.
+    #[allow(clippy::result_large_err)]
+    fn count(&mut self) -> Result<usize, Error> {
+        Ok(self.load()?.len())
+    }
+}
+
+impl<T: OutboxStorage + ?Sized> OutboxStorage for Box<T> {
+    fn push(&mut self, stanza: &Element) -> Result<(), Error> {
+        (**self).push(stanza)
+    }
+
+    fn load(&mut self) -> Result<Vec<Element>, Error> {
+        (**self).load()
+    }
+
+    fn pop(&mut self, count: usize) -> Result<(), Error> {
+        (**self).pop(count)
+    }
+
+    fn get(&mut self, index: usize) -> Result<Option<Element>, Error> {
+        (**self).get(index)
+    }
+
+    fn count(&mut self) -> Result<usize, Error> {
+        (**self).count()
+    }
+}
+
+/// Storage keeping the queue in memory, so it doesn't survive a restart
+#[derive(Debug, Clone, Default)]
+pub struct MemoryOutboxStorage {
+    queue: VecDeque<Element>,
+}
+
+impl MemoryOutboxStorage {
+    /// Create an empty storage.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl OutboxStorage for MemoryOutboxStorage {
+    fn push(&mut self, stanza: &Element) -> Result<(), Error> {
+        self.queue.push_back(stanza.clone());
+        Ok(())
+    }
+
+    fn load(&mut self) -> Result<Vec<Element>, Error> {
+        Ok(self.queue.iter().cloned().collect())
+    }
+
+    fn pop(&mut self, count: usize) -> Result<(), Error> {
+        let count = count.min(self.queue.len());
+        self.queue.drain(..count);
+        Ok(())
+    }
+
+    fn get(&mut self, index: usize) -> Result<Option<Element>, Error> {
+        Ok(self.queue.get(index).cloned())
+    }
+
+    fn count(&mut self) -> Result<usize, Error> {
+        Ok(self.queue.len())
+    }
+}
+
+/// Stanzas waiting for a connection
+///
+/// The oldest `in_flight` stanzas of the storage have already been sent,
+/// and are only kept until the server acknowledged them.
+#[derive(Debug)]
+pub struct Outbox<S: OutboxStorage = MemoryOutboxStorage> {
+    storage: S,
+    in_flight: usize,
+    capacity: Option<usize>,
+    // Number of stanzas in the storage, once loaded.
+    len: Option<usize>,
+}
+
+impl Default for Outbox {
+    fn default() -> Self {
+        Outbox::new(MemoryOutboxStorage::new())
+    }
+}
+
+impl<S: OutboxStorage> Outbox<S> {
+    /// Create an outbox on top of `storage`, which may still contain
+    /// stanzas from a previous run.
+    pub fn new(storage: S) -> Self {
+        Outbox {
+            storage,
+            in_flight: 0,
+            capacity: None,
+            len: None,
+        }
+    }
+
+    /// Refuse new stanzas once `capacity` of them are in the outbox, sent
+    /// or not, or accept any number of them with `None`.
+    pub fn set_capacity(&mut self, capacity: Option<usize>) {
+        self.capacity = capacity;
+    }
+
+    /// The maximal number of stanzas in the outbox, if bounded.
+    pub fn capacity(&self) -> Option<usize> {
+        self.capacity
+    }
+
+    /// Whether the outbox would refuse a new stanza.
+    pub fn is_full(&mut self) -> Result<bool, Error> {
+        match self.capacity {
+            Some(capacity) => Ok(self.count()? >= capacity),
+            None => Ok(false),
+        }
+    }
+
+    /// Queue `stanza` to be sent once connected, failing with
+    /// `Error::OutboxFull` when the outbox is full.
+    pub fn push(&mut self, stanza: Element) -> Result<(), Error> {
+        if self.is_full()? {
+            return Err(Error::OutboxFull);
+        }
+        self.storage.push(&stanza)?;
+        if let Some(ref mut len) = self.len {
+            *len += 1;
+        }
+        Ok(())
+    }
+
+    /// The oldest stanza which hasn't been sent yet, to pass to
+    /// [`Outbox::mark_sent`] once it got sent.
+    pub fn next_unsent(&mut self) -> Result<Option<Element>, Error> {
+        if self.pending()? == 0 {
+            return Ok(None);
+        }
+        self.storage.get(self.in_flight)
+    }
+
+    /// Mark the oldest stanza which hasn't been sent yet as sent.
+    pub fn mark_sent(&mut self) {
+        self.in_flight += 1;
+    }
+
+    /// Number of stanzas which haven't been sent yet
+    pub fn pending(&mut self) -> Result<usize, Error> {
+        Ok(self.count()? - self.in_flight)
+    }
+
+    /// Take the stanzas which haven't been sent yet, in order, marking
+    /// them as sent.
+    pub fn flush(&mut self) -> Result<Vec<Element>, Error> {
+        let stanzas: Vec<_> = self
+            .storage
+            .load()?
+            .into_iter()
+            .skip(self.in_flight)
+            .collect();
+        self.in_flight += stanzas.len();
+        Ok(stanzas)
+    }
+
+    fn pop(&mut self, count: usize) -> Result<(), Error> {
+        self.storage.pop(count)?;
+        self.len = None;
+        Ok(())
+    }
+
+    /// Forget about all of the sent stanzas once they got flushed, for
+    /// when stream management isn't in use.
+    pub fn delivered(&mut self) -> Result<(), Error> {
+        self.pop(self.in_flight)?;
+        self.in_flight = 0;
+        Ok(())
+    }
+
+    /// Mark all of the sent stanzas as unsent again, for when the
+    /// connection got lost before they were delivered and the session
+    /// can't get resumed.
+    pub fn requeue(&mut self) {
+        self.in_flight = 0;
+    }
+
+    /// Forget about the sent stanzas which the server acknowledged,
+    /// according to `state`.
+    ///
+    /// The queued stanzas which are still awaiting acknowledgement in
+    /// `state`, as is the case after restoring it from storage, are
+    /// marked as sent too: resuming the session will send them again.
+    pub fn reconcile(&mut self, state: &SmState) -> Result<(), Error> {
+        let mut acked = 0;
+        while acked < self.in_flight {
+            match self.storage.get(acked)? {
+                Some(ref stanza) if !state.unacked.contains(stanza) => acked += 1,
+                _ => break,
+            }
+        }
+        let mut unacked = 0;
+        while let Some(ref stanza) = self.storage.get(acked + unacked)? {
+            if !state.unacked.contains(stanza) {
+                break;
+            }
+            unacked += 1;
+        }
+        self.pop(acked)?;
+        if self.in_flight - acked > unacked {
+            warn!("Sent stanzas from the outbox are unknown to stream management.");
+        }
+        self.in_flight = unacked.max(self.in_flight - acked);
+        Ok(())
+    }
+
+    /// Number of stanzas in the outbox, sent or not
+    pub fn count(&mut self) -> Result<usize, Error> {
+        match self.len {
+            Some(len) => Ok(len),
+            None => {
+                let len = self.storage.count()?;
+                self.len = Some(len);
+                Ok(len)
+            }
+        }
+    }
+
+    /// Number of stanzas sent but not acknowledged yet
+    pub fn in_flight(&self) -> usize {
+        self.in_flight
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+    use std::str::FromStr;
+    use xmpp_parsers::sm::Enabled;
+    use xmpp_parsers::Jid;
+
+    fn message(id: &str) -> Element {
+        Element::builder("message", "jabber:client")
+            .attr("id", id)
+            .build()
+    }
+
+    fn ids(stanzas: &[Element]) -> Vec<&str> {
+        stanzas
+            .iter()
+            .map(|stanza| stanza.attr("id").unwrap())
+            .collect()
+    }
+
+    fn sm_state() -> SmState {
+        let elem: Element = "<enabled xmlns='urn:xmpp:sm:3' id='sm-id' resume='true'/>"
+            .parse()
+            .unwrap();
+        let jid = Jid::from_str("foo@bar/baz").unwrap();
+        SmState::new(&Enabled::try_from(elem).unwrap(), jid).unwrap()
+    }
+
+    #[test]
+    fn test_flush() {
+        let mut outbox = Outbox::default();
+        outbox.push(message("1")).unwrap();
+        outbox.push(message("2")).unwrap();
+        assert_eq!(ids(&outbox.flush().unwrap()), ["1", "2"]);
+        outbox.push(message("3")).unwrap();
+        assert_eq!(ids(&outbox.flush().unwrap()), ["3"]);
+        assert_eq!(outbox.in_flight(), 3);
+        outbox.delivered().unwrap();
+        assert_eq!(outbox.count().unwrap(), 0);
+        assert!(outbox.flush().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_capacity() {
+        let mut outbox = Outbox::default();
+        outbox.set_capacity(Some(2));
+        outbox.push(message("1")).unwrap();
+        outbox.push(message("2")).unwrap();
+        assert!(outbox.is_full().unwrap());
+        assert!(matches!(outbox.push(message("3")), Err(Error::OutboxFull)));
+
+        // Sent stanzas keep their room until delivered.
+        assert_eq!(ids(&[outbox.next_unsent().unwrap().unwrap()]), ["1"]);
+        assert_eq!(outbox.pending().unwrap(), 2);
+        outbox.mark_sent();
+        assert_eq!(outbox.pending().unwrap(), 1);
+        assert!(outbox.is_full().unwrap());
+        outbox.delivered().unwrap();
+        outbox.push(message("3")).unwrap();
+        assert_eq!(ids(&outbox.flush().unwrap()), ["2", "3"]);
+        assert!(outbox.next_unsent().unwrap().is_none());
+    }
+
+    /// Memory storage counting how often it gets loaded whole
+    #[derive(Default)]
+    struct CountingStorage {
+        inner: MemoryOutboxStorage,
+        loads: usize,
+    }
+
+    impl OutboxStorage for CountingStorage {
+        fn push(&mut self, stanza: &Element) -> Result<(), Error> {
+            self.inner.push(stanza)
+        }
+
+        fn load(&mut self) -> Result<Vec<Element>, Error> {
+            self.loads += 1;
+            self.inner.load()
+        }
+
+        fn pop(&mut self, count: usize) -> Result<(), Error> {
+            self.inner.pop(count)
+        }
+
+        fn get(&mut self, index: usize) -> Result<Option<Element>, Error> {
+            self.inner.get(index)
+        }
+
+        fn count(&mut self) -> Result<usize, Error> {
+            self.inner.count()
+        }
+    }
+
+    #[test]
+    fn test_offset_reads() {
+        let mut outbox = Outbox::new(CountingStorage::default());
+        for id in &["1", "2", "3"] {
+            outbox.push(message(id)).unwrap();
+        }
+        let mut sent = Vec::new();
+        while let Some(stanza) = outbox.next_unsent().unwrap() {
+            sent.push(stanza);
+            outbox.mark_sent();
+        }
+        assert_eq!(ids(&sent), ["1", "2", "3"]);
+        outbox.delivered().unwrap();
+        assert_eq!(outbox.count().unwrap(), 0);
+        assert_eq!(outbox.storage.loads, 0);
+    }
+
+    #[test]
+    fn test_requeue() {
+        let mut outbox = Outbox::default();
+        outbox.push(message("1")).unwrap();
+        outbox.push(message("2")).unwrap();
+        assert_eq!(ids(&outbox.flush().unwrap()), ["1", "2"]);
+
+        // The connection got lost before flushing them.
+        outbox.requeue();
+        assert_eq!(outbox.count().unwrap(), 2);
+        assert_eq!(ids(&outbox.flush().unwrap()), ["1", "2"]);
+    }
+
+    #[test]
+    fn test_reconcile() {
+        let mut outbox = Outbox::default();
+        let mut state = sm_state();
+        for id in &["1", "2", "3"] {
+            outbox.push(message(id)).unwrap();
+        }
+        for stanza in outbox.flush().unwrap() {
+            state.stanza_sent(stanza);
+        }
+        outbox.push(message("4")).unwrap();
+
+        state.acknowledged(2).unwrap();
+        outbox.reconcile(&state).unwrap();
+        assert_eq!(outbox.count().unwrap(), 2);
+        assert_eq!(outbox.in_flight(), 1);
+        assert_eq!(ids(&outbox.flush().unwrap()), ["4"]);
+    }
+
+    #[test]
+    fn test_reconcile_after_restart() {
+        let mut storage = MemoryOutboxStorage::new();
+        let mut state = sm_state();
+        for id in &["1", "2"] {
+            storage.push(&message(id)).unwrap();
+            state.stanza_sent(message(id));
+        }
+        storage.push(&message("3")).unwrap();
+
+        // The process restarted, the outbox doesn't know yet that some of
+        // its stanzas were sent and are pending resumption.
+        let mut outbox = Outbox::new(storage);
+        outbox.reconcile(&state).unwrap();
+        assert_eq!(outbox.in_flight(), 2);
+        assert_eq!(ids(&outbox.flush().unwrap()), ["3"]);
+    }
+}
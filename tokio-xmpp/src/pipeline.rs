@@ -0,0 +1,496 @@
+//! Decoding pipeline building and parsing stanzas on a worker pool
+//!
+//! `XMPPCodec` builds every stanza on the task which is doing the I/O,
+//! which becomes the bottleneck of high-throughput components. The
+//! [`Pipeline`] only tokenizes on that task: each stanza's raw XML events
+//! are then handed to a tokio worker which builds the `Element` and runs
+//! the typed parser on it, while stanzas are still yielded in the order
+//! they were received.
+
+use bytes::BytesMut;
+use futures::stream::FuturesOrdered;
+use futures::{task::Poll, Sink, Stream, StreamExt};
+use minidom::tree_builder::{ParserLimits, TreeBuilder};
+use rxml::{PushDriver, RawEvent, RawParser};
+use std::collections::BTreeMap;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::Context;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::task::JoinHandle;
+use tokio_util::codec::{Decoder, Encoder, Framed, FramedParts};
+use xmpp_parsers::Element;
+
+use crate::memory::MemoryCharge;
+use crate::sanitize::{self, ScrubCounter};
+use crate::xmpp_codec::{Packet, XMPPCodec};
+use crate::xmpp_stream::XMPPStream;
+use crate::Error;
+
+/// The raw XML events of a single top-level stanza, not yet built into
+/// an `Element`
+#[derive(Debug)]
+pub struct RawStanza {
+    events: Vec<RawEvent>,
+    prefixes: BTreeMap<Option<String>, String>,
+    limits: ParserLimits,
+}
+
+impl RawStanza {
+    /// Build the `Element` out of these events, resolving namespaces
+    /// against the ones declared on the stream root.
+    pub fn build(self) -> Result<Element, Error> {
+        let mut tree_builder = TreeBuilder::with_inherited_prefixes(self.prefixes);
+        tree_builder.set_limits(self.limits);
+        for event in self.events {
+            tree_builder.process_event(event)?;
+        }
+        tree_builder
+            .root
+            .take()
+            .ok_or_else(|| minidom::Error::EndOfDocument.into())
+    }
+}
+
+/// Anything that can be received by a [`StanzaSplitter`]
+#[derive(Debug)]
+pub enum RawPacket {
+    /// A complete stanza or nonza, as raw events
+    Stanza(RawStanza),
+    /// Plain text (think whitespace keep-alive)
+    Text(String),
+    /// `</stream:stream>` closing tag
+    StreamEnd,
+}
+
+/// Decoder splitting an already opened XMPP stream into [`RawStanza`]s,
+/// without building them
+///
+/// It keeps enforcing the parser limits, memory account and character
+/// policy of the codec it took over from. Encoding is the same as for
+/// `XMPPCodec`.
+pub struct StanzaSplitter {
+    driver: PushDriver<RawParser>,
+    prefixes: BTreeMap<Option<String>, String>,
+    limits: ParserLimits,
+    /// Element depth, the stream root being 1
+    depth: usize,
+    /// Number of elements held, the stream root included
+    elements: usize,
+    /// Number of attributes of the element being opened
+    attributes: usize,
+    events: Vec<RawEvent>,
+    /// Bytes consumed since the last complete stanza
+    stanza_bytes: usize,
+    memory: Option<MemoryCharge>,
+    /// Where the scrubbed bytes wait to be parsed, if scrubbing
+    scrub: Option<(ScrubCounter, BytesMut)>,
+    encoder: XMPPCodec,
+}
+
+impl StanzaSplitter {
+    fn from_codec(codec: XMPPCodec) -> Self {
+        let parts = codec.into_decoder_parts();
+        StanzaSplitter {
+            driver: parts.driver,
+            prefixes: parts.prefixes,
+            limits: parts.limits,
+            depth: 1,
+            elements: 1,
+            attributes: 0,
+            events: Vec::new(),
+            stanza_bytes: parts.stanza_bytes,
+            memory: parts.memory,
+            scrub: parts.scrub,
+            encoder: XMPPCodec::new(),
+        }
+    }
+
+    fn finish_stanza(&mut self) -> RawPacket {
+        self.driver.release_temporaries();
+        self.elements = 1;
+        self.stanza_bytes = 0;
+        RawPacket::Stanza(RawStanza {
+            events: std::mem::take(&mut self.events),
+            prefixes: self.prefixes.clone(),
+            limits: self.limits,
+        })
+    }
+
+    fn decode_packet(&mut self, buf: &mut BytesMut) -> Result<Option<RawPacket>, minidom::Error> {
+        loop {
+            let len = buf.len();
+            let event = self.driver.parse(buf, false);
+            self.stanza_bytes += len - buf.len();
+            let event = match event {
+                Ok(Some(event)) => event,
+                Ok(None) => break,
+                Err(rxml::Error::IO(e)) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e.into()),
+            };
+
+            match event {
+                RawEvent::XmlDeclaration(..) => {}
+                RawEvent::Text(_, text) if self.depth <= 1 => {
+                    return Ok(Some(RawPacket::Text(text.as_str().to_owned())));
+                }
+                RawEvent::ElementHeadOpen(..) => {
+                    if self.depth >= self.limits.max_depth {
+                        return Err(minidom::Error::LimitExceeded("depth"));
+                    }
+                    if self.elements >= self.limits.max_elements {
+                        return Err(minidom::Error::LimitExceeded("elements"));
+                    }
+                    self.elements += 1;
+                    self.attributes = 0;
+                    self.events.push(event);
+                }
+                RawEvent::Attribute(..) => {
+                    self.attributes += 1;
+                    if self.attributes > self.limits.max_attributes {
+                        return Err(minidom::Error::LimitExceeded("attributes"));
+                    }
+                    self.events.push(event);
+                }
+                RawEvent::ElementHeadClose(_) => {
+                    self.depth += 1;
+                    self.events.push(event);
+                }
+                RawEvent::ElementFoot(_) if self.depth <= 1 => {
+                    self.depth = 0;
+                    self.driver.release_temporaries();
+                    return Ok(Some(RawPacket::StreamEnd));
+                }
+                RawEvent::ElementFoot(_) => {
+                    self.depth -= 1;
+                    self.events.push(event);
+                    if self.depth == 1 {
+                        return Ok(Some(self.finish_stanza()));
+                    }
+                }
+                event => self.events.push(event),
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+impl Decoder for StanzaSplitter {
+    type Item = RawPacket;
+    type Error = Error;
+
+    fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        let (packet, buffered) = match self.scrub.take() {
+            Some((counter, mut clean)) => {
+                sanitize::scrub(buf, &mut clean, &counter);
+                let packet = self.decode_packet(&mut clean);
+                let buffered = buf.len() + clean.len();
+                self.scrub = Some((counter, clean));
+                (packet?, buffered)
+            }
+            None => (self.decode_packet(buf)?, buf.len()),
+        };
+        // The events of the stanza being split take about as much memory
+        // as the bytes they got parsed out of.
+        if let Some(ref mut memory) = self.memory {
+            memory.set(self.stanza_bytes + buffered)?;
+        }
+        Ok(packet)
+    }
+}
+
+impl Encoder<Packet> for StanzaSplitter {
+    type Error = io::Error;
+
+    fn encode(&mut self, item: Packet, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        self.encoder.encode(item, dst)
+    }
+}
+
+type Parser<T> = Arc<dyn Fn(Element) -> T + Send + Sync>;
+
+/// Stream of typed stanzas, built and parsed on the tokio worker pool
+///
+/// At most `parallelism` stanzas are being processed at any time, and
+/// they are yielded in the order they were received. This implements
+/// the `futures` crate's [`Stream`](#impl-Stream) and
+/// [`Sink`](#impl-Sink<Element>) traits.
+pub struct Pipeline<S: AsyncRead + AsyncWrite + Unpin, T> {
+    stream: Framed<S, StanzaSplitter>,
+    parse: Parser<T>,
+    in_flight: FuturesOrdered<JoinHandle<Result<T, Error>>>,
+    parallelism: usize,
+    eof: bool,
+    /// The error which ended the stream, yielded after the stanzas
+    /// received before it
+    error: Option<Error>,
+}
+
+impl<S, T> Pipeline<S, T>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    T: Send + 'static,
+{
+    /// Take over an already negotiated `XMPPStream`, running `parse` on
+    /// every received stanza.
+    ///
+    /// This must be called from within a tokio runtime.
+    pub fn new<F>(stream: XMPPStream<S>, parallelism: usize, parse: F) -> Self
+    where
+        F: Fn(Element) -> T + Send + Sync + 'static,
+    {
+        let parts = stream.stream.into_parts();
+        let mut new_parts =
+            FramedParts::new::<Packet>(parts.io, StanzaSplitter::from_codec(parts.codec));
+        new_parts.read_buf = parts.read_buf;
+        new_parts.write_buf = parts.write_buf;
+        Pipeline {
+            stream: Framed::from_parts(new_parts),
+            parse: Arc::new(parse),
+            in_flight: FuturesOrdered::new(),
+            parallelism: parallelism.max(1),
+            eof: false,
+            error: None,
+        }
+    }
+
+    /// Number of stanzas currently being built or parsed
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.len()
+    }
+}
+
+impl<S, T> Stream for Pipeline<S, T>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    T: Send + 'static,
+{
+    type Item = Result<T, Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        while !self.eof && self.in_flight.len() < self.parallelism {
+            match Pin::new(&mut self.stream).poll_next(cx) {
+                Poll::Ready(Some(Ok(RawPacket::Stanza(stanza)))) => {
+                    let parse = self.parse.clone();
+                    let job = tokio::spawn(async move { stanza.build().map(|elem| parse(elem)) });
+                    self.in_flight.push_back(job);
+                }
+                Poll::Ready(Some(Ok(RawPacket::Text(_)))) => {
+                    // Ignore text between stanzas
+                }
+                Poll::Ready(Some(Ok(RawPacket::StreamEnd))) | Poll::Ready(None) => {
+                    self.eof = true;
+                }
+                Poll::Ready(Some(Err(e))) => {
+                    self.eof = true;
+                    self.error = Some(e);
+                }
+                Poll::Pending => break,
+            }
+        }
+
+        match self.in_flight.poll_next_unpin(cx) {
+            Poll::Ready(Some(Ok(result))) => Poll::Ready(Some(result)),
+            Poll::Ready(Some(Err(e))) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+            Poll::Ready(Some(Err(_))) => {
+                self.eof = true;
+                Poll::Ready(Some(Err(Error::Cancelled)))
+            }
+            Poll::Ready(None) if self.eof => Poll::Ready(self.error.take().map(Err)),
+            Poll::Ready(None) | Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<S, T> Sink<Element> for Pipeline<S, T>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    type Error = Error;
+
+    fn start_send(mut self: Pin<&mut Self>, item: Element) -> Result<(), Self::Error> {
+        Pin::new(&mut self.stream)
+            .start_send(Packet::Stanza(item))
+            .map_err(|e| e.into())
+    }
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.stream)
+            .poll_ready(cx)
+            .map_err(|e| e.into())
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.stream)
+            .poll_flush(cx)
+            .map_err(|e| e.into())
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.stream)
+            .poll_close(cx)
+            .map_err(|e| e.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::{MemoryAccount, MemoryUse};
+    use crate::ProtocolError;
+    use bytes::BufMut;
+    use tokio::io::{duplex, AsyncWriteExt};
+
+    const STREAM_START: &[u8] = b"<?xml version='1.0'?><stream:stream xmlns:stream='http://etherx.jabber.org/streams' version='1.0' xmlns='jabber:component:accept' id='foo'>";
+
+    fn is_limit_exceeded(result: &Result<Option<RawPacket>, Error>, limit: &str) -> bool {
+        match result {
+            Err(Error::Protocol(ProtocolError::Parser(e))) => {
+                matches!(e.root_cause(), minidom::Error::LimitExceeded(l) if *l == limit)
+            }
+            _ => false,
+        }
+    }
+
+    fn splitter() -> StanzaSplitter {
+        splitter_from(XMPPCodec::new())
+    }
+
+    fn splitter_from(mut codec: XMPPCodec) -> StanzaSplitter {
+        let mut b = BytesMut::new();
+        b.put_slice(STREAM_START);
+        assert!(matches!(
+            codec.decode(&mut b),
+            Ok(Some(Packet::StreamStart(_)))
+        ));
+        StanzaSplitter::from_codec(codec)
+    }
+
+    #[test]
+    fn test_split_stanzas() {
+        let mut c = splitter();
+        let mut b = BytesMut::new();
+        b.put_slice(b"<message to='a@b'><body>Hello</body></message> <stream:error/>");
+
+        let elem = match c.decode(&mut b) {
+            Ok(Some(RawPacket::Stanza(stanza))) => stanza.build().unwrap(),
+            _ => panic!(),
+        };
+        assert!(elem.is("message", "jabber:component:accept"));
+        assert_eq!(elem.attr("to"), Some("a@b"));
+        assert_eq!(
+            elem.get_child("body", "jabber:component:accept")
+                .unwrap()
+                .text(),
+            "Hello"
+        );
+
+        assert!(matches!(c.decode(&mut b), Ok(Some(RawPacket::Text(ref text))) if text == " "));
+
+        let elem = match c.decode(&mut b) {
+            Ok(Some(RawPacket::Stanza(stanza))) => stanza.build().unwrap(),
+            _ => panic!(),
+        };
+        assert!(elem.is("error", "http://etherx.jabber.org/streams"));
+    }
+
+    #[test]
+    fn test_split_truncated() {
+        let mut c = splitter();
+        let mut b = BytesMut::new();
+        b.put_slice(b"<presence><show>away</sh");
+        assert!(matches!(c.decode(&mut b), Ok(None)));
+
+        b.put_slice(b"ow></presence></stream:stream>");
+        assert!(matches!(c.decode(&mut b), Ok(Some(RawPacket::Stanza(_)))));
+        assert!(matches!(c.decode(&mut b), Ok(Some(RawPacket::StreamEnd))));
+    }
+
+    #[test]
+    fn test_split_limits() {
+        let limits = ParserLimits {
+            max_depth: 3,
+            max_attributes: 4,
+            ..ParserLimits::default()
+        };
+        let mut c = splitter_from(XMPPCodec::with_parser_limits(limits));
+        let mut b = BytesMut::new();
+        b.put_slice(b"<message to='a@b' from='c@d'><body>Hello</body></message>");
+        let elem = match c.decode(&mut b) {
+            Ok(Some(RawPacket::Stanza(stanza))) => stanza.build().unwrap(),
+            _ => panic!(),
+        };
+        assert_eq!(elem.attr("from"), Some("c@d"));
+
+        b.put_slice(b"<message><body><a/></body></message>");
+        assert!(is_limit_exceeded(&c.decode(&mut b), "depth"));
+
+        let mut c = splitter_from(XMPPCodec::with_parser_limits(limits));
+        let mut b = BytesMut::new();
+        b.put_slice(b"<message to='a@b' from='c@d' id='e' type='chat' xml:lang='en'/>");
+        assert!(is_limit_exceeded(&c.decode(&mut b), "attributes"));
+    }
+
+    #[test]
+    fn test_split_memory() {
+        let account = MemoryAccount::new(64);
+        let mut codec = XMPPCodec::new();
+        codec.set_memory_account(&account);
+        let mut c = splitter_from(codec);
+        let mut b = BytesMut::new();
+        b.put_slice(b"<message><body>Hello</body></message>");
+        assert!(matches!(c.decode(&mut b), Ok(Some(RawPacket::Stanza(_)))));
+        assert_eq!(account.used(), 0);
+
+        // Growing a stanza a little at a time.
+        b.put_slice(b"<message><body>");
+        assert!(matches!(c.decode(&mut b), Ok(None)));
+        b.put_slice("a".repeat(64).as_bytes());
+        match c.decode(&mut b) {
+            Err(Error::MemoryLimit(e)) => assert_eq!(e.culprit, MemoryUse::Decoder),
+            _ => panic!(),
+        }
+
+        drop(c);
+        assert_eq!(account.used(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_pipeline_limits() {
+        let (client, mut server) = duplex(4096);
+        let limits = ParserLimits {
+            max_depth: 3,
+            ..ParserLimits::default()
+        };
+        let mut framed = Framed::new(client, XMPPCodec::with_parser_limits(limits));
+        server.write_all(STREAM_START).await.unwrap();
+        assert!(matches!(
+            framed.next().await,
+            Some(Ok(Packet::StreamStart(_)))
+        ));
+        let stream = XMPPStream::new(
+            "component.example".parse().unwrap(),
+            framed,
+            String::from("jabber:component:accept"),
+            String::from("foo"),
+            Element::builder("features", "http://etherx.jabber.org/streams").build(),
+        );
+        let mut pipeline = Pipeline::new(stream, 2, |elem: Element| elem);
+
+        server
+            .write_all(b"<message><body>Hello</body></message><message><body><a/></body></message>")
+            .await
+            .unwrap();
+        let elem = pipeline.next().await.unwrap().unwrap();
+        assert!(elem.is("message", "jabber:component:accept"));
+        match pipeline.next().await {
+            Some(Err(Error::Protocol(ProtocolError::Parser(e)))) => assert!(matches!(
+                e.root_cause(),
+                minidom::Error::LimitExceeded("depth")
+            )),
+            _ => panic!(),
+        }
+    }
+}
@@ -0,0 +1,128 @@
+//! Handling of the characters forbidden in XML arriving from a peer
+//!
+//! XML 1.0 forbids most C0 control characters, U+FFFE and U+FFFF, and of
+//! course invalid UTF-8. By default the parser rejects them, which ends
+//! the session. Some peers send them anyway, so they can instead get
+//! scrubbed before reaching the parser, making sure they never propagate
+//! to the storage of the application.
+
+use bytes::{Buf, BytesMut};
+use std::str;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// What to do with the characters forbidden in XML
+#[derive(Debug, Clone, Default)]
+pub enum CharPolicy {
+    /// Fail decoding, ending the session
+    #[default]
+    Reject,
+    /// Drop the forbidden characters and replace invalid UTF-8 with
+    /// U+FFFD, counting how many got scrubbed in this counter
+    Scrub(ScrubCounter),
+}
+
+/// Number of characters scrubbed, shared by every codec it is given to
+#[derive(Debug, Clone, Default)]
+pub struct ScrubCounter(Arc<AtomicUsize>);
+
+impl ScrubCounter {
+    /// Create a counter at 0
+    pub fn new() -> ScrubCounter {
+        ScrubCounter::default()
+    }
+
+    /// Number of characters scrubbed so far
+    pub fn get(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    fn add(&self, count: usize) {
+        if count > 0 {
+            self.0.fetch_add(count, Ordering::Relaxed);
+        }
+    }
+}
+
+fn is_allowed(c: char) -> bool {
+    match c {
+        '\t' | '\n' | '\r' => true,
+        '\u{0}'..='\u{1f}' | '\u{fffe}' | '\u{ffff}' => false,
+        _ => true,
+    }
+}
+
+fn push_allowed(text: &str, dst: &mut BytesMut) -> usize {
+    if text.chars().all(is_allowed) {
+        dst.extend_from_slice(text.as_bytes());
+        return 0;
+    }
+    let mut scrubbed = 0;
+    for c in text.chars() {
+        if is_allowed(c) {
+            let mut encoded = [0; 4];
+            dst.extend_from_slice(c.encode_utf8(&mut encoded).as_bytes());
+        } else {
+            scrubbed += 1;
+        }
+    }
+    scrubbed
+}
+
+/// Move the complete characters of `src` to `dst`, scrubbing them, and
+/// leaving a character truncated at the end in `src` until the rest of
+/// it arrives
+pub(crate) fn scrub(src: &mut BytesMut, dst: &mut BytesMut, counter: &ScrubCounter) {
+    let mut scrubbed = 0;
+    let mut consumed = 0;
+    loop {
+        let rest = &src[consumed..];
+        match str::from_utf8(rest) {
+            Ok(text) => {
+                scrubbed += push_allowed(text, dst);
+                consumed += rest.len();
+                break;
+            }
+            Err(err) => {
+                let valid = err.valid_up_to();
+                // Safe to unwrap, as the bytes up to here were just validated.
+                let text = str::from_utf8(&rest[..valid]).unwrap();
+                scrubbed += push_allowed(text, dst);
+                consumed += valid;
+                match err.error_len() {
+                    Some(len) => {
+                        dst.extend_from_slice("\u{fffd}".as_bytes());
+                        scrubbed += 1;
+                        consumed += len;
+                    }
+                    None => break,
+                }
+            }
+        }
+    }
+    src.advance(consumed);
+    counter.add(scrubbed);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scrub() {
+        let counter = ScrubCounter::new();
+        let mut src = BytesMut::from(&b"<a>\x01b\tc\xff\xef\xbf\xbf\xc3"[..]);
+        let mut dst = BytesMut::new();
+        scrub(&mut src, &mut dst, &counter);
+        assert_eq!(&dst[..], "<a>b\tc\u{fffd}".as_bytes());
+        assert_eq!(counter.get(), 3);
+
+        // The truncated character waits for its end.
+        assert_eq!(&src[..], b"\xc3");
+        src.extend_from_slice(b"\x9f</a>");
+        scrub(&mut src, &mut dst, &counter);
+        assert!(src.is_empty());
+        assert_eq!(&dst[..], "<a>b\tc\u{fffd}ß</a>".as_bytes());
+        assert_eq!(counter.clone().get(), 3);
+    }
+}
@@ -0,0 +1,101 @@
+//! Which SASL mechanisms a client may authenticate with
+//!
+//! The strongest mechanism offered by the server gets used, among SCRAM-SHA-256,
+//! SCRAM-SHA-1 and PLAIN when authenticating with a password, or ANONYMOUS
+//! when the JID doesn't have any node.  A [`SaslPolicy`] restricts this
+//! choice, so that the client fails to authenticate instead of downgrading
+//! to a mechanism it doesn't want.
+
+/// Restrictions on the SASL mechanisms to use
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SaslPolicy {
+    /// Only use these mechanisms, if set
+    pub allowed: Option<Vec<String>>,
+    /// Never use these mechanisms
+    pub denied: Vec<String>,
+    /// Refuse to send the password in PLAIN over a stream which isn't
+    /// encrypted
+    pub forbid_plain_without_tls: bool,
+}
+
+impl Default for SaslPolicy {
+    fn default() -> Self {
+        SaslPolicy {
+            allowed: None,
+            denied: Vec::new(),
+            forbid_plain_without_tls: true,
+        }
+    }
+}
+
+impl SaslPolicy {
+    /// Create the default policy, allowing every mechanism but PLAIN over
+    /// a cleartext stream
+    pub fn new() -> Self {
+        SaslPolicy::default()
+    }
+
+    /// Only use these mechanisms
+    pub fn with_allowed<I, S>(mut self, mechanisms: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.allowed = Some(mechanisms.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Never use this mechanism
+    pub fn with_denied<S: Into<String>>(mut self, mechanism: S) -> Self {
+        self.denied.push(mechanism.into());
+        self
+    }
+
+    /// Send the password in PLAIN even over a stream which isn't encrypted
+    pub fn allowing_plain_without_tls(mut self) -> Self {
+        self.forbid_plain_without_tls = false;
+        self
+    }
+
+    /// Whether this mechanism may be used, on an encrypted stream if
+    /// `secure`
+    pub fn permits(&self, mechanism: &str, secure: bool) -> bool {
+        if self.forbid_plain_without_tls && !secure && mechanism == "PLAIN" {
+            return false;
+        }
+        if self.denied.iter().any(|denied| denied == mechanism) {
+            return false;
+        }
+        match self.allowed {
+            Some(ref allowed) => allowed.iter().any(|allowed| allowed == mechanism),
+            None => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default() {
+        let policy = SaslPolicy::default();
+        assert!(policy.permits("SCRAM-SHA-1", false));
+        assert!(policy.permits("PLAIN", true));
+        assert!(!policy.permits("PLAIN", false));
+        let policy = policy.allowing_plain_without_tls();
+        assert!(policy.permits("PLAIN", false));
+    }
+
+    #[test]
+    fn test_lists() {
+        let policy = SaslPolicy::new().with_allowed(["SCRAM-SHA-256", "SCRAM-SHA-1"]);
+        assert!(policy.permits("SCRAM-SHA-256", true));
+        assert!(!policy.permits("PLAIN", true));
+        let policy = policy.with_denied("SCRAM-SHA-1");
+        assert!(!policy.permits("SCRAM-SHA-1", true));
+        let policy = SaslPolicy::new().with_denied("ANONYMOUS");
+        assert!(!policy.permits("ANONYMOUS", true));
+        assert!(policy.permits("PLAIN", true));
+    }
+}
@@ -1,71 +1,45 @@
 use futures::{sink::SinkExt, stream::StreamExt};
 
 #[cfg(feature = "tls-rust")]
-use {
-    std::convert::TryFrom,
-    std::sync::Arc,
-    tokio_rustls::{
-        client::TlsStream,
-        rustls::{ClientConfig, OwnedTrustAnchor, RootCertStore, ServerName},
-        TlsConnector,
-    },
-    webpki_roots,
-};
+use tokio_rustls::client::TlsStream;
 
 #[cfg(feature = "tls-native")]
-use {
-    native_tls::TlsConnector as NativeTlsConnector,
-    tokio_native_tls::{TlsConnector, TlsStream},
-};
+use tokio_native_tls::TlsStream;
 
 use tokio::io::{AsyncRead, AsyncWrite};
 use xmpp_parsers::{ns, Element};
 
+use crate::tls::TlsConfig;
+use crate::verify::Trust;
 use crate::xmpp_codec::Packet;
 use crate::xmpp_stream::XMPPStream;
 use crate::{Error, ProtocolError};
 
-#[cfg(feature = "tls-native")]
-async fn get_tls_stream<S: AsyncRead + AsyncWrite + Unpin>(
+/// Performs `<starttls/>` on an XMPPStream and returns a binary
+/// TlsStream.
+pub async fn starttls<S: AsyncRead + AsyncWrite + Unpin>(
     xmpp_stream: XMPPStream<S>,
 ) -> Result<TlsStream<S>, Error> {
-    let domain = &xmpp_stream.jid.clone().domain();
-    let stream = xmpp_stream.into_inner();
-    let tls_stream = TlsConnector::from(NativeTlsConnector::builder().build().unwrap())
-        .connect(&domain, stream)
-        .await?;
-    Ok(tls_stream)
+    starttls_with_config(xmpp_stream, &TlsConfig::default()).await
 }
 
-#[cfg(feature = "tls-rust")]
-async fn get_tls_stream<S: AsyncRead + AsyncWrite + Unpin>(
+/// Performs `<starttls/>` on an XMPPStream and returns a binary
+/// TlsStream established with this configuration.
+///
+/// The endpoint being unknown, DANE can't be used.
+pub async fn starttls_with_config<S: AsyncRead + AsyncWrite + Unpin>(
     xmpp_stream: XMPPStream<S>,
+    config: &TlsConfig,
 ) -> Result<TlsStream<S>, Error> {
-    let domain = &xmpp_stream.jid.clone().domain();
-    let domain = ServerName::try_from(domain.as_str())?;
-    let stream = xmpp_stream.into_inner();
-    let mut root_store = RootCertStore::empty();
-    root_store.add_server_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.0.iter().map(|ta| {
-        OwnedTrustAnchor::from_subject_spki_name_constraints(
-            ta.subject,
-            ta.spki,
-            ta.name_constraints,
-        )
-    }));
-    let config = ClientConfig::builder()
-        .with_safe_defaults()
-        .with_root_certificates(root_store)
-        .with_no_client_auth();
-    let tls_stream = TlsConnector::from(Arc::new(config))
-        .connect(domain, stream)
-        .await?;
-    Ok(tls_stream)
+    let domain = xmpp_stream.jid.clone().domain();
+    let trust = config.verification.resolve(&domain, None).await?;
+    starttls_with_trust(xmpp_stream, config, &trust).await
 }
 
-/// Performs `<starttls/>` on an XMPPStream and returns a binary
-/// TlsStream.
-pub async fn starttls<S: AsyncRead + AsyncWrite + Unpin>(
+pub(crate) async fn starttls_with_trust<S: AsyncRead + AsyncWrite + Unpin>(
     mut xmpp_stream: XMPPStream<S>,
+    config: &TlsConfig,
+    trust: &Trust,
 ) -> Result<TlsStream<S>, Error> {
     let nonza = Element::builder("starttls", ns::TLS).build();
     let packet = Packet::Stanza(nonza);
@@ -82,5 +56,8 @@ pub async fn starttls<S: AsyncRead + AsyncWrite + Unpin>(
         }
     }
 
-    get_tls_stream(xmpp_stream).await
+    let domain = xmpp_stream.jid.clone().domain();
+    config
+        .connect(&domain, xmpp_stream.into_inner(), trust, false)
+        .await
 }
@@ -3,6 +3,8 @@
 use crate::error::AuthError;
 use xmpp_parsers::{ns, Element};
 
+const SASL_CB: &str = "urn:xmpp:sasl-cb:0";
+
 /// Wraps `<stream:features/>`, usually the very first nonza of an
 /// XMPPStream.
 ///
@@ -32,8 +34,24 @@ impl StreamFeatures {
             .map(|mech_el| mech_el.text()))
     }
 
+    /// Iterate over the channel binding types advertised for SASL
+    /// (XEP-0440)
+    pub fn sasl_channel_bindings<'a>(&'a self) -> impl Iterator<Item = String> + 'a {
+        self.0
+            .get_child("sasl-channel-binding", SASL_CB)
+            .into_iter()
+            .flat_map(|bindings| bindings.children())
+            .filter(|child| child.is("channel-binding", SASL_CB))
+            .filter_map(|binding| binding.attr("type").map(String::from))
+    }
+
     /// Does server support user resource binding?
     pub fn can_bind(&self) -> bool {
         self.0.get_child("bind", ns::BIND).is_some()
     }
+
+    /// Does server support stream management (XEP-0198)?
+    pub fn can_stream_management(&self) -> bool {
+        self.0.get_child("sm", ns::SM).is_some()
+    }
 }
@@ -0,0 +1,339 @@
+//! XEP-0198: Stream Management state, and hooks to persist it
+//!
+//! Keeping the [`SmState`] of a session in a [`SmStorage`] which
+//! outlives the process, the application can resume the session after
+//! a crash or a restart, without losing the stanzas which were sent
+//! but not yet acknowledged by the server.
+//!
+//! `AsyncClient::set_stream_management()` enables it on the client.
+
+use futures::stream::StreamExt;
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use tokio::io::{AsyncRead, AsyncWrite};
+use xmpp_parsers::sm::{Enable, Enabled, Resume, Resumed, StreamId};
+use xmpp_parsers::{ns, Element, Jid};
+
+use crate::xmpp_codec::Packet;
+use crate::xmpp_stream::XMPPStream;
+use crate::{Error, ProtocolError};
+
+/// Everything needed to resume a stream management session
+#[derive(Debug, Clone, PartialEq)]
+pub struct SmState {
+    /// Id given by the server to resume the stream
+    pub stream_id: StreamId,
+    /// Where the server would like us to reconnect to for resumption
+    pub location: Option<String>,
+    /// The JID the session got bound to, which it keeps once resumed
+    pub bound_jid: Jid,
+    /// Number of stanzas received from the server, modulo 2^32
+    pub inbound: u32,
+    /// Number of stanzas acknowledged by the server, modulo 2^32
+    pub outbound: u32,
+    /// Stanzas sent but not acknowledged yet, oldest first
+    pub unacked: VecDeque<Element>,
+}
+
+impl SmState {
+    /// Create the state of a session bound to `bound_jid` which the
+    /// server just enabled, or `None` if it didn't allow resumption.
+    pub fn new(enabled: &Enabled, bound_jid: Jid) -> Option<SmState> {
+        let stream_id = enabled.id.clone()?;
+        Some(SmState {
+            stream_id,
+            location: enabled.location.clone(),
+            bound_jid,
+            inbound: 0,
+            outbound: 0,
+            unacked: VecDeque::new(),
+        })
+    }
+
+    /// Count a stanza received from the server.
+    pub fn stanza_received(&mut self) {
+        self.inbound = self.inbound.wrapping_add(1);
+    }
+
+    /// Keep a stanza sent to the server until it gets acknowledged.
+    pub fn stanza_sent(&mut self, stanza: Element) {
+        self.unacked.push_back(stanza);
+    }
+
+    /// Drop the stanzas acknowledged by the server with `h`, returning
+    /// them.
+    pub fn acknowledged(&mut self, h: u32) -> Result<Vec<Element>, Error> {
+        let count = h.wrapping_sub(self.outbound) as usize;
+        if count > self.unacked.len() {
+            return Err(ProtocolError::InvalidAck.into());
+        }
+        self.outbound = h;
+        Ok(self.unacked.drain(..count).collect())
+    }
+
+    /// The request to resume this session.
+    pub fn resume(&self) -> Resume {
+        Resume {
+            h: self.inbound,
+            previd: self.stream_id.clone(),
+        }
+    }
+
+    /// Handle the server resuming the session after having handled `h`
+    /// of our stanzas, returning the ones to send again, which will be
+    /// queued anew once passed to `stanza_sent()`.
+    pub fn resumed(&mut self, h: u32) -> Result<Vec<Element>, Error> {
+        self.acknowledged(h)?;
+        Ok(self.unacked.drain(..).collect())
+    }
+}
+
+/// Whether this element is a stanza, counted by stream management
+pub(crate) fn is_stanza(elem: &Element) -> bool {
+    elem.ns() == ns::JABBER_CLIENT && matches!(elem.name(), "message" | "presence" | "iq")
+}
+
+/// Wait for the answer of the server to a stream management request,
+/// keeping the stanzas received meanwhile in `early`
+async fn answer<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut XMPPStream<S>,
+    early: &mut Vec<Element>,
+) -> Result<Element, Error> {
+    loop {
+        match stream.next().await {
+            Some(Ok(Packet::Stanza(elem))) if elem.ns() == ns::SM => return Ok(elem),
+            Some(Ok(Packet::Stanza(elem))) => early.push(elem),
+            Some(Ok(Packet::Text(_))) => (),
+            Some(Ok(_)) => return Err(ProtocolError::InvalidToken.into()),
+            Some(Err(e)) => return Err(e),
+            None => return Err(Error::Disconnected),
+        }
+    }
+}
+
+/// Try to resume the session of `state` on this freshly authenticated
+/// stream, returning the stanzas to send again, or `None` if the server
+/// refused, in which case the stream can still get bound.
+pub(crate) async fn resume<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut XMPPStream<S>,
+    state: &mut SmState,
+) -> Result<Option<Vec<Element>>, Error> {
+    stream.send_stanza(state.resume()).await?;
+    let elem = answer(stream, &mut Vec::new()).await?;
+    if elem.is("resumed", ns::SM) {
+        let resumed = Resumed::try_from(elem).map_err(ProtocolError::Parsers)?;
+        if resumed.previd != state.stream_id {
+            return Err(ProtocolError::InvalidAck.into());
+        }
+        Ok(Some(state.resumed(resumed.h)?))
+    } else if elem.is("failed", ns::SM) {
+        Ok(None)
+    } else {
+        Err(ProtocolError::InvalidToken.into())
+    }
+}
+
+/// Enable stream management, with resumption, on this freshly bound
+/// stream, returning its state or `None` if the server refused
+///
+/// The stanzas received before the answer don't count for stream
+/// management, and get kept in `early`.
+pub(crate) async fn enable<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut XMPPStream<S>,
+    early: &mut Vec<Element>,
+) -> Result<Option<SmState>, Error> {
+    stream.send_stanza(Enable::new().with_resume()).await?;
+    let elem = answer(stream, early).await?;
+    if elem.is("enabled", ns::SM) {
+        let enabled = Enabled::try_from(elem).map_err(ProtocolError::Parsers)?;
+        Ok(SmState::new(&enabled, stream.jid.clone()))
+    } else if elem.is("failed", ns::SM) {
+        Ok(None)
+    } else {
+        Err(ProtocolError::InvalidToken.into())
+    }
+}
+
+/// Persistent storage of an [`SmState`]
+///
+/// The state should be saved every time it changes, at least on
+/// acknowledgement, and cleared once the session got closed cleanly or
+/// the server refused to resume it.
+pub trait SmStorage {
+    /// Store `state`, replacing any previous one.
+    fn save(&mut self, state: &SmState) -> Result<(), Error>;
+
+    /// Retrieve the last saved state, if any.
+    fn load(&mut self) -> Result<Option<SmState>, Error>;
+
+    /// Forget about the saved state.
+    fn clear(&mut self) -> Result<(), Error>;
+}
+
+/// Storage keeping the state in memory, which only allows resumption
+/// within the same process
+#[derive(Debug, Clone, Default)]
+pub struct MemorySmStorage {
+    state: Option<SmState>,
+}
+
+impl MemorySmStorage {
+    /// Create an empty storage.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl SmStorage for MemorySmStorage {
+    fn save(&mut self, state: &SmState) -> Result<(), Error> {
+        self.state = Some(state.clone());
+        Ok(())
+    }
+
+    fn load(&mut self) -> Result<Option<SmState>, Error> {
+        Ok(self.state.clone())
+    }
+
+    fn clear(&mut self) -> Result<(), Error> {
+        self.state = None;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use tokio::io::{duplex, AsyncReadExt, AsyncWriteExt, DuplexStream};
+
+    fn state() -> SmState {
+        let elem: Element = "<enabled xmlns='urn:xmpp:sm:3' id='some-long-sm-id' resume='true'/>"
+            .parse()
+            .unwrap();
+        let jid = Jid::from_str("foo@bar/baz").unwrap();
+        SmState::new(&Enabled::try_from(elem).unwrap(), jid).unwrap()
+    }
+
+    fn message(id: &str) -> Element {
+        Element::builder("message", "jabber:client")
+            .attr("id", id)
+            .build()
+    }
+
+    #[test]
+    fn test_acks() {
+        let mut state = state();
+        state.stanza_sent(message("1"));
+        state.stanza_sent(message("2"));
+        state.stanza_sent(message("3"));
+
+        let acked = state.acknowledged(2).unwrap();
+        assert_eq!(acked.len(), 2);
+        assert_eq!(acked[1].attr("id"), Some("2"));
+        assert_eq!(state.unacked.len(), 1);
+
+        match state.acknowledged(4) {
+            Err(Error::Protocol(ProtocolError::InvalidAck)) => (),
+            _ => panic!(),
+        }
+        assert_eq!(state.acknowledged(3).unwrap().len(), 1);
+        assert!(state.unacked.is_empty());
+    }
+
+    #[test]
+    fn test_wrapping() {
+        let mut state = state();
+        state.outbound = u32::MAX;
+        state.stanza_sent(message("1"));
+        state.stanza_sent(message("2"));
+        assert_eq!(state.acknowledged(1).unwrap().len(), 2);
+
+        state.inbound = u32::MAX;
+        state.stanza_received();
+        assert_eq!(state.inbound, 0);
+    }
+
+    #[test]
+    fn test_persisted_resumption() {
+        let mut storage = MemorySmStorage::new();
+        let mut state = state();
+        state.stanza_received();
+        state.stanza_sent(message("1"));
+        state.stanza_sent(message("2"));
+        storage.save(&state).unwrap();
+        drop(state);
+
+        let mut state = storage.load().unwrap().unwrap();
+        let resume = state.resume();
+        assert_eq!(resume.h, 1);
+        assert_eq!(resume.previd, StreamId(String::from("some-long-sm-id")));
+
+        let resend = state.resumed(1).unwrap();
+        assert_eq!(resend.len(), 1);
+        assert_eq!(resend[0].attr("id"), Some("2"));
+        assert_eq!(state.outbound, 1);
+        assert!(state.unacked.is_empty());
+
+        storage.clear().unwrap();
+        assert_eq!(storage.load().unwrap(), None);
+    }
+
+    /// Start a stream on which the server already sent `answers`.
+    async fn start(answers: &str) -> (XMPPStream<DuplexStream>, DuplexStream) {
+        let (client, mut server) = duplex(4096);
+        server
+            .write_all(b"<stream:stream xmlns='jabber:client' xmlns:stream='http://etherx.jabber.org/streams' version='1.0' id='s1'><stream:features><sm xmlns='urn:xmpp:sm:3'/></stream:features>")
+            .await
+            .unwrap();
+        server.write_all(answers.as_bytes()).await.unwrap();
+        let jid = Jid::from_str("foo@bar/baz").unwrap();
+        let stream = XMPPStream::start(client, jid, ns::JABBER_CLIENT.to_owned())
+            .await
+            .unwrap();
+        assert!(stream.stream_features.can_stream_management());
+        (stream, server)
+    }
+
+    async fn sent(server: &mut DuplexStream) -> String {
+        let mut buf = [0; 4096];
+        let read = server.read(&mut buf).await.unwrap();
+        String::from_utf8(buf[..read].to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_enable() {
+        let (mut stream, mut server) = start(
+            "<message xmlns='jabber:client' id='early'/><enabled xmlns='urn:xmpp:sm:3' id='new-id' resume='true'/>",
+        )
+        .await;
+        let mut early = Vec::new();
+        let state = enable(&mut stream, &mut early).await.unwrap().unwrap();
+        assert!(sent(&mut server).await.contains("<enable"));
+        assert_eq!(state.stream_id, StreamId(String::from("new-id")));
+        assert_eq!(state.bound_jid, Jid::from_str("foo@bar/baz").unwrap());
+        assert_eq!(early.len(), 1);
+        assert!(is_stanza(&early[0]));
+    }
+
+    #[tokio::test]
+    async fn test_resume() {
+        let mut state = state();
+        state.stanza_received();
+        state.stanza_sent(message("1"));
+        state.stanza_sent(message("2"));
+        let (mut stream, mut server) =
+            start("<resumed xmlns='urn:xmpp:sm:3' h='1' previd='some-long-sm-id'/>").await;
+        let resend = resume(&mut stream, &mut state).await.unwrap().unwrap();
+        let request = sent(&mut server).await;
+        assert!(request.contains("<resume"));
+        assert!(request.contains("h=\"1\""));
+        assert_eq!(resend.len(), 1);
+        assert_eq!(resend[0].attr("id"), Some("2"));
+
+        let (mut stream, _server) = start(
+            "<failed xmlns='urn:xmpp:sm:3'><item-not-found xmlns='urn:ietf:params:xml:ns:xmpp-stanzas'/></failed>",
+        )
+        .await;
+        assert_eq!(resume(&mut stream, &mut state).await.unwrap(), None);
+    }
+}
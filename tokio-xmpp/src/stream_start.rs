@@ -47,7 +47,7 @@ pub async fn start<S: AsyncRead + AsyncWrite + Unpin>(
         .get("id")
         .ok_or(ProtocolError::NoStreamId)?
         .clone();
-    let stream = if stream_ns == "jabber:client" && stream_attrs.get("version").is_some() {
+    let stream = if stream_ns == ns::JABBER_CLIENT && stream_attrs.contains_key("version") {
         let stream_features;
         loop {
             match stream.next().await {
@@ -0,0 +1,449 @@
+//! Configuration of the TLS connections
+//!
+//! The backend is chosen at build time, native-tls with the `tls-native`
+//! feature or rustls with the `tls-rust` one.  The settings of
+//! [`TlsConfig`] apply to either, and a fully configured connector of the
+//! backend in use can be provided instead for anything they don't cover.
+
+use std::fmt;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::verify::{Trust, VerificationPolicy};
+
+#[cfg(feature = "tls-rust")]
+use {
+    std::convert::TryFrom,
+    std::sync::Arc,
+    std::time::SystemTime,
+    tokio_rustls::{
+        client::TlsStream,
+        rustls::{
+            client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier},
+            Certificate, ClientConfig, Error as RustlsError, OwnedTrustAnchor, PrivateKey,
+            RootCertStore, ServerName,
+        },
+        TlsConnector,
+    },
+    webpki_roots,
+};
+
+#[cfg(feature = "tls-native")]
+use {
+    crate::ConnecterError,
+    base64::engine::general_purpose::STANDARD as BASE64,
+    base64::Engine,
+    native_tls::{Certificate, Identity, TlsConnector as NativeTlsConnector},
+    tokio_native_tls::{TlsConnector, TlsStream},
+};
+
+use crate::Error;
+
+/// The ALPN protocol offered by default on direct TLS connections, which
+/// lets servers sharing their port with other services (XEP-0368) route
+/// them
+pub const XMPP_CLIENT_ALPN: &str = "xmpp-client";
+
+/// A client certificate, to authenticate to the server with
+/// (for instance with SASL EXTERNAL).
+#[derive(Clone)]
+pub struct ClientIdentity {
+    /// The DER certificate chain, starting with the certificate of the
+    /// client
+    pub certificates: Vec<Vec<u8>>,
+    /// The DER PKCS#8 private key of the client certificate
+    pub key: Vec<u8>,
+}
+
+impl fmt::Debug for ClientIdentity {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        // Never log the private key.
+        fmt.debug_struct("ClientIdentity")
+            .field("certificates", &self.certificates.len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// How to establish the TLS connections
+#[derive(Clone)]
+pub struct TlsConfig {
+    /// Trust the roots built in the backend (the system ones with
+    /// native-tls, the Mozilla ones with rustls)
+    pub builtin_roots: bool,
+    /// Additional DER root certificates to trust
+    pub root_certificates: Vec<Vec<u8>>,
+    /// The client certificate to present, if any
+    pub client_identity: Option<ClientIdentity>,
+    /// The ALPN protocols to offer, in order of preference, direct TLS
+    /// connections always offering [`XMPP_CLIENT_ALPN`] first
+    pub alpn_protocols: Vec<String>,
+    /// The name to send in the SNI and to verify the certificate of the
+    /// server against, instead of the domain of the JID
+    pub server_name: Option<String>,
+    /// How to verify the certificate of the server
+    pub verification: VerificationPolicy,
+    #[cfg(feature = "tls-native")]
+    connector: Option<NativeTlsConnector>,
+    #[cfg(feature = "tls-rust")]
+    connector: Option<Arc<ClientConfig>>,
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        TlsConfig {
+            builtin_roots: true,
+            root_certificates: Vec::new(),
+            client_identity: None,
+            alpn_protocols: Vec::new(),
+            server_name: None,
+            verification: VerificationPolicy::default(),
+            connector: None,
+        }
+    }
+}
+
+impl fmt::Debug for TlsConfig {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("TlsConfig")
+            .field("builtin_roots", &self.builtin_roots)
+            .field("root_certificates", &self.root_certificates.len())
+            .field("client_identity", &self.client_identity)
+            .field("alpn_protocols", &self.alpn_protocols)
+            .field("server_name", &self.server_name)
+            .field("verification", &self.verification)
+            .field("custom_connector", &self.connector.is_some())
+            .finish()
+    }
+}
+
+impl TlsConfig {
+    /// Create the default configuration, trusting the builtin roots only
+    pub fn new() -> Self {
+        TlsConfig::default()
+    }
+
+    /// Also trust this DER root certificate
+    pub fn with_root_certificate(mut self, der: Vec<u8>) -> Self {
+        self.root_certificates.push(der);
+        self
+    }
+
+    /// Only trust the root certificates added with
+    /// [`TlsConfig::with_root_certificate`]
+    pub fn without_builtin_roots(mut self) -> Self {
+        self.builtin_roots = false;
+        self
+    }
+
+    /// Present this DER certificate chain, and its DER PKCS#8 private key
+    pub fn with_client_certificate(mut self, certificates: Vec<Vec<u8>>, key: Vec<u8>) -> Self {
+        self.client_identity = Some(ClientIdentity { certificates, key });
+        self
+    }
+
+    /// Offer this ALPN protocol, after the ones already added
+    pub fn with_alpn_protocol<P: Into<String>>(mut self, protocol: P) -> Self {
+        self.alpn_protocols.push(protocol.into());
+        self
+    }
+
+    /// Send this name in the SNI and verify the certificate of the server
+    /// against it, instead of the domain of the JID
+    pub fn with_server_name<N: Into<String>>(mut self, name: N) -> Self {
+        self.server_name = Some(name.into());
+        self
+    }
+
+    /// Verify the certificate of the server with this policy
+    pub fn with_verification(mut self, verification: VerificationPolicy) -> Self {
+        self.verification = verification;
+        self
+    }
+
+    /// Use this connector as is, ignoring every other setting but the
+    /// server name
+    #[cfg(feature = "tls-native")]
+    pub fn with_native_tls_connector(mut self, connector: NativeTlsConnector) -> Self {
+        self.connector = Some(connector);
+        self
+    }
+
+    /// Use this configuration as is, ignoring every other setting but the
+    /// server name
+    #[cfg(feature = "tls-rust")]
+    pub fn with_rustls_config(mut self, config: Arc<ClientConfig>) -> Self {
+        self.connector = Some(config);
+        self
+    }
+
+    /// The name to connect to for this domain
+    fn server_name<'a>(&'a self, domain: &'a str) -> &'a str {
+        self.server_name.as_deref().unwrap_or(domain)
+    }
+
+    /// The ALPN protocols to offer, on a direct TLS connection if
+    /// `direct_tls`
+    fn alpn_protocols(&self, direct_tls: bool) -> Vec<&str> {
+        let mut protocols = Vec::new();
+        if direct_tls {
+            protocols.push(XMPP_CLIENT_ALPN);
+        }
+        for protocol in &self.alpn_protocols {
+            if !protocols.contains(&protocol.as_str()) {
+                protocols.push(protocol);
+            }
+        }
+        protocols
+    }
+
+    #[cfg(feature = "tls-native")]
+    #[allow(clippy::result_large_err)]
+    fn connector(
+        &self,
+        domain: &str,
+        trust: &Trust,
+        direct_tls: bool,
+    ) -> Result<TlsConnector, Error> {
+        if let Some(ref connector) = self.connector {
+            return Ok(TlsConnector::from(connector.clone()));
+        }
+        let mut builder = NativeTlsConnector::builder();
+        builder.disable_built_in_roots(!self.builtin_roots);
+        // What native-tls can't verify gets verified after the handshake.
+        if !trust.needs_pki() {
+            builder.danger_accept_invalid_certs(true);
+        } else if self.server_name(domain) != domain {
+            builder.danger_accept_invalid_hostnames(true);
+        }
+        for der in &self.root_certificates {
+            builder.add_root_certificate(Certificate::from_der(der)?);
+        }
+        if let Some(ref identity) = self.client_identity {
+            let certificates: String = identity
+                .certificates
+                .iter()
+                .map(|der| pem("CERTIFICATE", der))
+                .collect();
+            let key = pem("PRIVATE KEY", &identity.key);
+            builder.identity(Identity::from_pkcs8(
+                certificates.as_bytes(),
+                key.as_bytes(),
+            )?);
+        }
+        builder.request_alpns(&self.alpn_protocols(direct_tls));
+        Ok(TlsConnector::from(builder.build()?))
+    }
+
+    #[cfg(feature = "tls-rust")]
+    #[allow(clippy::result_large_err)]
+    fn connector(
+        &self,
+        domain: &str,
+        trust: &Trust,
+        direct_tls: bool,
+    ) -> Result<TlsConnector, Error> {
+        if let Some(ref config) = self.connector {
+            return Ok(TlsConnector::from(config.clone()));
+        }
+        let mut root_store = RootCertStore::empty();
+        if self.builtin_roots {
+            root_store.add_server_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.0.iter().map(
+                |ta| {
+                    OwnedTrustAnchor::from_subject_spki_name_constraints(
+                        ta.subject,
+                        ta.spki,
+                        ta.name_constraints,
+                    )
+                },
+            ));
+        }
+        for der in &self.root_certificates {
+            root_store
+                .add(&Certificate(der.clone()))
+                .map_err(|e| RustlsError::General(format!("invalid root certificate: {}", e)))?;
+        }
+        let verifier = PolicyVerifier {
+            pki: WebPkiVerifier::new(root_store, None),
+            domain: ServerName::try_from(domain)?,
+            trust: trust.clone(),
+        };
+        let builder = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_custom_certificate_verifier(Arc::new(verifier));
+        let mut config = match self.client_identity {
+            Some(ref identity) => builder.with_single_cert(
+                identity
+                    .certificates
+                    .iter()
+                    .cloned()
+                    .map(Certificate)
+                    .collect(),
+                PrivateKey(identity.key.clone()),
+            )?,
+            None => builder.with_no_client_auth(),
+        };
+        config.alpn_protocols = self
+            .alpn_protocols(direct_tls)
+            .into_iter()
+            .map(|protocol| protocol.as_bytes().to_vec())
+            .collect();
+        Ok(TlsConnector::from(Arc::new(config)))
+    }
+
+    /// Establish TLS on `stream` for this domain, trusting what `trust`
+    /// says about its certificate, either right after connecting if
+    /// `direct_tls` or after STARTTLS
+    #[cfg(feature = "tls-native")]
+    pub(crate) async fn connect<S: AsyncRead + AsyncWrite + Unpin>(
+        &self,
+        domain: &str,
+        stream: S,
+        trust: &Trust,
+        direct_tls: bool,
+    ) -> Result<TlsStream<S>, Error> {
+        let server_name = self.server_name(domain);
+        let tls_stream = self
+            .connector(domain, trust, direct_tls)?
+            .connect(server_name, stream)
+            .await?;
+        let certificate = tls_stream
+            .get_ref()
+            .peer_certificate()?
+            .ok_or(ConnecterError::Certificate("no certificate"))?
+            .to_der()?;
+        trust.check(&certificate, domain, server_name != domain)?;
+        Ok(tls_stream)
+    }
+
+    /// Establish TLS on `stream` for this domain, trusting what `trust`
+    /// says about its certificate, either right after connecting if
+    /// `direct_tls` or after STARTTLS
+    #[cfg(feature = "tls-rust")]
+    pub(crate) async fn connect<S: AsyncRead + AsyncWrite + Unpin>(
+        &self,
+        domain: &str,
+        stream: S,
+        trust: &Trust,
+        direct_tls: bool,
+    ) -> Result<TlsStream<S>, Error> {
+        let server_name = ServerName::try_from(self.server_name(domain))?;
+        let tls_stream = self
+            .connector(domain, trust, direct_tls)?
+            .connect(server_name, stream)
+            .await?;
+        Ok(tls_stream)
+    }
+}
+
+/// Verifies the certificate against the XMPP domain instead of the name
+/// sent in the SNI, or only against what got pinned
+#[cfg(feature = "tls-rust")]
+struct PolicyVerifier {
+    pki: WebPkiVerifier,
+    domain: ServerName,
+    trust: Trust,
+}
+
+#[cfg(feature = "tls-rust")]
+impl ServerCertVerifier for PolicyVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        _server_name: &ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> Result<ServerCertVerified, RustlsError> {
+        if self.trust.needs_pki() {
+            let verified = self.pki.verify_server_cert(
+                end_entity,
+                intermediates,
+                &self.domain,
+                scts,
+                ocsp_response,
+                now,
+            )?;
+            if !self.trust.matches_unauthenticated(&end_entity.0) {
+                return Err(RustlsError::InvalidCertificateData(String::from(
+                    "not matching any TLSA record",
+                )));
+            }
+            return Ok(verified);
+        }
+        if self.trust.matches(&end_entity.0) {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(RustlsError::InvalidCertificateData(String::from(
+                "not pinned and not matching any TLSA record",
+            )))
+        }
+    }
+}
+
+/// Encode this DER blob as PEM, which is all native-tls accepts for
+/// PKCS#8 keys
+#[cfg(feature = "tls-native")]
+fn pem(label: &str, der: &[u8]) -> String {
+    let base64 = BASE64.encode(der);
+    let mut pem = format!("-----BEGIN {}-----\n", label);
+    for line in base64.as_bytes().chunks(64) {
+        pem.push_str(std::str::from_utf8(line).unwrap());
+        pem.push('\n');
+    }
+    pem.push_str(&format!("-----END {}-----\n", label));
+    pem
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_server_name() {
+        let config = TlsConfig::new();
+        assert_eq!(config.server_name("example.org"), "example.org");
+        let config = config.with_server_name("xmpp.example.net");
+        assert_eq!(config.server_name("example.org"), "xmpp.example.net");
+    }
+
+    #[test]
+    fn test_builder() {
+        let config = TlsConfig::new()
+            .without_builtin_roots()
+            .with_root_certificate(vec![1, 2, 3])
+            .with_alpn_protocol("xmpp-client");
+        assert!(!config.builtin_roots);
+        assert_eq!(config.root_certificates, [vec![1, 2, 3]]);
+        assert_eq!(config.alpn_protocols, ["xmpp-client"]);
+        assert!(config.client_identity.is_none());
+    }
+
+    #[test]
+    fn test_alpn_protocols() {
+        let config = TlsConfig::new();
+        assert_eq!(config.alpn_protocols(true), [XMPP_CLIENT_ALPN]);
+        assert!(config.alpn_protocols(false).is_empty());
+        let config = config
+            .with_alpn_protocol("h2")
+            .with_alpn_protocol(XMPP_CLIENT_ALPN);
+        assert_eq!(config.alpn_protocols(true), [XMPP_CLIENT_ALPN, "h2"]);
+        assert_eq!(config.alpn_protocols(false), ["h2", XMPP_CLIENT_ALPN]);
+    }
+
+    #[cfg(feature = "tls-native")]
+    #[test]
+    fn test_pem() {
+        assert_eq!(
+            pem("TEST", b"foobar"),
+            "-----BEGIN TEST-----\nZm9vYmFy\n-----END TEST-----\n"
+        );
+        assert_eq!(
+            pem("TEST", b"fooba"),
+            "-----BEGIN TEST-----\nZm9vYmE=\n-----END TEST-----\n"
+        );
+        assert_eq!(
+            pem("TEST", b"foob"),
+            "-----BEGIN TEST-----\nZm9vYg==\n-----END TEST-----\n"
+        );
+    }
+}
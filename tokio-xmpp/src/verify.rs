@@ -0,0 +1,638 @@
+//! Verification of the certificate of the server
+//!
+//! By default the certificate has to chain up to a trusted root, and to be
+//! valid for the XMPP domain, even when connecting to another host or
+//! sending another name in the SNI.  A [`VerificationPolicy`] can instead
+//! accept certificates whose fingerprint got pinned, including the ones
+//! published over HTTPS with POSH (RFC 7711), or matching the TLSA records
+//! of the endpoint with DANE (RFC 7673).
+//!
+//! Only DANE-EE records get used.  They only replace the trusted roots when
+//! they got validated with DNSSEC; otherwise the certificate has to both
+//! match them and chain up to a trusted root.
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use log::warn;
+use sha2::{Digest, Sha256, Sha512};
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+use trust_dns_resolver::proto::rr::{RData, RecordType};
+use trust_dns_resolver::proto::xfer::DnsRequestOptions;
+use trust_dns_resolver::system_conf::read_system_conf;
+use trust_dns_resolver::{IntoName, Name, TokioAsyncResolver};
+
+use crate::discovery::https_get;
+use crate::{ConnecterError, Error};
+
+/// The SHA-256 fingerprint of a DER certificate
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Fingerprint(pub [u8; 32]);
+
+impl Fingerprint {
+    /// Compute the fingerprint of this DER certificate
+    pub fn of(certificate: &[u8]) -> Self {
+        Fingerprint(Sha256::digest(certificate).into())
+    }
+}
+
+impl fmt::Debug for Fingerprint {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "Fingerprint({})", self)
+    }
+}
+
+/// Colon-separated uppercase hexadecimal, as most tools print them
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        for (i, byte) in self.0.iter().enumerate() {
+            if i != 0 {
+                write!(fmt, ":")?;
+            }
+            write!(fmt, "{:02X}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses hexadecimal, with or without colons
+impl FromStr for Fingerprint {
+    type Err = ConnecterError;
+
+    #[allow(clippy::result_large_err)]
+    fn from_str(s: &str) -> Result<Self, ConnecterError> {
+        let digits: Vec<u8> = s.bytes().filter(|&c| c != b':').collect();
+        let mut fingerprint = [0; 32];
+        if digits.len() != 64 {
+            return Err(ConnecterError::Certificate("invalid fingerprint length"));
+        }
+        for (byte, pair) in fingerprint.iter_mut().zip(digits.chunks(2)) {
+            let pair = std::str::from_utf8(pair)
+                .map_err(|_| ConnecterError::Certificate("invalid fingerprint"))?;
+            *byte = u8::from_str_radix(pair, 16)
+                .map_err(|_| ConnecterError::Certificate("invalid fingerprint"))?;
+        }
+        Ok(Fingerprint(fingerprint))
+    }
+}
+
+/// Whether to use the TLSA records of the endpoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DaneMode {
+    /// Ignore them
+    #[default]
+    Disabled,
+    /// Use them when there are some, verifying the certificate as usual
+    /// otherwise, or as well when they aren't validated with DNSSEC
+    Opportunistic,
+    /// Refuse to connect to an endpoint without any, while still verifying
+    /// the certificate as usual when they aren't validated with DNSSEC
+    Required,
+}
+
+/// How to verify the certificate of the server
+#[derive(Debug, Clone, Default)]
+pub struct VerificationPolicy {
+    /// Accept the certificates with these fingerprints, and only them, for
+    /// instance for self-signed servers
+    pub pins: Vec<Fingerprint>,
+    /// Whether to use DANE
+    pub dane: DaneMode,
+    /// Whether to also pin the fingerprints published with POSH by the
+    /// domain
+    pub posh: bool,
+}
+
+impl VerificationPolicy {
+    /// Create the default policy, verifying the certificate against the
+    /// trusted roots and the XMPP domain
+    pub fn new() -> Self {
+        VerificationPolicy::default()
+    }
+
+    /// Pin this fingerprint
+    pub fn with_pin(mut self, fingerprint: Fingerprint) -> Self {
+        self.pins.push(fingerprint);
+        self
+    }
+
+    /// Use DANE this way
+    pub fn with_dane(mut self, dane: DaneMode) -> Self {
+        self.dane = dane;
+        self
+    }
+
+    /// Also pin the fingerprints published with POSH
+    pub fn with_posh(mut self) -> Self {
+        self.posh = true;
+        self
+    }
+
+    /// Find out what to trust for this domain, when connecting to this
+    /// endpoint if known
+    pub(crate) async fn resolve(
+        &self,
+        domain: &str,
+        endpoint: Option<(&str, u16)>,
+    ) -> Result<Trust, Error> {
+        let mut trust = Trust {
+            fingerprints: self.pins.clone(),
+            ..Trust::default()
+        };
+        if self.posh {
+            match lookup_posh(domain).await {
+                Ok(fingerprints) => trust.fingerprints.extend(fingerprints),
+                Err(e) => warn!("Failed to fetch the POSH file of {}: {}", domain, e),
+            }
+        }
+        if self.dane != DaneMode::Disabled {
+            if let Some((host, port)) = endpoint {
+                let records = lookup_tlsa(host, port).await?;
+                trust.tlsa = records.records;
+                trust.authenticated = records.authenticated;
+            }
+            if self.dane == DaneMode::Required && trust.tlsa.is_empty() {
+                return Err(ConnecterError::Certificate("no usable TLSA record").into());
+            }
+        }
+        Ok(trust)
+    }
+}
+
+/// A DANE-EE TLSA record
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TlsaRecord {
+    /// What gets matched, 0 for the whole certificate and 1 for its
+    /// public key
+    pub selector: u8,
+    /// How it gets matched, 0 for exactly, 1 for its SHA-256 hash and 2
+    /// for its SHA-512 one
+    pub matching: u8,
+    /// The data to match
+    pub data: Vec<u8>,
+}
+
+impl TlsaRecord {
+    /// Whether this DER certificate matches this record
+    pub fn matches(&self, certificate: &[u8]) -> bool {
+        let selected = match self.selector {
+            0 => certificate,
+            1 => match parse_certificate(certificate) {
+                Some(parsed) => parsed.public_key,
+                None => return false,
+            },
+            _ => return false,
+        };
+        match self.matching {
+            0 => selected == self.data.as_slice(),
+            1 => Sha256::digest(selected).as_slice() == self.data.as_slice(),
+            2 => Sha512::digest(selected).as_slice() == self.data.as_slice(),
+            _ => false,
+        }
+    }
+}
+
+/// The DANE-EE TLSA records of an endpoint
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TlsaRecords {
+    /// The records
+    pub records: Vec<TlsaRecord>,
+    /// Whether they got validated with DNSSEC, up to the root trust anchor
+    pub authenticated: bool,
+}
+
+/// Looks up the DANE-EE TLSA records of this endpoint
+///
+/// They first get looked up with a resolver validating DNSSEC itself, then
+/// if that fails with the system one, whose answer isn't authenticated.
+pub async fn lookup_tlsa(host: &str, port: u16) -> Result<TlsaRecords, Error> {
+    let ascii_host = idna::domain_to_ascii(host).map_err(|_| Error::Idna)?;
+    let name = format!("_{}._tcp.{}.", port, ascii_host)
+        .into_name()
+        .map_err(ConnecterError::Dns)?;
+    let (config, mut options) = read_system_conf()?;
+    options.validate = true;
+    let validating = TokioAsyncResolver::tokio(config, options).map_err(ConnecterError::Resolve)?;
+    if let Some(records) = query_tlsa(&validating, name.clone()).await {
+        return Ok(TlsaRecords {
+            records,
+            authenticated: true,
+        });
+    }
+    let resolver = TokioAsyncResolver::tokio_from_system_conf().map_err(ConnecterError::Resolve)?;
+    // No record isn’t an error, DANE is just unavailable.
+    Ok(TlsaRecords {
+        records: query_tlsa(&resolver, name).await.unwrap_or_default(),
+        authenticated: false,
+    })
+}
+
+async fn query_tlsa(resolver: &TokioAsyncResolver, name: Name) -> Option<Vec<TlsaRecord>> {
+    let lookup = resolver
+        .lookup(name, RecordType::TLSA, DnsRequestOptions::default())
+        .await
+        .ok()?;
+    Some(
+        lookup
+            .iter()
+            .filter_map(|rdata| match rdata {
+                RData::TLSA(tlsa) if u8::from(tlsa.cert_usage()) == 3 => Some(TlsaRecord {
+                    selector: tlsa.selector().into(),
+                    matching: tlsa.matching().into(),
+                    data: tlsa.cert_data().to_vec(),
+                }),
+                _ => None,
+            })
+            .collect(),
+    )
+}
+
+/// Extracts the SHA-256 fingerprints of a POSH file
+#[allow(clippy::result_large_err)]
+pub fn parse_posh(json: &str) -> Result<Vec<Fingerprint>, ConnecterError> {
+    let mut fingerprints = Vec::new();
+    let mut rest = json;
+    while let Some(index) = rest.find("\"sha-256\"") {
+        rest = rest[index + 9..].trim_start();
+        rest = rest
+            .strip_prefix(':')
+            .ok_or(ConnecterError::Certificate("invalid POSH file"))?
+            .trim_start()
+            .strip_prefix('"')
+            .ok_or(ConnecterError::Certificate("invalid POSH file"))?;
+        let (value, after) = rest
+            .split_once('"')
+            .ok_or(ConnecterError::Certificate("invalid POSH file"))?;
+        let fingerprint = BASE64
+            .decode(value)
+            .ok()
+            .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+            .ok_or(ConnecterError::Certificate("invalid POSH fingerprint"))?;
+        fingerprints.push(Fingerprint(fingerprint));
+        rest = after;
+    }
+    Ok(fingerprints)
+}
+
+/// Fetches the fingerprints this domain publishes with POSH for its client
+/// service
+pub async fn lookup_posh(domain: &str) -> Result<Vec<Fingerprint>, Error> {
+    let body = https_get(
+        domain,
+        "/.well-known/posh/xmpp-client.json",
+        "application/json",
+    )
+    .await?;
+    Ok(parse_posh(&body)?)
+}
+
+/// What to trust when connecting to an endpoint
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Trust {
+    fingerprints: Vec<Fingerprint>,
+    tlsa: Vec<TlsaRecord>,
+    /// Whether the TLSA records got validated with DNSSEC
+    authenticated: bool,
+}
+
+impl Trust {
+    /// Whether the certificate has to get verified against the trusted
+    /// roots, nothing pinned or authenticated being known about it
+    pub(crate) fn needs_pki(&self) -> bool {
+        self.fingerprints.is_empty() && (self.tlsa.is_empty() || !self.authenticated)
+    }
+
+    /// Whether this DER certificate got pinned or matches a TLSA record
+    pub(crate) fn matches(&self, certificate: &[u8]) -> bool {
+        self.fingerprints.contains(&Fingerprint::of(certificate))
+            || self.tlsa.iter().any(|record| record.matches(certificate))
+    }
+
+    /// Whether this DER certificate, verified against the trusted roots,
+    /// also matches the unauthenticated TLSA records if there are some
+    pub(crate) fn matches_unauthenticated(&self, certificate: &[u8]) -> bool {
+        self.tlsa.is_empty() || self.tlsa.iter().any(|record| record.matches(certificate))
+    }
+
+    /// Verify what the TLS backend couldn't about this DER certificate:
+    /// that it matches when not verified against the roots, or else that
+    /// it is valid for this domain when `check_name`
+    #[cfg_attr(feature = "tls-rust", allow(unused))]
+    #[allow(clippy::result_large_err)]
+    pub(crate) fn check(
+        &self,
+        certificate: &[u8],
+        domain: &str,
+        check_name: bool,
+    ) -> Result<(), ConnecterError> {
+        if !self.needs_pki() {
+            if self.matches(certificate) {
+                return Ok(());
+            }
+            return Err(ConnecterError::Certificate(
+                "not pinned and not matching any TLSA record",
+            ));
+        }
+        if check_name {
+            let parsed = parse_certificate(certificate)
+                .ok_or(ConnecterError::Certificate("invalid certificate"))?;
+            if !parsed
+                .dns_names
+                .iter()
+                .any(|name| name_matches(name, domain))
+            {
+                return Err(ConnecterError::Certificate("not valid for the XMPP domain"));
+            }
+        }
+        if !self.matches_unauthenticated(certificate) {
+            return Err(ConnecterError::Certificate("not matching any TLSA record"));
+        }
+        Ok(())
+    }
+}
+
+/// Whether this DNS-ID of a certificate matches this domain, the leftmost
+/// label possibly being a wildcard
+fn name_matches(name: &str, domain: &str) -> bool {
+    let name = name.trim_end_matches('.').to_ascii_lowercase();
+    let domain = domain.trim_end_matches('.').to_ascii_lowercase();
+    match name.strip_prefix("*.") {
+        Some(suffix) => matches!(
+            domain.split_once('.'),
+            Some((label, rest)) if !label.is_empty() && rest == suffix
+        ),
+        None => name == domain,
+    }
+}
+
+/// A DER element
+struct Der<'a> {
+    tag: u8,
+    contents: &'a [u8],
+    /// The whole element, header included
+    raw: &'a [u8],
+}
+
+/// Splits the first DER element of `input` from what follows it
+fn der_next(input: &[u8]) -> Option<(Der<'_>, &[u8])> {
+    let (&tag, rest) = input.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+    let (len, rest) = if first < 0x80 {
+        (usize::from(first), rest)
+    } else {
+        let n = usize::from(first & 0x7f);
+        if n == 0 || n > 4 || rest.len() < n {
+            return None;
+        }
+        let len = rest[..n]
+            .iter()
+            .fold(0, |len, &byte| len << 8 | usize::from(byte));
+        (len, &rest[n..])
+    };
+    if rest.len() < len {
+        return None;
+    }
+    let header = input.len() - rest.len();
+    let der = Der {
+        tag,
+        contents: &rest[..len],
+        raw: &input[..header + len],
+    };
+    Some((der, &rest[len..]))
+}
+
+/// The parts of a certificate needed for its verification
+struct ParsedCertificate<'a> {
+    /// The DER SubjectPublicKeyInfo
+    public_key: &'a [u8],
+    /// The DNS-IDs of the subjectAltName extension
+    dns_names: Vec<&'a str>,
+}
+
+const SEQUENCE: u8 = 0x30;
+const SUBJECT_ALT_NAME: &[u8] = &[0x55, 0x1d, 0x11];
+
+fn parse_certificate(der: &[u8]) -> Option<ParsedCertificate<'_>> {
+    let (certificate, _) = der_next(der).filter(|(der, _)| der.tag == SEQUENCE)?;
+    let (tbs, _) = der_next(certificate.contents).filter(|(der, _)| der.tag == SEQUENCE)?;
+    let (field, mut rest) = der_next(tbs.contents)?;
+    // Skip the optional version, then the serial number, signature
+    // algorithm, issuer, validity and subject.
+    if field.tag == 0xa0 {
+        rest = der_next(rest)?.1;
+    }
+    for _ in 0..4 {
+        rest = der_next(rest)?.1;
+    }
+    let (public_key, mut rest) = der_next(rest)?;
+    let public_key = public_key.raw;
+    let mut dns_names = Vec::new();
+    while let Some((field, next)) = der_next(rest) {
+        rest = next;
+        if field.tag != 0xa3 {
+            continue;
+        }
+        let (extensions, _) = der_next(field.contents)?;
+        let mut extensions = extensions.contents;
+        while let Some((extension, next)) = der_next(extensions) {
+            extensions = next;
+            let (oid, value) = der_next(extension.contents)?;
+            if oid.contents != SUBJECT_ALT_NAME {
+                continue;
+            }
+            let (mut value, next) = der_next(value)?;
+            // Skip the critical flag.
+            if value.tag == 0x01 {
+                value = der_next(next)?.0;
+            }
+            let (names, _) = der_next(value.contents)?;
+            let mut names = names.contents;
+            while let Some((name, next)) = der_next(names) {
+                names = next;
+                if name.tag == 0x82 {
+                    dns_names.push(std::str::from_utf8(name.contents).ok()?);
+                }
+            }
+        }
+    }
+    Some(ParsedCertificate {
+        public_key,
+        dns_names,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tlv(tag: u8, contents: &[u8]) -> Vec<u8> {
+        let mut der = vec![tag];
+        if contents.len() < 0x80 {
+            der.push(contents.len() as u8);
+        } else {
+            der.extend([0x82, (contents.len() >> 8) as u8, contents.len() as u8]);
+        }
+        der.extend(contents);
+        der
+    }
+
+    fn certificate(dns_names: &[&str]) -> Vec<u8> {
+        let names: Vec<u8> = dns_names
+            .iter()
+            .flat_map(|name| tlv(0x82, name.as_bytes()))
+            .collect();
+        let san = [
+            tlv(0x06, SUBJECT_ALT_NAME),
+            tlv(0x04, &tlv(SEQUENCE, &names)),
+        ]
+        .concat();
+        let extensions = tlv(0xa3, &tlv(SEQUENCE, &tlv(SEQUENCE, &san)));
+        let tbs = [
+            tlv(0xa0, &tlv(0x02, &[2])),
+            tlv(0x02, &[1]),
+            tlv(SEQUENCE, &[]),
+            tlv(SEQUENCE, &[]),
+            tlv(SEQUENCE, &[]),
+            tlv(SEQUENCE, &[]),
+            public_key(),
+            extensions,
+        ]
+        .concat();
+        tlv(
+            SEQUENCE,
+            &[tlv(SEQUENCE, &tbs), tlv(SEQUENCE, &[])].concat(),
+        )
+    }
+
+    fn public_key() -> Vec<u8> {
+        tlv(SEQUENCE, &tlv(0x03, &[0, 1, 2, 3]))
+    }
+
+    #[test]
+    fn test_parse_certificate() {
+        let der = certificate(&["example.org", "*.example.org"]);
+        let parsed = parse_certificate(&der).unwrap();
+        assert_eq!(parsed.public_key, public_key().as_slice());
+        assert_eq!(parsed.dns_names, ["example.org", "*.example.org"]);
+        assert!(parse_certificate(&der[..der.len() - 1]).is_none());
+    }
+
+    #[test]
+    fn test_name_matches() {
+        assert!(name_matches("example.org", "Example.org."));
+        assert!(name_matches("*.example.org", "conference.example.org"));
+        assert!(!name_matches("*.example.org", "example.org"));
+        assert!(!name_matches("*.example.org", "a.b.example.org"));
+        assert!(!name_matches("xmpp.example.net", "example.org"));
+    }
+
+    #[test]
+    fn test_check_name() {
+        let der = certificate(&["example.org"]);
+        let trust = Trust::default();
+        assert!(trust.check(&der, "example.org", true).is_ok());
+        assert!(trust.check(&der, "example.net", true).is_err());
+        assert!(trust.check(&der, "example.net", false).is_ok());
+    }
+
+    #[test]
+    fn test_pins() {
+        let der = certificate(&["example.org"]);
+        let trust = Trust {
+            fingerprints: vec![Fingerprint::of(&der)],
+            ..Trust::default()
+        };
+        assert!(!trust.needs_pki());
+        // The name doesn’t matter once pinned.
+        assert!(trust.check(&der, "example.net", true).is_ok());
+        let other = certificate(&["example.net"]);
+        assert!(trust.check(&other, "example.net", true).is_err());
+    }
+
+    #[test]
+    fn test_tlsa() {
+        let der = certificate(&["example.org"]);
+        let full = TlsaRecord {
+            selector: 0,
+            matching: 1,
+            data: Sha256::digest(&der).to_vec(),
+        };
+        assert!(full.matches(&der));
+        let spki = TlsaRecord {
+            selector: 1,
+            matching: 0,
+            data: public_key(),
+        };
+        assert!(spki.matches(&der));
+        let spki = TlsaRecord {
+            selector: 1,
+            matching: 2,
+            data: Sha512::digest(public_key()).to_vec(),
+        };
+        assert!(spki.matches(&der));
+        assert!(!spki.matches(&certificate(&[])[..10]));
+    }
+
+    #[test]
+    fn test_tlsa_authenticated() {
+        let der = certificate(&["example.org"]);
+        let other = certificate(&["example.net"]);
+        let trust = Trust {
+            tlsa: vec![TlsaRecord {
+                selector: 0,
+                matching: 1,
+                data: Sha256::digest(&der).to_vec(),
+            }],
+            authenticated: true,
+            ..Trust::default()
+        };
+        assert!(!trust.needs_pki());
+        assert!(trust.check(&der, "example.net", true).is_ok());
+        assert!(trust.check(&other, "example.net", true).is_err());
+    }
+
+    #[test]
+    fn test_tlsa_unauthenticated() {
+        let der = certificate(&["example.org"]);
+        let other = certificate(&["example.org", "xmpp.example.org"]);
+        let trust = Trust {
+            tlsa: vec![TlsaRecord {
+                selector: 0,
+                matching: 1,
+                data: Sha256::digest(&der).to_vec(),
+            }],
+            authenticated: false,
+            ..Trust::default()
+        };
+        // Without DNSSEC, the roots still have to get checked…
+        assert!(trust.needs_pki());
+        assert!(trust.check(&der, "example.org", true).is_ok());
+        assert!(trust.check(&der, "example.net", true).is_err());
+        // …and the records to match too.
+        assert!(!trust.matches_unauthenticated(&other));
+        assert!(trust.check(&other, "example.org", true).is_err());
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let fingerprint: Fingerprint =
+            "E3:B0:C4:42:98:FC:1C:14:9A:FB:F4:C8:99:6F:B9:24:27:AE:41:E4:64:9B:93:4C:A4:95:99:1B:78:52:B8:55"
+                .parse()
+                .unwrap();
+        assert_eq!(fingerprint, Fingerprint::of(b""));
+        assert_eq!(
+            fingerprint.to_string(),
+            "E3:B0:C4:42:98:FC:1C:14:9A:FB:F4:C8:99:6F:B9:24:27:AE:41:E4:64:9B:93:4C:A4:95:99:1B:78:52:B8:55"
+        );
+        assert!("e3b0c442".parse::<Fingerprint>().is_err());
+    }
+
+    #[test]
+    fn test_posh() {
+        let json = r#"{"fingerprints": [{"sha-256": "47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU="}], "expires": 604800}"#;
+        let fingerprints = parse_posh(json).unwrap();
+        assert_eq!(fingerprints, [Fingerprint::of(b"")]);
+        assert!(parse_posh(r#"{"fingerprints": [{"sha-256": "AAAA"}]}"#).is_err());
+    }
+}
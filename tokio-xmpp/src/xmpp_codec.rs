@@ -1,12 +1,15 @@
 //! XML stream parser for XMPP
 
+use crate::memory::{MemoryAccount, MemoryCharge, MemoryUse};
+use crate::sanitize::{self, CharPolicy, ScrubCounter};
 use crate::Error;
 use bytes::{BufMut, BytesMut};
 use log::debug;
-use minidom::tree_builder::TreeBuilder;
-use rxml::{Lexer, PushDriver, RawParser};
+use minidom::tree_builder::ParserLimits;
+use minidom::{StreamEvent, StreamParser};
+use rxml::{PushDriver, RawParser};
 use std;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::default::Default;
 use std::fmt::Write;
 use std::io;
@@ -31,19 +34,99 @@ pub struct XMPPCodec {
     /// Outgoing
     ns: Option<String>,
     /// Incoming
-    driver: PushDriver<RawParser>,
-    stanza_builder: TreeBuilder,
+    parser: StreamParser,
+    limits: ParserLimits,
+    /// Bytes consumed since the last complete stanza
+    stanza_bytes: usize,
+    memory: Option<MemoryCharge>,
+    /// Where the scrubbed bytes wait to be parsed, if scrubbing
+    scrub: Option<(ScrubCounter, BytesMut)>,
 }
 
 impl XMPPCodec {
     /// Constructor
     pub fn new() -> Self {
-        let stanza_builder = TreeBuilder::new();
-        let driver = PushDriver::wrap(Lexer::new(), RawParser::new());
+        Self::with_parser_limits(ParserLimits::default())
+    }
+
+    /// Constructor, failing to decode once the incoming stream goes over
+    /// `limits`, its root element being at depth 1.
+    pub fn with_parser_limits(limits: ParserLimits) -> Self {
         XMPPCodec {
             ns: None,
+            parser: StreamParser::with_limits(limits),
+            limits,
+            stanza_bytes: 0,
+            memory: None,
+            scrub: None,
+        }
+    }
+
+    /// Charge the bytes buffered while decoding to `account`, failing
+    /// to decode once it goes over its ceiling.
+    pub fn set_memory_account(&mut self, account: &MemoryAccount) {
+        self.memory = Some(account.charge(MemoryUse::Decoder));
+    }
+
+    /// Set what to do with the characters forbidden in XML, by default
+    /// rejecting them.
+    pub fn set_char_policy(&mut self, policy: &CharPolicy) {
+        self.scrub = match policy {
+            CharPolicy::Reject => None,
+            CharPolicy::Scrub(counter) => Some((counter.clone(), BytesMut::new())),
+        };
+    }
+
+    /// A fresh codec for the restarted stream, keeping the parser
+    /// limits, the memory account and the character policy of this one.
+    pub(crate) fn restart(self) -> Self {
+        let mut memory = self.memory;
+        if let Some(ref mut memory) = memory {
+            // Releasing is always fine.
+            let _ = memory.set(0);
+        }
+        XMPPCodec {
+            ns: None,
+            parser: StreamParser::with_limits(self.limits),
+            limits: self.limits,
+            stanza_bytes: 0,
+            memory,
+            scrub: self.scrub.map(|(counter, _)| (counter, BytesMut::new())),
+        }
+    }
+}
+
+/// The incoming side of a taken apart [`XMPPCodec`], so that decoding can
+/// carry on where it left off.
+pub(crate) struct DecoderParts {
+    pub driver: PushDriver<RawParser>,
+    /// The namespace declarations of the stream root
+    pub prefixes: BTreeMap<Option<String>, String>,
+    pub limits: ParserLimits,
+    /// Bytes consumed since the last complete stanza
+    pub stanza_bytes: usize,
+    pub memory: Option<MemoryCharge>,
+    pub scrub: Option<(ScrubCounter, BytesMut)>,
+}
+
+impl XMPPCodec {
+    /// Unwraps the incoming parser, along with the namespace
+    /// declarations of the stream root, the parser limits, the memory
+    /// account and the character policy.
+    pub(crate) fn into_decoder_parts(self) -> DecoderParts {
+        let prefixes = self
+            .parser
+            .root()
+            .map(|root| root.prefixes.declared_prefixes().clone())
+            .unwrap_or_default();
+        let (driver, _) = self.parser.into_parts();
+        DecoderParts {
             driver,
-            stanza_builder,
+            prefixes,
+            limits: self.limits,
+            stanza_bytes: self.stanza_bytes,
+            memory: self.memory,
+            scrub: self.scrub,
         }
     }
 }
@@ -54,27 +137,17 @@ impl Default for XMPPCodec {
     }
 }
 
-impl Decoder for XMPPCodec {
-    type Item = Packet;
-    type Error = Error;
-
-    fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+impl XMPPCodec {
+    fn decode_packet(&mut self, buf: &mut BytesMut) -> Result<Option<Packet>, Error> {
         loop {
-            let token = match self.driver.parse(buf, false) {
-                Ok(Some(token)) => token,
-                Ok(None) => break,
-                Err(rxml::Error::IO(e)) if e.kind() == std::io::ErrorKind::WouldBlock => break,
-                Err(e) => return Err(minidom::Error::from(e).into()),
-            };
-
-            let had_stream_root = self.stanza_builder.depth() > 0;
-            self.stanza_builder.process_event(token)?;
-            let has_stream_root = self.stanza_builder.depth() > 0;
-
-            if !had_stream_root && has_stream_root {
-                let root = self.stanza_builder.top().unwrap();
-                let attrs =
-                    root.attrs()
+            let len = buf.len();
+            let event = self.parser.parse(buf, false);
+            self.stanza_bytes += len - buf.len();
+            match event? {
+                Some(StreamEvent::Start(root)) => {
+                    self.stanza_bytes = 0;
+                    let attrs = root
+                        .attrs()
                         .map(|(name, value)| (name.to_owned(), value.to_owned()))
                         .chain(root.prefixes.declared_prefixes().iter().map(
                             |(prefix, namespace)| {
@@ -88,21 +161,77 @@ impl Decoder for XMPPCodec {
                             },
                         ))
                         .collect();
-                return Ok(Some(Packet::StreamStart(attrs)));
-            } else if self.stanza_builder.depth() == 1 {
-                self.driver.release_temporaries();
-
-                if let Some(stanza) = self.stanza_builder.unshift_child() {
+                    return Ok(Some(Packet::StreamStart(attrs)));
+                }
+                Some(StreamEvent::Child(stanza)) => {
+                    self.stanza_bytes = 0;
                     return Ok(Some(Packet::Stanza(stanza)));
                 }
-            } else if let Some(_) = self.stanza_builder.root.take() {
-                self.driver.release_temporaries();
+                // Whitespace keep-alives between stanzas.
+                Some(StreamEvent::Text(_)) => (),
+                Some(StreamEvent::End) => return Ok(Some(Packet::StreamEnd)),
+                None => return Ok(None),
+            }
+        }
+    }
+}
+
+impl XMPPCodec {
+    /// Decode every complete packet in `buf`, stopping once it only
+    /// contains an incomplete one, or after the first error.
+    pub fn pull_all<'a>(&'a mut self, buf: &'a mut BytesMut) -> PullAll<'a> {
+        PullAll {
+            codec: self,
+            buf,
+            failed: false,
+        }
+    }
+}
+
+/// Iterator over the complete packets of a buffer, returned by
+/// [`XMPPCodec::pull_all`].
+pub struct PullAll<'a> {
+    codec: &'a mut XMPPCodec,
+    buf: &'a mut BytesMut,
+    failed: bool,
+}
+
+impl Iterator for PullAll<'_> {
+    type Item = Result<Packet, Error>;
 
-                return Ok(Some(Packet::StreamEnd));
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        match self.codec.decode(self.buf) {
+            Ok(packet) => packet.map(Ok),
+            Err(e) => {
+                self.failed = true;
+                Some(Err(e))
             }
         }
+    }
+}
+
+impl Decoder for XMPPCodec {
+    type Item = Packet;
+    type Error = Error;
 
-        Ok(None)
+    fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        let (packet, buffered) = match self.scrub.take() {
+            Some((counter, mut clean)) => {
+                sanitize::scrub(buf, &mut clean, &counter);
+                let packet = self.decode_packet(&mut clean);
+                let buffered = buf.len() + clean.len();
+                self.scrub = Some((counter, clean));
+                (packet?, buffered)
+            }
+            None => (self.decode_packet(buf)?, buf.len()),
+        };
+        if let Some(ref mut memory) = self.memory {
+            memory.set(self.stanza_bytes + buffered)?;
+        }
+        Ok(packet)
     }
 
     fn decode_eof(&mut self, buf: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
@@ -238,6 +367,38 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_pull_all() {
+        let mut c = XMPPCodec::new();
+        let mut b = BytesMut::with_capacity(1024);
+        b.put_slice(b"<stream:stream xmlns:stream='http://etherx.jabber.org/streams' version='1.0' xmlns='jabber:client'><message/><presence/><iq");
+        let packets: Vec<_> = c.pull_all(&mut b).collect();
+        assert_eq!(packets.len(), 3);
+        assert!(matches!(packets[0], Ok(Packet::StreamStart(_))));
+        assert!(matches!(&packets[2], Ok(Packet::Stanza(stanza)) if stanza.name() == "presence"));
+
+        b.put_slice(b"/><message></iq>");
+        let packets: Vec<_> = c.pull_all(&mut b).collect();
+        assert_eq!(packets.len(), 2);
+        assert!(matches!(&packets[0], Ok(Packet::Stanza(stanza)) if stanza.name() == "iq"));
+        assert!(packets[1].is_err());
+    }
+
+    #[test]
+    fn test_error_path() {
+        let mut c = XMPPCodec::new();
+        let mut b = BytesMut::with_capacity(1024);
+        b.put_slice(b"<stream:stream xmlns:stream='http://etherx.jabber.org/streams' version='1.0' xmlns='jabber:client'>");
+        c.decode(&mut b).unwrap();
+        b.put_slice(b"<iq><query xmlns='jabber:iq:roster'><item jid='a@b'></query></iq>");
+        match c.decode(&mut b) {
+            Err(Error::Protocol(crate::ProtocolError::Parser(err))) => {
+                assert_eq!(err.path(), Some("stream > iq > query > item"))
+            }
+            other => panic!("Unexpected {:?}", other),
+        }
+    }
+
     #[test]
     fn test_truncated_stanza() {
         let mut c = XMPPCodec::new();
@@ -362,4 +523,101 @@ mod tests {
             _ => false,
         });
     }
+
+    #[test]
+    fn test_memory_limit() {
+        let account = MemoryAccount::new(64);
+        let mut c = XMPPCodec::new();
+        c.set_memory_account(&account);
+        let mut b = BytesMut::with_capacity(1024);
+        b.put_slice(b"<?xml version='1.0'?><stream:stream xmlns:stream='http://etherx.jabber.org/streams' version='1.0' xmlns='jabber:client'>");
+        let r = c.decode(&mut b);
+        assert!(match r {
+            Ok(Some(Packet::StreamStart(_))) => true,
+            _ => false,
+        });
+        assert_eq!(account.used(), 0);
+
+        b.put_slice(b"<message><body>Foo</body></message>");
+        let r = c.decode(&mut b);
+        assert!(match r {
+            Ok(Some(Packet::Stanza(_))) => true,
+            _ => false,
+        });
+        assert_eq!(account.used(), 0);
+
+        b.put_slice(b"<message><body>");
+        b.put_slice("a".repeat(64).as_bytes());
+        let r = c.decode(&mut b);
+        assert!(match r {
+            Err(Error::MemoryLimit(e)) => e.culprit == MemoryUse::Decoder,
+            _ => false,
+        });
+
+        // The restarted stream still gets accounted.
+        let mut c = c.restart();
+        assert_eq!(account.used(), 0);
+        let mut b = BytesMut::with_capacity(1024);
+        b.put_slice(b"<stream:stream xmlns:stream='http://etherx.jabber.org/streams' version='1.0' xmlns='jabber:client'><message><body>");
+        b.put_slice("a".repeat(64).as_bytes());
+        assert!(c.pull_all(&mut b).any(|packet| match packet {
+            Err(Error::MemoryLimit(e)) => e.culprit == MemoryUse::Decoder,
+            _ => false,
+        }));
+
+        drop(c);
+        assert_eq!(account.used(), 0);
+    }
+
+    #[test]
+    fn test_char_policy() {
+        let mut b = BytesMut::with_capacity(1024);
+        b.put_slice(b"<stream:stream xmlns:stream='http://etherx.jabber.org/streams' version='1.0' xmlns='jabber:client'><message><body>a\x00b\xc3");
+
+        let mut c = XMPPCodec::new();
+        assert!(c.pull_all(&mut b.clone()).any(|packet| packet.is_err()));
+
+        let counter = ScrubCounter::new();
+        let mut c = XMPPCodec::new();
+        c.set_char_policy(&CharPolicy::Scrub(counter.clone()));
+        let packets: Vec<_> = c.pull_all(&mut b).collect();
+        assert!(matches!(packets[..], [Ok(Packet::StreamStart(_))]));
+        b.put_slice(b"\x9f</body></message>");
+        match c.decode(&mut b) {
+            Ok(Some(Packet::Stanza(stanza))) => {
+                assert_eq!(
+                    stanza.get_child("body", "jabber:client").unwrap().text(),
+                    "abß"
+                )
+            }
+            other => panic!("Unexpected {:?}", other),
+        }
+        assert_eq!(counter.get(), 1);
+    }
+    #[test]
+    fn test_parser_limits() {
+        let limits = ParserLimits {
+            max_depth: 3,
+            ..ParserLimits::default()
+        };
+        let mut c = XMPPCodec::with_parser_limits(limits);
+        let mut b = BytesMut::with_capacity(1024);
+        b.put_slice(b"<stream:stream xmlns:stream='http://etherx.jabber.org/streams' version='1.0' xmlns='jabber:client'><message><body>Foo</body></message>");
+        let packets: Vec<_> = c.pull_all(&mut b).collect();
+        assert!(matches!(
+            packets[..],
+            [Ok(Packet::StreamStart(_)), Ok(Packet::Stanza(_))]
+        ));
+
+        b.put_slice(b"<message><body><span/></body></message>");
+        match c.decode(&mut b) {
+            Err(Error::Protocol(crate::ProtocolError::Parser(e))) => {
+                assert!(matches!(
+                    e.root_cause(),
+                    minidom::Error::LimitExceeded("depth")
+                ))
+            }
+            other => panic!("Unexpected {:?}", other),
+        }
+    }
 }
@@ -1,11 +1,12 @@
 //! `XMPPStream` provides encoding/decoding for XMPP
 
+use bytes::BytesMut;
 use futures::sink::Send;
 use futures::{sink::SinkExt, task::Poll, Sink, Stream};
 use std::pin::Pin;
 use std::task::Context;
 use tokio::io::{AsyncRead, AsyncWrite};
-use tokio_util::codec::Framed;
+use tokio_util::codec::{Framed, FramedParts};
 use xmpp_parsers::{Element, Jid};
 
 use crate::stream_features::StreamFeatures;
@@ -13,6 +14,16 @@ use crate::stream_start;
 use crate::xmpp_codec::{Packet, XMPPCodec};
 use crate::Error;
 
+/// The read and write buffers of a stream, kept around to be reused by
+/// the next connection instead of growing new ones.
+#[derive(Debug, Default)]
+pub struct StreamBuffers {
+    /// Buffer of the bytes received but not decoded yet
+    pub read: BytesMut,
+    /// Buffer of the bytes encoded but not sent yet
+    pub write: BytesMut,
+}
+
 /// Wraps a binary stream (tokio's `AsyncRead + AsyncWrite`) to decode
 /// and encode XMPP packets.
 ///
@@ -57,15 +68,54 @@ impl<S: AsyncRead + AsyncWrite + Unpin> XMPPStream<S> {
         stream_start::start(xmpp_stream, jid, ns).await
     }
 
+    /// Send a `<stream:stream>` start tag, reusing `buffers` from a
+    /// previous connection
+    pub async fn start_with_buffers(
+        stream: S,
+        jid: Jid,
+        ns: String,
+        buffers: StreamBuffers,
+    ) -> Result<Self, Error> {
+        let mut parts = FramedParts::new::<Packet>(stream, XMPPCodec::new());
+        parts.read_buf = buffers.read;
+        parts.write_buf = buffers.write;
+        let xmpp_stream = Framed::from_parts(parts);
+        stream_start::start(xmpp_stream, jid, ns).await
+    }
+
+    /// Give back the memory of the read and write buffers above
+    /// `max_capacity` each, for instance after a large stanza went
+    /// through, keeping any bytes they still contain
+    pub fn shrink_buffers(&mut self, max_capacity: usize) {
+        shrink(self.stream.read_buffer_mut(), max_capacity);
+        shrink(self.stream.write_buffer_mut(), max_capacity);
+    }
+
+    /// Unwraps the inner stream, along with the now empty buffers, to
+    /// reuse them for another connection
+    ///
+    /// Any bytes not decoded or sent yet get dropped.
+    pub fn into_buffers(self) -> (S, StreamBuffers) {
+        let mut parts = self.stream.into_parts();
+        parts.read_buf.clear();
+        parts.write_buf.clear();
+        let buffers = StreamBuffers {
+            read: parts.read_buf,
+            write: parts.write_buf,
+        };
+        (parts.io, buffers)
+    }
+
     /// Unwraps the inner stream
     pub fn into_inner(self) -> S {
         self.stream.into_inner()
     }
 
-    /// Re-run `start()`
+    /// Re-run `start()`, keeping the settings of the codec
     pub async fn restart(self) -> Result<Self, Error> {
-        let stream = self.stream.into_inner();
-        Self::start(stream, self.jid, self.ns).await
+        let parts = self.stream.into_parts();
+        let xmpp_stream = Framed::new(parts.io, parts.codec.restart());
+        stream_start::start(xmpp_stream, self.jid, self.ns).await
     }
 }
 
@@ -74,6 +124,30 @@ impl<S: AsyncRead + AsyncWrite + Unpin> XMPPStream<S> {
     pub fn send_stanza<E: Into<Element>>(&mut self, e: E) -> Send<Self, Packet> {
         self.send(Packet::Stanza(e.into()))
     }
+
+    /// Encode all of `packets`, then flush them at once
+    ///
+    /// This saves syscalls and TLS records over sending them one by one,
+    /// for instance for the burst of stanzas sent right after login.
+    pub async fn send_all<I: IntoIterator<Item = Packet>>(
+        &mut self,
+        packets: I,
+    ) -> Result<(), Error> {
+        for packet in packets {
+            self.feed(packet).await?;
+        }
+        self.flush().await
+    }
+}
+
+/// Reallocates `buf` down to `max_capacity`, or to its length if it is
+/// bigger
+fn shrink(buf: &mut BytesMut, max_capacity: usize) {
+    if buf.capacity() > max_capacity {
+        let mut shrunk = BytesMut::with_capacity(max_capacity.max(buf.len()));
+        shrunk.extend_from_slice(buf);
+        *buf = shrunk;
+    }
 }
 
 /// Proxy to self.stream
@@ -127,3 +201,129 @@ impl<S: AsyncRead + AsyncWrite + Unpin> Stream for XMPPStream<S> {
             .map(|result| result.map(|result| result.map_err(|e| e.into())))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::stream::StreamExt;
+    use std::io;
+    use std::str::FromStr;
+    use tokio::io::{duplex, AsyncReadExt, AsyncWriteExt, DuplexStream, ReadBuf};
+    use xmpp_parsers::ns;
+
+    const STREAM_HEADER: &[u8] = b"<stream:stream xmlns='jabber:client' xmlns:stream='http://etherx.jabber.org/streams' id='a' version='1.0'><stream:features/>";
+
+    #[tokio::test]
+    async fn test_buffers() {
+        let jid = Jid::from_str("example.org").unwrap();
+        let (client, mut server) = duplex(1 << 20);
+        server.write_all(STREAM_HEADER).await.unwrap();
+        let mut stream = XMPPStream::start(client, jid.clone(), ns::JABBER_CLIENT.to_owned())
+            .await
+            .unwrap();
+
+        let body = "a".repeat(200_000);
+        let message = format!("<message><body>{}</body></message><iq", body);
+        server.write_all(message.as_bytes()).await.unwrap();
+        let stanza = match stream.next().await {
+            Some(Ok(Packet::Stanza(stanza))) => stanza,
+            other => panic!("Unexpected {:?}", other),
+        };
+        assert_eq!(stanza.name(), "message");
+        stream.stream.read_buffer_mut().reserve(200_000);
+
+        // The incomplete bytes survive the shrinking.
+        stream.shrink_buffers(4096);
+        assert!(stream.stream.read_buffer().capacity() <= 4096);
+        server.write_all(b" type='get' id='1'/>").await.unwrap();
+        match stream.next().await {
+            Some(Ok(Packet::Stanza(stanza))) => assert_eq!(stanza.name(), "iq"),
+            other => panic!("Unexpected {:?}", other),
+        }
+
+        let (_client, buffers) = stream.into_buffers();
+        assert!(buffers.read.is_empty());
+
+        let (client, mut server) = duplex(1 << 20);
+        server.write_all(STREAM_HEADER).await.unwrap();
+        let stream =
+            XMPPStream::start_with_buffers(client, jid, ns::JABBER_CLIENT.to_owned(), buffers)
+                .await
+                .unwrap();
+        assert_eq!(stream.id, "a");
+        let mut header = vec![0; 14];
+        server.read_exact(&mut header).await.unwrap();
+        assert_eq!(header, b"<stream:stream");
+    }
+
+    /// Counts the writes to the inner stream.
+    struct CountingStream {
+        inner: DuplexStream,
+        writes: usize,
+    }
+
+    impl AsyncRead for CountingStream {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context,
+            buf: &mut ReadBuf,
+        ) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.inner).poll_read(cx, buf)
+        }
+    }
+
+    impl AsyncWrite for CountingStream {
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            self.writes += 1;
+            Pin::new(&mut self.inner).poll_write(cx, buf)
+        }
+
+        fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.inner).poll_flush(cx)
+        }
+
+        fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.inner).poll_shutdown(cx)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_send_all() {
+        let jid = Jid::from_str("example.org").unwrap();
+        let (client, mut server) = duplex(1 << 20);
+        server.write_all(STREAM_HEADER).await.unwrap();
+        let client = CountingStream {
+            inner: client,
+            writes: 0,
+        };
+        let mut stream = XMPPStream::start(client, jid, ns::JABBER_CLIENT.to_owned())
+            .await
+            .unwrap();
+        let writes = stream.stream.get_ref().writes;
+
+        let stanzas = vec![
+            Element::builder("presence", ns::JABBER_CLIENT).build(),
+            Element::builder("iq", ns::JABBER_CLIENT)
+                .attr("type", "set")
+                .attr("id", "carbons")
+                .build(),
+        ];
+        stream
+            .send_all(stanzas.into_iter().map(Packet::Stanza))
+            .await
+            .unwrap();
+        assert_eq!(stream.stream.get_ref().writes, writes + 1);
+
+        let mut sent = vec![0; 1024];
+        let mut len = 0;
+        while !String::from_utf8_lossy(&sent[..len]).contains("carbons") {
+            len += server.read(&mut sent[len..]).await.unwrap();
+        }
+        let sent = String::from_utf8_lossy(&sent[..len]);
+        assert!(sent.contains("<presence"));
+    }
+}
@@ -0,0 +1,438 @@
+// Copyright (c) 2022 Emmanuel Gil Peyrot <linkmauve@linkmauve.fr>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Keeping track of the room bookmarks (XEP-0402) of the account, shared
+//! with the other devices of the user.
+//!
+//! Servers which don’t advertise the conversion between PEP Native
+//! Bookmarks and the legacy Private XML Storage (XEP-0411) get the
+//! bookmarks stored in the latter instead.
+
+use std::collections::HashMap;
+use xmpp_parsers::{
+    bookmarks::{Conference as LegacyConference, Storage},
+    bookmarks2::{Autojoin, Conference},
+    data_forms::{DataForm, DataFormType, Field},
+    disco::{DiscoInfoQuery, DiscoInfoResult, Feature},
+    iq::Iq,
+    ns,
+    private::Query as PrivateQuery,
+    pubsub::pubsub::{Item as PubSubItem, Items, Notify, PubSub, Publish, PublishOptions, Retract},
+    pubsub::{Item, ItemId, NodeName},
+    BareJid,
+};
+
+use crate::Event;
+
+/// The id of the disco#info request on the account.
+pub(crate) const DISCO_ID: &str = "bookmarks-disco";
+
+/// Where the bookmarks of the account get stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BookmarkStorage {
+    /// PEP Native Bookmarks (XEP-0402), which the server converts from and
+    /// to the legacy storage for older clients.
+    Pep,
+
+    /// The legacy Private XML Storage (XEP-0049), as a single list.
+    PrivateXml,
+}
+
+/// The last known state of the bookmarks of the account.
+#[derive(Debug, Default)]
+pub struct BookmarkManager {
+    bookmarks: HashMap<BareJid, Conference>,
+    storage: Option<BookmarkStorage>,
+    pep_loaded: bool,
+    migration_started: bool,
+}
+
+impl BookmarkManager {
+    /// Create a manager without any bookmark, until they get loaded.
+    pub fn new() -> BookmarkManager {
+        BookmarkManager::default()
+    }
+
+    /// The bookmark of `room`, if any.
+    pub fn get(&self, room: &BareJid) -> Option<&Conference> {
+        self.bookmarks.get(room)
+    }
+
+    /// All of the bookmarks.
+    pub fn iter(&self) -> impl Iterator<Item = (&BareJid, &Conference)> {
+        self.bookmarks.iter()
+    }
+
+    /// The rooms which should be joined automatically.
+    pub fn autojoin_rooms(&self) -> impl Iterator<Item = (&BareJid, &Conference)> {
+        self.bookmarks
+            .iter()
+            .filter(|(_, conference)| conference.autojoin == Autojoin::True)
+    }
+
+    /// Where the bookmarks are stored, once known.
+    pub fn storage(&self) -> Option<BookmarkStorage> {
+        self.storage
+    }
+
+    /// The request discovering whether the server of `account` converts
+    /// the bookmarks between both storages.
+    pub fn discover_request(account: &BareJid) -> Iq {
+        Iq::from_get(DISCO_ID, DiscoInfoQuery { node: None }).with_to(account.clone().into())
+    }
+
+    /// Pick the storage from the features of the account, or from their
+    /// absence if the request failed, returning the request fetching the
+    /// bookmarks from it.
+    pub fn discovered(&mut self, info: Option<&DiscoInfoResult>) -> Iq {
+        let compat = Feature::new(ns::BOOKMARKS2_COMPAT);
+        let storage = match info {
+            Some(info) if info.features.contains(&compat) => BookmarkStorage::Pep,
+            _ => BookmarkStorage::PrivateXml,
+        };
+        self.storage = Some(storage);
+        self.pep_loaded = false;
+        self.migration_started = false;
+        match storage {
+            BookmarkStorage::Pep => BookmarkManager::fetch_request(),
+            BookmarkStorage::PrivateXml => BookmarkManager::private_fetch_request(),
+        }
+    }
+
+    /// The request fetching all of the bookmarks.
+    pub fn fetch_request() -> Iq {
+        Iq::from_get("bookmarks", PubSub::Items(Items::new(ns::BOOKMARKS2)))
+    }
+
+    /// The request fetching all of the legacy bookmarks.
+    pub fn private_fetch_request() -> Iq {
+        Iq::from_get("bookmarks-private", PrivateQuery::new(Storage::new()))
+    }
+
+    /// The request replacing all of the legacy bookmarks with these ones.
+    fn private_store_request<'a, I>(bookmarks: I) -> Iq
+    where
+        I: Iterator<Item = (&'a BareJid, &'a Conference)>,
+    {
+        let mut storage = Storage::new();
+        for (room, conference) in bookmarks {
+            storage.conferences.push(LegacyConference::from_bookmarks2(
+                room.clone(),
+                conference.clone(),
+            ));
+        }
+        Iq::from_set("bookmarks-private-store", PrivateQuery::new(storage))
+    }
+
+    /// The request storing `conference` as the bookmark of `room`, in the
+    /// current storage.
+    pub fn store_request(&self, room: &BareJid, conference: &Conference) -> Iq {
+        match self.storage {
+            Some(BookmarkStorage::PrivateXml) => {
+                let others = self.bookmarks.iter().filter(|(jid, _)| *jid != room);
+                BookmarkManager::private_store_request(others.chain(Some((room, conference))))
+            }
+            _ => BookmarkManager::publish_request(room, conference.clone()),
+        }
+    }
+
+    /// The request removing the bookmark of `room`, in the current storage.
+    pub fn remove_request(&self, room: &BareJid) -> Iq {
+        match self.storage {
+            Some(BookmarkStorage::PrivateXml) => BookmarkManager::private_store_request(
+                self.bookmarks.iter().filter(|(jid, _)| *jid != room),
+            ),
+            _ => BookmarkManager::retract_request(room),
+        }
+    }
+
+    /// The request storing `conference` as the bookmark of `room`, with
+    /// the node options mandated for bookmarks.
+    pub fn publish_request(room: &BareJid, conference: Conference) -> Iq {
+        let options = DataForm::new(
+            DataFormType::Submit,
+            ns::PUBSUB_PUBLISH_OPTIONS,
+            vec![
+                Field::text_single("pubsub#persist_items", "true"),
+                Field::text_single("pubsub#max_items", "max"),
+                Field::text_single("pubsub#send_last_published_item", "never"),
+                Field::text_single("pubsub#access_model", "whitelist"),
+            ],
+        );
+        let item = Item::new(Some(ItemId(room.to_string())), None, Some(conference));
+        Iq::from_set(
+            format!("bookmark-{}", room),
+            PubSub::Publish {
+                publish: Publish {
+                    node: NodeName(String::from(ns::BOOKMARKS2)),
+                    items: vec![PubSubItem(item)],
+                },
+                publish_options: Some(PublishOptions {
+                    form: Some(options),
+                }),
+            },
+        )
+    }
+
+    /// The request removing the bookmark of `room`.
+    pub fn retract_request(room: &BareJid) -> Iq {
+        let item = Item {
+            id: Some(ItemId(room.to_string())),
+            publisher: None,
+            payload: None,
+        };
+        Iq::from_set(
+            format!("unbookmark-{}", room),
+            PubSub::Retract(Retract {
+                node: NodeName(String::from(ns::BOOKMARKS2)),
+                notify: Notify::True,
+                items: vec![PubSubItem(item)],
+            }),
+        )
+    }
+
+    /// Replace all of the bookmarks with the ones just fetched, asking for
+    /// every autojoin room to be joined, and for the rooms whose bookmark
+    /// disappeared to be left.
+    pub fn loaded(&mut self, bookmarks: Vec<(BareJid, Conference)>) -> Vec<Event> {
+        let bookmarks: HashMap<_, _> = bookmarks.into_iter().collect();
+        let mut events = Vec::new();
+        for room in self.bookmarks.keys() {
+            if !bookmarks.contains_key(room) {
+                events.push(Event::BookmarkRemoved(room.clone()));
+                events.push(Event::LeaveRoom(room.clone()));
+            }
+        }
+        for (room, conference) in bookmarks.iter() {
+            if conference.autojoin == Autojoin::True {
+                events.push(Event::JoinRoom(room.clone(), conference.clone()));
+            }
+        }
+        self.bookmarks = bookmarks;
+        self.pep_loaded = true;
+        events
+    }
+
+    /// The request fetching the legacy bookmarks to migrate them, once the
+    /// PEP ones got loaded, and only once per connection.
+    pub fn migration_request(&mut self) -> Option<Iq> {
+        if self.storage != Some(BookmarkStorage::Pep) || !self.pep_loaded || self.migration_started
+        {
+            return None;
+        }
+        self.migration_started = true;
+        Some(BookmarkManager::private_fetch_request())
+    }
+
+    /// Handle the legacy bookmarks just fetched: they are all of the
+    /// bookmarks with the Private XML Storage, and with PEP the ones still
+    /// missing there get migrated, returning the requests publishing them.
+    pub fn private_loaded(&mut self, storage: Storage) -> (Vec<Event>, Vec<Iq>) {
+        let bookmarks = storage
+            .conferences
+            .into_iter()
+            .map(LegacyConference::into_bookmarks2);
+        match self.storage {
+            Some(BookmarkStorage::PrivateXml) => (self.loaded(bookmarks.collect()), Vec::new()),
+            _ => {
+                let mut events = Vec::new();
+                let mut requests = Vec::new();
+                for (room, conference) in bookmarks {
+                    if self.bookmarks.contains_key(&room) {
+                        continue;
+                    }
+                    requests.push(BookmarkManager::publish_request(&room, conference.clone()));
+                    events.extend(self.published(room, conference));
+                }
+                (events, requests)
+            }
+        }
+    }
+
+    /// Handle a bookmark added or changed, possibly from another device.
+    pub fn published(&mut self, room: BareJid, conference: Conference) -> Vec<Event> {
+        let mut events = vec![Event::BookmarkChanged(room.clone(), conference.clone())];
+        if conference.autojoin == Autojoin::True {
+            events.push(Event::JoinRoom(room.clone(), conference.clone()));
+        } else {
+            events.push(Event::LeaveRoom(room.clone()));
+        }
+        self.bookmarks.insert(room, conference);
+        events
+    }
+
+    /// Handle a bookmark removed, possibly from another device.
+    pub fn retracted(&mut self, room: BareJid) -> Vec<Event> {
+        self.bookmarks.remove(&room);
+        vec![Event::BookmarkRemoved(room.clone()), Event::LeaveRoom(room)]
+    }
+
+    /// Handle all of the bookmarks being removed at once, leaving only the
+    /// rooms which were bookmarked.
+    pub fn purged(&mut self) -> Vec<Event> {
+        let mut events = Vec::new();
+        for room in std::mem::take(&mut self.bookmarks).into_keys() {
+            events.push(Event::BookmarkRemoved(room.clone()));
+            events.push(Event::LeaveRoom(room));
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+    use std::str::FromStr;
+    use xmpp_parsers::{iq::IqType, Element};
+
+    fn conference(autojoin: bool) -> Conference {
+        Conference {
+            autojoin: if autojoin {
+                Autojoin::True
+            } else {
+                Autojoin::False
+            },
+            ..Conference::new()
+        }
+    }
+
+    fn room(name: &str) -> BareJid {
+        BareJid::from_str(&format!("{}@muc.example", name)).unwrap()
+    }
+
+    #[test]
+    fn test_loaded() {
+        let mut manager = BookmarkManager::new();
+        let events = manager.loaded(vec![
+            (room("a"), conference(true)),
+            (room("b"), conference(false)),
+            (room("c"), conference(true)),
+        ]);
+        let mut joined: Vec<_> = events
+            .iter()
+            .map(|event| match event {
+                Event::JoinRoom(room, _) => room.clone(),
+                _ => panic!(),
+            })
+            .collect();
+        joined.sort_by_key(|room| room.to_string());
+        assert_eq!(joined, [room("a"), room("c")]);
+        assert_eq!(manager.iter().count(), 3);
+
+        // Only the rooms which lost their bookmark get left.
+        let events = manager.loaded(vec![(room("a"), conference(true))]);
+        let mut left: Vec<_> = events
+            .iter()
+            .filter_map(|event| match event {
+                Event::LeaveRoom(room) => Some(room.clone()),
+                _ => None,
+            })
+            .collect();
+        left.sort_by_key(|room| room.to_string());
+        assert_eq!(left, [room("b"), room("c")]);
+        assert!(!events
+            .iter()
+            .any(|event| matches!(event, Event::LeaveAllRooms)));
+    }
+
+    #[test]
+    fn test_remote_changes() {
+        let mut manager = BookmarkManager::new();
+        let events = manager.published(room("a"), conference(true));
+        assert!(matches!(&events[0], Event::BookmarkChanged(jid, _) if *jid == room("a")));
+        assert!(matches!(&events[1], Event::JoinRoom(jid, _) if *jid == room("a")));
+        assert_eq!(manager.autojoin_rooms().count(), 1);
+
+        let events = manager.published(room("a"), conference(false));
+        assert!(matches!(&events[1], Event::LeaveRoom(jid) if *jid == room("a")));
+        assert_eq!(manager.autojoin_rooms().count(), 0);
+
+        manager.published(room("b"), conference(true));
+        let events = manager.retracted(room("a"));
+        assert!(matches!(&events[0], Event::BookmarkRemoved(jid) if *jid == room("a")));
+        assert!(manager.get(&room("a")).is_none());
+
+        let events = manager.purged();
+        assert_eq!(events.len(), 2);
+        assert!(matches!(&events[0], Event::BookmarkRemoved(jid) if *jid == room("b")));
+        assert!(matches!(&events[1], Event::LeaveRoom(jid) if *jid == room("b")));
+        assert_eq!(manager.iter().count(), 0);
+    }
+
+    #[test]
+    fn test_requests() {
+        let iq = BookmarkManager::publish_request(&room("a"), conference(true));
+        let payload = match iq.payload {
+            IqType::Set(payload) => payload,
+            _ => panic!(),
+        };
+        let expected: Element = "<pubsub xmlns='http://jabber.org/protocol/pubsub'><publish node='urn:xmpp:bookmarks:1'><item id='a@muc.example'><conference xmlns='urn:xmpp:bookmarks:1' autojoin='true'/></item></publish><publish-options><x xmlns='jabber:x:data' type='submit'><field var='FORM_TYPE' type='hidden'><value>http://jabber.org/protocol/pubsub#publish-options</value></field><field var='pubsub#persist_items'><value>true</value></field><field var='pubsub#max_items'><value>max</value></field><field var='pubsub#send_last_published_item'><value>never</value></field><field var='pubsub#access_model'><value>whitelist</value></field></x></publish-options></pubsub>".parse().unwrap();
+        assert_eq!(payload, expected);
+
+        let iq = BookmarkManager::retract_request(&room("a"));
+        let payload = match iq.payload {
+            IqType::Set(payload) => payload,
+            _ => panic!(),
+        };
+        let expected: Element = "<pubsub xmlns='http://jabber.org/protocol/pubsub'><retract node='urn:xmpp:bookmarks:1' notify='true'><item id='a@muc.example'/></retract></pubsub>".parse().unwrap();
+        assert_eq!(payload, expected);
+    }
+
+    #[test]
+    fn test_private_storage() {
+        let mut manager = BookmarkManager::new();
+        let iq = manager.discovered(None);
+        assert_eq!(manager.storage(), Some(BookmarkStorage::PrivateXml));
+        assert_eq!(iq.id, "bookmarks-private");
+
+        let storage: Element = "<storage xmlns='storage:bookmarks'><conference jid='a@muc.example' autojoin='true'/></storage>".parse().unwrap();
+        let (events, requests) = manager.private_loaded(Storage::try_from(storage).unwrap());
+        assert!(requests.is_empty());
+        assert!(matches!(&events[0], Event::JoinRoom(jid, _) if *jid == room("a")));
+        assert!(manager.migration_request().is_none());
+
+        // The whole list gets stored again on every change.
+        let iq = manager.store_request(&room("b"), &conference(false));
+        let payload = match iq.payload {
+            IqType::Set(payload) => payload,
+            _ => panic!(),
+        };
+        let query = PrivateQuery::try_from(payload).unwrap();
+        let storage = Storage::try_from(query.payload).unwrap();
+        assert_eq!(storage.conferences.len(), 2);
+
+        let iq = manager.remove_request(&room("a"));
+        let payload = match iq.payload {
+            IqType::Set(payload) => payload,
+            _ => panic!(),
+        };
+        let query = PrivateQuery::try_from(payload).unwrap();
+        let storage = Storage::try_from(query.payload).unwrap();
+        assert!(storage.conferences.is_empty());
+    }
+
+    #[test]
+    fn test_migration() {
+        let mut manager = BookmarkManager::new();
+        let info: Element = "<query xmlns='http://jabber.org/protocol/disco#info'><identity category='account' type='registered'/><feature var='http://jabber.org/protocol/disco#info'/><feature var='urn:xmpp:bookmarks:1#compat'/></query>".parse().unwrap();
+        let info = DiscoInfoResult::try_from(info).unwrap();
+        let iq = manager.discovered(Some(&info));
+        assert_eq!(manager.storage(), Some(BookmarkStorage::Pep));
+        assert_eq!(iq.id, "bookmarks");
+
+        assert!(manager.migration_request().is_none());
+        manager.loaded(vec![(room("a"), conference(true))]);
+        assert!(manager.migration_request().is_some());
+        assert!(manager.migration_request().is_none());
+
+        let storage: Element = "<storage xmlns='storage:bookmarks'><conference jid='a@muc.example' autojoin='true'/><conference jid='b@muc.example' name='B'/></storage>".parse().unwrap();
+        let (events, requests) = manager.private_loaded(Storage::try_from(storage).unwrap());
+        assert_eq!(requests.len(), 1);
+        assert!(matches!(&events[0], Event::BookmarkChanged(jid, _) if *jid == room("b")));
+        assert_eq!(manager.get(&room("b")).unwrap().name.as_deref(), Some("B"));
+    }
+}
@@ -0,0 +1,395 @@
+// Copyright (c) 2022 Emmanuel Gil Peyrot <linkmauve@linkmauve.fr>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! The sequence of requests every client sends once its resource is bound,
+//! pipelined, and tracked until all of them got answered.
+
+use crate::pagination::Progress;
+use std::convert::TryFrom;
+use xmpp_parsers::{
+    carbons,
+    iq::{Iq, IqType},
+    mam::{Fin, Query as MamQuery, QueryId},
+    ns,
+    presence::Presence,
+    roster::Roster,
+    rsm::SetQuery,
+    BareJid, Element,
+};
+
+const CARBONS_ID: &str = "bootstrap-carbons";
+const ROSTER_ID: &str = "bootstrap-roster";
+const MAM_ID: &str = "bootstrap-mam";
+
+/// One of the requests of the bootstrap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BootstrapStep {
+    /// Enabling Stream Management (XEP-0198).
+    StreamManagement,
+
+    /// Enabling Message Carbons (XEP-0280).
+    Carbons,
+
+    /// Fetching the roster, possibly only its changes since a version.
+    Roster,
+
+    /// Fetching the messages archived (XEP-0313) since the last session.
+    MamCatchup,
+}
+
+/// Which requests to send once online, and which of them are still
+/// waiting for their answer.
+///
+/// All of them get sent at once, the server processing them in order: the
+/// roster before the initial presence, as RFC 6121 recommends. Stream
+/// Management gets enabled by tokio-xmpp before going online, so that it
+/// counts every stanza, including its own keepalive pings.
+#[derive(Debug, Clone)]
+pub struct SessionBootstrap {
+    stream_management: bool,
+    carbons: bool,
+    roster: bool,
+    roster_ver: Option<String>,
+    mam_catchup: Option<Option<String>>,
+    pending: Vec<BootstrapStep>,
+    failed: Vec<BootstrapStep>,
+    started: bool,
+    mam_progress: Progress,
+    progress: Vec<(BootstrapStep, Progress)>,
+}
+
+impl Default for SessionBootstrap {
+    fn default() -> SessionBootstrap {
+        SessionBootstrap::new()
+    }
+}
+
+impl SessionBootstrap {
+    /// Only fetch the roster and send the initial presence.
+    pub fn new() -> SessionBootstrap {
+        SessionBootstrap {
+            stream_management: false,
+            carbons: false,
+            roster: true,
+            roster_ver: None,
+            mam_catchup: None,
+            pending: Vec::new(),
+            failed: Vec::new(),
+            started: false,
+            mam_progress: Progress::default(),
+            progress: Vec::new(),
+        }
+    }
+
+    /// Whether to enable Stream Management, acknowledging the stanzas
+    /// received when the server asks for it, and resuming the session
+    /// after reconnecting.
+    pub fn with_stream_management(mut self, enable: bool) -> SessionBootstrap {
+        self.stream_management = enable;
+        self
+    }
+
+    /// Whether Stream Management got asked for.
+    pub(crate) fn uses_stream_management(&self) -> bool {
+        self.stream_management
+    }
+
+    /// Whether to receive the messages sent and received by the other
+    /// devices of the account.
+    pub fn with_carbons(mut self, enable: bool) -> SessionBootstrap {
+        self.carbons = enable;
+        self
+    }
+
+    /// Whether to fetch the roster.
+    pub fn with_roster(mut self, enable: bool) -> SessionBootstrap {
+        self.roster = enable;
+        self
+    }
+
+    /// Only fetch the changes of the roster since this version (XEP-0237),
+    /// if the server supports it.
+    pub fn with_roster_ver<S: Into<String>>(mut self, ver: S) -> SessionBootstrap {
+        self.roster_ver = Some(ver.into());
+        self
+    }
+
+    /// Fetch the messages archived after this archive id, or the whole
+    /// archive if `None`.
+    ///
+    /// Only the first page gets requested, use
+    /// [`crate::pagination::Pager`] on the answer to fetch the others.
+    pub fn with_mam_catchup(mut self, after: Option<String>) -> SessionBootstrap {
+        self.mam_catchup = Some(after);
+        self
+    }
+
+    /// Start the bootstrap of a new session, returning the stanzas to send
+    /// in this order, ending with `presence`.
+    ///
+    /// `stream_management` tells whether the server enabled Stream
+    /// Management while connecting.
+    pub fn start(&mut self, presence: Presence, stream_management: bool) -> Vec<Element> {
+        self.pending.clear();
+        self.failed.clear();
+        self.started = true;
+        self.mam_progress = Progress::default();
+        self.progress.clear();
+        if self.stream_management && !stream_management {
+            self.failed.push(BootstrapStep::StreamManagement);
+        }
+        let mut elements = Vec::new();
+        if self.carbons {
+            self.pending.push(BootstrapStep::Carbons);
+            elements.push(Iq::from_set(CARBONS_ID, carbons::Enable).into());
+        }
+        if self.roster {
+            self.pending.push(BootstrapStep::Roster);
+            let roster = Roster {
+                ver: self.roster_ver.clone(),
+                items: vec![],
+            };
+            elements.push(Iq::from_get(ROSTER_ID, roster).into());
+        }
+        elements.push(presence.into());
+        if let Some(ref after) = self.mam_catchup {
+            self.pending.push(BootstrapStep::MamCatchup);
+            let query = MamQuery {
+                queryid: Some(QueryId(String::from(MAM_ID))),
+                node: None,
+                form: None,
+                set: after.clone().map(|after| SetQuery {
+                    max: None,
+                    after: Some(after),
+                    before: None,
+                    index: None,
+                }),
+            };
+            elements.push(Iq::from_set(MAM_ID, query).into());
+        }
+        elements
+    }
+
+    fn step_done(&mut self, step: BootstrapStep, success: bool) -> bool {
+        let len = self.pending.len();
+        self.pending.retain(|pending| *pending != step);
+        if self.pending.len() == len {
+            return false;
+        }
+        if !success {
+            self.failed.push(step);
+        }
+        self.pending.is_empty()
+    }
+
+    /// Handle the answer to one of our requests, returning whether it was
+    /// the last one the bootstrap waited for.
+    ///
+    /// Only the iqs from our own server, `own_jid` being our bare JID,
+    /// get considered.
+    pub fn handle_iq(&mut self, iq: &Iq, own_jid: &BareJid) -> bool {
+        if let Some(ref from) = iq.from {
+            if !crate::is_same_bare(from, own_jid) {
+                return false;
+            }
+        }
+        let success = match iq.payload {
+            IqType::Result(_) => true,
+            IqType::Error(_) => false,
+            IqType::Get(_) | IqType::Set(_) => return false,
+        };
+        let step = match iq.id.as_str() {
+            CARBONS_ID => BootstrapStep::Carbons,
+            ROSTER_ID => BootstrapStep::Roster,
+            MAM_ID => BootstrapStep::MamCatchup,
+            _ => return false,
+        };
+        if let IqType::Result(Some(ref payload)) = iq.payload {
+            self.record_progress(step, payload);
+        }
+        self.step_done(step, success)
+    }
+
+    fn record_progress(&mut self, step: BootstrapStep, payload: &Element) {
+        match step {
+            BootstrapStep::Roster => {
+                let items = payload
+                    .children()
+                    .filter(|child| child.is("item", ns::ROSTER))
+                    .count();
+                let progress = Progress {
+                    fetched: items,
+                    count: Some(items),
+                    pages: 1,
+                    bytes: String::from(payload).len(),
+                };
+                self.progress.push((step, progress));
+            }
+            BootstrapStep::MamCatchup => {
+                self.mam_progress.pages += 1;
+                if let Ok(fin) = Fin::try_from(payload.clone()) {
+                    if fin.set.count.is_some() {
+                        self.mam_progress.count = fin.set.count;
+                    }
+                }
+                self.progress.push((step, self.mam_progress));
+            }
+            BootstrapStep::StreamManagement | BootstrapStep::Carbons => (),
+        }
+    }
+
+    /// Count a message received from the archive in answer to the catch-up
+    /// query, returning whether it was one.
+    pub fn handle_mam_message(&mut self, elem: &Element) -> bool {
+        let queryid = elem
+            .get_child("result", ns::MAM)
+            .and_then(|result| result.attr("queryid"));
+        if !self.pending.contains(&BootstrapStep::MamCatchup) || queryid != Some(MAM_ID) {
+            return false;
+        }
+        self.mam_progress.fetched += 1;
+        self.mam_progress.bytes += String::from(elem).len();
+        self.progress
+            .push((BootstrapStep::MamCatchup, self.mam_progress));
+        true
+    }
+
+    /// The progress made on the roster and the catch-up since the last
+    /// call, oldest first.
+    pub fn take_progress(&mut self) -> Vec<(BootstrapStep, Progress)> {
+        std::mem::take(&mut self.progress)
+    }
+
+    /// Whether every request has been answered, in which case the session
+    /// is ready.
+    pub fn is_ready(&self) -> bool {
+        self.started && self.pending.is_empty()
+    }
+
+    /// The requests which got an error back, or which got refused.
+    pub fn failed(&self) -> &[BootstrapStep] {
+        &self.failed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use xmpp_parsers::presence::Type as PresenceType;
+
+    fn result(id: &str) -> Iq {
+        Iq {
+            from: None,
+            to: None,
+            id: String::from(id),
+            payload: IqType::Result(None),
+        }
+    }
+
+    #[test]
+    fn test_full() {
+        let own = BareJid::from_str("juliet@capulet.lit").unwrap();
+        let mut bootstrap = SessionBootstrap::new()
+            .with_stream_management(true)
+            .with_carbons(true)
+            .with_roster_ver("ver14")
+            .with_mam_catchup(Some(String::from("28482-98726-73623")));
+        assert!(!bootstrap.is_ready());
+        let elements = bootstrap.start(Presence::new(PresenceType::None), true);
+        let names: Vec<_> = elements.iter().map(|elem| elem.name()).collect();
+        assert_eq!(names, ["iq", "iq", "presence", "iq"]);
+        let roster = elements[1].get_child("query", ns::ROSTER).unwrap();
+        assert_eq!(roster.attr("ver"), Some("ver14"));
+
+        // Only our own server can answer.
+        let mut spoofed = result(ROSTER_ID);
+        spoofed.from = Some(FromStr::from_str("romeo@montague.lit").unwrap());
+        assert!(!bootstrap.handle_iq(&spoofed, &own));
+
+        assert!(!bootstrap.handle_iq(&result(ROSTER_ID), &own));
+        assert!(!bootstrap.handle_iq(&result("other"), &own));
+        let mut error = result(CARBONS_ID);
+        error.payload = IqType::Error(xmpp_parsers::stanza_error::StanzaError::new(
+            xmpp_parsers::stanza_error::ErrorType::Cancel,
+            xmpp_parsers::stanza_error::DefinedCondition::FeatureNotImplemented,
+            "en",
+            "No carbons here",
+        ));
+        assert!(!bootstrap.handle_iq(&error, &own));
+        assert!(bootstrap.handle_iq(&result(MAM_ID), &own));
+        assert!(bootstrap.is_ready());
+        assert_eq!(bootstrap.failed(), [BootstrapStep::Carbons]);
+    }
+
+    #[test]
+    fn test_progress() {
+        let own = BareJid::from_str("juliet@capulet.lit").unwrap();
+        let mut bootstrap = SessionBootstrap::new().with_mam_catchup(None);
+        bootstrap.start(Presence::new(PresenceType::None), false);
+
+        let roster: Element = "<query xmlns='jabber:iq:roster'><item jid='romeo@montague.lit'/><item jid='nurse@capulet.lit'/></query>"
+            .parse()
+            .unwrap();
+        let mut iq = result(ROSTER_ID);
+        iq.payload = IqType::Result(Some(roster));
+        assert!(!bootstrap.handle_iq(&iq, &own));
+        let progress = bootstrap.take_progress();
+        assert_eq!(progress.len(), 1);
+        assert_eq!(progress[0].0, BootstrapStep::Roster);
+        assert_eq!(progress[0].1.fetched, 2);
+        assert_eq!(progress[0].1.count, Some(2));
+
+        let message: Element = "<message xmlns='jabber:client'><result xmlns='urn:xmpp:mam:2' queryid='bootstrap-mam' id='28482-98726-73623'><forwarded xmlns='urn:xmpp:forward:0'><message xmlns='jabber:client' from='romeo@montague.lit'><body>Hi</body></message></forwarded></result></message>"
+            .parse()
+            .unwrap();
+        assert!(bootstrap.handle_mam_message(&message));
+        assert!(bootstrap.handle_mam_message(&message));
+        let other: Element = "<message xmlns='jabber:client'><body>Hi</body></message>"
+            .parse()
+            .unwrap();
+        assert!(!bootstrap.handle_mam_message(&other));
+
+        let fin: Element = "<fin xmlns='urn:xmpp:mam:2'><set xmlns='http://jabber.org/protocol/rsm'><first index='0'>a</first><last>b</last><count>5</count></set></fin>"
+            .parse()
+            .unwrap();
+        let mut iq = result(MAM_ID);
+        iq.payload = IqType::Result(Some(fin));
+        assert!(bootstrap.handle_iq(&iq, &own));
+        let progress = bootstrap.take_progress();
+        assert_eq!(progress.len(), 3);
+        let last = progress[2].1;
+        assert_eq!(last.fetched, 2);
+        assert_eq!(last.count, Some(5));
+        assert_eq!(last.pages, 1);
+        assert_eq!(last.bytes, 2 * String::from(&message).len());
+        assert_eq!(last.remaining_pages(), Some(2));
+        assert!(bootstrap.take_progress().is_empty());
+    }
+
+    #[test]
+    fn test_without_requests() {
+        let own = BareJid::from_str("juliet@capulet.lit").unwrap();
+        let mut bootstrap = SessionBootstrap::new().with_roster(false);
+        let elements = bootstrap.start(Presence::new(PresenceType::None), false);
+        assert_eq!(elements.len(), 1);
+        assert!(bootstrap.is_ready());
+        assert!(!bootstrap.handle_iq(&result(ROSTER_ID), &own));
+    }
+
+    #[test]
+    fn test_stream_management_refused() {
+        let mut bootstrap = SessionBootstrap::new()
+            .with_roster(false)
+            .with_stream_management(true);
+        bootstrap.start(Presence::new(PresenceType::None), false);
+        assert!(bootstrap.is_ready());
+        assert_eq!(bootstrap.failed(), [BootstrapStep::StreamManagement]);
+
+        bootstrap.start(Presence::new(PresenceType::None), true);
+        assert!(bootstrap.failed().is_empty());
+    }
+}
@@ -0,0 +1,327 @@
+// Copyright (c) 2022 Emmanuel Gil Peyrot <linkmauve@linkmauve.fr>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Execution of ad-hoc commands (XEP-0050) offered by another entity.
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::future::Future;
+use xmpp_parsers::{
+    commands::{Action, Command, CommandSession},
+    data_forms::DataForm,
+    iq::{Iq, IqType},
+    stanza_error::StanzaError,
+    Jid,
+};
+
+use crate::iq::{make_id, IqRequester};
+use crate::Error;
+
+/// What the user chose to do with the current stage of a command.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandReply {
+    /// The action to execute.
+    pub action: Action,
+
+    /// The form filled by the user, if any.
+    pub form: Option<DataForm>,
+}
+
+impl CommandReply {
+    /// Execute the default action, submitting `form`.
+    pub fn execute(form: Option<DataForm>) -> CommandReply {
+        CommandReply {
+            action: Action::Execute,
+            form,
+        }
+    }
+
+    /// Go to the next stage, submitting `form`.
+    pub fn next(form: DataForm) -> CommandReply {
+        CommandReply {
+            action: Action::Next,
+            form: Some(form),
+        }
+    }
+
+    /// Go back to the previous stage.
+    pub fn prev() -> CommandReply {
+        CommandReply {
+            action: Action::Prev,
+            form: None,
+        }
+    }
+
+    /// Complete the command, submitting `form`.
+    pub fn complete(form: Option<DataForm>) -> CommandReply {
+        CommandReply {
+            action: Action::Complete,
+            form,
+        }
+    }
+
+    /// Cancel the command.
+    pub fn cancel() -> CommandReply {
+        CommandReply {
+            action: Action::Cancel,
+            form: None,
+        }
+    }
+}
+
+/// Why a command couldn’t be executed to its end.
+#[derive(Debug)]
+pub enum CommandError {
+    /// The request couldn’t be sent, or its response received.
+    Transport(Error),
+
+    /// The responder returned an error.
+    Stanza(StanzaError),
+
+    /// The responder returned something else than a command, or the
+    /// user chose an action which isn’t allowed at this stage.
+    Protocol(xmpp_parsers::Error),
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandError::Transport(err) => write!(fmt, "transport error: {}", err),
+            CommandError::Stanza(err) => write!(fmt, "stanza error: {:?}", err.defined_condition),
+            CommandError::Protocol(err) => write!(fmt, "protocol error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for CommandError {}
+
+impl From<Error> for CommandError {
+    fn from(err: Error) -> CommandError {
+        CommandError::Transport(err)
+    }
+}
+
+impl From<xmpp_parsers::Error> for CommandError {
+    fn from(err: xmpp_parsers::Error) -> CommandError {
+        CommandError::Protocol(err)
+    }
+}
+
+async fn send_command<R: IqRequester>(
+    requester: &mut R,
+    to: &Jid,
+    id: String,
+    command: Command,
+) -> Result<Command, CommandError> {
+    let iq = Iq::from_set(id, command).with_to(to.clone());
+    let response = requester.request(iq).await?;
+    match response.payload {
+        IqType::Result(Some(payload)) => Ok(Command::try_from(payload)?),
+        IqType::Result(None) => {
+            Err(xmpp_parsers::Error::ParseError("Command result without a command payload.").into())
+        }
+        IqType::Error(err) => Err(CommandError::Stanza(err)),
+        IqType::Get(_) | IqType::Set(_) => {
+            Err(xmpp_parsers::Error::ParseError("Command response isn’t a result.").into())
+        }
+    }
+}
+
+/// Execute the command `node` offered by `to`, until it either completed
+/// or got canceled.
+///
+/// Every time the responder asks for more input, `present` gets called
+/// with the current state of the session, typically to display its form
+/// and notes to the user, and resolves to what to do next. The returned
+/// session holds the final status, notes and result form.
+pub async fn execute_command<R, P, F>(
+    requester: &mut R,
+    to: Jid,
+    node: &str,
+    mut present: P,
+) -> Result<CommandSession, CommandError>
+where
+    R: IqRequester,
+    P: FnMut(&CommandSession) -> F,
+    F: Future<Output = CommandReply>,
+{
+    let mut session = CommandSession::new(node);
+    let mut request = session.start();
+    loop {
+        let response = send_command(requester, &to, make_id(), request).await?;
+        session.handle_response(response)?;
+        if !session.is_executing() {
+            return Ok(session);
+        }
+        let reply = present(&session).await;
+        request = session.request(reply.action, reply.form)?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future::{FutureExt, LocalBoxFuture};
+    use std::collections::VecDeque;
+    use std::str::FromStr;
+    use xmpp_parsers::{
+        commands::{Actions, NoteType, Status},
+        data_forms::{DataFormType, Field},
+        stanza_error::{DefinedCondition, ErrorType},
+    };
+
+    /// Answers the requests with canned commands, keeping the requests.
+    struct Scripted {
+        responses: VecDeque<Result<Command, StanzaError>>,
+        requests: Vec<Command>,
+    }
+
+    impl IqRequester for Scripted {
+        fn request(&mut self, iq: Iq) -> LocalBoxFuture<'_, Result<Iq, Error>> {
+            let payload = match iq.payload {
+                IqType::Set(payload) => payload,
+                _ => panic!(),
+            };
+            self.requests.push(Command::try_from(payload).unwrap());
+            let response = match self.responses.pop_front().unwrap() {
+                Ok(command) => Iq::from_result(iq.id, Some(command)),
+                Err(err) => Iq::from_error(iq.id, err),
+            };
+            futures::future::ready(Ok(response)).boxed_local()
+        }
+    }
+
+    fn form(type_: DataFormType) -> DataForm {
+        DataForm::new(type_, "urn:example", vec![Field::text_single("a", "b")])
+    }
+
+    fn stage(actions: Actions) -> Command {
+        Command::new("config")
+            .with_sessionid("s1")
+            .with_status(Status::Executing)
+            .with_actions(actions)
+            .with_form(form(DataFormType::Form))
+    }
+
+    #[tokio::test]
+    async fn test_multi_stage() {
+        let mut requester = Scripted {
+            responses: vec![
+                Ok(stage(Actions {
+                    execute: Some(Action::Next),
+                    next: true,
+                    ..Actions::default()
+                })),
+                Ok(stage(Actions {
+                    execute: Some(Action::Complete),
+                    prev: true,
+                    complete: true,
+                    ..Actions::default()
+                })),
+                Ok(stage(Actions {
+                    execute: Some(Action::Next),
+                    next: true,
+                    ..Actions::default()
+                })),
+                Ok(stage(Actions {
+                    execute: Some(Action::Complete),
+                    prev: true,
+                    complete: true,
+                    ..Actions::default()
+                })),
+                Ok(Command::new("config")
+                    .with_sessionid("s1")
+                    .with_status(Status::Completed)
+                    .with_note(NoteType::Info, "Done.")),
+            ]
+            .into(),
+            requests: Vec::new(),
+        };
+        let mut replies: VecDeque<_> = vec![
+            CommandReply::next(form(DataFormType::Submit)),
+            CommandReply::prev(),
+            CommandReply::next(form(DataFormType::Submit)),
+            CommandReply::complete(Some(form(DataFormType::Submit))),
+        ]
+        .into();
+
+        let to = Jid::from_str("service.example.org").unwrap();
+        let session = execute_command(&mut requester, to, "config", |session| {
+            assert!(session.form().is_some());
+            futures::future::ready(replies.pop_front().unwrap())
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(session.status(), Some(&Status::Completed));
+        assert_eq!(session.notes()[0].text, "Done.");
+        let actions: Vec<_> = requester
+            .requests
+            .iter()
+            .map(|request| request.action.clone())
+            .collect();
+        assert_eq!(
+            actions,
+            [
+                Action::Execute,
+                Action::Next,
+                Action::Prev,
+                Action::Next,
+                Action::Complete
+            ]
+        );
+        assert_eq!(requester.requests[0].sessionid, None);
+        assert_eq!(requester.requests[4].sessionid.as_deref(), Some("s1"));
+    }
+
+    #[tokio::test]
+    async fn test_cancel_and_errors() {
+        let mut requester = Scripted {
+            responses: vec![
+                Ok(stage(Actions::default())),
+                Ok(Command::new("config")
+                    .with_sessionid("s1")
+                    .with_status(Status::Canceled)),
+            ]
+            .into(),
+            requests: Vec::new(),
+        };
+        let to = Jid::from_str("service.example.org").unwrap();
+        let session = execute_command(&mut requester, to.clone(), "config", |_| {
+            futures::future::ready(CommandReply::cancel())
+        })
+        .await
+        .unwrap();
+        assert_eq!(session.status(), Some(&Status::Canceled));
+
+        // Going to a stage the responder didn’t allow.
+        requester.responses.push_back(Ok(stage(Actions::default())));
+        let err = execute_command(&mut requester, to.clone(), "config", |_| {
+            futures::future::ready(CommandReply::prev())
+        })
+        .await
+        .unwrap_err();
+        assert!(matches!(err, CommandError::Protocol(_)));
+
+        requester.responses.push_back(Err(StanzaError::new(
+            ErrorType::Cancel,
+            DefinedCondition::ItemNotFound,
+            "en",
+            "No such command.",
+        )));
+        let err = execute_command(&mut requester, to, "config", |_| {
+            futures::future::ready(CommandReply::cancel())
+        })
+        .await
+        .unwrap_err();
+        match err {
+            CommandError::Stanza(err) => {
+                assert_eq!(err.defined_condition, DefinedCondition::ItemNotFound)
+            }
+            _ => panic!(),
+        }
+    }
+}
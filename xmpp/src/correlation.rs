@@ -0,0 +1,84 @@
+// Copyright (c) 2022 Emmanuel Gil Peyrot <linkmauve@linkmauve.fr>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Correlation of the log lines about a single exchange.
+//!
+//! Every stanza received gets a [`CorrelationId`], which prefixes the log
+//! lines about its handling and about the replies sent meanwhile, so that
+//! a request and its response can be found together in the logs of a busy
+//! client.
+
+use std::fmt;
+
+/// Opaque identifier of a received stanza, unique for the lifetime of a
+/// [`Correlator`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CorrelationId(u64);
+
+impl CorrelationId {
+    /// The numeric value of this identifier.
+    pub fn value(self) -> u64 {
+        self.0
+    }
+}
+
+impl fmt::Display for CorrelationId {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "stanza#{}", self.0)
+    }
+}
+
+/// Hands out the identifiers of the received stanzas, and remembers the
+/// one being handled.
+#[derive(Debug, Default)]
+pub struct Correlator {
+    next: u64,
+    current: Option<CorrelationId>,
+}
+
+impl Correlator {
+    /// Start counting from the first identifier.
+    pub fn new() -> Correlator {
+        Correlator::default()
+    }
+
+    /// Start handling a newly received stanza, returning its identifier.
+    pub fn begin(&mut self) -> CorrelationId {
+        self.next += 1;
+        let id = CorrelationId(self.next);
+        self.current = Some(id);
+        id
+    }
+
+    /// The stanza got handled.
+    pub fn end(&mut self) {
+        self.current = None;
+    }
+
+    /// The identifier of the stanza being handled, if any.
+    pub fn current(&self) -> Option<CorrelationId> {
+        self.current
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_correlator() {
+        let mut correlator = Correlator::new();
+        assert_eq!(correlator.current(), None);
+        let first = correlator.begin();
+        assert_eq!(correlator.current(), Some(first));
+        correlator.end();
+        assert_eq!(correlator.current(), None);
+        let second = correlator.begin();
+        assert_ne!(first, second);
+        assert_eq!(second.value(), 2);
+        assert_eq!(second.to_string(), "stanza#2");
+    }
+}
@@ -0,0 +1,197 @@
+// Copyright (c) 2022 Emmanuel Gil Peyrot <linkmauve@linkmauve.fr>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Deduplication of messages received more than once, for instance when a
+//! stream resumption replays unacknowledged stanzas, or when a MAM
+//! (XEP-0313) catch-up overlaps with the live messages.
+
+use std::collections::{HashSet, VecDeque};
+use std::convert::TryFrom;
+use xmpp_parsers::{
+    message::Message,
+    ns,
+    stanza_id::{OriginId, StanzaId},
+    BareJid,
+};
+
+/// How many messages are remembered by default.
+pub const DEFAULT_WINDOW: usize = 256;
+
+/// What identifies a message across its deliveries.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum MessageKey {
+    /// The origin-id (XEP-0359) set by the sender, scoped to the bare JID
+    /// it was received from.
+    Origin(BareJid, String),
+
+    /// The stanza-id (XEP-0359) stamped by this archive, either our own
+    /// account or a room.
+    Stanza(BareJid, String),
+}
+
+impl MessageKey {
+    /// All the keys of this message.
+    ///
+    /// Only the stanza-ids stamped by `own_jid` or by the sender itself (a
+    /// room) are trusted, any other entity could have forged them.
+    pub fn from_message(message: &Message, own_jid: &BareJid) -> Vec<MessageKey> {
+        let from = match message.from {
+            Some(ref from) => BareJid::from(from.clone()),
+            None => own_jid.clone(),
+        };
+        let mut keys = Vec::new();
+        for payload in message.payloads.iter() {
+            if payload.is("origin-id", ns::SID) {
+                if let Ok(origin_id) = OriginId::try_from(payload.clone()) {
+                    keys.push(MessageKey::Origin(from.clone(), origin_id.id));
+                }
+            } else if payload.is("stanza-id", ns::SID) {
+                if let Ok(stanza_id) = StanzaId::try_from(payload.clone()) {
+                    let by = BareJid::from(stanza_id.by);
+                    if &by == own_jid || by == from {
+                        keys.push(MessageKey::Stanza(by, stanza_id.id));
+                    }
+                }
+            }
+        }
+        keys
+    }
+}
+
+/// Remembers the keys of the last messages received, forgetting the
+/// oldest ones past its window.
+#[derive(Debug)]
+pub struct MessageDeduplicator {
+    window: usize,
+    order: VecDeque<MessageKey>,
+    seen: HashSet<MessageKey>,
+}
+
+impl Default for MessageDeduplicator {
+    fn default() -> MessageDeduplicator {
+        MessageDeduplicator::new(DEFAULT_WINDOW)
+    }
+}
+
+impl MessageDeduplicator {
+    /// Create a deduplicator remembering up to `window` keys.
+    pub fn new(window: usize) -> MessageDeduplicator {
+        MessageDeduplicator {
+            window,
+            order: VecDeque::with_capacity(window),
+            seen: HashSet::with_capacity(window),
+        }
+    }
+
+    /// Whether any of these keys has already been seen.
+    pub fn contains(&self, keys: &[MessageKey]) -> bool {
+        keys.iter().any(|key| self.seen.contains(key))
+    }
+
+    /// Remember this key, returns `false` if it was already known.
+    pub fn insert(&mut self, key: MessageKey) -> bool {
+        if self.window == 0 || self.seen.contains(&key) {
+            return false;
+        }
+        if self.order.len() == self.window {
+            if let Some(oldest) = self.order.pop_front() {
+                self.seen.remove(&oldest);
+            }
+        }
+        self.order.push_back(key.clone());
+        self.seen.insert(key);
+        true
+    }
+
+    /// Remember the keys of this message, returns `false` if it is a
+    /// duplicate.
+    ///
+    /// Messages without any key are never considered duplicates.
+    pub fn check(&mut self, message: &Message, own_jid: &BareJid) -> bool {
+        let keys = MessageKey::from_message(message, own_jid);
+        if self.contains(&keys) {
+            // Also remember the keys this delivery added, like the
+            // stanza-id of a message we first got without one.
+            for key in keys {
+                self.insert(key);
+            }
+            return false;
+        }
+        for key in keys {
+            self.insert(key);
+        }
+        true
+    }
+
+    /// How many keys are currently remembered.
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    /// Whether no key is remembered.
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+
+    /// Forget every key.
+    pub fn clear(&mut self) {
+        self.order.clear();
+        self.seen.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xmpp_parsers::Element;
+
+    fn message(xml: &str) -> Message {
+        let elem: Element = xml.parse().unwrap();
+        Message::try_from(elem).unwrap()
+    }
+
+    #[test]
+    fn test_keys() {
+        let own = BareJid::new("juliet", "capulet.lit");
+        let msg = message("<message xmlns='jabber:client' from='romeo@montague.lit/orchard'><origin-id xmlns='urn:xmpp:sid:0' id='abc'/><stanza-id xmlns='urn:xmpp:sid:0' id='1' by='juliet@capulet.lit'/><stanza-id xmlns='urn:xmpp:sid:0' id='2' by='evil.example'/></message>");
+        assert_eq!(
+            MessageKey::from_message(&msg, &own),
+            vec![
+                MessageKey::Origin(BareJid::new("romeo", "montague.lit"), String::from("abc")),
+                MessageKey::Stanza(own.clone(), String::from("1")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_duplicates() {
+        let own = BareJid::new("juliet", "capulet.lit");
+        let mut dedup = MessageDeduplicator::new(2);
+        let live = message("<message xmlns='jabber:client' from='romeo@montague.lit/orchard'><origin-id xmlns='urn:xmpp:sid:0' id='abc'/></message>");
+        let archived = message("<message xmlns='jabber:client' from='romeo@montague.lit/orchard'><origin-id xmlns='urn:xmpp:sid:0' id='abc'/><stanza-id xmlns='urn:xmpp:sid:0' id='1' by='juliet@capulet.lit'/></message>");
+        let resumed = message("<message xmlns='jabber:client' from='romeo@montague.lit/balcony'><stanza-id xmlns='urn:xmpp:sid:0' id='1' by='juliet@capulet.lit'/></message>");
+        assert!(dedup.check(&live, &own));
+        assert!(!dedup.check(&archived, &own));
+        assert!(!dedup.check(&resumed, &own));
+        assert_eq!(dedup.len(), 2);
+
+        let plain = message("<message xmlns='jabber:client' from='romeo@montague.lit'/>");
+        assert!(dedup.check(&plain, &own));
+        assert!(dedup.check(&plain, &own));
+    }
+
+    #[test]
+    fn test_window() {
+        let romeo = BareJid::new("romeo", "montague.lit");
+        let mut dedup = MessageDeduplicator::new(2);
+        for id in ["1", "2", "3"].iter() {
+            assert!(dedup.insert(MessageKey::Origin(romeo.clone(), id.to_string())));
+        }
+        assert_eq!(dedup.len(), 2);
+        assert!(!dedup.contains(&[MessageKey::Origin(romeo.clone(), String::from("1"))]));
+        assert!(dedup.contains(&[MessageKey::Origin(romeo, String::from("3"))]));
+    }
+}
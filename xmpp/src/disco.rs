@@ -0,0 +1,416 @@
+// Copyright (c) 2022 Emmanuel Gil Peyrot <linkmauve@linkmauve.fr>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Service discovery (XEP-0030): walking the items of another entity,
+//! and answering the queries about ourselves.
+
+use futures::future::{FutureExt, LocalBoxFuture};
+use std::collections::HashSet;
+use xmpp_parsers::{
+    caps::{compute_disco, hash_caps, Caps},
+    data_forms::DataForm,
+    disco::{
+        DiscoInfoQuery, DiscoInfoResult, DiscoItemsQuery, DiscoItemsResult, Feature, Identity, Item,
+    },
+    hashes::Algo,
+    iq::Iq,
+    ns,
+    stanza_error::{DefinedCondition, ErrorType, StanzaError},
+    Jid,
+};
+
+use crate::iq::{make_id, request_payload, IqError, IqRequester};
+use crate::pagination::{fetch_all, Pager};
+
+/// Fetch all of the items of `node` on `jid`, `max` at a time.
+///
+/// Big lists get paged with Result Set Management (XEP-0059), while
+/// entities not supporting it return all of their items at once.
+pub async fn fetch_items<R: IqRequester>(
+    requester: &mut R,
+    jid: &Jid,
+    node: Option<&str>,
+    max: Option<usize>,
+) -> Result<Vec<Item>, IqError> {
+    fetch_all(requester, Pager::forward(max), |requester, rsm| {
+        let query = DiscoItemsQuery::new(node.map(String::from)).with_rsm(rsm);
+        let iq = Iq::from_get(make_id(), query).with_to(jid.clone());
+        async move {
+            let result: DiscoItemsResult = request_payload(requester, iq).await?;
+            Ok((result.items, result.rsm.unwrap_or_default()))
+        }
+        .boxed_local()
+    })
+    .await
+}
+
+/// An item found while walking a service, along with its own items.
+#[derive(Debug, Clone)]
+pub struct ItemTree {
+    /// The item itself.
+    pub item: Item,
+
+    /// The items of this item, empty if it has none or refused to list
+    /// them.
+    pub children: Vec<ItemTree>,
+}
+
+/// Recursively fetch the items of `node` on `jid`, then the items of
+/// each of them, down to `depth` levels, `max` at a time.
+///
+/// Items which return an error get walked as leaves, and items already
+/// seen elsewhere in the tree only appear once, to avoid looping forever
+/// on services listing their parent.
+pub async fn walk_items<R: IqRequester>(
+    requester: &mut R,
+    jid: &Jid,
+    node: Option<&str>,
+    max: Option<usize>,
+    depth: usize,
+) -> Result<Vec<ItemTree>, IqError> {
+    let mut seen = HashSet::new();
+    seen.insert((jid.to_string(), node.map(String::from)));
+    walk(
+        requester,
+        jid.clone(),
+        node.map(String::from),
+        max,
+        depth,
+        &mut seen,
+    )
+    .await
+}
+
+fn walk<'a, R: IqRequester>(
+    requester: &'a mut R,
+    jid: Jid,
+    node: Option<String>,
+    max: Option<usize>,
+    depth: usize,
+    seen: &'a mut HashSet<(String, Option<String>)>,
+) -> LocalBoxFuture<'a, Result<Vec<ItemTree>, IqError>> {
+    async move {
+        let items = fetch_items(requester, &jid, node.as_deref(), max).await?;
+        let mut trees = Vec::with_capacity(items.len());
+        for item in items {
+            if !seen.insert((item.jid.to_string(), item.node.clone())) {
+                continue;
+            }
+            let children = if depth > 1 {
+                let walked = walk(
+                    requester,
+                    item.jid.clone(),
+                    item.node.clone(),
+                    max,
+                    depth - 1,
+                    seen,
+                )
+                .await;
+                match walked {
+                    Ok(children) => children,
+                    Err(IqError::Stanza(err)) => {
+                        debug!("Not walking {}: {:?}", item.jid, err.defined_condition);
+                        Vec::new()
+                    }
+                    Err(err) => return Err(err),
+                }
+            } else {
+                Vec::new()
+            };
+            trees.push(ItemTree { item, children });
+        }
+        Ok(trees)
+    }
+    .boxed_local()
+}
+
+/// The identities and features we support, which every subsystem
+/// registers into, used to answer disco#info queries and to compute our
+/// entity capabilities (XEP-0115).
+#[derive(Debug, Clone)]
+pub struct DiscoResponder {
+    identities: Vec<Identity>,
+    features: Vec<Feature>,
+    extensions: Vec<DataForm>,
+    caps_node: Option<String>,
+}
+
+impl Default for DiscoResponder {
+    fn default() -> DiscoResponder {
+        DiscoResponder::new()
+    }
+}
+
+impl DiscoResponder {
+    /// Create a responder supporting only disco#info itself.
+    pub fn new() -> DiscoResponder {
+        DiscoResponder {
+            identities: Vec::new(),
+            features: vec![Feature::new(ns::DISCO_INFO)],
+            extensions: Vec::new(),
+            caps_node: None,
+        }
+    }
+
+    /// Advertise `identity`, unless already advertised.
+    pub fn register_identity(&mut self, identity: Identity) {
+        if !self.identities.contains(&identity) {
+            self.identities.push(identity);
+        }
+    }
+
+    /// Advertise the support of `var`, unless already advertised.
+    pub fn register_feature<V: Into<String>>(&mut self, var: V) {
+        let feature = Feature::new(var);
+        if !self.features.contains(&feature) {
+            self.features.push(feature);
+        }
+    }
+
+    /// Stop advertising the support of `var`.
+    pub fn unregister_feature(&mut self, var: &str) {
+        self.features.retain(|feature| feature.var != var);
+    }
+
+    /// Whether the support of `var` is advertised.
+    pub fn has_feature(&self, var: &str) -> bool {
+        self.features.iter().any(|feature| feature.var == var)
+    }
+
+    /// Advertise the extended information (XEP-0128) in `form`, replacing
+    /// any previous form of the same type.
+    pub fn register_extension(&mut self, form: DataForm) {
+        self.extensions
+            .retain(|extension| extension.form_type != form.form_type);
+        self.extensions.push(form);
+    }
+
+    /// Also answer queries on the capabilities node, usually the website
+    /// of the client.
+    pub fn set_caps_node<N: Into<String>>(&mut self, node: N) {
+        self.caps_node = Some(node.into());
+    }
+
+    /// Everything we advertise, as answered on `node`.
+    pub fn info(&self, node: Option<String>) -> DiscoInfoResult {
+        DiscoInfoResult {
+            node,
+            identities: self.identities.clone(),
+            features: self.features.clone(),
+            extensions: self.extensions.clone(),
+        }
+    }
+
+    /// Our current entity capabilities, to include in our presences.
+    pub fn caps(&self) -> Caps {
+        let data = compute_disco(&self.info(None));
+        let hash = hash_caps(&data, Algo::Sha_1).unwrap();
+        let node = self.caps_node.clone().unwrap_or_default();
+        Caps::new(node, hash)
+    }
+
+    /// Answer `query`, either on ourselves or on our current capabilities
+    /// node, other nodes being unknown.
+    pub fn respond(&self, query: DiscoInfoQuery) -> Result<DiscoInfoResult, StanzaError> {
+        let node = match query.node {
+            None => return Ok(self.info(None)),
+            Some(node) => node,
+        };
+        let caps = self.caps();
+        if self.caps_node.is_some() && node == format!("{}#{}", caps.node, caps.hash.to_base64()) {
+            return Ok(self.info(Some(node)));
+        }
+        Err(StanzaError::new(
+            ErrorType::Cancel,
+            DefinedCondition::ItemNotFound,
+            "en",
+            "Unknown disco#info node.",
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+    use std::str::FromStr;
+    use xmpp_parsers::{
+        iq::IqType,
+        rsm::SetResult,
+        stanza_error::{DefinedCondition, ErrorType, StanzaError},
+    };
+
+    /// A directory of `count` items, paging them if asked to.
+    struct Directory {
+        count: usize,
+        supports_rsm: bool,
+        requests: usize,
+    }
+
+    impl IqRequester for Directory {
+        fn request(&mut self, iq: Iq) -> LocalBoxFuture<'_, Result<Iq, crate::Error>> {
+            self.requests += 1;
+            let query = match iq.payload {
+                IqType::Get(payload) => DiscoItemsQuery::try_from(payload).unwrap(),
+                _ => panic!(),
+            };
+            let jid = |i: usize| Jid::from_str(&format!("room{}@muc.example", i)).unwrap();
+            let rsm = query.rsm.filter(|_| self.supports_rsm);
+            let start = match rsm.as_ref().and_then(|rsm| rsm.after.as_ref()) {
+                Some(after) => {
+                    (0..self.count)
+                        .position(|i| jid(i).to_string() == *after)
+                        .unwrap()
+                        + 1
+                }
+                None => 0,
+            };
+            let end = match rsm.as_ref().and_then(|rsm| rsm.max) {
+                Some(max) => (start + max).min(self.count),
+                None => self.count,
+            };
+            let items: Vec<_> = (start..end)
+                .map(|i| Item {
+                    jid: jid(i),
+                    node: None,
+                    name: None,
+                })
+                .collect();
+            let result = DiscoItemsResult {
+                node: query.node,
+                rsm: rsm.map(|_| SetResult {
+                    first: items.first().map(|item| item.jid.to_string()),
+                    first_index: Some(start),
+                    last: items.last().map(|item| item.jid.to_string()),
+                    count: Some(self.count),
+                }),
+                items,
+            };
+            futures::future::ready(Ok(Iq::from_result(iq.id, Some(result)))).boxed_local()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_paged() {
+        let mut directory = Directory {
+            count: 25,
+            supports_rsm: true,
+            requests: 0,
+        };
+        let muc = Jid::from_str("muc.example").unwrap();
+        let items = fetch_items(&mut directory, &muc, None, Some(10))
+            .await
+            .unwrap();
+        assert_eq!(items.len(), 25);
+        assert_eq!(items[24].jid, Jid::from_str("room24@muc.example").unwrap());
+        assert_eq!(directory.requests, 3);
+    }
+
+    #[tokio::test]
+    async fn test_unpaged() {
+        let mut directory = Directory {
+            count: 25,
+            supports_rsm: false,
+            requests: 0,
+        };
+        let muc = Jid::from_str("muc.example").unwrap();
+        let items = fetch_items(&mut directory, &muc, None, Some(10))
+            .await
+            .unwrap();
+        assert_eq!(items.len(), 25);
+        assert_eq!(directory.requests, 1);
+    }
+
+    /// A pubsub service whose root lists two nodes along with itself, the
+    /// first one having a leaf and the second one refusing to list it.
+    struct Service;
+
+    impl IqRequester for Service {
+        fn request(&mut self, iq: Iq) -> LocalBoxFuture<'_, Result<Iq, crate::Error>> {
+            let query = match iq.payload {
+                IqType::Get(payload) => DiscoItemsQuery::try_from(payload).unwrap(),
+                _ => panic!(),
+            };
+            let service = Jid::from_str("pubsub.example").unwrap();
+            let item =
+                |node: Option<&str>| Item::new(service.clone(), node.map(String::from), None);
+            let items = match query.node.as_deref() {
+                None => vec![item(Some("a")), item(Some("b")), item(None)],
+                Some("a") => vec![item(Some("a/1"))],
+                Some("a/1") => vec![],
+                Some("b") => {
+                    let error = StanzaError::new(
+                        ErrorType::Cancel,
+                        DefinedCondition::ItemNotFound,
+                        "en",
+                        "",
+                    );
+                    return futures::future::ready(Ok(Iq::from_error(iq.id, error))).boxed_local();
+                }
+                Some(node) => panic!("Unexpected node {}", node),
+            };
+            let result = DiscoItemsResult::new(query.node, items);
+            futures::future::ready(Ok(Iq::from_result(iq.id, Some(result)))).boxed_local()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_walk() {
+        let service = Jid::from_str("pubsub.example").unwrap();
+        let tree = walk_items(&mut Service, &service, None, None, 3)
+            .await
+            .unwrap();
+        assert_eq!(tree.len(), 2);
+        assert_eq!(tree[0].item.node.as_deref(), Some("a"));
+        assert_eq!(tree[0].children.len(), 1);
+        assert_eq!(tree[0].children[0].item.node.as_deref(), Some("a/1"));
+        assert_eq!(tree[1].item.node.as_deref(), Some("b"));
+        assert!(tree[1].children.is_empty());
+
+        let tree = walk_items(&mut Service, &service, None, None, 1)
+            .await
+            .unwrap();
+        assert!(tree[0].children.is_empty());
+    }
+
+    #[test]
+    fn test_responder() {
+        let mut responder = DiscoResponder::new();
+        responder.register_identity(Identity::new("client", "bot", "en", "xmpp-rs"));
+        responder.register_feature(ns::PING);
+        responder.register_feature(ns::PING);
+        responder.register_feature(ns::RECEIPTS);
+        responder.unregister_feature(ns::RECEIPTS);
+        responder.set_caps_node("https://xmpp.rs");
+        assert!(responder.has_feature(ns::DISCO_INFO));
+        assert!(!responder.has_feature(ns::RECEIPTS));
+
+        let info = responder.respond(DiscoInfoQuery { node: None }).unwrap();
+        assert_eq!(info.identities.len(), 1);
+        assert_eq!(info.features.len(), 2);
+
+        let caps = responder.caps();
+        assert_eq!(caps.node, "https://xmpp.rs");
+        let node = format!("{}#{}", caps.node, caps.hash.to_base64());
+        let info = responder
+            .respond(DiscoInfoQuery {
+                node: Some(node.clone()),
+            })
+            .unwrap();
+        assert_eq!(info.node, Some(node));
+
+        let err = responder
+            .respond(DiscoInfoQuery {
+                node: Some(String::from("https://xmpp.rs#old")),
+            })
+            .unwrap_err();
+        assert_eq!(err.defined_condition, DefinedCondition::ItemNotFound);
+
+        // Changing the features changes the capabilities.
+        responder.register_feature(ns::RECEIPTS);
+        assert_ne!(responder.caps().hash, caps.hash);
+    }
+}
@@ -0,0 +1,187 @@
+// Copyright (c) 2022 Emmanuel Gil Peyrot <linkmauve@linkmauve.fr>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Letting applications parse their own payloads, for instance private
+//! or vendor extensions, into typed events.
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use xmpp_parsers::{Element, Error};
+
+/// A payload parsed by an extension parser, to be downcast back to its
+/// type.
+pub struct ExtensionPayload {
+    name: String,
+    ns: String,
+    payload: Box<dyn Payload>,
+}
+
+trait Payload: fmt::Debug {
+    fn as_any(&self) -> &dyn Any;
+    fn into_any(self: Box<Self>) -> Box<dyn Any>;
+}
+
+impl<T: Any + fmt::Debug> Payload for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+}
+
+impl ExtensionPayload {
+    /// The name of the element this payload got parsed from.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The namespace of the element this payload got parsed from.
+    pub fn ns(&self) -> &str {
+        &self.ns
+    }
+
+    /// Whether this payload is a `T`.
+    pub fn is<T: Any>(&self) -> bool {
+        (*self.payload).as_any().is::<T>()
+    }
+
+    /// This payload, if it is a `T`.
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        (*self.payload).as_any().downcast_ref()
+    }
+
+    /// Take this payload if it is a `T`, or give it back otherwise.
+    pub fn downcast<T: Any>(self) -> Result<T, ExtensionPayload> {
+        if !self.is::<T>() {
+            return Err(self);
+        }
+        Ok(*self.payload.into_any().downcast().unwrap())
+    }
+}
+
+impl fmt::Debug for ExtensionPayload {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("ExtensionPayload")
+            .field("name", &self.name)
+            .field("ns", &self.ns)
+            .field("payload", &self.payload)
+            .finish()
+    }
+}
+
+type Parser = Box<dyn Fn(Element) -> Result<Box<dyn Payload>, Error>>;
+
+/// Parsers of the payloads unknown to this crate, keyed by the namespace
+/// and name of their element.
+#[derive(Default)]
+pub struct ExtensionRegistry {
+    parsers: HashMap<String, HashMap<String, Parser>>,
+}
+
+impl ExtensionRegistry {
+    /// Create a registry without any parser.
+    pub fn new() -> ExtensionRegistry {
+        ExtensionRegistry::default()
+    }
+
+    /// Parse `<name xmlns='ns'/>` elements into a `T`, replacing any
+    /// previous parser of these elements.
+    pub fn register<T>(&mut self, name: &str, ns: &str)
+    where
+        T: TryFrom<Element, Error = Error> + fmt::Debug + 'static,
+    {
+        self.register_with(name, ns, T::try_from);
+    }
+
+    /// Parse `<name xmlns='ns'/>` elements through `parser`, replacing any
+    /// previous parser of these elements.
+    pub fn register_with<T, F>(&mut self, name: &str, ns: &str, parser: F)
+    where
+        T: fmt::Debug + 'static,
+        F: Fn(Element) -> Result<T, Error> + 'static,
+    {
+        self.parsers.entry(String::from(ns)).or_default().insert(
+            String::from(name),
+            Box::new(move |elem| Ok(Box::new(parser(elem)?) as Box<dyn Payload>)),
+        );
+    }
+
+    fn parser(&self, elem: &Element) -> Option<&Parser> {
+        self.parsers.get(elem.ns())?.get(elem.name())
+    }
+
+    /// Whether a parser got registered for this element.
+    pub fn handles(&self, elem: &Element) -> bool {
+        self.parser(elem).is_some()
+    }
+
+    /// Parse `elem` with its registered parser, or `None` if there is no
+    /// such parser.
+    pub fn parse(&self, elem: Element) -> Option<Result<ExtensionPayload, Error>> {
+        let parser = self.parser(&elem)?;
+        let name = String::from(elem.name());
+        let ns = String::from(elem.ns());
+        Some(parser(elem).map(|payload| ExtensionPayload { name, ns, payload }))
+    }
+}
+
+impl fmt::Debug for ExtensionRegistry {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_set().entries(self.parsers.keys()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A made-up vendor payload.
+    #[derive(Debug, PartialEq)]
+    struct Score(u32);
+
+    impl TryFrom<Element> for Score {
+        type Error = Error;
+
+        fn try_from(elem: Element) -> Result<Score, Error> {
+            elem.text()
+                .parse()
+                .map(Score)
+                .map_err(|_| Error::ParseError("Invalid score."))
+        }
+    }
+
+    #[test]
+    fn test_registry() {
+        let mut registry = ExtensionRegistry::new();
+        registry.register::<Score>("score", "urn:example:game");
+
+        let elem: Element = "<score xmlns='urn:example:game'>42</score>"
+            .parse()
+            .unwrap();
+        assert!(registry.handles(&elem));
+        let payload = registry.parse(elem).unwrap().unwrap();
+        assert_eq!(payload.name(), "score");
+        assert!(payload.is::<Score>());
+        assert!(!payload.is::<String>());
+        let payload = payload.downcast::<String>().unwrap_err();
+        assert_eq!(payload.downcast::<Score>().unwrap(), Score(42));
+
+        let elem: Element = "<score xmlns='urn:example:game'>many</score>"
+            .parse()
+            .unwrap();
+        assert!(registry.parse(elem).unwrap().is_err());
+
+        let elem: Element = "<score xmlns='urn:example:other'>42</score>"
+            .parse()
+            .unwrap();
+        assert!(!registry.handles(&elem));
+        assert!(registry.parse(elem).is_none());
+    }
+}
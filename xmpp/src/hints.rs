@@ -0,0 +1,128 @@
+// Copyright (c) 2022 Emmanuel Gil Peyrot <linkmauve@linkmauve.fr>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Message Processing Hints (XEP-0334) attached to the messages we send,
+//! so that servers don’t archive or copy to every device the notifications
+//! only meaningful right now.
+
+use xmpp_parsers::{
+    hints::{NoCopy, NoStore, Store},
+    message::Message,
+    ns,
+};
+
+/// The namespaces of the payloads only meaningful while the conversation
+/// happens, such as typing notifications.
+const EPHEMERAL_NAMESPACES: &[&str] = &[ns::CHATSTATES];
+
+/// Which hints to attach to a message before sending it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HintPolicy {
+    /// Mark the message as ephemeral if it only carries ephemeral
+    /// payloads, leave it as is otherwise.
+    #[default]
+    Auto,
+
+    /// Never add any hint.
+    None,
+
+    /// Ask not to store nor copy this message.
+    Ephemeral,
+
+    /// Ask to store this message, even if it wouldn’t otherwise be.
+    Store,
+}
+
+/// Whether this message only carries payloads meaningful right now, such
+/// as a chat state without any body.
+pub fn is_ephemeral(message: &Message) -> bool {
+    message.bodies.is_empty()
+        && message.subjects.is_empty()
+        && !message.payloads.is_empty()
+        && message.payloads.iter().all(|payload| {
+            payload.is("no-store", ns::HINTS)
+                || payload.is("no-copy", ns::HINTS)
+                || EPHEMERAL_NAMESPACES.contains(&payload.ns())
+        })
+}
+
+/// Attach the hints `policy` asks for to `message`, unless it already
+/// carries some.
+pub fn apply_hints(message: &mut Message, policy: HintPolicy) {
+    if message
+        .payloads
+        .iter()
+        .any(|payload| payload.has_ns(ns::HINTS))
+    {
+        return;
+    }
+    let ephemeral = match policy {
+        HintPolicy::Auto => is_ephemeral(message),
+        HintPolicy::Ephemeral => true,
+        HintPolicy::Store => {
+            message.payloads.push(Store.into());
+            return;
+        }
+        HintPolicy::None => false,
+    };
+    if ephemeral {
+        message.payloads.push(NoStore.into());
+        message.payloads.push(NoCopy.into());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xmpp_parsers::{chatstates::ChatState, message::Body, Jid};
+
+    fn message() -> Message {
+        Message::new(Some(Jid::Bare("juliet@capulet.lit".parse().unwrap())))
+    }
+
+    fn chat_state(state: ChatState) -> Message {
+        let mut message = message();
+        message.payloads.push(state.into());
+        message
+    }
+
+    #[test]
+    fn test_auto() {
+        let mut typing = chat_state(ChatState::Composing);
+        assert!(is_ephemeral(&typing));
+        apply_hints(&mut typing, HintPolicy::Auto);
+        assert!(typing.has_hint("no-store"));
+        assert!(typing.has_hint("no-copy"));
+        assert_eq!(typing.payloads.len(), 3);
+
+        // Applying them again doesn’t duplicate them.
+        apply_hints(&mut typing, HintPolicy::Auto);
+        assert_eq!(typing.payloads.len(), 3);
+
+        let mut text = chat_state(ChatState::Active);
+        text.bodies.insert(String::new(), Body(String::from("Hi!")));
+        assert!(!is_ephemeral(&text));
+        apply_hints(&mut text, HintPolicy::Auto);
+        assert_eq!(text.payloads.len(), 1);
+    }
+
+    #[test]
+    fn test_override() {
+        let mut typing = chat_state(ChatState::Composing);
+        apply_hints(&mut typing, HintPolicy::None);
+        assert_eq!(typing.payloads.len(), 1);
+
+        let mut important = chat_state(ChatState::Gone);
+        apply_hints(&mut important, HintPolicy::Store);
+        assert!(important.has_hint("store"));
+        assert!(!important.has_hint("no-store"));
+
+        let mut text = message();
+        text.bodies.insert(String::new(), Body(String::from("Hi!")));
+        apply_hints(&mut text, HintPolicy::Ephemeral);
+        assert!(text.has_hint("no-store"));
+    }
+}
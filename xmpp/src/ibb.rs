@@ -0,0 +1,368 @@
+// Copyright (c) 2022 Emmanuel Gil Peyrot <linkmauve@linkmauve.fr>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Transfer of binary data over In-Band Bytestreams (XEP-0047).
+
+use futures::stream::{Stream, StreamExt};
+use std::convert::TryFrom;
+use std::fmt;
+use xmpp_parsers::{
+    ibb::{Close, Data, Open, Stanza, StreamId},
+    iq::{Iq, IqType},
+    message::Message,
+    ns, Element, Jid,
+};
+
+use crate::iq::{make_id, IqError, IqRequester};
+
+/// Why an In-Band Bytestream got interrupted.
+#[derive(Debug)]
+pub enum IbbError {
+    /// A request couldn’t be sent, or got an error back.
+    Iq(Box<IqError>),
+
+    /// A chunk arrived out of order, or got lost.
+    UnexpectedSeq {
+        /// The sequence number of the next chunk.
+        expected: u16,
+
+        /// The sequence number received instead.
+        received: u16,
+    },
+
+    /// A chunk or a close belonged to another stream.
+    WrongStream(StreamId),
+
+    /// A chunk was bigger than the block size of the stream.
+    ChunkTooBig(usize),
+
+    /// The peer went away before closing the stream.
+    Interrupted,
+}
+
+impl fmt::Display for IbbError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IbbError::Iq(err) => write!(fmt, "iq error: {}", err),
+            IbbError::UnexpectedSeq { expected, received } => {
+                write!(fmt, "unexpected chunk {}, expected {}", received, expected)
+            }
+            IbbError::WrongStream(sid) => write!(fmt, "chunk for another stream: {}", sid.0),
+            IbbError::ChunkTooBig(size) => write!(fmt, "chunk of {} bytes is too big", size),
+            IbbError::Interrupted => write!(fmt, "stream interrupted"),
+        }
+    }
+}
+
+impl std::error::Error for IbbError {}
+
+impl From<IqError> for IbbError {
+    fn from(err: IqError) -> IbbError {
+        IbbError::Iq(Box::new(err))
+    }
+}
+
+async fn request_empty<R: IqRequester>(requester: &mut R, iq: Iq) -> Result<(), IbbError> {
+    let response = requester.request(iq).await.map_err(IqError::from)?;
+    match response.payload {
+        IqType::Result(_) => Ok(()),
+        IqType::Error(err) => Err(IqError::Stanza(err).into()),
+        IqType::Get(_) | IqType::Set(_) => {
+            Err(IqError::Parse(xmpp_parsers::Error::ParseError("Response isn’t a result.")).into())
+        }
+    }
+}
+
+/// The sending side of an In-Band Bytestream, splitting the data into
+/// chunks of at most the block size, sent in iqs.
+#[derive(Debug)]
+pub struct IbbTransfer {
+    to: Jid,
+    sid: StreamId,
+    block_size: u16,
+    seq: u16,
+}
+
+impl IbbTransfer {
+    /// Prepare a stream to `to`, with chunks of at most `block_size` bytes.
+    pub fn new(to: Jid, sid: StreamId, block_size: u16) -> IbbTransfer {
+        IbbTransfer {
+            to,
+            sid,
+            block_size,
+            seq: 0,
+        }
+    }
+
+    /// The request opening this stream.
+    pub fn open_request(&self) -> Open {
+        Open::new(self.sid.clone(), self.block_size)
+    }
+
+    /// Split `data` into the next chunks of this stream.
+    ///
+    /// This is useful to send them in messages, as the peer can’t
+    /// acknowledge them.
+    pub fn chunks(&mut self, data: &[u8]) -> Vec<Data> {
+        data.chunks(usize::from(self.block_size.max(1)))
+            .map(|chunk| {
+                let data = Data::new(self.sid.clone(), self.seq, chunk.to_vec());
+                self.seq = self.seq.wrapping_add(1);
+                data
+            })
+            .collect()
+    }
+
+    /// The next chunks of this stream, each in a message.
+    pub fn messages(&mut self, data: &[u8]) -> Vec<Message> {
+        let to = self.to.clone();
+        self.chunks(data)
+            .into_iter()
+            .map(|chunk| {
+                let mut message = Message::new(Some(to.clone()));
+                message.payloads.push(chunk.into());
+                message
+            })
+            .collect()
+    }
+
+    /// Open this stream, and wait for the peer to accept it.
+    pub async fn open<R: IqRequester>(&mut self, requester: &mut R) -> Result<(), IbbError> {
+        let iq = Iq::from_set(make_id(), self.open_request()).with_to(self.to.clone());
+        request_empty(requester, iq).await
+    }
+
+    /// Send `data`, waiting for each chunk to be acknowledged before
+    /// sending the next one.
+    pub async fn send<R: IqRequester>(
+        &mut self,
+        requester: &mut R,
+        data: &[u8],
+    ) -> Result<(), IbbError> {
+        for chunk in self.chunks(data) {
+            let iq = Iq::from_set(make_id(), chunk).with_to(self.to.clone());
+            request_empty(requester, iq).await?;
+        }
+        Ok(())
+    }
+
+    /// Close this stream.
+    pub async fn close<R: IqRequester>(self, requester: &mut R) -> Result<(), IbbError> {
+        let iq = Iq::from_set(make_id(), Close::new(self.sid.clone())).with_to(self.to);
+        request_empty(requester, iq).await
+    }
+}
+
+/// Open a stream to `to`, send all of `data` over it, then close it.
+pub async fn send_ibb<R: IqRequester>(
+    requester: &mut R,
+    to: Jid,
+    sid: StreamId,
+    block_size: u16,
+    data: &[u8],
+) -> Result<(), IbbError> {
+    let mut transfer = IbbTransfer::new(to, sid, block_size);
+    transfer.open(requester).await?;
+    transfer.send(requester, data).await?;
+    transfer.close(requester).await
+}
+
+/// The receiving side of an In-Band Bytestream, reassembling its chunks.
+#[derive(Debug)]
+pub struct IbbReceiver {
+    sid: StreamId,
+    block_size: u16,
+    stanza: Stanza,
+    seq: u16,
+    data: Vec<u8>,
+    closed: bool,
+}
+
+impl IbbReceiver {
+    /// Accept the stream requested by `open`.
+    pub fn new(open: &Open) -> IbbReceiver {
+        IbbReceiver {
+            sid: open.sid.clone(),
+            block_size: open.block_size,
+            stanza: open.stanza.clone(),
+            seq: 0,
+            data: Vec::new(),
+            closed: false,
+        }
+    }
+
+    /// Which stanza type the chunks are exchanged with.
+    pub fn stanza(&self) -> &Stanza {
+        &self.stanza
+    }
+
+    /// Append this chunk, which has to be the next one of this stream.
+    pub fn handle_data(&mut self, data: Data) -> Result<(), IbbError> {
+        if data.sid != self.sid {
+            return Err(IbbError::WrongStream(data.sid));
+        }
+        if data.seq != self.seq {
+            return Err(IbbError::UnexpectedSeq {
+                expected: self.seq,
+                received: data.seq,
+            });
+        }
+        if data.data.len() > usize::from(self.block_size) {
+            return Err(IbbError::ChunkTooBig(data.data.len()));
+        }
+        self.seq = self.seq.wrapping_add(1);
+        self.data.extend_from_slice(&data.data);
+        Ok(())
+    }
+
+    /// Close this stream.
+    pub fn handle_close(&mut self, close: Close) -> Result<(), IbbError> {
+        if close.sid != self.sid {
+            return Err(IbbError::WrongStream(close.sid));
+        }
+        self.closed = true;
+        Ok(())
+    }
+
+    /// Handle an `<data/>` or `<close/>` payload, ignoring anything else.
+    pub fn handle_payload(&mut self, payload: Element) -> Result<(), IbbError> {
+        if payload.is("data", ns::IBB) {
+            let data = Data::try_from(payload).map_err(IqError::Parse)?;
+            self.handle_data(data)
+        } else if payload.is("close", ns::IBB) {
+            let close = Close::try_from(payload).map_err(IqError::Parse)?;
+            self.handle_close(close)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Whether the sender closed this stream.
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    /// The data received so far.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Take the data received.
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+/// Reassemble the stream requested by `open` from its incoming `<data/>`
+/// and `<close/>` payloads, resolving to its data once closed.
+pub async fn receive_ibb<S>(open: &Open, mut payloads: S) -> Result<Vec<u8>, IbbError>
+where
+    S: Stream<Item = Element> + Unpin,
+{
+    let mut receiver = IbbReceiver::new(open);
+    while let Some(payload) = payloads.next().await {
+        receiver.handle_payload(payload)?;
+        if receiver.is_closed() {
+            return Ok(receiver.into_data());
+        }
+    }
+    Err(IbbError::Interrupted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+    use futures::future::{FutureExt, LocalBoxFuture};
+    use std::str::FromStr;
+
+    /// Acknowledges every request, keeping them.
+    struct Acknowledging {
+        requests: Vec<Element>,
+    }
+
+    impl IqRequester for Acknowledging {
+        fn request(&mut self, iq: Iq) -> LocalBoxFuture<'_, Result<Iq, Error>> {
+            match iq.payload {
+                IqType::Set(payload) => self.requests.push(payload),
+                _ => panic!(),
+            }
+            let response = Iq {
+                from: None,
+                to: iq.to,
+                id: iq.id,
+                payload: IqType::Result(None),
+            };
+            futures::future::ready(Ok(response)).boxed_local()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_round_trip() {
+        let mut requester = Acknowledging {
+            requests: Vec::new(),
+        };
+        let to = Jid::from_str("juliet@capulet.lit/balcony").unwrap();
+        let sid = StreamId(String::from("i781hf64"));
+        let data: Vec<u8> = (0..10).collect();
+        send_ibb(&mut requester, to, sid, 4, &data).await.unwrap();
+
+        // Open, three chunks, and close.
+        assert_eq!(requester.requests.len(), 5);
+        let open = Open::try_from(requester.requests.remove(0)).unwrap();
+        assert_eq!(open.block_size, 4);
+        let payloads = futures::stream::iter(requester.requests);
+        let received = receive_ibb(&open, payloads).await.unwrap();
+        assert_eq!(received, data);
+    }
+
+    #[test]
+    fn test_sequence() {
+        let sid = StreamId(String::from("i781hf64"));
+        let open = Open::new(sid.clone(), 4);
+        let mut receiver = IbbReceiver::new(&open);
+        receiver
+            .handle_data(Data::new(sid.clone(), 0, vec![1, 2]))
+            .unwrap();
+        match receiver.handle_data(Data::new(sid.clone(), 2, vec![3])) {
+            Err(IbbError::UnexpectedSeq {
+                expected: 1,
+                received: 2,
+            }) => (),
+            err => panic!("Unexpected result: {:?}", err),
+        }
+        match receiver.handle_data(Data::new(sid.clone(), 1, vec![0; 5])) {
+            Err(IbbError::ChunkTooBig(5)) => (),
+            err => panic!("Unexpected result: {:?}", err),
+        }
+        let other = StreamId(String::from("other"));
+        assert!(receiver.handle_close(Close::new(other)).is_err());
+        assert!(!receiver.is_closed());
+        assert_eq!(receiver.data(), [1, 2]);
+    }
+
+    #[test]
+    fn test_wraparound() {
+        let to = Jid::from_str("juliet@capulet.lit/balcony").unwrap();
+        let sid = StreamId(String::from("i781hf64"));
+        let mut transfer = IbbTransfer::new(to, sid.clone(), 1);
+        transfer.seq = 65535;
+        let chunks = transfer.chunks(&[1, 2]);
+        assert_eq!(chunks[0].seq, 65535);
+        assert_eq!(chunks[1].seq, 0);
+
+        let mut receiver = IbbReceiver::new(&Open::new(sid, 1));
+        receiver.seq = 65535;
+        for chunk in chunks {
+            receiver.handle_data(chunk).unwrap();
+        }
+        assert_eq!(receiver.data(), [1, 2]);
+
+        let messages = transfer.messages(&[3]);
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].payloads[0].is("data", ns::IBB));
+    }
+}
@@ -0,0 +1,545 @@
+// Copyright (c) 2022 Emmanuel Gil Peyrot <linkmauve@linkmauve.fr>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Sending IQ requests and waiting for their response, and serving the
+//! requests of other entities.
+
+use futures::future::{FutureExt, LocalBoxFuture};
+use futures::stream::StreamExt;
+use rand::Rng;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+use tokio_xmpp::{Event as TokioXmppEvent, SimpleClient};
+use xmpp_parsers::{
+    iq::{Iq, IqGetPayload, IqResultPayload, IqSetPayload, IqType},
+    ns,
+    stanza_error::{DefinedCondition, ErrorType, StanzaError},
+    BareJid, Element, Jid,
+};
+
+use crate::{Agent, Error};
+
+/// Something able to send an IQ request and wait for its response.
+pub trait IqRequester {
+    /// Send `iq`, and resolve to the result or error with the same id,
+    /// coming from the entity it got sent to.
+    fn request(&mut self, iq: Iq) -> LocalBoxFuture<'_, Result<Iq, Error>>;
+}
+
+/// A new random id for a request, so that other entities can’t guess it
+/// to spoof its response.
+pub fn make_id() -> String {
+    format!("{:016x}", rand::thread_rng().gen::<u64>())
+}
+
+/// Whether `elem` is the response to the request `id` which `own` sent to
+/// `to`.
+///
+/// Requests without any recipient go to our own account, which answers
+/// either without any sender or from our bare JID.
+pub(crate) fn is_response(elem: &Element, id: &str, to: Option<&Jid>, own: &Jid) -> bool {
+    if !elem.is("iq", ns::JABBER_CLIENT)
+        || elem.attr("id") != Some(id)
+        || !matches!(elem.attr("type"), Some("result") | Some("error"))
+    {
+        return false;
+    }
+    let account = Jid::Bare(BareJid::from(own.clone()));
+    let from = match elem.attr("from").map(Jid::from_str) {
+        Some(Ok(from)) => from,
+        Some(Err(_)) => return false,
+        None => account.clone(),
+    };
+    from == *to.unwrap_or(&account)
+}
+
+/// Sends the request, then keeps every other incoming stanza until its
+/// response, to be yielded afterwards.
+impl IqRequester for SimpleClient {
+    fn request(&mut self, iq: Iq) -> LocalBoxFuture<'_, Result<Iq, Error>> {
+        async move {
+            let id = iq.id.clone();
+            let to = iq.to.clone();
+            let own = self.bound_jid().clone();
+            self.send_stanza(iq).await?;
+            let mut unrelated = Vec::new();
+            let result = loop {
+                let elem = match self.next().await {
+                    Some(Ok(elem)) => elem,
+                    Some(Err(err)) => break Err(err),
+                    None => break Err(Error::Disconnected),
+                };
+                if !is_response(&elem, &id, to.as_ref(), &own) {
+                    unrelated.push(elem);
+                    continue;
+                }
+                match Iq::try_from(elem) {
+                    Ok(iq) => break Ok(iq),
+                    Err(err) => warn!("Invalid iq while waiting for {}: {}", id, err),
+                }
+            };
+            for elem in unrelated {
+                self.defer(elem);
+            }
+            result
+        }
+        .boxed_local()
+    }
+}
+
+/// Sends the request, then keeps every other incoming event until its
+/// response, for [`Agent::wait_for_events`] to handle afterwards.
+impl IqRequester for Agent {
+    fn request(&mut self, iq: Iq) -> LocalBoxFuture<'_, Result<Iq, Error>> {
+        async move {
+            let own = match self.client.bound_jid() {
+                Some(jid) => jid.clone(),
+                None => return Err(Error::Disconnected),
+            };
+            let id = iq.id.clone();
+            let to = iq.to.clone();
+            self.send_stanza(iq.into()).await?;
+            loop {
+                let event = match self.client.next().await {
+                    Some(event) => event,
+                    None => return Err(Error::Disconnected),
+                };
+                match event {
+                    TokioXmppEvent::Stanza(elem) if is_response(&elem, &id, to.as_ref(), &own) => {
+                        match Iq::try_from(elem) {
+                            Ok(iq) => return Ok(iq),
+                            Err(err) => warn!("Invalid iq while waiting for {}: {}", id, err),
+                        }
+                    }
+                    TokioXmppEvent::Disconnected(_) => {
+                        self.deferred.push_back(event);
+                        return Err(Error::Disconnected);
+                    }
+                    event => self.deferred.push_back(event),
+                }
+            }
+        }
+        .boxed_local()
+    }
+}
+
+/// Why an IQ request didn’t get a usable result.
+#[derive(Debug)]
+pub enum IqError {
+    /// The request couldn’t be sent, or its response received.
+    Transport(Error),
+
+    /// The recipient returned an error.
+    Stanza(StanzaError),
+
+    /// The recipient returned an invalid or unexpected payload.
+    Parse(xmpp_parsers::Error),
+}
+
+impl fmt::Display for IqError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IqError::Transport(err) => write!(fmt, "transport error: {}", err),
+            IqError::Stanza(err) => write!(fmt, "stanza error: {:?}", err.defined_condition),
+            IqError::Parse(err) => write!(fmt, "parse error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for IqError {}
+
+impl From<Error> for IqError {
+    fn from(err: Error) -> IqError {
+        IqError::Transport(err)
+    }
+}
+
+impl From<xmpp_parsers::Error> for IqError {
+    fn from(err: xmpp_parsers::Error) -> IqError {
+        IqError::Parse(err)
+    }
+}
+
+/// Send `iq`, and parse the payload of its result as `P`.
+pub async fn request_payload<R, P>(requester: &mut R, iq: Iq) -> Result<P, IqError>
+where
+    R: IqRequester,
+    P: TryFrom<Element, Error = xmpp_parsers::Error>,
+{
+    let response = requester.request(iq).await?;
+    match response.payload {
+        IqType::Result(Some(payload)) => Ok(P::try_from(payload)?),
+        IqType::Result(None) => {
+            Err(xmpp_parsers::Error::ParseError("Result without a payload.").into())
+        }
+        IqType::Error(err) => Err(IqError::Stanza(err)),
+        IqType::Get(_) | IqType::Set(_) => {
+            Err(xmpp_parsers::Error::ParseError("Response isn’t a result.").into())
+        }
+    }
+}
+
+/// What to do when a peer reuses the id of one of its requests which is
+/// still being processed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CollisionPolicy {
+    /// Log a warning, and process the new request anyway.
+    #[default]
+    Warn,
+
+    /// Silently ignore the new request.
+    Drop,
+
+    /// Answer the new request with a conflict error.
+    Conflict,
+}
+
+/// What to do with an incoming request.
+#[derive(Debug, Clone)]
+pub enum Admission {
+    /// Process it, then call [`InboundRequests::finish`] once answered.
+    Process,
+
+    /// Ignore it.
+    Drop,
+
+    /// Send back this error, without processing it.
+    Reply(Box<Iq>),
+}
+
+/// Keeps track of the incoming requests being processed, by sender and
+/// id, so that stateful handlers never see two requests aliasing each
+/// other.
+#[derive(Debug, Clone, Default)]
+pub struct InboundRequests {
+    policy: CollisionPolicy,
+    // How many requests with this sender and id are being processed.
+    pending: HashMap<(Jid, String), usize>,
+}
+
+impl InboundRequests {
+    /// Track the incoming requests, handling collisions with `policy`.
+    pub fn new(policy: CollisionPolicy) -> InboundRequests {
+        InboundRequests {
+            policy,
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Start processing the request `id` from `from`, unless the policy
+    /// says otherwise.
+    pub fn begin(&mut self, from: &Jid, id: &str) -> Admission {
+        let count = self
+            .pending
+            .entry((from.clone(), String::from(id)))
+            .or_insert(0);
+        if *count == 0 {
+            *count = 1;
+            return Admission::Process;
+        }
+        match self.policy {
+            CollisionPolicy::Warn => {
+                warn!("{} reused the id {} of a request being processed", from, id);
+                *count += 1;
+                Admission::Process
+            }
+            CollisionPolicy::Drop => {
+                debug!("Dropping request {} from {}, its id is in use", id, from);
+                Admission::Drop
+            }
+            CollisionPolicy::Conflict => {
+                let error = StanzaError::new(
+                    ErrorType::Cancel,
+                    DefinedCondition::Conflict,
+                    "en",
+                    "This id is already used by a request being processed.",
+                );
+                Admission::Reply(Box::new(Iq::from_error(id, error).with_to(from.clone())))
+            }
+        }
+    }
+
+    /// The request `id` from `from` got answered, returning whether it
+    /// was being processed.
+    pub fn finish(&mut self, from: &Jid, id: &str) -> bool {
+        let key = (from.clone(), String::from(id));
+        match self.pending.get_mut(&key) {
+            Some(count) if *count > 1 => *count -= 1,
+            Some(_) => {
+                self.pending.remove(&key);
+            }
+            None => return false,
+        }
+        true
+    }
+
+    /// Number of requests being processed.
+    pub fn len(&self) -> usize {
+        self.pending.values().sum()
+    }
+
+    /// Whether no request is being processed.
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+type IqHandler = Box<dyn FnMut(Option<&Jid>, Element) -> Result<Option<Element>, StanzaError>>;
+
+/// Handlers of the incoming get and set requests, by namespace of their
+/// payload, answering those nobody handles with a service-unavailable
+/// error as the specification mandates.
+#[derive(Default)]
+pub struct IqDispatcher {
+    get: HashMap<String, IqHandler>,
+    set: HashMap<String, IqHandler>,
+    deferred: HashSet<String>,
+}
+
+fn typed_handler<P, R, F>(mut handler: F) -> IqHandler
+where
+    P: TryFrom<Element, Error = xmpp_parsers::Error>,
+    R: IqResultPayload,
+    F: FnMut(Option<&Jid>, P) -> Result<Option<R>, StanzaError> + 'static,
+{
+    Box::new(move |from, payload| {
+        let payload =
+            P::try_from(payload).map_err(|err| StanzaError::bad_request(format!("{}", err)))?;
+        handler(from, payload).map(|result| result.map(Into::into))
+    })
+}
+
+impl IqDispatcher {
+    /// Create a dispatcher without any handler.
+    pub fn new() -> IqDispatcher {
+        IqDispatcher::default()
+    }
+
+    /// Answer the get requests whose payload is in `ns` with `handler`,
+    /// given their sender and their payload parsed as a `P`.  A payload
+    /// failing to parse gets a bad-request error.
+    pub fn register_get<P, R, F>(&mut self, ns: &str, handler: F)
+    where
+        P: IqGetPayload + TryFrom<Element, Error = xmpp_parsers::Error>,
+        R: IqResultPayload,
+        F: FnMut(Option<&Jid>, P) -> Result<Option<R>, StanzaError> + 'static,
+    {
+        self.get.insert(String::from(ns), typed_handler(handler));
+    }
+
+    /// Answer the set requests whose payload is in `ns` with `handler`,
+    /// given their sender and their payload parsed as a `P`.  A payload
+    /// failing to parse gets a bad-request error.
+    pub fn register_set<P, R, F>(&mut self, ns: &str, handler: F)
+    where
+        P: IqSetPayload + TryFrom<Element, Error = xmpp_parsers::Error>,
+        R: IqResultPayload,
+        F: FnMut(Option<&Jid>, P) -> Result<Option<R>, StanzaError> + 'static,
+    {
+        self.set.insert(String::from(ns), typed_handler(handler));
+    }
+
+    /// Leave the get and set requests whose payload is in `ns` to the
+    /// application, which answers them later.
+    pub fn defer(&mut self, ns: &str) {
+        self.deferred.insert(String::from(ns));
+    }
+
+    /// Whether this request got left to the application.
+    pub fn is_deferred(&self, iq: &Iq) -> bool {
+        match iq.payload {
+            IqType::Get(ref payload) | IqType::Set(ref payload) => {
+                self.deferred.contains(payload.ns())
+            }
+            IqType::Result(_) | IqType::Error(_) => false,
+        }
+    }
+
+    /// Whether a handler got registered for this request.
+    pub fn handles(&self, iq: &Iq) -> bool {
+        match iq.payload {
+            IqType::Get(ref payload) => self.get.contains_key(payload.ns()),
+            IqType::Set(ref payload) => self.set.contains_key(payload.ns()),
+            IqType::Result(_) | IqType::Error(_) => false,
+        }
+    }
+
+    /// Answer the get or set request `iq` with its handler, or with a
+    /// service-unavailable error if there is none.  Results and errors
+    /// don’t get any reply.
+    pub fn dispatch(&mut self, iq: &Iq) -> Option<Element> {
+        let (handlers, payload) = match iq.payload {
+            IqType::Get(ref payload) => (&mut self.get, payload),
+            IqType::Set(ref payload) => (&mut self.set, payload),
+            IqType::Result(_) | IqType::Error(_) => return None,
+        };
+        let response = match handlers.get_mut(payload.ns()) {
+            Some(handler) => handler(iq.from.as_ref(), payload.clone()),
+            None => Err(StanzaError::service_unavailable(
+                "No handler defined for this kind of iq.",
+            )),
+        };
+        Some(match response {
+            Ok(result) => Iq {
+                from: None,
+                to: iq.from.clone(),
+                id: iq.id.clone(),
+                payload: IqType::Result(result),
+            }
+            .into(),
+            Err(error) => Iq::error_reply(iq, error),
+        })
+    }
+}
+
+impl fmt::Debug for IqDispatcher {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("IqDispatcher")
+            .field("get", &self.get.keys().collect::<Vec<_>>())
+            .field("set", &self.set.keys().collect::<Vec<_>>())
+            .field("deferred", &self.deferred)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_collisions() {
+        let romeo = Jid::from_str("romeo@montague.lit/orchard").unwrap();
+        let juliet = Jid::from_str("juliet@capulet.lit/balcony").unwrap();
+
+        let mut inbound = InboundRequests::new(CollisionPolicy::Drop);
+        assert!(matches!(inbound.begin(&romeo, "1"), Admission::Process));
+        assert!(matches!(inbound.begin(&juliet, "1"), Admission::Process));
+        assert!(matches!(inbound.begin(&romeo, "1"), Admission::Drop));
+        assert!(inbound.finish(&romeo, "1"));
+        assert!(!inbound.finish(&romeo, "1"));
+        assert_eq!(inbound.len(), 1);
+        assert!(matches!(inbound.begin(&romeo, "1"), Admission::Process));
+
+        // Both requests get processed, so both have to get answered.
+        let mut inbound = InboundRequests::new(CollisionPolicy::Warn);
+        assert!(matches!(inbound.begin(&romeo, "1"), Admission::Process));
+        assert!(matches!(inbound.begin(&romeo, "1"), Admission::Process));
+        assert_eq!(inbound.len(), 2);
+        assert!(inbound.finish(&romeo, "1"));
+        assert!(inbound.finish(&romeo, "1"));
+        assert!(inbound.is_empty());
+
+        let mut inbound = InboundRequests::new(CollisionPolicy::Conflict);
+        assert!(matches!(inbound.begin(&romeo, "1"), Admission::Process));
+        let reply = match inbound.begin(&romeo, "1") {
+            Admission::Reply(reply) => reply,
+            admission => panic!("{:?}", admission),
+        };
+        assert_eq!(reply.id, "1");
+        assert_eq!(reply.to, Some(romeo.clone()));
+        match reply.payload {
+            IqType::Error(error) => {
+                assert_eq!(error.defined_condition, DefinedCondition::Conflict)
+            }
+            _ => panic!(),
+        }
+        inbound.finish(&romeo, "1");
+        assert!(inbound.is_empty());
+    }
+
+    #[test]
+    fn test_is_response() {
+        let own = Jid::from_str("juliet@capulet.lit/balcony").unwrap();
+        let romeo = Jid::from_str("romeo@montague.lit/orchard").unwrap();
+        let elem = |xml: &str| xml.parse::<Element>().unwrap();
+
+        let result = elem(
+            "<iq xmlns='jabber:client' from='romeo@montague.lit/orchard' id='a1' type='result'/>",
+        );
+        assert!(is_response(&result, "a1", Some(&romeo), &own));
+        assert!(!is_response(&result, "a2", Some(&romeo), &own));
+        assert!(!is_response(&result, "a1", None, &own));
+
+        // Someone else guessing the id.
+        let spoofed = elem(
+            "<iq xmlns='jabber:client' from='tybalt@capulet.lit/street' id='a1' type='error'/>",
+        );
+        assert!(!is_response(&spoofed, "a1", Some(&romeo), &own));
+
+        // A request of the peer reusing the id.
+        let request = elem(
+            "<iq xmlns='jabber:client' from='romeo@montague.lit/orchard' id='a1' type='get'/>",
+        );
+        assert!(!is_response(&request, "a1", Some(&romeo), &own));
+
+        let account = Jid::from_str("juliet@capulet.lit").unwrap();
+        for xml in &[
+            "<iq xmlns='jabber:client' id='r1' type='result'/>",
+            "<iq xmlns='jabber:client' from='juliet@capulet.lit' id='r1' type='result'/>",
+        ] {
+            assert!(is_response(&elem(xml), "r1", None, &own));
+            assert!(is_response(&elem(xml), "r1", Some(&account), &own));
+        }
+
+        assert_ne!(make_id(), make_id());
+    }
+
+    #[test]
+    fn test_dispatcher() {
+        use xmpp_parsers::version::{VersionQuery, VersionResult};
+
+        let mut dispatcher = IqDispatcher::new();
+        dispatcher.register_get(ns::VERSION, |from: Option<&Jid>, _: VersionQuery| {
+            assert!(from.is_some());
+            Ok(Some(VersionResult {
+                name: String::from("xmpp-rs"),
+                version: String::from("0.4.0"),
+                os: None,
+            }))
+        });
+
+        let iq = |xml: &str| Iq::try_from(xml.parse::<Element>().unwrap()).unwrap();
+        let query = iq("<iq xmlns='jabber:client' from='romeo@montague.lit/orchard' id='v1' type='get'><query xmlns='jabber:iq:version'/></iq>");
+        assert!(dispatcher.handles(&query));
+        let reply = Iq::try_from(dispatcher.dispatch(&query).unwrap()).unwrap();
+        assert_eq!(reply.id, "v1");
+        assert_eq!(reply.to, query.from);
+        match reply.payload {
+            IqType::Result(Some(payload)) => {
+                let result = VersionResult::try_from(payload).unwrap();
+                assert_eq!(result.name, "xmpp-rs");
+            }
+            _ => panic!(),
+        }
+
+        let invalid = iq("<iq xmlns='jabber:client' from='romeo@montague.lit/orchard' id='v2' type='get'><query xmlns='jabber:iq:version'><name>foo</name></query></iq>");
+        let reply = Iq::try_from(dispatcher.dispatch(&invalid).unwrap()).unwrap();
+        match reply.payload {
+            IqType::Error(error) => {
+                assert_eq!(error.defined_condition, DefinedCondition::BadRequest)
+            }
+            _ => panic!(),
+        }
+
+        // The set iqs in the same namespace aren’t handled.
+        let set = iq("<iq xmlns='jabber:client' from='romeo@montague.lit/orchard' id='v3' type='set'><query xmlns='jabber:iq:version'/></iq>");
+        assert!(!dispatcher.handles(&set));
+        let reply = Iq::try_from(dispatcher.dispatch(&set).unwrap()).unwrap();
+        match reply.payload {
+            IqType::Error(error) => assert_eq!(
+                error.defined_condition,
+                DefinedCondition::ServiceUnavailable
+            ),
+            _ => panic!(),
+        }
+
+        let result = iq("<iq xmlns='jabber:client' id='v4' type='result'/>");
+        assert!(dispatcher.dispatch(&result).is_none());
+    }
+}
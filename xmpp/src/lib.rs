@@ -6,43 +6,92 @@
 
 #![deny(bare_trait_objects)]
 
+use chrono::{FixedOffset, Offset, Utc};
 use futures::stream::StreamExt;
 use reqwest::{
     header::HeaderMap as ReqwestHeaderMap, Body as ReqwestBody, Client as ReqwestClient,
 };
 use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
 use std::convert::TryFrom;
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use std::time::Duration;
 use tokio::fs::File;
 use tokio_util::codec::{BytesCodec, FramedRead};
-use tokio_xmpp::{AsyncClient as TokioXmppClient, Event as TokioXmppEvent};
+use tokio_xmpp::stream_management::MemorySmStorage;
+use tokio_xmpp::{AsyncClient as TokioXmppClient, Event as TokioXmppEvent, Packet};
 use xmpp_parsers::{
+    activity::Activity,
+    bookmarks::Storage,
     bookmarks2::Conference,
-    caps::{compute_disco, hash_caps, Caps},
-    disco::{DiscoInfoQuery, DiscoInfoResult, Feature, Identity},
-    hashes::Algo,
-    http_upload::{Header as HttpUploadHeader, SlotRequest, SlotResult},
-    iq::{Iq, IqType},
+    chatstates::ChatState,
+    date::DateTime,
+    disco::{DiscoInfoQuery, DiscoInfoResult, Identity},
+    http_upload::{Header as HttpUploadHeader, SlotRequest, SlotResult, UploadError},
+    iq::{Iq, IqGetPayload, IqResultPayload, IqSetPayload, IqType},
     message::{Body, Message, MessageType},
-    muc::{
-        user::{MucUser, Status},
-        Muc,
-    },
-    ns,
+    mood::Mood,
+    muc::{user::MucUser, Muc},
     presence::{Presence, Type as PresenceType},
-    pubsub::pubsub::{Items, PubSub},
+    private::Query as PrivateQuery,
     roster::{Item as RosterItem, Roster},
     stanza_error::{DefinedCondition, ErrorType, StanzaError},
+    time::TimeResult,
+    tune::Tune,
+    version::VersionResult,
     BareJid, Element, FullJid, Jid,
 };
 #[macro_use]
 extern crate log;
 
+pub mod bookmarks;
+pub mod bootstrap;
+pub mod command;
+pub mod correlation;
+pub mod dedup;
+pub mod disco;
+pub mod extensions;
+pub mod hints;
+pub mod ibb;
+pub mod iq;
+pub mod nick;
+pub mod pagination;
+pub mod ping;
 mod pubsub;
+pub mod received;
+#[cfg(feature = "avatars")]
+mod room_avatar;
+pub mod router;
+pub mod subscriptions;
+
+use bookmarks::BookmarkManager;
+use bootstrap::{BootstrapStep, SessionBootstrap};
+use correlation::{CorrelationId, Correlator};
+use dedup::MessageDeduplicator;
+use disco::DiscoResponder;
+use extensions::{ExtensionPayload, ExtensionRegistry};
+use hints::{apply_hints, HintPolicy};
+use iq::{Admission, CollisionPolicy, InboundRequests, IqDispatcher};
+use nick::{normalize_nick, NickMatcher};
+use pagination::Progress;
+use ping::{LatencyError, LatencyMetrics};
+use received::{PresenceChange, ReceivedMessage};
+use router::{Handling, StanzaKind, StanzaRouter};
+use subscriptions::{SubscriptionQueue, SubscriptionRequest};
+
+pub use xmpp_parsers::ns;
 
 pub type Error = tokio_xmpp::Error;
 
+/// Whether `jid` is `bare` or one of its resources.
+pub(crate) fn is_same_bare(jid: &Jid, bare: &BareJid) -> bool {
+    match jid {
+        Jid::Bare(jid) => jid == bare,
+        Jid::Full(full) => full.node == bare.node && full.domain == bare.domain,
+    }
+}
+
 #[derive(Debug)]
 pub enum ClientType {
     Bot,
@@ -70,13 +119,61 @@ pub enum ClientFeature {
     Avatars,
     ContactList,
     JoinRooms,
+    /// Join the autojoin bookmarks, and leave them once they get removed
+    /// or lose their autojoin flag, instead of letting the application do
+    /// it upon `Event::JoinRoom` and `Event::LeaveRoom`.
+    Autojoin,
+    /// Answer software version (XEP-0092) queries, with the name and
+    /// version set with `ClientBuilder::set_software_version()`.
+    SoftwareVersion,
+    /// Answer entity time (XEP-0202) queries, in the timezone set with
+    /// `ClientBuilder::set_timezone()`.
+    EntityTime,
+    /// Receive the moods, activities and tunes of contacts, as
+    /// `Event::MoodChanged`, `Event::ActivityChanged` and
+    /// `Event::TuneChanged`.
+    RichPresence,
 }
 
 pub type RoomNick = String;
 
+/// Why the upload service refused to give a slot for a file.
+#[derive(Debug)]
+pub enum UploadFailure {
+    /// The file is too large, the service accepts files up to this size if
+    /// it told it; the file can be downscaled or compressed before trying
+    /// again.
+    FileTooLarge(Option<u64>),
+    /// The quota is reached, a slot can be requested again at this time.
+    RetryAfter(DateTime),
+    /// Any other error.
+    Other(Box<StanzaError>),
+}
+
+impl From<StanzaError> for UploadFailure {
+    fn from(error: StanzaError) -> UploadFailure {
+        match UploadError::from_stanza_error(&error) {
+            Some(UploadError::FileTooLarge(file_too_large)) => {
+                UploadFailure::FileTooLarge(file_too_large.max_file_size)
+            }
+            Some(UploadError::Retry(retry)) => UploadFailure::RetryAfter(retry.stamp),
+            None => UploadFailure::Other(Box::new(error)),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum Event {
     Online,
+    /// Someone asked to subscribe to our presence, see
+    /// `Agent::approve_subscription()` and `Agent::deny_subscription()`.
+    SubscriptionRequest(SubscriptionRequest),
+    /// Every request of the session bootstrap got answered, see
+    /// `Agent::bootstrap()` for those which failed.
+    SessionReady,
+    /// More of the roster or of the archived messages got fetched during
+    /// the session bootstrap.
+    SyncProgress(BootstrapStep, Progress),
     Disconnected,
     ContactAdded(RosterItem),
     ContactRemoved(RosterItem),
@@ -84,13 +181,41 @@ pub enum Event {
     #[cfg(feature = "avatars")]
     AvatarRetrieved(Jid, String),
     ChatMessage(BareJid, Body),
+    /// A message got received, whether from a contact or a room, with its
+    /// payloads parsed by the extension parsers.
+    MessageReceived(ReceivedMessage),
+    /// A contact, outside of the rooms we joined, changed its availability
+    /// from this resource.
+    PresenceChanged(Jid, PresenceChange),
     JoinRoom(BareJid, Conference),
     LeaveRoom(BareJid),
     LeaveAllRooms,
+    /// A bookmark got added or changed, possibly by another device.
+    BookmarkChanged(BareJid, Conference),
+    /// A bookmark got removed, possibly by another device.
+    BookmarkRemoved(BareJid),
     RoomJoined(BareJid),
     RoomLeft(BareJid),
     RoomMessage(BareJid, RoomNick, Body),
+    /// The avatar of this room (XEP-0486) got saved at this path.
+    #[cfg(feature = "avatars")]
+    RoomAvatarRetrieved(BareJid, String),
     HttpUploadedFile(String),
+    /// The upload service refused to give a slot for this file.
+    HttpUploadFailed(PathBuf, UploadFailure),
+    /// This contact published a new mood (XEP-0107), or none anymore.
+    MoodChanged(BareJid, Mood),
+    /// This contact published a new activity (XEP-0108), or none anymore.
+    ActivityChanged(BareJid, Activity),
+    /// This contact published a new tune (XEP-0118), or an empty one.
+    TuneChanged(BareJid, Tune),
+    /// A get or set iq left to us by `ClientBuilder::defer_iq_requests()`,
+    /// to answer with `Agent::answer_iq()`.
+    IqRequest(Box<Iq>),
+    /// A presence from this entity contained a payload parsed by one of
+    /// the extension parsers, those of messages being part of
+    /// `Event::MessageReceived` instead.
+    Extension(Jid, ExtensionPayload),
 }
 
 #[derive(Default)]
@@ -102,6 +227,18 @@ pub struct ClientBuilder<'a> {
     lang: Vec<String>,
     disco: (ClientType, String),
     features: Vec<ClientFeature>,
+    extra_features: Vec<String>,
+    extensions: ExtensionRegistry,
+    software_version: Option<VersionResult>,
+    timezone: Option<FixedOffset>,
+    dedup_window: usize,
+    keepalive: Option<(Duration, Duration)>,
+    bootstrap: SessionBootstrap,
+    iq_collision_policy: CollisionPolicy,
+    router: StanzaRouter,
+    iq_dispatcher: IqDispatcher,
+    #[cfg(feature = "avatars")]
+    avatar_dir: String,
 }
 
 impl ClientBuilder<'_> {
@@ -114,6 +251,18 @@ impl ClientBuilder<'_> {
             lang: vec![String::from("en")],
             disco: (ClientType::default(), String::from("tokio-xmpp")),
             features: vec![],
+            extra_features: vec![],
+            extensions: ExtensionRegistry::new(),
+            software_version: None,
+            timezone: None,
+            dedup_window: dedup::DEFAULT_WINDOW,
+            keepalive: None,
+            bootstrap: SessionBootstrap::new(),
+            iq_collision_policy: CollisionPolicy::default(),
+            router: StanzaRouter::new(),
+            iq_dispatcher: IqDispatcher::new(),
+            #[cfg(feature = "avatars")]
+            avatar_dir: String::from("data"),
         }
     }
 
@@ -137,34 +286,184 @@ impl ClientBuilder<'_> {
         self
     }
 
+    /// Set the software version to answer with, defaulting to the client
+    /// name and the version of this crate.
+    pub fn set_software_version(mut self, name: &str, version: &str, os: Option<&str>) -> Self {
+        let mut software_version = VersionResult::new(name, version);
+        if let Some(os) = os {
+            software_version = software_version.with_os(os);
+        }
+        self.software_version = Some(software_version);
+        self
+    }
+
+    /// Set the local timezone to answer with, defaulting to UTC.
+    pub fn set_timezone(mut self, timezone: FixedOffset) -> Self {
+        self.timezone = Some(timezone);
+        self
+    }
+
+    /// Set how many received messages are remembered to drop their
+    /// duplicates, by origin-id or stanza-id, 0 disabling deduplication.
+    pub fn set_dedup_window(mut self, window: usize) -> Self {
+        self.dedup_window = window;
+        self
+    }
+
+    /// Ping the server every `interval` without hearing from it,
+    /// reconnecting if a ping stays unanswered after `timeout`, which also
+    /// estimates the latency returned by `Agent::server_latency()`.
+    pub fn set_keepalive(mut self, interval: Duration, timeout: Duration) -> Self {
+        self.keepalive = Some((interval, timeout));
+        self
+    }
+
+    /// Set what to do when a peer reuses the id of one of its requests
+    /// which is still being processed, by default only warning.
+    pub fn set_iq_collision_policy(mut self, policy: CollisionPolicy) -> Self {
+        self.iq_collision_policy = policy;
+        self
+    }
+
+    /// Replace the requests sent once online, by default only fetching
+    /// the roster.
+    pub fn set_session_bootstrap(mut self, bootstrap: SessionBootstrap) -> Self {
+        self.bootstrap = bootstrap;
+        self
+    }
+
+    /// Set the directory the avatars get saved in, one subdirectory per
+    /// contact or room, by default `data`.
+    #[cfg(feature = "avatars")]
+    pub fn set_avatar_dir(mut self, dir: &str) -> Self {
+        self.avatar_dir = String::from(dir);
+        self
+    }
+
     pub fn enable_feature(mut self, feature: ClientFeature) -> Self {
         self.features.push(feature);
         self
     }
 
-    fn make_disco(&self) -> DiscoInfoResult {
-        let identities = vec![Identity::new(
+    /// Advertise the support of `var`, for features implemented by the
+    /// application itself.
+    pub fn register_feature(mut self, var: &str) -> Self {
+        self.extra_features.push(String::from(var));
+        self
+    }
+
+    /// Call `handler` for the incoming stanzas of this `kind` having a
+    /// payload in `ns`, or for all of them if `None`, before the built-in
+    /// handling which it can prevent by consuming them.  The handlers get
+    /// called by decreasing `priority`, and the replies they push get sent.
+    pub fn register_handler<F>(
+        mut self,
+        kind: StanzaKind,
+        ns: Option<&str>,
+        priority: i32,
+        handler: F,
+    ) -> Self
+    where
+        F: FnMut(&Element, &mut Vec<Element>) -> Handling + 'static,
+    {
+        self.router.register(kind, ns, priority, handler);
+        self
+    }
+
+    /// Answer the get iqs whose payload is in `ns` with `handler`, instead
+    /// of the built-in handling.  Returning `Ok(None)` sends an empty
+    /// result, and the unhandled get iqs still get a service-unavailable
+    /// error.
+    pub fn register_iq_get_handler<P, R, F>(mut self, ns: &str, handler: F) -> Self
+    where
+        P: IqGetPayload + TryFrom<Element, Error = xmpp_parsers::Error>,
+        R: IqResultPayload,
+        F: FnMut(Option<&Jid>, P) -> Result<Option<R>, StanzaError> + 'static,
+    {
+        self.iq_dispatcher.register_get(ns, handler);
+        self
+    }
+
+    /// Answer the set iqs whose payload is in `ns` with `handler`, instead
+    /// of the built-in handling.  Returning `Ok(None)` sends an empty
+    /// result, and the unhandled set iqs still get a service-unavailable
+    /// error.
+    pub fn register_iq_set_handler<P, R, F>(mut self, ns: &str, handler: F) -> Self
+    where
+        P: IqSetPayload + TryFrom<Element, Error = xmpp_parsers::Error>,
+        R: IqResultPayload,
+        F: FnMut(Option<&Jid>, P) -> Result<Option<R>, StanzaError> + 'static,
+    {
+        self.iq_dispatcher.register_set(ns, handler);
+        self
+    }
+
+    /// Emit the get and set iqs whose payload is in `ns` as
+    /// `Event::IqRequest`, instead of the built-in handling, to answer them
+    /// later with `Agent::answer_iq()`.  Until then, the requests of the
+    /// same sender reusing their id get handled as
+    /// `set_iq_collision_policy()` says.
+    pub fn defer_iq_requests(mut self, ns: &str) -> Self {
+        self.iq_dispatcher.defer(ns);
+        self
+    }
+
+    /// Parse the `<name xmlns='ns'/>` payloads of messages and presences
+    /// into a `T`, part of `Event::MessageReceived` or emitted as
+    /// `Event::Extension` respectively.
+    pub fn register_extension<T>(mut self, name: &str, ns: &str) -> Self
+    where
+        T: TryFrom<Element, Error = xmpp_parsers::Error> + std::fmt::Debug + 'static,
+    {
+        self.extensions.register::<T>(name, ns);
+        self
+    }
+
+    /// Parse the `<name xmlns='ns'/>` payloads of messages and presences
+    /// through `parser`, part of `Event::MessageReceived` or emitted as
+    /// `Event::Extension` respectively.
+    pub fn register_extension_with<T, F>(mut self, name: &str, ns: &str, parser: F) -> Self
+    where
+        T: std::fmt::Debug + 'static,
+        F: Fn(Element) -> Result<T, xmpp_parsers::Error> + 'static,
+    {
+        self.extensions.register_with(name, ns, parser);
+        self
+    }
+
+    fn make_disco(&self) -> DiscoResponder {
+        let mut disco = DiscoResponder::new();
+        disco.register_identity(Identity::new(
             "client",
             self.disco.0.to_string(),
             "en",
             self.disco.1.to_string(),
-        )];
-        let mut features = vec![Feature::new(ns::DISCO_INFO)];
+        ));
+        disco.set_caps_node(self.website.clone());
         #[cfg(feature = "avatars")]
         {
             if self.features.contains(&ClientFeature::Avatars) {
-                features.push(Feature::new(format!("{}+notify", ns::AVATAR_METADATA)));
+                disco.register_feature(format!("{}+notify", ns::AVATAR_METADATA));
             }
         }
         if self.features.contains(&ClientFeature::JoinRooms) {
-            features.push(Feature::new(format!("{}+notify", ns::BOOKMARKS2)));
+            disco.register_feature(format!("{}+notify", ns::BOOKMARKS2));
+        }
+        if self.features.contains(&ClientFeature::SoftwareVersion) {
+            disco.register_feature(ns::VERSION);
+        }
+        if self.features.contains(&ClientFeature::EntityTime) {
+            disco.register_feature(ns::TIME);
         }
-        DiscoInfoResult {
-            node: None,
-            identities,
-            features,
-            extensions: vec![],
+        if self.features.contains(&ClientFeature::RichPresence) {
+            disco.register_feature(format!("{}+notify", ns::MOOD));
+            disco.register_feature(format!("{}+notify", ns::ACTIVITY));
+            disco.register_feature(format!("{}+notify", ns::TUNE));
         }
+        for var in &self.extra_features {
+            disco.register_feature(var.clone());
+        }
+        disco
     }
 
     pub fn build(self) -> Result<Agent, Error> {
@@ -173,17 +472,57 @@ impl ClientBuilder<'_> {
     }
 
     // This function is meant to be used for testing build
-    pub(crate) fn build_impl(self, client: TokioXmppClient) -> Result<Agent, Error> {
+    pub(crate) fn build_impl(self, mut client: TokioXmppClient) -> Result<Agent, Error> {
+        if let Some((interval, timeout)) = self.keepalive {
+            client.set_keepalive(interval, timeout);
+        }
+        if self.bootstrap.uses_stream_management() {
+            client.set_stream_management(MemorySmStorage::new());
+        }
+        let jid = BareJid::from(self.jid.parse::<Jid>()?);
         let disco = self.make_disco();
-        let node = self.website;
+        let autojoin = self.features.contains(&ClientFeature::Autojoin);
+        let software_version = if self.features.contains(&ClientFeature::SoftwareVersion) {
+            let disco_name = &self.disco.1;
+            Some(self.software_version.clone().unwrap_or_else(|| {
+                VersionResult::new(disco_name.as_str(), env!("CARGO_PKG_VERSION"))
+            }))
+        } else {
+            None
+        };
+        let timezone = if self.features.contains(&ClientFeature::EntityTime) {
+            Some(self.timezone.unwrap_or_else(|| Utc.fix()))
+        } else {
+            None
+        };
 
         let agent = Agent {
             client,
             default_nick: Rc::new(RefCell::new(self.default_nick)),
             lang: Rc::new(self.lang),
             disco,
-            node,
             uploads: Vec::new(),
+            rooms: HashMap::new(),
+            occupant_ids: HashMap::new(),
+            nick_matcher: NickMatcher::new(),
+            bookmarks: BookmarkManager::new(),
+            autojoin,
+            extensions: self.extensions,
+            software_version,
+            timezone,
+            jid,
+            dedup: MessageDeduplicator::new(self.dedup_window),
+            bootstrap: self.bootstrap,
+            subscriptions: SubscriptionQueue::new(),
+            inbound: InboundRequests::new(self.iq_collision_policy),
+            correlator: Correlator::new(),
+            router: self.router,
+            iq_dispatcher: self.iq_dispatcher,
+            deferred: VecDeque::new(),
+            latency_metrics: None,
+            reported_latency: None,
+            #[cfg(feature = "avatars")]
+            avatar_dir: self.avatar_dir,
         };
 
         Ok(agent)
@@ -194,9 +533,31 @@ pub struct Agent {
     client: TokioXmppClient,
     default_nick: Rc<RefCell<String>>,
     lang: Rc<Vec<String>>,
-    disco: DiscoInfoResult,
-    node: String,
+    disco: DiscoResponder,
     uploads: Vec<(String, Jid, PathBuf)>,
+    rooms: HashMap<BareJid, RoomNick>,
+    occupant_ids: HashMap<BareJid, HashMap<RoomNick, String>>,
+    nick_matcher: NickMatcher,
+    bookmarks: BookmarkManager,
+    autojoin: bool,
+    extensions: ExtensionRegistry,
+    software_version: Option<VersionResult>,
+    timezone: Option<FixedOffset>,
+    jid: BareJid,
+    dedup: MessageDeduplicator,
+    bootstrap: SessionBootstrap,
+    subscriptions: SubscriptionQueue,
+    inbound: InboundRequests,
+    correlator: Correlator,
+    router: StanzaRouter,
+    iq_dispatcher: IqDispatcher,
+    // Events received while waiting for the response to a request.
+    deferred: VecDeque<TokioXmppEvent>,
+    latency_metrics: Option<Box<dyn LatencyMetrics>>,
+    // The server latency last given to the metrics.
+    reported_latency: Option<Duration>,
+    #[cfg(feature = "avatars")]
+    avatar_dir: String,
 }
 
 impl Agent {
@@ -204,6 +565,24 @@ impl Agent {
         self.client.send_end().await
     }
 
+    /// The identifier of the received stanza being handled, which prefixes
+    /// the log lines about it and about the replies sent meanwhile.
+    pub fn current_correlation_id(&self) -> Option<CorrelationId> {
+        self.correlator.current()
+    }
+
+    pub(crate) async fn send_stanza(&mut self, stanza: Element) -> Result<(), Error> {
+        if let Some(id) = self.correlator.current() {
+            debug!(
+                "{}: sending {} to {:?}",
+                id,
+                stanza.name(),
+                stanza.attr("to")
+            );
+        }
+        self.client.send_stanza(stanza).await
+    }
+
     pub async fn join_room(
         &mut self,
         room: BareJid,
@@ -218,11 +597,237 @@ impl Agent {
         }
 
         let nick = nick.unwrap_or_else(|| self.default_nick.borrow().clone());
+        let nick = normalize_nick(&nick);
+        self.rooms.insert(room.clone(), nick.clone());
         let room_jid = room.with_resource(nick);
         let mut presence = Presence::new(PresenceType::None).with_to(Jid::Full(room_jid));
         presence.add_payload(muc);
-        presence.set_status(String::from(lang), String::from(status));
-        let _ = self.client.send_stanza(presence.into()).await;
+        if !status.is_empty() {
+            presence.set_status(String::from(lang), String::from(status));
+        }
+        let _ = self.send_stanza(presence.into()).await;
+    }
+
+    /// Join `room` as `nick`, without password nor status.
+    pub async fn join_room_as(&mut self, room: BareJid, nick: &str) {
+        self.join_room(room, Some(String::from(nick)), None, "", "")
+            .await;
+    }
+
+    /// Leave a room we previously joined, doing nothing if we didn’t.
+    pub async fn leave_room(&mut self, room: &BareJid, lang: &str, status: &str) {
+        let nick = match self.rooms.remove(room) {
+            Some(nick) => nick,
+            None => return,
+        };
+        self.occupant_ids.remove(room);
+        let room_jid = room.clone().with_resource(nick);
+        let mut presence = Presence::new(PresenceType::Unavailable).with_to(Jid::Full(room_jid));
+        if !status.is_empty() {
+            presence.set_status(String::from(lang), String::from(status));
+        }
+        let _ = self.send_stanza(presence.into()).await;
+    }
+
+    /// The occupant id (XEP-0421) the room attached to the last presence of
+    /// this occupant, identifying them across renames in anonymous rooms.
+    pub fn occupant_id(&self, room: &BareJid, nick: &str) -> Option<&str> {
+        self.occupant_ids.get(room)?.get(nick).map(String::as_str)
+    }
+
+    /// The bookmarks of the account, as last known.
+    pub fn bookmarks(&self) -> &BookmarkManager {
+        &self.bookmarks
+    }
+
+    /// Persistently bookmark `room`, shared with the other devices of the
+    /// user, joining it right away if it is to be joined automatically.
+    pub async fn bookmark_room(&mut self, room: BareJid, conference: Conference) {
+        let iq = self.bookmarks.store_request(&room, &conference);
+        let _ = self.send_stanza(iq.into()).await;
+        let events = self.bookmarks.published(room, conference);
+        self.autojoin(&events).await;
+    }
+
+    /// Remove the bookmark of `room`, leaving it too.
+    pub async fn remove_bookmark(&mut self, room: BareJid) {
+        let iq = self.bookmarks.remove_request(&room);
+        let _ = self.send_stanza(iq.into()).await;
+        let events = self.bookmarks.retracted(room);
+        self.autojoin(&events).await;
+    }
+
+    /// Join or leave the rooms as asked by bookmark events, if we are in
+    /// charge of it.
+    async fn autojoin(&mut self, events: &[Event]) {
+        if !self.autojoin {
+            return;
+        }
+        for event in events {
+            match event {
+                Event::JoinRoom(room, conference) if !self.rooms.contains_key(room) => {
+                    let conference = conference.clone();
+                    self.join_room(room.clone(), conference.nick, conference.password, "", "")
+                        .await;
+                }
+                Event::LeaveRoom(room) => self.leave_room(room, "", "").await,
+                Event::LeaveAllRooms => {
+                    let rooms: Vec<_> = self.rooms.keys().cloned().collect();
+                    for room in rooms {
+                        self.leave_room(&room, "", "").await;
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+
+    /// The identities and features we advertise.
+    pub fn disco(&self) -> &DiscoResponder {
+        &self.disco
+    }
+
+    /// The requests sent once online, and which of them failed.
+    pub fn bootstrap(&self) -> &SessionBootstrap {
+        &self.bootstrap
+    }
+
+    /// The subscription requests waiting for an answer, including those
+    /// received before the last reconnection.
+    pub fn pending_subscriptions(&self) -> &SubscriptionQueue {
+        &self.subscriptions
+    }
+
+    /// Allow `jid` to see our presence, also adding it to the roster if
+    /// `add_to_roster` is set. Does nothing if it didn’t ask for it.
+    pub async fn approve_subscription(&mut self, jid: &BareJid, add_to_roster: bool) {
+        if let Some(stanzas) = self.subscriptions.approve(jid, add_to_roster) {
+            let _ = self
+                .client
+                .send_all(stanzas.into_iter().map(Packet::Stanza))
+                .await;
+        }
+    }
+
+    /// Refuse to let `jid` see our presence. Does nothing if it didn’t
+    /// ask for it.
+    pub async fn deny_subscription(&mut self, jid: &BareJid) {
+        if let Some(stanza) = self.subscriptions.deny(jid) {
+            let _ = self.send_stanza(stanza).await;
+        }
+    }
+
+    /// The received messages remembered to drop their duplicates, for
+    /// instance to also record the messages fetched from an archive.
+    pub fn dedup_mut(&mut self) -> &mut MessageDeduplicator {
+        &mut self.dedup
+    }
+
+    /// The round-trip time to our server, smoothed over the keepalive
+    /// pings enabled with `ClientBuilder::set_keepalive()`, or `None`
+    /// until one got answered on the current connection.
+    ///
+    /// To measure it to another entity, see [`Agent::measure_latency`].
+    pub fn server_latency(&self) -> Option<Duration> {
+        self.client.latency()
+    }
+
+    /// Send a ping (XEP-0199) to `jid`, and resolve to the time its answer
+    /// took to come back, giving up after `timeout`.
+    ///
+    /// The events received meanwhile get returned by the next calls to
+    /// `wait_for_events()`.
+    pub async fn measure_latency(
+        &mut self,
+        jid: Jid,
+        timeout: Duration,
+    ) -> Result<Duration, LatencyError> {
+        let rtt = ping::measure_latency(self, jid.clone(), timeout).await?;
+        if let Some(ref mut metrics) = self.latency_metrics {
+            metrics.latency_measured(&jid, rtt);
+        }
+        Ok(rtt)
+    }
+
+    /// Report the round-trip times to `metrics`: the ones measured with
+    /// `Agent::measure_latency()`, and the estimate to our server each
+    /// time it changes, noticed while waiting for the next events.
+    pub fn set_latency_metrics<M: LatencyMetrics + 'static>(&mut self, metrics: M) {
+        self.latency_metrics = Some(Box::new(metrics));
+        self.reported_latency = None;
+    }
+
+    fn report_server_latency(&mut self) {
+        let latency = self.client.latency();
+        if latency == self.reported_latency {
+            return;
+        }
+        self.reported_latency = latency;
+        if let (Some(latency), Some(metrics)) = (latency, self.latency_metrics.as_mut()) {
+            metrics.server_latency(latency);
+        }
+    }
+
+    /// The milestones and errors of the last connections, to tell what
+    /// happened before a disconnection in a bug report.
+    pub fn audit_trail(&self) -> &tokio_xmpp::audit::AuditTrail {
+        self.client.audit_trail()
+    }
+
+    /// Replaces how nicknames get compared, for instance to detect
+    /// confusable ones.
+    pub fn set_nick_matcher(&mut self, matcher: NickMatcher) {
+        self.nick_matcher = matcher;
+    }
+
+    /// Fetch the vCard avatar of this room, which will be emitted as
+    /// `Event::RoomAvatarRetrieved` if it has one.
+    ///
+    /// Rooms advertise changes of their avatar in their presence, so this
+    /// is only needed to get it without having joined the room.
+    #[cfg(feature = "avatars")]
+    pub async fn fetch_room_avatar(&mut self, room: &BareJid) {
+        let iq = room_avatar::request_vcard(room).into();
+        let _ = self.send_stanza(iq).await;
+    }
+
+    /// Answer `request`, emitted as `Event::IqRequest`, with either a
+    /// result and its optional payload or an error.
+    pub async fn answer_iq(
+        &mut self,
+        request: &Iq,
+        response: Result<Option<Element>, StanzaError>,
+    ) {
+        let from = match request.from.clone() {
+            Some(from) => from,
+            None => match self.client.bound_jid() {
+                Some(jid) => jid.clone(),
+                None => return,
+            },
+        };
+        if !self.inbound.finish(&from, &request.id) {
+            warn!("Request {} from {} got answered already", request.id, from);
+            return;
+        }
+        let reply = match response {
+            Ok(payload) => Iq {
+                from: None,
+                to: request.from.clone(),
+                id: request.id.clone(),
+                payload: IqType::Result(payload),
+            }
+            .into(),
+            Err(error) => Iq::error_reply(request, error),
+        };
+        let _ = self.send_stanza(reply).await;
+    }
+
+    /// Whether `nick` designates ourself in `room`, once normalized.
+    pub fn is_own_nick(&self, room: &BareJid, nick: &str) -> bool {
+        match self.rooms.get(room) {
+            Some(own) => self.nick_matcher.matches(own, nick),
+            None => false,
+        }
     }
 
     pub async fn send_message(
@@ -237,36 +842,90 @@ impl Agent {
         message
             .bodies
             .insert(String::from(lang), Body(String::from(text)));
-        let _ = self.client.send_stanza(message.into()).await;
+        self.send_message_with_hints(message, HintPolicy::Auto)
+            .await;
     }
 
-    fn make_initial_presence(disco: &DiscoInfoResult, node: &str) -> Presence {
-        let caps_data = compute_disco(disco);
-        let hash = hash_caps(&caps_data, Algo::Sha_1).unwrap();
-        let caps = Caps::new(node, hash);
+    /// Send `text` as a chat message to `recipient`, in our preferred
+    /// language.
+    pub async fn send_chat_message(&mut self, recipient: Jid, text: &str) {
+        let lang = self.lang.first().cloned().unwrap_or_default();
+        self.send_message(recipient, MessageType::Chat, &lang, text)
+            .await;
+    }
 
+    /// Send a chat state (XEP-0085), which servers get asked not to store
+    /// nor copy to our other devices.
+    pub async fn send_chat_state(&mut self, recipient: Jid, type_: MessageType, state: ChatState) {
+        let mut message = Message::new(Some(recipient));
+        message.type_ = type_;
+        message.payloads.push(state.into());
+        self.send_message_with_hints(message, HintPolicy::Auto)
+            .await;
+    }
+
+    /// Send `message`, attaching the storage hints (XEP-0334) `policy`
+    /// asks for, `HintPolicy::Auto` deciding from its payloads.
+    pub async fn send_message_with_hints(&mut self, mut message: Message, policy: HintPolicy) {
+        apply_hints(&mut message, policy);
+        let _ = self.send_stanza(message.into()).await;
+    }
+
+    fn make_initial_presence(disco: &DiscoResponder) -> Presence {
         let mut presence = Presence::new(PresenceType::None);
-        presence.add_payload(caps);
+        presence.add_payload(disco.caps());
         presence
     }
 
     async fn handle_iq(&mut self, iq: Iq) -> Vec<Event> {
         let mut events = vec![];
+        let ready = self.bootstrap.handle_iq(&iq, &self.jid);
+        for (step, progress) in self.bootstrap.take_progress() {
+            events.push(Event::SyncProgress(step, progress));
+        }
+        if ready {
+            events.push(Event::SessionReady);
+        }
         let from = iq
             .from
             .clone()
             .unwrap_or_else(|| self.client.bound_jid().unwrap().clone());
+        let inbound_id = match iq.payload {
+            IqType::Get(_) | IqType::Set(_) => match self.inbound.begin(&from, &iq.id) {
+                Admission::Process => Some(iq.id.clone()),
+                Admission::Drop => return events,
+                Admission::Reply(reply) => {
+                    let _ = self.send_stanza((*reply).into()).await;
+                    return events;
+                }
+            },
+            IqType::Result(_) | IqType::Error(_) => None,
+        };
+        if self.iq_dispatcher.is_deferred(&iq) {
+            // Still being processed until answered.
+            events.push(Event::IqRequest(Box::new(iq)));
+            return events;
+        }
+        if self.iq_dispatcher.handles(&iq) {
+            if let Some(reply) = self.iq_dispatcher.dispatch(&iq) {
+                let _ = self.send_stanza(reply).await;
+            }
+            if let Some(id) = inbound_id {
+                self.inbound.finish(&from, &id);
+            }
+            return events;
+        }
         if let IqType::Get(payload) = iq.payload {
             if payload.is("query", ns::DISCO_INFO) {
                 let query = DiscoInfoQuery::try_from(payload);
                 match query {
                     Ok(query) => {
-                        let mut disco_info = self.disco.clone();
-                        disco_info.node = query.node;
-                        let iq = Iq::from_result(iq.id, Some(disco_info))
-                            .with_to(iq.from.unwrap())
-                            .into();
-                        let _ = self.client.send_stanza(iq).await;
+                        let reply = match self.disco.respond(query) {
+                            Ok(disco_info) => Iq::from_result(iq.id, Some(disco_info)),
+                            Err(error) => Iq::from_error(iq.id, error),
+                        };
+                        let reply = reply.with_to(iq.from.unwrap()).into();
+                        let _ = self.send_stanza(reply).await;
                     }
                     Err(err) => {
                         let error = StanzaError::new(
@@ -278,9 +937,21 @@ impl Agent {
                         let iq = Iq::from_error(iq.id, error)
                             .with_to(iq.from.unwrap())
                             .into();
-                        let _ = self.client.send_stanza(iq).await;
+                        let _ = self.send_stanza(iq).await;
                     }
                 }
+            } else if payload.is("query", ns::VERSION) && self.software_version.is_some() {
+                let software_version = self.software_version.clone().unwrap();
+                let iq = Iq::from_result(iq.id, Some(software_version))
+                    .with_to(iq.from.unwrap())
+                    .into();
+                let _ = self.send_stanza(iq).await;
+            } else if payload.is("time", ns::TIME) && self.timezone.is_some() {
+                let time = TimeResult::now(self.timezone.unwrap());
+                let iq = Iq::from_result(iq.id, Some(time))
+                    .with_to(iq.from.unwrap())
+                    .into();
+                let _ = self.send_stanza(iq).await;
             } else {
                 // We MUST answer unhandled get iqs with a service-unavailable error.
                 let error = StanzaError::new(
@@ -292,23 +963,70 @@ impl Agent {
                 let iq = Iq::from_error(iq.id, error)
                     .with_to(iq.from.unwrap())
                     .into();
-                let _ = self.client.send_stanza(iq).await;
+                let _ = self.send_stanza(iq).await;
             }
         } else if let IqType::Result(Some(payload)) = iq.payload {
             // TODO: move private iqs like this one somewhere else, for
             // security reasons.
             if payload.is("query", ns::ROSTER) && iq.from.is_none() {
                 let roster = Roster::try_from(payload).unwrap();
+                self.subscriptions.handle_roster(&roster);
                 for item in roster.items.into_iter() {
                     events.push(Event::ContactAdded(item));
                 }
             } else if payload.is("pubsub", ns::PUBSUB) {
-                let new_events = pubsub::handle_iq_result(&from, payload);
+                let new_events = pubsub::handle_iq_result(&from, payload, self);
                 events.extend(new_events);
+                if let Some(iq) = self.bookmarks.migration_request() {
+                    let _ = self.send_stanza(iq.into()).await;
+                }
+            } else if payload.is("query", ns::DISCO_INFO)
+                && iq.id == bookmarks::DISCO_ID
+                && is_same_bare(&from, &self.jid)
+            {
+                let info = DiscoInfoResult::try_from(payload).ok();
+                let iq = self.bookmarks.discovered(info.as_ref());
+                let _ = self.send_stanza(iq.into()).await;
+            } else if payload.is("query", ns::PRIVATE) && is_same_bare(&from, &self.jid) {
+                match PrivateQuery::try_from(payload)
+                    .and_then(|query| Storage::try_from(query.payload))
+                {
+                    Ok(storage) => {
+                        let (new_events, requests) = self.bookmarks.private_loaded(storage);
+                        for iq in requests {
+                            let _ = self.send_stanza(iq.into()).await;
+                        }
+                        events.extend(new_events);
+                    }
+                    Err(err) => warn!("Invalid legacy bookmarks: {}", err),
+                }
+            } else if payload.is("vCard", ns::VCARD) {
+                #[cfg(feature = "avatars")]
+                {
+                    // Only the answer of the room itself to our request, an
+                    // occupant could otherwise replace the avatar of the room.
+                    let room = BareJid::from(from.clone());
+                    if self.rooms.contains_key(&room)
+                        && from == Jid::Bare(room.clone())
+                        && iq.id == room_avatar::vcard_id(&room)
+                    {
+                        let dir = &self.avatar_dir;
+                        events.extend(room_avatar::handle_vcard_result(dir, &room, payload));
+                    }
+                }
             } else if payload.is("slot", ns::HTTP_UPLOAD) {
                 let new_events = handle_upload_result(&from, iq.id, payload, self).await;
                 events.extend(new_events);
             }
+        } else if let IqType::Error(error) = iq.payload {
+            if let Some(path) = self.take_upload(&from, &iq.id) {
+                events.push(Event::HttpUploadFailed(path, UploadFailure::from(error)));
+            } else if iq.id == bookmarks::DISCO_ID {
+                // Without knowing the features of the account, stick to the
+                // legacy storage which every server implements.
+                let iq = self.bookmarks.discovered(None);
+                let _ = self.send_stanza(iq.into()).await;
+            }
         } else if let IqType::Set(_) = iq.payload {
             // We MUST answer unhandled set iqs with a service-unavailable error.
             let error = StanzaError::new(
@@ -320,18 +1038,30 @@ impl Agent {
             let iq = Iq::from_error(iq.id, error)
                 .with_to(iq.from.unwrap())
                 .into();
-            let _ = self.client.send_stanza(iq).await;
+            let _ = self.send_stanza(iq).await;
         }
 
+        if let Some(id) = inbound_id {
+            self.inbound.finish(&from, &id);
+        }
         events
     }
 
     async fn handle_message(&mut self, message: Message) -> Vec<Event> {
         let mut events = vec![];
         let from = message.from.clone().unwrap();
+        // The deduplication window is kept across reconnections, so that
+        // replayed or archived copies of a message get dropped.
+        if !self.dedup.check(&message, &self.jid) {
+            debug!("Dropping duplicate message from {}", from);
+            return events;
+        }
         let langs: Vec<&str> = self.lang.iter().map(String::as_str).collect();
-        match message.get_best_body(langs) {
-            Some((_lang, body)) => match message.type_ {
+        let best_body = message
+            .get_best_body(langs)
+            .map(|(_lang, body)| body.clone());
+        if let Some(ref body) = best_body {
+            match message.type_ {
                 MessageType::Groupchat => {
                     let event = Event::RoomMessage(
                         from.clone().into(),
@@ -345,90 +1075,186 @@ impl Agent {
                     events.push(event)
                 }
                 _ => (),
-            },
-            None => (),
+            }
         }
+        let mut extensions = vec![];
         for child in message.payloads {
             if child.is("event", ns::PUBSUB_EVENT) {
                 let new_events = pubsub::handle_event(&from, child, self).await;
                 events.extend(new_events);
+            } else if let Some(payload) = self.parse_extension_payload(&from, child) {
+                extensions.push(payload);
             }
         }
+        if best_body.is_some() || !extensions.is_empty() {
+            events.push(Event::MessageReceived(ReceivedMessage {
+                from,
+                type_: message.type_,
+                body: best_body,
+                extensions,
+            }));
+        }
 
         events
     }
 
     async fn handle_presence(&mut self, presence: Presence) -> Vec<Event> {
         let mut events = vec![];
-        let from: BareJid = match presence.from.clone().unwrap() {
+        let full_from = presence.from.clone().unwrap();
+        let from: BareJid = match full_from.clone() {
             Jid::Full(FullJid { node, domain, .. }) => BareJid { node, domain },
             Jid::Bare(bare) => bare,
         };
+        if let Some(request) = self.subscriptions.handle_presence(&presence) {
+            events.push(Event::SubscriptionRequest(request.clone()));
+        }
+        if !self.rooms.contains_key(&from) {
+            if let Some(change) = PresenceChange::from_presence(&presence, &self.lang) {
+                events.push(Event::PresenceChanged(full_from.clone(), change));
+            }
+        }
+        // Servers not flagging the self-presence (status 110) still send it
+        // from our nick.
+        let own = match full_from {
+            Jid::Full(ref occupant) => {
+                presence.type_ == PresenceType::None && self.is_own_nick(&from, &occupant.resource)
+            }
+            Jid::Bare(_) => false,
+        };
+        if let Jid::Full(ref occupant) = full_from {
+            if self.rooms.contains_key(&from) {
+                let occupants = self.occupant_ids.entry(from.clone()).or_default();
+                match (presence.type_.clone(), presence.occupant_id()) {
+                    (PresenceType::None, Some(occupant_id)) => {
+                        occupants.insert(occupant.resource.clone(), occupant_id.id);
+                    }
+                    _ => {
+                        occupants.remove(&occupant.resource);
+                    }
+                }
+            }
+        }
         for payload in presence.payloads.into_iter() {
+            if self.extensions.handles(&payload) {
+                events.extend(self.parse_extension(&full_from, payload));
+                continue;
+            }
+            // Only the room itself advertises its avatar, occupants do so
+            // from their own full JID.
+            #[cfg(feature = "avatars")]
+            {
+                if payload.is("x", ns::VCARD_UPDATE)
+                    && matches!(full_from, Jid::Bare(_))
+                    && self.rooms.contains_key(&from)
+                {
+                    let new_events = room_avatar::handle_vcard_update(&from, payload, self).await;
+                    events.extend(new_events);
+                    continue;
+                }
+            }
             let muc_user = match MucUser::try_from(payload) {
                 Ok(muc_user) => muc_user,
                 _ => continue,
             };
-            for status in muc_user.status.into_iter() {
-                if status == Status::SelfPresence {
-                    events.push(Event::RoomJoined(from.clone()));
-                    break;
-                }
+            if muc_user.is_self_presence() || own {
+                events.push(Event::RoomJoined(from.clone()));
             }
         }
 
         events
     }
 
+    /// Parse `payload` with its extension parser, if any.
+    fn parse_extension(&self, from: &Jid, payload: Element) -> Option<Event> {
+        self.parse_extension_payload(from, payload)
+            .map(|payload| Event::Extension(from.clone(), payload))
+    }
+
+    fn parse_extension_payload(&self, from: &Jid, payload: Element) -> Option<ExtensionPayload> {
+        match self.extensions.parse(payload)? {
+            Ok(payload) => Some(payload),
+            Err(err) => {
+                warn!("Invalid extension payload from {}: {}", from, err);
+                None
+            }
+        }
+    }
+
     pub async fn wait_for_events(&mut self) -> Option<Vec<Event>> {
-        if let Some(event) = self.client.next().await {
+        let event = match self.deferred.pop_front() {
+            Some(event) => Some(event),
+            None => self.client.next().await,
+        };
+        if let Some(event) = event {
             let mut events = Vec::new();
 
             match event {
                 TokioXmppEvent::Online { resumed: false, .. } => {
-                    let presence = Self::make_initial_presence(&self.disco, &self.node).into();
-                    let _ = self.client.send_stanza(presence).await;
-                    events.push(Event::Online);
-                    // TODO: only send this when the ContactList feature is enabled.
-                    let iq = Iq::from_get(
-                        "roster",
-                        Roster {
-                            ver: None,
-                            items: vec![],
-                        },
-                    )
-                    .into();
-                    let _ = self.client.send_stanza(iq).await;
+                    // Without resumption, we aren’t in any room anymore.
+                    self.rooms.clear();
+                    self.occupant_ids.clear();
+                    let presence = Self::make_initial_presence(&self.disco);
+                    let stream_management = self.client.stream_management().is_some();
+                    // TODO: only fetch the roster when the ContactList feature is enabled.
+                    let mut stanzas = self.bootstrap.start(presence, stream_management);
                     // TODO: only send this when the JoinRooms feature is enabled.
-                    let iq =
-                        Iq::from_get("bookmarks", PubSub::Items(Items::new(ns::BOOKMARKS2))).into();
-                    let _ = self.client.send_stanza(iq).await;
+                    stanzas.push(BookmarkManager::discover_request(&self.jid).into());
+                    // Flush the whole burst at once.
+                    let _ = self
+                        .client
+                        .send_all(stanzas.into_iter().map(Packet::Stanza))
+                        .await;
+                    events.push(Event::Online);
+                    if self.bootstrap.is_ready() {
+                        events.push(Event::SessionReady);
+                    }
                 }
                 TokioXmppEvent::Online { resumed: true, .. } => {}
                 TokioXmppEvent::Disconnected(_) => {
                     events.push(Event::Disconnected);
                 }
                 TokioXmppEvent::Stanza(elem) => {
-                    if elem.is("iq", "jabber:client") {
+                    let id = self.correlator.begin();
+                    debug!(
+                        "{}: received {} from {:?}",
+                        id,
+                        elem.name(),
+                        elem.attr("from")
+                    );
+                    let routed = self.router.route(&elem);
+                    for reply in routed.replies {
+                        let _ = self.send_stanza(reply).await;
+                    }
+                    if routed.consumed {
+                        debug!("{}: consumed by a handler", id);
+                    } else if elem.is("iq", ns::JABBER_CLIENT) {
                         let iq = Iq::try_from(elem).unwrap();
                         let new_events = self.handle_iq(iq).await;
                         events.extend(new_events);
-                    } else if elem.is("message", "jabber:client") {
+                    } else if elem.is("message", ns::JABBER_CLIENT) {
+                        if self.bootstrap.handle_mam_message(&elem) {
+                            for (step, progress) in self.bootstrap.take_progress() {
+                                events.push(Event::SyncProgress(step, progress));
+                            }
+                        }
                         let message = Message::try_from(elem).unwrap();
                         let new_events = self.handle_message(message).await;
                         events.extend(new_events);
-                    } else if elem.is("presence", "jabber:client") {
+                    } else if elem.is("presence", ns::JABBER_CLIENT) {
                         let presence = Presence::try_from(elem).unwrap();
                         let new_events = self.handle_presence(presence).await;
                         events.extend(new_events);
-                    } else if elem.is("error", "http://etherx.jabber.org/streams") {
+                    } else if elem.is("error", ns::STREAM) {
                         println!("Received a fatal stream error: {}", String::from(&elem));
                     } else {
                         panic!("Unknown stanza: {}", String::from(&elem));
                     }
+                    self.correlator.end();
                 }
             }
 
+            self.report_server_latency();
+            self.autojoin(&events).await;
             Some(events)
         } else {
             None
@@ -448,7 +1274,17 @@ impl Agent {
         let request = Iq::from_get("upload1", slot_request).with_to(to.clone());
         self.uploads
             .push((String::from("upload1"), to, path.to_path_buf()));
-        self.client.send_stanza(request.into()).await.unwrap();
+        self.send_stanza(request.into()).await.unwrap();
+    }
+
+    /// Forgets the pending upload requested from `from` with this id,
+    /// returning the path of its file.
+    fn take_upload(&mut self, from: &Jid, id: &str) -> Option<PathBuf> {
+        let index = self
+            .uploads
+            .iter()
+            .position(|(upload_id, to, _)| to == from && upload_id == id)?;
+        Some(self.uploads.remove(index).2)
     }
 }
 
@@ -458,17 +1294,7 @@ async fn handle_upload_result(
     elem: Element,
     agent: &mut Agent,
 ) -> impl IntoIterator<Item = Event> {
-    let mut res: Option<(usize, PathBuf)> = None;
-
-    for (i, (id, to, filepath)) in agent.uploads.iter().enumerate() {
-        if to == from && id == &iqid {
-            res = Some((i, filepath.to_path_buf()));
-            break;
-        }
-    }
-
-    if let Some((index, file)) = res {
-        agent.uploads.remove(index);
+    if let Some(file) = agent.take_upload(from, &iqid) {
         let slot = SlotResult::try_from(elem).unwrap();
 
         let mut headers = ReqwestHeaderMap::new();
@@ -501,8 +1327,13 @@ async fn handle_upload_result(
 
 #[cfg(test)]
 mod tests {
-    use super::{Agent, ClientBuilder, ClientFeature, ClientType, Event};
+    use super::{Agent, ClientBuilder, ClientFeature, ClientType, CollisionPolicy, Event};
+    use std::convert::TryFrom;
     use tokio_xmpp::AsyncClient as TokioXmppClient;
+    use xmpp_parsers::iq::Iq;
+    use xmpp_parsers::message::Message;
+    use xmpp_parsers::mood::{Mood, MoodEnum};
+    use xmpp_parsers::{BareJid, Element};
 
     #[tokio::test]
     async fn test_simple() {
@@ -527,4 +1358,295 @@ mod tests {
             break;
         }
     }
+
+    #[tokio::test]
+    async fn test_rich_presence() {
+        let client = TokioXmppClient::new("foo@bar", "meh").unwrap();
+        let mut agent = ClientBuilder::new("foo@bar", "meh")
+            .enable_feature(ClientFeature::RichPresence)
+            .build_impl(client)
+            .unwrap();
+        assert!(agent
+            .disco()
+            .has_feature("http://jabber.org/protocol/mood+notify"));
+
+        let message: Element = "<message xmlns='jabber:client' from='juliet@capulet.lit' to='foo@bar'><event xmlns='http://jabber.org/protocol/pubsub#event'><items node='http://jabber.org/protocol/mood'><item id='current'><mood xmlns='http://jabber.org/protocol/mood'><happy/></mood></item></items></event></message>"
+            .parse()
+            .unwrap();
+        let events = agent
+            .handle_message(Message::try_from(message).unwrap())
+            .await;
+        assert_eq!(events.len(), 1);
+        match &events[0] {
+            Event::MoodChanged(jid, mood) => {
+                assert_eq!(jid, &BareJid::new("juliet", "capulet.lit"));
+                assert_eq!(mood, &Mood::new(MoodEnum::Happy));
+            }
+            event => panic!("Unexpected event: {:?}", event),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dedup() {
+        let client = TokioXmppClient::new("foo@bar", "meh").unwrap();
+        let mut agent = ClientBuilder::new("foo@bar", "meh")
+            .build_impl(client)
+            .unwrap();
+
+        let message: Element = "<message xmlns='jabber:client' from='juliet@capulet.lit/balcony' to='foo@bar' type='chat'><body>Hi!</body><origin-id xmlns='urn:xmpp:sid:0' id='abc'/></message>"
+            .parse()
+            .unwrap();
+        let events = agent
+            .handle_message(Message::try_from(message.clone()).unwrap())
+            .await;
+        assert_eq!(events.len(), 2);
+
+        // The same message replayed after a resumption.
+        let events = agent
+            .handle_message(Message::try_from(message).unwrap())
+            .await;
+        assert!(events.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_semantic_events() {
+        use std::str::FromStr;
+        use xmpp_parsers::message::{Body, MessageType};
+        use xmpp_parsers::presence::{Presence, Show};
+        use xmpp_parsers::Jid;
+
+        #[derive(Debug, PartialEq)]
+        struct Score(u32);
+
+        impl TryFrom<Element> for Score {
+            type Error = xmpp_parsers::Error;
+
+            fn try_from(elem: Element) -> Result<Score, xmpp_parsers::Error> {
+                elem.text()
+                    .parse()
+                    .map(Score)
+                    .map_err(|_| xmpp_parsers::Error::ParseError("Invalid score."))
+            }
+        }
+
+        let client = TokioXmppClient::new("foo@bar", "meh").unwrap();
+        let mut agent = ClientBuilder::new("foo@bar", "meh")
+            .register_extension::<Score>("score", "urn:example:game")
+            .build_impl(client)
+            .unwrap();
+
+        let message: Element = "<message xmlns='jabber:client' from='juliet@capulet.lit/balcony' to='foo@bar' type='chat'><body>Hi!</body><score xmlns='urn:example:game'>42</score></message>"
+            .parse()
+            .unwrap();
+        let events = agent
+            .handle_message(Message::try_from(message).unwrap())
+            .await;
+        assert_eq!(events.len(), 2);
+        match &events[1] {
+            Event::MessageReceived(message) => {
+                assert_eq!(
+                    message.from,
+                    Jid::from_str("juliet@capulet.lit/balcony").unwrap()
+                );
+                assert_eq!(message.type_, MessageType::Chat);
+                assert_eq!(message.body, Some(Body(String::from("Hi!"))));
+                assert_eq!(message.extension::<Score>(), Some(&Score(42)));
+            }
+            event => panic!("Unexpected event: {:?}", event),
+        }
+
+        let presence: Element = "<presence xmlns='jabber:client' from='juliet@capulet.lit/balcony' to='foo@bar'><show>dnd</show></presence>"
+            .parse()
+            .unwrap();
+        let events = agent
+            .handle_presence(Presence::try_from(presence).unwrap())
+            .await;
+        match &events[..] {
+            [Event::PresenceChanged(jid, change)] => {
+                assert_eq!(jid, &Jid::from_str("juliet@capulet.lit/balcony").unwrap());
+                assert!(change.available);
+                assert_eq!(change.show, Some(Show::Dnd));
+            }
+            events => panic!("Unexpected events: {:?}", events),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_occupant_id() {
+        use xmpp_parsers::presence::Presence;
+
+        let client = TokioXmppClient::new("foo@bar", "meh").unwrap();
+        let mut agent = ClientBuilder::new("foo@bar", "meh")
+            .build_impl(client)
+            .unwrap();
+        let room = BareJid::new("coven", "chat.shakespeare.lit");
+        agent.rooms.insert(room.clone(), String::from("thirdwitch"));
+
+        let presence: Element = "<presence xmlns='jabber:client' from='coven@chat.shakespeare.lit/firstwitch' to='foo@bar'><x xmlns='http://jabber.org/protocol/muc#user'><item affiliation='none' role='participant'/></x><occupant-id xmlns='urn:xmpp:occupant-id:0' id='dd72603deec90a38ba552f7c68cbcc61bca202cd'/></presence>"
+            .parse()
+            .unwrap();
+        let events = agent
+            .handle_presence(Presence::try_from(presence).unwrap())
+            .await;
+        assert!(events.is_empty());
+        assert_eq!(
+            agent.occupant_id(&room, "firstwitch"),
+            Some("dd72603deec90a38ba552f7c68cbcc61bca202cd")
+        );
+
+        let presence: Element = "<presence xmlns='jabber:client' from='coven@chat.shakespeare.lit/thirdwitch' to='foo@bar'><x xmlns='http://jabber.org/protocol/muc#user'><item affiliation='member' role='participant'/><status code='110'/></x></presence>"
+            .parse()
+            .unwrap();
+        let events = agent
+            .handle_presence(Presence::try_from(presence).unwrap())
+            .await;
+        assert!(matches!(events[..], [Event::RoomJoined(ref jid)] if jid == &room));
+
+        // Without status 110, our nick still tells it is ours, whatever its
+        // case.
+        let presence: Element = "<presence xmlns='jabber:client' from='coven@chat.shakespeare.lit/ThirdWitch' to='foo@bar'><x xmlns='http://jabber.org/protocol/muc#user'><item affiliation='member' role='participant'/></x></presence>"
+            .parse()
+            .unwrap();
+        let events = agent
+            .handle_presence(Presence::try_from(presence).unwrap())
+            .await;
+        assert!(matches!(events[..], [Event::RoomJoined(ref jid)] if jid == &room));
+
+        let presence: Element = "<presence xmlns='jabber:client' from='coven@chat.shakespeare.lit/firstwitch' to='foo@bar' type='unavailable'><x xmlns='http://jabber.org/protocol/muc#user'><item affiliation='none' role='none'/></x></presence>"
+            .parse()
+            .unwrap();
+        agent
+            .handle_presence(Presence::try_from(presence).unwrap())
+            .await;
+        assert_eq!(agent.occupant_id(&room, "firstwitch"), None);
+    }
+
+    #[cfg(feature = "avatars")]
+    #[tokio::test]
+    async fn test_room_avatar() {
+        use xmpp_parsers::iq::Iq;
+        use xmpp_parsers::presence::Presence;
+
+        let dir = std::env::temp_dir().join(format!("xmpp-rs-room-avatar-{}", std::process::id()));
+        let dir = dir.to_str().unwrap();
+        let client = TokioXmppClient::new("foo@bar", "meh").unwrap();
+        let mut agent = ClientBuilder::new("foo@bar", "meh")
+            .set_avatar_dir(dir)
+            .build_impl(client)
+            .unwrap();
+        let room = BareJid::new("coven", "chat.shakespeare.lit");
+        agent.rooms.insert(room.clone(), String::from("thirdwitch"));
+
+        // An occupant answering in the name of the room, through the MUC.
+        let iq: Element = "<iq xmlns='jabber:client' from='coven@chat.shakespeare.lit/firstwitch' to='foo@bar' id='room-vcard-coven@chat.shakespeare.lit' type='result'><vCard xmlns='vcard-temp'><PHOTO><TYPE>image/png</TYPE><BINVAL>iVBORw0KGgo=</BINVAL></PHOTO></vCard></iq>"
+            .parse()
+            .unwrap();
+        let events = agent.handle_iq(Iq::try_from(iq).unwrap()).await;
+        assert!(events.is_empty());
+
+        // The room, but not answering our request.
+        let iq: Element = "<iq xmlns='jabber:client' from='coven@chat.shakespeare.lit' to='foo@bar' id='other' type='result'><vCard xmlns='vcard-temp'><PHOTO><TYPE>image/png</TYPE><BINVAL>iVBORw0KGgo=</BINVAL></PHOTO></vCard></iq>"
+            .parse()
+            .unwrap();
+        let events = agent.handle_iq(Iq::try_from(iq).unwrap()).await;
+        assert!(events.is_empty());
+        assert!(!std::path::Path::new(dir).exists());
+
+        let iq: Element = "<iq xmlns='jabber:client' from='coven@chat.shakespeare.lit' to='foo@bar' id='room-vcard-coven@chat.shakespeare.lit' type='result'><vCard xmlns='vcard-temp'><PHOTO><TYPE>image/png</TYPE><BINVAL>iVBORw0KGgo=</BINVAL></PHOTO></vCard></iq>"
+            .parse()
+            .unwrap();
+        let events = agent.handle_iq(Iq::try_from(iq).unwrap()).await;
+        let path = format!(
+            "{}/coven@chat.shakespeare.lit/4caece539b039b16e16206ea2478f8c5ffb2ca05",
+            dir
+        );
+        assert_eq!(events.len(), 1);
+        match &events[0] {
+            Event::RoomAvatarRetrieved(jid, filename) => {
+                assert_eq!(jid, &room);
+                assert_eq!(filename, &path);
+            }
+            event => panic!("Unexpected event: {:?}", event),
+        }
+        assert_eq!(std::fs::read(&path).unwrap(), b"\x89PNG\r\n\x1a\n");
+
+        // The room advertising the avatar we already have.
+        let presence: Element = "<presence xmlns='jabber:client' from='coven@chat.shakespeare.lit' to='foo@bar'><x xmlns='vcard-temp:x:update'><photo>4caece539b039b16e16206ea2478f8c5ffb2ca05</photo></x></presence>"
+            .parse()
+            .unwrap();
+        let events = agent
+            .handle_presence(Presence::try_from(presence).unwrap())
+            .await;
+        assert_eq!(events.len(), 1);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_deferred_iq() {
+        let mut client = TokioXmppClient::new("foo@bar", "meh").unwrap();
+        // Queue the replies instead of waiting for a connection.
+        client.set_outbox(tokio_xmpp::outbox::MemoryOutboxStorage::new());
+        let mut agent = ClientBuilder::new("foo@bar", "meh")
+            .defer_iq_requests("urn:example:slow")
+            .set_iq_collision_policy(CollisionPolicy::Drop)
+            .build_impl(client)
+            .unwrap();
+        let iq: Element = "<iq xmlns='jabber:client' from='romeo@montague.lit/orchard' id='s1' type='get'><slow xmlns='urn:example:slow'/></iq>"
+            .parse()
+            .unwrap();
+        let iq = Iq::try_from(iq).unwrap();
+
+        let events = agent.handle_iq(iq.clone()).await;
+        let request = match &events[..] {
+            [Event::IqRequest(request)] => request.clone(),
+            events => panic!("Unexpected events: {:?}", events),
+        };
+        assert_eq!(agent.inbound.len(), 1);
+
+        // The same id while the first one is still being processed.
+        assert!(agent.handle_iq(iq.clone()).await.is_empty());
+
+        agent.answer_iq(&request, Ok(None)).await;
+        assert!(agent.inbound.is_empty());
+        assert_eq!(agent.client.outbox_len().unwrap(), 1);
+
+        // Answering twice sends a single reply.
+        agent.answer_iq(&request, Ok(None)).await;
+        assert_eq!(agent.client.outbox_len().unwrap(), 1);
+
+        let events = agent.handle_iq(iq).await;
+        assert!(matches!(events[..], [Event::IqRequest(_)]));
+    }
+
+    #[tokio::test]
+    async fn test_measure_latency_disconnected() {
+        use crate::iq::IqError;
+        use crate::ping::{LatencyError, LatencyMetrics};
+        use std::cell::Cell;
+        use std::rc::Rc;
+        use std::time::Duration;
+        use xmpp_parsers::Jid;
+
+        struct Counter(Rc<Cell<usize>>);
+
+        impl LatencyMetrics for Counter {
+            fn latency_measured(&mut self, _jid: &Jid, _rtt: Duration) {
+                self.0.set(self.0.get() + 1);
+            }
+        }
+
+        let client = TokioXmppClient::new("foo@bar", "meh").unwrap();
+        let mut agent = ClientBuilder::new("foo@bar", "meh")
+            .build_impl(client)
+            .unwrap();
+        let measured = Rc::new(Cell::new(0));
+        agent.set_latency_metrics(Counter(measured.clone()));
+
+        let jid: Jid = "bar".parse().unwrap();
+        match agent.measure_latency(jid, Duration::from_secs(1)).await {
+            Err(LatencyError::Iq(IqError::Transport(crate::Error::Disconnected))) => (),
+            other => panic!("Unexpected {:?}", other),
+        }
+        assert_eq!(measured.get(), 0);
+    }
 }
@@ -0,0 +1,116 @@
+// Copyright (c) 2022 Emmanuel Gil Peyrot <linkmauve@linkmauve.fr>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Normalization of MUC nicknames and roster names, loosely following the
+//! PRECIS Nickname profile (RFC 8266).
+
+use unicode_normalization::UnicodeNormalization;
+
+/// Maps every kind of space to an ASCII space, removes leading and trailing
+/// spaces, collapses the remaining ones, and applies NFKC.
+///
+/// This is the form to use when sending or displaying a nickname.
+pub fn normalize_nick(nick: &str) -> String {
+    let mut normalized = String::with_capacity(nick.len());
+    for word in nick
+        .split(char::is_whitespace)
+        .filter(|word| !word.is_empty())
+    {
+        if !normalized.is_empty() {
+            normalized.push(' ');
+        }
+        normalized.push_str(word);
+    }
+    normalized.nfkc().collect()
+}
+
+/// Same as [`normalize_nick`], but also case folded, for comparing two
+/// nicknames.
+pub fn nick_comparison_key(nick: &str) -> String {
+    let lowercase = normalize_nick(nick).to_lowercase();
+    // Lowercasing may have produced unnormalized sequences again.
+    lowercase.nfkc().collect()
+}
+
+/// A hook turning a nickname into its “skeleton”, so that two visually
+/// confusable nicknames (for instance using Latin and Cyrillic letters)
+/// compare equal.
+pub trait ConfusableSkeleton {
+    /// The skeleton of `nick`, already turned into its comparison key,
+    /// mapping every confusable character to a single one of them.
+    fn skeleton(&self, nick: &str) -> String;
+}
+
+/// Compares nicknames through their comparison key, and their skeleton if
+/// a confusable detector has been set.
+#[derive(Default)]
+pub struct NickMatcher {
+    confusables: Option<Box<dyn ConfusableSkeleton>>,
+}
+
+impl NickMatcher {
+    /// A matcher only comparing the comparison keys.
+    pub fn new() -> NickMatcher {
+        NickMatcher::default()
+    }
+
+    /// Also compare the skeletons given by `confusables`.
+    pub fn with_confusables(mut self, confusables: Box<dyn ConfusableSkeleton>) -> NickMatcher {
+        self.confusables = Some(confusables);
+        self
+    }
+
+    /// What `nick` gets compared by: its comparison key, or the skeleton
+    /// of it with a confusable detector.
+    pub fn key(&self, nick: &str) -> String {
+        let key = nick_comparison_key(nick);
+        match self.confusables {
+            Some(ref confusables) => confusables.skeleton(&key),
+            None => key,
+        }
+    }
+
+    /// Whether `a` and `b` designate the same occupant.
+    pub fn matches(&self, a: &str, b: &str) -> bool {
+        self.key(a) == self.key(b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize_nick("  Juliet  "), "Juliet");
+        assert_eq!(
+            normalize_nick("Juliet\u{a0}\u{2003}Capulet"),
+            "Juliet Capulet"
+        );
+        // Fullwidth letters are mapped to their narrow form.
+        assert_eq!(normalize_nick("Ｊｕｌｉｅｔ"), "Juliet");
+        // Composed and decomposed forms end up the same.
+        assert_eq!(normalize_nick("Ame\u{301}lie"), "Amélie");
+    }
+
+    #[test]
+    fn test_matcher() {
+        let matcher = NickMatcher::new();
+        assert!(matcher.matches("Juliet", " juliet"));
+        assert!(matcher.matches("ΣΊΣΥΦΟΣ", "σίσυφος"));
+        assert!(!matcher.matches("Juliet", "Romeo"));
+
+        struct Cyrillic;
+        impl ConfusableSkeleton for Cyrillic {
+            fn skeleton(&self, nick: &str) -> String {
+                nick.replace('а', "a").replace('е', "e")
+            }
+        }
+        assert!(!matcher.matches("Jаne", "Jane"));
+        let matcher = NickMatcher::new().with_confusables(Box::new(Cyrillic));
+        assert!(matcher.matches("Jаne", "Jane"));
+    }
+}
@@ -0,0 +1,366 @@
+// Copyright (c) 2022 Emmanuel Gil Peyrot <linkmauve@linkmauve.fr>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Paging through big result sets with Result Set Management (XEP-0059),
+//! as used by MAM, disco#items or pubsub.
+
+use futures::future::LocalBoxFuture;
+use xmpp_parsers::rsm::{SetQuery, SetResult};
+
+/// In which order to go through the pages.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    /// From the first page to the last one.
+    Forward,
+
+    /// From the last page to the first one.
+    Backward,
+}
+
+/// How far along a result set we are, for instance to render a progress
+/// bar.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Progress {
+    /// Number of items fetched so far.
+    pub fetched: usize,
+
+    /// The size of the full set, if the responder told it.
+    pub count: Option<usize>,
+
+    /// Number of pages fetched so far.
+    pub pages: usize,
+
+    /// Size of the items fetched so far, in bytes of serialised XML.
+    pub bytes: usize,
+}
+
+impl Progress {
+    /// An estimate of the number of pages left, from the average size of
+    /// the pages fetched so far, if the size of the set is known.
+    pub fn remaining_pages(&self) -> Option<usize> {
+        let count = self.count?;
+        let left = count.saturating_sub(self.fetched);
+        if left == 0 {
+            return Some(0);
+        }
+        if self.fetched == 0 || self.pages == 0 {
+            return None;
+        }
+        let per_page = self.fetched.div_ceil(self.pages);
+        Some(left.div_ceil(per_page))
+    }
+
+    /// The fraction of the set fetched so far, between 0 and 1, if the
+    /// size of the set is known.
+    pub fn fraction(&self) -> Option<f32> {
+        match self.count? {
+            0 => Some(1.),
+            count => Some((self.fetched.min(count) as f32) / (count as f32)),
+        }
+    }
+}
+
+/// Keeps track of where we are in a result set.
+///
+/// Call [`next_query`](#method.next_query) to get the query for the next
+/// page, send it, then pass the resulting set to
+/// [`handle_result`](#method.handle_result), until there are no more
+/// queries.
+#[derive(Debug, Clone)]
+pub struct Pager {
+    max: Option<usize>,
+    direction: Direction,
+    next: Option<SetQuery>,
+    fetched: usize,
+    count: Option<usize>,
+    pages: usize,
+    bytes: usize,
+}
+
+impl Pager {
+    /// Go through the set from its start, `max` items at a time.
+    pub fn forward(max: Option<usize>) -> Pager {
+        Pager {
+            max,
+            direction: Direction::Forward,
+            next: Some(SetQuery::first_page(max)),
+            fetched: 0,
+            count: None,
+            pages: 0,
+            bytes: 0,
+        }
+    }
+
+    /// Go through the set from its end, `max` items at a time.
+    pub fn backward(max: Option<usize>) -> Pager {
+        Pager {
+            max,
+            direction: Direction::Backward,
+            next: Some(SetQuery::last_page(max)),
+            fetched: 0,
+            count: None,
+            pages: 0,
+            bytes: 0,
+        }
+    }
+
+    /// The query for the next page, or `None` once the whole set got
+    /// fetched.
+    pub fn next_query(&mut self) -> Option<SetQuery> {
+        self.next.take()
+    }
+
+    /// Handle the result of the last query, which returned `items` items.
+    pub fn handle_result(&mut self, result: &SetResult, items: usize) {
+        self.handle_page(result, items, 0);
+    }
+
+    /// Handle the result of the last query, which returned `items` items
+    /// weighing `bytes` bytes, to account for them in the
+    /// [`progress`](#method.progress).
+    pub fn handle_page(&mut self, result: &SetResult, items: usize, bytes: usize) {
+        self.fetched += items;
+        self.pages += 1;
+        self.bytes += bytes;
+        if result.count.is_some() {
+            self.count = result.count;
+        }
+        // A responder may return fewer items than asked for before the end
+        // of the set, so only its position in the set tells the end.
+        let complete = match (self.direction, result.first_index, self.count) {
+            (Direction::Forward, Some(index), Some(count)) => index + items >= count,
+            (Direction::Backward, Some(index), _) => index == 0,
+            _ => false,
+        };
+        self.next = if items == 0 || complete {
+            None
+        } else {
+            match self.direction {
+                Direction::Forward => result.next_page(self.max),
+                Direction::Backward => result.previous_page(self.max),
+            }
+        };
+    }
+
+    /// Number of items fetched so far.
+    pub fn fetched(&self) -> usize {
+        self.fetched
+    }
+
+    /// The size of the full set, if the responder told it.
+    pub fn count(&self) -> Option<usize> {
+        self.count
+    }
+
+    /// How far along the set we are.
+    pub fn progress(&self) -> Progress {
+        Progress {
+            fetched: self.fetched,
+            count: self.count,
+            pages: self.pages,
+            bytes: self.bytes,
+        }
+    }
+}
+
+/// Fetch every page of a set through `fetch`, which sends the query to
+/// `requester` and resolves to the items of the page along with its
+/// result set, returning all of the items in the order they got fetched.
+pub async fn fetch_all<R, T, E, F>(
+    requester: &mut R,
+    mut pager: Pager,
+    mut fetch: F,
+) -> Result<Vec<T>, E>
+where
+    F: for<'a> FnMut(&'a mut R, SetQuery) -> LocalBoxFuture<'a, Result<(Vec<T>, SetResult), E>>,
+{
+    let mut all = Vec::new();
+    while let Some(query) = pager.next_query() {
+        let (items, result) = fetch(requester, query).await?;
+        pager.handle_result(&result, items.len());
+        all.extend(items);
+    }
+    Ok(all)
+}
+
+/// Like [`fetch_all`], with `fetch` also resolving to the size in bytes of
+/// the page, and calling `on_progress` after each page.
+pub async fn fetch_all_with_progress<R, T, E, F, P>(
+    requester: &mut R,
+    mut pager: Pager,
+    mut fetch: F,
+    mut on_progress: P,
+) -> Result<Vec<T>, E>
+where
+    F: for<'a> FnMut(
+        &'a mut R,
+        SetQuery,
+    ) -> LocalBoxFuture<'a, Result<(Vec<T>, SetResult, usize), E>>,
+    P: FnMut(Progress),
+{
+    let mut all = Vec::new();
+    while let Some(query) = pager.next_query() {
+        let (items, result, bytes) = fetch(requester, query).await?;
+        pager.handle_page(&result, items.len(), bytes);
+        on_progress(pager.progress());
+        all.extend(items);
+    }
+    Ok(all)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future::FutureExt;
+
+    /// A set of single letter items, served `max` at a time.
+    struct Letters {
+        items: Vec<String>,
+        queries: Vec<SetQuery>,
+    }
+
+    impl Letters {
+        fn new(count: usize) -> Letters {
+            Letters {
+                items: (b'a'..=b'z')
+                    .take(count)
+                    .map(|c| String::from(c as char))
+                    .collect(),
+                queries: Vec::new(),
+            }
+        }
+
+        fn page(&mut self, query: SetQuery) -> (Vec<String>, SetResult) {
+            self.queries.push(query.clone());
+            let max = query.max.unwrap_or(self.items.len());
+            let position = |uid: &str| self.items.iter().position(|item| item == uid).unwrap();
+            let (start, end) = match (&query.after, &query.before) {
+                (Some(after), _) => {
+                    let start = position(after) + 1;
+                    (start, (start + max).min(self.items.len()))
+                }
+                (None, Some(before)) if before.is_empty() => {
+                    (self.items.len().saturating_sub(max), self.items.len())
+                }
+                (None, Some(before)) => {
+                    let end = position(before);
+                    (end.saturating_sub(max), end)
+                }
+                (None, None) => (0, max.min(self.items.len())),
+            };
+            let page: Vec<String> = self.items[start..end].to_vec();
+            let result = SetResult {
+                first: page.first().cloned(),
+                first_index: page.first().map(|_| start),
+                last: page.last().cloned(),
+                count: Some(self.items.len()),
+            };
+            (page, result)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_forward() {
+        let mut letters = Letters::new(7);
+        let all = fetch_all(&mut letters, Pager::forward(Some(3)), |letters, query| {
+            futures::future::ready(Ok::<_, ()>(letters.page(query))).boxed_local()
+        })
+        .await
+        .unwrap();
+        assert_eq!(all, ["a", "b", "c", "d", "e", "f", "g"]);
+        assert_eq!(letters.queries.len(), 3);
+        assert_eq!(letters.queries[2].after.as_deref(), Some("f"));
+    }
+
+    #[tokio::test]
+    async fn test_backward() {
+        let mut letters = Letters::new(6);
+        let all = fetch_all(&mut letters, Pager::backward(Some(3)), |letters, query| {
+            futures::future::ready(Ok::<_, ()>(letters.page(query))).boxed_local()
+        })
+        .await
+        .unwrap();
+        assert_eq!(all, ["d", "e", "f", "a", "b", "c"]);
+        // The count told us we were done without asking for an empty page.
+        assert_eq!(letters.queries.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_short_pages() {
+        // Pages shorter than asked for don’t end the set.
+        let mut letters = Letters::new(7);
+        let all = fetch_all(
+            &mut letters,
+            Pager::forward(Some(3)),
+            |letters, mut query| {
+                query.max = Some(2);
+                futures::future::ready(Ok::<_, ()>(letters.page(query))).boxed_local()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(all, ["a", "b", "c", "d", "e", "f", "g"]);
+        assert_eq!(letters.queries.len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_progress() {
+        let mut letters = Letters::new(7);
+        let mut progresses = Vec::new();
+        let all = fetch_all_with_progress(
+            &mut letters,
+            Pager::forward(Some(3)),
+            |letters, query| {
+                let (page, result) = letters.page(query);
+                let bytes = page.len() * 10;
+                futures::future::ready(Ok::<_, ()>((page, result, bytes))).boxed_local()
+            },
+            |progress| progresses.push(progress),
+        )
+        .await
+        .unwrap();
+        assert_eq!(all.len(), 7);
+        assert_eq!(progresses.len(), 3);
+        assert_eq!(
+            progresses[0],
+            Progress {
+                fetched: 3,
+                count: Some(7),
+                pages: 1,
+                bytes: 30,
+            }
+        );
+        assert_eq!(progresses[0].remaining_pages(), Some(2));
+        assert_eq!(progresses[1].remaining_pages(), Some(1));
+        assert_eq!(progresses[2].remaining_pages(), Some(0));
+        assert_eq!(progresses[2].fraction(), Some(1.));
+        assert_eq!(progresses[2].bytes, 70);
+        assert_eq!(Progress::default().remaining_pages(), None);
+    }
+
+    #[test]
+    fn test_pager() {
+        let mut pager = Pager::forward(None);
+        assert_eq!(pager.next_query(), Some(SetQuery::first_page(None)));
+        assert_eq!(pager.next_query(), None);
+
+        // Without count nor max, only an empty page ends the set.
+        let result = SetResult {
+            first: Some(String::from("a")),
+            last: Some(String::from("b")),
+            ..SetResult::default()
+        };
+        pager.handle_result(&result, 2);
+        assert_eq!(
+            pager.next_query(),
+            Some(SetQuery::first_page(None).with_after("b"))
+        );
+        pager.handle_result(&SetResult::default(), 0);
+        assert_eq!(pager.next_query(), None);
+        assert_eq!(pager.fetched(), 2);
+        assert_eq!(pager.count(), None);
+    }
+}
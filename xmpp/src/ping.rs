@@ -0,0 +1,168 @@
+// Copyright (c) 2022 Emmanuel Gil Peyrot <linkmauve@linkmauve.fr>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Measuring the round-trip time to an entity with XMPP pings (XEP-0199).
+
+use std::fmt;
+use std::time::Duration;
+use tokio::time::Instant;
+use xmpp_parsers::{
+    iq::{Iq, IqType},
+    ping::Ping,
+    stanza_error::{DefinedCondition, StanzaError},
+    Jid,
+};
+
+use crate::iq::{make_id, IqError, IqRequester};
+
+/// Why the round-trip time to an entity couldn’t be measured.
+#[derive(Debug)]
+pub enum LatencyError {
+    /// The entity doesn’t answer pings, although it is reachable.
+    Unsupported(StanzaError),
+
+    /// No answer came back in time.
+    Timeout,
+
+    /// The ping couldn’t be sent, or got another error back.
+    Iq(IqError),
+}
+
+impl fmt::Display for LatencyError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LatencyError::Unsupported(err) => {
+                write!(fmt, "pings unsupported: {:?}", err.defined_condition)
+            }
+            LatencyError::Timeout => write!(fmt, "ping timed out"),
+            LatencyError::Iq(err) => write!(fmt, "iq error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for LatencyError {}
+
+impl From<IqError> for LatencyError {
+    fn from(err: IqError) -> LatencyError {
+        LatencyError::Iq(err)
+    }
+}
+
+/// Receives the round-trip times measured by an [`Agent`](crate::Agent),
+/// for instance to export them as metrics, see
+/// `Agent::set_latency_metrics()`.
+pub trait LatencyMetrics {
+    /// The smoothed round-trip time to our server changed, after one of
+    /// the keepalive pings got answered.
+    fn server_latency(&mut self, _latency: Duration) {}
+
+    /// A ping sent by `Agent::measure_latency()` to `jid` came back after
+    /// `rtt`.
+    fn latency_measured(&mut self, _jid: &Jid, _rtt: Duration) {}
+}
+
+/// Send a ping to `jid`, and resolve to the time its answer took to come
+/// back, giving up after `timeout`.
+///
+/// An entity not implementing pings answers with an error, which still
+/// tells it is reachable, but isn’t counted as a round-trip.
+///
+/// The time is read from the clock of the Tokio runtime, which tests can
+/// pause to make the timeout deterministic.
+pub async fn measure_latency<R: IqRequester>(
+    requester: &mut R,
+    jid: Jid,
+    timeout: Duration,
+) -> Result<Duration, LatencyError> {
+    let iq = Iq::from_get(make_id(), Ping).with_to(jid);
+    let start = Instant::now();
+    let response = match tokio::time::timeout(timeout, requester.request(iq)).await {
+        Ok(response) => response.map_err(IqError::from)?,
+        Err(_) => return Err(LatencyError::Timeout),
+    };
+    let rtt = start.elapsed();
+    match response.payload {
+        IqType::Result(_) => Ok(rtt),
+        IqType::Error(err) => match err.defined_condition {
+            DefinedCondition::ServiceUnavailable | DefinedCondition::FeatureNotImplemented => {
+                Err(LatencyError::Unsupported(err))
+            }
+            _ => Err(IqError::Stanza(err).into()),
+        },
+        IqType::Get(_) | IqType::Set(_) => {
+            Err(IqError::Parse(xmpp_parsers::Error::ParseError("Response isn’t a result.")).into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+    use futures::future::{FutureExt, LocalBoxFuture};
+    use std::str::FromStr;
+    use xmpp_parsers::stanza_error::ErrorType;
+
+    /// Answers every ping with this payload, or never.
+    struct Answering(Option<IqType>);
+
+    impl IqRequester for Answering {
+        fn request(&mut self, iq: Iq) -> LocalBoxFuture<'_, Result<Iq, Error>> {
+            match self.0.take() {
+                Some(payload) => {
+                    let response = Iq {
+                        from: iq.to,
+                        to: None,
+                        id: iq.id,
+                        payload,
+                    };
+                    futures::future::ready(Ok(response)).boxed_local()
+                }
+                None => futures::future::pending().boxed_local(),
+            }
+        }
+    }
+
+    fn error(condition: DefinedCondition) -> IqType {
+        IqType::Error(StanzaError::new(
+            ErrorType::Cancel,
+            condition,
+            "en",
+            "Not here",
+        ))
+    }
+
+    // The clock is paused, so the timeout doesn’t take real time.
+    #[tokio::test(start_paused = true)]
+    async fn test_measure_latency() {
+        let jid = Jid::from_str("juliet@capulet.lit/balcony").unwrap();
+        let timeout = Duration::from_secs(60);
+
+        let mut requester = Answering(Some(IqType::Result(None)));
+        let rtt = measure_latency(&mut requester, jid.clone(), timeout)
+            .await
+            .unwrap();
+        assert_eq!(rtt, Duration::ZERO);
+
+        let mut requester = Answering(Some(error(DefinedCondition::ServiceUnavailable)));
+        match measure_latency(&mut requester, jid.clone(), timeout).await {
+            Err(LatencyError::Unsupported(_)) => (),
+            other => panic!("Unexpected {:?}", other),
+        }
+
+        let mut requester = Answering(Some(error(DefinedCondition::ItemNotFound)));
+        match measure_latency(&mut requester, jid.clone(), timeout).await {
+            Err(LatencyError::Iq(IqError::Stanza(_))) => (),
+            other => panic!("Unexpected {:?}", other),
+        }
+
+        let mut requester = Answering(None);
+        match measure_latency(&mut requester, jid, timeout).await {
+            Err(LatencyError::Timeout) => (),
+            other => panic!("Unexpected {:?}", other),
+        }
+    }
+}
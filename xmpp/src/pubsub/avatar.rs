@@ -32,7 +32,7 @@ pub(crate) async fn handle_metadata_pubsub_event(
         if payload.is("metadata", ns::AVATAR_METADATA) {
             let metadata = Metadata::try_from(payload).unwrap();
             for info in metadata.infos {
-                let filename = format!("data/{}/{}", from, &*info.id.to_hex());
+                let filename = format!("{}/{}/{}", agent.avatar_dir, from, &*info.id.to_hex());
                 let file_length = match fs::metadata(filename.clone()) {
                     Ok(metadata) => metadata.len(),
                     Err(_) => 0,
@@ -42,7 +42,7 @@ pub(crate) async fn handle_metadata_pubsub_event(
                     events.push(Event::AvatarRetrieved(from.clone(), filename));
                 } else {
                     let iq = download_avatar(from);
-                    let _ = agent.client.send_stanza(iq.into()).await;
+                    let _ = agent.send_stanza(iq.into()).await;
                 }
             }
         }
@@ -66,6 +66,7 @@ fn download_avatar(from: &Jid) -> Iq {
 // The return value of this function will be simply pushed to a Vec in the caller function,
 // so it makes no sense to allocate a Vec here - we're lazy instead
 pub(crate) fn handle_data_pubsub_iq<'a>(
+    dir: &'a str,
     from: &'a Jid,
     items: &'a Items,
 ) -> impl IntoIterator<Item = Event> + 'a {
@@ -76,16 +77,16 @@ pub(crate) fn handle_data_pubsub_iq<'a>(
         .filter_map(move |item| match (&item.id, &item.payload) {
             (Some(id), Some(payload)) => {
                 let data = Data::try_from(payload.clone()).unwrap();
-                let filename = save_avatar(&from, id.0.clone(), &data.data).unwrap();
+                let filename = save_avatar(dir, &from, id.0.clone(), &data.data).unwrap();
                 Some(Event::AvatarRetrieved(from.clone(), filename))
             }
             _ => None,
         })
 }
 
-fn save_avatar(from: &Jid, id: String, data: &[u8]) -> io::Result<String> {
-    let directory = format!("data/{}", from);
-    let filename = format!("data/{}/{}", from, id);
+fn save_avatar(dir: &str, from: &Jid, id: String, data: &[u8]) -> io::Result<String> {
+    let directory = format!("{}/{}", dir, from);
+    let filename = format!("{}/{}/{}", dir, from, id);
     fs::create_dir_all(directory)?;
     let mut file = File::create(&filename)?;
     file.write_all(data)?;
@@ -9,11 +9,14 @@ use crate::Event;
 use std::convert::TryFrom;
 use std::str::FromStr;
 use xmpp_parsers::{
-    bookmarks2::{Autojoin, Conference},
+    activity::Activity,
+    bookmarks2::Conference,
+    mood::Mood,
     ns,
-    pubsub::event::PubSubEvent,
+    pubsub::event::{Item, PubSubEvent},
     pubsub::pubsub::PubSub,
-    BareJid, Element, Jid,
+    tune::Tune,
+    BareJid, Element, Error, Jid,
 };
 
 #[cfg(feature = "avatars")]
@@ -40,15 +43,27 @@ pub(crate) async fn handle_event(from: &Jid, elem: Element, agent: &mut Agent) -
                     let payload = item.payload.clone().unwrap();
                     match Conference::try_from(payload) {
                         Ok(conference) => {
-                            if conference.autojoin == Autojoin::True {
-                                events.push(Event::JoinRoom(jid, conference));
-                            } else {
-                                events.push(Event::LeaveRoom(jid));
-                            }
+                            events.extend(agent.bookmarks.published(jid, conference));
                         }
                         Err(err) => println!("not bookmark: {}", err),
                     }
                 }
+                ref node if node == ns::MOOD => {
+                    let event = parse_last_item(items, Mood::try_from)
+                        .map(|mood| Event::MoodChanged(BareJid::from(from.clone()), mood));
+                    events.extend(event);
+                }
+                ref node if node == ns::ACTIVITY => {
+                    let event = parse_last_item(items, Activity::try_from).map(|activity| {
+                        Event::ActivityChanged(BareJid::from(from.clone()), activity)
+                    });
+                    events.extend(event);
+                }
+                ref node if node == ns::TUNE => {
+                    let event = parse_last_item(items, Tune::try_from)
+                        .map(|tune| Event::TuneChanged(BareJid::from(from.clone()), tune));
+                    events.extend(event);
+                }
                 ref node => unimplemented!("node {}", node),
             }
         }
@@ -59,7 +74,7 @@ pub(crate) async fn handle_event(from: &Jid, elem: Element, agent: &mut Agent) -
                     assert_eq!(items.len(), 1);
                     let item = items.clone().pop().unwrap();
                     let jid = BareJid::from_str(&item.0).unwrap();
-                    events.push(Event::LeaveRoom(jid));
+                    events.extend(agent.bookmarks.retracted(jid));
                 }
                 ref node => unimplemented!("node {}", node),
             }
@@ -68,7 +83,7 @@ pub(crate) async fn handle_event(from: &Jid, elem: Element, agent: &mut Agent) -
             match node.0 {
                 ref node if node == ns::BOOKMARKS2 => {
                     // TODO: Check that our bare JID is the sender.
-                    events.push(Event::LeaveAllRooms);
+                    events.extend(agent.bookmarks.purged());
                 }
                 ref node => unimplemented!("node {}", node),
             }
@@ -78,7 +93,24 @@ pub(crate) async fn handle_event(from: &Jid, elem: Element, agent: &mut Agent) -
     events
 }
 
-pub(crate) fn handle_iq_result(from: &Jid, elem: Element) -> impl IntoIterator<Item = Event> {
+/// Parses the payload of the most recent of these items, ignoring invalid
+/// ones like the other clients would.
+fn parse_last_item<T>(items: Vec<Item>, parse: fn(Element) -> Result<T, Error>) -> Option<T> {
+    let payload = items.into_iter().last()?.0.payload?;
+    match parse(payload) {
+        Ok(payload) => Some(payload),
+        Err(err) => {
+            warn!("Invalid PEP payload: {}", err);
+            None
+        }
+    }
+}
+
+pub(crate) fn handle_iq_result(
+    from: &Jid,
+    elem: Element,
+    agent: &mut Agent,
+) -> impl IntoIterator<Item = Event> {
     let mut events = Vec::new();
     let pubsub = PubSub::try_from(elem).unwrap();
     trace!("PubSub: {:#?}", pubsub);
@@ -86,24 +118,21 @@ pub(crate) fn handle_iq_result(from: &Jid, elem: Element) -> impl IntoIterator<I
         match items.node.0.clone() {
             #[cfg(feature = "avatars")]
             ref node if node == ns::AVATAR_DATA => {
-                let new_events = avatar::handle_data_pubsub_iq(&from, &items);
+                let new_events = avatar::handle_data_pubsub_iq(&agent.avatar_dir, &from, &items);
                 events.extend(new_events);
             }
             ref node if node == ns::BOOKMARKS2 => {
-                events.push(Event::LeaveAllRooms);
+                let mut bookmarks = Vec::new();
                 for item in items.items {
                     let item = item.0;
                     let jid = BareJid::from_str(&item.id.clone().unwrap().0).unwrap();
                     let payload = item.payload.clone().unwrap();
                     match Conference::try_from(payload) {
-                        Ok(conference) => {
-                            if let Autojoin::True = conference.autojoin {
-                                events.push(Event::JoinRoom(jid, conference));
-                            }
-                        }
+                        Ok(conference) => bookmarks.push((jid, conference)),
                         Err(err) => panic!("Wrong payload type in bookmarks 2 item: {}", err),
                     }
                 }
+                events.extend(agent.bookmarks.loaded(bookmarks));
             }
             _ => unimplemented!(),
         }
@@ -0,0 +1,116 @@
+// Copyright (c) 2022 Emmanuel Gil Peyrot <linkmauve@linkmauve.fr>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! What the semantic events carry about the messages and presences we
+//! receive, so that bots don't have to look at their elements.
+
+use std::any::Any;
+use xmpp_parsers::{
+    message::{Body, MessageType},
+    presence::{Presence, Show, Type as PresenceType},
+    Jid,
+};
+
+use crate::extensions::ExtensionPayload;
+
+/// A message, with its body in our preferred language and its payloads
+/// parsed by the extension parsers.
+#[derive(Debug)]
+pub struct ReceivedMessage {
+    /// The sender of this message.
+    pub from: Jid,
+
+    /// The type of this message.
+    pub type_: MessageType,
+
+    /// The body of this message, if any, in our preferred language.
+    pub body: Option<Body>,
+
+    /// The payloads of this message parsed by one of the extension
+    /// parsers.
+    pub extensions: Vec<ExtensionPayload>,
+}
+
+impl ReceivedMessage {
+    /// The first extension payload of this message which is a `T`.
+    pub fn extension<T: Any>(&self) -> Option<&T> {
+        self.extensions
+            .iter()
+            .find_map(|payload| payload.downcast_ref())
+    }
+}
+
+/// The availability of a contact, as advertised by one of its resources.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PresenceChange {
+    /// Whether this resource is online.
+    pub available: bool,
+
+    /// How available this resource is, if online.
+    pub show: Option<Show>,
+
+    /// The status message, if any, in our preferred language.
+    pub status: Option<String>,
+
+    /// The priority of this resource.
+    pub priority: i8,
+}
+
+impl PresenceChange {
+    /// The availability this presence advertises, or `None` if it is about
+    /// subscriptions, probes or errors instead.
+    pub fn from_presence(presence: &Presence, langs: &[String]) -> Option<PresenceChange> {
+        let available = match presence.type_ {
+            PresenceType::None => true,
+            PresenceType::Unavailable => false,
+            _ => return None,
+        };
+        let langs: Vec<&str> = langs.iter().map(String::as_str).collect();
+        let status = presence
+            .get_best_status(langs)
+            .map(|(_lang, status)| status.clone());
+        Some(PresenceChange {
+            available,
+            show: presence.show.clone(),
+            status,
+            priority: presence.priority,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+    use xmpp_parsers::Element;
+
+    fn presence(xml: &str) -> Presence {
+        Presence::try_from(xml.parse::<Element>().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_presence_change() {
+        let langs = vec![String::from("fr"), String::from("en")];
+        let away = presence("<presence xmlns='jabber:client'><show>away</show><status xml:lang='de'>Weg</status><status xml:lang='en'>Away</status><priority>5</priority></presence>");
+        assert_eq!(
+            PresenceChange::from_presence(&away, &langs),
+            Some(PresenceChange {
+                available: true,
+                show: Some(Show::Away),
+                status: Some(String::from("Away")),
+                priority: 5,
+            })
+        );
+
+        let gone = presence("<presence xmlns='jabber:client' type='unavailable'><status xml:lang='de'>Weg</status></presence>");
+        let change = PresenceChange::from_presence(&gone, &langs).unwrap();
+        assert!(!change.available);
+        assert_eq!(change.status.as_deref(), Some("Weg"));
+
+        let probe = presence("<presence xmlns='jabber:client' type='probe'/>");
+        assert_eq!(PresenceChange::from_presence(&probe, &langs), None);
+    }
+}
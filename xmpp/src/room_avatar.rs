@@ -0,0 +1,92 @@
+// Copyright (c) 2022 Emmanuel Gil Peyrot <linkmauve@linkmauve.fr>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Avatars of MUC rooms, advertised in the presence of their bare JID
+//! (XEP-0153) and fetched from their vCard (XEP-0054), as described in
+//! XEP-0486.
+
+use super::Agent;
+use crate::Event;
+use std::convert::TryFrom;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+use xmpp_parsers::{
+    iq::Iq,
+    vcard::{VCard, VCardQuery},
+    vcard_update::VCardUpdate,
+    BareJid, Element, Jid,
+};
+
+/// The path an avatar of this room with this hash gets saved at, under
+/// `dir`.
+fn avatar_path(dir: &str, room: &BareJid, hash: &str) -> String {
+    format!("{}/{}/{}", dir, room, hash)
+}
+
+/// The id of our request for the vCard of this room.
+pub(crate) fn vcard_id(room: &BareJid) -> String {
+    format!("room-vcard-{}", room)
+}
+
+pub(crate) fn request_vcard(room: &BareJid) -> Iq {
+    Iq::from_get(vcard_id(room), VCardQuery).with_to(Jid::Bare(room.clone()))
+}
+
+pub(crate) async fn handle_vcard_update(
+    room: &BareJid,
+    payload: Element,
+    agent: &mut Agent,
+) -> Vec<Event> {
+    let update = match VCardUpdate::try_from(payload) {
+        Ok(update) => update,
+        Err(err) => {
+            warn!("Invalid vCard update from {}: {}", room, err);
+            return vec![];
+        }
+    };
+    // Without a hash, the room either has no avatar or doesn’t know it yet.
+    let hash = match update.hash() {
+        Some(hash) => hash,
+        None => return vec![],
+    };
+    let filename = avatar_path(&agent.avatar_dir, room, hash);
+    if Path::new(&filename).exists() {
+        vec![Event::RoomAvatarRetrieved(room.clone(), filename)]
+    } else {
+        let _ = agent.send_stanza(request_vcard(room).into()).await;
+        vec![]
+    }
+}
+
+pub(crate) fn handle_vcard_result(dir: &str, room: &BareJid, payload: Element) -> Vec<Event> {
+    let vcard = match VCard::try_from(payload) {
+        Ok(vcard) => vcard,
+        Err(err) => {
+            warn!("Invalid vCard from {}: {}", room, err);
+            return vec![];
+        }
+    };
+    let photo = match vcard.photo {
+        Some(photo) => photo,
+        None => return vec![],
+    };
+    match save_avatar(dir, room, &photo.hash(), &photo.binval.data) {
+        Ok(filename) => vec![Event::RoomAvatarRetrieved(room.clone(), filename)],
+        Err(err) => {
+            warn!("Couldn’t save the avatar of {}: {}", room, err);
+            vec![]
+        }
+    }
+}
+
+fn save_avatar(dir: &str, room: &BareJid, hash: &str, data: &[u8]) -> io::Result<String> {
+    fs::create_dir_all(format!("{}/{}", dir, room))?;
+    let filename = avatar_path(dir, room, hash);
+    let mut file = File::create(&filename)?;
+    file.write_all(data)?;
+    Ok(filename)
+}
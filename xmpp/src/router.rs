@@ -0,0 +1,293 @@
+// Copyright (c) 2022 Emmanuel Gil Peyrot <linkmauve@linkmauve.fr>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Dispatching the incoming stanzas to the handlers registered for their
+//! kind and the namespace of their payloads, so that features like
+//! receipts or ping responders can be composed.
+
+use std::convert::TryFrom;
+use std::fmt;
+use xmpp_parsers::{
+    iq::{Iq, IqType},
+    message::{Message, MessageType},
+    ns,
+    receipts::Received,
+    Element,
+};
+
+/// The kind of a stanza.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StanzaKind {
+    /// A `<message/>`.
+    Message,
+
+    /// A `<presence/>`.
+    Presence,
+
+    /// An `<iq/>`.
+    Iq,
+}
+
+impl StanzaKind {
+    /// The kind of `stanza`, or `None` if it isn’t one.
+    pub fn of(stanza: &Element) -> Option<StanzaKind> {
+        if stanza.ns() != ns::JABBER_CLIENT {
+            return None;
+        }
+        match stanza.name() {
+            "message" => Some(StanzaKind::Message),
+            "presence" => Some(StanzaKind::Presence),
+            "iq" => Some(StanzaKind::Iq),
+            _ => None,
+        }
+    }
+}
+
+/// What a handler did with a stanza.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Handling {
+    /// The stanza got fully handled, the next handlers and the built-in
+    /// handling don’t see it.
+    Consume,
+
+    /// The stanza continues to the next handlers.
+    Pass,
+}
+
+/// A handler, given a stanza and the replies to send.
+pub type Handler = Box<dyn FnMut(&Element, &mut Vec<Element>) -> Handling>;
+
+struct Route {
+    kind: StanzaKind,
+    ns: Option<String>,
+    priority: i32,
+    handler: Handler,
+}
+
+impl Route {
+    fn matches(&self, kind: StanzaKind, stanza: &Element) -> bool {
+        if self.kind != kind {
+            return false;
+        }
+        match self.ns {
+            None => true,
+            Some(ref ns) => stanza.children().any(|child| child.ns() == *ns),
+        }
+    }
+}
+
+/// The outcome of routing a stanza.
+#[derive(Debug, Default)]
+pub struct Routed {
+    /// Whether a handler consumed the stanza.
+    pub consumed: bool,
+
+    /// The replies the handlers asked to send.
+    pub replies: Vec<Element>,
+}
+
+/// Handlers of the incoming stanzas, called by decreasing priority until
+/// one of them consumes the stanza.
+#[derive(Default)]
+pub struct StanzaRouter {
+    routes: Vec<Route>,
+}
+
+impl StanzaRouter {
+    /// Create a router without any handler.
+    pub fn new() -> StanzaRouter {
+        StanzaRouter::default()
+    }
+
+    /// Call `handler` for the stanzas of this `kind` having a payload in
+    /// `ns`, or for all of them if `None`.  Handlers of the same priority
+    /// get called in the order they got registered.
+    pub fn register<F>(&mut self, kind: StanzaKind, ns: Option<&str>, priority: i32, handler: F)
+    where
+        F: FnMut(&Element, &mut Vec<Element>) -> Handling + 'static,
+    {
+        let index = self
+            .routes
+            .iter()
+            .position(|route| route.priority < priority)
+            .unwrap_or(self.routes.len());
+        self.routes.insert(
+            index,
+            Route {
+                kind,
+                ns: ns.map(String::from),
+                priority,
+                handler: Box::new(handler),
+            },
+        );
+    }
+
+    /// Whether no handler got registered.
+    pub fn is_empty(&self) -> bool {
+        self.routes.is_empty()
+    }
+
+    /// Pass `stanza` to the matching handlers, until one consumes it.
+    pub fn route(&mut self, stanza: &Element) -> Routed {
+        let mut routed = Routed::default();
+        let kind = match StanzaKind::of(stanza) {
+            Some(kind) => kind,
+            None => return routed,
+        };
+        for route in self.routes.iter_mut() {
+            if !route.matches(kind, stanza) {
+                continue;
+            }
+            if (route.handler)(stanza, &mut routed.replies) == Handling::Consume {
+                routed.consumed = true;
+                break;
+            }
+        }
+        routed
+    }
+}
+
+impl fmt::Debug for StanzaRouter {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_list()
+            .entries(
+                self.routes
+                    .iter()
+                    .map(|route| (route.kind, &route.ns, route.priority)),
+            )
+            .finish()
+    }
+}
+
+/// A handler answering pings (XEP-0199), to register for
+/// `StanzaKind::Iq` in `ns::PING`.
+pub fn ping_responder() -> impl FnMut(&Element, &mut Vec<Element>) -> Handling {
+    |stanza, replies| {
+        let iq = match Iq::try_from(stanza.clone()) {
+            Ok(iq) => iq,
+            Err(_) => return Handling::Pass,
+        };
+        match (iq.payload, iq.from) {
+            (IqType::Get(ref payload), Some(from)) if payload.is("ping", ns::PING) => {
+                replies.push(Iq::empty_result(from, iq.id).into());
+                Handling::Consume
+            }
+            _ => Handling::Pass,
+        }
+    }
+}
+
+/// A handler acknowledging the messages requesting a receipt (XEP-0184),
+/// to register for `StanzaKind::Message` in `ns::RECEIPTS`.  It lets them
+/// continue to the next handlers.
+pub fn receipts_responder() -> impl FnMut(&Element, &mut Vec<Element>) -> Handling {
+    |stanza, replies| {
+        let message = match Message::try_from(stanza.clone()) {
+            Ok(message) => message,
+            Err(_) => return Handling::Pass,
+        };
+        let requested = message
+            .payloads
+            .iter()
+            .any(|payload| payload.is("request", ns::RECEIPTS));
+        let answerable = !matches!(message.type_, MessageType::Groupchat | MessageType::Error);
+        if let (true, true, Some(from), Some(id)) =
+            (requested, answerable, message.from, message.id)
+        {
+            let mut receipt = Message::new(Some(from));
+            receipt.type_ = message.type_;
+            receipt.payloads.push(Received { id }.into());
+            replies.push(receipt.into());
+        }
+        Handling::Pass
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn elem(xml: &str) -> Element {
+        xml.parse().unwrap()
+    }
+
+    #[test]
+    fn test_priorities() {
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let mut router = StanzaRouter::new();
+        for (name, priority, handling) in [
+            ("low", 0, Handling::Pass),
+            ("high", 10, Handling::Pass),
+            ("consumer", 5, Handling::Consume),
+            ("never", 0, Handling::Pass),
+        ]
+        .iter()
+        .cloned()
+        {
+            let calls = calls.clone();
+            router.register(
+                StanzaKind::Message,
+                Some("urn:example:game"),
+                priority,
+                move |_, _| {
+                    calls.borrow_mut().push(name);
+                    handling
+                },
+            );
+        }
+
+        let routed = router.route(&elem(
+            "<message xmlns='jabber:client'><score xmlns='urn:example:game'/></message>",
+        ));
+        assert!(routed.consumed);
+        assert_eq!(*calls.borrow(), ["high", "consumer"]);
+
+        // Other namespaces and other kinds aren’t routed to them.
+        calls.borrow_mut().clear();
+        let routed = router.route(&elem(
+            "<message xmlns='jabber:client'><body>Hi!</body></message>",
+        ));
+        assert!(!routed.consumed);
+        let routed = router.route(&elem(
+            "<presence xmlns='jabber:client'><score xmlns='urn:example:game'/></presence>",
+        ));
+        assert!(!routed.consumed);
+        assert!(calls.borrow().is_empty());
+    }
+
+    #[test]
+    fn test_responders() {
+        let mut router = StanzaRouter::new();
+        router.register(StanzaKind::Iq, Some(ns::PING), 0, ping_responder());
+        router.register(
+            StanzaKind::Message,
+            Some(ns::RECEIPTS),
+            0,
+            receipts_responder(),
+        );
+
+        let routed = router.route(&elem("<iq xmlns='jabber:client' from='capulet.lit' id='c2s1' type='get'><ping xmlns='urn:xmpp:ping'/></iq>"));
+        assert!(routed.consumed);
+        assert_eq!(
+            routed.replies,
+            [elem(
+                "<iq xmlns='jabber:client' to='capulet.lit' id='c2s1' type='result'/>"
+            )]
+        );
+
+        let routed = router.route(&elem("<message xmlns='jabber:client' from='juliet@capulet.lit/balcony' id='richard2-4.1.247' type='chat'><body>My lord, dispatch; read o'er these articles.</body><request xmlns='urn:xmpp:receipts'/></message>"));
+        assert!(!routed.consumed);
+        assert_eq!(
+            routed.replies,
+            [elem("<message xmlns='jabber:client' to='juliet@capulet.lit/balcony' type='chat'><received xmlns='urn:xmpp:receipts' id='richard2-4.1.247'/></message>")]
+        );
+
+        let routed = router.route(&elem("<message xmlns='jabber:client' from='coven@chat.shakespeare.lit/firstwitch' id='abc' type='groupchat'><request xmlns='urn:xmpp:receipts'/></message>"));
+        assert!(routed.replies.is_empty());
+    }
+}
@@ -0,0 +1,287 @@
+// Copyright (c) 2022 Emmanuel Gil Peyrot <linkmauve@linkmauve.fr>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! The requests of other entities to subscribe to our presence, kept
+//! until the user approves or denies them.
+
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use xmpp_parsers::{
+    iq::Iq,
+    nick::Nick,
+    ns,
+    presence::{Presence, Type as PresenceType},
+    roster::{Item, Roster, Subscription},
+    BareJid, Element, Jid,
+};
+
+/// How many requests are kept by default.
+pub const DEFAULT_CAPACITY: usize = 256;
+
+/// A request to subscribe to our presence.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubscriptionRequest {
+    /// Who wants to subscribe.
+    pub from: BareJid,
+
+    /// The nickname they gave themselves (XEP-0172), if any.
+    pub nick: Option<String>,
+
+    /// The message they sent along, if any.
+    pub status: Option<String>,
+}
+
+/// The subscription requests waiting for an answer, in the order they
+/// arrived.
+#[derive(Debug)]
+pub struct SubscriptionQueue {
+    capacity: usize,
+    pending: Vec<SubscriptionRequest>,
+    /// The contacts already in the roster.
+    contacts: HashSet<BareJid>,
+}
+
+impl Default for SubscriptionQueue {
+    fn default() -> SubscriptionQueue {
+        SubscriptionQueue::with_capacity(DEFAULT_CAPACITY)
+    }
+}
+
+impl SubscriptionQueue {
+    /// Create an empty queue.
+    pub fn new() -> SubscriptionQueue {
+        SubscriptionQueue::default()
+    }
+
+    /// Create an empty queue keeping up to `capacity` requests, ignoring
+    /// the new ones past that, so that a flood of them can’t exhaust our
+    /// memory.  The server sends them again on the next login.
+    pub fn with_capacity(capacity: usize) -> SubscriptionQueue {
+        SubscriptionQueue {
+            capacity,
+            pending: Vec::new(),
+            contacts: HashSet::new(),
+        }
+    }
+
+    /// The request of this entity, if it is waiting for an answer.
+    pub fn get(&self, jid: &BareJid) -> Option<&SubscriptionRequest> {
+        self.pending.iter().find(|request| request.from == *jid)
+    }
+
+    /// All of the requests waiting for an answer.
+    pub fn iter(&self) -> impl Iterator<Item = &SubscriptionRequest> {
+        self.pending.iter()
+    }
+
+    /// How many requests are waiting for an answer.
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Whether no request is waiting for an answer.
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Keep track of the requests in `presence`, returning the new one if
+    /// it was one.
+    ///
+    /// The server sends the pending requests again on every login, and
+    /// the requester can cancel theirs by unsubscribing, so only requests
+    /// not already known are new.
+    pub fn handle_presence(&mut self, presence: &Presence) -> Option<&SubscriptionRequest> {
+        let from = match presence.from {
+            Some(ref from) => BareJid::from(from.clone()),
+            None => return None,
+        };
+        match presence.type_ {
+            PresenceType::Subscribe => {
+                if self.get(&from).is_some() || self.pending.len() >= self.capacity {
+                    return None;
+                }
+                let nick = presence
+                    .payloads
+                    .iter()
+                    .find(|payload| payload.is("nick", ns::NICK))
+                    .and_then(|payload| Nick::try_from(payload.clone()).ok())
+                    .map(|nick| nick.0);
+                let status = presence
+                    .statuses
+                    .get("")
+                    .or_else(|| presence.statuses.values().next())
+                    .cloned();
+                self.pending
+                    .push(SubscriptionRequest { from, nick, status });
+                self.pending.last()
+            }
+            PresenceType::Unsubscribe => {
+                self.take(&from);
+                None
+            }
+            _ => None,
+        }
+    }
+
+    /// Keep track of the contacts of `roster`, as received in full from
+    /// the server, so that approving their requests doesn’t add them again.
+    pub fn handle_roster(&mut self, roster: &Roster) {
+        self.contacts = roster
+            .items
+            .iter()
+            .filter(|item| item.subscription != Subscription::Remove)
+            .map(|item| item.jid.clone())
+            .collect();
+    }
+
+    fn take(&mut self, jid: &BareJid) -> Option<SubscriptionRequest> {
+        let index = self
+            .pending
+            .iter()
+            .position(|request| request.from == *jid)?;
+        Some(self.pending.remove(index))
+    }
+
+    /// Approve the request of `jid`, returning the stanzas to send, or
+    /// `None` if it didn’t request anything.
+    ///
+    /// With `add_to_roster`, the contact also gets added to the roster,
+    /// with the nickname it gave as its name, unless it already is in it.
+    pub fn approve(&mut self, jid: &BareJid, add_to_roster: bool) -> Option<Vec<Element>> {
+        let request = self.take(jid)?;
+        let to = Jid::Bare(request.from.clone());
+        let mut stanzas = Vec::new();
+        if add_to_roster && self.contacts.insert(request.from.clone()) {
+            let roster = Roster {
+                ver: None,
+                items: vec![Item {
+                    jid: request.from.clone(),
+                    name: request.nick,
+                    subscription: Default::default(),
+                    ask: Default::default(),
+                    groups: vec![],
+                }],
+            };
+            let id = format!("subscription-roster-{}", request.from);
+            stanzas.push(Iq::from_set(id, roster).into());
+        }
+        stanzas.push(Presence::new(PresenceType::Subscribed).with_to(to).into());
+        Some(stanzas)
+    }
+
+    /// Deny the request of `jid`, returning the stanza to send, or `None`
+    /// if it didn’t request anything.
+    pub fn deny(&mut self, jid: &BareJid) -> Option<Element> {
+        let request = self.take(jid)?;
+        let to = Jid::Bare(request.from);
+        Some(Presence::new(PresenceType::Unsubscribed).with_to(to).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn presence(xml: &str) -> Presence {
+        Presence::try_from(xml.parse::<Element>().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_queue() {
+        let mut queue = SubscriptionQueue::new();
+        let romeo = BareJid::from_str("romeo@montague.lit").unwrap();
+        let request = presence("<presence xmlns='jabber:client' from='romeo@montague.lit/orchard' type='subscribe'><status>Wherefore art thou?</status><nick xmlns='http://jabber.org/protocol/nick'>Romeo</nick></presence>");
+        let new = queue.handle_presence(&request).unwrap();
+        assert_eq!(new.from, romeo);
+        assert_eq!(new.nick.as_deref(), Some("Romeo"));
+        assert_eq!(new.status.as_deref(), Some("Wherefore art thou?"));
+
+        // Sent again on the next login.
+        assert!(queue.handle_presence(&request).is_none());
+        assert_eq!(queue.len(), 1);
+
+        let stanzas = queue.approve(&romeo, true).unwrap();
+        assert_eq!(stanzas.len(), 2);
+        let roster = stanzas[0].get_child("query", ns::ROSTER).unwrap();
+        let item = roster.get_child("item", ns::ROSTER).unwrap();
+        assert_eq!(item.attr("name"), Some("Romeo"));
+        assert_eq!(stanzas[1].attr("type"), Some("subscribed"));
+        assert!(queue.is_empty());
+        assert!(queue.approve(&romeo, true).is_none());
+    }
+
+    #[test]
+    fn test_deny_and_cancel() {
+        let mut queue = SubscriptionQueue::new();
+        let benvolio = BareJid::from_str("benvolio@montague.lit").unwrap();
+        let tybalt = BareJid::from_str("tybalt@capulet.lit").unwrap();
+        queue.handle_presence(&presence(
+            "<presence xmlns='jabber:client' from='benvolio@montague.lit' type='subscribe'/>",
+        ));
+        queue.handle_presence(&presence(
+            "<presence xmlns='jabber:client' from='tybalt@capulet.lit' type='subscribe'/>",
+        ));
+        assert_eq!(queue.iter().count(), 2);
+
+        queue.handle_presence(&presence(
+            "<presence xmlns='jabber:client' from='benvolio@montague.lit' type='unsubscribe'/>",
+        ));
+        assert!(queue.get(&benvolio).is_none());
+
+        let stanza = queue.deny(&tybalt).unwrap();
+        assert_eq!(stanza.attr("type"), Some("unsubscribed"));
+        assert_eq!(stanza.attr("to"), Some("tybalt@capulet.lit"));
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_known_contact() {
+        let mut queue = SubscriptionQueue::new();
+        let juliet = BareJid::from_str("juliet@capulet.lit").unwrap();
+        let roster: Element = "<query xmlns='jabber:iq:roster'><item jid='juliet@capulet.lit' subscription='to'/></query>".parse().unwrap();
+        queue.handle_roster(&Roster::try_from(roster).unwrap());
+
+        let request = presence(
+            "<presence xmlns='jabber:client' from='juliet@capulet.lit' type='subscribe'/>",
+        );
+        queue.handle_presence(&request);
+        let stanzas = queue.approve(&juliet, true).unwrap();
+        assert_eq!(stanzas.len(), 1);
+        assert_eq!(stanzas[0].attr("type"), Some("subscribed"));
+
+        // Only added once, even if asking again before the roster push.
+        let romeo = BareJid::from_str("romeo@montague.lit").unwrap();
+        let request = presence(
+            "<presence xmlns='jabber:client' from='romeo@montague.lit' type='subscribe'/>",
+        );
+        queue.handle_presence(&request);
+        assert_eq!(queue.approve(&romeo, true).unwrap().len(), 2);
+        queue.handle_presence(&request);
+        assert_eq!(queue.approve(&romeo, true).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_capacity() {
+        let mut queue = SubscriptionQueue::with_capacity(2);
+        for name in ["a", "b", "c"].iter() {
+            queue.handle_presence(&presence(&format!(
+                "<presence xmlns='jabber:client' from='{}@example.org' type='subscribe'/>",
+                name
+            )));
+        }
+        assert_eq!(queue.len(), 2);
+        assert!(queue
+            .get(&BareJid::from_str("c@example.org").unwrap())
+            .is_none());
+
+        // Room again once one got answered.
+        queue.deny(&BareJid::from_str("a@example.org").unwrap());
+        let request =
+            presence("<presence xmlns='jabber:client' from='c@example.org' type='subscribe'/>");
+        assert!(queue.handle_presence(&request).is_some());
+    }
+}